@@ -36,7 +36,7 @@ fn benchmark_buffers(c: &mut Criterion) {
                         ),
                     );
                     config.sinks["out"].buffer = BufferConfig::Memory {
-                        max_events: 100,
+                        max_events: Some(100),
                         when_full: Default::default(),
                     };
 
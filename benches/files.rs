@@ -49,8 +49,11 @@ fn benchmark_files_without_partitions(c: &mut Criterion) {
                     &["in"],
                     sinks::file::FileSinkConfig {
                         path: output.into(),
+                        timezone: Default::default(),
                         idle_timeout_secs: None,
                         encoding: sinks::file::Encoding::Text.into(),
+                        permissions: Default::default(),
+                        directory_permissions: Default::default(),
                     },
                 );
 
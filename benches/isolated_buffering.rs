@@ -102,7 +102,7 @@ fn benchmark_buffers(c: &mut Criterion) {
 
                     let plenty_of_room = num_lines * line_size * 2;
                     let (writer, _reader, _acker) =
-                        leveldb_buffer::Buffer::build(path, plenty_of_room).unwrap();
+                        leveldb_buffer::Buffer::build(path, plenty_of_room, "basic_sink").unwrap();
 
                     (rt, writer)
                 },
@@ -127,7 +127,7 @@ fn benchmark_buffers(c: &mut Criterion) {
 
                     let plenty_of_room = num_lines * line_size * 2;
                     let (writer, reader, acker) =
-                        leveldb_buffer::Buffer::build(path, plenty_of_room).unwrap();
+                        leveldb_buffer::Buffer::build(path, plenty_of_room, "basic_sink").unwrap();
 
                     let send = writer.send_all(random_events(line_size).take(num_lines as u64));
                     let write_handle = rt.spawn_handle(send.compat());
@@ -158,7 +158,7 @@ fn benchmark_buffers(c: &mut Criterion) {
 
                     let plenty_of_room = num_lines * line_size * 2;
                     let (writer, reader, acker) =
-                        leveldb_buffer::Buffer::build(path, plenty_of_room).unwrap();
+                        leveldb_buffer::Buffer::build(path, plenty_of_room, "basic_sink").unwrap();
 
                     let read_loop = StreamSink::new(NullSink, acker).send_all(reader);
 
@@ -1,9 +1,38 @@
 fn main() {
+    println!("cargo:rerun-if-changed=proto/arrow");
     println!("cargo:rerun-if-changed=proto/event.proto");
+    println!("cargo:rerun-if-changed=proto/opentelemetry");
+    println!("cargo:rerun-if-changed=proto/prometheus");
     let mut prost_build = prost_build::Config::new();
     prost_build.btree_map(&["."]);
     prost_build
-        .compile_protos(&["proto/event.proto"], &["proto/"])
+        .compile_protos(
+            &[
+                "proto/arrow/flight.proto",
+                "proto/event.proto",
+                "proto/opentelemetry/proto/collector/logs/v1/logs_service.proto",
+                "proto/opentelemetry/proto/collector/metrics/v1/metrics_service.proto",
+                "proto/prometheus/remote.proto",
+            ],
+            &["proto/"],
+        )
         .unwrap();
+    #[cfg(feature = "sources-ibm_mq")]
+    build_ibm_mq();
     built::write_built_file().unwrap();
 }
+
+/// Compiles the shim of the `ibm_mq` source against the MQ client, which is
+/// installed separately.
+#[cfg(feature = "sources-ibm_mq")]
+fn build_ibm_mq() {
+    println!("cargo:rerun-if-changed=src/sources/ibm_mq/mqi.c");
+    println!("cargo:rerun-if-env-changed=MQ_INSTALLATION_PATH");
+    let mq = std::env::var("MQ_INSTALLATION_PATH").unwrap_or_else(|_| "/opt/mqm".into());
+    cc::Build::new()
+        .file("src/sources/ibm_mq/mqi.c")
+        .include(format!("{}/inc", mq))
+        .compile("vector_mqi");
+    println!("cargo:rustc-link-search=native={}/lib64", mq);
+    println!("cargo:rustc-link-lib=dylib=mqic_r");
+}
@@ -1,9 +1,13 @@
-use crate::{file_watcher::FileWatcher, FileFingerprint, FilePosition};
+use crate::{
+    file_watcher::FileWatcher,
+    notifier::{Changes, Notifier},
+    FileFingerprint, FilePosition,
+};
 use bytes::Bytes;
 use futures::{
     executor::block_on,
     future::{select, Either},
-    stream, Future, Sink, SinkExt,
+    stream, Future, FutureExt, Sink, SinkExt,
 };
 use glob::glob;
 use indexmap::IndexMap;
@@ -19,10 +23,11 @@ use crate::metadata_ext::PortableFileExt;
 use crate::paths_provider::PathsProvider;
 
 /// `FileServer` is a Source which cooperatively schedules reads over files,
-/// converting the lines of said files into `LogLine` structures. As
-/// `FileServer` is intended to be useful across multiple operating systems with
-/// POSIX filesystem semantics `FileServer` must poll for changes. That is, no
-/// event notification is used by `FileServer`.
+/// converting the lines of said files into `LogLine` structures. When
+/// `use_notifications` is set `FileServer` is notified of changes by the
+/// operating system where it can be, and only reads the files notified as
+/// changed, while still polling everything occasionally in case notifications
+/// were missed. Otherwise, or where notifications are unavailable, it polls.
 ///
 /// `FileServer` is configured on a path to watch. The files do _not_ need to
 /// exist at startup. `FileServer` will discover new files which match
@@ -40,8 +45,16 @@ where
     pub glob_minimum_cooldown: time::Duration,
     pub fingerprinter: Fingerprinter,
     pub oldest_first: bool,
+    pub use_notifications: bool,
 }
 
+/// How long `FileServer` goes without globbing or reading every file when
+/// relying on notifications.
+const NOTIFICATIONS_FALLBACK_INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+/// How often shutdown is checked for while waiting on notifications.
+const NOTIFICATIONS_SHUTDOWN_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
 /// `FileServer` as Source
 ///
 /// The 'run' of `FileServer` performs the cooperative scheduling of reads over
@@ -81,6 +94,12 @@ where
 
         let mut known_small_files = HashSet::new();
 
+        let notifier = if self.use_notifications {
+            self.notifier()
+        } else {
+            None
+        };
+
         let mut existing_files = Vec::new();
         for path in self.paths_provider.paths().into_iter() {
             if let Some(file_id) = self.fingerprinter.get_fingerprint_or_log_error(
@@ -117,11 +136,47 @@ where
         // exponential fashion to some hard-coded cap. To reduce time using glob,
         // we do not re-scan for major file changes (new files, moves, deletes),
         // or write new checkpoints, on every iteration.
+        //
+        // With notifications we rather wait until files change. Only the
+        // files notified, or which had lines last time, are read and paths are
+        // globbed once files are created, removed or renamed. Everything is
+        // still read and globbed every so often, in case notifications were
+        // missed.
         let mut next_glob_time = time::Instant::now();
+        let mut next_fallback_time = time::Instant::now();
+        let mut changes: Option<Changes> = None;
+        let mut rescan_pending = false;
+        let mut active_files = HashSet::new();
+        let mut read_interrupted = false;
+        let mut checkpoints_pending = false;
         loop {
-            // Glob find files to follow, but not too often.
             let now_time = time::Instant::now();
-            if next_glob_time <= now_time {
+            let fallback = notifier.is_none() || next_fallback_time <= now_time;
+            if fallback {
+                next_fallback_time = now_time + NOTIFICATIONS_FALLBACK_INTERVAL;
+            }
+            let modified = match changes.take() {
+                Some(changes) => {
+                    rescan_pending |= changes.rescan;
+                    changes.modified
+                }
+                None => HashSet::new(),
+            };
+            if !rescan_pending && !modified.is_empty() {
+                // Files which aren't watched yet, like those too small to be
+                // fingerprinted when found, have to be searched for again.
+                let watched = fp_map
+                    .values()
+                    .map(|watcher| &watcher.path)
+                    .collect::<HashSet<_>>();
+                rescan_pending = modified.iter().any(|path| !watched.contains(path));
+            }
+            rescan_pending |= fallback;
+            let mut read_all = fallback || read_interrupted;
+
+            // Glob find files to follow, but not too often.
+            let glob_due = next_glob_time <= now_time;
+            if glob_due {
                 // Schedule the next glob time.
                 next_glob_time = now_time.checked_add(self.glob_minimum_cooldown).unwrap();
 
@@ -130,6 +185,13 @@ where
                     .write_checkpoints()
                     .map_err(|e| warn!("Problem writing checkpoints: {:?}", e))
                     .ok();
+                checkpoints_pending = false;
+            }
+
+            if glob_due && rescan_pending {
+                rescan_pending = false;
+                // Files found are read right away.
+                read_all = true;
 
                 // Search (glob) for files to detect major file changes.
                 for (_file_id, watcher) in &mut fp_map {
@@ -192,8 +254,13 @@ where
             // Collect lines by polling files.
             let mut global_bytes_read: usize = 0;
             let mut maxed_out_reading_single_file = false;
+            read_interrupted = false;
             for (&file_id, watcher) in &mut fp_map {
-                if !watcher.should_read() {
+                let notified = modified.contains(&watcher.path);
+                if !notified && !watcher.should_read() {
+                    continue;
+                }
+                if !notified && !read_all && !active_files.contains(&file_id) {
                     continue;
                 }
 
@@ -226,9 +293,15 @@ where
                 if bytes_read > 0 {
                     global_bytes_read = global_bytes_read.saturating_add(bytes_read);
                     checkpointer.set_checkpoint(file_id, watcher.get_file_position());
+                    checkpoints_pending = true;
+                    active_files.insert(file_id);
+                } else {
+                    active_files.remove(&file_id);
                 }
                 // Do not move on to newer files if we are behind on an older file
                 if self.oldest_first && maxed_out_reading_single_file {
+                    // Files notified as changed may not have been read.
+                    read_interrupted = true;
                     break;
                 }
             }
@@ -236,6 +309,7 @@ where
             // A FileWatcher is dead when the underlying file has disappeared.
             // If the FileWatcher is dead we don't retain it; it will be deallocated.
             fp_map.retain(|_file_id, watcher| !watcher.dead());
+            active_files.retain(|file_id| fp_map.contains_key(file_id));
 
             let mut stream = stream::iter(lines.drain(..).map(Ok));
             let result = block_on(chans.send_all(&mut stream));
@@ -251,33 +325,79 @@ where
             // limited by the hard-coded cap. Else, we set the backup_cap to its
             // minimum on the assumption that next time through there will be
             // more lines to read promptly.
+            // Notifications wake us up as soon as files change, so we can wait
+            // longer.
+            let max_backoff_cap = if notifier.is_some() {
+                NOTIFICATIONS_FALLBACK_INTERVAL.as_millis() as usize
+            } else {
+                2_048
+            };
             if global_bytes_read == 0 {
                 let lim = backoff_cap.saturating_mul(2);
-                if lim > 2_048 {
-                    backoff_cap = 2_048;
+                if lim > max_backoff_cap {
+                    backoff_cap = max_backoff_cap;
                 } else {
                     backoff_cap = lim;
                 }
             } else {
                 backoff_cap = 1;
             }
-            let backoff = backoff_cap.saturating_sub(global_bytes_read);
+            let backoff =
+                time::Duration::from_millis(backoff_cap.saturating_sub(global_bytes_read) as u64);
+
+            if let Some(notifier) = &notifier {
+                let mut deadline = time::Instant::now() + backoff;
+                if rescan_pending || checkpoints_pending {
+                    // Don't wait past the next glob for changes to be found
+                    // and checkpoints written.
+                    deadline = deadline.min(next_glob_time);
+                }
+                loop {
+                    if (&mut shutdown).now_or_never().is_some() {
+                        return Ok(Shutdown);
+                    }
+                    let remaining = deadline.saturating_duration_since(time::Instant::now());
+                    if remaining == time::Duration::from_secs(0) {
+                        break;
+                    }
+                    let notified = notifier.wait(remaining.min(NOTIFICATIONS_SHUTDOWN_INTERVAL));
+                    if !notified.is_empty() {
+                        changes = Some(notified);
+                        break;
+                    }
+                }
+                continue;
+            }
 
             // This works only if run inside tokio context since we are using
             // tokio's Timer. Outside of such context, this will panic on the first
             // call. Also since we are using block_on here and in the above code,
             // this should be run in it's own thread. `spawn_blocking` fulfills
             // all of these requirements.
-            match block_on(select(
-                shutdown,
-                delay_for(time::Duration::from_millis(backoff as u64)),
-            )) {
+            match block_on(select(shutdown, delay_for(backoff))) {
                 Either::Left((_, _)) => return Ok(Shutdown),
                 Either::Right((_, future)) => shutdown = future,
             }
         }
     }
 
+    fn notifier(&self) -> Option<Notifier> {
+        let dirs = self.paths_provider.watched_dirs();
+        if dirs.is_empty() {
+            return None;
+        }
+        match Notifier::new(&dirs) {
+            Ok(notifier) => Some(notifier),
+            Err(error) => {
+                warn!(
+                    message = "Unable to be notified of file changes, falling back to polling.",
+                    ?error
+                );
+                None
+            }
+        }
+    }
+
     fn watch_new_file(
         &self,
         path: PathBuf,
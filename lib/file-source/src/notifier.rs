@@ -0,0 +1,113 @@
+use crate::paths_provider::WatchedDir;
+use notify::{raw_watcher, Op, RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use tracing::field;
+
+/// Notifies the file server of changes in the watched directories, using
+/// inotify on Linux, FSEvents on macOS and ReadDirectoryChangesW on Windows.
+pub struct Notifier {
+    // Notifications stop once it's dropped.
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<RawEvent>,
+    /// The watched directories whose canonical path differs, as backends
+    /// like FSEvents report changes under the canonical one.
+    canonical_dirs: Vec<(PathBuf, PathBuf)>,
+}
+
+/// The changes notified while waiting.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// The files written to.
+    pub modified: HashSet<PathBuf>,
+    /// Whether files were created, removed or renamed, or changes may have
+    /// been missed, so paths have to be searched for again.
+    pub rescan: bool,
+}
+
+impl Notifier {
+    /// Watches the directories which exist, changes in the others are left
+    /// to polling.
+    pub fn new(dirs: &[WatchedDir]) -> notify::Result<Self> {
+        let (sender, receiver) = channel();
+        let mut watcher = raw_watcher(sender)?;
+        let mut canonical_dirs = Vec::new();
+        for dir in dirs {
+            let mode = if dir.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            match watcher.watch(&dir.path, mode) {
+                Ok(()) => {
+                    if let Ok(canonical) = fs::canonicalize(&dir.path) {
+                        if canonical != dir.path {
+                            canonical_dirs.push((canonical, dir.path.clone()));
+                        }
+                    }
+                }
+                Err(error) => warn!(
+                    message = "Unable to watch directory, falling back to polling it.",
+                    path = field::debug(&dir.path),
+                    ?error,
+                ),
+            }
+        }
+        Ok(Notifier {
+            _watcher: watcher,
+            receiver,
+            canonical_dirs,
+        })
+    }
+
+    /// Waits up to `timeout` for changes, returning all of those notified.
+    pub fn wait(&self, timeout: Duration) -> Changes {
+        let mut changes = Changes::default();
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => self.add(&mut changes, event),
+            Err(RecvTimeoutError::Timeout) => return changes,
+            Err(RecvTimeoutError::Disconnected) => {
+                // The backend stopped, polling takes over.
+                thread::sleep(timeout);
+                return changes;
+            }
+        }
+        while let Ok(event) = self.receiver.try_recv() {
+            self.add(&mut changes, event);
+        }
+        changes
+    }
+
+    fn add(&self, changes: &mut Changes, event: RawEvent) {
+        match (event.op, event.path) {
+            (Ok(op), Some(path))
+                if !op.intersects(Op::CREATE | Op::REMOVE | Op::RENAME | Op::RESCAN) =>
+            {
+                if op.intersects(Op::WRITE | Op::CLOSE_WRITE) {
+                    changes.modified.insert(self.configured_path(path));
+                }
+            }
+            // Errors and events without paths may hide any change.
+            _ => changes.rescan = true,
+        }
+    }
+
+    fn configured_path(&self, path: PathBuf) -> PathBuf {
+        for (canonical, configured) in &self.canonical_dirs {
+            if let Ok(relative) = path.strip_prefix(canonical) {
+                return configured.join(relative);
+            }
+        }
+        path
+    }
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_empty() && !self.rescan
+    }
+}
@@ -1,9 +1,9 @@
 //! [`Glob`] paths provider.
 
-use super::PathsProvider;
+use super::{PathsProvider, WatchedDir};
 
 use glob::Pattern;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 pub use glob::MatchOptions;
 
@@ -64,4 +64,79 @@ impl PathsProvider for Glob {
             })
             .collect()
     }
+
+    fn watched_dirs(&self) -> Vec<WatchedDir> {
+        let mut dirs = Vec::new();
+        for include_pattern in &self.include_patterns {
+            let dir = watched_dir(include_pattern);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+}
+
+/// The directory up to the first component of the pattern with wildcards,
+/// watched recursively if wildcards may match subdirectories.
+fn watched_dir(pattern: &str) -> WatchedDir {
+    let mut components = Path::new(pattern).components();
+    let mut path = PathBuf::new();
+    let mut wildcards = 0;
+    for component in &mut components {
+        if is_wildcard(component) {
+            wildcards = 1;
+            break;
+        }
+        path.push(component);
+    }
+    let remaining = wildcards + components.count();
+
+    if remaining == 0 {
+        // A single file, its directory is watched for it to be created.
+        path.pop();
+    }
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+    WatchedDir {
+        path,
+        recursive: remaining > 1 || pattern.contains("**"),
+    }
+}
+
+fn is_wildcard(component: Component) -> bool {
+    component
+        .as_os_str()
+        .to_str()
+        .map_or(false, |component| component.contains(&['*', '?', '['][..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watched(path: &str, recursive: bool) -> WatchedDir {
+        WatchedDir {
+            path: path.into(),
+            recursive,
+        }
+    }
+
+    #[test]
+    fn glob_watched_dirs() {
+        assert_eq!(watched_dir("/var/log/*.log"), watched("/var/log", false));
+        assert_eq!(watched_dir("/var/log/app.log"), watched("/var/log", false));
+        assert_eq!(watched_dir("/var/log/*/app.log"), watched("/var/log", true));
+        assert_eq!(watched_dir("/var/log/**"), watched("/var/log", true));
+        assert_eq!(watched_dir("*.log"), watched(".", false));
+
+        let glob = Glob::new(
+            &["/var/log/a*.log".into(), "/var/log/b*.log".into()],
+            &[],
+            MatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(glob.watched_dirs(), vec![watched("/var/log", false)]);
+    }
 }
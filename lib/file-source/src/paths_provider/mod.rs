@@ -30,4 +30,21 @@ pub trait PathsProvider {
 
     /// Provides a set of paths.
     fn paths(&self) -> Self::IntoIter;
+
+    /// Provides the directories where changes may affect the set of paths or
+    /// their contents, for the file server to be notified of them.
+    ///
+    /// Providers returning none are polled.
+    fn watched_dirs(&self) -> Vec<WatchedDir> {
+        Vec::new()
+    }
+}
+
+/// A directory to be notified of changes in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchedDir {
+    /// The path of the directory.
+    pub path: PathBuf,
+    /// Whether changes in its subdirectories matter too.
+    pub recursive: bool,
 }
@@ -0,0 +1,68 @@
+/*
+ * Embedded Vector pipelines, for programs to ship their events through
+ * Vector in process.
+ *
+ * Build with `cargo build --release -p vector-ffi`, and link against
+ * `libvector_ffi.so` (or `.dylib`, `.dll`), or `libvector_ffi.a`.
+ *
+ *     const char *config =
+ *         "[sinks.out]\n"
+ *         "type = \"http\"\n"
+ *         "inputs = [\"app\"]\n"
+ *         "uri = \"https://logs.example.com\"\n"
+ *         "encoding.codec = \"ndjson\"\n";
+ *     char error[256];
+ *
+ *     vector_pipeline *pipeline = vector_pipeline_start(config, "app", error, sizeof(error));
+ *     if (pipeline == NULL) {
+ *         fprintf(stderr, "vector: %s\n", error);
+ *         return 1;
+ *     }
+ *     vector_pipeline_send(pipeline, "hello", 5);
+ *     vector_pipeline_stop(pipeline);
+ */
+
+#ifndef VECTOR_H
+#define VECTOR_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct VectorPipeline vector_pipeline;
+
+/*
+ * Starts the pipeline of `config`, a TOML config as in config files, with
+ * the events sent to it as the input named `input`. Returns NULL on
+ * errors, writing them NUL-terminated to `error` unless it is NULL.
+ */
+vector_pipeline *vector_pipeline_start(const char *config, const char *input, char *error,
+                                       size_t error_len);
+
+/*
+ * Sends a line of text, as the message of a log event. Blocks while the
+ * pipeline is backed up. Returns 0 once it is queued, or -1 when the
+ * pipeline is gone. Safe to call from several threads.
+ */
+int vector_pipeline_send(const vector_pipeline *pipeline, const char *message, size_t len);
+
+/*
+ * Sends a JSON object, as the fields of a log event timestamped now unless
+ * it has a timestamp. Returns 0 once it is queued, or -1 when the JSON
+ * isn't an object or the pipeline is gone.
+ */
+int vector_pipeline_send_json(const vector_pipeline *pipeline, const char *json, size_t len);
+
+/*
+ * Stops the pipeline once the events sent went through it, and frees it.
+ * The pipeline isn't to be used after.
+ */
+void vector_pipeline_stop(vector_pipeline *pipeline);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,310 @@
+//! A C interface to embedded Vector pipelines, for programs written in other
+//! languages to ship their events through Vector in process. The functions
+//! are declared in `include/vector.h`.
+//!
+//! The pipeline is configured in TOML, as in config files, with its
+//! transforms and sinks taking the events the program sends as the input
+//! named when starting it.
+
+use chrono::Utc;
+use futures01::{sync::mpsc::Sender, Future, Sink};
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::Mutex,
+};
+use vector::{
+    embed::{self, Pipeline},
+    event::{self, Value},
+    topology::Config,
+    Event,
+};
+
+/// The events queued up before sending blocks.
+const BUFFER_EVENTS: usize = 1000;
+
+pub struct VectorPipeline {
+    pipeline: Pipeline,
+    input: Input,
+}
+
+/// The sending end of the input, shared by the threads of the program.
+struct Input {
+    /// Taken out while sending, as sending consumes the sender. A clone
+    /// would get a slot of its own, so sending on clones never blocks.
+    sender: Mutex<Option<Sender<Event>>>,
+}
+
+impl Input {
+    fn new(sender: Sender<Event>) -> Self {
+        Self {
+            sender: Mutex::new(Some(sender)),
+        }
+    }
+
+    /// Blocks while the pipeline is backed up, and other threads sending
+    /// wait their turn.
+    fn send(&self, event: Event) -> c_int {
+        let mut sender = self.sender.lock().unwrap();
+        let sent = match sender.take() {
+            Some(taken) => taken.send(event).wait(),
+            None => return -1,
+        };
+        match sent {
+            Ok(taken) => {
+                *sender = Some(taken);
+                0
+            }
+            Err(_) => -1,
+        }
+    }
+}
+
+impl VectorPipeline {
+    fn start(config: &str, input: &str) -> Result<Self, String> {
+        let mut config = Config::load(config.as_bytes()).map_err(|errors| errors.join("; "))?;
+        let (sender, source) = embed::source(BUFFER_EVENTS);
+        config.add_source(input, source);
+
+        let pipeline = Pipeline::start(config, false).map_err(|errors| errors.join("; "))?;
+        Ok(Self {
+            pipeline,
+            input: Input::new(sender),
+        })
+    }
+
+    fn send(&self, event: Event) -> c_int {
+        self.input.send(event)
+    }
+}
+
+/// Panics are caught before they cross into C, returning `failed` instead.
+fn catch<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(failed)
+}
+
+unsafe fn to_str<'a>(string: *const c_char, name: &str) -> Result<&'a str, String> {
+    if string.is_null() {
+        return Err(format!("`{}` is null", name));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| format!("`{}` isn't UTF-8", name))
+}
+
+/// Copies as much of the message as fits, always NUL-terminated.
+unsafe fn write_error(message: &str, error: *mut c_char, error_len: usize) {
+    if error.is_null() || error_len == 0 {
+        return;
+    }
+    let len = message.len().min(error_len - 1);
+    ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, error, len);
+    *error.add(len) = 0;
+}
+
+/// Starts the pipeline of `config`, a TOML config, with the events sent
+/// to it as the input named `input`. Returns null on errors, writing them
+/// to `error` unless it is null.
+///
+/// # Safety
+///
+/// `config` and `input` must be NUL-terminated strings, and `error` must
+/// point to `error_len` writable bytes unless it is null.
+#[no_mangle]
+pub unsafe extern "C" fn vector_pipeline_start(
+    config: *const c_char,
+    input: *const c_char,
+    error: *mut c_char,
+    error_len: usize,
+) -> *mut VectorPipeline {
+    let started = catch(Err("Vector panicked".to_owned()), || {
+        VectorPipeline::start(to_str(config, "config")?, to_str(input, "input")?)
+    });
+    match started {
+        Ok(pipeline) => Box::into_raw(Box::new(pipeline)),
+        Err(message) => {
+            write_error(&message, error, error_len);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Sends a line of text, as the message of a log event. Returns 0 once
+/// it is queued, or -1 when the pipeline is gone.
+///
+/// # Safety
+///
+/// `pipeline` must come from `vector_pipeline_start`, and `message` must
+/// point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vector_pipeline_send(
+    pipeline: *const VectorPipeline,
+    message: *const c_char,
+    len: usize,
+) -> c_int {
+    if pipeline.is_null() || message.is_null() {
+        return -1;
+    }
+    let message = slice::from_raw_parts(message as *const u8, len);
+    let pipeline = &*pipeline;
+    catch(-1, || {
+        pipeline.send(Event::from(String::from_utf8_lossy(message).as_ref()))
+    })
+}
+
+/// Sends a JSON object, as the fields of a log event timestamped now unless
+/// it has a timestamp. Returns 0 once it is queued, or -1 when the JSON
+/// isn't an object or the pipeline is gone.
+///
+/// # Safety
+///
+/// `pipeline` must come from `vector_pipeline_start`, and `json` must
+/// point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vector_pipeline_send_json(
+    pipeline: *const VectorPipeline,
+    json: *const c_char,
+    len: usize,
+) -> c_int {
+    if pipeline.is_null() || json.is_null() {
+        return -1;
+    }
+    let json = slice::from_raw_parts(json as *const u8, len);
+    let pipeline = &*pipeline;
+    catch(-1, || {
+        let fields =
+            match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(json) {
+                Ok(fields) => fields,
+                Err(_) => return -1,
+            };
+        let mut event = Event::new_empty_log();
+        let log = event.as_mut_log();
+        for (key, value) in fields {
+            log.insert_flat(key, Value::from(value));
+        }
+        let timestamp_key = event::log_schema().timestamp_key();
+        if !log.contains(timestamp_key) {
+            log.insert(timestamp_key, Utc::now());
+        }
+        pipeline.send(event)
+    })
+}
+
+/// Stops the pipeline once the events sent went through it, and frees it.
+///
+/// # Safety
+///
+/// `pipeline` must come from `vector_pipeline_start`, and isn't to be used
+/// after.
+#[no_mangle]
+pub unsafe extern "C" fn vector_pipeline_stop(pipeline: *mut VectorPipeline) {
+    if pipeline.is_null() {
+        return;
+    }
+    let VectorPipeline { pipeline, input } = *Box::from_raw(pipeline);
+    // The input ends with its sender.
+    drop(input);
+    catch((), || pipeline.wait());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures01::{sync::mpsc, Stream};
+    use std::{
+        ffi::CString,
+        sync::{mpsc as std_mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn send_blocks_while_input_is_full() {
+        // Holds the buffered event, and one for the sender.
+        let (sender, receiver) = mpsc::channel(1);
+        let input = Arc::new(Input::new(sender));
+        let (sent_tx, sent) = std_mpsc::channel();
+
+        let producer = {
+            let input = Arc::clone(&input);
+            thread::spawn(move || {
+                for i in 0..5 {
+                    assert_eq!(input.send(Event::from("hello")), 0);
+                    sent_tx.send(i).unwrap();
+                }
+            })
+        };
+
+        assert_eq!(sent.recv(), Ok(0));
+        assert_eq!(sent.recv(), Ok(1));
+        // Not sent however long it's waited for, the input being full.
+        assert!(sent.recv_timeout(Duration::from_millis(100)).is_err());
+
+        let mut receiver = receiver.wait();
+        receiver.next().unwrap().unwrap();
+        assert_eq!(sent.recv(), Ok(2));
+
+        assert_eq!(receiver.take(4).count(), 4);
+        producer.join().unwrap();
+        assert_eq!(sent.iter().collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn sends_through_pipeline() {
+        let config = CString::new(
+            r#"
+            [sinks.out]
+            type = "blackhole"
+            inputs = ["app"]
+            print_amount = 1000
+            "#,
+        )
+        .unwrap();
+        let input = CString::new("app").unwrap();
+        let mut error = [0 as c_char; 256];
+
+        unsafe {
+            let pipeline = vector_pipeline_start(
+                config.as_ptr(),
+                input.as_ptr(),
+                error.as_mut_ptr(),
+                error.len(),
+            );
+            assert!(!pipeline.is_null());
+
+            let message = "hello";
+            let sent = vector_pipeline_send(pipeline, message.as_ptr() as *const c_char, 5);
+            assert_eq!(sent, 0);
+
+            let json = r#"{"message": "hello", "level": "info"}"#;
+            let sent =
+                vector_pipeline_send_json(pipeline, json.as_ptr() as *const c_char, json.len());
+            assert_eq!(sent, 0);
+            let sent = vector_pipeline_send_json(pipeline, message.as_ptr() as *const c_char, 5);
+            assert_eq!(sent, -1);
+
+            vector_pipeline_stop(pipeline);
+        }
+    }
+
+    #[test]
+    fn reports_config_errors() {
+        let config = CString::new("[sinks.out]\ntype = \"nope\"").unwrap();
+        let input = CString::new("app").unwrap();
+        let mut error = [0 as c_char; 256];
+
+        let pipeline = unsafe {
+            vector_pipeline_start(
+                config.as_ptr(),
+                input.as_ptr(),
+                error.as_mut_ptr(),
+                error.len(),
+            )
+        };
+        assert!(pipeline.is_null());
+        let error = unsafe { CStr::from_ptr(error.as_ptr()) };
+        assert!(error.to_str().unwrap().contains("nope"));
+    }
+}
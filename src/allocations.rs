@@ -0,0 +1,238 @@
+//! An allocator attributing the memory it hands out to the component whose
+//! task allocated it, to find which part of a config uses the most memory.
+//!
+//! Memory is attributed until freed, so events waiting in the buffer of a
+//! sink count against the component that produced them.
+
+use crate::internal_events::{ComponentAllocationsReported, UnattributedAllocationsReported};
+use futures01::{Future, Poll, Stream};
+use once_cell::sync::Lazy;
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    mem, ptr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio01::timer::Interval;
+
+static GROUPS: Lazy<Mutex<Vec<&'static Group>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// All the memory allocated, attributed or not.
+static TOTAL: AtomicI64 = AtomicI64::new(0);
+
+thread_local! {
+    /// The group of the task being polled, as a pointer, or 0 if none.
+    static CURRENT: Cell<usize> = Cell::new(0);
+}
+
+#[derive(Debug)]
+pub struct Group {
+    pub component_kind: &'static str,
+    pub component_name: String,
+    allocated: AtomicI64,
+}
+
+impl Group {
+    /// The group of the component, shared by its tasks and kept across
+    /// reloads.
+    pub fn get(component_kind: &'static str, component_name: &str) -> &'static Group {
+        let mut groups = GROUPS.lock().unwrap();
+        if let Some(group) = groups.iter().find(|group| {
+            group.component_kind == component_kind && group.component_name == component_name
+        }) {
+            return group;
+        }
+
+        let group = Box::leak(Box::new(Group {
+            component_kind,
+            component_name: component_name.to_owned(),
+            allocated: AtomicI64::new(0),
+        }));
+        groups.push(group);
+        group
+    }
+
+    pub fn allocated_bytes(&self) -> i64 {
+        self.allocated.load(Ordering::Relaxed)
+    }
+
+    /// Runs the closure, attributing what it allocates to the group.
+    pub fn enter<T>(&'static self, f: impl FnOnce() -> T) -> T {
+        let previous = CURRENT
+            .try_with(|current| current.replace(self as *const Group as usize))
+            .unwrap_or(0);
+        let result = f();
+        let _ = CURRENT.try_with(|current| current.set(previous));
+        result
+    }
+}
+
+/// The groups of every component that allocated, with the memory still
+/// allocated by each, and the memory allocated outside of any.
+pub fn usage() -> (Vec<&'static Group>, i64) {
+    let groups = GROUPS.lock().unwrap().clone();
+    let attributed: i64 = groups.iter().map(|group| group.allocated_bytes()).sum();
+    let unattributed = TOTAL.load(Ordering::Relaxed) - attributed;
+    (groups, unattributed)
+}
+
+/// Attributes what the future allocates while polled to the component.
+pub fn track<F: Future>(
+    component_kind: &'static str,
+    component_name: &str,
+    inner: F,
+) -> Tracked<F> {
+    Tracked {
+        group: Group::get(component_kind, component_name),
+        inner,
+    }
+}
+
+pub struct Tracked<F> {
+    group: &'static Group,
+    inner: F,
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = &mut self.inner;
+        self.group.enter(|| inner.poll())
+    }
+}
+
+/// Reports the memory allocated by each component every second.
+pub fn report_periodically() -> impl Future<Item = (), Error = ()> {
+    Interval::new(Instant::now(), Duration::from_secs(1))
+        .map_err(|error| error!(message = "Timer error.", %error))
+        .for_each(|_| {
+            let (groups, unattributed) = usage();
+            for group in groups {
+                emit!(ComponentAllocationsReported { group });
+            }
+            emit!(UnattributedAllocationsReported {
+                bytes: unattributed
+            });
+            Ok(())
+        })
+}
+
+/// Wraps an allocator, prefixing each allocation with a header naming the
+/// group it's attributed to, so it's freed from the same group.
+pub struct TrackingAllocator<A> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+/// The header keeps the allocation aligned, and is at least a pointer wide.
+fn header_size(layout: &Layout) -> usize {
+    layout.align().max(mem::size_of::<usize>())
+}
+
+fn with_header(layout: Layout, size: usize) -> Layout {
+    // The size can't overflow as allocations are at most `isize::MAX` bytes.
+    unsafe { Layout::from_size_align_unchecked(size + header_size(&layout), layout.align()) }
+}
+
+fn current_group() -> usize {
+    CURRENT.try_with(Cell::get).unwrap_or(0)
+}
+
+unsafe fn account(group: usize, bytes: i64) {
+    TOTAL.fetch_add(bytes, Ordering::Relaxed);
+    if group != 0 {
+        (*(group as *const Group))
+            .allocated
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Writes the group at the end of the header, returning the allocation.
+unsafe fn tag(base: *mut u8, header: usize, group: usize) -> *mut u8 {
+    if base.is_null() {
+        return base;
+    }
+    let allocation = base.add(header);
+    ptr::write_unaligned(allocation.sub(mem::size_of::<usize>()) as *mut usize, group);
+    allocation
+}
+
+unsafe fn group_of(allocation: *mut u8) -> usize {
+    ptr::read_unaligned(allocation.sub(mem::size_of::<usize>()) as *const usize)
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let header = header_size(&layout);
+        let base = self.inner.alloc(with_header(layout, layout.size()));
+        let group = current_group();
+        if !base.is_null() {
+            account(group, layout.size() as i64);
+        }
+        tag(base, header, group)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let header = header_size(&layout);
+        let base = self.inner.alloc_zeroed(with_header(layout, layout.size()));
+        let group = current_group();
+        if !base.is_null() {
+            account(group, layout.size() as i64);
+        }
+        tag(base, header, group)
+    }
+
+    unsafe fn dealloc(&self, allocation: *mut u8, layout: Layout) {
+        let header = header_size(&layout);
+        account(group_of(allocation), -(layout.size() as i64));
+        self.inner
+            .dealloc(allocation.sub(header), with_header(layout, layout.size()));
+    }
+
+    unsafe fn realloc(&self, allocation: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let header = header_size(&layout);
+        // The memory stays with the group it was first allocated by.
+        let group = group_of(allocation);
+        let base = self.inner.realloc(
+            allocation.sub(header),
+            with_header(layout, layout.size()),
+            new_size + header,
+        );
+        if !base.is_null() {
+            account(group, new_size as i64 - layout.size() as i64);
+        }
+        tag(base, header, group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_attributed_until_freed() {
+        let group = Group::get("transform", "allocations_test");
+        let before = group.allocated_bytes();
+
+        let mut allocated = group.enter(|| Vec::<u8>::with_capacity(1 << 20));
+        assert_eq!(group.allocated_bytes() - before, 1 << 20);
+
+        // Growing outside of the group keeps attributing to it.
+        allocated.reserve_exact(2 << 20);
+        assert_eq!(group.allocated_bytes() - before, 2 << 20);
+
+        drop(allocated);
+        assert_eq!(group.allocated_bytes(), before);
+    }
+}
@@ -0,0 +1,359 @@
+//! Authentication shared by the `azure_event_hubs` source and sink.
+
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Instance metadata endpoint handing out tokens for managed identities.
+const MANAGED_IDENTITY_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+pub const EVENT_HUBS_RESOURCE: &str = "https://eventhubs.azure.net";
+
+pub const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// SAS tokens are minted per request, so a short lifetime is plenty.
+const SAS_TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Snafu)]
+pub enum AzureError {
+    #[snafu(display("Connection string is missing `{}`", key))]
+    MissingConnectionStringKey { key: &'static str },
+    #[snafu(display("Connection string endpoint {:?} is not a valid URL", endpoint))]
+    InvalidEndpoint { endpoint: String },
+    #[snafu(display("One of `namespace` or `connection_string` must be set"))]
+    MissingNamespace,
+    #[snafu(display(
+        "One of `event_hub_name` or an `EntityPath` in the connection string must be set"
+    ))]
+    MissingEventHubName,
+    #[snafu(display("Both `shared_access_key_name` and `shared_access_key` must be set"))]
+    IncompleteSharedAccessKey,
+    #[snafu(display("Failed to get managed identity token: {}", source))]
+    GetManagedIdentityToken { source: reqwest::Error },
+    #[snafu(display("Managed identity token request returned {}", status))]
+    ManagedIdentityStatus { status: reqwest::StatusCode },
+}
+
+/// The parts of an Event Hubs (or any Service Bus) connection string, e.g.
+/// `Endpoint=sb://ns.servicebus.windows.net/;SharedAccessKeyName=name;SharedAccessKey=key;EntityPath=hub`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionString {
+    pub namespace_host: String,
+    pub shared_access_key_name: String,
+    pub shared_access_key: String,
+    pub entity_path: Option<String>,
+}
+
+impl ConnectionString {
+    pub fn parse(s: &str) -> Result<Self, AzureError> {
+        let parts = connection_string_parts(s);
+        let get = |key: &'static str| connection_string_part(&parts, key);
+
+        let endpoint = get("Endpoint")?;
+        let namespace_host = url::Url::parse(&endpoint)
+            .ok()
+            .and_then(|url| url.host_str().map(Into::into))
+            .ok_or(AzureError::InvalidEndpoint { endpoint })?;
+
+        Ok(Self {
+            namespace_host,
+            shared_access_key_name: get("SharedAccessKeyName")?,
+            shared_access_key: get("SharedAccessKey")?,
+            entity_path: parts.get("EntityPath").map(|value| value.to_string()),
+        })
+    }
+}
+
+/// Splits the `Key=value` pairs of any Azure connection string.
+pub fn connection_string_parts(s: &str) -> HashMap<&str, &str> {
+    s.split(';')
+        .filter_map(|part| {
+            let mut kv = part.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) => Some((key.trim(), value.trim())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn connection_string_part(
+    parts: &HashMap<&str, &str>,
+    key: &'static str,
+) -> Result<String, AzureError> {
+    parts
+        .get(key)
+        .map(|value| value.to_string())
+        .ok_or(AzureError::MissingConnectionStringKey { key })
+}
+
+/// Builds a Shared Access Signature token for the given resource URI.
+pub fn sas_token(resource_uri: &str, key_name: &str, key: &str, expiry: SystemTime) -> String {
+    let expiry = expiry
+        .duration_since(UNIX_EPOCH)
+        .expect("Time can't drift behind the epoch!")
+        .as_secs();
+    let encoded_uri =
+        url::form_urlencoded::byte_serialize(resource_uri.as_bytes()).collect::<String>();
+    let to_sign = format!("{}\n{}", encoded_uri, expiry);
+
+    let signature = base64::encode(&hmac_sha256(key.as_bytes(), to_sign.as_bytes()));
+
+    format!(
+        "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+        encoded_uri,
+        url::form_urlencoded::byte_serialize(signature.as_bytes()).collect::<String>(),
+        expiry,
+        key_name
+    )
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).expect("HMAC keys can be of any length");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("SHA256 is supported");
+    signer.update(data).expect("Signing into memory can't fail");
+    signer.sign_to_vec().expect("Signing can't fail")
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AzureAuthConfig {
+    pub connection_string: Option<String>,
+    pub shared_access_key_name: Option<String>,
+    pub shared_access_key: Option<String>,
+    /// Object id of a user-assigned identity. Defaults to the system-assigned one.
+    pub managed_identity_client_id: Option<String>,
+}
+
+impl AzureAuthConfig {
+    pub fn parse_connection_string(&self) -> Result<Option<ConnectionString>, AzureError> {
+        self.connection_string
+            .as_ref()
+            .map(|s| ConnectionString::parse(s))
+            .transpose()
+    }
+
+    /// The host of the namespace and the name of the event hub, from the
+    /// options or else the connection string.
+    pub fn event_hub(
+        &self,
+        namespace: Option<&str>,
+        event_hub_name: Option<&str>,
+    ) -> Result<(String, String), AzureError> {
+        let parsed = self.parse_connection_string()?;
+        let host = match (namespace, &parsed) {
+            (Some(namespace), _) => format!("{}.servicebus.windows.net", namespace),
+            (None, Some(parsed)) => parsed.namespace_host.clone(),
+            (None, None) => return Err(AzureError::MissingNamespace),
+        };
+        let event_hub_name = event_hub_name
+            .map(Into::into)
+            .or_else(|| parsed.and_then(|parsed| parsed.entity_path))
+            .ok_or(AzureError::MissingEventHubName)?;
+        Ok((host, event_hub_name))
+    }
+
+    /// Prefers shared access keys, from the connection string or given
+    /// explicitly, and falls back to the managed identity of the host.
+    pub fn make_credentials(&self) -> Result<AzureCredentials, AzureError> {
+        if let Some(parsed) = self.parse_connection_string()? {
+            return Ok(AzureCredentials::SharedAccessKey {
+                key_name: parsed.shared_access_key_name,
+                key: parsed.shared_access_key,
+            });
+        }
+
+        match (&self.shared_access_key_name, &self.shared_access_key) {
+            (Some(key_name), Some(key)) => Ok(AzureCredentials::SharedAccessKey {
+                key_name: key_name.clone(),
+                key: key.clone(),
+            }),
+            (None, None) => Ok(AzureCredentials::ManagedIdentity(ManagedIdentity::new(
+                self.managed_identity_client_id.clone(),
+                EVENT_HUBS_RESOURCE,
+            )?)),
+            _ => Err(AzureError::IncompleteSharedAccessKey),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum AzureCredentials {
+    SharedAccessKey { key_name: String, key: String },
+    ManagedIdentity(ManagedIdentity),
+}
+
+impl AzureCredentials {
+    /// The `Authorization` header value for a request to `resource_uri`.
+    pub fn authorization(&self, resource_uri: &str) -> String {
+        match self {
+            AzureCredentials::SharedAccessKey { key_name, key } => sas_token(
+                resource_uri,
+                key_name,
+                key,
+                SystemTime::now() + SAS_TOKEN_TTL,
+            ),
+            AzureCredentials::ManagedIdentity(identity) => {
+                format!("Bearer {}", identity.access_token())
+            }
+        }
+    }
+
+    /// The type and value of the token put to the claims-based security
+    /// node of an AMQP connection, to access `audience`.
+    pub fn cbs_token(&self, audience: &str) -> (&'static str, String) {
+        match self {
+            AzureCredentials::SharedAccessKey { key_name, key } => (
+                "servicebus.windows.net:sastoken",
+                sas_token(audience, key_name, key, SystemTime::now() + SAS_TOKEN_TTL),
+            ),
+            AzureCredentials::ManagedIdentity(identity) => ("jwt", identity.access_token()),
+        }
+    }
+
+    /// Managed identity tokens expire, so keep them fresh in the background.
+    pub fn spawn_regenerate_token(&self) {
+        if let AzureCredentials::ManagedIdentity(identity) = self {
+            identity.spawn_regenerate_token();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ManagedIdentityToken {
+    access_token: String,
+    // The token endpoint returns this as a string.
+    expires_in: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ManagedIdentity {
+    client_id: Option<String>,
+    resource: &'static str,
+    token: Arc<RwLock<ManagedIdentityToken>>,
+}
+
+impl ManagedIdentity {
+    pub fn new(client_id: Option<String>, resource: &'static str) -> Result<Self, AzureError> {
+        let token = get_managed_identity_token(client_id.as_ref(), resource)?;
+        Ok(Self {
+            client_id,
+            resource,
+            token: Arc::new(RwLock::new(token)),
+        })
+    }
+
+    pub fn access_token(&self) -> String {
+        self.token.read().unwrap().access_token.clone()
+    }
+
+    pub fn spawn_regenerate_token(&self) {
+        use futures01::{Future, Stream};
+        use tokio01::timer::Interval;
+
+        let expires_in = self
+            .token
+            .read()
+            .unwrap()
+            .expires_in
+            .parse::<u64>()
+            .unwrap_or(3600);
+        let interval = (expires_in / 2).max(1);
+        let copy = self.clone();
+        let renew_task = Interval::new_interval(Duration::from_secs(interval))
+            .for_each(move |_instant| {
+                debug!("Renewing Azure managed identity token");
+                match get_managed_identity_token(copy.client_id.as_ref(), copy.resource) {
+                    Ok(token) => *copy.token.write().unwrap() = token,
+                    Err(error) => {
+                        error!(message = "Failed to update Azure managed identity token", %error)
+                    }
+                }
+                Ok(())
+            })
+            .map_err(
+                |error| error!(message = "Azure managed identity token regenerate interval failed", %error),
+            );
+
+        tokio01::spawn(renew_task);
+    }
+}
+
+fn get_managed_identity_token(
+    client_id: Option<&String>,
+    resource: &str,
+) -> Result<ManagedIdentityToken, AzureError> {
+    let mut query = vec![("api-version", "2018-02-01"), ("resource", resource)];
+    if let Some(client_id) = client_id {
+        query.push(("client_id", client_id));
+    }
+
+    let mut response = reqwest::Client::new()
+        .get(MANAGED_IDENTITY_TOKEN_URL)
+        .query(&query)
+        .header("Metadata", "true")
+        .send()
+        .context(GetManagedIdentityToken)?;
+    if !response.status().is_success() {
+        return Err(AzureError::ManagedIdentityStatus {
+            status: response.status(),
+        });
+    }
+    response.json().context(GetManagedIdentityToken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_connection_string() {
+        let parsed = ConnectionString::parse(
+            "Endpoint=sb://vector.servicebus.windows.net/;SharedAccessKeyName=send;SharedAccessKey=c2VjcmV0PQ==;EntityPath=logs",
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed,
+            ConnectionString {
+                namespace_host: "vector.servicebus.windows.net".into(),
+                shared_access_key_name: "send".into(),
+                shared_access_key: "c2VjcmV0PQ==".into(),
+                entity_path: Some("logs".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_connection_string_missing_key() {
+        let error = ConnectionString::parse(
+            "Endpoint=sb://vector.servicebus.windows.net/;SharedAccessKeyName=send",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Connection string is missing `SharedAccessKey`"
+        );
+    }
+
+    #[test]
+    fn sas_token_format() {
+        let expiry = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let token = sas_token(
+            "https://vector.servicebus.windows.net/logs",
+            "send",
+            "secret",
+            expiry,
+        );
+
+        assert!(token.starts_with(
+            "SharedAccessSignature sr=https%3A%2F%2Fvector.servicebus.windows.net%2Flogs&sig="
+        ));
+        assert!(token.ends_with("&se=1600000000&skn=send"));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::event::{proto, Event};
+use crate::{
+    event::{proto, Event},
+    internal_events::{DiskBufferCompacted, DiskBufferEventCorrupted, DiskBufferSize},
+};
 use futures01::{
     task::{self, AtomicTask, Task},
     Async, AsyncSink, Poll, Sink, Stream,
@@ -19,8 +22,9 @@ use std::{
     path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc, Mutex,
+        mpsc, Arc, Mutex,
     },
+    thread,
 };
 
 use super::{DataDirOpenError, Error};
@@ -42,7 +46,56 @@ impl db_key::Key for Key {
     }
 }
 
+/// Compaction is requested once this many bytes have been deleted since the
+/// last compaction, or once the buffer drains completely.
+const COMPACTION_THRESHOLD: usize = 16 * 1024 * 1024;
+
+/// Tracks the on-disk size of the buffer, shared between the writers and the
+/// reader. Sizes are the encoded event sizes, which is what leveldb stores
+/// before its own compression.
+#[derive(Debug, Default)]
+struct Usage {
+    bytes: AtomicUsize,
+    events: AtomicUsize,
+}
+
+impl Usage {
+    /// Reserves room for an event, failing if the buffer would grow past
+    /// `max_size`. An empty buffer always accepts an event, so a single event
+    /// larger than `max_size` can't wedge the sink forever.
+    fn try_reserve(&self, event_size: usize, max_size: usize) -> bool {
+        let mut current = self.bytes.load(Ordering::Acquire);
+        loop {
+            if current > 0 && current + event_size > max_size {
+                return false;
+            }
+            let previous =
+                self.bytes
+                    .compare_and_swap(current, current + event_size, Ordering::AcqRel);
+            if previous == current {
+                self.events.fetch_add(1, Ordering::AcqRel);
+                return true;
+            }
+            current = previous;
+        }
+    }
+
+    fn release(&self, bytes: usize, events: usize) {
+        self.bytes.fetch_sub(bytes, Ordering::AcqRel);
+        self.events.fetch_sub(events, Ordering::AcqRel);
+    }
+
+    fn emit(&self, name: &str) {
+        emit!(DiskBufferSize {
+            name,
+            byte_size: self.bytes.load(Ordering::Acquire),
+            events: self.events.load(Ordering::Acquire),
+        });
+    }
+}
+
 pub struct Writer {
+    name: Arc<str>,
     db: Arc<Database<Key>>,
     offset: Arc<AtomicUsize>,
     write_notifier: Arc<AtomicTask>,
@@ -50,7 +103,7 @@ pub struct Writer {
     writebatch: Writebatch<Key>,
     batch_size: usize,
     max_size: usize,
-    current_size: Arc<AtomicUsize>,
+    usage: Arc<Usage>,
 }
 
 // Writebatch isn't Send, but the leveldb docs explicitly say that it's okay to share across threads
@@ -59,6 +112,7 @@ unsafe impl Send for Writer {}
 impl Clone for Writer {
     fn clone(&self) -> Self {
         Self {
+            name: Arc::clone(&self.name),
             db: Arc::clone(&self.db),
             offset: Arc::clone(&self.offset),
             write_notifier: Arc::clone(&self.write_notifier),
@@ -66,7 +120,7 @@ impl Clone for Writer {
             writebatch: Writebatch::new(),
             batch_size: 0,
             max_size: self.max_size,
-            current_size: Arc::clone(&self.current_size),
+            usage: Arc::clone(&self.usage),
         }
     }
 }
@@ -83,20 +137,20 @@ impl Sink for Writer {
         proto::EventWrapper::from(event).encode(&mut value).unwrap(); // This will not error when writing to a Vec
         let event_size = value.len();
 
-        if self.current_size.fetch_add(event_size, Ordering::Relaxed) + (event_size / 2)
-            > self.max_size
-        {
+        if !self.usage.try_reserve(event_size, self.max_size) {
             self.blocked_write_tasks
                 .lock()
                 .unwrap()
                 .push(task::current());
 
-            self.current_size.fetch_sub(event_size, Ordering::Relaxed);
+            // The reader may have freed up space between the check above and
+            // registering this task, so check once more before parking.
+            if !self.usage.try_reserve(event_size, self.max_size) {
+                self.poll_complete()?;
 
-            self.poll_complete()?;
-
-            let event = proto::EventWrapper::decode(value).unwrap().into();
-            return Ok(AsyncSink::NotReady(event));
+                let event = proto::EventWrapper::decode(value).unwrap().into();
+                return Ok(AsyncSink::NotReady(event));
+            }
         }
 
         let key = self.offset.fetch_add(1, Ordering::Relaxed);
@@ -131,6 +185,7 @@ impl Writer {
         self.writebatch = Writebatch::new();
         self.batch_size = 0;
         self.write_notifier.notify();
+        self.usage.emit(&self.name);
     }
 }
 
@@ -146,15 +201,18 @@ impl Drop for Writer {
 }
 
 pub struct Reader {
+    name: Arc<str>,
     db: Arc<Database<Key>>,
     read_offset: usize,
     delete_offset: usize,
     write_notifier: Arc<AtomicTask>,
     blocked_write_tasks: Arc<Mutex<Vec<Task>>>,
-    current_size: Arc<AtomicUsize>,
+    usage: Arc<Usage>,
     ack_counter: Arc<AtomicUsize>,
-    unacked_sizes: VecDeque<usize>,
-    buffer: Vec<Vec<u8>>,
+    // Keys and sizes of events handed out but not yet acked, in read order.
+    unacked: VecDeque<(usize, usize)>,
+    buffer: Vec<(Key, Vec<u8>)>,
+    compactor: Compactor,
 }
 
 // Writebatch isn't Send, but the leveldb docs explicitly say that it's okay to share across threads
@@ -176,7 +234,7 @@ impl Stream for Reader {
             // the app), this will have to go to disk.
             let new_data = tokio::task::block_in_place(|| {
                 self.db
-                    .value_iter(ReadOptions::new())
+                    .iter(ReadOptions::new())
                     .from(&Key(self.read_offset))
                     .to(&Key(self.read_offset + 100))
                     .collect()
@@ -185,18 +243,22 @@ impl Stream for Reader {
             self.buffer.reverse(); // so we can pop
         }
 
-        if let Some(value) = self.buffer.pop() {
-            self.unacked_sizes.push_back(value.len());
-            self.read_offset += 1;
+        if let Some((key, value)) = self.buffer.pop() {
+            let event_size = value.len();
+            self.read_offset = key.0 + 1;
 
             match proto::EventWrapper::decode(value) {
                 Ok(event) => {
+                    self.unacked.push_back((key.0, event_size));
                     let event = Event::from(event);
                     Ok(Async::Ready(Some(event)))
                 }
-                Err(err) => {
-                    error!("Error deserializing proto: {:?}", err);
-                    debug_assert!(false);
+                Err(error) => {
+                    // The sink will never see this event, so it will never
+                    // ack it either. Drop it from disk right away so it
+                    // doesn't throw off the ack accounting of later events.
+                    emit!(DiskBufferEventCorrupted { error });
+                    self.delete(&[(key.0, event_size)]);
                     self.poll()
                 }
             }
@@ -212,6 +274,9 @@ impl Stream for Reader {
 impl Drop for Reader {
     fn drop(&mut self) {
         self.delete_acked();
+        // Leave the buffer compacted on shutdown rather than waiting for the
+        // next start to reclaim the space.
+        self.compactor.flush(&self.db);
     }
 }
 
@@ -220,30 +285,115 @@ impl Reader {
         let num_to_delete = self.ack_counter.swap(0, Ordering::Relaxed);
 
         if num_to_delete > 0 {
-            let new_offset = self.delete_offset + num_to_delete;
             assert!(
-                new_offset <= self.read_offset,
+                num_to_delete <= self.unacked.len(),
                 "Tried to ack beyond read offset"
             );
 
-            let mut delete_batch = Writebatch::new();
+            let acked = self.unacked.drain(..num_to_delete).collect::<Vec<_>>();
+            self.delete(&acked);
+        }
 
-            for i in self.delete_offset..new_offset {
-                delete_batch.delete(Key(i));
-            }
+        for task in self.blocked_write_tasks.lock().unwrap().drain(..) {
+            task.notify();
+        }
+    }
 
-            self.db.write(WriteOptions::new(), &delete_batch).unwrap();
+    fn delete(&mut self, entries: &[(usize, usize)]) {
+        let mut delete_batch = Writebatch::new();
+        let mut bytes = 0;
+        for (key, size) in entries {
+            delete_batch.delete(Key(*key));
+            bytes += size;
+            self.delete_offset = self.delete_offset.max(key + 1);
+        }
 
-            self.delete_offset = new_offset;
+        self.db.write(WriteOptions::new(), &delete_batch).unwrap();
 
-            self.db.compact(&Key(0), &Key(self.delete_offset));
+        self.usage.release(bytes, entries.len());
+        self.usage.emit(&self.name);
 
-            let size_deleted = self.unacked_sizes.drain(..num_to_delete).sum();
-            self.current_size.fetch_sub(size_deleted, Ordering::Relaxed);
+        let drained = self.usage.events.load(Ordering::Acquire) == 0;
+        self.compactor.deleted(bytes, self.delete_offset, drained);
+    }
+}
+
+/// Hands compaction of deleted key ranges off to a background thread, so the
+/// reader doesn't stall on leveldb rewriting its tables.
+struct Compactor {
+    name: Arc<str>,
+    tx: Option<mpsc::Sender<usize>>,
+    handle: Option<thread::JoinHandle<()>>,
+    uncompacted_bytes: usize,
+    compacted_offset: usize,
+}
+
+impl Compactor {
+    fn new(
+        name: Arc<str>,
+        db: &Arc<Database<Key>>,
+        write_notifier: &Arc<AtomicTask>,
+        compacted_offset: usize,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<usize>();
+        let db = Arc::downgrade(db);
+        let write_notifier = Arc::clone(write_notifier);
+        let thread_name = Arc::clone(&name);
+
+        let handle = thread::Builder::new()
+            .name(format!("{}-compactor", name))
+            .spawn(move || {
+                while let Ok(offset) = rx.recv() {
+                    // Skip ahead to the most recent request if several piled up.
+                    let offset = rx.try_iter().fold(offset, usize::max);
+                    match db.upgrade() {
+                        Some(db) => {
+                            db.compact(&Key(0), &Key(offset));
+                            drop(db);
+                            emit!(DiskBufferCompacted { name: &thread_name });
+                            // Holding the database may have made the reader
+                            // think writers are still around, so wake it up
+                            // to look again.
+                            write_notifier.notify();
+                        }
+                        None => break,
+                    }
+                }
+            })
+            .expect("Unable to spawn buffer compaction thread");
+
+        Self {
+            name,
+            tx: Some(tx),
+            handle: Some(handle),
+            uncompacted_bytes: 0,
+            compacted_offset,
         }
+    }
 
-        for task in self.blocked_write_tasks.lock().unwrap().drain(..) {
-            task.notify();
+    fn deleted(&mut self, bytes: usize, offset: usize, drained: bool) {
+        self.uncompacted_bytes += bytes;
+        if offset > self.compacted_offset
+            && (drained || self.uncompacted_bytes >= COMPACTION_THRESHOLD)
+        {
+            if let Some(tx) = &self.tx {
+                let _ = tx.send(offset);
+            }
+            self.uncompacted_bytes = 0;
+            self.compacted_offset = offset;
+        }
+    }
+
+    /// Stops the background thread and compacts anything outstanding.
+    fn flush(&mut self, db: &Database<Key>) {
+        self.tx.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        if self.uncompacted_bytes > 0 {
+            tokio::task::block_in_place(|| db.compact(&Key(0), &Key(self.compacted_offset)));
+            emit!(DiskBufferCompacted { name: &self.name });
+            self.uncompacted_bytes = 0;
         }
     }
 }
@@ -254,7 +404,11 @@ impl super::DiskBuffer for Buffer {
     type Writer = Writer;
     type Reader = Reader;
 
-    fn build(path: PathBuf, max_size: usize) -> Result<(Self::Writer, Self::Reader, Acker), Error> {
+    fn build(
+        path: PathBuf,
+        max_size: usize,
+        name: &str,
+    ) -> Result<(Self::Writer, Self::Reader, Acker), Error> {
         let mut options = Options::new();
         options.create_if_missing = true;
 
@@ -263,6 +417,7 @@ impl super::DiskBuffer for Buffer {
                 data_dir: path.parent().expect("always a parent"),
             })?;
         let db = Arc::new(db);
+        let name: Arc<str> = name.into();
 
         let head;
         let tail;
@@ -273,8 +428,18 @@ impl super::DiskBuffer for Buffer {
             tail = if iter.valid() { iter.key().0 + 1 } else { 0 };
         }
 
-        let initial_size = db.value_iter(ReadOptions::new()).map(|v| v.len()).sum();
-        let current_size = Arc::new(AtomicUsize::new(initial_size));
+        // Deleted entries from a previous run may never have been compacted
+        // away, so reclaim that space before starting.
+        if head > 0 {
+            db.compact(&Key(0), &Key(head));
+        }
+
+        let usage = Arc::new(Usage::default());
+        for value in db.value_iter(ReadOptions::new()) {
+            usage.bytes.fetch_add(value.len(), Ordering::Relaxed);
+            usage.events.fetch_add(1, Ordering::Relaxed);
+        }
+        usage.emit(&name);
 
         let write_notifier = Arc::new(AtomicTask::new());
 
@@ -283,7 +448,10 @@ impl super::DiskBuffer for Buffer {
         let ack_counter = Arc::new(AtomicUsize::new(0));
         let acker = Acker::Disk(Arc::clone(&ack_counter), Arc::clone(&write_notifier));
 
+        let compactor = Compactor::new(Arc::clone(&name), &db, &write_notifier, head);
+
         let writer = Writer {
+            name: Arc::clone(&name),
             db: Arc::clone(&db),
             write_notifier: Arc::clone(&write_notifier),
             blocked_write_tasks: Arc::clone(&blocked_write_tasks),
@@ -291,21 +459,56 @@ impl super::DiskBuffer for Buffer {
             writebatch: Writebatch::new(),
             batch_size: 0,
             max_size,
-            current_size: Arc::clone(&current_size),
+            usage: Arc::clone(&usage),
         };
 
         let reader = Reader {
+            name,
             db: Arc::clone(&db),
             write_notifier: Arc::clone(&write_notifier),
             blocked_write_tasks,
             read_offset: head,
             delete_offset: head,
-            current_size,
+            usage,
             ack_counter,
-            unacked_sizes: VecDeque::new(),
+            unacked: VecDeque::new(),
             buffer: Vec::new(),
+            compactor,
         };
 
         Ok((writer, reader, acker))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Usage;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn usage_reserves_up_to_max_size() {
+        let usage = Usage::default();
+
+        assert!(usage.try_reserve(40, 100));
+        assert!(usage.try_reserve(60, 100));
+        assert!(!usage.try_reserve(1, 100));
+        assert_eq!(usage.bytes.load(Ordering::Relaxed), 100);
+        assert_eq!(usage.events.load(Ordering::Relaxed), 2);
+
+        usage.release(40, 1);
+        assert!(usage.try_reserve(40, 100));
+        assert_eq!(usage.events.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn usage_accepts_oversized_event_when_empty() {
+        let usage = Usage::default();
+
+        assert!(usage.try_reserve(500, 100));
+        assert!(!usage.try_reserve(1, 100));
+
+        usage.release(500, 1);
+        assert_eq!(usage.bytes.load(Ordering::Relaxed), 0);
+        assert_eq!(usage.events.load(Ordering::Relaxed), 0);
+    }
+}
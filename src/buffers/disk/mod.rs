@@ -33,6 +33,7 @@ pub trait DiskBuffer {
     fn build(
         path: PathBuf,
         max_size: usize,
+        name: &str,
     ) -> Result<(Self::Writer, Self::Reader, super::Acker), Error>;
 }
 
@@ -59,7 +60,7 @@ impl Sink for Writer {
 
 pub fn open(
     data_dir: &Path,
-    name: &str,
+    sink_name: &str,
     max_size: usize,
 ) -> Result<
     (
@@ -69,7 +70,7 @@ pub fn open(
     ),
     Error,
 > {
-    let path = data_dir.join(name);
+    let path = data_dir.join(format!("{}_buffer", sink_name));
 
     // Check data dir
     std::fs::metadata(&data_dir)
@@ -95,6 +96,6 @@ pub fn open(
             }
         })?;
 
-    let (writer, reader, acker) = leveldb_buffer::Buffer::build(path, max_size)?;
+    let (writer, reader, acker) = leveldb_buffer::Buffer::build(path, max_size, sink_name)?;
     Ok((Writer { inner: writer }, Box::new(reader), acker))
 }
@@ -1,4 +1,4 @@
-use crate::Event;
+use crate::{topology::config::defaults_profile, Event};
 use futures01::{sync::mpsc, task::AtomicTask, AsyncSink, Poll, Sink, StartSend, Stream};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -9,14 +9,18 @@ use std::sync::{
 
 #[cfg(feature = "leveldb")]
 pub mod disk;
+pub mod usage;
+
+use usage::{BufferUsage, UsageSink, UsageStream};
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum BufferConfig {
     Memory {
-        #[serde(default = "BufferConfig::memory_max_events")]
-        max_events: usize,
+        /// Defaults to 500, adjusted by the defaults profile.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_events: Option<usize>,
         #[serde(default)]
         when_full: WhenFull,
     },
@@ -31,7 +35,7 @@ pub enum BufferConfig {
 impl Default for BufferConfig {
     fn default() -> Self {
         BufferConfig::Memory {
-            max_events: BufferConfig::memory_max_events(),
+            max_events: None,
             when_full: Default::default(),
         }
     }
@@ -51,16 +55,18 @@ impl Default for WhenFull {
 }
 
 pub enum BufferInputCloner {
-    Memory(mpsc::Sender<Event>, WhenFull),
+    Memory(mpsc::Sender<Event>, WhenFull, Arc<BufferUsage>),
     #[cfg(feature = "leveldb")]
-    Disk(disk::Writer, WhenFull),
+    Disk(disk::Writer, WhenFull, Arc<BufferUsage>),
 }
 
 impl BufferInputCloner {
     pub fn get(&self) -> Box<dyn Sink<SinkItem = Event, SinkError = ()> + Send> {
         match self {
-            BufferInputCloner::Memory(tx, when_full) => {
+            BufferInputCloner::Memory(tx, when_full, usage) => {
                 let inner = tx.clone().sink_map_err(|e| error!("sender error: {:?}", e));
+                // Dropped events never reach the buffer, so aren't counted.
+                let inner = UsageSink::new(inner, Arc::clone(usage));
                 if when_full == &WhenFull::DropNewest {
                     Box::new(DropWhenFull { inner })
                 } else {
@@ -69,13 +75,12 @@ impl BufferInputCloner {
             }
 
             #[cfg(feature = "leveldb")]
-            BufferInputCloner::Disk(writer, when_full) => {
+            BufferInputCloner::Disk(writer, when_full, usage) => {
+                let inner = UsageSink::new(writer.clone(), Arc::clone(usage));
                 if when_full == &WhenFull::DropNewest {
-                    Box::new(DropWhenFull {
-                        inner: writer.clone(),
-                    })
+                    Box::new(DropWhenFull { inner })
                 } else {
-                    Box::new(writer.clone())
+                    Box::new(inner)
                 }
             }
         }
@@ -106,9 +111,13 @@ impl BufferConfig {
                 max_events,
                 when_full,
             } => {
-                let (tx, rx) = mpsc::channel(*max_events);
-                let tx = BufferInputCloner::Memory(tx, *when_full);
-                let rx = Box::new(rx);
+                let max_events = max_events.unwrap_or_else(|| {
+                    defaults_profile().memory_buffer_events(BufferConfig::memory_max_events())
+                });
+                let (tx, rx) = mpsc::channel(max_events);
+                let usage = BufferUsage::register(sink_name, "sink", "memory");
+                let tx = BufferInputCloner::Memory(tx, *when_full, Arc::clone(&usage));
+                let rx = Box::new(UsageStream::new(rx, usage));
                 Ok((tx, rx, Acker::Null))
             }
 
@@ -120,12 +129,11 @@ impl BufferConfig {
                 let data_dir = data_dir
                     .as_ref()
                     .ok_or_else(|| "Must set data_dir to use on-disk buffering.".to_string())?;
-                let buffer_dir = format!("{}_buffer", sink_name);
-
-                let (tx, rx, acker) = disk::open(&data_dir, buffer_dir.as_ref(), *max_size)
-                    .map_err(|err| err.to_string())?;
-                let tx = BufferInputCloner::Disk(tx, *when_full);
-                let rx = Box::new(rx);
+                let (tx, rx, acker) =
+                    disk::open(&data_dir, sink_name, *max_size).map_err(|err| err.to_string())?;
+                let usage = BufferUsage::register(sink_name, "sink", "disk");
+                let tx = BufferInputCloner::Disk(tx, *when_full, Arc::clone(&usage));
+                let rx = Box::new(UsageStream::new(rx, usage));
                 Ok((tx, rx, acker))
             }
         }
@@ -252,7 +260,7 @@ mod test {
           type = "memory"
           "#,
             BufferConfig::Memory {
-                max_events: 500,
+                max_events: None,
                 when_full: WhenFull::Block,
             },
         );
@@ -263,7 +271,7 @@ mod test {
           max_events = 100
           "#,
             BufferConfig::Memory {
-                max_events: 100,
+                max_events: Some(100),
                 when_full: WhenFull::Block,
             },
         );
@@ -274,7 +282,7 @@ mod test {
           when_full = "drop_newest"
           "#,
             BufferConfig::Memory {
-                max_events: 500,
+                max_events: None,
                 when_full: WhenFull::DropNewest,
             },
         );
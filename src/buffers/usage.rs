@@ -0,0 +1,259 @@
+//! How many events wait in each buffer, about how large they are and how
+//! long the oldest of them has waited, to tell time spent buffered apart from
+//! time spent batching or sending.
+
+use crate::{
+    event::{metric::Metric, Event, Value},
+    internal_events::BufferUsageReported,
+};
+use futures01::{Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
+use once_cell::sync::Lazy;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    mem,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+use tokio01::timer::Interval;
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Buffers are forgotten once their component is removed, or dropped.
+static USAGES: Lazy<Mutex<BTreeMap<String, Weak<BufferUsage>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug)]
+pub struct BufferUsage {
+    component_kind: &'static str,
+    buffer_type: &'static str,
+    state: Mutex<UsageState>,
+}
+
+#[derive(Debug, Default)]
+struct UsageState {
+    /// When each event was buffered, and its estimated size.
+    buffered: VecDeque<(Instant, usize)>,
+    byte_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferSnapshot {
+    pub component_kind: &'static str,
+    pub buffer_type: &'static str,
+    pub events: usize,
+    pub byte_size: usize,
+    pub oldest_event_age: Option<Duration>,
+}
+
+impl BufferUsage {
+    /// Tracks the buffer of the component, replacing the one it had.
+    pub fn register(
+        name: &str,
+        component_kind: &'static str,
+        buffer_type: &'static str,
+    ) -> Arc<Self> {
+        let usage = Arc::new(Self {
+            component_kind,
+            buffer_type,
+            state: Mutex::new(UsageState::default()),
+        });
+        USAGES
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), Arc::downgrade(&usage));
+        usage
+    }
+
+    fn pop(&self) {
+        let mut state = self.state.lock().unwrap();
+        // Events a disk buffer kept from a previous run were never counted.
+        if let Some((_, size)) = state.buffered.pop_front() {
+            state.byte_size -= size;
+        }
+    }
+
+    pub fn snapshot(&self) -> BufferSnapshot {
+        let state = self.state.lock().unwrap();
+        BufferSnapshot {
+            component_kind: self.component_kind,
+            buffer_type: self.buffer_type,
+            events: state.buffered.len(),
+            byte_size: state.byte_size,
+            oldest_event_age: state.buffered.front().map(|(at, _)| at.elapsed()),
+        }
+    }
+}
+
+/// Stops tracking the buffer of a component removed on reload, which may
+/// still be drained for a while.
+pub fn forget(name: &str) {
+    USAGES.lock().unwrap().remove(name);
+}
+
+/// The usage of the buffers, by component.
+pub fn usages() -> BTreeMap<String, BufferSnapshot> {
+    let mut usages = USAGES.lock().unwrap();
+    usages.retain(|_, usage| usage.upgrade().is_some());
+    usages
+        .iter()
+        .filter_map(|(name, usage)| Some((name.clone(), usage.upgrade()?.snapshot())))
+        .collect()
+}
+
+/// Reports the usage of the buffers as internal metrics, periodically so the
+/// age of events stuck in a buffer keeps growing.
+pub fn report_periodically() -> impl Future<Item = (), Error = ()> {
+    Interval::new(Instant::now() + REPORT_INTERVAL, REPORT_INTERVAL)
+        .map_err(|error| error!(message = "Buffer usage timer failed.", %error))
+        .for_each(|_| {
+            for (name, snapshot) in usages() {
+                emit!(BufferUsageReported {
+                    name: &name,
+                    snapshot: &snapshot,
+                });
+            }
+            Ok(())
+        })
+}
+
+/// Counts the events written to a buffer.
+pub struct UsageSink<S> {
+    inner: S,
+    usage: Arc<BufferUsage>,
+}
+
+impl<S> UsageSink<S> {
+    pub fn new(inner: S, usage: Arc<BufferUsage>) -> Self {
+        Self { inner, usage }
+    }
+}
+
+impl<S: Sink<SinkItem = Event>> Sink for UsageSink<S> {
+    type SinkItem = Event;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, item: Event) -> StartSend<Event, Self::SinkError> {
+        let size = size_estimate(&item);
+        // Counted before it can be read, so the reader never misses it. The
+        // usage isn't locked while sending, which writes to a disk buffer.
+        {
+            let mut state = self.usage.state.lock().unwrap();
+            state.buffered.push_back((Instant::now(), size));
+            state.byte_size += size;
+        }
+        let result = self.inner.start_send(item);
+        match result {
+            Ok(AsyncSink::Ready) => (),
+            _ => {
+                // The last counted may be of another writer, which doesn't
+                // change the counts.
+                let mut state = self.usage.state.lock().unwrap();
+                if let Some((_, size)) = state.buffered.pop_back() {
+                    state.byte_size -= size;
+                }
+            }
+        }
+        result
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.inner.poll_complete()
+    }
+}
+
+/// Counts the events read from a buffer.
+pub struct UsageStream<S> {
+    inner: S,
+    usage: Arc<BufferUsage>,
+}
+
+impl<S> UsageStream<S> {
+    pub fn new(inner: S, usage: Arc<BufferUsage>) -> Self {
+        Self { inner, usage }
+    }
+}
+
+impl<S: Stream<Item = Event>> Stream for UsageStream<S> {
+    type Item = Event;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Event>, Self::Error> {
+        let event = self.inner.poll()?;
+        if let Async::Ready(Some(_)) = event {
+            self.usage.pop();
+        }
+        Ok(event)
+    }
+}
+
+/// Cheaper than encoding the event, which would double the cost of buffering.
+fn size_estimate(event: &Event) -> usize {
+    match event {
+        Event::Log(log) => log
+            .all_fields()
+            .map(|(key, value)| {
+                key.len()
+                    + match value {
+                        Value::Bytes(bytes) => bytes.len(),
+                        _ => mem::size_of::<Value>(),
+                    }
+            })
+            .sum(),
+        Event::Metric(metric) => {
+            mem::size_of::<Metric>()
+                + metric.name.len()
+                + metric.tags.as_ref().map_or(0, |tags| {
+                    tags.iter()
+                        .map(|(name, value)| name.len() + value.len())
+                        .sum()
+                })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffers::BufferConfig;
+    use crate::test_util::block_on;
+    use futures01::future;
+
+    #[test]
+    fn buffer_usage_tracks_buffered_events() {
+        block_on::<_, _, ()>(future::lazy(|| {
+            let config = BufferConfig::Memory {
+                max_events: Some(10),
+                when_full: Default::default(),
+            };
+            let (tx, mut rx, _) = config.build(&None, "usage_sink").unwrap();
+            let mut tx = tx.get();
+
+            assert_eq!(tx.start_send(Event::from("hello")), Ok(AsyncSink::Ready));
+            assert_eq!(tx.start_send(Event::from("world!")), Ok(AsyncSink::Ready));
+            let snapshot = usages()["usage_sink"].clone();
+            assert_eq!(snapshot.component_kind, "sink");
+            assert_eq!(snapshot.buffer_type, "memory");
+            assert_eq!(snapshot.events, 2);
+            assert!(snapshot.byte_size >= "helloworld!".len());
+            assert!(snapshot.oldest_event_age.is_some());
+
+            assert!(rx.poll().unwrap().is_ready());
+            assert!(rx.poll().unwrap().is_ready());
+            let snapshot = usages()["usage_sink"].clone();
+            assert_eq!(snapshot.events, 0);
+            assert_eq!(snapshot.byte_size, 0);
+            assert_eq!(snapshot.oldest_event_age, None);
+
+            drop((tx, rx));
+            assert!(!usages().contains_key("usage_sink"));
+
+            let (_tx, _rx, _) = config.build(&None, "usage_sink").unwrap();
+            assert!(usages().contains_key("usage_sink"));
+            forget("usage_sink");
+            assert!(!usages().contains_key("usage_sink"));
+
+            future::ok(())
+        }))
+        .unwrap();
+    }
+}
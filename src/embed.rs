@@ -0,0 +1,287 @@
+//! Running Vector pipelines inside other Rust programs, with no Vector
+//! process of their own.
+//!
+//! A pipeline is configured in code, with `embedded` sources taking the
+//! events the program sends them, and `embedded` sinks handing the events
+//! that reach them back to the program:
+//!
+//! ```no_run
+//! use futures01::{Future, Sink, Stream};
+//! use vector::{embed, topology::Config, Event};
+//!
+//! let (input, source) = embed::source(100);
+//! let (output, sink) = embed::sink(100);
+//!
+//! let mut config = Config::empty();
+//! config.add_source("in", source);
+//! config.add_sink("out", &["in"], sink);
+//!
+//! let pipeline = embed::Pipeline::start(config, true).unwrap();
+//! let input = input.send(Event::from("hello")).wait().unwrap();
+//! let event = output.wait().next();
+//!
+//! drop(input);
+//! pipeline.wait();
+//! ```
+//!
+//! Other sources, transforms and sinks are added to the config just as
+//! they are in config files.
+//!
+//! The `log_schema` and `defaults_profile` of the first pipeline started
+//! apply to the whole program, so the pipelines started after it have to
+//! use the same.
+
+use crate::{
+    event::{self, Event},
+    runtime::Runtime,
+    shutdown::ShutdownSignal,
+    sinks::{util::StreamSink, Healthcheck, RouterSink},
+    sources::Source,
+    stream::StreamExt,
+    topology::{
+        self, builder,
+        config::{
+            DataType, GlobalOptions, SinkConfig, SinkContext, SourceConfig, DEFAULTS_PROFILE,
+        },
+        Config, ConfigDiff, RunningTopology,
+    },
+};
+use futures01::{
+    future,
+    sync::mpsc::{self, Receiver, Sender, UnboundedReceiver},
+    Future, Sink, Stream,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("The events of an embedded source are taken by the first pipeline built"))]
+    ReceiverTaken,
+    #[snafu(display("Embedded sinks are only made by `embed::sink`"))]
+    MissingSender,
+}
+
+/// A source, and the sender of the events it emits.
+pub fn source(buffer: usize) -> (Sender<Event>, EmbeddedSourceConfig) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let source = EmbeddedSourceConfig {
+        receiver: Arc::new(Mutex::new(Some(rx))),
+    };
+    (tx, source)
+}
+
+/// A sink, and the receiver of the events sent to it.
+pub fn sink(buffer: usize) -> (Receiver<Event>, EmbeddedSinkConfig) {
+    let (tx, rx) = mpsc::channel(buffer);
+    let sink = EmbeddedSinkConfig { sender: Some(tx) };
+    (rx, sink)
+}
+
+/// Emits the events sent from the program, until all their senders are
+/// dropped.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct EmbeddedSourceConfig {
+    #[serde(skip)]
+    receiver: Arc<Mutex<Option<Receiver<Event>>>>,
+}
+
+#[typetag::serde(name = "embedded")]
+impl SourceConfig for EmbeddedSourceConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<Source> {
+        let receiver = self
+            .receiver
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(BuildError::ReceiverTaken)?;
+
+        Ok(Box::new(
+            receiver
+                .take_until(shutdown)
+                .forward(
+                    out.sink_map_err(
+                        |error| error!(message = "Unable to send event to out.", %error),
+                    ),
+                )
+                .map(|_| info!("finished sending")),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn source_type(&self) -> &'static str {
+        "embedded"
+    }
+}
+
+/// Hands the events it receives to the program.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct EmbeddedSinkConfig {
+    #[serde(skip)]
+    sender: Option<Sender<Event>>,
+}
+
+#[typetag::serde(name = "embedded")]
+impl SinkConfig for EmbeddedSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let sender = self.sender.clone().ok_or(BuildError::MissingSender)?;
+        let sender = sender
+            .sink_map_err(|error| error!(message = "Unable to hand event to the program.", %error));
+        let sink = StreamSink::new(sender, cx.acker());
+
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "embedded"
+    }
+}
+
+/// A pipeline running on a runtime of its own.
+pub struct Pipeline {
+    runtime: Runtime,
+    topology: RunningTopology,
+    crashed: UnboundedReceiver<()>,
+}
+
+impl Pipeline {
+    /// Builds and starts the pipeline of the config, returning the errors
+    /// of the config, or of the healthchecks when `require_healthy`.
+    pub fn start(mut config: Config, require_healthy: bool) -> Result<Self, Vec<String>> {
+        set_globals(&config)?;
+        config.expand_macros()?;
+        let mut runtime = Runtime::new().map_err(|error| vec![error.to_string()])?;
+
+        let diff = ConfigDiff::initial(&config);
+        let (pieces, warnings) = builder::build_pieces(&config, &diff, runtime.executor())?;
+        for warning in warnings {
+            warn!("Configuration warning: {}", warning);
+        }
+
+        let (topology, crashed) =
+            topology::start_validated(config, diff, pieces, &mut runtime, require_healthy)
+                .ok_or_else(|| vec!["Sinks unhealthy.".to_owned()])?;
+
+        Ok(Self {
+            runtime,
+            topology,
+            crashed,
+        })
+    }
+
+    /// Replaces the config of the running pipeline, returning whether it
+    /// was. The pipeline can't be used after an `Err`.
+    pub fn reload(&mut self, config: Config, require_healthy: bool) -> Result<bool, ()> {
+        self.topology
+            .reload_config_and_respawn(config, &mut self.runtime, require_healthy)
+    }
+
+    /// Waits until all the sources have finished, or a component crashed,
+    /// and stops the pipeline.
+    pub fn wait(self) {
+        let Pipeline {
+            mut runtime,
+            topology,
+            crashed,
+        } = self;
+
+        let crashed = crashed.into_future().map(drop).map_err(drop);
+        let _ = runtime.block_on(
+            crashed
+                .select(topology.sources_finished())
+                .map(drop)
+                .map_err(drop),
+        );
+
+        shutdown(runtime, topology);
+    }
+
+    /// Stops the sources, waiting for the events they emitted to go through
+    /// the rest of the pipeline.
+    pub fn stop(self) {
+        shutdown(self.runtime, self.topology);
+    }
+}
+
+/// Sets the `log_schema` and `defaults_profile` of the program, unless an
+/// earlier pipeline set them already, in which case they have to match.
+fn set_globals(config: &Config) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    let log_schema = event::LOG_SCHEMA.get_or_init(|| config.global.log_schema.clone());
+    if *log_schema != config.global.log_schema {
+        errors.push(format!(
+            "The log_schema differs from the one of the pipeline started first: {:?}",
+            log_schema
+        ));
+    }
+    let defaults_profile = DEFAULTS_PROFILE.get_or_init(|| config.global.defaults_profile);
+    if *defaults_profile != config.global.defaults_profile {
+        errors.push(format!(
+            "The defaults_profile differs from the one of the pipeline started first: {:?}",
+            defaults_profile
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn shutdown(mut runtime: Runtime, topology: RunningTopology) {
+    let _ = runtime.block_on(topology.stop());
+    let _ = runtime.shutdown_now().wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::log_schema;
+
+    #[test]
+    fn events_go_through_embedded_pipeline() {
+        let (input, source) = source(10);
+        let (output, sink) = sink(10);
+
+        let mut config = Config::empty();
+        config.add_source("in", source);
+        config.add_sink("out", &["in"], sink);
+
+        let pipeline = Pipeline::start(config, true).unwrap();
+        let input = input.send(Event::from("hello")).wait().unwrap();
+
+        let mut output = output.wait();
+        let event = output.next().unwrap().unwrap();
+        assert_eq!(event.as_log()[&log_schema().message_key()], "hello".into());
+
+        drop(input);
+        pipeline.wait();
+        assert!(output.next().is_none());
+    }
+
+    #[test]
+    fn pipelines_share_the_log_schema() {
+        set_globals(&Config::empty()).unwrap();
+
+        let mut other = Config::empty();
+        other.global.log_schema.set_message_key("msg".into());
+        let errors = set_globals(&other).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("log_schema"));
+    }
+}
@@ -17,6 +17,7 @@ pub mod metric;
 mod util;
 
 pub use metric::Metric;
+pub(crate) use util::log::escape_key;
 pub(crate) use util::log::PathComponent;
 pub(crate) use util::log::PathIter;
 
@@ -162,6 +163,14 @@ impl LogEvent {
         util::log::all_fields(&self.fields)
     }
 
+    /// The paths of `all_fields` escaped, to be passed back to `get`,
+    /// `insert` or `remove`.
+    pub fn all_fields_escaped<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (String, &'a Value)> + Serialize {
+        util::log::all_fields_escaped(&self.fields)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.fields.is_empty()
     }
@@ -1,4 +1,4 @@
-use super::Value;
+use super::{escape_key, Value};
 use serde::{Serialize, Serializer};
 use std::{
     collections::{btree_map, BTreeMap},
@@ -6,11 +6,20 @@ use std::{
 };
 
 /// Iterates over all paths in form "a.b[0].c[1]" in alphabetical order
-/// and their corresponding values.
+/// and their corresponding values. Keys are taken as they are, as sinks
+/// send them to other systems.
 pub fn all_fields<'a>(
     fields: &'a BTreeMap<String, Value>,
 ) -> impl Iterator<Item = (String, &'a Value)> + Serialize {
-    FieldsIter::new(fields)
+    FieldsIter::new(fields, false)
+}
+
+/// Like `all_fields`, but with special characters in keys escaped, so the
+/// paths can be passed back to `get`, `insert`, or `remove`.
+pub fn all_fields_escaped<'a>(
+    fields: &'a BTreeMap<String, Value>,
+) -> impl Iterator<Item = (String, &'a Value)> + Serialize {
+    FieldsIter::new(fields, true)
 }
 
 #[derive(Clone)]
@@ -32,13 +41,16 @@ struct FieldsIter<'a> {
     stack: Vec<LeafIter<'a>>,
     /// Path components from the root up to the top of the stack.
     path: Vec<PathComponent<'a>>,
+    /// Whether special characters in keys are escaped.
+    escape: bool,
 }
 
 impl<'a> FieldsIter<'a> {
-    fn new(fields: &'a BTreeMap<String, Value>) -> FieldsIter<'a> {
+    fn new(fields: &'a BTreeMap<String, Value>, escape: bool) -> FieldsIter<'a> {
         FieldsIter {
             stack: vec![LeafIter::Map(fields.iter())],
             path: vec![],
+            escape,
         }
     }
 
@@ -69,7 +81,8 @@ impl<'a> FieldsIter<'a> {
         loop {
             match path_iter.next() {
                 None => return String::from(res),
-                Some(PathComponent::Key(key)) => res.push_str(&key),
+                Some(PathComponent::Key(key)) if self.escape => res.push_str(&escape_key(key)),
+                Some(PathComponent::Key(key)) => res.push_str(key),
                 Some(PathComponent::Index(index)) => res.push_str(&format!("[{}]", index)),
             }
             if let Some(PathComponent::Key(_)) = path_iter.peek() {
@@ -173,4 +186,35 @@ mod test {
         let collected: Vec<_> = all_fields(&fields).collect();
         assert_eq!(collected, expected);
     }
+
+    #[test]
+    fn keys_escaped() {
+        let fields = fields_from_json(json!({
+            "labels": {
+                "app.kubernetes.io/name": "vector"
+            }
+        }));
+
+        let collected: Vec<_> = all_fields(&fields).collect();
+        assert_eq!(
+            collected,
+            vec![(
+                "labels.app.kubernetes.io/name".into(),
+                &Value::from("vector")
+            )]
+        );
+
+        let collected: Vec<_> = all_fields_escaped(&fields).collect();
+        assert_eq!(
+            collected,
+            vec![(
+                "labels.app\\.kubernetes\\.io/name".into(),
+                &Value::from("vector")
+            )]
+        );
+        assert_eq!(
+            super::super::get(&fields, &collected[0].0),
+            Some(&Value::from("vector"))
+        );
+    }
 }
@@ -1,11 +1,11 @@
-use super::{all_fields, Value};
+use super::{all_fields_escaped, Value};
 use std::collections::BTreeMap;
 
-/// Iterates over all paths in form "a.b[0].c[1]" in alphabetical order.
-/// It is implemented as a wrapper around `all_fields` to reduce code
-/// duplication.
+/// Iterates over all paths in form "a.b[0].c[1]" in alphabetical order,
+/// escaped to be passed back to `get`. It is implemented as a wrapper
+/// around `all_fields_escaped` to reduce code duplication.
 pub fn keys<'a>(fields: &'a BTreeMap<String, Value>) -> impl Iterator<Item = String> + 'a {
-    all_fields(fields).map(|(k, _)| k)
+    all_fields_escaped(fields).map(|(k, _)| k)
 }
 
 #[cfg(test)]
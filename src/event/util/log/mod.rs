@@ -8,9 +8,9 @@ mod path_iter;
 mod remove;
 
 pub(self) use super::Value;
-pub(crate) use path_iter::{PathComponent, PathIter};
+pub(crate) use path_iter::{escape_key, PathComponent, PathIter};
 
-pub use all_fields::all_fields;
+pub use all_fields::{all_fields, all_fields_escaped};
 pub use contains::contains;
 pub use get::get;
 pub use get_mut::get_mut;
@@ -34,6 +34,19 @@ impl<'a> PathIter<'a> {
     }
 }
 
+/// Escapes the characters which are special in paths, so that the key is
+/// read back by `PathIter` as a single component.
+pub fn escape_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+    for c in key.chars() {
+        if c == '.' || c == '[' || c == ']' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 /// The parsing is implemented using a state machine.
 /// The idea of using Rust enums to model states is taken from
 /// https://hoverbear.org/blog/rust-state-machine-pattern/ .
@@ -209,6 +222,24 @@ mod test {
         }
     }
 
+    #[test]
+    fn path_iter_escaped_keys_round_trip() {
+        use PathComponent::*;
+
+        let keys = vec!["app.kubernetes.io/name", "a[0]", "back\\slash", "plain"];
+        let path = keys
+            .iter()
+            .map(|key| escape_key(key))
+            .collect::<Vec<_>>()
+            .join(".");
+
+        let expected = keys
+            .into_iter()
+            .map(|key| Key(key.into()))
+            .collect::<Vec<_>>();
+        assert_eq!(PathIter::new(&path).collect::<Vec<_>>(), expected);
+    }
+
     #[test]
     fn path_iter_invalid() {
         let inputs = vec![
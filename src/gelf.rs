@@ -0,0 +1,342 @@
+//! The Graylog Extended Log Format: decoding the messages received by the
+//! socket sources and encoding those sent by the `gelf` sink.
+//!
+//! GELF messages are JSON objects. Over UDP they may be compressed with gzip
+//! or zlib, and split in chunks when larger than a datagram.
+
+use crate::event::{log_schema, LogEvent, Value};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::GzEncoder,
+};
+use serde_json::{Map, Value as JsonValue};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+const CHUNK_HEADER_SIZE: usize = 12;
+const MAX_CHUNKS: usize = 128;
+/// Graylog drops the chunks of messages still incomplete after as long.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_PENDING_MESSAGES: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum DecodeError {
+    #[snafu(display("Unable to inflate message: {}", source))]
+    Inflate { source: std::io::Error },
+    #[snafu(display("Message is not JSON: {}", source))]
+    InvalidJson { source: serde_json::Error },
+    #[snafu(display("Message is not a JSON object"))]
+    NotAnObject,
+    #[snafu(display("Message has no short_message"))]
+    MissingShortMessage,
+}
+
+/// Decodes a complete message, inflating it first if compressed.
+pub fn decode(message: &[u8]) -> Result<LogEvent, DecodeError> {
+    let mut inflated = Vec::new();
+    let message = if message.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(message)
+            .read_to_end(&mut inflated)
+            .context(Inflate)?;
+        &inflated[..]
+    } else if message.starts_with(&[0x78]) {
+        ZlibDecoder::new(message)
+            .read_to_end(&mut inflated)
+            .context(Inflate)?;
+        &inflated[..]
+    } else {
+        message
+    };
+
+    let fields = match serde_json::from_slice(message).context(InvalidJson)? {
+        JsonValue::Object(fields) => fields,
+        _ => return Err(DecodeError::NotAnObject),
+    };
+
+    let mut log = LogEvent::new();
+    for (name, value) in fields {
+        match name.as_str() {
+            "version" => (),
+            "short_message" => {
+                log.insert(log_schema().message_key(), Value::from(value));
+            }
+            "host" => {
+                log.insert(log_schema().host_key(), Value::from(value));
+            }
+            "timestamp" => {
+                if let Some(seconds) = value.as_f64() {
+                    let timestamp = Utc.timestamp(
+                        seconds.trunc() as i64,
+                        (seconds.fract() * 1_000_000_000.0) as u32,
+                    );
+                    log.insert(log_schema().timestamp_key(), timestamp);
+                }
+            }
+            // Additional fields are prefixed with an underscore.
+            _ => {
+                let name = if name.starts_with('_') {
+                    name[1..].to_owned()
+                } else {
+                    name
+                };
+                log.insert_flat(name, Value::from(value));
+            }
+        }
+    }
+
+    if !log.contains(log_schema().message_key()) {
+        return Err(DecodeError::MissingShortMessage);
+    }
+    if !log.contains(log_schema().timestamp_key()) {
+        log.insert(log_schema().timestamp_key(), Utc::now());
+    }
+    Ok(log)
+}
+
+/// Reassembles the messages split in chunks, passing the others through.
+#[derive(Debug, Default)]
+pub struct ChunkAssembler {
+    pending: HashMap<[u8; 8], PendingMessage>,
+}
+
+#[derive(Debug)]
+struct PendingMessage {
+    chunks: Vec<Option<Bytes>>,
+    received: usize,
+    started: Instant,
+}
+
+impl ChunkAssembler {
+    /// Returns the message once all of its chunks were received.
+    pub fn push(&mut self, datagram: Bytes) -> Option<Bytes> {
+        if !datagram.starts_with(&CHUNK_MAGIC) {
+            return Some(datagram);
+        }
+        if datagram.len() <= CHUNK_HEADER_SIZE {
+            debug!(
+                message = "Dropping truncated GELF chunk.",
+                rate_limit_secs = 30
+            );
+            return None;
+        }
+
+        let mut id = [0; 8];
+        id.copy_from_slice(&datagram[2..10]);
+        let sequence = datagram[10] as usize;
+        let count = datagram[11] as usize;
+        if count == 0 || count > MAX_CHUNKS || sequence >= count {
+            debug!(
+                message = "Dropping GELF chunk with an invalid sequence.",
+                %sequence,
+                %count,
+                rate_limit_secs = 30,
+            );
+            return None;
+        }
+
+        let now = Instant::now();
+        self.pending
+            .retain(|_, message| now.duration_since(message.started) < CHUNK_TIMEOUT);
+        if !self.pending.contains_key(&id) && self.pending.len() >= MAX_PENDING_MESSAGES {
+            debug!(
+                message = "Too many incomplete GELF messages; dropping chunk.",
+                rate_limit_secs = 30,
+            );
+            return None;
+        }
+
+        let message = self.pending.entry(id).or_insert_with(|| PendingMessage {
+            chunks: vec![None; count],
+            received: 0,
+            started: now,
+        });
+        if message.chunks.len() != count {
+            return None;
+        }
+        if message.chunks[sequence].is_none() {
+            message.chunks[sequence] = Some(datagram.slice_from(CHUNK_HEADER_SIZE));
+            message.received += 1;
+        }
+        if message.received < count {
+            return None;
+        }
+
+        let message = self.pending.remove(&id)?;
+        let mut assembled = Vec::new();
+        for chunk in message.chunks.into_iter().flatten() {
+            assembled.extend_from_slice(&chunk);
+        }
+        Some(assembled.into())
+    }
+}
+
+/// Encodes the log as a GELF message, or `None` if it has no message, which
+/// GELF requires.
+pub fn encode(log: &LogEvent) -> Option<Vec<u8>> {
+    let message_key = log_schema().message_key();
+    let host_key = log_schema().host_key();
+    let timestamp_key = log_schema().timestamp_key();
+
+    let short_message = log.get(message_key)?.to_string_lossy();
+    let host = log
+        .get(host_key)
+        .map(Value::to_string_lossy)
+        .or_else(hostname::get_hostname)
+        .unwrap_or_else(|| "vector".into());
+    let timestamp = match log.get(timestamp_key) {
+        Some(Value::Timestamp(timestamp)) => *timestamp,
+        _ => Utc::now(),
+    };
+
+    let mut message = Map::new();
+    message.insert("version".into(), "1.1".into());
+    message.insert("host".into(), host.into());
+    message.insert("short_message".into(), short_message.into());
+    message.insert(
+        "timestamp".into(),
+        (timestamp.timestamp_millis() as f64 / 1000.0).into(),
+    );
+
+    for (name, value) in log.all_fields() {
+        if [message_key, host_key, timestamp_key]
+            .iter()
+            .any(|key| name == &***key)
+        {
+            continue;
+        }
+        match (name.as_str(), value) {
+            ("full_message", Value::Bytes(_)) => {
+                message.insert(name, value.to_string_lossy().into());
+            }
+            ("level", Value::Integer(level)) if (0..=7).contains(level) => {
+                message.insert(name, (*level).into());
+            }
+            (_, Value::Null) => (),
+            _ => {
+                // Numbers are kept, anything else becomes a string.
+                let value = match value {
+                    Value::Integer(integer) => (*integer).into(),
+                    Value::Float(float) => (*float).into(),
+                    Value::Timestamp(timestamp) => timestamp.to_rfc3339().into(),
+                    value => value.to_string_lossy().into(),
+                };
+                message.insert(additional_field_name(&name), value);
+            }
+        }
+    }
+
+    serde_json::to_vec(&message)
+        .map_err(|error| error!(message = "Unable to encode GELF message.", %error))
+        .ok()
+}
+
+/// Additional fields may only use word characters, dots and dashes, and
+/// `_id` is reserved.
+fn additional_field_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c,
+            '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    if name == "id" {
+        "__id".into()
+    } else {
+        format!("_{}", name)
+    }
+}
+
+/// Compresses the message with gzip.
+pub fn compress(message: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(message)
+        .expect("Writing to a Vec can't fail");
+    encoder.finish().expect("Writing to a Vec can't fail")
+}
+
+/// Splits the message in chunks of at most `max_size` bytes, headers
+/// included, or `None` if it would take more chunks than allowed.
+pub fn chunk(message: Vec<u8>, max_size: usize) -> Option<Vec<Bytes>> {
+    if message.len() <= max_size {
+        return Some(vec![message.into()]);
+    }
+
+    let chunk_size = max_size.saturating_sub(CHUNK_HEADER_SIZE).max(1);
+    let count = (message.len() + chunk_size - 1) / chunk_size;
+    if count > MAX_CHUNKS {
+        return None;
+    }
+
+    let id = rand::random::<u64>().to_be_bytes();
+    let chunks = message
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(sequence, data)| {
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + data.len());
+            chunk.extend_from_slice(&CHUNK_MAGIC);
+            chunk.extend_from_slice(&id);
+            chunk.push(sequence as u8);
+            chunk.push(count as u8);
+            chunk.extend_from_slice(data);
+            chunk.into()
+        })
+        .collect();
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+
+    #[test]
+    fn gelf_decodes_chunked_compressed_messages() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("host", "web01");
+        event.as_mut_log().insert("level", 3);
+        event.as_mut_log().insert("http.status", 200);
+        event.as_mut_log().insert("id", "abc");
+        event.as_mut_log().insert("padding", "x".repeat(100));
+        let encoded = encode(event.as_log()).unwrap();
+
+        let fields: JsonValue = serde_json::from_slice(&encoded).unwrap();
+        assert_eq!(fields["version"], "1.1");
+        assert_eq!(fields["short_message"], "hello");
+        assert_eq!(fields["host"], "web01");
+        assert_eq!(fields["level"], 3);
+        assert_eq!(fields["_http.status"], 200);
+        assert_eq!(fields["__id"], "abc");
+
+        let chunks = chunk(compress(&encoded), 40).unwrap();
+        assert!(chunks.len() > 1);
+
+        let mut assembler = ChunkAssembler::default();
+        let mut assembled = None;
+        for chunk in chunks.into_iter().rev() {
+            assert!(assembled.is_none());
+            assembled = assembler.push(chunk);
+        }
+        let log = decode(&assembled.unwrap()).unwrap();
+        assert_eq!(log[&log_schema().message_key()], "hello".into());
+        assert_eq!(log[&log_schema().host_key()], "web01".into());
+        assert_eq!(log[&"level".into()], 3.into());
+        assert_eq!(log[&"http.status".into()], 200.into());
+        assert!(log.get(&"version".into()).is_none());
+    }
+
+    #[test]
+    fn gelf_requires_short_message() {
+        assert!(decode(br#"{"version":"1.1","host":"web01"}"#).is_err());
+        assert!(decode(b"[1]").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use crate::topology::{
+    config::default_data_dir,
+    lifecycle::{self, Trace},
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Opts {
+    /// The trace to show the hops of. All traces are listed if omitted.
+    id: Option<String>,
+
+    /// Only list the traces which went through this component.
+    #[structopt(long)]
+    component: Option<String>,
+
+    /// The `data_dir` of the Vector instance to report on. Defaults to
+    /// `/var/lib/vector/`.
+    #[structopt(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Format the traces in an encoding scheme.
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: Format,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            s => Err(format!(
+                "{} is not a valid option, expected `text` or `json`",
+                s
+            )),
+        }
+    }
+}
+
+pub fn cmd(opts: &Opts) -> exitcode::ExitCode {
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .or_else(default_data_dir)
+        .expect("default data_dir");
+    let traces = match lifecycle::read_traces(&data_dir) {
+        Ok(traces) => traces,
+        Err(error) => {
+            error!(message = "Unable to read lifecycle traces.", data_dir = ?data_dir, %error);
+            return exitcode::IOERR;
+        }
+    };
+
+    if let Some(id) = &opts.id {
+        let trace = match traces.into_iter().find(|trace| &trace.id == id) {
+            Some(trace) => trace,
+            None => {
+                error!(message = "No such trace.", %id);
+                return exitcode::DATAERR;
+            }
+        };
+        match opts.format {
+            Format::Text => print_hops(&trace),
+            Format::Json => println!("{}", serde_json::to_string(&trace).unwrap()),
+        }
+        return exitcode::OK;
+    }
+
+    let traces = traces
+        .into_iter()
+        .filter(|trace| match &opts.component {
+            Some(component) => trace.hops.iter().any(|hop| &hop.component == component),
+            None => true,
+        })
+        .collect::<Vec<_>>();
+    match opts.format {
+        Format::Text => {
+            if traces.is_empty() {
+                println!("No traces persisted yet, is `lifecycle.sample_ratio` set?");
+            }
+            for trace in &traces {
+                let source = trace.hops.first().map_or("", |hop| hop.component.as_str());
+                println!(
+                    "{}  {:>3} hops  {:>8} ms  from {}",
+                    trace.id,
+                    trace.hops.len(),
+                    trace.latency().num_milliseconds(),
+                    source
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&traces).unwrap()),
+    }
+
+    exitcode::OK
+}
+
+fn print_hops(trace: &Trace) {
+    let width = trace
+        .hops
+        .iter()
+        .map(|hop| hop.component.len())
+        .max()
+        .unwrap_or(0);
+    let mut previous = None;
+    for hop in &trace.hops {
+        let latency = previous.map_or(0, |previous| (hop.at - previous).num_milliseconds());
+        println!(
+            "{}  {:9}  {:width$}  +{} ms",
+            hop.at.to_rfc3339(),
+            hop.component_kind,
+            hop.component,
+            latency,
+            width = width
+        );
+        previous = Some(hop.at);
+    }
+    println!("Total: {} ms", trace.latency().num_milliseconds());
+}
@@ -0,0 +1,22 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::net::SocketAddr;
+
+#[derive(Debug)]
+pub struct PeerDenied {
+    pub peer_addr: SocketAddr,
+}
+
+impl InternalEvent for PeerDenied {
+    fn emit_logs(&self) {
+        warn!(
+            message = "peer not allowed; dropping connection.",
+            peer_addr = %self.peer_addr,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connections_denied", 1);
+    }
+}
@@ -0,0 +1,25 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AccountingTenantEventProcessed<'a> {
+    pub component_kind: &'static str,
+    pub component_name: &'a str,
+    pub tenant: &'a str,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AccountingTenantEventProcessed<'_> {
+    fn emit_metrics(&self) {
+        counter!("tenant_events_processed", 1,
+            "component_kind" => self.component_kind,
+            "component_name" => self.component_name.to_owned(),
+            "tenant" => self.tenant.to_owned(),
+        );
+        counter!("tenant_bytes_processed", self.byte_size as u64,
+            "component_kind" => self.component_kind,
+            "component_name" => self.component_name.to_owned(),
+            "tenant" => self.tenant.to_owned(),
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use super::InternalEvent;
+use chrono::{DateTime, Utc};
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AggregateEventProcessed;
+
+impl InternalEvent for AggregateEventProcessed {
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "transform",
+            "component_type" => "aggregate",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AggregateLateEventDropped<'a> {
+    pub name: &'a str,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl InternalEvent for AggregateLateEventDropped<'_> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "metric arrived after its window closed; dropping.",
+            name = %self.name,
+            timestamp = %self.timestamp,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("processing_error", 1,
+            "component_kind" => "transform",
+            "component_type" => "aggregate",
+            "error_type" => "late_event",
+        );
+    }
+}
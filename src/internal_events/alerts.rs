@@ -0,0 +1,96 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AlertsEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AlertsEventSent {
+    fn emit_logs(&self) {
+        debug!(message = "sent alert.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "alerts",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "alerts",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AlertsEventSuppressed<'a> {
+    pub group_key: &'a str,
+}
+
+impl<'a> InternalEvent for AlertsEventSuppressed<'a> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "alert of an open group suppressed.",
+            group_key = %self.group_key,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_suppressed", 1,
+            "component_kind" => "sink",
+            "component_type" => "alerts",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AlertsEventRateLimited;
+
+impl InternalEvent for AlertsEventRateLimited {
+    fn emit_logs(&self) {
+        warn!(
+            message = "alert rate limit reached; dropping event.",
+            rate_limit_secs = 60,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_dropped", 1,
+            "component_kind" => "sink",
+            "component_type" => "alerts",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AlertsSendFailed {
+    pub reason: String,
+    pub will_retry: bool,
+}
+
+impl InternalEvent for AlertsSendFailed {
+    fn emit_logs(&self) {
+        if self.will_retry {
+            warn!(
+                message = "failed to send alert; retrying.",
+                reason = %self.reason,
+                rate_limit_secs = 30,
+            );
+        } else {
+            error!(
+                message = "failed to send alert; dropping event.",
+                reason = %self.reason,
+                rate_limit_secs = 30,
+            );
+        }
+    }
+
+    fn emit_metrics(&self) {
+        counter!("send_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "alerts",
+        );
+    }
+}
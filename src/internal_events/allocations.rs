@@ -0,0 +1,28 @@
+use super::InternalEvent;
+use crate::allocations::Group;
+use metrics::gauge;
+
+#[derive(Debug)]
+pub struct ComponentAllocationsReported<'a> {
+    pub group: &'a Group,
+}
+
+impl InternalEvent for ComponentAllocationsReported<'_> {
+    fn emit_metrics(&self) {
+        gauge!("component_allocated_bytes", self.group.allocated_bytes(),
+            "component_kind" => self.group.component_kind,
+            "component_name" => self.group.component_name.clone(),
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct UnattributedAllocationsReported {
+    pub bytes: i64,
+}
+
+impl InternalEvent for UnattributedAllocationsReported {
+    fn emit_metrics(&self) {
+        gauge!("unattributed_allocated_bytes", self.bytes);
+    }
+}
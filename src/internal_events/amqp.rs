@@ -0,0 +1,24 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AmqpEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AmqpEventSent {
+    fn emit_logs(&self) {
+        trace!(message = "processed one event.");
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "amqp",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "amqp",
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use super::InternalEvent;
+use crate::sources::amqp::ConsumeError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AmqpMessageReceived {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AmqpMessageReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received message.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => "amqp",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "amqp",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AmqpMessageInvalid {
+    pub error: serde_json::Error,
+}
+
+impl InternalEvent for AmqpMessageInvalid {
+    fn emit_logs(&self) {
+        warn!(
+            message = "invalid message; rejecting it.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("messages_rejected", 1,
+            "component_kind" => "source",
+            "component_type" => "amqp",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AmqpAckFailed {
+    pub error: lapin::Error,
+}
+
+impl InternalEvent for AmqpAckFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed to acknowledge message.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("ack_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "amqp",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AmqpConsumeFailed {
+    pub error: ConsumeError,
+}
+
+impl InternalEvent for AmqpConsumeFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "consuming failed; reconnecting.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("consume_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "amqp",
+        );
+    }
+}
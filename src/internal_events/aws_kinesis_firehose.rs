@@ -0,0 +1,55 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AwsKinesisFirehoseRecordTooLarge {
+    pub byte_size: usize,
+    pub max_byte_size: usize,
+}
+
+impl InternalEvent for AwsKinesisFirehoseRecordTooLarge {
+    fn emit_logs(&self) {
+        error!(
+            message = "record is larger than firehose accepts; dropping it.",
+            byte_size = %self.byte_size,
+            max_byte_size = %self.max_byte_size,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "records_dropped", 1,
+            "component_kind" => "sink",
+            "component_type" => "aws_kinesis_firehose",
+            "reason" => "too_large",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AwsKinesisFirehoseRecordsFailed<'a> {
+    pub count: usize,
+    pub error_code: &'a str,
+    pub error_message: &'a str,
+}
+
+impl<'a> InternalEvent for AwsKinesisFirehoseRecordsFailed<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "records of the batch failed to be put.",
+            count = %self.count,
+            error_code = %self.error_code,
+            error_message = %self.error_message,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "records_failed", self.count as u64,
+            "component_kind" => "sink",
+            "component_type" => "aws_kinesis_firehose",
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::error::Error;
+
+#[derive(Debug)]
+pub struct S3ObjectUploaded<'a> {
+    pub key: &'a str,
+    pub parts: usize,
+    pub byte_size: usize,
+}
+
+impl<'a> InternalEvent for S3ObjectUploaded<'a> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "completed multipart upload.",
+            key = %self.key,
+            parts = %self.parts,
+            byte_size = %self.byte_size,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("objects_uploaded", 1,
+            "component_kind" => "sink",
+            "component_type" => "aws_s3",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct S3PartUploadFailed<'a> {
+    pub key: &'a str,
+    pub part_number: i64,
+    pub error: &'a dyn Error,
+    pub will_retry: bool,
+}
+
+impl<'a> InternalEvent for S3PartUploadFailed<'a> {
+    fn emit_logs(&self) {
+        if self.will_retry {
+            warn!(
+                message = "failed to upload part; retrying.",
+                key = %self.key,
+                part_number = %self.part_number,
+                error = %self.error,
+                rate_limit_secs = 30,
+            );
+        } else {
+            error!(
+                message = "failed to upload part.",
+                key = %self.key,
+                part_number = %self.part_number,
+                error = %self.error,
+            );
+        }
+    }
+
+    fn emit_metrics(&self) {
+        counter!("part_upload_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct S3MultipartUploadAborted<'a> {
+    pub key: &'a str,
+    pub error: &'a crate::Error,
+}
+
+impl<'a> InternalEvent for S3MultipartUploadAborted<'a> {
+    fn emit_logs(&self) {
+        error!(
+            message = "aborting multipart upload; dropping its events.",
+            key = %self.key,
+            error = %self.error,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("uploads_aborted", 1,
+            "component_kind" => "sink",
+            "component_type" => "aws_s3",
+        );
+    }
+}
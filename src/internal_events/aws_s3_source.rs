@@ -0,0 +1,124 @@
+use super::InternalEvent;
+use metrics::counter;
+use rusoto_core::RusotoError;
+use rusoto_sqs::{DeleteMessageError, ReceiveMessageError};
+
+#[derive(Debug)]
+pub struct AwsS3ObjectProcessed<'a> {
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AwsS3ObjectProcessed<'_> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "processed object.",
+            bucket = %self.bucket,
+            key = %self.key,
+            count = %self.count,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AwsS3ObjectFailed<'a> {
+    pub bucket: &'a str,
+    pub key: &'a str,
+    pub error: crate::Error,
+}
+
+impl InternalEvent for AwsS3ObjectFailed<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed reading object, its notification will be received again.",
+            bucket = %self.bucket,
+            key = %self.key,
+            error = %self.error,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("object_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AwsSqsNotificationInvalid {
+    pub error: serde_json::Error,
+}
+
+impl InternalEvent for AwsSqsNotificationInvalid {
+    fn emit_logs(&self) {
+        error!(
+            message = "invalid S3 event notification.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("parse_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AwsSqsReceiveFailed {
+    pub error: RusotoError<ReceiveMessageError>,
+}
+
+impl InternalEvent for AwsSqsReceiveFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed receiving SQS messages.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("sqs_receive_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AwsSqsMessageDeleteFailed {
+    pub error: RusotoError<DeleteMessageError>,
+}
+
+impl InternalEvent for AwsSqsMessageDeleteFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed deleting SQS message, its objects will be read again.",
+            error = %self.error,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("sqs_delete_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "aws_s3",
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use super::InternalEvent;
+use crate::sources::azure_event_hubs::{CheckpointError, ReceiveError};
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct AzureEventHubsMessageReceived {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for AzureEventHubsMessageReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received message.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => "azure_event_hubs",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "azure_event_hubs",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AzureEventHubsPartitionsFailed {
+    pub error: ReceiveError,
+}
+
+impl InternalEvent for AzureEventHubsPartitionsFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed reading the partitions of the event hub, retrying.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("receive_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "azure_event_hubs",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AzureEventHubsReceiveFailed {
+    pub partition_id: String,
+    pub error: ReceiveError,
+}
+
+impl InternalEvent for AzureEventHubsReceiveFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "receiving from partition failed; reconnecting.",
+            partition_id = %self.partition_id,
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("receive_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "azure_event_hubs",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct AzureEventHubsCheckpointFailed {
+    pub partition_id: String,
+    pub error: CheckpointError,
+}
+
+impl InternalEvent for AzureEventHubsCheckpointFailed {
+    fn emit_logs(&self) {
+        warn!(
+            message = "failed saving checkpoint, retrying.",
+            partition_id = %self.partition_id,
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("checkpoint_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "azure_event_hubs",
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use super::InternalEvent;
+use crate::sinks::util::flush::FlushReason;
+use metrics::{timing, value};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct BatchFlushed<'a> {
+    pub component_name: Option<&'a str>,
+    pub fill_ratio: f64,
+    pub time_to_flush: Duration,
+    pub reason: FlushReason,
+}
+
+impl InternalEvent for BatchFlushed<'_> {
+    fn emit_logs(&self) {
+        trace!(
+            message = "flushing batch.",
+            fill_ratio = %self.fill_ratio,
+            time_to_flush = ?self.time_to_flush,
+            reason = %self.reason.as_str(),
+        );
+    }
+
+    fn emit_metrics(&self) {
+        let name = self.component_name.unwrap_or("unknown").to_owned();
+        value!("batch_fill_ratio_percent", (self.fill_ratio * 100.0).round() as u64,
+            "component_kind" => "sink",
+            "component_name" => name.clone(),
+            "reason" => self.reason.as_str(),
+        );
+        timing!("batch_time_to_flush", self.time_to_flush.as_nanos() as u64,
+            "component_kind" => "sink",
+            "component_name" => name,
+            "reason" => self.reason.as_str(),
+        );
+    }
+}
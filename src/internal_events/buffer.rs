@@ -0,0 +1,33 @@
+use super::InternalEvent;
+use crate::buffers::usage::BufferSnapshot;
+use metrics::gauge;
+
+#[derive(Debug)]
+pub struct BufferUsageReported<'a> {
+    pub name: &'a str,
+    pub snapshot: &'a BufferSnapshot,
+}
+
+impl InternalEvent for BufferUsageReported<'_> {
+    fn emit_metrics(&self) {
+        gauge!("buffer_in_flight_events", self.snapshot.events as i64,
+            "component_kind" => self.snapshot.component_kind,
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => self.snapshot.buffer_type,
+        );
+        gauge!("buffer_in_flight_byte_size", self.snapshot.byte_size as i64,
+            "component_kind" => self.snapshot.component_kind,
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => self.snapshot.buffer_type,
+        );
+        let age = self
+            .snapshot
+            .oldest_event_age
+            .map_or(0, |age| age.as_millis() as i64);
+        gauge!("buffer_oldest_event_age_ms", age,
+            "component_kind" => self.snapshot.component_kind,
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => self.snapshot.buffer_type,
+        );
+    }
+}
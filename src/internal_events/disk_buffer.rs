@@ -0,0 +1,65 @@
+use super::InternalEvent;
+use metrics::{counter, gauge};
+
+#[derive(Debug)]
+pub struct DiskBufferSize<'a> {
+    pub name: &'a str,
+    pub byte_size: usize,
+    pub events: usize,
+}
+
+impl InternalEvent for DiskBufferSize<'_> {
+    fn emit_metrics(&self) {
+        gauge!("buffer_byte_size", self.byte_size as i64,
+            "component_kind" => "sink",
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => "disk",
+        );
+        gauge!("buffer_events", self.events as i64,
+            "component_kind" => "sink",
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => "disk",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskBufferCompacted<'a> {
+    pub name: &'a str,
+}
+
+impl InternalEvent for DiskBufferCompacted<'_> {
+    fn emit_logs(&self) {
+        trace!(message = "compacted disk buffer.", name = %self.name);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("buffer_compactions", 1,
+            "component_kind" => "sink",
+            "component_name" => self.name.to_owned(),
+            "buffer_type" => "disk",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct DiskBufferEventCorrupted {
+    pub error: prost::DecodeError,
+}
+
+impl InternalEvent for DiskBufferEventCorrupted {
+    fn emit_logs(&self) {
+        error!(
+            message = "error deserializing event from disk buffer; discarding it.",
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("buffer_corrupted_events", 1,
+            "component_kind" => "sink",
+            "buffer_type" => "disk",
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::io;
+
+#[derive(Debug)]
+pub struct ExecEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for ExecEventSent {
+    fn emit_logs(&self) {
+        trace!(message = "wrote event to process.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "exec",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "exec",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecProcessExited<'a> {
+    pub command: &'a str,
+    /// `None` when the process was killed by a signal.
+    pub exit_status: Option<i32>,
+}
+
+impl InternalEvent for ExecProcessExited<'_> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "process exited; restarting it.",
+            command = %self.command,
+            exit_status = ?self.exit_status,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("process_exits", 1,
+            "component_kind" => "sink",
+            "component_type" => "exec",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecProcessFailed<'a> {
+    pub command: &'a str,
+    pub error: io::Error,
+}
+
+impl InternalEvent for ExecProcessFailed<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "unable to start process; retrying.",
+            command = %self.command,
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("process_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "exec",
+        );
+    }
+}
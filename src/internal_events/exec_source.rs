@@ -0,0 +1,113 @@
+use super::InternalEvent;
+use crate::sources::exec::ExecError;
+use metrics::{counter, timing};
+use std::{io, time::Duration};
+
+#[derive(Debug)]
+pub struct ExecEventsReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for ExecEventsReceived {
+    fn emit_logs(&self) {
+        trace!(
+            message = "received events.",
+            count = %self.count,
+            byte_size = %self.byte_size,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "exec",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "exec",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecCommandExited<'a> {
+    pub command: &'a str,
+    /// `None` when the command was killed by a signal.
+    pub exit_status: Option<i32>,
+    pub elapsed: Duration,
+}
+
+impl InternalEvent for ExecCommandExited<'_> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "command exited.",
+            command = %self.command,
+            exit_status = ?self.exit_status,
+            elapsed_millis = %self.elapsed.as_millis(),
+        );
+    }
+
+    fn emit_metrics(&self) {
+        let exit_status = self
+            .exit_status
+            .map_or_else(|| "signal".to_owned(), |code| code.to_string());
+        counter!("command_executed", 1,
+            "component_kind" => "source",
+            "component_type" => "exec",
+            "exit_status" => exit_status,
+        );
+        timing!("command_execution_duration_nanoseconds", self.elapsed.as_nanos() as u64,
+            "component_kind" => "source",
+            "component_type" => "exec",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecFailed<'a> {
+    pub command: &'a str,
+    pub error: ExecError,
+}
+
+impl InternalEvent for ExecFailed<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "unable to run command.",
+            command = %self.command,
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("command_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "exec",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecReadFailed {
+    pub stream: &'static str,
+    pub error: io::Error,
+}
+
+impl InternalEvent for ExecReadFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "unable to read command output.",
+            stream = %self.stream,
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("read_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "exec",
+        );
+    }
+}
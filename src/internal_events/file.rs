@@ -1,5 +1,6 @@
 use super::InternalEvent;
 use metrics::counter;
+use std::path::Path;
 
 #[derive(Debug)]
 pub struct FileEventReceived<'a> {
@@ -29,3 +30,43 @@ impl InternalEvent for FileEventReceived<'_> {
         );
     }
 }
+
+#[derive(Debug)]
+pub struct FileArchiveRead<'a> {
+    pub path: &'a Path,
+    pub lines: usize,
+}
+
+impl InternalEvent for FileArchiveRead<'_> {
+    fn emit_logs(&self) {
+        info!(message = "Finished reading file.", path = ?self.path, lines = self.lines);
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "files_read", 1,
+            "component_kind" => "source",
+            "component_type" => "file",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct FileArchiveReadFailed<'a> {
+    pub path: &'a Path,
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for FileArchiveReadFailed<'_> {
+    fn emit_logs(&self) {
+        error!(message = "Unable to read file.", path = ?self.path, error = %self.error);
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "file_read_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "file",
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use super::InternalEvent;
+use metrics::counter;
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Debug)]
+pub struct GcpPubsubMessageTooLarge {
+    pub byte_size: usize,
+    pub max_byte_size: usize,
+}
+
+impl InternalEvent for GcpPubsubMessageTooLarge {
+    fn emit_logs(&self) {
+        error!(
+            message = "message is larger than pubsub accepts; dropping it.",
+            byte_size = %self.byte_size,
+            max_byte_size = %self.max_byte_size,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "events_dropped", 1,
+            "component_kind" => "sink",
+            "component_type" => "gcp_pubsub",
+            "reason" => "too_large",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GcpPubsubOrderingKeyMissing {
+    pub missing_keys: Vec<Atom>,
+}
+
+impl InternalEvent for GcpPubsubOrderingKeyMissing {
+    fn emit_logs(&self) {
+        warn!(
+            message = "fields of the ordering key are missing; publishing without a key.",
+            missing_keys = ?self.missing_keys,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "ordering_key_missing", 1,
+            "component_kind" => "sink",
+            "component_type" => "gcp_pubsub",
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use super::InternalEvent;
+use crate::sources::gcp_pubsub::RequestError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct GcpPubsubMessagesReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for GcpPubsubMessagesReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received messages.", count = %self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "gcp_pubsub",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "gcp_pubsub",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GcpPubsubMessageInvalid {
+    pub error: base64::DecodeError,
+}
+
+impl InternalEvent for GcpPubsubMessageInvalid {
+    fn emit_logs(&self) {
+        error!(
+            message = "message data is not valid base64; dropping it.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_dropped", 1,
+            "component_kind" => "source",
+            "component_type" => "gcp_pubsub",
+            "reason" => "invalid_data",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GcpPubsubPullFailed {
+    pub error: RequestError,
+}
+
+impl InternalEvent for GcpPubsubPullFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed pulling messages, retrying.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("pull_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "gcp_pubsub",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GcpPubsubAckFailed {
+    pub error: RequestError,
+}
+
+impl InternalEvent for GcpPubsubAckFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed acknowledging messages, they will be delivered again.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("ack_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "gcp_pubsub",
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use super::InternalEvent;
+use crate::gelf::DecodeError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct GelfDecodeFailed {
+    pub mode: &'static str,
+    pub error: DecodeError,
+}
+
+impl InternalEvent for GelfDecodeFailed {
+    fn emit_logs(&self) {
+        warn!(
+            message = "unable to decode GELF message.",
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("decode_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "socket",
+            "mode" => self.mode,
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GelfMessageDropped {
+    pub reason: &'static str,
+}
+
+impl InternalEvent for GelfMessageDropped {
+    fn emit_logs(&self) {
+        warn!(
+            message = "dropping GELF message.",
+            reason = self.reason,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_discarded", 1,
+            "component_kind" => "sink",
+            "component_type" => "gelf",
+            "reason" => self.reason,
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use super::InternalEvent;
+use crate::sources::graphite::parser::ParseError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct GraphiteEventReceived {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for GraphiteEventReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received line.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => "graphite",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "graphite",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphiteParseError {
+    pub error: ParseError,
+}
+
+impl InternalEvent for GraphiteParseError {
+    fn emit_logs(&self) {
+        warn!(
+            message = "failed to parse line.",
+            error = %self.error,
+            rate_limit_secs = 30
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("parse_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "graphite",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphiteUdpReadError {
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for GraphiteUdpReadError {
+    fn emit_logs(&self) {
+        error!(message = "error reading datagram.", error = %self.error);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("graphite_udp_read_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "graphite",
+            "mode" => "udp",
+        );
+    }
+}
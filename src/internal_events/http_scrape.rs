@@ -0,0 +1,103 @@
+use super::InternalEvent;
+use crate::sources::http_scrape::DecodeError;
+use http::StatusCode;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct HttpScrapeEventsReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for HttpScrapeEventsReceived {
+    fn emit_logs(&self) {
+        trace!(message = "scraped events.", count = %self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("requests_completed", 1,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpScrapeHttpError<'a> {
+    pub error: hyper::Error,
+    pub endpoint: &'a str,
+}
+
+impl InternalEvent for HttpScrapeHttpError<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "http request processing error.",
+            endpoint = %self.endpoint,
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("http_request_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpScrapeResponseError<'a> {
+    pub status: StatusCode,
+    pub endpoint: &'a str,
+}
+
+impl InternalEvent for HttpScrapeResponseError<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "unexpected response status.",
+            endpoint = %self.endpoint,
+            status = %self.status,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("http_request_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpScrapeDecodeError<'a> {
+    pub error: DecodeError,
+    pub endpoint: &'a str,
+}
+
+impl InternalEvent for HttpScrapeDecodeError<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed decoding response.",
+            endpoint = %self.endpoint,
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("parse_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "http_scrape",
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use super::InternalEvent;
+use crate::sources::ibm_mq::MqError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct IbmMqMessagesReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for IbmMqMessagesReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received messages.", count = %self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "ibm_mq",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "ibm_mq",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct IbmMqConnectFailed {
+    pub error: MqError,
+}
+
+impl InternalEvent for IbmMqConnectFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "unable to connect to the queue manager, retrying.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("connection_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "ibm_mq",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct IbmMqGetFailed {
+    pub error: MqError,
+}
+
+impl InternalEvent for IbmMqGetFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "getting messages failed; reconnecting.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("get_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "ibm_mq",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct IbmMqCommitFailed {
+    pub error: MqError,
+}
+
+impl InternalEvent for IbmMqCommitFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "committing messages failed, they will be got again; reconnecting.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("commit_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "ibm_mq",
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct JournaldEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for JournaldEventSent {
+    fn emit_logs(&self) {
+        trace!(message = "sent event.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "journald",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "journald",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct JournaldSendFailed<'a> {
+    pub error: std::io::Error,
+    pub path: &'a std::path::Path,
+}
+
+impl InternalEvent for JournaldSendFailed<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed to send event to the journal; dropping event.",
+            error = %self.error,
+            path = ?self.path,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("send_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "journald",
+        );
+    }
+}
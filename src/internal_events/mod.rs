@@ -1,38 +1,232 @@
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-socket",
+    feature = "sources-splunk_hec"
+))]
+mod access;
+mod accounting;
 mod add_fields;
+#[cfg(feature = "transforms-aggregate")]
+mod aggregate;
+#[cfg(feature = "sinks-alerts")]
+mod alerts;
+#[cfg(feature = "allocation-tracing")]
+mod allocations;
+#[cfg(feature = "sinks-amqp")]
+mod amqp;
+#[cfg(feature = "sources-amqp")]
+mod amqp_source;
+#[cfg(feature = "sinks-aws_kinesis_firehose")]
+mod aws_kinesis_firehose;
 mod aws_kinesis_streams;
+#[cfg(feature = "sinks-aws_s3")]
+mod aws_s3;
+#[cfg(feature = "sources-aws_s3")]
+mod aws_s3_source;
+#[cfg(feature = "sources-azure_event_hubs")]
+mod azure_event_hubs_source;
+mod batch;
 mod blackhole;
+mod buffer;
+#[cfg(feature = "leveldb")]
+mod disk_buffer;
 mod elasticsearch;
+#[cfg(feature = "sinks-exec")]
+mod exec;
+#[cfg(feature = "sources-exec")]
+mod exec_source;
 mod file;
+#[cfg(feature = "sinks-gcp")]
+mod gcp_pubsub;
+#[cfg(feature = "sources-gcp_pubsub")]
+mod gcp_pubsub_source;
+#[cfg(any(feature = "sources-socket", feature = "sinks-gelf"))]
+mod gelf;
+#[cfg(feature = "sources-graphite")]
+mod graphite;
+#[cfg(feature = "sources-http_scrape")]
+mod http_scrape;
+#[cfg(feature = "sources-ibm_mq")]
+mod ibm_mq;
+#[cfg(all(feature = "sinks-journald", feature = "unix"))]
+mod journald;
 mod json;
 #[cfg(feature = "transforms-lua")]
 mod lua;
+#[cfg(feature = "sinks-nats")]
+mod nats;
+#[cfg(feature = "sources-nats")]
+mod nats_source;
+#[cfg(feature = "sources-opentelemetry")]
+mod opentelemetry;
+#[cfg(feature = "sinks-postgres")]
+mod postgres;
+#[cfg(feature = "sources-postgres_cdc")]
+mod postgres_cdc;
+mod profile;
 #[cfg(feature = "sources-prometheus")]
 mod prometheus;
+#[cfg(feature = "sinks-prometheus")]
+mod prometheus_pushgateway;
+#[cfg(feature = "sinks-prometheus")]
+mod prometheus_remote_write;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+mod quota;
+#[cfg(feature = "sinks-redis")]
+mod redis;
+#[cfg(feature = "sources-redis")]
+mod redis_source;
+#[cfg(feature = "transforms-reduce")]
+mod reduce;
 mod regex;
+mod scheduler;
+#[cfg(feature = "sinks-smtp")]
+mod smtp;
+#[cfg(feature = "sources-snmp_trap")]
+mod snmp_trap;
 mod splunk_hec;
+#[cfg(any(
+    feature = "transforms-aggregate",
+    feature = "transforms-dedupe",
+    feature = "transforms-reduce"
+))]
+mod state_snapshot;
 mod syslog;
 mod tcp;
+#[cfg(feature = "transforms-throttle")]
+mod throttle;
+mod tls;
 mod udp;
 mod unix;
 mod vector;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+mod wal;
+#[cfg(any(feature = "sinks-aws_s3", feature = "sinks-gcp"))]
+mod watermark;
 
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-socket",
+    feature = "sources-splunk_hec"
+))]
+pub use self::access::*;
+pub use self::accounting::*;
 pub use self::add_fields::*;
+#[cfg(feature = "transforms-aggregate")]
+pub use self::aggregate::*;
+#[cfg(feature = "sinks-alerts")]
+pub use self::alerts::*;
+#[cfg(feature = "allocation-tracing")]
+pub use self::allocations::*;
+#[cfg(feature = "sinks-amqp")]
+pub use self::amqp::*;
+#[cfg(feature = "sources-amqp")]
+pub use self::amqp_source::*;
+#[cfg(feature = "sinks-aws_kinesis_firehose")]
+pub use self::aws_kinesis_firehose::*;
 pub use self::aws_kinesis_streams::*;
+#[cfg(feature = "sinks-aws_s3")]
+pub use self::aws_s3::*;
+#[cfg(feature = "sources-aws_s3")]
+pub use self::aws_s3_source::*;
+#[cfg(feature = "sources-azure_event_hubs")]
+pub use self::azure_event_hubs_source::*;
+pub use self::batch::*;
 pub use self::blackhole::*;
+pub use self::buffer::*;
+#[cfg(feature = "leveldb")]
+pub use self::disk_buffer::*;
 pub use self::elasticsearch::*;
+#[cfg(feature = "sinks-exec")]
+pub use self::exec::*;
+#[cfg(feature = "sources-exec")]
+pub use self::exec_source::*;
 pub use self::file::*;
+#[cfg(feature = "sinks-gcp")]
+pub use self::gcp_pubsub::*;
+#[cfg(feature = "sources-gcp_pubsub")]
+pub use self::gcp_pubsub_source::*;
+#[cfg(any(feature = "sources-socket", feature = "sinks-gelf"))]
+pub use self::gelf::*;
+#[cfg(feature = "sources-graphite")]
+pub use self::graphite::*;
+#[cfg(feature = "sources-http_scrape")]
+pub use self::http_scrape::*;
+#[cfg(feature = "sources-ibm_mq")]
+pub use self::ibm_mq::*;
+#[cfg(all(feature = "sinks-journald", feature = "unix"))]
+pub use self::journald::*;
 pub use self::json::*;
 #[cfg(feature = "transforms-lua")]
 pub use self::lua::*;
+#[cfg(feature = "sinks-nats")]
+pub use self::nats::*;
+#[cfg(feature = "sources-nats")]
+pub use self::nats_source::*;
+#[cfg(feature = "sources-opentelemetry")]
+pub use self::opentelemetry::*;
+#[cfg(feature = "sinks-postgres")]
+pub use self::postgres::*;
+#[cfg(feature = "sources-postgres_cdc")]
+pub use self::postgres_cdc::*;
+pub use self::profile::*;
 #[cfg(feature = "sources-prometheus")]
 pub use self::prometheus::*;
+#[cfg(feature = "sinks-prometheus")]
+pub use self::prometheus_pushgateway::*;
+#[cfg(feature = "sinks-prometheus")]
+pub use self::prometheus_remote_write::*;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+pub use self::quota::*;
+#[cfg(feature = "sinks-redis")]
+pub use self::redis::*;
+#[cfg(feature = "sources-redis")]
+pub use self::redis_source::*;
+#[cfg(feature = "transforms-reduce")]
+pub use self::reduce::*;
 pub use self::regex::*;
+pub use self::scheduler::*;
+#[cfg(feature = "sinks-smtp")]
+pub use self::smtp::*;
+#[cfg(feature = "sources-snmp_trap")]
+pub use self::snmp_trap::*;
 pub use self::splunk_hec::*;
+#[cfg(any(
+    feature = "transforms-aggregate",
+    feature = "transforms-dedupe",
+    feature = "transforms-reduce"
+))]
+pub use self::state_snapshot::*;
 pub use self::syslog::*;
 pub use self::tcp::*;
+#[cfg(feature = "transforms-throttle")]
+pub use self::throttle::*;
+pub use self::tls::*;
 pub use self::udp::*;
 pub use self::unix::*;
 pub use self::vector::*;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+pub use self::wal::*;
+#[cfg(any(feature = "sinks-aws_s3", feature = "sinks-gcp"))]
+pub use self::watermark::*;
 
 pub trait InternalEvent: std::fmt::Debug {
     fn emit_logs(&self) {}
@@ -0,0 +1,50 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct OpentelemetryEventsReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for OpentelemetryEventsReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received events.", count = %self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "opentelemetry",
+        );
+        counter!(
+            "bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "opentelemetry",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct OpentelemetryRequestError<'a> {
+    pub error: &'a dyn std::error::Error,
+}
+
+impl<'a> InternalEvent for OpentelemetryRequestError<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "rejected OTLP request.",
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!(
+            "request_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "opentelemetry",
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use super::InternalEvent;
+use crate::sinks::postgres::ColumnType;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct PostgresEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for PostgresEventSent {
+    fn emit_logs(&self) {
+        trace!(message = "processed one event.");
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "postgres",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "postgres",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct PostgresFieldInvalid<'a> {
+    pub column: &'a str,
+    pub column_type: ColumnType,
+}
+
+impl<'a> InternalEvent for PostgresFieldInvalid<'a> {
+    fn emit_logs(&self) {
+        warn!(
+            message = "field can't be converted to the type of its column; dropping event.",
+            column = %self.column,
+            column_type = ?self.column_type,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_dropped", 1,
+            "component_kind" => "sink",
+            "component_type" => "postgres",
+        );
+    }
+}
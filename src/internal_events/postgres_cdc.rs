@@ -0,0 +1,52 @@
+use super::InternalEvent;
+use crate::sources::postgres_cdc::ReplicateError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct PostgresCdcChangesReceived {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for PostgresCdcChangesReceived {
+    fn emit_logs(&self) {
+        trace!(
+            message = "received changes.",
+            count = %self.count,
+            byte_size = %self.byte_size,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.count as u64,
+            "component_kind" => "source",
+            "component_type" => "postgres_cdc",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "postgres_cdc",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct PostgresCdcReplicateFailed {
+    pub error: ReplicateError,
+}
+
+impl InternalEvent for PostgresCdcReplicateFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "reading changes failed; reconnecting.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("replicate_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "postgres_cdc",
+        );
+    }
+}
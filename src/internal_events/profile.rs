@@ -0,0 +1,20 @@
+use super::InternalEvent;
+use metrics::timing;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct TransformEventTimed<'a> {
+    pub component_name: &'a str,
+    pub component_type: &'static str,
+    pub elapsed: Duration,
+}
+
+impl InternalEvent for TransformEventTimed<'_> {
+    fn emit_metrics(&self) {
+        timing!("transform_event_duration_nanoseconds", self.elapsed.as_nanos() as u64,
+            "component_kind" => "transform",
+            "component_name" => self.component_name.to_owned(),
+            "component_type" => self.component_type,
+        );
+    }
+}
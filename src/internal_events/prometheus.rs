@@ -1,5 +1,5 @@
 use super::InternalEvent;
-use crate::sources::prometheus::parser::ParserError;
+use crate::sources::prometheus::{kubernetes::DiscoveryError, parser::ParserError};
 use metrics::counter;
 
 #[derive(Debug)]
@@ -50,3 +50,36 @@ impl InternalEvent for PrometheusHttpError {
         );
     }
 }
+
+#[derive(Debug)]
+pub struct PrometheusTargetsDiscovered {
+    pub count: usize,
+}
+
+impl InternalEvent for PrometheusTargetsDiscovered {
+    fn emit_logs(&self) {
+        debug!(message = "discovered targets.", count = %self.count);
+    }
+}
+
+#[derive(Debug)]
+pub struct PrometheusKubernetesDiscoveryError {
+    pub error: DiscoveryError,
+}
+
+impl InternalEvent for PrometheusKubernetesDiscoveryError {
+    fn emit_logs(&self) {
+        error!(
+            message = "kubernetes discovery failed, keeping the previous targets.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("discovery_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "prometheus",
+        );
+    }
+}
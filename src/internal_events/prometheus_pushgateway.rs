@@ -0,0 +1,55 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct PrometheusPushed<'a> {
+    pub group: &'a str,
+    pub series: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for PrometheusPushed<'_> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "pushed metrics.",
+            group = %self.group,
+            series = %self.series,
+            byte_size = %self.byte_size,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.series as u64,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_pushgateway",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_pushgateway",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct PrometheusPushFailed<'a> {
+    pub group: &'a str,
+    pub reason: String,
+}
+
+impl InternalEvent for PrometheusPushFailed<'_> {
+    fn emit_logs(&self) {
+        error!(
+            message = "push to the pushgateway failed.",
+            group = %self.group,
+            reason = %self.reason,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("send_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_pushgateway",
+        );
+    }
+}
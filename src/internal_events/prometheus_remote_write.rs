@@ -0,0 +1,78 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct PrometheusRemoteWriteSent {
+    pub series: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for PrometheusRemoteWriteSent {
+    fn emit_logs(&self) {
+        debug!(
+            message = "sent write request.",
+            series = %self.series,
+            byte_size = %self.byte_size,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", self.series as u64,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_remote_write",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_remote_write",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct PrometheusRemoteWriteFailed {
+    pub reason: String,
+    pub will_retry: bool,
+}
+
+impl InternalEvent for PrometheusRemoteWriteFailed {
+    fn emit_logs(&self) {
+        if self.will_retry {
+            warn!(
+                message = "write request failed; retrying.",
+                reason = %self.reason,
+                rate_limit_secs = 30,
+            );
+        } else {
+            error!(
+                message = "write request failed; dropping samples.",
+                reason = %self.reason,
+                rate_limit_secs = 30,
+            );
+        }
+    }
+
+    fn emit_metrics(&self) {
+        counter!("send_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_remote_write",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct PrometheusStaleSeries {
+    pub count: usize,
+}
+
+impl InternalEvent for PrometheusStaleSeries {
+    fn emit_logs(&self) {
+        debug!(message = "marked series as stale.", count = %self.count);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("stale_series", self.count as u64,
+            "component_kind" => "sink",
+            "component_type" => "prometheus_remote_write",
+        );
+    }
+}
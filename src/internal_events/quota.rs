@@ -0,0 +1,22 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub retry_after: Duration,
+}
+
+impl InternalEvent for QuotaExceeded {
+    fn emit_logs(&self) {
+        warn!(
+            message = "ingestion quota exceeded; refusing request.",
+            retry_after = ?self.retry_after,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("quota_refused_requests", 1);
+    }
+}
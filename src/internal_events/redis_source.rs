@@ -0,0 +1,47 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct RedisMessageReceived<'a> {
+    pub key: &'a str,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for RedisMessageReceived<'_> {
+    fn emit_logs(&self) {
+        trace!(message = "received message.", key = %self.key, byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => "redis",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "redis",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct RedisReceiveFailed {
+    pub error: redis::RedisError,
+}
+
+impl InternalEvent for RedisReceiveFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "failed to receive message.",
+            error = %self.error,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("receive_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "redis",
+        );
+    }
+}
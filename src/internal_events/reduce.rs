@@ -0,0 +1,14 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct ReduceEventProcessed;
+
+impl InternalEvent for ReduceEventProcessed {
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "transform",
+            "component_type" => "reduce",
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct ScrapeDeadlineExceeded {
+    pub source_type: &'static str,
+    pub deadline: Duration,
+}
+
+impl InternalEvent for ScrapeDeadlineExceeded {
+    fn emit_logs(&self) {
+        warn!(
+            message = "scrape didn't finish before its deadline, skipping it.",
+            source_type = %self.source_type,
+            deadline = ?self.deadline,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("scrape_deadlines_exceeded", 1,
+            "component_kind" => "source",
+            "component_type" => self.source_type,
+        );
+    }
+}
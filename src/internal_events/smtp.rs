@@ -0,0 +1,74 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct SmtpEventSent {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for SmtpEventSent {
+    fn emit_logs(&self) {
+        debug!(message = "sent mail.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "sink",
+            "component_type" => "smtp",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "sink",
+            "component_type" => "smtp",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct SmtpSendFailed<E> {
+    pub error: E,
+    pub will_retry: bool,
+}
+
+impl<E: std::fmt::Display + std::fmt::Debug> InternalEvent for SmtpSendFailed<E> {
+    fn emit_logs(&self) {
+        if self.will_retry {
+            warn!(
+                message = "failed to send mail; retrying.",
+                error = %self.error,
+                rate_limit_secs = 30,
+            );
+        } else {
+            error!(
+                message = "failed to send mail; dropping event.",
+                error = %self.error,
+                rate_limit_secs = 30,
+            );
+        }
+    }
+
+    fn emit_metrics(&self) {
+        counter!("send_errors", 1,
+            "component_kind" => "sink",
+            "component_type" => "smtp",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct SmtpEventRateLimited;
+
+impl InternalEvent for SmtpEventRateLimited {
+    fn emit_logs(&self) {
+        warn!(
+            message = "mail rate limit reached; dropping event.",
+            rate_limit_secs = 60,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_dropped", 1,
+            "component_kind" => "sink",
+            "component_type" => "smtp",
+        );
+    }
+}
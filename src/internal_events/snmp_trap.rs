@@ -0,0 +1,65 @@
+use super::InternalEvent;
+use crate::sources::snmp_trap::DecodeError;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct SnmpTrapEventReceived {
+    pub byte_size: usize,
+}
+
+impl InternalEvent for SnmpTrapEventReceived {
+    fn emit_logs(&self) {
+        trace!(message = "received trap.", byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => "snmp_trap",
+        );
+        counter!("bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => "snmp_trap",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct SnmpTrapDecodeFailed {
+    pub error: DecodeError,
+}
+
+impl InternalEvent for SnmpTrapDecodeFailed {
+    fn emit_logs(&self) {
+        warn!(
+            message = "unable to decode trap.",
+            error = %self.error,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("decode_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "snmp_trap",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct SnmpTrapUdpReadError {
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for SnmpTrapUdpReadError {
+    fn emit_logs(&self) {
+        error!(message = "error reading datagram.", error = %self.error);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("snmp_trap_udp_read_errors", 1,
+            "component_kind" => "source",
+            "component_type" => "snmp_trap",
+        );
+    }
+}
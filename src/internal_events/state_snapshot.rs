@@ -0,0 +1,83 @@
+use super::InternalEvent;
+use metrics::counter;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct StateSnapshotSaved<'a> {
+    pub path: &'a Path,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for StateSnapshotSaved<'_> {
+    fn emit_logs(&self) {
+        info!(message = "saved state snapshot.", path = ?self.path, byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("state_snapshots_saved", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateSnapshotRestored<'a> {
+    pub path: &'a Path,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for StateSnapshotRestored<'_> {
+    fn emit_logs(&self) {
+        info!(message = "restored state snapshot.", path = ?self.path, byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("state_snapshots_restored", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateSnapshotFailed<'a> {
+    pub path: &'a Path,
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for StateSnapshotFailed<'_> {
+    fn emit_logs(&self) {
+        error!(message = "state snapshot failed.", path = ?self.path, error = %self.error);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("state_snapshot_errors", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateSnapshotInvalid<'a> {
+    pub path: &'a Path,
+    pub error: String,
+}
+
+impl InternalEvent for StateSnapshotInvalid<'_> {
+    fn emit_logs(&self) {
+        error!(message = "invalid state snapshot; discarding it.", path = ?self.path, error = %self.error);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("state_snapshot_errors", 1);
+    }
+}
+
+#[derive(Debug)]
+pub struct StateSnapshotStale<'a> {
+    pub path: &'a Path,
+    pub max_age_secs: u64,
+}
+
+impl InternalEvent for StateSnapshotStale<'_> {
+    fn emit_logs(&self) {
+        warn!(message = "state snapshot is stale; discarding it.", path = ?self.path, max_age_secs = %self.max_age_secs);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("state_snapshots_discarded", 1);
+    }
+}
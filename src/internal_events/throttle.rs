@@ -0,0 +1,44 @@
+use super::InternalEvent;
+use crate::transforms::throttle::Action;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct ThrottleEventProcessed;
+
+impl InternalEvent for ThrottleEventProcessed {
+    fn emit_metrics(&self) {
+        counter!("events_processed", 1,
+            "component_kind" => "transform",
+            "component_type" => "throttle",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ThrottleEventThrottled<'a> {
+    pub key: &'a str,
+    pub action: Action,
+}
+
+impl InternalEvent for ThrottleEventThrottled<'_> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "event throttled.",
+            key = %self.key,
+            action = ?self.action,
+            rate_limit_secs = 30,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        let action = match self.action {
+            Action::Drop => "drop",
+            Action::Tag => "tag",
+        };
+        counter!("events_throttled", 1,
+            "component_kind" => "transform",
+            "component_type" => "throttle",
+            "action" => action,
+        );
+    }
+}
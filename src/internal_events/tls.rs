@@ -0,0 +1,22 @@
+use super::InternalEvent;
+use crate::tls::VerifyFailure;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct TlsPeerVerificationFailed {
+    pub failure: VerifyFailure,
+}
+
+impl InternalEvent for TlsPeerVerificationFailed {
+    fn emit_logs(&self) {
+        error!(
+            message = "TLS peer certificate verification failed.",
+            reason = %self.failure,
+            rate_limit_secs = 10,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("tls_verification_failures", 1);
+    }
+}
@@ -43,3 +43,27 @@ impl InternalEvent for UdpSocketError {
         );
     }
 }
+
+#[derive(Debug)]
+pub struct UdpShardEventReceived {
+    pub component_type: &'static str,
+    pub shard: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for UdpShardEventReceived {
+    fn emit_metrics(&self) {
+        counter!("shard_events_processed", 1,
+            "component_kind" => "source",
+            "component_type" => self.component_type,
+            "mode" => "udp",
+            "shard" => self.shard.to_string(),
+        );
+        counter!("shard_bytes_processed", self.byte_size as u64,
+            "component_kind" => "source",
+            "component_type" => self.component_type,
+            "mode" => "udp",
+            "shard" => self.shard.to_string(),
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use super::InternalEvent;
+use metrics::counter;
+
+#[derive(Debug)]
+pub struct WalEventsRecorded {
+    pub count: usize,
+    pub byte_size: usize,
+}
+
+impl InternalEvent for WalEventsRecorded {
+    fn emit_logs(&self) {
+        trace!(message = "recorded events.", count = %self.count, byte_size = %self.byte_size);
+    }
+
+    fn emit_metrics(&self) {
+        counter!("wal_events_recorded", self.count as u64);
+        counter!("wal_bytes_recorded", self.byte_size as u64);
+    }
+}
+
+#[derive(Debug)]
+pub struct WalRecordInvalid {
+    pub error: std::io::Error,
+}
+
+impl InternalEvent for WalRecordInvalid {
+    fn emit_logs(&self) {
+        error!(
+            message = "invalid write-ahead log record; skipping the rest of its segment.",
+            error = %self.error,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        counter!("wal_record_errors", 1);
+    }
+}
@@ -0,0 +1,28 @@
+use super::InternalEvent;
+use metrics::gauge;
+
+#[derive(Debug)]
+pub struct WatermarkAdvanced<'a> {
+    pub sink_type: &'static str,
+    pub partition: &'a str,
+    /// In seconds since the epoch.
+    pub watermark: i64,
+}
+
+impl InternalEvent for WatermarkAdvanced<'_> {
+    fn emit_logs(&self) {
+        debug!(
+            message = "watermark advanced.",
+            partition = %self.partition,
+            watermark = %self.watermark,
+        );
+    }
+
+    fn emit_metrics(&self) {
+        gauge!("watermark_timestamp_seconds", self.watermark,
+            "component_kind" => "sink",
+            "component_type" => self.sink_type,
+            "partition" => self.partition.to_owned(),
+        );
+    }
+}
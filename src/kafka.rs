@@ -8,6 +8,28 @@ use std::path::PathBuf;
 enum KafkaError {
     #[snafu(display("invalid path: {:?}", path))]
     InvalidPath { path: PathBuf },
+    #[snafu(display("SASL mechanism {} requires `{}` to be set", mechanism, option))]
+    MissingSaslOption {
+        mechanism: &'static str,
+        option: &'static str,
+    },
+    #[snafu(display(
+        "SASL mechanism GSSAPI requires vector to be built with the `kafka-gssapi` feature"
+    ))]
+    GssapiUnsupported,
+    #[snafu(display(
+        "SASL mechanism OAUTHBEARER requires `oauthbearer_unsecure_jwt`, as no other token handler is supported"
+    ))]
+    OAuthBearerUnsupported,
+    #[snafu(display("`bootstrap_servers` must be set, unless `azure_event_hubs` is"))]
+    MissingBootstrapServers,
+    #[snafu(display(
+        "`azure_event_hubs` configures the brokers, TLS and SASL, remove `{}`",
+        option
+    ))]
+    EventHubsConflict { option: &'static str },
+    #[snafu(display("Invalid Event Hubs connection string: {}", reason))]
+    InvalidConnectionString { reason: &'static str },
 }
 
 #[derive(Clone, Copy, Debug, Derivative, Deserialize, Serialize)]
@@ -55,7 +77,362 @@ impl KafkaTlsConfig {
     }
 }
 
+#[derive(Clone, Copy, Debug, Derivative, Deserialize, Serialize, Eq, PartialEq)]
+#[derivative(Default)]
+pub(crate) enum KafkaSaslMechanism {
+    #[derivative(Default)]
+    #[serde(rename = "PLAIN")]
+    Plain,
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+    #[serde(rename = "OAUTHBEARER")]
+    OAuthBearer,
+    #[serde(rename = "GSSAPI")]
+    Gssapi,
+}
+
+impl KafkaSaslMechanism {
+    fn as_str(self) -> &'static str {
+        match self {
+            KafkaSaslMechanism::Plain => "PLAIN",
+            KafkaSaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            KafkaSaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+            KafkaSaslMechanism::OAuthBearer => "OAUTHBEARER",
+            KafkaSaslMechanism::Gssapi => "GSSAPI",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KafkaSaslConfig {
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub mechanism: KafkaSaslMechanism,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Passed through as librdkafka's `sasl.oauthbearer.config`.
+    pub oauthbearer_config: Option<String>,
+    /// Use librdkafka's built-in unsecured JWT token handler. Only meant for
+    /// development brokers, but the only handler refreshing the tokens: the
+    /// client doesn't let us register one of our own.
+    #[serde(default)]
+    pub oauthbearer_unsecure_jwt: bool,
+    pub kerberos_service_name: Option<String>,
+    pub kerberos_principal: Option<String>,
+    pub kerberos_keytab: Option<PathBuf>,
+    pub kerberos_kinit_cmd: Option<String>,
+}
+
+impl KafkaSaslConfig {
+    /// Applies the SASL settings. Must be called after the TLS settings, as
+    /// it upgrades `security.protocol` to its SASL counterpart.
+    pub(crate) fn apply(&self, client: &mut ClientConfig, tls_enabled: bool) -> crate::Result<()> {
+        if !self.enabled() {
+            return Ok(());
+        }
+
+        client.set(
+            "security.protocol",
+            if tls_enabled {
+                "sasl_ssl"
+            } else {
+                "sasl_plaintext"
+            },
+        );
+        client.set("sasl.mechanism", self.mechanism.as_str());
+
+        match self.mechanism {
+            KafkaSaslMechanism::Plain
+            | KafkaSaslMechanism::ScramSha256
+            | KafkaSaslMechanism::ScramSha512 => {
+                client.set("sasl.username", self.required(&self.username, "username")?);
+                client.set("sasl.password", self.required(&self.password, "password")?);
+            }
+            KafkaSaslMechanism::OAuthBearer => {
+                if !self.oauthbearer_unsecure_jwt {
+                    return Err(KafkaError::OAuthBearerUnsupported.into());
+                }
+                client.set("enable.sasl.oauthbearer.unsecure.jwt", "true");
+                if let Some(config) = &self.oauthbearer_config {
+                    client.set("sasl.oauthbearer.config", config);
+                }
+            }
+            KafkaSaslMechanism::Gssapi => {
+                if !cfg!(feature = "kafka-gssapi") {
+                    return Err(KafkaError::GssapiUnsupported.into());
+                }
+                if let Some(name) = &self.kerberos_service_name {
+                    client.set("sasl.kerberos.service.name", name);
+                }
+                if let Some(principal) = &self.kerberos_principal {
+                    client.set("sasl.kerberos.principal", principal);
+                }
+                if let Some(path) = &self.kerberos_keytab {
+                    client.set("sasl.kerberos.keytab", pathbuf_to_string(path)?);
+                }
+                if let Some(cmd) = &self.kerberos_kinit_cmd {
+                    client.set("sasl.kerberos.kinit.cmd", cmd);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+
+    fn required<'a>(
+        &self,
+        value: &'a Option<String>,
+        option: &'static str,
+    ) -> crate::Result<&'a str> {
+        value.as_deref().ok_or_else(|| {
+            KafkaError::MissingSaslOption {
+                mechanism: self.mechanism.as_str(),
+                option,
+            }
+            .into()
+        })
+    }
+}
+
+/// Connects to the Kafka endpoint of an Azure Event Hubs namespace.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct KafkaEventHubsConfig {
+    /// The connection string of the namespace or of a single event hub, as
+    /// shown in the shared access policies of the Azure portal.
+    pub connection_string: String,
+}
+
+impl KafkaEventHubsConfig {
+    /// Fills in the brokers, TLS and SASL settings, which the connection
+    /// string determines. Returns the event hub of the connection string,
+    /// when it is for a single one.
+    fn apply(
+        &self,
+        bootstrap_servers: &mut String,
+        tls: &mut Option<KafkaTlsConfig>,
+        sasl: &mut Option<KafkaSaslConfig>,
+    ) -> crate::Result<Option<String>> {
+        if !bootstrap_servers.is_empty() {
+            return Err(KafkaError::EventHubsConflict {
+                option: "bootstrap_servers",
+            }
+            .into());
+        }
+        if tls.is_some() {
+            return Err(KafkaError::EventHubsConflict { option: "tls" }.into());
+        }
+        if sasl.is_some() {
+            return Err(KafkaError::EventHubsConflict { option: "sasl" }.into());
+        }
+
+        let mut host = None;
+        let mut has_key = false;
+        let mut event_hub = None;
+        for part in self.connection_string.split(';') {
+            let mut pair = part.splitn(2, '=');
+            match (pair.next().map(str::trim), pair.next()) {
+                (Some("Endpoint"), Some(endpoint)) => {
+                    let endpoint = endpoint.trim_start_matches("sb://").trim_end_matches('/');
+                    host = Some(endpoint.to_owned());
+                }
+                (Some("SharedAccessKey"), Some(_)) => has_key = true,
+                (Some("EntityPath"), Some(path)) => event_hub = Some(path.to_owned()),
+                _ => (),
+            }
+        }
+        let host = host.ok_or(KafkaError::InvalidConnectionString {
+            reason: "missing `Endpoint`",
+        })?;
+        if !has_key {
+            return Err(KafkaError::InvalidConnectionString {
+                reason: "missing `SharedAccessKey`",
+            }
+            .into());
+        }
+
+        // See https://docs.microsoft.com/azure/event-hubs/event-hubs-kafka-connect-tutorial
+        *bootstrap_servers = format!("{}:9093", host);
+        *tls = Some(KafkaTlsConfig {
+            enabled: Some(true),
+            options: Default::default(),
+        });
+        *sasl = Some(KafkaSaslConfig {
+            enabled: Some(true),
+            mechanism: KafkaSaslMechanism::Plain,
+            username: Some("$ConnectionString".into()),
+            password: Some(self.connection_string.clone()),
+            ..Default::default()
+        });
+        Ok(event_hub)
+    }
+}
+
+/// Resolves the brokers of the kafka source and sink, from the Event Hubs
+/// connection string when set.
+pub(crate) fn resolve_brokers(
+    event_hubs: &Option<KafkaEventHubsConfig>,
+    bootstrap_servers: &mut String,
+    tls: &mut Option<KafkaTlsConfig>,
+    sasl: &mut Option<KafkaSaslConfig>,
+) -> crate::Result<Option<String>> {
+    match event_hubs {
+        Some(event_hubs) => event_hubs.apply(bootstrap_servers, tls, sasl),
+        None if bootstrap_servers.is_empty() => Err(KafkaError::MissingBootstrapServers.into()),
+        None => Ok(None),
+    }
+}
+
+/// Applies the TLS and SASL settings shared by the kafka source and sink.
+pub(crate) fn apply_security(
+    client: &mut ClientConfig,
+    tls: &Option<KafkaTlsConfig>,
+    sasl: &Option<KafkaSaslConfig>,
+) -> crate::Result<()> {
+    if let Some(tls) = tls {
+        tls.apply(client)?;
+    }
+    if let Some(sasl) = sasl {
+        let tls_enabled = tls.as_ref().map(KafkaTlsConfig::enabled).unwrap_or(false);
+        sasl.apply(client, tls_enabled)?;
+    }
+    Ok(())
+}
+
 fn pathbuf_to_string(path: &PathBuf) -> crate::Result<&str> {
     path.to_str()
         .ok_or_else(|| KafkaError::InvalidPath { path: path.into() }.into())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(tls: Option<KafkaTlsConfig>, sasl: KafkaSaslConfig) -> crate::Result<ClientConfig> {
+        let mut client = ClientConfig::new();
+        apply_security(&mut client, &tls, &Some(sasl))?;
+        Ok(client)
+    }
+
+    #[test]
+    fn sasl_scram_over_tls() {
+        let tls = KafkaTlsConfig {
+            enabled: Some(true),
+            ..Default::default()
+        };
+        let sasl = KafkaSaslConfig {
+            enabled: Some(true),
+            mechanism: KafkaSaslMechanism::ScramSha512,
+            username: Some("user".into()),
+            password: Some("pass".into()),
+            ..Default::default()
+        };
+
+        let client = apply(Some(tls), sasl).unwrap();
+        assert_eq!(client.get("security.protocol"), Some("sasl_ssl"));
+        assert_eq!(client.get("sasl.mechanism"), Some("SCRAM-SHA-512"));
+        assert_eq!(client.get("sasl.username"), Some("user"));
+        assert_eq!(client.get("sasl.password"), Some("pass"));
+    }
+
+    #[test]
+    fn sasl_plain_requires_credentials() {
+        let sasl = KafkaSaslConfig {
+            enabled: Some(true),
+            username: Some("user".into()),
+            ..Default::default()
+        };
+
+        assert!(apply(None, sasl).is_err());
+    }
+
+    #[test]
+    fn sasl_oauthbearer() {
+        let sasl = KafkaSaslConfig {
+            enabled: Some(true),
+            mechanism: KafkaSaslMechanism::OAuthBearer,
+            oauthbearer_config: Some("principal=vector".into()),
+            ..Default::default()
+        };
+        assert!(apply(None, sasl.clone()).is_err());
+
+        let sasl = KafkaSaslConfig {
+            oauthbearer_unsecure_jwt: true,
+            ..sasl
+        };
+        let client = apply(None, sasl).unwrap();
+        assert_eq!(client.get("security.protocol"), Some("sasl_plaintext"));
+        assert_eq!(client.get("sasl.mechanism"), Some("OAUTHBEARER"));
+        assert_eq!(
+            client.get("enable.sasl.oauthbearer.unsecure.jwt"),
+            Some("true")
+        );
+        assert_eq!(
+            client.get("sasl.oauthbearer.config"),
+            Some("principal=vector")
+        );
+    }
+
+    #[test]
+    fn sasl_disabled_is_noop() {
+        let sasl = KafkaSaslConfig {
+            enabled: Some(false),
+            username: Some("user".into()),
+            ..Default::default()
+        };
+
+        let client = apply(None, sasl).unwrap();
+        assert_eq!(client.get("security.protocol"), None);
+    }
+
+    #[test]
+    fn event_hubs_connection_string() {
+        let event_hubs = KafkaEventHubsConfig {
+            connection_string: "Endpoint=sb://logs.servicebus.windows.net/;\
+                                SharedAccessKeyName=send;SharedAccessKey=a2V5;EntityPath=app"
+                .into(),
+        };
+        let (mut bootstrap_servers, mut tls, mut sasl) = (String::new(), None, None);
+        let event_hub = resolve_brokers(
+            &Some(event_hubs.clone()),
+            &mut bootstrap_servers,
+            &mut tls,
+            &mut sasl,
+        )
+        .unwrap();
+        assert_eq!(event_hub.as_deref(), Some("app"));
+        assert_eq!(bootstrap_servers, "logs.servicebus.windows.net:9093");
+
+        let mut client = ClientConfig::new();
+        apply_security(&mut client, &tls, &sasl).unwrap();
+        assert_eq!(client.get("security.protocol"), Some("sasl_ssl"));
+        assert_eq!(client.get("sasl.mechanism"), Some("PLAIN"));
+        assert_eq!(client.get("sasl.username"), Some("$ConnectionString"));
+        assert_eq!(
+            client.get("sasl.password"),
+            Some(event_hubs.connection_string.as_str())
+        );
+
+        let mut bootstrap_servers = "localhost:9092".to_owned();
+        assert!(resolve_brokers(
+            &Some(event_hubs),
+            &mut bootstrap_servers,
+            &mut None,
+            &mut None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sasl_mechanism_names() {
+        let sasl: KafkaSaslConfig = toml::from_str(r#"mechanism = "SCRAM-SHA-256""#).unwrap();
+        assert_eq!(sasl.mechanism, KafkaSaslMechanism::ScramSha256);
+    }
+}
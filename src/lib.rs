@@ -5,17 +5,42 @@ extern crate tracing;
 #[macro_use]
 extern crate derivative;
 
-#[cfg(feature = "jemallocator")]
+#[cfg(all(feature = "jemallocator", not(feature = "allocation-tracing")))]
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
+#[cfg(all(feature = "jemallocator", feature = "allocation-tracing"))]
+#[global_allocator]
+static ALLOC: allocations::TrackingAllocator<jemallocator::Jemalloc> =
+    allocations::TrackingAllocator::new(jemallocator::Jemalloc);
+
+#[cfg(all(not(feature = "jemallocator"), feature = "allocation-tracing"))]
+#[global_allocator]
+static ALLOC: allocations::TrackingAllocator<std::alloc::System> =
+    allocations::TrackingAllocator::new(std::alloc::System);
+
+#[cfg(feature = "allocation-tracing")]
+pub mod allocations;
+#[cfg(any(feature = "sources-amqp", feature = "sinks-amqp"))]
+pub mod amqp;
+#[cfg(any(
+    feature = "sources-azure_event_hubs",
+    feature = "sinks-azure_event_hubs"
+))]
+pub mod azure;
 pub mod buffers;
 pub mod conditions;
 pub mod config_paths;
 pub mod dns;
+pub mod embed;
 pub mod event;
 pub mod expiring_hash_map;
+#[cfg(any(feature = "sources-gcp_pubsub", feature = "sinks-gcp"))]
+pub mod gcp;
+#[cfg(any(feature = "sources-socket", feature = "sinks-gelf"))]
+pub mod gelf;
 pub mod generate;
+pub mod hops;
 #[macro_use]
 pub mod internal_events;
 pub mod async_read;
@@ -23,7 +48,13 @@ pub mod async_read;
 pub mod kafka;
 pub mod list;
 pub mod metrics;
+#[cfg(any(feature = "sources-nats", feature = "sinks-nats"))]
+pub mod nats;
+#[cfg(any(feature = "sources-opentelemetry", feature = "sinks-opentelemetry"))]
+pub mod opentelemetry;
+pub mod permissions;
 pub mod region;
+pub mod replay;
 pub mod runtime;
 pub mod serde;
 pub mod shutdown;
@@ -34,6 +65,7 @@ pub mod template;
 pub mod test_util;
 pub mod tls;
 pub mod topology;
+pub mod totals;
 pub mod trace;
 pub mod transforms;
 pub mod types;
@@ -6,12 +6,16 @@ use std::{
     cmp::max,
     fs::File,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use structopt::{clap::AppSettings, StructOpt};
 #[cfg(unix)]
-use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGQUIT, SIGTERM};
-use topology::Config;
-use vector::{config_paths, event, generate, list, metrics, runtime, topology, trace, unit_test};
+use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2};
+use topology::{config::remote::RemoteConfig, Config};
+use vector::{
+    buffers, config_paths, event, generate, hops, list, metrics, replay, runtime, sinks, topology,
+    totals, trace, unit_test,
+};
 
 #[derive(StructOpt, Debug)]
 #[structopt(rename_all = "kebab-case")]
@@ -69,6 +73,31 @@ struct RootOpts {
     /// Watch for changes in configuration file, and reload accordingly.
     #[structopt(short, long)]
     watch_config: bool,
+
+    /// Write a JSON report of the components to stderr once their healthchecks
+    /// completed, with their configuration, secrets masked, and the addresses
+    /// they listen on.
+    #[structopt(long)]
+    startup_report: bool,
+
+    /// Fetch a signed configuration bundle from this URL, loading it along with
+    /// the config files and reloading whenever it changes.
+    #[structopt(long)]
+    remote_config: Option<String>,
+
+    /// The PEM encoded public key verifying the signatures of remote configuration bundles.
+    #[structopt(long)]
+    remote_config_key: Option<PathBuf>,
+
+    /// How often to fetch the remote configuration bundle.
+    #[structopt(long, default_value = "60")]
+    remote_config_interval_secs: u64,
+
+    /// Where the verified remote configuration bundle is kept, so it's available
+    /// when the URL isn't on startup. Defaults to `remote_config.toml` in the
+    /// default data directory.
+    #[structopt(long)]
+    remote_config_path: Option<PathBuf>,
 }
 
 #[derive(StructOpt, Debug)]
@@ -86,6 +115,18 @@ enum SubCommand {
     /// Run Vector config unit tests, then exit. This command is experimental and therefore subject to change.
     /// For guidance on how to write unit tests check out: https://vector.dev/docs/setup/guides/unit-testing/
     Test(unit_test::Opts),
+
+    /// Show the events and bytes each component handled, as persisted by
+    /// `accounting`, then exit.
+    Totals(totals::Opts),
+
+    /// Show the path and per-hop latency of the events traced by
+    /// `lifecycle`, or list the traces without an id, then exit.
+    Hops(hops::Opts),
+
+    /// Replay a range of the history of a `kafka` or `aws_s3` source through
+    /// the transforms and sinks downstream of it, then exit.
+    Replay(replay::Opts),
 }
 
 #[derive(StructOpt, Debug)]
@@ -99,6 +140,12 @@ struct Validate {
     #[structopt(short, long)]
     deny_warnings: bool,
 
+    /// Run the healthcheck of each sink and have it build a request for a
+    /// sample event. The requests of HTTP based sinks are built but not sent,
+    /// other sinks are only healthchecked.
+    #[structopt(long)]
+    dry_run: bool,
+
     /// Any number of Vector config files to validate. If none are specified the
     /// default config path `/etc/vector/vector.toml` will be targeted.
     paths: Vec<PathBuf>,
@@ -211,6 +258,9 @@ fn main() {
             SubCommand::List(l) => list::cmd(&l),
             SubCommand::Test(t) => unit_test::cmd(&t),
             SubCommand::Generate(g) => generate::cmd(&g),
+            SubCommand::Totals(t) => totals::cmd(&t),
+            SubCommand::Replay(r) => replay::cmd(&r),
+            SubCommand::Hops(h) => hops::cmd(&h),
         })
     });
 
@@ -223,27 +273,29 @@ fn main() {
         }
     }
 
-    let mut config_paths = config_paths::expand(opts.config_paths.clone()).unwrap_or_else(|| {
-        std::process::exit(exitcode::CONFIG);
+    let remote_config = opts.remote_config.as_ref().map(|url| {
+        start_remote_config(url, &opts).unwrap_or_else(|| {
+            std::process::exit(exitcode::CONFIG);
+        })
     });
+
+    // The bundle is enough configuration on its own.
+    let mut config_paths = if remote_config.is_some() && opts.config_paths.is_empty() {
+        Vec::new()
+    } else {
+        config_paths::expand(opts.config_paths.clone()).unwrap_or_else(|| {
+            std::process::exit(exitcode::CONFIG);
+        })
+    };
     config_paths.sort();
     config_paths.dedup();
+    if let Some(remote_config) = &remote_config {
+        config_paths.push(remote_config.bundle_path().to_owned());
+    }
     config_paths::CONFIG_PATHS
         .set(config_paths.clone())
         .expect("Cannot set global config paths");
 
-    if opts.watch_config {
-        // Start listening for config changes immediately.
-        vector::topology::config::watcher::config_watcher(
-            config_paths.clone(),
-            vector::topology::config::watcher::CONFIG_WATCH_DELAY,
-        )
-        .unwrap_or_else(|error| {
-            error!(message = "Unable to start config watcher.", %error);
-            std::process::exit(exitcode::CONFIG);
-        });
-    }
-
     info!(
         message = "Loading configs.",
         path = ?config_paths
@@ -257,6 +309,39 @@ fn main() {
     event::LOG_SCHEMA
         .set(config.global.log_schema.clone())
         .expect("Couldn't set schema");
+    topology::config::DEFAULTS_PROFILE
+        .set(config.global.defaults_profile)
+        .expect("Couldn't set defaults profile");
+
+    let hardening = config.global.hardening.clone();
+    let data_dir = config.global.data_dir.clone();
+    if hardening.enabled {
+        // Each thread holds its own capabilities, so they are dropped before
+        // any other thread is started.
+        topology::hardening::drop_capabilities(&hardening).unwrap_or_else(|error| {
+            error!(message = "Unable to drop capabilities.", %error);
+            std::process::exit(exitcode::OSERR);
+        });
+    }
+
+    if opts.watch_config {
+        // Start listening for config changes.
+        vector::topology::config::watcher::config_watcher(
+            config_paths.clone(),
+            vector::topology::config::watcher::CONFIG_WATCH_DELAY,
+        )
+        .unwrap_or_else(|error| {
+            error!(message = "Unable to start config watcher.", %error);
+            std::process::exit(exitcode::CONFIG);
+        });
+    }
+
+    if let Some(remote_config) = remote_config {
+        topology::config::remote::remote_config_poller(
+            remote_config,
+            Duration::from_secs(opts.remote_config_interval_secs.max(1)),
+        );
+    }
 
     let mut rt = {
         let threads = opts.threads.unwrap_or(max(1, num_cpus::get()));
@@ -276,7 +361,7 @@ fn main() {
     }
 
     let diff = topology::ConfigDiff::initial(&config);
-    let pieces = topology::validate(&config, &diff, rt.executor()).unwrap_or_else(|| {
+    let mut pieces = topology::validate(&config, &diff, rt.executor()).unwrap_or_else(|| {
         std::process::exit(exitcode::CONFIG);
     });
 
@@ -285,16 +370,82 @@ fn main() {
         std::process::exit(exitcode::OK);
     }
 
+    if config.global.accounting.enabled {
+        topology::accounting::init(&config.global).unwrap_or_else(|error| {
+            error!(message = "Unable to load accounting totals.", %error);
+            std::process::exit(exitcode::CONFIG);
+        });
+        rt.spawn(topology::accounting::persist_periodically(
+            &config.global.accounting,
+        ));
+    }
+    if config.global.lifecycle.enabled() {
+        topology::lifecycle::init(&config.global).unwrap_or_else(|error| {
+            error!(message = "Unable to prepare lifecycle tracing.", %error);
+            std::process::exit(exitcode::CONFIG);
+        });
+        rt.spawn(topology::lifecycle::persist_periodically(
+            &config.global.lifecycle,
+        ));
+    }
+    if let Some(address) = config.global.profile.address {
+        let server = topology::profile::serve(address).unwrap_or_else(|error| {
+            error!(message = "Unable to serve the profiled schemas.", %address, %error);
+            std::process::exit(exitcode::CONFIG);
+        });
+        rt.spawn(server);
+    }
+    rt.spawn(buffers::usage::report_periodically());
+    #[cfg(feature = "allocation-tracing")]
+    rt.spawn(vector::allocations::report_periodically());
+
+    // Resolves once the healthchecks did, writing the startup report.
+    let results = std::mem::take(&mut pieces.healthcheck_results);
+    let healthchecks: Box<dyn Future<Item = (), Error = ()> + Send> = if opts.startup_report {
+        let report = topology::startup_report::StartupReport::new(&config)
+            .with_healthchecks(results)
+            .map(|report| report.write());
+        Box::new(report)
+    } else {
+        let results = results
+            .into_iter()
+            .map(|(_, result)| result.then(|_| Ok::<_, ()>(())));
+        Box::new(future::join_all(results).map(|_| ()))
+    };
+
     let result = topology::start_validated(config, diff, pieces, &mut rt, opts.require_healthy);
-    let (topology, mut graceful_crash) = result.unwrap_or_else(|| {
-        std::process::exit(exitcode::CONFIG);
-    });
+    let (topology, mut graceful_crash) = match result {
+        Some(started) => started,
+        None => {
+            // The failed healthchecks are reported as well.
+            if opts.startup_report {
+                let _ = rt.block_on(healthchecks);
+            }
+            std::process::exit(exitcode::CONFIG);
+        }
+    };
+
+    // The process is hardened once the healthchecks, which may connect where
+    // the chroot no longer can, resolved as well.
+    if opts.dry_run || hardening.enabled {
+        let _ = rt.block_on(healthchecks);
+    } else {
+        rt.spawn(healthchecks);
+    }
 
     if opts.dry_run {
         info!("Healthchecks passed, exiting.");
         std::process::exit(exitcode::OK);
     }
 
+    if hardening.enabled {
+        topology::hardening::lock_down(&hardening, data_dir.as_deref()).unwrap_or_else(|error| {
+            error!(message = "Unable to harden the process.", %error);
+            std::process::exit(exitcode::OSERR);
+        });
+        info!(message = "Hardening applied.");
+    }
+
     #[cfg(unix)]
     {
         let mut topology = topology;
@@ -302,8 +453,11 @@ fn main() {
         let sigterm = Signal::new(SIGTERM).flatten_stream();
         let sigquit = Signal::new(SIGQUIT).flatten_stream();
         let sighup = Signal::new(SIGHUP).flatten_stream();
+        let sigusr1 = Signal::new(SIGUSR1).flatten_stream();
+        let sigusr2 = Signal::new(SIGUSR2).flatten_stream();
 
-        let mut signals = sigint.select(sigterm.select(sigquit.select(sighup)));
+        let mut signals =
+            sigint.select(sigterm.select(sigquit.select(sighup.select(sigusr1.select(sigusr2)))));
 
         let signal = loop {
             let signal = future::poll_fn(|| signals.poll());
@@ -323,6 +477,20 @@ fn main() {
                 future::Either::B((_to_shutdown, _)) => SIGINT,
             };
 
+            if signal == SIGUSR1 {
+                let batch_sinks = sinks::util::flush::flush_all();
+                info!(message = "Flushing batches.", %batch_sinks);
+                continue;
+            }
+
+            if signal == SIGUSR2 {
+                match topology.dump_state() {
+                    Ok(path) => info!(message = "Wrote state dump.", ?path),
+                    Err(error) => error!(message = "Unable to write state dump.", %error),
+                }
+                continue;
+            }
+
             if signal != SIGHUP {
                 break signal;
             }
@@ -354,6 +522,7 @@ fn main() {
             info!("Shutting down.");
             let shutdown = topology.stop();
 
+            let signals = signals.filter(|signal| *signal != SIGUSR1 && *signal != SIGUSR2);
             match rt.block_on(shutdown.select2(signals.into_future())) {
                 Ok(Either::A(_)) => { /* Graceful shutdown finished */ }
                 Ok(Either::B(_)) => {
@@ -401,9 +570,54 @@ fn main() {
         }
     }
 
+    if let Err(error) = topology::accounting::persist() {
+        error!(message = "Unable to persist accounting totals.", %error);
+    }
+    if let Err(error) = topology::lifecycle::persist() {
+        error!(message = "Unable to persist lifecycle traces.", %error);
+    }
+
     rt.shutdown_now().wait().unwrap();
 }
 
+/// Fetches the remote configuration bundle, falling back to the one kept
+/// from before when that fails.
+fn start_remote_config(url: &str, opts: &RootOpts) -> Option<RemoteConfig> {
+    let key_path = match &opts.remote_config_key {
+        Some(path) => path,
+        None => {
+            error!("The `--remote-config-key` argument is required with `--remote-config`.");
+            return None;
+        }
+    };
+    let default_path =
+        || topology::config::default_data_dir().map(|dir| dir.join("remote_config.toml"));
+    let bundle_path = match opts.remote_config_path.clone().or_else(default_path) {
+        Some(path) => path,
+        None => {
+            error!("No default data directory, set `--remote-config-path`.");
+            return None;
+        }
+    };
+
+    let mut remote_config = RemoteConfig::new(url.into(), key_path, bundle_path)
+        .map_err(|error| error!(message = "Unable to set up remote configuration.", %error))
+        .ok()?;
+    info!(message = "Fetching remote configuration.", %url);
+    if let Err(error) = remote_config.fetch() {
+        if remote_config.bundle_path().exists() {
+            warn!(
+                message = "Unable to fetch remote configuration, using the one fetched before.",
+                %error
+            );
+        } else {
+            error!(message = "Unable to fetch remote configuration.", %error);
+            return None;
+        }
+    }
+    Some(remote_config)
+}
+
 fn handle_config_errors(config: Result<Config, Vec<String>>) -> Option<Config> {
     match config {
         Err(errors) => {
@@ -529,6 +743,14 @@ fn validate(opts: &Validate) -> exitcode::ExitCode {
             }
         }
 
+        if opts.dry_run && !dry_run_sinks(&config) {
+            error!(
+                message = "Failed to dry run sinks.",
+                path = ?config_path
+            );
+            return exitcode::CONFIG;
+        }
+
         debug!(
             message = "Validation successful.",
             path = ?config_path
@@ -538,6 +760,57 @@ fn validate(opts: &Validate) -> exitcode::ExitCode {
     exitcode::OK
 }
 
+/// Logs the outcome of the dry run of each sink, returning whether they all
+/// passed.
+fn dry_run_sinks(config: &Config) -> bool {
+    use topology::dry_run::{check_sinks, RequestOutcome};
+
+    let mut rt = runtime::Runtime::new().expect("Unable to create async runtime");
+    let mut passed = true;
+    for (sink, report) in check_sinks(config, &mut rt) {
+        let report = match report {
+            Ok(report) => report,
+            Err(error) => {
+                error!(message = "Failed to build sink.", %sink, %error);
+                passed = false;
+                continue;
+            }
+        };
+        passed &= report.is_ok();
+
+        match &report.healthcheck {
+            None => info!(message = "Healthcheck disabled.", %sink),
+            Some(Ok(())) => info!(message = "Healthcheck passed.", %sink),
+            Some(Err(error)) => error!(message = "Healthcheck failed.", %sink, %error),
+        }
+        match report.request {
+            RequestOutcome::Built(requests) => {
+                for request in requests {
+                    info!(
+                        message = "Built request for the sample event, not sent.",
+                        %sink,
+                        method = %request.method,
+                        uri = %request.uri,
+                        byte_size = %request.byte_size,
+                    );
+                }
+            }
+            RequestOutcome::NotBuilt => error!(
+                message = "The sample event did not make it into a request; it may have failed to be encoded.",
+                %sink,
+            ),
+            RequestOutcome::Unsupported => info!(
+                message = "Sink can't build requests without sending them; skipped the sample event.",
+                %sink,
+            ),
+            RequestOutcome::Failed(error) => {
+                error!(message = "Failed to build a request for the sample event.", %sink, %error)
+            }
+        }
+    }
+    passed
+}
+
 #[allow(unused)]
 mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -0,0 +1,127 @@
+//! The connection options shared by the `nats` source and sink.
+
+use crate::tls::TlsOptions;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{io, path::PathBuf};
+
+#[derive(Debug, Snafu)]
+enum NatsError {
+    #[snafu(display("Invalid NKey seed: {}", source))]
+    InvalidSeed { source: nkeys::error::Error },
+    #[snafu(display("`tls.key_pass` isn't supported, the key file has to be unencrypted"))]
+    KeyPassUnsupported,
+    #[snafu(display("`tls.crt_file` and `tls.key_file` have to be set together"))]
+    IncompleteClientCertificate,
+    #[snafu(display("Unable to connect to {}: {}", url, source))]
+    Connect { url: String, source: io::Error },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", tag = "strategy")]
+pub enum NatsAuthConfig {
+    UserPassword {
+        user: String,
+        password: String,
+    },
+    Token {
+        token: String,
+    },
+    /// A `.creds` file holding the JWT of the user and its NKey seed.
+    CredentialsFile {
+        path: PathBuf,
+    },
+    /// Signs the nonce of the server with the seed, the public key is
+    /// derived from it.
+    Nkey {
+        seed: String,
+    },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct NatsTlsConfig {
+    /// Requires TLS even when the server doesn't.
+    pub enabled: Option<bool>,
+    #[serde(flatten)]
+    pub options: TlsOptions,
+}
+
+pub(crate) fn default_connection_name() -> String {
+    "vector".into()
+}
+
+/// Connects, reconnecting on its own once connected.
+pub(crate) fn connect(
+    url: &str,
+    connection_name: &str,
+    auth: &Option<NatsAuthConfig>,
+    tls: &Option<NatsTlsConfig>,
+) -> crate::Result<nats::Connection> {
+    let mut options = match auth {
+        None => nats::Options::new(),
+        Some(NatsAuthConfig::UserPassword { user, password }) => {
+            nats::Options::with_user_pass(user, password)
+        }
+        Some(NatsAuthConfig::Token { token }) => nats::Options::with_token(token),
+        Some(NatsAuthConfig::CredentialsFile { path }) => nats::Options::with_credentials(path),
+        Some(NatsAuthConfig::Nkey { seed }) => {
+            let key_pair = nkeys::KeyPair::from_seed(seed).context(InvalidSeed)?;
+            nats::Options::with_nkey(&key_pair.public_key(), move |nonce| {
+                key_pair.sign(nonce).expect("a key pair from a seed signs")
+            })
+        }
+    };
+    options = options.with_name(connection_name).max_reconnects(None);
+
+    if let Some(tls) = tls {
+        options = options.tls_required(tls.enabled.unwrap_or(false));
+        if tls.options.key_pass.is_some() {
+            return Err(Box::new(NatsError::KeyPassUnsupported));
+        }
+        if let Some(path) = &tls.options.ca_file {
+            options = options.add_root_certificate(path);
+        }
+        match (&tls.options.crt_file, &tls.options.key_file) {
+            (Some(crt_file), Some(key_file)) => options = options.client_cert(crt_file, key_file),
+            (None, None) => (),
+            _ => return Err(Box::new(NatsError::IncompleteClientCertificate)),
+        }
+    }
+
+    options
+        .connect(url)
+        .context(Connect { url })
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nats_parses_auth() {
+        let auth: NatsAuthConfig = toml::from_str(
+            r#"
+            strategy = "credentials_file"
+            path = "/etc/nats/user.creds"
+            "#,
+        )
+        .unwrap();
+        match auth {
+            NatsAuthConfig::CredentialsFile { path } => {
+                assert_eq!(path, PathBuf::from("/etc/nats/user.creds"))
+            }
+            auth => panic!("unexpected auth: {:?}", auth),
+        }
+
+        let invalid = connect(
+            "nats://localhost:4222",
+            "vector",
+            &Some(NatsAuthConfig::Nkey {
+                seed: "not a seed".into(),
+            }),
+            &None,
+        );
+        assert!(invalid.is_err());
+    }
+}
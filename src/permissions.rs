@@ -0,0 +1,244 @@
+//! The mode and ownership given to the sockets, files and directories
+//! Vector creates.
+
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{io, path::Path};
+
+#[derive(Debug, Snafu)]
+pub enum PermissionsError {
+    #[snafu(display("Invalid mode {:?}, expected octal digits such as \"0640\"", mode))]
+    InvalidMode { mode: String },
+    #[snafu(display("Unknown user {:?}", user))]
+    UnknownUser { user: String },
+    #[snafu(display("Unknown group {:?}", group))]
+    UnknownGroup { group: String },
+    #[snafu(display("Permissions can only be set on Unix"))]
+    Unsupported,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionsConfig {
+    /// In octal, such as `0640`.
+    pub mode: Option<String>,
+    /// A user name or id.
+    pub user: Option<String>,
+    /// A group name or id.
+    pub group: Option<String>,
+}
+
+/// Permissions with the users and groups resolved to their ids, as they
+/// are looked up once, when components are built.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Permissions {
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl PermissionsConfig {
+    pub fn build(&self) -> Result<Permissions, PermissionsError> {
+        if *self == Self::default() {
+            return Ok(Permissions::default());
+        }
+        if !cfg!(unix) {
+            return Err(PermissionsError::Unsupported);
+        }
+
+        let mode = match &self.mode {
+            Some(mode) => match u32::from_str_radix(mode, 8) {
+                Ok(parsed) if parsed <= 0o7777 => Some(parsed),
+                _ => return Err(PermissionsError::InvalidMode { mode: mode.clone() }),
+            },
+            None => None,
+        };
+        let uid = match &self.user {
+            Some(user) => Some(
+                user.parse()
+                    .ok()
+                    .or_else(|| unix::user_id(user))
+                    .ok_or_else(|| PermissionsError::UnknownUser { user: user.clone() })?,
+            ),
+            None => None,
+        };
+        let gid = match &self.group {
+            Some(group) => Some(
+                group
+                    .parse()
+                    .ok()
+                    .or_else(|| unix::group_id(group))
+                    .ok_or_else(|| PermissionsError::UnknownGroup {
+                        group: group.clone(),
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(Permissions { mode, uid, gid })
+    }
+}
+
+impl Permissions {
+    /// Changing the owner takes privileges, unless only the group changes
+    /// to one the user of Vector is a member of.
+    pub fn apply(&self, path: &Path) -> io::Result<()> {
+        if let Some(mode) = self.mode {
+            unix::set_mode(path, mode)?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            unix::chown(path, self.uid, self.gid)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a directory and its missing parents, applying the
+    /// permissions to each directory created.
+    pub fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        match std::fs::create_dir(path) {
+            Ok(()) => self.apply(path),
+            // Created concurrently.
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use nix::{
+        libc,
+        unistd::{self, Gid, Uid},
+    };
+    use std::{
+        ffi::CString, fs, io, mem::MaybeUninit, os::unix::fs::PermissionsExt, path::Path, ptr,
+    };
+
+    /// Large enough for the entries of most user and group databases.
+    const BUFFER_SIZE: usize = 16 * 1024;
+
+    pub fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    pub fn chown(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        unistd::chown(path, uid.map(Uid::from_raw), gid.map(Gid::from_raw))
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))
+    }
+
+    pub fn user_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = ptr::null_mut();
+        let code = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                passwd.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if code == 0 && !result.is_null() {
+            Some(unsafe { (*result).pw_uid })
+        } else {
+            None
+        }
+    }
+
+    pub fn group_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        let mut group = MaybeUninit::<libc::group>::uninit();
+        let mut buffer = vec![0 as libc::c_char; BUFFER_SIZE];
+        let mut result = ptr::null_mut();
+        let code = unsafe {
+            libc::getgrnam_r(
+                name.as_ptr(),
+                group.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+        if code == 0 && !result.is_null() {
+            Some(unsafe { (*result).gr_gid })
+        } else {
+            None
+        }
+    }
+}
+
+/// Building fails before these are reached when permissions are set.
+#[cfg(not(unix))]
+mod unix {
+    use std::{io, path::Path};
+
+    pub fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn chown(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn user_id(_name: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn group_id(_name: &str) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn permissions_are_resolved_and_applied() {
+        let config: PermissionsConfig = toml::from_str(
+            r#"
+            mode = "0750"
+            user = "root"
+            group = "0"
+            "#,
+        )
+        .unwrap();
+        let permissions = config.build().unwrap();
+        assert_eq!(
+            permissions,
+            Permissions {
+                mode: Some(0o750),
+                uid: Some(0),
+                gid: Some(0),
+            }
+        );
+
+        let invalid = PermissionsConfig {
+            mode: Some("0999".into()),
+            ..Default::default()
+        };
+        assert!(invalid.build().is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        let permissions = PermissionsConfig {
+            mode: Some("0700".into()),
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        permissions.create_dir_all(&nested).unwrap();
+        for path in &[dir.path().join("a"), nested] {
+            let mode = std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o7777, 0o700);
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Replays a historical range of a `kafka` or `aws_s3` source through the
+//! transforms and sinks downstream of it, for example to backfill a sink
+//! after data was lost downstream of it.
+
+use crate::{
+    config_paths,
+    event::{self, Event},
+    runtime,
+    shutdown::ShutdownSignal,
+    topology::{
+        self,
+        config::{Config, DataType, GlobalOptions, SourceConfig, DEFAULTS_PROFILE},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    future::{FutureExt, TryFutureExt},
+    StreamExt,
+};
+use futures01::{sync::mpsc, Future, Sink, Stream};
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use structopt::StructOpt;
+use tokio::time::{delay_until, Instant};
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Opts {
+    /// The `kafka` or `aws_s3` source of the config to replay.
+    #[structopt(long)]
+    source: String,
+
+    /// Only replay into these sinks, rather than into all the sinks downstream
+    /// of the source.
+    #[structopt(long, use_delimiter = true)]
+    sinks: Vec<String>,
+
+    /// Replay the messages or objects written since this RFC 3339 time.
+    #[structopt(long)]
+    since: Option<DateTime<Utc>>,
+
+    /// Replay the messages or objects written until this RFC 3339 time.
+    #[structopt(long)]
+    until: Option<DateTime<Utc>>,
+
+    /// The offset each Kafka partition is replayed from.
+    #[structopt(long)]
+    start_offset: Option<i64>,
+
+    /// The offset each Kafka partition is replayed up to, excluded.
+    #[structopt(long)]
+    end_offset: Option<i64>,
+
+    /// The bucket whose objects an `aws_s3` source replays.
+    #[structopt(long)]
+    bucket: Option<String>,
+
+    /// Only replay the objects whose keys start with this prefix.
+    #[structopt(long)]
+    prefix: Option<String>,
+
+    /// The number of events replayed per second, at most.
+    #[structopt(long)]
+    rate_limit: Option<u64>,
+
+    /// Read configuration from one or more files. Wildcard paths are supported.
+    /// If zero files are specified the default config path
+    /// `/etc/vector/vector.toml` will be targeted.
+    #[structopt(name = "config", short, long)]
+    config_paths: Vec<PathBuf>,
+}
+
+/// The part of the history of a source to replay.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Range {
+    #[serde(flatten)]
+    pub window: TimeWindow,
+    pub start_offset: Option<i64>,
+    pub end_offset: Option<i64>,
+    pub prefix: Option<String>,
+}
+
+/// The `since` and `until` times, both inclusive, the replayable sources
+/// constrain what they read to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TimeWindow {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    pub fn new(since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> Self {
+        Self { since, until }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Times that aren't known are taken to be within the window.
+    pub fn contains(&self, time: Option<DateTime<Utc>>) -> bool {
+        match time {
+            Some(time) => {
+                self.since.map_or(true, |since| time >= since)
+                    && self.until.map_or(true, |until| time <= until)
+            }
+            None => true,
+        }
+    }
+}
+
+pub fn cmd(opts: &Opts) -> exitcode::ExitCode {
+    let paths = match config_paths::expand(opts.config_paths.clone()) {
+        Some(paths) => paths,
+        None => return exitcode::CONFIG,
+    };
+    let config = match read_configs(&paths) {
+        Ok(config) => config,
+        Err(errors) => {
+            for error in errors {
+                error!("Configuration error: {}", error);
+            }
+            return exitcode::CONFIG;
+        }
+    };
+    event::LOG_SCHEMA
+        .set(config.global.log_schema.clone())
+        .expect("Couldn't set schema");
+    DEFAULTS_PROFILE
+        .set(config.global.defaults_profile)
+        .expect("Couldn't set defaults profile");
+
+    let failed = Arc::new(AtomicBool::new(false));
+    let config = match replay_config(config, opts, Arc::clone(&failed)) {
+        Ok(config) => config,
+        Err(error) => {
+            error!(message = "Unable to set up the replay.", %error);
+            return exitcode::CONFIG;
+        }
+    };
+
+    let mut rt = runtime::Runtime::new().expect("Unable to create async runtime");
+    let (topology, graceful_crash) = match topology::start(config, &mut rt, false) {
+        Some(started) => started,
+        None => return exitcode::CONFIG,
+    };
+    info!(message = "Replaying.", source = %opts.source);
+
+    let finished = topology.sources_finished().map(|_| false);
+    let crashed = graceful_crash.into_future().map(|_| true).map_err(|_| ());
+    let crashed = rt
+        .block_on(finished.select(crashed))
+        .map(|(crashed, _)| crashed)
+        .unwrap_or(true);
+
+    // Stopping flushes what the sinks still hold.
+    let _ = rt.block_on(topology.stop());
+    rt.shutdown_now().wait().unwrap();
+
+    if crashed || failed.load(Ordering::SeqCst) {
+        error!("Replay failed.");
+        exitcode::SOFTWARE
+    } else {
+        info!("Replay finished.");
+        exitcode::OK
+    }
+}
+
+fn read_configs(paths: &[PathBuf]) -> Result<Config, Vec<String>> {
+    let mut config = Config::empty();
+    for path in paths {
+        let file = File::open(path)
+            .map_err(|error| vec![format!("Could not open {:?}: {}", path, error)])?;
+        Config::load(file).and_then(|loaded| config.append(loaded))?;
+    }
+    config.expand_macros()?;
+    Ok(config)
+}
+
+/// Swaps the source for one replaying its history, and keeps only the
+/// components downstream of it.
+fn replay_config(
+    mut config: Config,
+    opts: &Opts,
+    failed: Arc<AtomicBool>,
+) -> Result<Config, String> {
+    if opts.rate_limit == Some(0) {
+        return Err("`--rate-limit` must be at least 1".into());
+    }
+    let source = config
+        .sources
+        .remove(&opts.source)
+        .ok_or_else(|| format!("Source {:?} isn't in the config", opts.source))?;
+    let replayed = Replayed::new(&opts.source, &*source, opts)?;
+
+    let mut downstream = HashSet::new();
+    downstream.insert(opts.source.clone());
+    loop {
+        let count = downstream.len();
+        for (name, transform) in &config.transforms {
+            if transform
+                .inputs
+                .iter()
+                .any(|input| downstream.contains(input))
+            {
+                downstream.insert(name.clone());
+            }
+        }
+        if downstream.len() == count {
+            break;
+        }
+    }
+
+    for name in &opts.sinks {
+        let sink = config
+            .sinks
+            .get(name)
+            .ok_or_else(|| format!("Sink {:?} isn't in the config", name))?;
+        if !sink.inputs.iter().any(|input| downstream.contains(input)) {
+            return Err(format!(
+                "Sink {:?} isn't downstream of source {:?}",
+                name, opts.source
+            ));
+        }
+    }
+
+    config.transforms = config
+        .transforms
+        .into_iter()
+        .filter(|(name, _)| downstream.contains(name))
+        .collect();
+    for transform in config.transforms.values_mut() {
+        transform.inputs.retain(|input| downstream.contains(input));
+    }
+    config.sinks = config
+        .sinks
+        .into_iter()
+        .filter(|(name, sink)| {
+            (opts.sinks.is_empty() || opts.sinks.contains(name))
+                && sink.inputs.iter().any(|input| downstream.contains(input))
+        })
+        .collect();
+    if config.sinks.is_empty() {
+        return Err(format!("No sink is downstream of source {:?}", opts.source));
+    }
+    for sink in config.sinks.values_mut() {
+        sink.inputs.retain(|input| downstream.contains(input));
+        // Blocks rather than drops, and keeps clear of the disk buffers of
+        // the running instance.
+        sink.buffer = Default::default();
+    }
+
+    let source = ReplaySourceConfig {
+        replayed,
+        range: Range {
+            window: TimeWindow::new(opts.since, opts.until),
+            start_offset: opts.start_offset,
+            end_offset: opts.end_offset,
+            prefix: opts.prefix.clone(),
+        },
+        rate_limit: opts.rate_limit,
+        failed,
+    };
+    config.sources = IndexMap::new();
+    config.sources.insert(opts.source.clone(), Box::new(source));
+    config.tests.clear();
+    Ok(config)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Replayed {
+    #[cfg(all(feature = "sources-kafka", feature = "rdkafka"))]
+    Kafka {
+        config: crate::sources::kafka::KafkaSourceConfig,
+    },
+    #[cfg(feature = "sources-aws_s3")]
+    AwsS3 {
+        config: crate::sources::aws_s3::AwsS3Config,
+        bucket: String,
+    },
+}
+
+impl Replayed {
+    /// Reads the config of the source back through its serialized form, as
+    /// that's all sources have in common.
+    fn new(name: &str, source: &dyn SourceConfig, opts: &Opts) -> Result<Self, String> {
+        let mut value = serde_json::to_value(source).map_err(|error| error.to_string())?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("type");
+        }
+        match source.source_type() {
+            #[cfg(all(feature = "sources-kafka", feature = "rdkafka"))]
+            "kafka" => Ok(Replayed::Kafka {
+                config: serde_json::from_value(value).map_err(|error| error.to_string())?,
+            }),
+            #[cfg(feature = "sources-aws_s3")]
+            "aws_s3" => Ok(Replayed::AwsS3 {
+                config: serde_json::from_value(value).map_err(|error| error.to_string())?,
+                bucket: opts
+                    .bucket
+                    .clone()
+                    .ok_or("`--bucket` is required to replay an `aws_s3` source")?,
+            }),
+            other => Err(format!(
+                "Only `kafka` and `aws_s3` sources can be replayed, {:?} is a `{}` source",
+                name, other
+            )),
+        }
+    }
+
+    async fn replay(self, range: Range, out: mpsc::Sender<Event>) -> crate::Result<()> {
+        match self {
+            #[cfg(all(feature = "sources-kafka", feature = "rdkafka"))]
+            Replayed::Kafka { config } => crate::sources::kafka::replay(config, range, out).await,
+            #[cfg(feature = "sources-aws_s3")]
+            Replayed::AwsS3 { config, bucket } => {
+                crate::sources::aws_s3::replay(config, bucket, range, out).await
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ReplaySourceConfig {
+    replayed: Replayed,
+    range: Range,
+    rate_limit: Option<u64>,
+    #[serde(skip)]
+    failed: Arc<AtomicBool>,
+}
+
+#[typetag::serialize(name = "replay")]
+impl SourceConfig for ReplaySourceConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<crate::sources::Source> {
+        let replayed = self.replayed.clone();
+        let range = self.range.clone();
+        let rate_limit = self.rate_limit;
+        let failed = Arc::clone(&self.failed);
+
+        let replay = async move {
+            let (tx, forward) = match rate_limit {
+                Some(rate_limit) => {
+                    let (tx, rx) = mpsc::channel(100);
+                    (tx, Some(throttle(rx, out, rate_limit)))
+                }
+                None => (out, None),
+            };
+            let result = match forward {
+                Some(forward) => {
+                    futures::future::join(replayed.replay(range, tx), forward)
+                        .await
+                        .0
+                }
+                None => replayed.replay(range, tx).await,
+            };
+            if let Err(error) = result {
+                error!(message = "Replay failed.", %error);
+                failed.store(true, Ordering::SeqCst);
+            }
+            Ok(())
+        };
+
+        Ok(Box::new(
+            replay
+                .boxed()
+                .compat()
+                .select(shutdown.map(|_| ()))
+                .map(|_| ())
+                .map_err(|_| ()),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "replay"
+    }
+
+    fn typetag_deserialize(&self) {
+        unimplemented!("not intended for use in real configs")
+    }
+}
+
+/// Forwards at most `rate_limit` events each second.
+async fn throttle(rx: mpsc::Receiver<Event>, mut out: mpsc::Sender<Event>, rate_limit: u64) {
+    let mut rx = rx.compat();
+    let mut window = Instant::now();
+    let mut sent = 0;
+    while let Some(Ok(event)) = rx.next().await {
+        if window.elapsed() >= Duration::from_secs(1) {
+            window = Instant::now();
+            sent = 0;
+        }
+        if sent == rate_limit {
+            delay_until(window + Duration::from_secs(1)).await;
+            window = Instant::now();
+            sent = 0;
+        }
+        sent += 1;
+        out = match out.send(event).compat().await {
+            Ok(out) => out,
+            Err(error) => return error!(message = "Error sending replayed event.", %error),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_window_contains_times() {
+        let time = |s: &str| Some(s.parse::<DateTime<Utc>>().unwrap());
+        let window = TimeWindow::new(time("2020-06-01T00:00:00Z"), time("2020-06-02T00:00:00Z"));
+        assert!(window.contains(time("2020-06-01T12:00:00Z")));
+        assert!(window.contains(time("2020-06-02T00:00:00Z")));
+        assert!(!window.contains(time("2020-05-31T23:59:59Z")));
+        assert!(!window.contains(time("2020-06-02T00:00:01Z")));
+        assert!(window.contains(None));
+        assert!(TimeWindow::default().contains(time("2020-06-01T12:00:00Z")));
+    }
+}
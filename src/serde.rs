@@ -1,3 +1,4 @@
+use crate::event::escape_key;
 use indexmap::map::IndexMap;
 use serde::{Deserialize, Serialize};
 use string_cache::DefaultAtom as Atom;
@@ -32,6 +33,9 @@ pub enum FieldsOrValue<V> {
 pub struct Fields<V>(IndexMap<String, FieldsOrValue<V>>);
 
 impl<V: 'static> Fields<V> {
+    /// Flattens the nested tables into field paths. The keys at the top are
+    /// paths already, while the keys of nested tables are taken literally, so
+    /// `labels."app.kubernetes.io/name"` names a single nested field.
     pub fn all_fields(self) -> impl Iterator<Item = (Atom, V)> {
         self.0
             .into_iter()
@@ -40,12 +44,59 @@ impl<V: 'static> Fields<V> {
                     // boxing is used as a way to avoid incompatible types of the match arms
                     FieldsOrValue::Value(v) => Box::new(std::iter::once((k.into(), v))),
                     FieldsOrValue::Fields(f) => Box::new(
-                        f.all_fields()
-                            .into_iter()
+                        f.nested_fields()
                             .map(move |(nested_k, v)| (format!("{}.{}", k, nested_k).into(), v)),
                     ),
                 }
             })
             .flatten()
     }
+
+    fn nested_fields(self) -> Box<dyn Iterator<Item = (String, V)>> {
+        Box::new(
+            self.0
+                .into_iter()
+                .map(|(k, v)| -> Box<dyn Iterator<Item = (String, V)>> {
+                    let k = escape_key(&k);
+                    match v {
+                        FieldsOrValue::Value(v) => Box::new(std::iter::once((k, v))),
+                        FieldsOrValue::Fields(f) => Box::new(
+                            f.nested_fields()
+                                .map(move |(nested_k, v)| (format!("{}.{}", k, nested_k), v)),
+                        ),
+                    }
+                })
+                .flatten(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_keys_are_escaped() {
+        let fields: Fields<i64> = toml::from_str(
+            r#"
+            "a.b" = 1
+            c.d = 2
+            labels."app.kubernetes.io/name" = 3
+            "#,
+        )
+        .unwrap();
+
+        let fields = fields
+            .all_fields()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                ("a.b".into(), 1),
+                ("c.d".into(), 2),
+                ("labels.app\\.kubernetes\\.io/name".into(), 3),
+            ]
+        );
+    }
 }
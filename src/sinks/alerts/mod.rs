@@ -0,0 +1,480 @@
+use crate::{
+    event::{self, Event, Value},
+    internal_events::{
+        AlertsEventRateLimited, AlertsEventSent, AlertsEventSuppressed, AlertsSendFailed,
+    },
+    sinks::util::{
+        http2::{HttpClient, HttpRetryLogic},
+        rate_limit::RateLimiter,
+        retries2::{RetryAction, RetryLogic},
+        StreamSink, UriSerde,
+    },
+    template::Template,
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use async_trait::async_trait;
+use bytes05::Bytes;
+use chrono::Utc;
+use futures::{pin_mut, stream::Stream, StreamExt};
+use futures01::future;
+use http02::{Request, Response, Uri};
+use hyper13::Body;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use string_cache::DefaultAtom as Atom;
+use tokio::time::{delay_for, timeout};
+use tower03::Service;
+
+use super::streaming_sink::{self, StreamingSink};
+
+mod payload;
+
+use payload::{Alert, Severity};
+
+const PAGERDUTY_ENDPOINT: &str = "https://events.pagerduty.com/v2/enqueue";
+
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Expired groups are only cleaned up once this many are tracked.
+const MAX_GROUPS: usize = 10_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AlertsSinkConfig {
+    pub provider: Provider,
+    pub endpoint: Option<UriSerde>,
+    pub routing_key: Option<String>,
+    pub title: Template,
+    pub text: Option<Template>,
+    #[serde(default = "default_severity")]
+    pub severity: Template,
+    pub source: Option<Template>,
+    #[serde(default)]
+    pub group_by: Vec<Atom>,
+    #[serde(default = "default_group_window_secs")]
+    pub group_window_secs: u64,
+    #[serde(default = "default_rate_limit_num")]
+    pub rate_limit_num: u64,
+    #[serde(default = "default_rate_limit_duration_secs")]
+    pub rate_limit_duration_secs: u64,
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    Slack,
+    Pagerduty,
+    Teams,
+}
+
+fn default_severity() -> Template {
+    "critical".into()
+}
+
+fn default_group_window_secs() -> u64 {
+    300
+}
+
+fn default_rate_limit_num() -> u64 {
+    20
+}
+
+fn default_rate_limit_duration_secs() -> u64 {
+    60
+}
+
+fn default_retry_attempts() -> usize {
+    5
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<AlertsSinkConfig>("alerts")
+}
+
+#[typetag::serde(name = "alerts")]
+impl SinkConfig for AlertsSinkConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let endpoint = match (self.provider, &self.endpoint) {
+            (_, Some(endpoint)) => Uri::from(endpoint.clone()),
+            (Provider::Pagerduty, None) => PAGERDUTY_ENDPOINT.parse::<Uri>()?,
+            (_, None) => return Err("`endpoint` must be set to the webhook URL".into()),
+        };
+        let routing_key = match (self.provider, &self.routing_key) {
+            (Provider::Pagerduty, Some(key)) => key.clone(),
+            (Provider::Pagerduty, None) => {
+                return Err("`routing_key` is required for PagerDuty".into())
+            }
+            (_, Some(_)) => return Err("`routing_key` is only used by PagerDuty".into()),
+            (_, None) => String::new(),
+        };
+
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let client = HttpClient::new(cx.resolver(), tls)?;
+
+        let sink = AlertsSink {
+            config: self.clone(),
+            endpoint,
+            routing_key,
+            client,
+            groups: Grouper::new(Duration::from_secs(self.group_window_secs)),
+            limiter: RateLimiter::new(
+                self.rate_limit_num,
+                Duration::from_secs(self.rate_limit_duration_secs),
+            ),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        // Webhooks have no request that could be sent without notifying
+        // someone, so there is nothing to check.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "alerts"
+    }
+}
+
+struct AlertsSink {
+    config: AlertsSinkConfig,
+    endpoint: Uri,
+    routing_key: String,
+    client: HttpClient,
+    groups: Grouper,
+    limiter: RateLimiter,
+}
+
+#[async_trait]
+impl StreamingSink for AlertsSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        while let Some(event) = input.next().await {
+            let mut alert = match build_alert(&self.config, event) {
+                Some(alert) => alert,
+                None => continue,
+            };
+
+            let now = Instant::now();
+            alert.suppressed = match self.groups.check(&alert.group_key, now) {
+                Some(suppressed) => suppressed,
+                None => {
+                    emit!(AlertsEventSuppressed {
+                        group_key: &alert.group_key
+                    });
+                    continue;
+                }
+            };
+            // Alerts that aren't delivered leave the window of their group
+            // closed, for the next alert of it to go out.
+            if !self.limiter.allow(now) {
+                emit!(AlertsEventRateLimited);
+                continue;
+            }
+
+            let body = match self.config.provider {
+                Provider::Slack => payload::slack(&alert),
+                Provider::Pagerduty => payload::pagerduty(&alert, &self.routing_key),
+                Provider::Teams => payload::teams(&alert),
+            };
+            let body = Bytes::from(serde_json::to_vec(&body)?);
+            if self.send(body).await {
+                self.groups.open(&alert.group_key, now);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AlertsSink {
+    /// Returns whether the alert was delivered, retries included.
+    async fn send(&mut self, body: Bytes) -> bool {
+        let request_timeout = Duration::from_secs(self.config.timeout_secs);
+        let mut attempt = 0;
+        loop {
+            let request = Request::post(self.endpoint.clone())
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.clone()))
+                .expect("Building a request from a valid URI can't fail");
+
+            let action = match timeout(request_timeout, send(&mut self.client, request)).await {
+                Ok(Ok(response)) => HttpRetryLogic.should_retry_response(&response),
+                Ok(Err(error)) if HttpRetryLogic.is_retriable_error(&error) => {
+                    RetryAction::Retry(error.to_string())
+                }
+                Ok(Err(error)) => RetryAction::DontRetry(error.to_string()),
+                Err(_) => RetryAction::Retry("request timed out".into()),
+            };
+
+            match action {
+                RetryAction::Successful => {
+                    emit!(AlertsEventSent {
+                        byte_size: body.len()
+                    });
+                    return true;
+                }
+                RetryAction::Retry(reason) if attempt < self.config.retry_attempts => {
+                    emit!(AlertsSendFailed {
+                        reason,
+                        will_retry: true
+                    });
+                    attempt += 1;
+                    delay_for(RETRY_BACKOFF * attempt as u32).await;
+                }
+                RetryAction::Retry(reason) | RetryAction::DontRetry(reason) => {
+                    emit!(AlertsSendFailed {
+                        reason,
+                        will_retry: false
+                    });
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+async fn send(
+    client: &mut HttpClient,
+    request: Request<Body>,
+) -> Result<Response<Bytes>, hyper13::Error> {
+    let response = client.call(request).await?;
+    let (parts, body) = response.into_parts();
+    let body = hyper13::body::to_bytes(body).await?;
+    Ok(Response::from_parts(parts, body))
+}
+
+fn build_alert(config: &AlertsSinkConfig, event: Event) -> Option<Alert> {
+    let title = render(&config.title, &event)?;
+    let text = match &config.text {
+        Some(text) => Some(render(text, &event)?),
+        None => None,
+    };
+    let severity = render(&config.severity, &event)?;
+    let severity = Severity::parse(&severity).unwrap_or_else(|| {
+        warn!(
+            message = "unknown severity, sending as critical.",
+            %severity,
+            rate_limit_secs = 30,
+        );
+        Severity::Critical
+    });
+    let source = match &config.source {
+        Some(source) => render(source, &event)?,
+        None => hostname::get_hostname().unwrap_or_else(|| "vector".into()),
+    };
+
+    let log = event.into_log();
+    let group_key = if config.group_by.is_empty() {
+        title.clone()
+    } else {
+        config
+            .group_by
+            .iter()
+            .map(|field| {
+                log.get(field)
+                    .map(|value| value.to_string_lossy())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    let timestamp = match log.get(&event::log_schema().timestamp_key()) {
+        Some(Value::Timestamp(timestamp)) => *timestamp,
+        _ => Utc::now(),
+    };
+    let fields = match serde_json::to_value(&log) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => Default::default(),
+    };
+
+    Some(Alert {
+        title,
+        text,
+        severity,
+        source,
+        group_key,
+        suppressed: 0,
+        timestamp,
+        fields,
+    })
+}
+
+fn render(template: &Template, event: &Event) -> Option<String> {
+    template
+        .render_string(event)
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event. Dropping event.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()
+}
+
+/// Collapses alerts of the same group: the first one sent opens a window,
+/// the rest of the window is suppressed and counted. The count is reported
+/// with the alert that opens the next window.
+struct Grouper {
+    window: Duration,
+    groups: HashMap<String, Group>,
+}
+
+struct Group {
+    opened: Instant,
+    suppressed: usize,
+}
+
+impl Grouper {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of alerts suppressed since the group was last
+    /// sent, or `None` if its window is open and this alert is to be
+    /// suppressed as well.
+    fn check(&mut self, key: &str, now: Instant) -> Option<usize> {
+        match self.groups.get_mut(key) {
+            Some(group) if now.duration_since(group.opened) < self.window => {
+                group.suppressed += 1;
+                None
+            }
+            Some(group) => Some(group.suppressed),
+            None => Some(0),
+        }
+    }
+
+    /// Opens a window for the group, once its alert was sent.
+    fn open(&mut self, key: &str, now: Instant) {
+        let window = self.window;
+        if let Some(group) = self.groups.get_mut(key) {
+            group.opened = now;
+            group.suppressed = 0;
+            return;
+        }
+
+        if self.groups.len() >= MAX_GROUPS {
+            self.groups
+                .retain(|_, group| now.duration_since(group.opened) < window);
+        }
+        self.groups.insert(
+            key.into(),
+            Group {
+                opened: now,
+                suppressed: 0,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    fn config(extra: &str) -> AlertsSinkConfig {
+        let (config, _, _) = load_sink::<AlertsSinkConfig>(&format!(
+            r#"
+            provider = "pagerduty"
+            routing_key = "R0UT1NGK3Y"
+            title = "{{{{ host }}}}: {{{{ message }}}}"
+            {}
+        "#,
+            extra
+        ))
+        .unwrap();
+        config
+    }
+
+    fn event() -> Event {
+        let mut event = Event::from("disk full");
+        event.as_mut_log().insert("host", "db-1");
+        event.as_mut_log().insert("level", "warn");
+        event
+    }
+
+    #[test]
+    fn alerts_builds_alert() {
+        let config = config(r#"severity = "{{ level }}""#);
+        let alert = build_alert(&config, event()).unwrap();
+
+        assert_eq!(alert.title, "db-1: disk full");
+        assert_eq!(alert.severity, Severity::Warning);
+        assert_eq!(alert.group_key, "db-1: disk full");
+        assert_eq!(alert.fields["host"], "db-1");
+    }
+
+    #[test]
+    fn alerts_groups_by_fields() {
+        let config = config(r#"group_by = ["host", "service"]"#);
+        let alert = build_alert(&config, event()).unwrap();
+
+        assert_eq!(alert.group_key, "db-1/");
+        assert_eq!(alert.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn alerts_drops_events_missing_title_fields() {
+        let config = config("");
+        assert!(build_alert(&config, Event::from("disk full")).is_none());
+    }
+
+    #[test]
+    fn alerts_suppresses_within_group_window() {
+        let mut groups = Grouper::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert_eq!(groups.check("a", start), Some(0));
+        groups.open("a", start);
+        assert_eq!(groups.check("a", start + Duration::from_secs(1)), None);
+        assert_eq!(groups.check("b", start + Duration::from_secs(1)), Some(0));
+        assert_eq!(groups.check("a", start + Duration::from_secs(2)), None);
+        assert_eq!(groups.check("a", start + Duration::from_secs(61)), Some(2));
+        groups.open("a", start + Duration::from_secs(61));
+        assert_eq!(groups.check("a", start + Duration::from_secs(62)), None);
+    }
+
+    #[test]
+    fn alerts_keeps_window_closed_until_sent() {
+        let mut groups = Grouper::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        // Rate limited, or failed to send.
+        assert_eq!(groups.check("a", start), Some(0));
+        assert_eq!(groups.check("a", start + Duration::from_secs(1)), Some(0));
+    }
+
+    #[test]
+    fn alerts_requires_provider_settings() {
+        let (config, cx, _) = load_sink::<AlertsSinkConfig>(
+            r#"
+            provider = "slack"
+            title = "{{ message }}"
+        "#,
+        )
+        .unwrap();
+        assert!(config.build(cx).is_err());
+    }
+}
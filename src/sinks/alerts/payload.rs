@@ -0,0 +1,208 @@
+//! Request bodies of the supported alerting services.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// PagerDuty rejects summaries and deduplication keys longer than this.
+const PAGERDUTY_MAX_SUMMARY_LEN: usize = 1024;
+const PAGERDUTY_MAX_DEDUP_KEY_LEN: usize = 255;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Critical,
+    Error,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "critical" | "crit" | "fatal" | "emergency" | "emerg" | "alert" => {
+                Some(Severity::Critical)
+            }
+            "error" | "err" => Some(Severity::Error),
+            "warning" | "warn" => Some(Severity::Warning),
+            "info" | "notice" | "debug" => Some(Severity::Info),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Critical => "D00000",
+            Severity::Error => "E8590C",
+            Severity::Warning => "F2C744",
+            Severity::Info => "439FE0",
+        }
+    }
+}
+
+/// An alert rendered from an event, before it is shaped for a service.
+#[derive(Debug)]
+pub struct Alert {
+    pub title: String,
+    pub text: Option<String>,
+    pub severity: Severity,
+    pub source: String,
+    pub group_key: String,
+    /// Alerts of the same group dropped since the last one was sent.
+    pub suppressed: usize,
+    pub timestamp: DateTime<Utc>,
+    pub fields: Map<String, Value>,
+}
+
+impl Alert {
+    fn suppressed_note(&self) -> Option<String> {
+        match self.suppressed {
+            0 => None,
+            1 => Some("1 similar alert was suppressed".into()),
+            n => Some(format!("{} similar alerts were suppressed", n)),
+        }
+    }
+}
+
+/// https://api.slack.com/messaging/webhooks
+pub fn slack(alert: &Alert) -> Value {
+    let mut attachment = json!({
+        "color": format!("#{}", alert.severity.color()),
+        "fallback": alert.title,
+        "ts": alert.timestamp.timestamp(),
+    });
+    if let Some(text) = &alert.text {
+        attachment["text"] = text.as_str().into();
+    }
+    if let Some(note) = alert.suppressed_note() {
+        attachment["footer"] = note.into();
+    }
+
+    json!({
+        "text": format!("*{}*", alert.title),
+        "attachments": [attachment],
+    })
+}
+
+/// https://developer.pagerduty.com/docs/events-api-v2/trigger-events/
+pub fn pagerduty(alert: &Alert, routing_key: &str) -> Value {
+    let mut details = alert.fields.clone();
+    if let Some(text) = &alert.text {
+        details.insert("text".into(), text.as_str().into());
+    }
+    if alert.suppressed > 0 {
+        details.insert("suppressed_alerts".into(), alert.suppressed.into());
+    }
+
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": truncate(&alert.group_key, PAGERDUTY_MAX_DEDUP_KEY_LEN),
+        "payload": {
+            "summary": truncate(&alert.title, PAGERDUTY_MAX_SUMMARY_LEN),
+            "source": alert.source,
+            "severity": alert.severity.as_str(),
+            "timestamp": alert.timestamp.to_rfc3339(),
+            "custom_details": details,
+        },
+    })
+}
+
+/// https://docs.microsoft.com/en-us/outlook/actionable-messages/message-card-reference
+pub fn teams(alert: &Alert) -> Value {
+    let text = alert
+        .text
+        .iter()
+        .cloned()
+        .chain(alert.suppressed_note().map(|note| format!("_{}_", note)))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let mut card = json!({
+        "@type": "MessageCard",
+        "@context": "https://schema.org/extensions",
+        "summary": alert.title,
+        "title": alert.title,
+        "themeColor": alert.severity.color(),
+    });
+    if !text.is_empty() {
+        card["text"] = text.into();
+    }
+    card
+}
+
+fn truncate(value: &str, max_len: usize) -> &str {
+    match value.char_indices().nth(max_len) {
+        Some((end, _)) => &value[..end],
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn alert() -> Alert {
+        Alert {
+            title: "db-1: disk full".into(),
+            text: Some("/var is at 100%".into()),
+            severity: Severity::Critical,
+            source: "db-1".into(),
+            group_key: "db-1".into(),
+            suppressed: 2,
+            timestamp: Utc.ymd(2020, 6, 1).and_hms(12, 0, 0),
+            fields: Map::new(),
+        }
+    }
+
+    #[test]
+    fn alerts_slack_payload() {
+        let payload = slack(&alert());
+        assert_eq!(payload["text"], "*db-1: disk full*");
+        assert_eq!(payload["attachments"][0]["color"], "#D00000");
+        assert_eq!(payload["attachments"][0]["text"], "/var is at 100%");
+        assert_eq!(
+            payload["attachments"][0]["footer"],
+            "2 similar alerts were suppressed"
+        );
+    }
+
+    #[test]
+    fn alerts_pagerduty_payload() {
+        let payload = pagerduty(&alert(), "R0UT1NGK3Y");
+        assert_eq!(payload["routing_key"], "R0UT1NGK3Y");
+        assert_eq!(payload["event_action"], "trigger");
+        assert_eq!(payload["dedup_key"], "db-1");
+        assert_eq!(payload["payload"]["severity"], "critical");
+        assert_eq!(payload["payload"]["timestamp"], "2020-06-01T12:00:00+00:00");
+        assert_eq!(payload["payload"]["custom_details"]["suppressed_alerts"], 2);
+    }
+
+    #[test]
+    fn alerts_teams_payload() {
+        let payload = teams(&alert());
+        assert_eq!(payload["@type"], "MessageCard");
+        assert_eq!(payload["title"], "db-1: disk full");
+        assert_eq!(
+            payload["text"],
+            "/var is at 100%\n\n_2 similar alerts were suppressed_"
+        );
+    }
+
+    #[test]
+    fn alerts_parses_severity() {
+        assert_eq!(Severity::parse("CRIT"), Some(Severity::Critical));
+        assert_eq!(Severity::parse("warn"), Some(Severity::Warning));
+        assert_eq!(Severity::parse("bogus"), None);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    amqp,
+    event::{self, Event, Value},
+    internal_events::AmqpEventSent,
+    sinks::util::{
+        encoding::{EncodingConfig, EncodingConfiguration},
+        retries::RetryLogic,
+        BatchEventsConfig, TowerRequestConfig,
+    },
+    template::Template,
+    tls::TlsOptions,
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures::future::{FutureExt, TryFutureExt};
+use futures01::{stream::iter_ok, Future, Poll, Sink};
+use lapin::{
+    options::{BasicPublishOptions, ConfirmSelectOptions},
+    publisher_confirm::Confirmation,
+    types::{AMQPValue, FieldTable, LongString, ShortString},
+    BasicProperties, Channel, Connection,
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+use string_cache::DefaultAtom as Atom;
+use tower::Service;
+
+#[derive(Debug, Snafu)]
+pub enum PublishError {
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: crate::Error },
+    #[snafu(display("Publishing failed: {}", source))]
+    Publish { source: lapin::Error },
+    #[snafu(display("The broker rejected a message"))]
+    Nacked,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AmqpSinkConfig {
+    pub uri: String,
+    pub exchange: Template,
+    #[serde(default = "default_routing_key")]
+    pub routing_key: Template,
+    pub encoding: EncodingConfig<Encoding>,
+    #[serde(default)]
+    pub properties: PropertiesConfig,
+    pub tls: Option<TlsOptions>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PropertiesConfig {
+    /// Defaults to the media type of the encoding.
+    pub content_type: Option<String>,
+    /// Has the broker write the messages to disk in durable queues.
+    #[serde(default = "default_persistent")]
+    pub persistent: bool,
+    /// The fields set as headers of the message, under their own name.
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+impl Default for PropertiesConfig {
+    fn default() -> Self {
+        Self {
+            content_type: None,
+            persistent: default_persistent(),
+            headers: Vec::new(),
+        }
+    }
+}
+
+fn default_routing_key() -> Template {
+    Template::from("")
+}
+
+fn default_persistent() -> bool {
+    true
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        rate_limit_num: Some(1000),
+        timeout_secs: Some(30),
+        ..Default::default()
+    };
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Text,
+    Json,
+}
+
+impl Encoding {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Text => "text/plain",
+            Encoding::Json => "application/json",
+        }
+    }
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<AmqpSinkConfig>("amqp")
+}
+
+#[typetag::serde(name = "amqp")]
+impl SinkConfig for AmqpSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let batch = self.batch.unwrap_or(100, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+        let config = self.clone();
+
+        let service = AmqpService {
+            config: self.clone(),
+            session: Arc::new(Mutex::new(None)),
+        };
+        let healthcheck = healthcheck(self.clone()).boxed().compat();
+        let sink = request
+            .batch_sink(AmqpRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|error| error!("Fatal amqp sink error: {}", error))
+            .with_flat_map(move |event| iter_ok(encode_event(event, &config)));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "amqp"
+    }
+}
+
+async fn healthcheck(config: AmqpSinkConfig) -> crate::Result<()> {
+    let connection = amqp::connect(&config.uri, &config.tls).await?;
+    connection.close(200, "OK").await?;
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+pub struct AmqpMessage {
+    exchange: String,
+    routing_key: String,
+    payload: Vec<u8>,
+    properties: BasicProperties,
+}
+
+struct Session {
+    _connection: Connection,
+    channel: Channel,
+}
+
+#[derive(Clone)]
+struct AmqpService {
+    config: AmqpSinkConfig,
+    /// Opened on first use, and again once its channel closed.
+    session: Arc<Mutex<Option<Session>>>,
+}
+
+impl AmqpService {
+    async fn channel(&self) -> Result<Channel, PublishError> {
+        let open = self
+            .session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|session| session.channel.status().is_connected())
+            .map(|session| session.channel.clone());
+        if let Some(channel) = open {
+            return Ok(channel);
+        }
+
+        let connection = amqp::connect(&self.config.uri, &self.config.tls)
+            .await
+            .context(Connect)?;
+        let channel = connection.create_channel().await.context(Publish)?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .context(Publish)?;
+        *self.session.lock().unwrap() = Some(Session {
+            _connection: connection,
+            channel: channel.clone(),
+        });
+        Ok(channel)
+    }
+
+    async fn publish(self, messages: Vec<AmqpMessage>) -> Result<(), PublishError> {
+        let channel = self.channel().await?;
+        let mut confirms = Vec::with_capacity(messages.len());
+        for message in messages {
+            let confirm = channel
+                .basic_publish(
+                    &message.exchange,
+                    &message.routing_key,
+                    BasicPublishOptions::default(),
+                    message.payload,
+                    message.properties,
+                )
+                .await
+                .context(Publish)?;
+            confirms.push(confirm);
+        }
+
+        // The broker confirms a message once the queues it was routed to
+        // took it, written to disk for persistent messages in durable
+        // queues.
+        for confirm in confirms {
+            if let Confirmation::Nack(_) = confirm.await.context(Publish)? {
+                return Err(PublishError::Nacked);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Service<Vec<AmqpMessage>> for AmqpService {
+    type Response = ();
+    type Error = PublishError;
+    type Future = Box<dyn Future<Item = (), Error = PublishError> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, messages: Vec<AmqpMessage>) -> Self::Future {
+        debug!(message = "publishing messages.", count = %messages.len());
+        Box::new(self.clone().publish(messages).boxed().compat())
+    }
+}
+
+impl fmt::Debug for AmqpService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AmqpService")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AmqpRetryLogic;
+
+impl RetryLogic for AmqpRetryLogic {
+    type Error = PublishError;
+    type Response = ();
+
+    // A nack means the broker failed to take the message, rather than that
+    // it is invalid.
+    fn is_retriable_error(&self, _error: &Self::Error) -> bool {
+        true
+    }
+}
+
+fn encode_event(mut event: Event, config: &AmqpSinkConfig) -> Option<AmqpMessage> {
+    let render = |template: &Template| {
+        template
+            .render_string(&event)
+            .map_err(|missing_keys| {
+                warn!(
+                    message = "Keys do not exist on the event; dropping event.",
+                    ?missing_keys,
+                    rate_limit_secs = 30,
+                );
+            })
+            .ok()
+    };
+    let exchange = render(&config.exchange)?;
+    let routing_key = render(&config.routing_key)?;
+    let properties = properties(&event, config);
+
+    config.encoding.apply_rules(&mut event);
+    let log = event.into_log();
+    let payload = match config.encoding.codec() {
+        Encoding::Json => serde_json::to_vec(&log).expect("Error encoding event as json."),
+        Encoding::Text => log
+            .get(&event::log_schema().message_key())
+            .map(|value| value.as_bytes().to_vec())
+            .unwrap_or_default(),
+    };
+
+    emit!(AmqpEventSent {
+        byte_size: payload.len()
+    });
+    Some(AmqpMessage {
+        exchange,
+        routing_key,
+        payload,
+        properties,
+    })
+}
+
+fn properties(event: &Event, config: &AmqpSinkConfig) -> BasicProperties {
+    let log = event.as_log();
+    let content_type = match &config.properties.content_type {
+        Some(content_type) => content_type.clone(),
+        None => config.encoding.codec().content_type().to_owned(),
+    };
+    let mut properties = BasicProperties::default().with_content_type(content_type.into());
+    if config.properties.persistent {
+        properties = properties.with_delivery_mode(2);
+    }
+    if let Some(Value::Timestamp(timestamp)) = log.get(&event::log_schema().timestamp_key()) {
+        properties = properties.with_timestamp(timestamp.timestamp() as u64);
+    }
+
+    let mut headers = FieldTable::default();
+    for field in &config.properties.headers {
+        if let Some(value) = log.get(&Atom::from(field.as_str())) {
+            headers.insert(
+                ShortString::from(field.as_str()),
+                AMQPValue::LongString(LongString::from(value.to_string_lossy())),
+            );
+        }
+    }
+    if !config.properties.headers.is_empty() {
+        properties = properties.with_headers(headers);
+    }
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn amqp_encodes_messages() {
+        let config: AmqpSinkConfig = toml::from_str(
+            r#"
+            uri = "amqp://localhost:5672/%2f"
+            exchange = "logs"
+            routing_key = "app.{{ app }}"
+            encoding = "json"
+
+            [properties]
+            headers = ["app", "trace_id"]
+            "#,
+        )
+        .unwrap();
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("app", "web");
+        event.as_mut_log().insert(
+            event::log_schema().timestamp_key(),
+            Utc.timestamp(1_590_000_000, 0),
+        );
+
+        let message = encode_event(event, &config).unwrap();
+        assert_eq!(message.exchange, "logs");
+        assert_eq!(message.routing_key, "app.web");
+        let json: serde_json::Value = serde_json::from_slice(&message.payload).unwrap();
+        assert_eq!(json["message"], "hello");
+
+        let properties = message.properties;
+        assert_eq!(
+            properties.content_type(),
+            &Some(ShortString::from("application/json"))
+        );
+        assert_eq!(properties.delivery_mode(), &Some(2));
+        assert_eq!(properties.timestamp(), &Some(1_590_000_000));
+        let headers = properties.headers().clone().unwrap();
+        assert_eq!(
+            headers.inner().get(&ShortString::from("app")),
+            Some(&AMQPValue::LongString(LongString::from("web".to_owned())))
+        );
+        assert!(headers
+            .inner()
+            .get(&ShortString::from("trace_id"))
+            .is_none());
+
+        assert!(encode_event(Event::from("hello"), &config).is_none());
+    }
+}
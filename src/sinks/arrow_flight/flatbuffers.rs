@@ -0,0 +1,247 @@
+//! Just enough of a FlatBuffers writer to encode Arrow IPC metadata.
+//!
+//! Objects are laid out front to back: every table is followed by the
+//! objects it refers to, so all offsets point forward as the format
+//! requires. Tables start on 8 byte boundaries and their fields are placed
+//! largest first, which keeps every scalar naturally aligned.
+
+pub enum Field {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    String(String),
+    Table(Table),
+    Tables(Vec<Table>),
+    /// A vector of structs, given as their little endian encoding. The
+    /// structs must be 8 byte aligned.
+    Structs {
+        count: usize,
+        data: Vec<u8>,
+    },
+}
+
+impl Field {
+    fn inline_size(&self) -> usize {
+        match self {
+            Field::Bool(_) | Field::U8(_) => 1,
+            Field::I16(_) => 2,
+            Field::I64(_) => 8,
+            Field::I32(_)
+            | Field::String(_)
+            | Field::Table(_)
+            | Field::Tables(_)
+            | Field::Structs { .. } => 4,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Table {
+    fields: Vec<(u16, Field)>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the field with the given id, as numbered in the schema.
+    pub fn with(mut self, id: u16, field: Field) -> Self {
+        self.fields.push((id, field));
+        self
+    }
+}
+
+/// Writes a buffer with `root` as its root table.
+pub fn finish(root: &Table) -> Vec<u8> {
+    let mut writer = Writer { buf: vec![0; 4] };
+    let root = writer.table(root);
+    writer.patch(0, root);
+    writer.buf
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    /// Pads the buffer so that `len` bytes written next end on a multiple
+    /// of `align`.
+    fn pad(&mut self, align: usize, len: usize) {
+        while (self.buf.len() + len) % align != 0 {
+            self.buf.push(0);
+        }
+    }
+
+    /// Points the offset at `at` to the object at `target`.
+    fn patch(&mut self, at: usize, target: usize) {
+        let offset = (target - at) as u32;
+        self.buf[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    fn table(&mut self, table: &Table) -> usize {
+        let mut fields = table.fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|(_, field)| std::cmp::Reverse(field.inline_size()));
+
+        // The table starts with the offset to its vtable.
+        let mut size = 4;
+        let mut layout = Vec::with_capacity(fields.len());
+        for (id, field) in fields {
+            let field_size = field.inline_size();
+            size = (size + field_size - 1) / field_size * field_size;
+            layout.push((*id, size, field));
+            size += field_size;
+        }
+
+        let slots = table
+            .fields
+            .iter()
+            .map(|(id, _)| *id as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let vtable_len = 4 + 2 * slots;
+        let mut vtable = vec![0u16; slots];
+        for (id, position, _) in &layout {
+            vtable[*id as usize] = *position as u16;
+        }
+
+        self.pad(8, vtable_len);
+        let vtable_start = self.buf.len();
+        self.buf
+            .extend_from_slice(&(vtable_len as u16).to_le_bytes());
+        self.buf.extend_from_slice(&(size as u16).to_le_bytes());
+        for position in vtable {
+            self.buf.extend_from_slice(&position.to_le_bytes());
+        }
+
+        let start = self.buf.len();
+        self.buf.resize(start + size, 0);
+        let vtable_offset = (start - vtable_start) as i32;
+        self.buf[start..start + 4].copy_from_slice(&vtable_offset.to_le_bytes());
+
+        let mut children = Vec::new();
+        for (_, position, field) in layout {
+            let at = start + position;
+            match field {
+                Field::Bool(value) => self.buf[at] = *value as u8,
+                Field::U8(value) => self.buf[at] = *value,
+                Field::I16(value) => self.buf[at..at + 2].copy_from_slice(&value.to_le_bytes()),
+                Field::I32(value) => self.buf[at..at + 4].copy_from_slice(&value.to_le_bytes()),
+                Field::I64(value) => self.buf[at..at + 8].copy_from_slice(&value.to_le_bytes()),
+                _ => children.push((at, field)),
+            }
+        }
+
+        for (at, field) in children {
+            let target = self.object(field);
+            self.patch(at, target);
+        }
+
+        start
+    }
+
+    fn object(&mut self, field: &Field) -> usize {
+        match field {
+            Field::Table(table) => self.table(table),
+            Field::String(value) => {
+                self.pad(4, 0);
+                let start = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(value.len() as u32).to_le_bytes());
+                self.buf.extend_from_slice(value.as_bytes());
+                self.buf.push(0);
+                start
+            }
+            Field::Tables(tables) => {
+                self.pad(4, 0);
+                let start = self.buf.len();
+                self.buf
+                    .extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                let slots = self.buf.len();
+                self.buf.resize(slots + 4 * tables.len(), 0);
+                for (index, table) in tables.iter().enumerate() {
+                    let target = self.table(table);
+                    self.patch(slots + 4 * index, target);
+                }
+                start
+            }
+            Field::Structs { count, data } => {
+                // The length precedes the structs, which must be aligned.
+                self.pad(8, 4);
+                let start = self.buf.len();
+                self.buf.extend_from_slice(&(*count as u32).to_le_bytes());
+                self.buf.extend_from_slice(data);
+                start
+            }
+            Field::Bool(_) | Field::U8(_) | Field::I16(_) | Field::I32(_) | Field::I64(_) => {
+                unreachable!("scalars are stored inline")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(buf: &[u8], at: usize) -> usize {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&buf[at..at + 4]);
+        u32::from_le_bytes(bytes) as usize
+    }
+
+    fn u16_at(buf: &[u8], at: usize) -> usize {
+        buf[at] as usize | (buf[at + 1] as usize) << 8
+    }
+
+    /// Resolves the position of a field the way a reader does.
+    fn field_position(buf: &[u8], table: usize, id: usize) -> Option<usize> {
+        let vtable = table - u32_at(buf, table);
+        let vtable_len = u16_at(buf, vtable);
+        if 4 + 2 * id >= vtable_len {
+            return None;
+        }
+        match u16_at(buf, vtable + 4 + 2 * id) {
+            0 => None,
+            offset => Some(table + offset),
+        }
+    }
+
+    #[test]
+    fn flatbuffers_layout() {
+        let root = Table::new()
+            .with(0, Field::I16(3))
+            .with(2, Field::I64(-2))
+            .with(3, Field::String("abc".into()))
+            .with(
+                4,
+                Field::Tables(vec![Table::new().with(0, Field::Bool(true))]),
+            );
+        let buf = finish(&root);
+
+        let table = u32_at(&buf, 0);
+        assert_eq!(table % 8, 0);
+        assert_eq!(field_position(&buf, table, 1), None);
+
+        let short = field_position(&buf, table, 0).unwrap();
+        assert_eq!(u16_at(&buf, short), 3);
+
+        let long = field_position(&buf, table, 2).unwrap();
+        assert_eq!(long % 8, 0);
+        assert_eq!(buf[long..long + 8], (-2i64).to_le_bytes());
+
+        let string = field_position(&buf, table, 3).unwrap();
+        let string = string + u32_at(&buf, string);
+        assert_eq!(u32_at(&buf, string), 3);
+        assert_eq!(&buf[string + 4..string + 8], b"abc\0");
+
+        let vector = field_position(&buf, table, 4).unwrap();
+        let vector = vector + u32_at(&buf, vector);
+        assert_eq!(u32_at(&buf, vector), 1);
+        let child = vector + 4 + u32_at(&buf, vector + 4);
+        let flag = field_position(&buf, child, 0).unwrap();
+        assert_eq!(buf[flag], 1);
+    }
+}
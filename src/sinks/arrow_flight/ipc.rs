@@ -0,0 +1,325 @@
+//! Encodes log events as Arrow IPC messages: a schema message describing
+//! the columns, and record batch messages holding their values.
+//!
+//! See https://arrow.apache.org/docs/format/Columnar.html
+
+use super::flatbuffers::{finish, Field, Table};
+use crate::{
+    event::{LogEvent, Value},
+    sinks::util::columns::{
+        to_bool, to_f64, to_i64, to_string, to_timestamp_micros, Column, ColumnType,
+    },
+};
+
+/// `MetadataVersion::V5`, written by Arrow 1.0 and later.
+const METADATA_VERSION: i16 = 4;
+
+// `MessageHeader` union members.
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+// `Type` union members.
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_TIMESTAMP: u8 = 10;
+
+const PRECISION_DOUBLE: i16 = 2;
+const TIME_UNIT_MICROSECOND: i16 = 2;
+
+/// Buffers in the message body are padded to this many bytes.
+const BODY_ALIGNMENT: usize = 8;
+
+/// An encoded IPC message: the flatbuffer metadata and the body it
+/// describes.
+#[derive(Debug)]
+pub struct Message {
+    pub header: Vec<u8>,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    /// Frames the message as in the IPC streaming format, with the
+    /// continuation marker and the length of the padded metadata.
+    pub fn to_stream_bytes(&self) -> Vec<u8> {
+        let padding = (8 - self.header.len() % 8) % 8;
+        let mut bytes = Vec::with_capacity(8 + self.header.len() + padding + self.body.len());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&((self.header.len() + padding) as i32).to_le_bytes());
+        bytes.extend_from_slice(&self.header);
+        bytes.resize(bytes.len() + padding, 0);
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+pub fn schema_message(columns: &[Column]) -> Message {
+    let fields = columns.iter().map(schema_field).collect();
+    let schema = Table::new().with(1, Field::Tables(fields));
+
+    Message {
+        header: message(HEADER_SCHEMA, schema, 0),
+        body: Vec::new(),
+    }
+}
+
+fn schema_field(column: &Column) -> Table {
+    let (type_id, type_table) = match column.column_type {
+        ColumnType::Utf8 => (TYPE_UTF8, Table::new()),
+        ColumnType::Int64 => (
+            TYPE_INT,
+            Table::new()
+                .with(0, Field::I32(64))
+                .with(1, Field::Bool(true)),
+        ),
+        ColumnType::Float64 => (
+            TYPE_FLOATING_POINT,
+            Table::new().with(0, Field::I16(PRECISION_DOUBLE)),
+        ),
+        ColumnType::Boolean => (TYPE_BOOL, Table::new()),
+        ColumnType::Timestamp => (
+            TYPE_TIMESTAMP,
+            Table::new()
+                .with(0, Field::I16(TIME_UNIT_MICROSECOND))
+                .with(1, Field::String("UTC".into())),
+        ),
+    };
+
+    Table::new()
+        .with(0, Field::String(column.name.to_string()))
+        .with(1, Field::Bool(true))
+        .with(2, Field::U8(type_id))
+        .with(3, Field::Table(type_table))
+        // Readers expect the children, even for flat types.
+        .with(5, Field::Tables(Vec::new()))
+}
+
+pub fn record_batch_message(columns: &[Column], events: &[LogEvent]) -> Message {
+    let mut body = Body::default();
+    for column in columns {
+        let values = events
+            .iter()
+            .map(|log| log.get(&column.name))
+            .collect::<Vec<_>>();
+        match column.column_type {
+            ColumnType::Utf8 => body.utf8(&values),
+            ColumnType::Int64 => body.fixed(&values, to_i64),
+            ColumnType::Float64 => body.fixed(&values, to_f64),
+            ColumnType::Boolean => body.boolean(&values),
+            ColumnType::Timestamp => body.fixed(&values, to_timestamp_micros),
+        }
+    }
+
+    let record_batch = Table::new()
+        .with(0, Field::I64(events.len() as i64))
+        .with(
+            1,
+            Field::Structs {
+                count: body.nodes.len() / 16,
+                data: body.nodes,
+            },
+        )
+        .with(
+            2,
+            Field::Structs {
+                count: body.buffers.len() / 16,
+                data: body.buffers,
+            },
+        );
+
+    Message {
+        header: message(HEADER_RECORD_BATCH, record_batch, body.data.len()),
+        body: body.data,
+    }
+}
+
+fn message(header_type: u8, header: Table, body_len: usize) -> Vec<u8> {
+    let message = Table::new()
+        .with(0, Field::I16(METADATA_VERSION))
+        .with(1, Field::U8(header_type))
+        .with(2, Field::Table(header))
+        .with(3, Field::I64(body_len as i64));
+    finish(&message)
+}
+
+/// The body of a record batch, with the `FieldNode` and `Buffer` structs
+/// that locate each column in it.
+#[derive(Default)]
+struct Body {
+    nodes: Vec<u8>,
+    buffers: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Body {
+    fn node(&mut self, length: usize, null_count: usize) {
+        self.nodes.extend_from_slice(&(length as i64).to_le_bytes());
+        self.nodes
+            .extend_from_slice(&(null_count as i64).to_le_bytes());
+    }
+
+    fn buffer(&mut self, bytes: &[u8]) {
+        let offset = self.data.len();
+        self.buffers
+            .extend_from_slice(&(offset as i64).to_le_bytes());
+        self.buffers
+            .extend_from_slice(&(bytes.len() as i64).to_le_bytes());
+        self.data.extend_from_slice(bytes);
+        let padded = (self.data.len() + BODY_ALIGNMENT - 1) / BODY_ALIGNMENT * BODY_ALIGNMENT;
+        self.data.resize(padded, 0);
+    }
+
+    /// Adds the node and validity bitmap shared by all columns.
+    fn validity<T>(&mut self, values: &[Option<T>]) {
+        let null_count = values.iter().filter(|value| value.is_none()).count();
+        self.node(values.len(), null_count);
+        self.buffer(&bitmap(values.iter().map(Option::is_some)));
+    }
+
+    fn fixed<T: Fixed64>(&mut self, values: &[Option<&Value>], convert: fn(&Value) -> Option<T>) {
+        let values = values
+            .iter()
+            .map(|value| value.and_then(convert))
+            .collect::<Vec<_>>();
+        self.validity(&values);
+
+        let mut data = Vec::with_capacity(values.len() * 8);
+        for value in &values {
+            data.extend_from_slice(&value.unwrap_or_default().to_bytes());
+        }
+        self.buffer(&data);
+    }
+
+    fn boolean(&mut self, values: &[Option<&Value>]) {
+        let values = values
+            .iter()
+            .map(|value| value.and_then(to_bool))
+            .collect::<Vec<_>>();
+        self.validity(&values);
+        self.buffer(&bitmap(values.iter().map(|value| value.unwrap_or(false))));
+    }
+
+    fn utf8(&mut self, values: &[Option<&Value>]) {
+        let values = values
+            .iter()
+            .map(|value| value.and_then(to_string))
+            .collect::<Vec<_>>();
+        self.validity(&values);
+
+        let mut offsets = Vec::with_capacity((values.len() + 1) * 4);
+        let mut data = Vec::new();
+        offsets.extend_from_slice(&0i32.to_le_bytes());
+        for value in &values {
+            if let Some(value) = value {
+                data.extend_from_slice(value.as_bytes());
+            }
+            offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        }
+        self.buffer(&offsets);
+        self.buffer(&data);
+    }
+}
+
+/// Values stored in 8 byte slots of a fixed width column.
+trait Fixed64: Default + Copy {
+    fn to_bytes(self) -> [u8; 8];
+}
+
+impl Fixed64 for i64 {
+    fn to_bytes(self) -> [u8; 8] {
+        self.to_le_bytes()
+    }
+}
+
+impl Fixed64 for f64 {
+    fn to_bytes(self) -> [u8; 8] {
+        self.to_bits().to_le_bytes()
+    }
+}
+
+/// Packs the flags least significant bit first, as Arrow bitmaps are.
+fn bitmap(flags: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut bitmap = Vec::new();
+    for (index, flag) in flags.enumerate() {
+        if index % 8 == 0 {
+            bitmap.push(0);
+        }
+        if flag {
+            *bitmap.last_mut().unwrap() |= 1 << (index % 8);
+        }
+    }
+    bitmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use chrono::{TimeZone, Utc};
+
+    fn column(name: &str, column_type: ColumnType) -> Column {
+        Column {
+            name: name.into(),
+            column_type,
+        }
+    }
+
+    fn i64_at(bytes: &[u8], at: usize) -> i64 {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&bytes[at..at + 8]);
+        i64::from_le_bytes(buf)
+    }
+
+    #[test]
+    fn arrow_bitmaps() {
+        let flags = vec![true, false, true, true, false, false, false, false, true];
+        assert_eq!(bitmap(flags.into_iter()), vec![0b0000_1101, 0b0000_0001]);
+    }
+
+    #[test]
+    fn arrow_record_batch_body() {
+        let columns = vec![
+            column("message", ColumnType::Utf8),
+            column("status", ColumnType::Int64),
+            column("timestamp", ColumnType::Timestamp),
+        ];
+
+        let mut first = Event::from("ab");
+        first.as_mut_log().insert("status", 200);
+        first
+            .as_mut_log()
+            .insert("timestamp", Utc.timestamp(1, 500_000));
+        let mut second = Event::from("c");
+        second.as_mut_log().insert("status", "oops");
+        second.as_mut_log().remove(&"timestamp".into());
+        let events = vec![first.into_log(), second.into_log()];
+
+        let message = record_batch_message(&columns, &events);
+
+        let body = &message.body;
+        assert_eq!(body.len() % 8, 0);
+
+        // message: validity, offsets, data
+        assert_eq!(body[0], 0b11);
+        assert_eq!(&body[8..20], &[0, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&body[24..27], b"abc");
+        // status: validity, values
+        assert_eq!(body[32], 0b01);
+        assert_eq!(i64_at(body, 40), 200);
+        // timestamp: validity, values
+        assert_eq!(body[56], 0b01);
+        assert_eq!(i64_at(body, 64), 1_000_500);
+    }
+
+    #[test]
+    fn arrow_stream_framing() {
+        let message = schema_message(&[column("message", ColumnType::Utf8)]);
+        let bytes = message.to_stream_bytes();
+
+        assert_eq!(&bytes[..4], &[0xff; 4]);
+        let len = i64::from(i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]));
+        assert_eq!(len % 8, 0);
+        assert_eq!(bytes.len() as i64, 8 + len);
+    }
+}
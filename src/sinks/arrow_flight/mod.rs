@@ -0,0 +1,329 @@
+use crate::{
+    event::Event,
+    sinks::{
+        util::{
+            columns::{self, Column, ColumnType},
+            grpc::{self, GrpcRetryLogic},
+            http2::HttpClient,
+            service2::TowerRequestConfig,
+            BatchEventsConfig, UriSerde,
+        },
+        UriParseError2,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes05::Bytes;
+use futures::future::BoxFuture;
+use futures01::{future, Sink};
+use http02::{
+    header::{self, HeaderName, HeaderValue},
+    Method, Request, Response, Uri,
+};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::task::{Context, Poll};
+use tower03::Service;
+
+mod flatbuffers;
+mod ipc;
+
+#[allow(clippy::all)]
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/arrow.flight.protocol.rs"));
+}
+
+use proto::{flight_descriptor::DescriptorType, FlightData, FlightDescriptor};
+
+const DO_PUT_GRPC_PATH: &str = "/arrow.flight.protocol.FlightService/DoPut";
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Exactly one of `path` and `command` must be set"))]
+    MissingDescriptor,
+    #[snafu(display("{}: {}", source, name))]
+    InvalidHeaderName {
+        name: String,
+        source: header::InvalidHeaderName,
+    },
+    #[snafu(display("{}: {}", source, value))]
+    InvalidHeaderValue {
+        value: String,
+        source: header::InvalidHeaderValue,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ArrowFlightSinkConfig {
+    pub endpoint: UriSerde,
+    #[serde(default)]
+    pub path: Vec<String>,
+    pub command: Option<String>,
+    /// Maps event fields to column types, in column order.
+    #[serde(default)]
+    pub schema: IndexMap<String, ColumnType>,
+    #[serde(default)]
+    pub headers: IndexMap<String, String>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(5),
+        timeout_secs: Some(60),
+        rate_limit_num: Some(10),
+        ..Default::default()
+    };
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<ArrowFlightSinkConfig>("arrow_flight")
+}
+
+#[typetag::serde(name = "arrow_flight")]
+impl SinkConfig for ArrowFlightSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let client = HttpClient::new_http2(cx.resolver(), Some(tls))?;
+
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| InvalidHeaderName { name })?;
+                let value =
+                    HeaderValue::from_str(value).with_context(|| InvalidHeaderValue { value })?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+
+        let service = ArrowFlightService {
+            client,
+            uri: self.uri()?,
+            headers,
+            descriptor: self.descriptor()?,
+            columns: self.columns(),
+        };
+
+        let batch = self.batch.unwrap_or(10_000, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+
+        let sink = request
+            .batch_sink(GrpcRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|e| error!("Fatal arrow_flight sink error: {}", e));
+
+        // Flight has no call that every server implements and that can be
+        // made without side effects.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "arrow_flight"
+    }
+}
+
+impl ArrowFlightSinkConfig {
+    fn uri(&self) -> crate::Result<Uri> {
+        let endpoint = self.endpoint.to_string();
+        let uri = format!("{}{}", endpoint.trim_end_matches('/'), DO_PUT_GRPC_PATH)
+            .parse::<Uri>()
+            .context(UriParseError2)?;
+        Ok(uri)
+    }
+
+    fn descriptor(&self) -> Result<FlightDescriptor, BuildError> {
+        match (&self.command, self.path.is_empty()) {
+            (Some(command), true) => Ok(FlightDescriptor {
+                r#type: DescriptorType::Cmd as i32,
+                cmd: command.clone().into_bytes(),
+                path: Vec::new(),
+            }),
+            (None, false) => Ok(FlightDescriptor {
+                r#type: DescriptorType::Path as i32,
+                cmd: Vec::new(),
+                path: self.path.clone(),
+            }),
+            _ => Err(BuildError::MissingDescriptor),
+        }
+    }
+
+    fn columns(&self) -> Vec<Column> {
+        columns::columns(&self.schema)
+    }
+}
+
+#[derive(Clone)]
+struct ArrowFlightService {
+    client: HttpClient,
+    uri: Uri,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    descriptor: FlightDescriptor,
+    columns: Vec<Column>,
+}
+
+impl Service<Vec<Event>> for ArrowFlightService {
+    type Response = Response<Bytes>;
+    type Error = hyper13::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, events: Vec<Event>) -> Self::Future {
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(self.uri.clone())
+            .header("Content-Type", "application/grpc")
+            .header("TE", "trailers");
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let body = encode_stream(&self.descriptor, &self.columns, events);
+        let request = builder.body(body.into()).unwrap();
+
+        let mut client = self.client.clone();
+        Box::pin(async move { grpc::call(&mut client, request).await })
+    }
+}
+
+/// Encodes a batch as the stream of a `DoPut` call: the descriptor and
+/// schema first, followed by a single record batch.
+fn encode_stream(descriptor: &FlightDescriptor, columns: &[Column], events: Vec<Event>) -> Vec<u8> {
+    let logs = events.into_iter().map(Event::into_log).collect::<Vec<_>>();
+    let schema = ipc::schema_message(columns);
+    let record_batch = ipc::record_batch_message(columns, &logs);
+
+    let messages = vec![
+        FlightData {
+            flight_descriptor: Some(descriptor.clone()),
+            data_header: schema.header,
+            app_metadata: Vec::new(),
+            data_body: schema.body,
+        },
+        FlightData {
+            flight_descriptor: None,
+            data_header: record_batch.header,
+            app_metadata: Vec::new(),
+            data_body: record_batch.body,
+        },
+    ];
+
+    let mut body = Vec::new();
+    for message in messages {
+        let mut encoded = Vec::with_capacity(message.encoded_len());
+        message
+            .encode(&mut encoded)
+            .expect("Encoding into a Vec can't fail");
+        body.extend(grpc::frame(false, &encoded));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    fn config(extra: &str) -> ArrowFlightSinkConfig {
+        let (config, _, _) = load_sink::<ArrowFlightSinkConfig>(&format!(
+            r#"
+            endpoint = "http://localhost:8815/"
+            {}
+        "#,
+            extra
+        ))
+        .unwrap();
+        config
+    }
+
+    #[test]
+    fn arrow_flight_descriptor() {
+        let descriptor = config(r#"path = ["vector", "logs"]"#).descriptor().unwrap();
+        assert_eq!(descriptor.r#type, DescriptorType::Path as i32);
+        assert_eq!(descriptor.path, vec!["vector", "logs"]);
+
+        let descriptor = config(r#"command = "INSERT INTO logs""#)
+            .descriptor()
+            .unwrap();
+        assert_eq!(descriptor.r#type, DescriptorType::Cmd as i32);
+        assert_eq!(descriptor.cmd, b"INSERT INTO logs");
+
+        assert!(config("").descriptor().is_err());
+        assert!(config(
+            r#"
+            path = ["logs"]
+            command = "INSERT INTO logs"
+        "#
+        )
+        .descriptor()
+        .is_err());
+    }
+
+    #[test]
+    fn arrow_flight_columns() {
+        let columns = config("").columns();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(columns[0].column_type, ColumnType::Timestamp);
+
+        let columns = config(
+            r#"
+            [schema]
+            status = "int64"
+            message = "utf8"
+        "#,
+        )
+        .columns();
+        assert_eq!(
+            columns,
+            vec![
+                Column {
+                    name: "status".into(),
+                    column_type: ColumnType::Int64,
+                },
+                Column {
+                    name: "message".into(),
+                    column_type: ColumnType::Utf8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn arrow_flight_encodes_do_put_stream() {
+        let config = config(r#"path = ["logs"]"#);
+        let descriptor = config.descriptor().unwrap();
+        let body = encode_stream(&descriptor, &config.columns(), vec![Event::from("hello")]);
+
+        let mut frames = Vec::new();
+        let mut rest = &body[..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+            frames.push(FlightData::decode(&rest[5..5 + len]).unwrap());
+            rest = &rest[5 + len..];
+        }
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].flight_descriptor, Some(descriptor));
+        assert!(frames[0].data_body.is_empty());
+        assert_eq!(frames[1].flight_descriptor, None);
+        assert_eq!(frames[1].data_body.len() % 8, 0);
+        assert_eq!(
+            config.uri().unwrap(),
+            "http://localhost:8815/arrow.flight.protocol.FlightService/DoPut"
+        );
+    }
+}
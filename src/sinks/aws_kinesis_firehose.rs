@@ -1,29 +1,39 @@
 use crate::{
     dns::Resolver,
     event::{self, Event},
+    internal_events::{AwsKinesisFirehoseRecordTooLarge, AwsKinesisFirehoseRecordsFailed},
     region::RegionOrEndpoint,
     sinks::util::{
         encoding::{EncodingConfig, EncodingConfiguration},
-        retries::RetryLogic,
+        retries::{RetryAction, RetryLogic},
         rusoto2::{self, AwsCredentialsProvider},
-        BatchEventsConfig, TowerRequestConfig,
+        Batch, BatchEventsConfig, TowerRequestConfig,
     },
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use bytes::Bytes;
 use futures01::{stream::iter_ok, Future, Poll, Sink};
 use lazy_static::lazy_static;
-use rusoto_core::{Region, RusotoError, RusotoFuture};
+use rusoto_core::{Region, RusotoError};
 use rusoto_firehose::{
     DescribeDeliveryStreamInput, KinesisFirehose, KinesisFirehoseClient, PutRecordBatchError,
     PutRecordBatchInput, PutRecordBatchOutput, Record,
 };
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
-use std::{convert::TryInto, fmt};
+use std::{
+    convert::TryInto,
+    fmt,
+    sync::{Arc, Mutex},
+};
 use tower::Service;
 use tracing_futures::{Instrument, Instrumented};
 
+/// The limits of a `PutRecordBatch` request.
+const MAX_RECORDS: usize = 500;
+const MAX_BATCH_BYTES: usize = 4 * 1024 * 1024;
+const MAX_RECORD_BYTES: usize = 1000 * 1024;
+
 #[derive(Clone)]
 pub struct KinesisFirehoseService {
     client: KinesisFirehoseClient,
@@ -37,6 +47,10 @@ pub struct KinesisFirehoseSinkConfig {
     #[serde(flatten)]
     pub region: RegionOrEndpoint,
     pub encoding: EncodingConfig<Encoding>,
+    /// Ends each record with a newline, so records delivered together to
+    /// destinations like S3 stay apart.
+    #[serde(default)]
+    pub newline_delimited: bool,
     #[serde(default)]
     pub batch: BatchEventsConfig,
     #[serde(default)]
@@ -58,6 +72,16 @@ pub enum Encoding {
     Json,
 }
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display(
+        "`batch.max_events` is {}, but firehose accepts at most {} records per batch",
+        max_events,
+        MAX_RECORDS
+    ))]
+    BatchMaxEventsTooLarge { max_events: usize },
+}
+
 inventory::submit! {
     SinkDescription::new_without_default::<KinesisFirehoseSinkConfig>("aws_kinesis_firehose")
 }
@@ -91,9 +115,15 @@ impl KinesisFirehoseService {
             cx.resolver(),
         )?;
 
-        let batch = config.batch.unwrap_or(500, 1);
+        let batch = config.batch.unwrap_or(MAX_RECORDS as u64, 1);
+        if batch.size > MAX_RECORDS {
+            return Err(Box::new(BuildError::BatchMaxEventsTooLarge {
+                max_events: batch.size,
+            }));
+        }
         let request = config.request.unwrap_with(&REQUEST_DEFAULTS);
         let encoding = config.encoding.clone();
+        let newline_delimited = config.newline_delimited;
 
         let kinesis = KinesisFirehoseService { client, config };
 
@@ -101,41 +131,122 @@ impl KinesisFirehoseService {
             .batch_sink(
                 KinesisFirehoseRetryLogic,
                 kinesis,
-                Vec::new(),
+                RecordBatch::default(),
                 batch,
                 cx.acker(),
             )
             .sink_map_err(|e| error!("Fatal kinesis firehose sink error: {}", e))
-            .with_flat_map(move |e| iter_ok(encode_event(e, &encoding)));
+            .with_flat_map(move |e| iter_ok(encode_event(e, &encoding, newline_delimited)));
 
         Ok(sink)
     }
 }
 
-impl Service<Vec<Record>> for KinesisFirehoseService {
+/// Records batched within the limits of a `PutRecordBatch` request, besides
+/// their number which is limited by the batch settings.
+#[derive(Debug, Default)]
+struct RecordBatch {
+    records: Vec<Record>,
+    byte_size: usize,
+}
+
+impl Batch for RecordBatch {
+    type Input = Record;
+    type Output = RecordsRequest;
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn push(&mut self, record: Record) {
+        self.byte_size += record.data.len();
+        self.records.push(record);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    fn fresh(&self) -> Self {
+        Self::default()
+    }
+
+    fn finish(self) -> RecordsRequest {
+        RecordsRequest {
+            records: Arc::new(Mutex::new(self.records)),
+        }
+    }
+
+    fn num_items(&self) -> usize {
+        self.records.len()
+    }
+
+    fn would_overflow(&self, record: &Record) -> bool {
+        self.byte_size + record.data.len() > MAX_BATCH_BYTES
+    }
+}
+
+/// The records of a batch which remain to be put. Retries of the request
+/// share them, so once some records failed only those are retried.
+#[derive(Clone, Debug)]
+pub struct RecordsRequest {
+    records: Arc<Mutex<Vec<Record>>>,
+}
+
+type PutRecordBatchFuture =
+    Box<dyn Future<Item = PutRecordBatchOutput, Error = RusotoError<PutRecordBatchError>> + Send>;
+
+impl Service<RecordsRequest> for KinesisFirehoseService {
     type Response = PutRecordBatchOutput;
     type Error = RusotoError<PutRecordBatchError>;
-    type Future = Instrumented<RusotoFuture<PutRecordBatchOutput, PutRecordBatchError>>;
+    type Future = Instrumented<PutRecordBatchFuture>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         Ok(().into())
     }
 
-    fn call(&mut self, records: Vec<Record>) -> Self::Future {
+    fn call(&mut self, request: RecordsRequest) -> Self::Future {
+        let records = request.records.lock().unwrap().clone();
+
         debug!(
             message = "sending records.",
             events = %records.len(),
         );
 
-        let request = PutRecordBatchInput {
-            records,
+        let input = PutRecordBatchInput {
+            records: records.clone(),
             delivery_stream_name: self.config.stream_name.clone(),
         };
 
-        self.client
-            .put_record_batch(request)
-            .instrument(info_span!("request"))
+        let future = self.client.put_record_batch(input).map(move |output| {
+            if output.failed_put_count > 0 {
+                *request.records.lock().unwrap() = failed_records(records, &output);
+            }
+            output
+        });
+
+        let future: PutRecordBatchFuture = Box::new(future);
+        future.instrument(info_span!("request"))
+    }
+}
+
+/// The records the response reports as failed, which are to be retried.
+fn failed_records(records: Vec<Record>, output: &PutRecordBatchOutput) -> Vec<Record> {
+    let failed = records
+        .into_iter()
+        .zip(output.request_responses.iter())
+        .filter(|(_, response)| response.error_code.is_some())
+        .collect::<Vec<_>>();
+
+    if let Some((_, response)) = failed.first() {
+        emit!(AwsKinesisFirehoseRecordsFailed {
+            count: failed.len(),
+            error_code: response.error_code.as_deref().unwrap_or_default(),
+            error_message: response.error_message.as_deref().unwrap_or_default(),
+        });
     }
+
+    failed.into_iter().map(|(record, _)| record).collect()
 }
 
 impl fmt::Debug for KinesisFirehoseService {
@@ -161,6 +272,17 @@ impl RetryLogic for KinesisFirehoseRetryLogic {
             _ => false,
         }
     }
+
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        if response.failed_put_count > 0 {
+            RetryAction::Retry(format!(
+                "{} records failed to be put",
+                response.failed_put_count
+            ))
+        } else {
+            RetryAction::Successful
+        }
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -210,10 +332,14 @@ fn create_client(
     Ok(KinesisFirehoseClient::new_with(client, creds, region))
 }
 
-fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Option<Record> {
+fn encode_event(
+    mut event: Event,
+    encoding: &EncodingConfig<Encoding>,
+    newline_delimited: bool,
+) -> Option<Record> {
     encoding.apply_rules(&mut event);
     let log = event.into_log();
-    let data = match encoding.codec() {
+    let mut data = match encoding.codec() {
         Encoding::Json => serde_json::to_vec(&log).expect("Error encoding event as json."),
 
         Encoding::Text => log
@@ -221,6 +347,17 @@ fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Option
             .map(|v| v.as_bytes().to_vec())
             .unwrap_or_default(),
     };
+    if newline_delimited {
+        data.push(b'\n');
+    }
+
+    if data.len() > MAX_RECORD_BYTES {
+        emit!(AwsKinesisFirehoseRecordTooLarge {
+            byte_size: data.len(),
+            max_byte_size: MAX_RECORD_BYTES,
+        });
+        return None;
+    }
 
     let data = Bytes::from(data);
 
@@ -231,12 +368,13 @@ fn encode_event(mut event: Event, encoding: &EncodingConfig<Encoding>) -> Option
 mod tests {
     use super::*;
     use crate::event::{self, Event};
+    use rusoto_firehose::PutRecordBatchResponseEntry;
     use std::collections::BTreeMap;
 
     #[test]
     fn firehose_encode_event_text() {
         let message = "hello world".to_string();
-        let event = encode_event(message.clone().into(), &Encoding::Text.into()).unwrap();
+        let event = encode_event(message.clone().into(), &Encoding::Text.into(), false).unwrap();
 
         assert_eq!(&event.data[..], message.as_bytes());
     }
@@ -246,13 +384,70 @@ mod tests {
         let message = "hello world".to_string();
         let mut event = Event::from(message.clone());
         event.as_mut_log().insert("key", "value");
-        let event = encode_event(event, &Encoding::Json.into()).unwrap();
+        let event = encode_event(event, &Encoding::Json.into(), false).unwrap();
 
         let map: BTreeMap<String, String> = serde_json::from_slice(&event.data[..]).unwrap();
 
         assert_eq!(map[&event::log_schema().message_key().to_string()], message);
         assert_eq!(map["key"], "value".to_string());
     }
+
+    #[test]
+    fn firehose_encode_event_newline_delimited() {
+        let event = encode_event("hello".into(), &Encoding::Text.into(), true).unwrap();
+        assert_eq!(&event.data[..], b"hello\n");
+    }
+
+    #[test]
+    fn firehose_drops_records_too_large() {
+        let message = "a".repeat(MAX_RECORD_BYTES + 1);
+        assert!(encode_event(message.into(), &Encoding::Text.into(), false).is_none());
+    }
+
+    #[test]
+    fn firehose_batch_stays_within_bytes_limit() {
+        let record = |size| Record {
+            data: Bytes::from(vec![b'a'; size]),
+        };
+        let mut batch = RecordBatch::default();
+        batch.push(record(MAX_RECORD_BYTES));
+        batch.push(record(MAX_RECORD_BYTES));
+        batch.push(record(MAX_RECORD_BYTES));
+        batch.push(record(MAX_RECORD_BYTES));
+        assert!(!batch.would_overflow(&record(MAX_BATCH_BYTES - 4 * MAX_RECORD_BYTES)));
+        assert!(batch.would_overflow(&record(MAX_BATCH_BYTES - 4 * MAX_RECORD_BYTES + 1)));
+    }
+
+    #[test]
+    fn firehose_retries_failed_records() {
+        let records = vec!["a", "b", "c"]
+            .into_iter()
+            .map(|data| Record { data: data.into() })
+            .collect::<Vec<_>>();
+        let entry = |error_code: Option<&str>| PutRecordBatchResponseEntry {
+            error_code: error_code.map(Into::into),
+            error_message: error_code.map(|_| "Slow down.".into()),
+            record_id: None,
+        };
+        let output = PutRecordBatchOutput {
+            encrypted: None,
+            failed_put_count: 2,
+            request_responses: vec![
+                entry(Some("ServiceUnavailableException")),
+                entry(None),
+                entry(Some("ServiceUnavailableException")),
+            ],
+        };
+
+        let failed = failed_records(records, &output);
+        assert_eq!(failed.len(), 2);
+        assert_eq!(&failed[0].data[..], b"a");
+        assert_eq!(&failed[1].data[..], b"c");
+        assert!(matches!(
+            KinesisFirehoseRetryLogic.should_retry_response(&output),
+            RetryAction::Retry(_)
+        ));
+    }
 }
 
 #[cfg(feature = "aws-kinesis-firehose-integration-tests")]
@@ -292,6 +487,7 @@ mod integration_tests {
             stream_name: stream.clone(),
             region: RegionOrEndpoint::with_endpoint("http://localhost:4573".into()),
             encoding: EncodingConfig::from(Encoding::Json), // required for ES destination w/ localstack
+            newline_delimited: false,
             batch: BatchEventsConfig {
                 max_events: Some(2),
                 timeout_secs: None,
@@ -317,7 +513,7 @@ mod integration_tests {
         thread::sleep(Duration::from_secs(1));
 
         let config = ElasticSearchConfig {
-            auth: Some(ElasticSearchAuth::Aws),
+            auth: Some(ElasticSearchAuth::Aws { service: None }),
             host: "http://localhost:4571".into(),
             index: Some(stream.clone()),
             ..Default::default()
@@ -5,18 +5,25 @@ use crate::{
     serde::to_string,
     sinks::util::{
         encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+        manifest::{
+            event_timestamp, with_timestamp, write_manifests, Manifest, ManifestConfig,
+            ManifestEntry, ObjectStats, StatsBuffer,
+        },
         retries::RetryLogic,
-        rusoto, BatchBytesConfig, Buffer, Compression, PartitionBatchSink, PartitionBuffer,
+        rusoto,
+        watermark::{write_watermarks, WatermarkConfig, Watermarks},
+        BatchBytesConfig, Buffer, Compression, Partition, PartitionBatchSink, PartitionBuffer,
         PartitionInnerBuffer, ServiceBuilderExt, TowerRequestConfig,
     },
-    template::Template,
+    template::{Template, TimeZone},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use bytes::Bytes;
 use chrono::Utc;
+use futures::{compat::Future01CompatExt, future::BoxFuture, FutureExt, TryFutureExt};
 use futures01::{stream::iter_ok, Future, Poll, Sink};
 use lazy_static::lazy_static;
-use rusoto_core::{Region, RusotoError, RusotoFuture};
+use rusoto_core::{Region, RusotoError};
 use rusoto_s3::{
     HeadBucketRequest, PutObjectError, PutObjectOutput, PutObjectRequest, S3Client, S3,
 };
@@ -26,12 +33,18 @@ use std::collections::BTreeMap;
 use std::convert::TryInto;
 use tower::{Service, ServiceBuilder};
 use tracing::field;
-use tracing_futures::{Instrument, Instrumented};
+use tracing_futures::Instrument;
 use uuid::Uuid;
 
+mod multipart;
+
+use multipart::{MultipartConfig, MultipartSink, MIN_PART_SIZE};
+
 #[derive(Clone)]
 pub struct S3Sink {
     client: S3Client,
+    manifest: Option<Manifest>,
+    watermarks: Option<Watermarks>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Default)]
@@ -42,6 +55,9 @@ pub struct S3SinkConfig {
     pub filename_time_format: Option<String>,
     pub filename_append_uuid: Option<bool>,
     pub filename_extension: Option<String>,
+    /// The time zone of the dates in keys, UTC by default.
+    #[serde(default)]
+    pub timezone: TimeZone,
     #[serde(flatten)]
     options: S3Options,
     #[serde(flatten)]
@@ -58,6 +74,13 @@ pub struct S3SinkConfig {
     #[serde(default)]
     pub request: TowerRequestConfig,
     pub assume_role: Option<String>,
+    /// Uploads objects in parts as their batches fill instead of buffering
+    /// them whole, `batch.max_size` then bounding the size of objects.
+    pub multipart: Option<MultipartConfig>,
+    /// Periodically writes manifests listing the objects written.
+    pub manifest: Option<ManifestConfig>,
+    /// Periodically writes the watermarks of the partitions written to.
+    pub watermark: Option<WatermarkConfig>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -133,7 +156,10 @@ inventory::submit! {
 impl SinkConfig for S3SinkConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         let healthcheck = S3Sink::healthcheck(self, cx.resolver())?;
-        let sink = S3Sink::new(self, cx)?;
+        let sink = match &self.multipart {
+            Some(multipart) => MultipartSink::new(self, multipart, cx)?,
+            None => S3Sink::new(self, cx)?,
+        };
 
         Ok((sink, healthcheck))
     }
@@ -147,6 +173,16 @@ impl SinkConfig for S3SinkConfig {
     }
 }
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display(
+        "multipart.part_size must be at least {} bytes, not {}",
+        MIN_PART_SIZE,
+        part_size
+    ))]
+    PartSizeTooSmall { part_size: usize },
+}
+
 #[derive(Debug, Snafu)]
 enum HealthcheckError {
     #[snafu(display("Invalid credentials"))]
@@ -171,13 +207,25 @@ impl S3Sink {
             Template::from(kp.as_str())
         } else {
             Template::from("date=%F/")
-        };
+        }
+        .with_timezone(config.timezone);
+        let timezone = config.timezone;
 
         let region = config.region.clone().try_into()?;
+        let client = Self::create_client(region, config.assume_role.clone(), cx.resolver())?;
 
         let s3 = S3Sink {
-            client: Self::create_client(region, config.assume_role.clone(), cx.resolver())?,
+            manifest: config
+                .manifest
+                .as_ref()
+                .map(|manifest| spawn_manifests(&cx, &client, &config.bucket, manifest)),
+            watermarks: config
+                .watermark
+                .as_ref()
+                .map(|watermark| spawn_watermarks(&cx, &client, &config.bucket, watermark)),
+            client,
         };
+        let watermarks = s3.watermarks.clone();
 
         let filename_extension = config.filename_extension.clone();
         let bucket = config.bucket.clone();
@@ -188,6 +236,7 @@ impl S3Sink {
                 build_request(
                     req,
                     filename_time_format.clone(),
+                    timezone,
                     filename_extension.clone(),
                     filename_append_uuid,
                     compression,
@@ -198,10 +247,17 @@ impl S3Sink {
             .settings(request, S3RetryLogic)
             .service(s3);
 
-        let buffer = PartitionBuffer::new(Buffer::new(config.compression));
+        let buffer = PartitionBuffer::new(StatsBuffer::new(Buffer::new(config.compression)));
 
         let sink = PartitionBatchSink::new(svc, buffer, batch, cx.acker())
-            .with_flat_map(move |e| iter_ok(encode_event(e, &key_prefix, &encoding)))
+            .with_flat_map(move |e| {
+                let timestamp = event_timestamp(&e);
+                let encoded = encode_event(e, &key_prefix, &encoding);
+                if let (Some(watermarks), Some(encoded)) = (&watermarks, &encoded) {
+                    watermarks.received(&encoded.partition(), timestamp);
+                }
+                iter_ok(encoded.map(|encoded| with_timestamp(encoded, timestamp)))
+            })
             .sink_map_err(|error| error!("Sink failed to flush: {}", error));
 
         Ok(Box::new(sink))
@@ -259,10 +315,65 @@ impl S3Sink {
     }
 }
 
+/// Writes the manifests of the objects of a sink to its bucket.
+fn spawn_manifests(
+    cx: &SinkContext,
+    client: &S3Client,
+    bucket: &str,
+    config: &ManifestConfig,
+) -> Manifest {
+    let manifest = Manifest::default();
+    cx.executor().spawn_std(write_manifests(
+        manifest.clone(),
+        config.clone(),
+        put_json(client, bucket),
+    ));
+    manifest
+}
+
+/// Writes the watermarks of the partitions of a sink to its bucket.
+fn spawn_watermarks(
+    cx: &SinkContext,
+    client: &S3Client,
+    bucket: &str,
+    config: &WatermarkConfig,
+) -> Watermarks {
+    let watermarks = Watermarks::default();
+    cx.executor().spawn_std(write_watermarks(
+        watermarks.clone(),
+        config.clone(),
+        "aws_s3",
+        put_json(client, bucket),
+    ));
+    watermarks
+}
+
+fn put_json(
+    client: &S3Client,
+    bucket: &str,
+) -> impl Fn(String, Vec<u8>) -> BoxFuture<'static, crate::Result<()>> {
+    let client = client.clone();
+    let bucket = bucket.to_owned();
+    move |key, body| {
+        client
+            .put_object(PutObjectRequest {
+                body: Some(body.into()),
+                bucket: bucket.clone(),
+                key,
+                content_type: Some("application/json".into()),
+                ..Default::default()
+            })
+            .compat()
+            .map_ok(|_| ())
+            .err_into::<crate::Error>()
+            .boxed()
+    }
+}
+
 impl Service<Request> for S3Sink {
     type Response = PutObjectOutput;
     type Error = RusotoError<PutObjectError>;
-    type Future = Instrumented<RusotoFuture<PutObjectOutput, PutObjectError>>;
+    type Future = Box<dyn Future<Item = PutObjectOutput, Error = Self::Error> + Send>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         Ok(().into())
@@ -270,14 +381,21 @@ impl Service<Request> for S3Sink {
 
     fn call(&mut self, request: Request) -> Self::Future {
         let options = request.options;
-        let mut tagging = url::form_urlencoded::Serializer::new(String::new());
-        if let Some(tags) = options.tags {
-            for (p, v) in tags {
-                tagging.append_pair(&p, &v);
+        let tagging = tagging(options.tags);
+        let written = match &self.manifest {
+            Some(manifest) => {
+                let entry =
+                    ManifestEntry::new(request.key.clone(), &request.body, request.stats.clone());
+                Some((manifest.clone(), entry))
             }
-        }
-        let tagging = tagging.finish();
-        self.client
+            None => None,
+        };
+        let watermark = match &self.watermarks {
+            Some(watermarks) => Some((watermarks.clone(), request.partition, request.stats)),
+            None => None,
+        };
+        let response = self
+            .client
             .put_object(PutObjectRequest {
                 body: Some(request.body.into()),
                 bucket: request.bucket,
@@ -295,35 +413,39 @@ impl Service<Request> for S3Sink {
                 ..Default::default()
             })
             .instrument(info_span!("request"))
+            .map(move |output| {
+                if let Some((manifest, entry)) = written {
+                    manifest.record(entry);
+                }
+                if let Some((watermarks, partition, stats)) = watermark {
+                    watermarks.written(&partition, &stats);
+                }
+                output
+            });
+        Box::new(response)
     }
 }
 
 fn build_request(
-    req: PartitionInnerBuffer<Vec<u8>, Bytes>,
+    req: PartitionInnerBuffer<(Vec<u8>, ObjectStats), Bytes>,
     time_format: String,
+    timezone: TimeZone,
     extension: Option<String>,
     uuid: bool,
     compression: Compression,
     bucket: String,
     options: S3Options,
 ) -> Request {
-    let (inner, key) = req.into_parts();
-
-    // TODO: pull the seconds from the last event
-    let filename = {
-        let seconds = Utc::now().format(&time_format);
-
-        if uuid {
-            let uuid = Uuid::new_v4();
-            format!("{}-{}", seconds, uuid.to_hyphenated())
-        } else {
-            seconds.to_string()
-        }
-    };
-
-    let extension = extension.unwrap_or_else(|| compression.extension().into());
-    let key = String::from_utf8_lossy(&key[..]).into_owned();
-    let key = format!("{}{}.{}", key, filename, extension);
+    let ((inner, stats), partition) = req.into_parts();
+
+    let key = object_key(
+        &partition,
+        &time_format,
+        timezone,
+        extension.as_deref(),
+        uuid,
+        compression,
+    );
 
     debug!(
         message = "sending events.",
@@ -334,6 +456,8 @@ fn build_request(
 
     Request {
         body: inner,
+        stats,
+        partition,
         bucket,
         key,
         content_encoding: compression.content_encoding().map(|ce| ce.to_string()),
@@ -341,9 +465,48 @@ fn build_request(
     }
 }
 
+/// The key of a new object, named after the time it is written at below the
+/// prefix its events were partitioned by.
+fn object_key(
+    prefix: &[u8],
+    time_format: &str,
+    timezone: TimeZone,
+    extension: Option<&str>,
+    uuid: bool,
+    compression: Compression,
+) -> String {
+    // TODO: pull the seconds from the last event
+    let filename = {
+        let seconds = timezone.format(Utc::now(), time_format);
+
+        if uuid {
+            let uuid = Uuid::new_v4();
+            format!("{}-{}", seconds, uuid.to_hyphenated())
+        } else {
+            seconds
+        }
+    };
+
+    let extension = extension.unwrap_or_else(|| compression.extension());
+    let prefix = String::from_utf8_lossy(prefix);
+    format!("{}{}.{}", prefix, filename, extension)
+}
+
+fn tagging(tags: Option<BTreeMap<String, String>>) -> String {
+    let mut tagging = url::form_urlencoded::Serializer::new(String::new());
+    if let Some(tags) = tags {
+        for (p, v) in tags {
+            tagging.append_pair(&p, &v);
+        }
+    }
+    tagging.finish()
+}
+
 #[derive(Debug, Clone)]
 struct Request {
     body: Vec<u8>,
+    stats: ObjectStats,
+    partition: Bytes,
     bucket: String,
     key: String,
     content_encoding: Option<String>,
@@ -358,11 +521,15 @@ impl RetryLogic for S3RetryLogic {
     type Response = PutObjectOutput;
 
     fn is_retriable_error(&self, error: &Self::Error) -> bool {
-        match error {
-            RusotoError::HttpDispatch(_) => true,
-            RusotoError::Unknown(res) if res.status.is_server_error() => true,
-            _ => false,
-        }
+        is_retriable(error)
+    }
+}
+
+fn is_retriable<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(res) if res.status.is_server_error() => true,
+        _ => false,
     }
 }
 
@@ -469,11 +636,13 @@ mod tests {
 
     #[test]
     fn s3_build_request() {
-        let buf = PartitionInnerBuffer::new(vec![0u8; 10], Bytes::from("key/"));
+        let buf =
+            PartitionInnerBuffer::new((vec![0u8; 10], ObjectStats::default()), Bytes::from("key/"));
 
         let req = build_request(
             buf.clone(),
             "date".into(),
+            TimeZone::default(),
             Some("ext".into()),
             false,
             Compression::None,
@@ -485,6 +654,7 @@ mod tests {
         let req = build_request(
             buf.clone(),
             "date".into(),
+            TimeZone::default(),
             None,
             false,
             Compression::None,
@@ -496,6 +666,7 @@ mod tests {
         let req = build_request(
             buf.clone(),
             "date".into(),
+            TimeZone::default(),
             None,
             false,
             Compression::Gzip,
@@ -507,6 +678,7 @@ mod tests {
         let req = build_request(
             buf.clone(),
             "date".into(),
+            TimeZone::default(),
             None,
             true,
             Compression::Gzip,
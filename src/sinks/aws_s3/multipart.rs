@@ -0,0 +1,473 @@
+//! Uploads of objects in parts as their events arrive, so that objects far
+//! larger than what is kept in memory can be written.
+//!
+//! Every part is compressed on its own; gzip members concatenate into a
+//! valid gzip file.
+
+use super::{
+    encode_event, is_retriable, object_key, spawn_manifests, spawn_watermarks, tagging, BuildError,
+    Encoding, S3Options, S3Sink, S3SinkConfig, REQUEST_DEFAULTS,
+};
+use crate::{
+    internal_events::{S3MultipartUploadAborted, S3ObjectUploaded, S3PartUploadFailed},
+    serde::to_string,
+    sinks::{
+        streaming_sink::{self, StreamingSink},
+        util::{
+            encoding::EncodingConfigWithDefault,
+            manifest::{event_timestamp, Checksum, Manifest, ObjectStats},
+            watermark::Watermarks,
+            Batch, Buffer, Compression, StreamSink, TowerRequestSettings,
+        },
+        RouterSink,
+    },
+    template::{Template, TimeZone},
+    topology::config::SinkContext,
+    Event,
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{compat::Future01CompatExt, pin_mut, Stream, StreamExt};
+use rusoto_core::RusotoFuture;
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, S3Client, UploadPartRequest, S3,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    error::Error,
+    mem,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, interval, timeout};
+
+/// S3 doesn't accept smaller parts, except for the last part of an object.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// Nor more parts than these to an object.
+const MAX_PARTS: usize = 10_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MultipartConfig {
+    #[serde(default = "default_part_size")]
+    pub part_size: usize,
+}
+
+fn default_part_size() -> usize {
+    8 * 1024 * 1024
+}
+
+pub struct MultipartSink {
+    client: S3Client,
+    bucket: String,
+    options: S3Options,
+    key_prefix: Template,
+    encoding: EncodingConfigWithDefault<Encoding>,
+    compression: Compression,
+    filename_time_format: String,
+    filename_append_uuid: bool,
+    filename_extension: Option<String>,
+    timezone: TimeZone,
+    part_size: usize,
+    max_object_size: usize,
+    object_timeout: Duration,
+    request: TowerRequestSettings,
+    manifest: Option<Manifest>,
+    watermarks: Option<Watermarks>,
+    uploads: HashMap<Bytes, Upload>,
+}
+
+/// An object being uploaded, with the part not uploaded yet.
+struct Upload {
+    partition: Bytes,
+    key: String,
+    upload_id: String,
+    started: Instant,
+    part: Buffer,
+    parts: Vec<CompletedPart>,
+    byte_size: usize,
+    stats: ObjectStats,
+    checksum: Checksum,
+}
+
+impl MultipartSink {
+    pub fn new(
+        config: &S3SinkConfig,
+        multipart: &MultipartConfig,
+        mut cx: SinkContext,
+    ) -> crate::Result<RouterSink> {
+        if multipart.part_size < MIN_PART_SIZE {
+            return Err(BuildError::PartSizeTooSmall {
+                part_size: multipart.part_size,
+            }
+            .into());
+        }
+        let batch = config.batch.unwrap_or(bytesize::mib(10u64), 300);
+        let client = S3Sink::create_client(
+            config.region.clone().try_into()?,
+            config.assume_role.clone(),
+            cx.resolver(),
+        )?;
+
+        let sink = MultipartSink {
+            manifest: config
+                .manifest
+                .as_ref()
+                .map(|manifest| spawn_manifests(&cx, &client, &config.bucket, manifest)),
+            watermarks: config
+                .watermark
+                .as_ref()
+                .map(|watermark| spawn_watermarks(&cx, &client, &config.bucket, watermark)),
+            client,
+            bucket: config.bucket.clone(),
+            options: config.options.clone(),
+            key_prefix: Template::from(config.key_prefix.as_deref().unwrap_or("date=%F/"))
+                .with_timezone(config.timezone),
+            encoding: config.encoding.clone(),
+            compression: config.compression,
+            filename_time_format: config
+                .filename_time_format
+                .clone()
+                .unwrap_or_else(|| "%s".into()),
+            filename_append_uuid: config.filename_append_uuid.unwrap_or(true),
+            filename_extension: config.filename_extension.clone(),
+            timezone: config.timezone,
+            part_size: multipart.part_size,
+            max_object_size: batch.size,
+            object_timeout: batch.timeout,
+            request: config.request.unwrap_with(&REQUEST_DEFAULTS),
+            uploads: HashMap::new(),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+        Ok(Box::new(sink))
+    }
+
+    async fn process_event(&mut self, event: Event) {
+        let timestamp = event_timestamp(&event);
+        let (bytes, prefix) = match encode_event(event, &self.key_prefix, &self.encoding) {
+            Some(encoded) => encoded.into_parts(),
+            None => return,
+        };
+        if let Some(watermarks) = &self.watermarks {
+            watermarks.received(&prefix, timestamp);
+        }
+
+        let mut upload = match self.uploads.remove(&prefix) {
+            Some(upload) => upload,
+            None => match self.create_upload(&prefix).await {
+                Ok(upload) => upload,
+                Err(error) => {
+                    error!(
+                        message = "Unable to start multipart upload; dropping event.",
+                        %error,
+                        rate_limit_secs = 30,
+                    );
+                    return;
+                }
+            },
+        };
+        upload.part.push(&bytes);
+        upload.stats.add(timestamp);
+        if upload.part.len() >= self.part_size {
+            if let Err(error) = self.upload_part(&mut upload).await {
+                self.abort(upload, error).await;
+                return;
+            }
+        }
+
+        if upload.byte_size + upload.part.len() >= self.max_object_size
+            || upload.parts.len() + 1 >= MAX_PARTS
+        {
+            self.complete(upload).await;
+        } else {
+            self.uploads.insert(prefix, upload);
+        }
+    }
+
+    /// Completes the uploads started longer than the batch timeout ago.
+    async fn complete_expired(&mut self) {
+        let object_timeout = self.object_timeout;
+        let expired = self
+            .uploads
+            .iter()
+            .filter(|(_, upload)| upload.started.elapsed() >= object_timeout)
+            .map(|(prefix, _)| prefix.clone())
+            .collect::<Vec<_>>();
+        for prefix in expired {
+            if let Some(upload) = self.uploads.remove(&prefix) {
+                self.complete(upload).await;
+            }
+        }
+    }
+
+    async fn create_upload(&self, prefix: &Bytes) -> crate::Result<Upload> {
+        let key = object_key(
+            prefix,
+            &self.filename_time_format,
+            self.timezone,
+            self.filename_extension.as_deref(),
+            self.filename_append_uuid,
+            self.compression,
+        );
+        let options = self.options.clone();
+        let request = CreateMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: key.clone(),
+            content_encoding: self.compression.content_encoding().map(Into::into),
+            acl: options.acl.map(to_string),
+            grant_full_control: options.grant_full_control,
+            grant_read: options.grant_read,
+            grant_read_acp: options.grant_read_acp,
+            grant_write_acp: options.grant_write_acp,
+            server_side_encryption: options.server_side_encryption.map(to_string),
+            ssekms_key_id: options.ssekms_key_id,
+            storage_class: options.storage_class.map(to_string),
+            tagging: Some(tagging(options.tags)),
+            ..Default::default()
+        };
+        let output = self
+            .send(|| self.client.create_multipart_upload(request.clone()))
+            .await?;
+        let upload_id = output
+            .upload_id
+            .ok_or("S3 returned no upload id for the multipart upload")?;
+
+        debug!(message = "started multipart upload.", bucket = %self.bucket, %key);
+        Ok(Upload {
+            partition: prefix.clone(),
+            key,
+            upload_id,
+            started: Instant::now(),
+            part: Buffer::new(self.compression),
+            parts: Vec::new(),
+            byte_size: 0,
+            stats: ObjectStats::default(),
+            checksum: Checksum::default(),
+        })
+    }
+
+    /// Uploads the part buffered, retrying it on its own if it fails.
+    async fn upload_part(&self, upload: &mut Upload) -> crate::Result<()> {
+        let body = mem::replace(&mut upload.part, Buffer::new(self.compression)).finish();
+        let part_number = upload.parts.len() as i64 + 1;
+        let request = UploadPartRequest {
+            bucket: self.bucket.clone(),
+            key: upload.key.clone(),
+            upload_id: upload.upload_id.clone(),
+            part_number,
+            content_length: Some(body.len() as i64),
+            ..Default::default()
+        };
+
+        let key = &upload.key;
+        let output = self
+            .send_with(
+                || {
+                    self.client.upload_part(UploadPartRequest {
+                        body: Some(body.clone().into()),
+                        ..request.clone()
+                    })
+                },
+                |error, will_retry| {
+                    emit!(S3PartUploadFailed {
+                        key,
+                        part_number,
+                        error,
+                        will_retry,
+                    })
+                },
+            )
+            .await?;
+
+        upload.byte_size += body.len();
+        upload.checksum.update(&body);
+        upload.parts.push(CompletedPart {
+            e_tag: output.e_tag,
+            part_number: Some(part_number),
+        });
+        Ok(())
+    }
+
+    /// Uploads the last part and completes the object, aborting the upload
+    /// if either fails so that S3 doesn't keep the parts around.
+    async fn complete(&self, mut upload: Upload) {
+        if upload.part.num_items() > 0 {
+            if let Err(error) = self.upload_part(&mut upload).await {
+                return self.abort(upload, error).await;
+            }
+        }
+        if upload.parts.is_empty() {
+            return self.abort(upload, "no events were written".into()).await;
+        }
+
+        let request = CompleteMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: upload.key.clone(),
+            upload_id: upload.upload_id.clone(),
+            multipart_upload: Some(CompletedMultipartUpload {
+                parts: Some(upload.parts.clone()),
+            }),
+            ..Default::default()
+        };
+        match self
+            .send(|| self.client.complete_multipart_upload(request.clone()))
+            .await
+        {
+            Ok(_) => {
+                emit!(S3ObjectUploaded {
+                    key: &upload.key,
+                    parts: upload.parts.len(),
+                    byte_size: upload.byte_size,
+                });
+                if let Some(watermarks) = &self.watermarks {
+                    watermarks.written(&upload.partition, &upload.stats);
+                }
+                if let Some(manifest) = &self.manifest {
+                    manifest.record(upload.checksum.finish(
+                        upload.key,
+                        upload.byte_size,
+                        upload.stats,
+                    ));
+                }
+            }
+            Err(error) => self.abort(upload, error).await,
+        }
+    }
+
+    async fn abort(&self, upload: Upload, error: crate::Error) {
+        emit!(S3MultipartUploadAborted {
+            key: &upload.key,
+            error: &error,
+        });
+        let request = AbortMultipartUploadRequest {
+            bucket: self.bucket.clone(),
+            key: upload.key.clone(),
+            upload_id: upload.upload_id.clone(),
+            ..Default::default()
+        };
+        if let Err(error) = self
+            .send(|| self.client.abort_multipart_upload(request.clone()))
+            .await
+        {
+            error!(
+                message = "Unable to abort multipart upload, its parts are left in the bucket.",
+                key = %upload.key,
+                upload_id = %upload.upload_id,
+                %error,
+            );
+        }
+    }
+
+    async fn send<T, E>(&self, request: impl Fn() -> RusotoFuture<T, E>) -> crate::Result<T>
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        self.send_with(request, |error, will_retry| {
+            if will_retry {
+                warn!(message = "S3 request failed; retrying.", %error, rate_limit_secs = 30);
+            }
+        })
+        .await
+    }
+
+    /// Sends the request, retrying it with backoff as the request settings
+    /// of the sink allow.
+    async fn send_with<T, E>(
+        &self,
+        request: impl Fn() -> RusotoFuture<T, E>,
+        on_error: impl Fn(&dyn Error, bool),
+    ) -> crate::Result<T>
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        let mut backoff = self.request.retry_initial_backoff_secs;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error: crate::Error = match timeout(self.request.timeout, request().compat()).await
+            {
+                Ok(Ok(output)) => return Ok(output),
+                Ok(Err(error)) if !is_retriable(&error) => {
+                    on_error(&error, false);
+                    return Err(error.into());
+                }
+                Ok(Err(error)) => error.into(),
+                Err(elapsed) => elapsed.into(),
+            };
+            let will_retry = attempts < self.request.retry_attempts;
+            on_error(&*error, will_retry);
+            if !will_retry {
+                return Err(error);
+            }
+            delay_for(backoff).await;
+            backoff = (backoff * 2).min(self.request.retry_max_duration_secs);
+        }
+    }
+}
+
+#[async_trait]
+impl StreamingSink for MultipartSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        let mut expire = interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                event = input.next() => match event {
+                    Some(event) => self.process_event(event).await,
+                    None => break,
+                },
+                _ = expire.tick() => self.complete_expired().await,
+            }
+        }
+
+        // Objects left incomplete would never show up in the bucket.
+        for (_, upload) in mem::replace(&mut self.uploads, HashMap::new()) {
+            self.complete(upload).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn parts_concatenate_into_one_gzip_object() {
+        let mut object = Vec::new();
+        for line in &["first part\n", "second part\n"] {
+            let mut part = Buffer::new(Compression::Gzip);
+            part.push(line.as_bytes());
+            object.extend(part.finish());
+        }
+
+        let mut decoded = String::new();
+        MultiGzDecoder::new(&object[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "first part\nsecond part\n");
+    }
+
+    #[test]
+    fn rejects_parts_smaller_than_s3_accepts() {
+        let config = S3SinkConfig {
+            bucket: "bucket".into(),
+            multipart: Some(MultipartConfig { part_size: 1024 }),
+            ..Default::default()
+        };
+        let cx = SinkContext::new_test(runtime().executor());
+        match MultipartSink::new(&config, config.multipart.as_ref().unwrap(), cx) {
+            Err(error) => assert!(error.to_string().starts_with("multipart.part_size")),
+            Ok(_) => panic!("part size was accepted"),
+        }
+    }
+}
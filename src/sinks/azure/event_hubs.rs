@@ -0,0 +1,315 @@
+use super::{AzureAuthConfig, AzureCredentials};
+use crate::{
+    event::{self, Event},
+    sinks::{
+        util::{
+            encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+            http2::{BatchedHttpSink, HttpClient, HttpSink},
+            service2::TowerRequestConfig,
+            BatchBytesConfig, BoxedRawValue, JsonArrayBuffer,
+        },
+        Healthcheck, RouterSink, UriParseError2,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures::{FutureExt, TryFutureExt};
+use futures01::Sink;
+use http02::{Method, Request, StatusCode, Uri};
+use hyper13::Body;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use snafu::{ResultExt, Snafu};
+use string_cache::DefaultAtom as Atom;
+
+const API_VERSION: &str = "2014-01";
+
+const CONTENT_TYPE: &str = "application/vnd.microsoft.servicebus.json";
+
+#[derive(Debug, Snafu)]
+enum HealthcheckError {
+    #[snafu(display("Configured event hub not found"))]
+    EventHubNotFound,
+    #[snafu(display("Credentials were rejected, reading the event hub needs the `Manage` claim"))]
+    Unauthorized,
+    #[snafu(display("Unexpected status: {}", status))]
+    UnexpectedStatus { status: StatusCode },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct EventHubsConfig {
+    pub namespace: Option<String>,
+    pub event_hub_name: Option<String>,
+    #[serde(flatten)]
+    pub auth: AzureAuthConfig,
+    pub partition_key_field: Option<Atom>,
+
+    #[serde(default)]
+    pub batch: BatchBytesConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub encoding: EncodingConfigWithDefault<Encoding>,
+
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum Encoding {
+    #[derivative(Default)]
+    Json,
+    Text,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<EventHubsConfig>("azure_event_hubs")
+}
+
+#[typetag::serde(name = "azure_event_hubs")]
+impl SinkConfig for EventHubsConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let sink = EventHubsSink::from_config(self)?;
+        // Standard tier event hubs reject batches larger than 1MB, leave
+        // some room for the JSON envelope of each message.
+        let batch_settings = self.batch.unwrap_or(bytesize::kib(768u64), 1);
+        let request_settings = self.request.unwrap_with(&Default::default());
+        let tls_settings = TlsSettings::from_options(&self.tls)?;
+
+        let healthcheck = healthcheck(
+            cx.clone(),
+            sink.uri(&format!("?api-version={}", API_VERSION))?,
+            sink.resource_uri.clone(),
+            tls_settings.clone(),
+            sink.creds.clone(),
+        )
+        .boxed()
+        .compat();
+
+        let sink = BatchedHttpSink::new(
+            sink,
+            JsonArrayBuffer::default(),
+            request_settings,
+            batch_settings,
+            Some(tls_settings),
+            &cx,
+        )
+        .sink_map_err(|e| error!("Fatal azure event hubs sink error: {}", e));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "azure_event_hubs"
+    }
+}
+
+struct EventHubsSink {
+    creds: AzureCredentials,
+    resource_uri: String,
+    partition_key_field: Option<Atom>,
+    encoding: EncodingConfigWithDefault<Encoding>,
+}
+
+impl EventHubsSink {
+    fn from_config(config: &EventHubsConfig) -> crate::Result<Self> {
+        let (host, event_hub_name) = config.auth.event_hub(
+            config.namespace.as_deref(),
+            config.event_hub_name.as_deref(),
+        )?;
+
+        Ok(Self {
+            creds: config.auth.make_credentials()?,
+            resource_uri: format!("https://{}/{}", host, event_hub_name),
+            partition_key_field: config.partition_key_field.clone(),
+            encoding: config.encoding.clone(),
+        })
+    }
+
+    fn uri(&self, suffix: &str) -> crate::Result<Uri> {
+        format!("{}{}", self.resource_uri, suffix)
+            .parse::<Uri>()
+            .context(UriParseError2)
+            .map_err(Into::into)
+    }
+}
+
+impl HttpSink for EventHubsSink {
+    type Input = Value;
+    type Output = Vec<BoxedRawValue>;
+
+    fn encode_event(&self, mut event: Event) -> Option<Self::Input> {
+        self.encoding.apply_rules(&mut event);
+        let log = event.into_log();
+
+        let partition_key = self
+            .partition_key_field
+            .as_ref()
+            .and_then(|field| log.get(field))
+            .map(|value| value.to_string_lossy());
+
+        let body = match self.encoding.codec() {
+            Encoding::Json => serde_json::to_string(&log).unwrap(),
+            Encoding::Text => log
+                .get(&event::log_schema().message_key())
+                .map(|value| value.to_string_lossy())
+                .unwrap_or_default(),
+        };
+
+        let mut message = json!({ "Body": body });
+        if let Some(partition_key) = partition_key {
+            message["BrokerProperties"] = json!({ "PartitionKey": partition_key });
+        }
+
+        Some(message)
+    }
+
+    fn build_request(&self, events: Self::Output) -> Request<Vec<u8>> {
+        let body = serde_json::to_vec(&events).unwrap();
+        let uri = self
+            .uri(&format!("/messages?timeout=60&api-version={}", API_VERSION))
+            .unwrap();
+
+        Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("Content-Type", CONTENT_TYPE)
+            .header(
+                "Authorization",
+                self.creds.authorization(&self.resource_uri),
+            )
+            .body(body)
+            .unwrap()
+    }
+}
+
+async fn healthcheck(
+    cx: SinkContext,
+    uri: Uri,
+    resource_uri: String,
+    tls: TlsSettings,
+    creds: AzureCredentials,
+) -> crate::Result<()> {
+    let request = Request::get(uri)
+        .header("Authorization", creds.authorization(&resource_uri))
+        .body(Body::empty())
+        .unwrap();
+
+    let mut client = HttpClient::new(cx.resolver(), tls)?;
+    let response = client.send(request).await?;
+
+    match response.status() {
+        StatusCode::OK => {
+            creds.spawn_regenerate_token();
+            Ok(())
+        }
+        StatusCode::UNAUTHORIZED => Err(HealthcheckError::Unauthorized.into()),
+        StatusCode::NOT_FOUND => Err(HealthcheckError::EventHubNotFound.into()),
+        status => Err(HealthcheckError::UnexpectedStatus { status }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    const CONNECTION_STRING: &str = "Endpoint=sb://vector.servicebus.windows.net/;SharedAccessKeyName=send;SharedAccessKey=secret;EntityPath=logs";
+
+    fn sink(config: &str) -> EventHubsSink {
+        let (config, _, _) = load_sink::<EventHubsConfig>(config).unwrap();
+        EventHubsSink::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn azure_event_hubs_from_connection_string() {
+        let sink = sink(&format!(r#"connection_string = "{}""#, CONNECTION_STRING));
+
+        assert_eq!(
+            sink.resource_uri,
+            "https://vector.servicebus.windows.net/logs"
+        );
+        match sink.creds {
+            AzureCredentials::SharedAccessKey { key_name, key } => {
+                assert_eq!(key_name, "send");
+                assert_eq!(key, "secret");
+            }
+            _ => panic!("expected shared access key credentials"),
+        }
+    }
+
+    #[test]
+    fn azure_event_hubs_requires_event_hub_name() {
+        let (config, _, _) = load_sink::<EventHubsConfig>(
+            r#"
+            namespace = "vector"
+            shared_access_key_name = "send"
+            shared_access_key = "secret"
+        "#,
+        )
+        .unwrap();
+
+        assert!(EventHubsSink::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn azure_event_hubs_encode_event() {
+        let sink = sink(&format!(
+            r#"
+            connection_string = "{}"
+            partition_key_field = "host"
+        "#,
+            CONNECTION_STRING
+        ));
+
+        let mut event = Event::from("hello world");
+        event.as_mut_log().insert("host", "example.com");
+        let message = sink.encode_event(event).unwrap();
+
+        assert_eq!(message["BrokerProperties"]["PartitionKey"], "example.com");
+        let body: Value = serde_json::from_str(message["Body"].as_str().unwrap()).unwrap();
+        assert_eq!(body["message"], "hello world");
+        assert_eq!(body["host"], "example.com");
+    }
+
+    #[test]
+    fn azure_event_hubs_encode_event_text() {
+        let sink = sink(&format!(
+            r#"
+            connection_string = "{}"
+            encoding = "text"
+        "#,
+            CONNECTION_STRING
+        ));
+
+        let message = sink.encode_event(Event::from("hello world")).unwrap();
+
+        assert_eq!(message, json!({ "Body": "hello world" }));
+    }
+
+    #[test]
+    fn azure_event_hubs_build_request() {
+        let sink = sink(&format!(r#"connection_string = "{}""#, CONNECTION_STRING));
+
+        let request = sink.build_request(vec![]);
+
+        assert_eq!(
+            request.uri(),
+            "https://vector.servicebus.windows.net/logs/messages?timeout=60&api-version=2014-01"
+        );
+        assert_eq!(request.headers()["Content-Type"], CONTENT_TYPE);
+        assert!(request.headers()["Authorization"]
+            .to_str()
+            .unwrap()
+            .starts_with("SharedAccessSignature "));
+    }
+}
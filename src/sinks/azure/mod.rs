@@ -0,0 +1,3 @@
+pub use crate::azure::{AzureAuthConfig, AzureCredentials};
+
+pub mod event_hubs;
@@ -17,7 +17,17 @@ use http02::{Method, Request, StatusCode, Uri};
 use hyper13::Body;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
+use snafu::{ResultExt, Snafu};
+
+mod row_binary;
+
+pub use row_binary::{Column, ColumnType};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("The `row_binary` format requires at least one entry in `columns`"))]
+    MissingColumns,
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 #[serde(deny_unknown_fields)]
@@ -27,6 +37,10 @@ pub struct ClickhouseConfig {
     pub database: Option<String>,
     #[serde(default = "Compression::default_gzip")]
     pub compression: Compression,
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub columns: Vec<Column>,
     #[serde(
         skip_serializing_if = "crate::serde::skip_serializing_if_default",
         default
@@ -58,9 +72,22 @@ pub enum Encoding {
     Default,
 }
 
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Copy, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum Format {
+    #[derivative(Default)]
+    JsonEachRow,
+    RowBinary,
+}
+
 #[typetag::serde(name = "clickhouse")]
 impl SinkConfig for ClickhouseConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        if self.format == Format::RowBinary && self.columns.is_empty() {
+            return Err(BuildError::MissingColumns.into());
+        }
+
         let batch = self.batch.unwrap_or(bytesize::mib(10u64), 1);
         let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
         let tls_settings = TlsSettings::from_options(&self.tls)?;
@@ -96,11 +123,29 @@ impl HttpSink for ClickhouseConfig {
     fn encode_event(&self, mut event: Event) -> Option<Self::Input> {
         self.encoding.apply_rules(&mut event);
 
-        let mut body =
-            serde_json::to_vec(&event.as_log().all_fields()).expect("Events should be valid json!");
-        body.push(b'\n');
+        match self.format {
+            Format::JsonEachRow => {
+                let mut body = serde_json::to_vec(&event.as_log().all_fields())
+                    .expect("Events should be valid json!");
+                body.push(b'\n');
+
+                Some(body)
+            }
+            Format::RowBinary => {
+                let mut body = Vec::new();
+                if let Err(error) = row_binary::encode_row(&self.columns, event.as_log(), &mut body)
+                {
+                    warn!(
+                        message = "Event does not fit the configured columns; Dropping event.",
+                        %error,
+                        rate_limit_secs = 30,
+                    );
+                    return None;
+                }
 
-        Some(body)
+                Some(body)
+            }
+        }
     }
 
     fn build_request(&self, events: Self::Output) -> http02::Request<Vec<u8>> {
@@ -110,12 +155,24 @@ impl HttpSink for ClickhouseConfig {
             "default"
         };
 
-        let uri = encode_uri(&self.host, database, &self.table).expect("Unable to encode uri");
+        let uri = encode_uri(
+            &self.host,
+            database,
+            &self.table,
+            self.format,
+            &self.columns,
+        )
+        .expect("Unable to encode uri");
+
+        let content_type = match self.format {
+            Format::JsonEachRow => "application/x-ndjson",
+            Format::RowBinary => "application/octet-stream",
+        };
 
         let mut builder = Request::builder()
             .method(Method::POST)
             .uri(uri.clone())
-            .header("Content-Type", "application/x-ndjson");
+            .header("Content-Type", content_type);
 
         if let Some(ce) = self.compression.content_encoding() {
             builder = builder.header("Content-Encoding", ce);
@@ -151,17 +208,33 @@ async fn healthcheck(resolver: Resolver, config: ClickhouseConfig) -> crate::Res
     }
 }
 
-fn encode_uri(host: &str, database: &str, table: &str) -> crate::Result<Uri> {
+fn encode_uri(
+    host: &str,
+    database: &str,
+    table: &str,
+    format: Format,
+    columns: &[Column],
+) -> crate::Result<Uri> {
+    let insert = match format {
+        Format::JsonEachRow => format!(
+            "INSERT INTO \"{}\".\"{}\" FORMAT JSONEachRow",
+            database,
+            table.replace("\"", "\\\"")
+        ),
+        // The row layout is positional, so the columns have to be spelled out.
+        Format::RowBinary => format!(
+            "INSERT INTO \"{}\".\"{}\" ({}) FORMAT RowBinary",
+            database,
+            table.replace("\"", "\\\""),
+            columns
+                .iter()
+                .map(|column| format!("\"{}\"", column.name.replace("\"", "\\\"")))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    };
     let query = url::form_urlencoded::Serializer::new(String::new())
-        .append_pair(
-            "query",
-            format!(
-                "INSERT INTO \"{}\".\"{}\" FORMAT JSONEachRow",
-                database,
-                table.replace("\"", "\\\"")
-            )
-            .as_str(),
-        )
+        .append_pair("query", &insert)
         .finish();
 
     let url = if host.ends_with('/') {
@@ -216,16 +289,81 @@ mod tests {
 
     #[test]
     fn encode_valid() {
-        let uri = encode_uri("http://localhost:80", "my_database", "my_table").unwrap();
+        let uri = encode_uri(
+            "http://localhost:80",
+            "my_database",
+            "my_table",
+            Format::JsonEachRow,
+            &[],
+        )
+        .unwrap();
         assert_eq!(uri, "http://localhost:80/?query=INSERT+INTO+%22my_database%22.%22my_table%22+FORMAT+JSONEachRow");
 
-        let uri = encode_uri("http://localhost:80", "my_database", "my_\"table\"").unwrap();
+        let uri = encode_uri(
+            "http://localhost:80",
+            "my_database",
+            "my_\"table\"",
+            Format::JsonEachRow,
+            &[],
+        )
+        .unwrap();
         assert_eq!(uri, "http://localhost:80/?query=INSERT+INTO+%22my_database%22.%22my_%5C%22table%5C%22%22+FORMAT+JSONEachRow");
     }
 
     #[test]
     fn encode_invalid() {
-        encode_uri("localhost:80", "my_database", "my_table").unwrap_err();
+        encode_uri(
+            "localhost:80",
+            "my_database",
+            "my_table",
+            Format::JsonEachRow,
+            &[],
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn encode_row_binary_uri() {
+        let config: ClickhouseConfig = toml::from_str(
+            r#"
+host = "http://localhost:8123"
+table = "my_table"
+format = "row_binary"
+[[columns]]
+  name = "timestamp"
+  type = "DateTime64(3)"
+[[columns]]
+  name = "msg"
+  type = "String"
+  field = "message"
+"#,
+        )
+        .unwrap();
+
+        let uri = encode_uri(
+            &config.host,
+            "default",
+            &config.table,
+            config.format,
+            &config.columns,
+        )
+        .unwrap();
+        assert_eq!(uri, "http://localhost:8123/?query=INSERT+INTO+%22default%22.%22my_table%22+%28%22timestamp%22%2C%22msg%22%29+FORMAT+RowBinary");
+    }
+
+    #[test]
+    fn row_binary_rejects_unknown_column_type() {
+        toml::from_str::<ClickhouseConfig>(
+            r#"
+host = "http://localhost:8123"
+table = "my_table"
+format = "row_binary"
+[[columns]]
+  name = "tags"
+  type = "Array(String)"
+"#,
+        )
+        .unwrap_err();
     }
 }
 
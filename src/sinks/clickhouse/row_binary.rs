@@ -0,0 +1,408 @@
+//! Encoding of log events into ClickHouse's `RowBinary` input format.
+//!
+//! `RowBinary` carries no column names or types, so every row has to be
+//! written in exactly the order and representation of the columns listed
+//! in the `INSERT` query. The mapping is given in the sink config.
+
+use crate::event::{LogEvent, Value};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+};
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    /// The event field to read, defaults to the column name.
+    pub field: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum ColumnType {
+    String,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    DateTime(Option<String>),
+    DateTime64(u32, Option<String>),
+    Nullable(Box<ColumnType>),
+    // Has the same representation as the wrapped type in `RowBinary`.
+    LowCardinality(Box<ColumnType>),
+}
+
+impl TryFrom<String> for ColumnType {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ColumnType> for String {
+    fn from(column_type: ColumnType) -> Self {
+        column_type.to_string()
+    }
+}
+
+impl std::str::FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, args) = match s.find('(') {
+            Some(open) if s.ends_with(')') => (&s[..open], Some(&s[open + 1..s.len() - 1])),
+            Some(_) => return Err(format!("Unbalanced parentheses in column type {:?}", s)),
+            None => (s, None),
+        };
+
+        let column_type = match (name, args) {
+            ("String", None) => ColumnType::String,
+            ("UInt8", None) => ColumnType::UInt8,
+            ("UInt16", None) => ColumnType::UInt16,
+            ("UInt32", None) => ColumnType::UInt32,
+            ("UInt64", None) => ColumnType::UInt64,
+            ("Int8", None) => ColumnType::Int8,
+            ("Int16", None) => ColumnType::Int16,
+            ("Int32", None) => ColumnType::Int32,
+            ("Int64", None) => ColumnType::Int64,
+            ("Float32", None) => ColumnType::Float32,
+            ("Float64", None) => ColumnType::Float64,
+            ("DateTime", None) => ColumnType::DateTime(None),
+            ("DateTime", Some(timezone)) => ColumnType::DateTime(Some(timezone.trim().into())),
+            ("DateTime64", Some(args)) => {
+                let mut args = args.splitn(2, ',');
+                let precision = args
+                    .next()
+                    .and_then(|precision| precision.trim().parse().ok())
+                    .filter(|precision| *precision <= 9)
+                    .ok_or_else(|| format!("Invalid DateTime64 precision in {:?}", s))?;
+                let timezone = args.next().map(|timezone| timezone.trim().into());
+                ColumnType::DateTime64(precision, timezone)
+            }
+            ("Nullable", Some(inner)) => ColumnType::Nullable(Box::new(inner.parse()?)),
+            ("LowCardinality", Some(inner)) => ColumnType::LowCardinality(Box::new(inner.parse()?)),
+            _ => return Err(format!("Unsupported column type {:?}", s)),
+        };
+
+        Ok(column_type)
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnType::String => write!(f, "String"),
+            ColumnType::UInt8 => write!(f, "UInt8"),
+            ColumnType::UInt16 => write!(f, "UInt16"),
+            ColumnType::UInt32 => write!(f, "UInt32"),
+            ColumnType::UInt64 => write!(f, "UInt64"),
+            ColumnType::Int8 => write!(f, "Int8"),
+            ColumnType::Int16 => write!(f, "Int16"),
+            ColumnType::Int32 => write!(f, "Int32"),
+            ColumnType::Int64 => write!(f, "Int64"),
+            ColumnType::Float32 => write!(f, "Float32"),
+            ColumnType::Float64 => write!(f, "Float64"),
+            ColumnType::DateTime(None) => write!(f, "DateTime"),
+            ColumnType::DateTime(Some(timezone)) => write!(f, "DateTime({})", timezone),
+            ColumnType::DateTime64(precision, None) => write!(f, "DateTime64({})", precision),
+            ColumnType::DateTime64(precision, Some(timezone)) => {
+                write!(f, "DateTime64({}, {})", precision, timezone)
+            }
+            ColumnType::Nullable(inner) => write!(f, "Nullable({})", inner),
+            ColumnType::LowCardinality(inner) => write!(f, "LowCardinality({})", inner),
+        }
+    }
+}
+
+/// Appends one row for `log` to `buf`. Missing fields are written as
+/// `NULL` for nullable columns and as the type's zero value otherwise.
+pub fn encode_row(columns: &[Column], log: &LogEvent, buf: &mut Vec<u8>) -> Result<(), String> {
+    for column in columns {
+        let field = column.field.as_ref().unwrap_or(&column.name);
+        let value = log.get(&Atom::from(field.as_str()));
+        column
+            .column_type
+            .encode(value, buf)
+            .map_err(|error| format!("column {:?}: {}", column.name, error))?;
+    }
+    Ok(())
+}
+
+impl ColumnType {
+    fn encode(&self, value: Option<&Value>, buf: &mut Vec<u8>) -> Result<(), String> {
+        let value = value.filter(|value| **value != Value::Null);
+
+        match self {
+            ColumnType::Nullable(inner) => match value {
+                Some(value) => {
+                    buf.push(0);
+                    inner.encode(Some(value), buf)
+                }
+                None => {
+                    buf.push(1);
+                    Ok(())
+                }
+            },
+            ColumnType::LowCardinality(inner) => inner.encode(value, buf),
+            ColumnType::String => {
+                let bytes = match value {
+                    Some(Value::Bytes(bytes)) => bytes.to_vec(),
+                    Some(value) => value.to_string_lossy().into_bytes(),
+                    None => Vec::new(),
+                };
+                write_varint(bytes.len() as u64, buf);
+                buf.extend_from_slice(&bytes);
+                Ok(())
+            }
+            ColumnType::UInt8 => write_int::<u8>(self, value, buf, |n, buf| buf.push(n)),
+            ColumnType::UInt16 => write_int::<u16>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::UInt32 => write_int::<u32>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::UInt64 => write_int::<u64>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::Int8 => write_int::<i8>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::Int16 => write_int::<i16>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::Int32 => write_int::<i32>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::Int64 => write_int::<i64>(self, value, buf, |n, buf| {
+                buf.extend_from_slice(&n.to_le_bytes())
+            }),
+            ColumnType::Float32 => {
+                let n = to_float(self, value)? as f32;
+                buf.extend_from_slice(&n.to_le_bytes());
+                Ok(())
+            }
+            ColumnType::Float64 => {
+                let n = to_float(self, value)?;
+                buf.extend_from_slice(&n.to_le_bytes());
+                Ok(())
+            }
+            ColumnType::DateTime(_) => {
+                let seconds = match to_timestamp(self, value)? {
+                    Some(timestamp) => u32::try_from(timestamp.timestamp())
+                        .map_err(|_| format!("{} is out of range for {}", timestamp, self))?,
+                    None => 0,
+                };
+                buf.extend_from_slice(&seconds.to_le_bytes());
+                Ok(())
+            }
+            ColumnType::DateTime64(precision, _) => {
+                let ticks = match to_timestamp(self, value)? {
+                    Some(timestamp) => {
+                        let nanos = i128::from(timestamp.timestamp()) * 1_000_000_000
+                            + i128::from(timestamp.timestamp_subsec_nanos());
+                        let ticks = nanos / 10i128.pow(9 - precision);
+                        i64::try_from(ticks)
+                            .map_err(|_| format!("{} is out of range for {}", timestamp, self))?
+                    }
+                    None => 0,
+                };
+                buf.extend_from_slice(&ticks.to_le_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_int<T>(
+    column_type: &ColumnType,
+    value: Option<&Value>,
+    buf: &mut Vec<u8>,
+    write: impl FnOnce(T, &mut Vec<u8>),
+) -> Result<(), String>
+where
+    T: TryFrom<i128> + Default,
+{
+    let n = match value {
+        None => T::default(),
+        Some(value) => {
+            let n: i128 = match value {
+                Value::Integer(n) => i128::from(*n),
+                Value::Boolean(b) => i128::from(*b as u8),
+                Value::Timestamp(timestamp) => i128::from(timestamp.timestamp()),
+                Value::Bytes(bytes) => String::from_utf8_lossy(bytes)
+                    .trim()
+                    .parse()
+                    .map_err(|_| mismatch(column_type, value))?,
+                _ => return Err(mismatch(column_type, value)),
+            };
+            n.try_into()
+                .map_err(|_| format!("{} is out of range for {}", n, column_type))?
+        }
+    };
+    write(n, buf);
+    Ok(())
+}
+
+fn to_float(column_type: &ColumnType, value: Option<&Value>) -> Result<f64, String> {
+    match value {
+        None => Ok(0.0),
+        Some(Value::Float(n)) => Ok(*n),
+        Some(Value::Integer(n)) => Ok(*n as f64),
+        Some(value) => match value {
+            Value::Bytes(bytes) => String::from_utf8_lossy(bytes)
+                .trim()
+                .parse()
+                .map_err(|_| mismatch(column_type, value)),
+            _ => Err(mismatch(column_type, value)),
+        },
+    }
+}
+
+fn to_timestamp(
+    column_type: &ColumnType,
+    value: Option<&Value>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    match value {
+        None => Ok(None),
+        Some(Value::Timestamp(timestamp)) => Ok(Some(*timestamp)),
+        Some(value) => match value {
+            Value::Bytes(bytes) => DateTime::parse_from_rfc3339(&String::from_utf8_lossy(bytes))
+                .map(|timestamp| Some(timestamp.with_timezone(&Utc)))
+                .map_err(|_| mismatch(column_type, value)),
+            _ => Err(mismatch(column_type, value)),
+        },
+    }
+}
+
+fn mismatch(column_type: &ColumnType, value: &Value) -> String {
+    format!(
+        "can't convert {:?} to {}",
+        value.to_string_lossy(),
+        column_type
+    )
+}
+
+/// Unsigned LEB128, used for string lengths.
+fn write_varint(mut n: u64, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use chrono::TimeZone;
+
+    fn column(name: &str, column_type: &str) -> Column {
+        Column {
+            name: name.into(),
+            column_type: column_type.parse().unwrap(),
+            field: None,
+        }
+    }
+
+    #[test]
+    fn row_binary_parses_column_types() {
+        for column_type in &[
+            "String",
+            "UInt64",
+            "Int8",
+            "Float32",
+            "DateTime",
+            "DateTime('UTC')",
+            "DateTime64(3)",
+            "DateTime64(6, 'Europe/Oslo')",
+            "Nullable(Int32)",
+            "LowCardinality(Nullable(String))",
+        ] {
+            let parsed: ColumnType = column_type.parse().unwrap();
+            assert_eq!(&parsed.to_string(), column_type);
+        }
+
+        assert!("Array(String)".parse::<ColumnType>().is_err());
+        assert!("DateTime64(12)".parse::<ColumnType>().is_err());
+        assert!("Nullable(String".parse::<ColumnType>().is_err());
+    }
+
+    #[test]
+    fn row_binary_encodes_row() {
+        let columns = vec![
+            column("message", "String"),
+            column("status", "UInt16"),
+            column("duration", "Float64"),
+            column("timestamp", "DateTime"),
+            column("user", "Nullable(String)"),
+        ];
+
+        let mut event = Event::from("hi");
+        event.as_mut_log().insert("status", 200);
+        event.as_mut_log().insert("duration", "1.5");
+        event
+            .as_mut_log()
+            .insert("timestamp", Utc.timestamp(1_000_000_000, 0));
+
+        let mut buf = Vec::new();
+        encode_row(&columns, event.as_log(), &mut buf).unwrap();
+
+        let mut expected = vec![2, b'h', b'i', 200, 0];
+        expected.extend_from_slice(&1.5f64.to_le_bytes());
+        expected.extend_from_slice(&1_000_000_000u32.to_le_bytes());
+        expected.push(1);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn row_binary_encodes_datetime64() {
+        let columns = vec![column("timestamp", "DateTime64(3)")];
+
+        let mut event = Event::new_empty_log();
+        event
+            .as_mut_log()
+            .insert("timestamp", Utc.timestamp(1, 234_567_890));
+
+        let mut buf = Vec::new();
+        encode_row(&columns, event.as_log(), &mut buf).unwrap();
+
+        assert_eq!(buf, 1234i64.to_le_bytes().to_vec());
+    }
+
+    #[test]
+    fn row_binary_rejects_out_of_range() {
+        let columns = vec![column("status", "UInt8")];
+
+        let mut event = Event::new_empty_log();
+        event.as_mut_log().insert("status", 300);
+
+        let mut buf = Vec::new();
+        assert!(encode_row(&columns, event.as_log(), &mut buf).is_err());
+    }
+
+    #[test]
+    fn row_binary_varint() {
+        let mut buf = Vec::new();
+        write_varint(300, &mut buf);
+        assert_eq!(buf, vec![0xac, 0x02]);
+    }
+}
@@ -1,25 +1,74 @@
+use super::{healthcheck, intake_request, DatadogRetryLogic};
 use crate::{
-    event::{log_schema, Event},
+    event::{log_schema, Event, LogEvent, Value},
     sinks::util::{
-        self,
-        encoding::{EncodingConfig, EncodingConfiguration},
-        tcp::{tcp_healthcheck, TcpSink},
-        Encoding, UriSerde,
+        encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+        http2::{BatchedHttpSink, HttpSink},
+        service2::TowerRequestConfig,
+        BatchBytesConfig, BoxedRawValue, Compression, JsonArrayBuffer, UriSerde,
     },
-    tls::{MaybeTlsSettings, TlsConfig},
+    tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
-use bytes::Bytes;
-use futures01::{stream::iter_ok, Sink};
+use futures::{FutureExt, TryFutureExt};
+use futures01::Sink;
+use http02::{Request, Uri};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use string_cache::DefaultAtom as Atom;
 
-#[derive(Deserialize, Serialize, Debug)]
+const PATH: &str = "/api/v2/logs";
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display(
+        "`endpoint` {:?} has no scheme; the sink now sends to the HTTP intake, not the TCP intake at `host:port`",
+        endpoint
+    ))]
+    MissingScheme { endpoint: String },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct DatadogLogsConfig {
+    /// Overrides the intake of the site.
     endpoint: Option<UriSerde>,
+    #[serde(default = "default_site")]
+    site: String,
     api_key: String,
-    encoding: EncodingConfig<Encoding>,
-    tls: Option<TlsConfig>,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    encoding: EncodingConfigWithDefault<Encoding>,
+    #[serde(default = "Compression::default_gzip")]
+    compression: Compression,
+    /// Tags added to every log, as `name:value`.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Moves fields into tags, from the name of the tag to the field.
+    #[serde(default)]
+    tag_fields: IndexMap<String, String>,
+    #[serde(default)]
+    batch: BatchBytesConfig,
+    #[serde(default)]
+    request: TowerRequestConfig,
+    tls: Option<TlsOptions>,
+}
+
+fn default_site() -> String {
+    "datadoghq.com".into()
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum Encoding {
+    #[derivative(Default)]
+    Json,
+    /// Deprecated, sends only the message and tags of each log.
+    Text,
 }
 
 inventory::submit! {
@@ -29,35 +78,38 @@ inventory::submit! {
 #[typetag::serde(name = "datadog_logs")]
 impl SinkConfig for DatadogLogsConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let (host, port, tls) = if let Some(uri) = &self.endpoint {
-            let host = uri
-                .host()
-                .ok_or_else(|| "A host is required for endpoints".to_string())?;
-            let port = uri
-                .port_u16()
-                .ok_or_else(|| "A port is required for endpoints".to_string())?;
-
-            (format!("{}", host), port, self.tls.clone())
-        } else {
-            let tls = self.tls.clone().unwrap_or({
-                let mut tls = TlsConfig::default();
-                tls.enabled = Some(true);
-                tls
-            });
-
-            ("intake.logs.datadoghq.com".to_string(), 10516, Some(tls))
-        };
+        if *self.encoding.codec() == Encoding::Text {
+            warn!("The `text` encoding is deprecated, use `json` instead");
+        }
 
-        let tls_settings = MaybeTlsSettings::from_config(&tls, false)?;
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let healthcheck = healthcheck(
+            format!("https://api.{}", self.site),
+            self.api_key.clone(),
+            cx.resolver(),
+            tls.clone(),
+        )
+        .boxed()
+        .compat();
 
-        let sink = TcpSink::new(host.clone(), port, cx.resolver(), tls_settings);
-        let healthcheck = tcp_healthcheck(host.clone(), port, cx.resolver());
+        // The intake accepts at most 5MB of uncompressed logs per request.
+        let batch = self.batch.unwrap_or(bytesize::mib(5u64), 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig::default());
 
-        let encoding = self.encoding.clone();
-        let api_key = Bytes::from(format!("{} ", self.api_key));
-
-        let sink =
-            sink.with_flat_map(move |e| iter_ok(encode_event(e, api_key.clone(), &encoding)));
+        let sink = DatadogLogsSink {
+            uri: self.uri()?,
+            config: self.clone(),
+        };
+        let sink = BatchedHttpSink::with_retry_logic(
+            sink,
+            JsonArrayBuffer::default(),
+            DatadogRetryLogic,
+            request,
+            batch,
+            tls,
+            &cx,
+        )
+        .sink_map_err(|e| error!("Fatal datadog_logs sink error: {}", e));
 
         Ok((Box::new(sink), Box::new(healthcheck)))
     }
@@ -71,34 +123,166 @@ impl SinkConfig for DatadogLogsConfig {
     }
 }
 
-fn encode_event(
-    mut event: Event,
-    mut api_key: Bytes,
-    encoding: &EncodingConfig<Encoding>,
-) -> Option<Bytes> {
-    encoding.apply_rules(&mut event);
+impl DatadogLogsConfig {
+    fn uri(&self) -> crate::Result<Uri> {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) if endpoint.scheme().is_none() => {
+                return Err(BuildError::MissingScheme {
+                    endpoint: endpoint.to_string(),
+                }
+                .into())
+            }
+            Some(endpoint) => endpoint.to_string(),
+            None => format!("https://http-intake.logs.{}", self.site),
+        };
+        let uri = format!("{}{}", endpoint.trim_end_matches('/'), PATH);
+        Ok(uri.parse::<Uri>().context(super::UriParseError2)?)
+    }
+}
+
+struct DatadogLogsSink {
+    config: DatadogLogsConfig,
+    uri: Uri,
+}
+
+impl HttpSink for DatadogLogsSink {
+    type Input = serde_json::Value;
+    type Output = Vec<BoxedRawValue>;
+
+    fn encode_event(&self, mut event: Event) -> Option<Self::Input> {
+        self.config.encoding.apply_rules(&mut event);
+
+        let log = event.as_mut_log();
+
+        if let Some(message) = log.remove(&log_schema().message_key()) {
+            log.insert("message", message);
+        }
 
-    let log = event.as_mut_log();
+        if let Some(timestamp) = log.remove(&log_schema().timestamp_key()) {
+            log.insert("date", timestamp);
+        }
 
-    if let Some(message) = log.remove(&log_schema().message_key()) {
-        log.insert("message", message);
+        if let Some(host) = log.remove(&log_schema().host_key()) {
+            log.insert("host", host);
+        }
+
+        let mut tags = self.config.tags.clone();
+        for (tag, field) in &self.config.tag_fields {
+            if let Some(value) = log.remove(&Atom::from(field.as_str())) {
+                tags.push(format!("{}:{}", tag, value.to_string_lossy()));
+            }
+        }
+        if !tags.is_empty() {
+            if let Some(Value::Bytes(existing)) = log.remove(&Atom::from("ddtags")) {
+                tags.insert(0, String::from_utf8_lossy(&existing).into_owned());
+            }
+            log.insert("ddtags", tags.join(","));
+        }
+
+        if *self.config.encoding.codec() == Encoding::Text {
+            let mut text = LogEvent::new();
+            for field in &["message", "ddtags"] {
+                if let Some(value) = log.remove(&Atom::from(*field)) {
+                    text.insert(*field, value);
+                }
+            }
+            *log = text;
+        }
+
+        serde_json::to_value(log)
+            .map_err(|error| error!(message = "Error encoding event as json.", %error))
+            .ok()
     }
 
-    if let Some(timestamp) = log.remove(&log_schema().timestamp_key()) {
-        log.insert("date", timestamp);
+    fn build_request(&self, events: Self::Output) -> Request<Vec<u8>> {
+        let body = serde_json::to_vec(&events).expect("logs serialize to JSON");
+        intake_request(
+            self.uri.clone(),
+            &self.config.api_key,
+            self.config.compression,
+            body,
+        )
     }
+}
 
-    if let Some(host) = log.remove(&log_schema().host_key()) {
-        log.insert("host", host);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+    use http02::Method;
+
+    #[test]
+    fn datadog_logs_maps_fields_and_tags() {
+        let (config, _, _) = load_sink::<DatadogLogsConfig>(
+            r#"
+            api_key = "abc"
+            site = "datadoghq.eu"
+            compression = "none"
+            tags = ["env:prod"]
+            tag_fields = { service = "app" }
+        "#,
+        )
+        .unwrap();
+        let sink = DatadogLogsSink {
+            uri: config.uri().unwrap(),
+            config,
+        };
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("app", "web");
+        event.as_mut_log().insert("ddtags", "team:a");
+        let encoded = sink.encode_event(event).unwrap();
+        assert_eq!(encoded["message"], "hello");
+        assert_eq!(encoded["ddtags"], "team:a,env:prod,service:web");
+        assert!(encoded.get("app").is_none());
+        assert!(encoded.get("date").is_some());
+
+        let raw = serde_json::value::RawValue::from_string(encoded.to_string()).unwrap();
+        let request = sink.build_request(vec![raw]);
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(
+            request.uri(),
+            &Uri::from_static("https://http-intake.logs.datadoghq.eu/api/v2/logs")
+        );
+        assert_eq!(request.headers()["DD-API-KEY"], "abc");
+        let body: serde_json::Value = serde_json::from_slice(request.body()).unwrap();
+        assert_eq!(body[0]["message"], "hello");
     }
 
-    if let Some(bytes) = util::encode_event(event, encoding) {
-        // Prepend the api_key:
-        // {API_KEY} {EVENT_BYTES}
-        api_key.extend(bytes);
+    #[test]
+    fn datadog_logs_text_sends_message_and_tags() {
+        let (config, _, _) = load_sink::<DatadogLogsConfig>(
+            r#"
+            api_key = "abc"
+            encoding = "text"
+            tags = ["env:prod"]
+        "#,
+        )
+        .unwrap();
+        let sink = DatadogLogsSink {
+            uri: config.uri().unwrap(),
+            config,
+        };
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("app", "web");
+        let encoded = sink.encode_event(event).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({"message": "hello", "ddtags": "env:prod"})
+        );
+    }
 
-        Some(api_key)
-    } else {
-        None
+    #[test]
+    fn datadog_logs_rejects_tcp_endpoint() {
+        let (config, _, _) = load_sink::<DatadogLogsConfig>(
+            r#"
+            api_key = "abc"
+            endpoint = "intake.logs.datadoghq.com:10516"
+        "#,
+        )
+        .unwrap();
+        let error = config.uri().unwrap_err().to_string();
+        assert!(error.contains("no scheme"), "{}", error);
     }
 }
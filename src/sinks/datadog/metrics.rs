@@ -1,22 +1,24 @@
+use super::{healthcheck, intake_request, DatadogRetryLogic};
 use crate::{
-    dns::Resolver,
     event::{
         metric::{Metric, MetricKind, MetricValue},
         Event,
     },
     sinks::util::{
-        http2::{BatchedHttpSink, HttpClient, HttpSink},
+        http2::{BatchedHttpSink, HttpSink},
         service2::TowerRequestConfig,
-        BatchEventsConfig, MetricBuffer,
+        BatchEventsConfig, Compression, MetricBuffer,
     },
+    tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use chrono::{DateTime, Utc};
 use futures::{FutureExt, TryFutureExt};
 use futures01::Sink;
-use http02::{uri::InvalidUri, Request, StatusCode, Uri};
+use http02::{uri::InvalidUri, Request, Uri};
+use indexmap::IndexMap;
 use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use snafu::{ResultExt, Snafu};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
@@ -40,10 +42,19 @@ pub struct DatadogConfig {
     #[serde(default = "default_host")]
     pub host: String,
     pub api_key: String,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    /// Tags added to every series, unless the metric has them already.
+    #[serde(default)]
+    pub tags: IndexMap<String, String>,
+    /// The metric tag naming the host the series is attributed to.
+    #[serde(default = "default_host_tag")]
+    pub host_tag: String,
     #[serde(default)]
     pub batch: BatchEventsConfig,
     #[serde(default)]
     pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
 }
 
 struct DatadogSink {
@@ -59,7 +70,7 @@ lazy_static! {
     };
 }
 
-// https://docs.datadoghq.com/api/?lang=bash#post-timeseries-points
+// https://docs.datadoghq.com/api/latest/metrics/#submit-metrics
 #[derive(Debug, Clone, PartialEq, Serialize)]
 struct DatadogRequest {
     series: Vec<DatadogMetric>,
@@ -69,25 +80,54 @@ pub fn default_host() -> String {
     String::from("https://api.datadoghq.com")
 }
 
+fn default_host_tag() -> String {
+    String::from("host")
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize)]
 struct DatadogMetric {
     metric: String,
     r#type: DatadogMetricType,
+    #[serde(skip_serializing_if = "Option::is_none")]
     interval: Option<i64>,
     points: Vec<DatadogPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    resources: Vec<DatadogResource>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
-#[serde(rename_all = "snake_case")]
+/// Serialized as the numbers the v2 API identifies them by.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DatadogMetricType {
-    Gauge,
-    Count,
-    Rate,
+    Count = 1,
+    Rate = 2,
+    Gauge = 3,
+}
+
+impl Serialize for DatadogMetricType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*self as u8)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
-struct DatadogPoint(i64, f64);
+struct DatadogPoint {
+    timestamp: i64,
+    value: f64,
+}
+
+impl DatadogPoint {
+    fn new(timestamp: i64, value: f64) -> Self {
+        Self { timestamp, value }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DatadogResource {
+    name: String,
+    r#type: &'static str,
+}
 
 #[derive(Debug, Clone, PartialEq)]
 struct DatadogStats {
@@ -107,7 +147,15 @@ inventory::submit! {
 #[typetag::serde(name = "datadog_metrics")]
 impl SinkConfig for DatadogConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let healthcheck = healthcheck(self.clone(), cx.resolver()).boxed().compat();
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let healthcheck = healthcheck(
+            self.host.clone(),
+            self.api_key.clone(),
+            cx.resolver(),
+            tls.clone(),
+        )
+        .boxed()
+        .compat();
 
         let batch = self.batch.unwrap_or(20, 1);
         let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
@@ -121,8 +169,16 @@ impl SinkConfig for DatadogConfig {
             last_sent_timestamp: AtomicI64::new(timestamp),
         };
 
-        let sink = BatchedHttpSink::new(sink, MetricBuffer::new(), request, batch, None, &cx)
-            .sink_map_err(|e| error!("Fatal datadog error: {}", e));
+        let sink = BatchedHttpSink::with_retry_logic(
+            sink,
+            MetricBuffer::new(),
+            DatadogRetryLogic,
+            request,
+            batch,
+            tls,
+            &cx,
+        )
+        .sink_map_err(|e| error!("Fatal datadog error: {}", e));
 
         Ok((Box::new(sink), Box::new(healthcheck)))
     }
@@ -149,42 +205,56 @@ impl HttpSink for DatadogSink {
         let interval = now - self.last_sent_timestamp.load(SeqCst);
         self.last_sent_timestamp.store(now, SeqCst);
 
+        let events = events
+            .into_iter()
+            .map(|metric| with_default_tags(metric, &self.config.tags))
+            .collect();
         let input = encode_events(events, interval, &self.config.namespace);
+        let input = attribute_hosts(input, &self.config.host_tag);
         let body = serde_json::to_vec(&input).unwrap();
 
-        Request::post(self.uri.clone())
-            .header("Content-Type", "application/json")
-            .header("DD-API-KEY", self.config.api_key.clone())
-            .body(body)
-            .unwrap()
+        intake_request(
+            self.uri.clone(),
+            &self.config.api_key,
+            self.config.compression,
+            body,
+        )
     }
 }
 
 fn build_uri(host: &str) -> crate::Result<Uri> {
-    let uri = format!("{}/api/v1/series", host)
+    let uri = format!("{}/api/v2/series", host.trim_end_matches('/'))
         .parse::<Uri>()
         .context(super::UriParseError2)?;
 
     Ok(uri)
 }
 
-async fn healthcheck(config: DatadogConfig, resolver: Resolver) -> crate::Result<()> {
-    let uri = format!("{}/api/v1/validate", config.host)
-        .parse::<Uri>()
-        .context(super::UriParseError2)?;
-
-    let request = Request::get(uri)
-        .header("DD-API-KEY", config.api_key)
-        .body(hyper13::Body::empty())
-        .unwrap();
-
-    let mut client = HttpClient::new(resolver, None)?;
-    let response = client.send(request).await?;
+fn with_default_tags(mut metric: Metric, defaults: &IndexMap<String, String>) -> Metric {
+    if !defaults.is_empty() {
+        let tags = metric.tags.get_or_insert_with(BTreeMap::new);
+        for (name, value) in defaults {
+            tags.entry(name.clone()).or_insert_with(|| value.clone());
+        }
+    }
+    metric
+}
 
-    match response.status() {
-        StatusCode::OK => Ok(()),
-        other => Err(super::HealthcheckError::UnexpectedStatus2 { status: other }.into()),
+/// Moves the host tag of each series into its resources.
+fn attribute_hosts(mut request: DatadogRequest, host_tag: &str) -> DatadogRequest {
+    let prefix = format!("{}:", host_tag);
+    for series in &mut request.series {
+        if let Some(tags) = &mut series.tags {
+            if let Some(index) = tags.iter().position(|tag| tag.starts_with(&prefix)) {
+                let host = tags.remove(index)[prefix.len()..].to_owned();
+                series.resources.push(DatadogResource {
+                    name: host,
+                    r#type: "host",
+                });
+            }
+        }
     }
+    request
 }
 
 fn encode_tags(tags: BTreeMap<String, String>) -> Vec<String> {
@@ -277,8 +347,9 @@ fn encode_events(events: Vec<Metric>, interval: i64, namespace: &str) -> Datadog
                         metric: fullname,
                         r#type: DatadogMetricType::Count,
                         interval: Some(interval),
-                        points: vec![DatadogPoint(ts, value)],
+                        points: vec![DatadogPoint::new(ts, value)],
                         tags,
+                        resources: Vec::new(),
                     }]),
                     MetricValue::Distribution {
                         values,
@@ -291,36 +362,41 @@ fn encode_events(events: Vec<Metric>, interval: i64, namespace: &str) -> Datadog
                                     metric: format!("{}.min", &fullname),
                                     r#type: DatadogMetricType::Gauge,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, s.min)],
+                                    points: vec![DatadogPoint::new(ts, s.min)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 },
                                 DatadogMetric {
                                     metric: format!("{}.avg", &fullname),
                                     r#type: DatadogMetricType::Gauge,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, s.avg)],
+                                    points: vec![DatadogPoint::new(ts, s.avg)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 },
                                 DatadogMetric {
                                     metric: format!("{}.count", &fullname),
                                     r#type: DatadogMetricType::Rate,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, s.count)],
+                                    points: vec![DatadogPoint::new(ts, s.count)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 },
                                 DatadogMetric {
                                     metric: format!("{}.median", &fullname),
                                     r#type: DatadogMetricType::Gauge,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, s.median)],
+                                    points: vec![DatadogPoint::new(ts, s.median)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 },
                                 DatadogMetric {
                                     metric: format!("{}.max", &fullname),
                                     r#type: DatadogMetricType::Gauge,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, s.max)],
+                                    points: vec![DatadogPoint::new(ts, s.max)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 },
                             ];
                             for (q, v) in s.quantiles {
@@ -332,8 +408,9 @@ fn encode_events(events: Vec<Metric>, interval: i64, namespace: &str) -> Datadog
                                     ),
                                     r#type: DatadogMetricType::Gauge,
                                     interval: Some(interval),
-                                    points: vec![DatadogPoint(ts, v)],
+                                    points: vec![DatadogPoint::new(ts, v)],
                                     tags: tags.clone(),
+                                    resources: Vec::new(),
                                 })
                             }
                             Some(result)
@@ -345,8 +422,9 @@ fn encode_events(events: Vec<Metric>, interval: i64, namespace: &str) -> Datadog
                         metric: fullname,
                         r#type: DatadogMetricType::Gauge,
                         interval: None,
-                        points: vec![DatadogPoint(ts, values.len() as f64)],
+                        points: vec![DatadogPoint::new(ts, values.len() as f64)],
                         tags,
+                        resources: Vec::new(),
                     }]),
                     _ => None,
                 },
@@ -355,8 +433,9 @@ fn encode_events(events: Vec<Metric>, interval: i64, namespace: &str) -> Datadog
                         metric: fullname,
                         r#type: DatadogMetricType::Gauge,
                         interval: None,
-                        points: vec![DatadogPoint(ts, value)],
+                        points: vec![DatadogPoint::new(ts, value)],
                         tags,
+                        resources: Vec::new(),
                     }]),
                     _ => None,
                 },
@@ -439,7 +518,32 @@ mod tests {
         assert_eq!(req.method(), Method::POST);
         assert_eq!(
             req.uri(),
-            &Uri::from_static("https://api.datadoghq.com/api/v1/series")
+            &Uri::from_static("https://api.datadoghq.com/api/v2/series")
+        );
+    }
+
+    #[test]
+    fn encode_host_resources() {
+        let mut defaults = IndexMap::new();
+        defaults.insert("env".to_owned(), "prod".to_owned());
+        defaults.insert("host".to_owned(), "fallback".to_owned());
+        let mut tags = tags();
+        tags.insert("host".to_owned(), "web-1".to_owned());
+        let metric = Metric {
+            name: "volume".into(),
+            timestamp: Some(ts()),
+            tags: Some(tags),
+            kind: MetricKind::Absolute,
+            value: MetricValue::Gauge { value: 1.0 },
+        };
+
+        let metric = with_default_tags(metric, &defaults);
+        let input = attribute_hosts(encode_events(vec![metric], 60, ""), "host");
+        let json = serde_json::to_string(&input).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"series":[{"metric":"volume","type":3,"points":[{"timestamp":1542182950,"value":1.0}],"tags":["empty_tag:","env:prod","normal_tag:value","true_tag:true"],"resources":[{"name":"web-1","type":"host"}]}]}"#
         );
     }
 
@@ -489,7 +593,7 @@ mod tests {
 
         assert_eq!(
             json,
-            format!("{{\"series\":[{{\"metric\":\"ns.total\",\"type\":\"count\",\"interval\":60,\"points\":[[{},1.5]],\"tags\":null}},{{\"metric\":\"ns.check\",\"type\":\"count\",\"interval\":60,\"points\":[[1542182950,1.0]],\"tags\":[\"empty_tag:\",\"normal_tag:value\",\"true_tag:true\"]}}]}}", now)
+            format!("{{\"series\":[{{\"metric\":\"ns.total\",\"type\":1,\"interval\":60,\"points\":[{{\"timestamp\":{},\"value\":1.5}}]}},{{\"metric\":\"ns.check\",\"type\":1,\"interval\":60,\"points\":[{{\"timestamp\":1542182950,\"value\":1.0}}],\"tags\":[\"empty_tag:\",\"normal_tag:value\",\"true_tag:true\"]}}]}}", now)
         );
     }
 
@@ -516,7 +620,7 @@ mod tests {
 
         assert_eq!(
             json,
-            r#"{"series":[{"metric":"volume","type":"gauge","interval":null,"points":[[1542182950,-1.1]],"tags":null}]}"#
+            r#"{"series":[{"metric":"volume","type":3,"points":[{"timestamp":1542182950,"value":-1.1}]}]}"#
         );
     }
 
@@ -536,7 +640,7 @@ mod tests {
 
         assert_eq!(
             json,
-            r#"{"series":[{"metric":"users","type":"gauge","interval":null,"points":[[1542182950,2.0]],"tags":null}]}"#
+            r#"{"series":[{"metric":"users","type":3,"points":[{"timestamp":1542182950,"value":2.0}]}]}"#
         );
     }
 
@@ -643,7 +747,7 @@ mod tests {
 
         assert_eq!(
             json,
-            r#"{"series":[{"metric":"requests.min","type":"gauge","interval":60,"points":[[1542182950,1.0]],"tags":null},{"metric":"requests.avg","type":"gauge","interval":60,"points":[[1542182950,1.875]],"tags":null},{"metric":"requests.count","type":"rate","interval":60,"points":[[1542182950,8.0]],"tags":null},{"metric":"requests.median","type":"gauge","interval":60,"points":[[1542182950,2.0]],"tags":null},{"metric":"requests.max","type":"gauge","interval":60,"points":[[1542182950,3.0]],"tags":null},{"metric":"requests.95percentile","type":"gauge","interval":60,"points":[[1542182950,3.0]],"tags":null}]}"#
+            r#"{"series":[{"metric":"requests.min","type":3,"interval":60,"points":[{"timestamp":1542182950,"value":1.0}]},{"metric":"requests.avg","type":3,"interval":60,"points":[{"timestamp":1542182950,"value":1.875}]},{"metric":"requests.count","type":2,"interval":60,"points":[{"timestamp":1542182950,"value":8.0}]},{"metric":"requests.median","type":3,"interval":60,"points":[{"timestamp":1542182950,"value":2.0}]},{"metric":"requests.max","type":3,"interval":60,"points":[{"timestamp":1542182950,"value":3.0}]},{"metric":"requests.95percentile","type":3,"interval":60,"points":[{"timestamp":1542182950,"value":3.0}]}]}"#
         );
     }
 }
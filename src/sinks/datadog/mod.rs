@@ -2,3 +2,138 @@ pub mod logs;
 pub mod metrics;
 
 pub(self) use super::{Healthcheck, HealthcheckError, RouterSink, UriParseError2};
+
+use crate::{
+    dns::Resolver,
+    sinks::util::{
+        http2::HttpClient,
+        retries2::{RetryAction, RetryLogic},
+        Compression,
+    },
+    tls::TlsSettings,
+};
+use bytes05::Bytes;
+use flate2::write::GzEncoder;
+use http02::{Request, StatusCode, Uri};
+use snafu::ResultExt;
+use std::io::Write;
+
+/// Builds a request to one of the intake APIs, authenticated by the API key.
+fn intake_request(
+    uri: Uri,
+    api_key: &str,
+    compression: Compression,
+    body: Vec<u8>,
+) -> Request<Vec<u8>> {
+    let mut builder = Request::post(uri)
+        .header("Content-Type", "application/json")
+        .header("DD-API-KEY", api_key);
+    let body = match compression {
+        Compression::None => body,
+        Compression::Gzip => {
+            builder = builder.header("Content-Encoding", "gzip");
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder
+                .write_all(&body)
+                .expect("Writing to a Vec can't fail");
+            encoder.finish().expect("Writing to a Vec can't fail")
+        }
+    };
+    builder.body(body).unwrap()
+}
+
+/// Validates the API key against the API of the site, as the intakes have no
+/// endpoint of their own for it.
+async fn healthcheck(
+    api: String,
+    api_key: String,
+    resolver: Resolver,
+    tls: TlsSettings,
+) -> crate::Result<()> {
+    let uri = format!("{}/api/v1/validate", api.trim_end_matches('/'))
+        .parse::<Uri>()
+        .context(UriParseError2)?;
+
+    let request = Request::get(uri)
+        .header("DD-API-KEY", api_key)
+        .body(hyper13::Body::empty())
+        .unwrap();
+
+    let mut client = HttpClient::new(resolver, tls)?;
+    let response = client.send(request).await?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        other => Err(HealthcheckError::UnexpectedStatus2 { status: other }.into()),
+    }
+}
+
+/// Retries the statuses the intake APIs document as transient, and explains
+/// the ones they reject a payload with for good.
+#[derive(Debug, Default, Clone)]
+struct DatadogRetryLogic;
+
+impl RetryLogic for DatadogRetryLogic {
+    type Error = hyper13::Error;
+    type Response = hyper13::Response<Bytes>;
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        error.is_connect() || error.is_closed()
+    }
+
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        let status = response.status();
+
+        match status {
+            StatusCode::REQUEST_TIMEOUT => RetryAction::Retry("Request timeout".into()),
+            StatusCode::TOO_MANY_REQUESTS => RetryAction::Retry("Too many requests".into()),
+            _ if status.is_server_error() => RetryAction::Retry(
+                format!("{}: {}", status, String::from_utf8_lossy(response.body())).into(),
+            ),
+            _ if status.is_success() => RetryAction::Successful,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                RetryAction::DontRetry(format!("{}: the API key was rejected", status))
+            }
+            StatusCode::PAYLOAD_TOO_LARGE => RetryAction::DontRetry(format!(
+                "{}: the batch is larger than the intake accepts",
+                status
+            )),
+            _ => RetryAction::DontRetry(format!(
+                "{}: {}",
+                status,
+                String::from_utf8_lossy(response.body())
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(status: u16) -> RetryAction {
+        let response = hyper13::Response::builder()
+            .status(status)
+            .body(Bytes::from("{\"errors\":[]}"))
+            .unwrap();
+        DatadogRetryLogic.should_retry_response(&response)
+    }
+
+    #[test]
+    fn datadog_retries_transient_statuses() {
+        let retried = |status| match action(status) {
+            RetryAction::Retry(_) => true,
+            _ => false,
+        };
+        assert!(retried(408));
+        assert!(retried(429));
+        assert!(retried(503));
+        assert!(!retried(400));
+        assert!(!retried(403));
+        assert!(!retried(413));
+        match action(202) {
+            RetryAction::Successful => (),
+            _ => panic!("202 should be successful"),
+        }
+    }
+}
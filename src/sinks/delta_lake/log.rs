@@ -0,0 +1,164 @@
+//! Commits to the Delta transaction log: every version is a file of
+//! newline delimited actions in `_delta_log`.
+//!
+//! See https://github.com/delta-io/delta/blob/master/PROTOCOL.md
+
+use crate::sinks::util::columns::{Column, ColumnType};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+pub const LOG_DIR: &str = "_delta_log/";
+
+/// The key of the commit file of a version, relative to the table.
+pub fn commit_key(version: u64) -> String {
+    format!("{}{:020}.json", LOG_DIR, version)
+}
+
+/// The version of a commit file, given a key relative to the table.
+/// Checkpoints and other files in the log have none.
+pub fn version_of(key: &str) -> Option<u64> {
+    if !key.starts_with(LOG_DIR) || !key.ends_with(".json") {
+        return None;
+    }
+    let name = &key[LOG_DIR.len()..key.len() - ".json".len()];
+    if name.len() == 20 && name.bytes().all(|byte| byte.is_ascii_digit()) {
+        name.parse().ok()
+    } else {
+        None
+    }
+}
+
+pub struct AddFile<'a> {
+    pub path: &'a str,
+    pub size: usize,
+    pub num_records: usize,
+    pub modification_time: i64,
+}
+
+/// The actions committing a data file. The first version also creates the
+/// table, with the schema of the columns.
+pub fn commit(version: u64, columns: &[Column], file: &AddFile<'_>) -> Vec<u8> {
+    let mut actions = Vec::new();
+    if version == 0 {
+        actions.push(json!({
+            "protocol": { "minReaderVersion": 1, "minWriterVersion": 2 }
+        }));
+        actions.push(json!({
+            "metaData": {
+                "id": Uuid::new_v4().to_hyphenated().to_string(),
+                "format": { "provider": "parquet", "options": {} },
+                "schemaString": schema_string(columns),
+                "partitionColumns": [],
+                "configuration": {},
+                "createdTime": file.modification_time,
+            }
+        }));
+    }
+
+    actions.push(json!({
+        "add": {
+            "path": file.path,
+            "partitionValues": {},
+            "size": file.size,
+            "modificationTime": file.modification_time,
+            "dataChange": true,
+            "stats": json!({ "numRecords": file.num_records }).to_string(),
+        }
+    }));
+    actions.push(json!({
+        "commitInfo": {
+            "timestamp": file.modification_time,
+            "operation": "WRITE",
+            "operationParameters": { "mode": "Append" },
+            "isBlindAppend": true,
+            "engineInfo": format!("Vector/{}", crate::get_version()),
+        }
+    }));
+
+    let mut bytes = Vec::new();
+    for action in actions {
+        bytes.extend(action.to_string().into_bytes());
+        bytes.push(b'\n');
+    }
+    bytes
+}
+
+/// Whether a commit adds the data file at `path`.
+pub fn adds_file(commit: &[u8], path: &str) -> bool {
+    commit
+        .split(|byte| *byte == b'\n')
+        .filter_map(|line| serde_json::from_slice::<Value>(line).ok())
+        .any(|action| action["add"]["path"] == path)
+}
+
+/// The table schema, as a Spark `StructType` in JSON.
+fn schema_string(columns: &[Column]) -> String {
+    let fields = columns
+        .iter()
+        .map(|column| {
+            let data_type = match column.column_type {
+                ColumnType::Utf8 => "string",
+                ColumnType::Int64 => "long",
+                ColumnType::Float64 => "double",
+                ColumnType::Boolean => "boolean",
+                ColumnType::Timestamp => "timestamp",
+            };
+            json!({
+                "name": column.name.to_string(),
+                "type": data_type,
+                "nullable": true,
+                "metadata": {},
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({ "type": "struct", "fields": fields }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_log_versions() {
+        assert_eq!(commit_key(12), "_delta_log/00000000000000000012.json");
+        assert_eq!(version_of(&commit_key(12)), Some(12));
+        assert_eq!(
+            version_of("_delta_log/00000000000000000010.checkpoint.parquet"),
+            None
+        );
+        assert_eq!(version_of("_delta_log/_last_checkpoint"), None);
+        assert_eq!(version_of("part-0.parquet"), None);
+    }
+
+    #[test]
+    fn delta_log_commit() {
+        let columns = vec![Column {
+            name: "message".into(),
+            column_type: ColumnType::Utf8,
+        }];
+        let file = AddFile {
+            path: "part-1.parquet",
+            size: 100,
+            num_records: 2,
+            modification_time: 1000,
+        };
+
+        let first = commit(0, &columns, &file);
+        let actions = first
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(actions.len(), 4);
+        assert_eq!(actions[0]["protocol"]["minReaderVersion"], 1);
+        let schema: Value =
+            serde_json::from_str(actions[1]["metaData"]["schemaString"].as_str().unwrap()).unwrap();
+        assert_eq!(schema["fields"][0]["type"], "string");
+        assert_eq!(actions[2]["add"]["size"], 100);
+        assert!(adds_file(&first, "part-1.parquet"));
+        assert!(!adds_file(&first, "part-2.parquet"));
+
+        let later = commit(1, &columns, &file);
+        assert_eq!(later.split(|byte| *byte == b'\n').count(), 3);
+    }
+}
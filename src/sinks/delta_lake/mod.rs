@@ -0,0 +1,409 @@
+use crate::{
+    event::Event,
+    region::RegionOrEndpoint,
+    sinks::{
+        aws_s3::S3Sink,
+        util::{
+            columns::{self, Column, ColumnType},
+            retries2::RetryLogic,
+            service2::TowerRequestConfig,
+            BatchEventsConfig, Compression,
+        },
+        Healthcheck, RouterSink,
+    },
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use chrono::Utc;
+use futures::{compat::Future01CompatExt, future::BoxFuture, FutureExt, TryFutureExt};
+use futures01::{Sink, Stream};
+use http::StatusCode;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use openssl::hash::{hash, MessageDigest};
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    GetObjectError, GetObjectRequest, HeadBucketRequest, ListObjectsV2Error, ListObjectsV2Request,
+    PutObjectError, PutObjectRequest, S3Client, S3,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    convert::TryInto,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::sync::Mutex;
+use tower03::Service;
+
+mod log;
+mod parquet;
+mod thrift;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DeltaLakeSinkConfig {
+    pub bucket: String,
+    /// The path of the table within the bucket.
+    pub table_path: String,
+    #[serde(flatten)]
+    pub region: RegionOrEndpoint,
+    pub assume_role: Option<String>,
+    /// Maps event fields to column types, in column order.
+    #[serde(default)]
+    pub schema: IndexMap<String, ColumnType>,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+}
+
+lazy_static! {
+    // Commits are serialized anyway, concurrent requests would only race
+    // for the next version.
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        timeout_secs: Some(120),
+        ..Default::default()
+    };
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<DeltaLakeSinkConfig>("delta_lake")
+}
+
+#[typetag::serde(name = "delta_lake")]
+impl SinkConfig for DeltaLakeSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let client = S3Sink::create_client(
+            self.region.clone().try_into()?,
+            self.assume_role.clone(),
+            cx.resolver(),
+        )?;
+
+        let healthcheck = healthcheck(client.clone(), self.bucket.clone())
+            .boxed()
+            .compat();
+
+        let service = DeltaLakeService {
+            client,
+            bucket: self.bucket.clone(),
+            table: table_prefix(&self.table_path),
+            columns: columns::columns(&self.schema),
+            compression: self.compression,
+            log: Arc::new(Mutex::new(LogState::default())),
+        };
+
+        let batch = self.batch.unwrap_or(100_000, 300);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+
+        let sink = request
+            .batch_sink(DeltaRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|e| error!("Fatal delta_lake sink error: {}", e));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "delta_lake"
+    }
+}
+
+/// The key prefix of everything in the table, empty or ending in `/`.
+fn table_prefix(table_path: &str) -> String {
+    let path = table_path.trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", path)
+    }
+}
+
+/// Data files are named after their content, so a retried batch is
+/// written to the same file and can be recognized in the log.
+fn data_file_name(body: &[u8], compression: Compression) -> String {
+    let digest = hash(MessageDigest::sha256(), body).expect("SHA-256 is always available");
+    let digest = digest[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    match compression {
+        Compression::None => format!("part-{}.parquet", digest),
+        Compression::Gzip => format!("part-{}.gz.parquet", digest),
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum DeltaError {
+    #[snafu(display("Failed to write data file: {}", source))]
+    PutFile { source: RusotoError<PutObjectError> },
+    #[snafu(display("Failed to list the transaction log: {}", source))]
+    ListLog {
+        source: RusotoError<ListObjectsV2Error>,
+    },
+    #[snafu(display("Failed to get commit {}: {}", version, source))]
+    GetCommit {
+        version: u64,
+        source: RusotoError<GetObjectError>,
+    },
+    #[snafu(display("Failed to read commit {}: {}", version, source))]
+    ReadCommit {
+        version: u64,
+        source: std::io::Error,
+    },
+    #[snafu(display("Failed to write commit {}: {}", version, source))]
+    PutCommit {
+        version: u64,
+        source: RusotoError<PutObjectError>,
+    },
+}
+
+#[derive(Debug, Default)]
+struct LogState {
+    loaded: bool,
+    /// The latest version of the table, if it exists.
+    version: Option<u64>,
+}
+
+#[derive(Clone)]
+struct DeltaLakeService {
+    client: S3Client,
+    bucket: String,
+    table: String,
+    columns: Vec<Column>,
+    compression: Compression,
+    log: Arc<Mutex<LogState>>,
+}
+
+impl Service<Vec<Event>> for DeltaLakeService {
+    type Response = ();
+    type Error = DeltaError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, events: Vec<Event>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move { service.write(events).await })
+    }
+}
+
+impl DeltaLakeService {
+    async fn write(self, events: Vec<Event>) -> Result<(), DeltaError> {
+        let events = events.into_iter().map(Event::into_log).collect::<Vec<_>>();
+        let body = parquet::write(&self.columns, &events, self.compression);
+        let path = data_file_name(&body, self.compression);
+        let file = log::AddFile {
+            path: &path,
+            size: body.len(),
+            num_records: events.len(),
+            modification_time: Utc::now().timestamp_millis(),
+        };
+
+        self.client
+            .put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: format!("{}{}", self.table, path),
+                body: Some(body.into()),
+                ..Default::default()
+            })
+            .compat()
+            .await
+            .context(PutFile)?;
+
+        self.commit(&file).await
+    }
+
+    /// Adds the file in the next version of the table, unless a commit
+    /// since the last one we know of already did.
+    async fn commit(&self, file: &log::AddFile<'_>) -> Result<(), DeltaError> {
+        let mut state = self.log.lock().await;
+        if !state.loaded {
+            state.version = self.versions(None).await?.pop();
+            state.loaded = true;
+        }
+
+        for version in self.versions(state.version).await? {
+            let commit = self.read_commit(version).await?;
+            state.version = Some(version);
+            if log::adds_file(&commit, file.path) {
+                debug!(
+                    message = "file already committed.",
+                    path = file.path,
+                    version = version
+                );
+                return Ok(());
+            }
+        }
+
+        let version = state.version.map_or(0, |version| version + 1);
+        self.client
+            .put_object(PutObjectRequest {
+                bucket: self.bucket.clone(),
+                key: format!("{}{}", self.table, log::commit_key(version)),
+                body: Some(log::commit(version, &self.columns, file).into()),
+                content_type: Some("application/json".into()),
+                ..Default::default()
+            })
+            .compat()
+            .await
+            .context(PutCommit { version })?;
+        state.version = Some(version);
+
+        debug!(
+            message = "committed file.",
+            path = file.path,
+            version = version
+        );
+        Ok(())
+    }
+
+    /// The versions in the log after the given one, in order.
+    async fn versions(&self, after: Option<u64>) -> Result<Vec<u64>, DeltaError> {
+        let prefix = format!("{}{}", self.table, log::LOG_DIR);
+        let start_after =
+            after.map(|version| format!("{}{}", self.table, log::commit_key(version)));
+
+        let mut versions = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let response = self
+                .client
+                .list_objects_v2(ListObjectsV2Request {
+                    bucket: self.bucket.clone(),
+                    prefix: Some(prefix.clone()),
+                    start_after: start_after.clone(),
+                    continuation_token,
+                    ..Default::default()
+                })
+                .compat()
+                .await
+                .context(ListLog)?;
+
+            versions.extend(
+                response
+                    .contents
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|object| object.key)
+                    .filter_map(|key| log::version_of(&key[self.table.len()..])),
+            );
+
+            match response.next_continuation_token {
+                Some(token) if response.is_truncated == Some(true) => {
+                    continuation_token = Some(token)
+                }
+                _ => break,
+            }
+        }
+
+        versions.sort();
+        Ok(versions)
+    }
+
+    async fn read_commit(&self, version: u64) -> Result<Vec<u8>, DeltaError> {
+        let response = self
+            .client
+            .get_object(GetObjectRequest {
+                bucket: self.bucket.clone(),
+                key: format!("{}{}", self.table, log::commit_key(version)),
+                ..Default::default()
+            })
+            .compat()
+            .await
+            .context(GetCommit { version })?;
+
+        match response.body {
+            Some(body) => {
+                let bytes = body
+                    .concat2()
+                    .compat()
+                    .await
+                    .context(ReadCommit { version })?;
+                Ok(bytes.to_vec())
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn is_retriable<E>(error: &RusotoError<E>) -> bool {
+    match error {
+        RusotoError::HttpDispatch(_) => true,
+        RusotoError::Unknown(response) => response.status.is_server_error(),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DeltaRetryLogic;
+
+impl RetryLogic for DeltaRetryLogic {
+    type Error = DeltaError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        match error {
+            DeltaError::PutFile { source } => is_retriable(source),
+            DeltaError::ListLog { source } => is_retriable(source),
+            DeltaError::GetCommit { source, .. } => is_retriable(source),
+            DeltaError::ReadCommit { .. } => true,
+            DeltaError::PutCommit { source, .. } => is_retriable(source),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum HealthcheckError {
+    #[snafu(display("Unknown bucket: {:?}", bucket))]
+    UnknownBucket { bucket: String },
+}
+
+async fn healthcheck(client: S3Client, bucket: String) -> crate::Result<()> {
+    let response = client
+        .head_bucket(HeadBucketRequest {
+            bucket: bucket.clone(),
+        })
+        .compat()
+        .await;
+    match response {
+        Ok(_) => Ok(()),
+        Err(RusotoError::Unknown(response)) if response.status == StatusCode::NOT_FOUND => {
+            Err(HealthcheckError::UnknownBucket { bucket }.into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_lake_table_prefix() {
+        assert_eq!(table_prefix("/logs/events/"), "logs/events/");
+        assert_eq!(table_prefix("logs"), "logs/");
+        assert_eq!(table_prefix(""), "");
+    }
+
+    #[test]
+    fn delta_lake_data_file_name() {
+        let name = data_file_name(b"data", Compression::Gzip);
+        assert_eq!(name, data_file_name(b"data", Compression::Gzip));
+        assert_ne!(name, data_file_name(b"other", Compression::Gzip));
+        assert!(name.starts_with("part-"));
+        assert!(name.ends_with(".gz.parquet"));
+        assert_eq!(name.len(), "part-.gz.parquet".len() + 32);
+        assert_eq!(
+            data_file_name(b"data", Compression::None),
+            name.replace(".gz.parquet", ".parquet")
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Writes log events as a Parquet file with a single row group. Every
+//! column is optional and stored in one PLAIN encoded data page.
+//!
+//! See https://github.com/apache/parquet-format
+
+use super::thrift::{write_varint, Struct, Value as Thrift};
+use crate::{
+    event::{LogEvent, Value},
+    sinks::util::{
+        columns::{to_bool, to_f64, to_i64, to_string, to_timestamp_micros, Column, ColumnType},
+        Compression,
+    },
+};
+use flate2::write::GzEncoder;
+use std::io::Write;
+
+const MAGIC: &[u8] = b"PAR1";
+
+// `Type`
+const TYPE_BOOLEAN: i32 = 0;
+const TYPE_INT64: i32 = 2;
+const TYPE_DOUBLE: i32 = 5;
+const TYPE_BYTE_ARRAY: i32 = 6;
+
+// `ConvertedType`
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_TIMESTAMP_MICROS: i32 = 10;
+
+// `Encoding`
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+
+// `CompressionCodec`
+const CODEC_UNCOMPRESSED: i32 = 0;
+const CODEC_GZIP: i32 = 2;
+
+const REPETITION_OPTIONAL: i32 = 1;
+const PAGE_DATA: i32 = 0;
+
+pub fn write(columns: &[Column], events: &[LogEvent], compression: Compression) -> Vec<u8> {
+    let mut file = MAGIC.to_vec();
+    let mut chunks = Vec::with_capacity(columns.len());
+    let mut total_byte_size = 0;
+
+    for column in columns {
+        let values = events
+            .iter()
+            .map(|log| log.get(&column.name))
+            .collect::<Vec<_>>();
+        let page = page_data(column.column_type, &values);
+        let compressed = match compression {
+            Compression::None => page.clone(),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&page)
+                    .expect("Writing to a Vec can't fail");
+                encoder.finish().expect("Writing to a Vec can't fail")
+            }
+        };
+
+        let header = Struct::new()
+            .with(1, Thrift::I32(PAGE_DATA))
+            .with(2, Thrift::I32(page.len() as i32))
+            .with(3, Thrift::I32(compressed.len() as i32))
+            .with(
+                5,
+                Thrift::Struct(
+                    Struct::new()
+                        .with(1, Thrift::I32(events.len() as i32))
+                        .with(2, Thrift::I32(ENCODING_PLAIN))
+                        .with(3, Thrift::I32(ENCODING_RLE))
+                        .with(4, Thrift::I32(ENCODING_RLE)),
+                ),
+            )
+            .to_bytes();
+
+        let offset = file.len() as i64;
+        let uncompressed_size = (header.len() + page.len()) as i64;
+        file.extend_from_slice(&header);
+        file.extend_from_slice(&compressed);
+        total_byte_size += uncompressed_size;
+
+        let (physical_type, _) = types(column.column_type);
+        let codec = match compression {
+            Compression::None => CODEC_UNCOMPRESSED,
+            Compression::Gzip => CODEC_GZIP,
+        };
+        let meta_data = Struct::new()
+            .with(1, Thrift::I32(physical_type))
+            .with(
+                2,
+                Thrift::List(vec![Thrift::I32(ENCODING_PLAIN), Thrift::I32(ENCODING_RLE)]),
+            )
+            .with(3, Thrift::List(vec![Thrift::string(&column.name)]))
+            .with(4, Thrift::I32(codec))
+            .with(5, Thrift::I64(events.len() as i64))
+            .with(6, Thrift::I64(uncompressed_size))
+            .with(7, Thrift::I64((header.len() + compressed.len()) as i64))
+            .with(9, Thrift::I64(offset));
+        chunks.push(Thrift::Struct(
+            Struct::new()
+                .with(2, Thrift::I64(offset))
+                .with(3, Thrift::Struct(meta_data)),
+        ));
+    }
+
+    let row_group = Struct::new()
+        .with(1, Thrift::List(chunks))
+        .with(2, Thrift::I64(total_byte_size))
+        .with(3, Thrift::I64(events.len() as i64));
+
+    let footer = Struct::new()
+        .with(1, Thrift::I32(1))
+        .with(2, Thrift::List(schema(columns)))
+        .with(3, Thrift::I64(events.len() as i64))
+        .with(4, Thrift::List(vec![Thrift::Struct(row_group)]))
+        .with(
+            6,
+            Thrift::string(&format!("Vector {}", crate::get_version())),
+        )
+        .to_bytes();
+
+    file.extend_from_slice(&footer);
+    file.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+    file.extend_from_slice(MAGIC);
+    file
+}
+
+/// The physical and converted type of a column.
+fn types(column_type: ColumnType) -> (i32, Option<i32>) {
+    match column_type {
+        ColumnType::Utf8 => (TYPE_BYTE_ARRAY, Some(CONVERTED_UTF8)),
+        ColumnType::Int64 => (TYPE_INT64, None),
+        ColumnType::Float64 => (TYPE_DOUBLE, None),
+        ColumnType::Boolean => (TYPE_BOOLEAN, None),
+        ColumnType::Timestamp => (TYPE_INT64, Some(CONVERTED_TIMESTAMP_MICROS)),
+    }
+}
+
+fn schema(columns: &[Column]) -> Vec<Thrift> {
+    let root = Struct::new()
+        .with(4, Thrift::string("schema"))
+        .with(5, Thrift::I32(columns.len() as i32));
+
+    let mut elements = vec![Thrift::Struct(root)];
+    for column in columns {
+        let (physical_type, converted_type) = types(column.column_type);
+        let mut element = Struct::new()
+            .with(1, Thrift::I32(physical_type))
+            .with(3, Thrift::I32(REPETITION_OPTIONAL))
+            .with(4, Thrift::string(&column.name));
+        if let Some(converted_type) = converted_type {
+            element = element.with(6, Thrift::I32(converted_type));
+        }
+        elements.push(Thrift::Struct(element));
+    }
+    elements
+}
+
+/// The definition levels, prefixed with their length, followed by the
+/// values that are present.
+fn page_data(column_type: ColumnType, values: &[Option<&Value>]) -> Vec<u8> {
+    let mut present = Vec::with_capacity(values.len());
+    let mut data = Vec::new();
+    match column_type {
+        ColumnType::Utf8 => {
+            for value in values {
+                let value = value.and_then(to_string);
+                present.push(value.is_some());
+                if let Some(value) = value {
+                    data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                    data.extend_from_slice(value.as_bytes());
+                }
+            }
+        }
+        ColumnType::Int64 | ColumnType::Timestamp => {
+            let convert = if column_type == ColumnType::Int64 {
+                to_i64
+            } else {
+                to_timestamp_micros
+            };
+            for value in values {
+                let value = value.and_then(convert);
+                present.push(value.is_some());
+                if let Some(value) = value {
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        ColumnType::Float64 => {
+            for value in values {
+                let value = value.and_then(to_f64);
+                present.push(value.is_some());
+                if let Some(value) = value {
+                    data.extend_from_slice(&value.to_bits().to_le_bytes());
+                }
+            }
+        }
+        ColumnType::Boolean => {
+            let mut flags = Vec::new();
+            for value in values {
+                let value = value.and_then(to_bool);
+                present.push(value.is_some());
+                flags.extend(value);
+            }
+            data = bit_pack(&flags);
+        }
+    }
+
+    let levels = definition_levels(&present);
+    let mut page = Vec::with_capacity(4 + levels.len() + data.len());
+    page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+    page.extend_from_slice(&levels);
+    page.extend_from_slice(&data);
+    page
+}
+
+/// Encodes the levels of a flat optional column, which are 1 for present
+/// values, as RLE runs with a bit width of 1.
+fn definition_levels(present: &[bool]) -> Vec<u8> {
+    let mut levels = Vec::new();
+    let mut rest = present;
+    while let Some(first) = rest.first() {
+        let run = rest.iter().take_while(|value| *value == first).count();
+        write_varint(&mut levels, (run as u64) << 1);
+        levels.push(*first as u8);
+        rest = &rest[run..];
+    }
+    levels
+}
+
+fn bit_pack(flags: &[bool]) -> Vec<u8> {
+    let mut packed = vec![0; (flags.len() + 7) / 8];
+    for (index, flag) in flags.iter().enumerate() {
+        if *flag {
+            packed[index / 8] |= 1 << (index % 8);
+        }
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+
+    fn column(name: &str, column_type: ColumnType) -> Column {
+        Column {
+            name: name.into(),
+            column_type,
+        }
+    }
+
+    #[test]
+    fn parquet_definition_levels() {
+        assert_eq!(
+            definition_levels(&[true, true, false, true]),
+            vec![0x04, 1, 0x02, 0, 0x02, 1]
+        );
+        assert!(definition_levels(&[]).is_empty());
+    }
+
+    #[test]
+    fn parquet_page_data() {
+        let first = Value::from("ab");
+        let second = Value::from(true);
+        assert_eq!(
+            page_data(ColumnType::Utf8, &[Some(&first), None]),
+            vec![4, 0, 0, 0, 0x02, 1, 0x02, 0, 2, 0, 0, 0, b'a', b'b']
+        );
+        assert_eq!(
+            page_data(ColumnType::Boolean, &[None, Some(&second), Some(&second)]),
+            vec![4, 0, 0, 0, 0x02, 0, 0x04, 1, 0b11]
+        );
+    }
+
+    #[test]
+    fn parquet_file_layout() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("status", 200);
+        let columns = vec![
+            column("message", ColumnType::Utf8),
+            column("status", ColumnType::Int64),
+        ];
+
+        for compression in &[Compression::None, Compression::Gzip] {
+            let file = write(&columns, &[event.as_log().clone()], *compression);
+            assert_eq!(&file[..4], MAGIC);
+            assert_eq!(&file[file.len() - 4..], MAGIC);
+
+            let footer_len = file.len() - 8;
+            let mut len = [0; 4];
+            len.copy_from_slice(&file[footer_len..footer_len + 4]);
+            let footer_start = footer_len - u32::from_le_bytes(len) as usize;
+            assert!(footer_start > 4);
+            // The footer starts with the version.
+            assert_eq!(&file[footer_start..footer_start + 2], &[0x15, 0x02]);
+        }
+
+        let file = write(&columns, &[event.into_log()], Compression::None);
+        let needle = b"\x05\x00\x00\x00hello";
+        assert!(file.windows(needle.len()).any(|window| window == needle));
+    }
+}
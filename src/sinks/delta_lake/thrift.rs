@@ -0,0 +1,139 @@
+//! Just enough of the Thrift compact protocol to encode Parquet metadata.
+//!
+//! See https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+
+const TYPE_I32: u8 = 5;
+const TYPE_I64: u8 = 6;
+const TYPE_BINARY: u8 = 8;
+const TYPE_LIST: u8 = 9;
+const TYPE_STRUCT: u8 = 12;
+
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    Binary(Vec<u8>),
+    List(Vec<Value>),
+    Struct(Struct),
+}
+
+impl Value {
+    pub fn string(value: &str) -> Self {
+        Value::Binary(value.as_bytes().to_vec())
+    }
+
+    fn type_id(&self) -> u8 {
+        match self {
+            Value::I32(_) => TYPE_I32,
+            Value::I64(_) => TYPE_I64,
+            Value::Binary(_) => TYPE_BINARY,
+            Value::List(_) => TYPE_LIST,
+            Value::Struct(_) => TYPE_STRUCT,
+        }
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        match self {
+            Value::I32(value) => write_varint(output, zigzag(i64::from(*value))),
+            Value::I64(value) => write_varint(output, zigzag(*value)),
+            Value::Binary(value) => {
+                write_varint(output, value.len() as u64);
+                output.extend_from_slice(value);
+            }
+            Value::List(values) => {
+                // Lists are homogeneous, an empty one can claim any type.
+                let element_type = values.first().map_or(TYPE_I32, Value::type_id);
+                if values.len() < 15 {
+                    output.push((values.len() as u8) << 4 | element_type);
+                } else {
+                    output.push(0xf0 | element_type);
+                    write_varint(output, values.len() as u64);
+                }
+                for value in values {
+                    value.write(output);
+                }
+            }
+            Value::Struct(value) => value.write(output),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Struct {
+    fields: Vec<(i16, Value)>,
+}
+
+impl Struct {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the field with the given id, as numbered in the IDL. Fields
+    /// must be set in increasing order.
+    pub fn with(mut self, id: i16, value: Value) -> Self {
+        debug_assert!(self.fields.last().map_or(true, |(last, _)| *last < id));
+        self.fields.push((id, value));
+        self
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.write(&mut output);
+        output
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        let mut last = 0;
+        for (id, value) in &self.fields {
+            let delta = id - last;
+            if delta > 0 && delta <= 15 {
+                output.push((delta as u8) << 4 | value.type_id());
+            } else {
+                output.push(value.type_id());
+                write_varint(output, zigzag(i64::from(*id)));
+            }
+            value.write(output);
+            last = *id;
+        }
+        output.push(0);
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thrift_compact_struct() {
+        let bytes = Struct::new()
+            .with(1, Value::I32(-1))
+            .with(2, Value::string("ab"))
+            .with(20, Value::I64(300))
+            .with(21, Value::List(vec![Value::I32(1), Value::I32(2)]))
+            .with(22, Value::Struct(Struct::new()))
+            .to_bytes();
+
+        assert_eq!(
+            bytes,
+            vec![
+                0x15, 0x01, // field 1, i32 -1
+                0x18, 0x02, b'a', b'b', // field 2, binary
+                0x06, 0x28, 0xd8, 0x04, // field 20 in long form, i64 300
+                0x19, 0x25, 0x02, 0x04, // field 21, list of two i32
+                0x1c, 0x00, // field 22, empty struct
+                0x00,
+            ]
+        );
+    }
+}
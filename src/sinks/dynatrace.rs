@@ -0,0 +1,206 @@
+use crate::{
+    sinks::{
+        util::{
+            http2::BatchedHttpSink,
+            line_protocol::{LineProtocol, LineProtocolSink, Sample, SampleKind},
+            service2::TowerRequestConfig,
+            BatchEventsConfig, UriSerde,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures01::{future, Sink};
+use http02::{Request, Uri};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DynatraceConfig {
+    /// The metrics ingestion endpoint of either an environment or a local
+    /// OneAgent.
+    #[serde(default = "default_endpoint")]
+    pub endpoint: UriSerde,
+    /// An API token with the `metrics.ingest` scope, not needed by OneAgent.
+    pub api_token: Option<String>,
+    pub namespace: Option<String>,
+    /// Dimensions added to every metric, unless the metric has them already.
+    #[serde(default)]
+    pub tags: IndexMap<String, String>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+fn default_endpoint() -> UriSerde {
+    Uri::from_static("http://localhost:14499/metrics/ingest").into()
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<DynatraceConfig>("dynatrace")
+}
+
+#[typetag::serde(name = "dynatrace")]
+impl SinkConfig for DynatraceConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let batch = self.batch.unwrap_or(1000, 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig::default());
+
+        let protocol = Dynatrace {
+            uri: self.endpoint.clone().into(),
+            api_token: self.api_token.clone(),
+        };
+        let sink = LineProtocolSink::new(protocol, self.namespace.clone(), self.tags.clone());
+        let sink = BatchedHttpSink::new(sink, Vec::new(), request, batch, tls, &cx)
+            .sink_map_err(|e| error!("Fatal dynatrace sink error: {}", e));
+
+        // Ingest tokens can't read anything to check them against.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "dynatrace"
+    }
+}
+
+/// The Dynatrace metrics ingestion protocol,
+/// `name,dimension="value" gauge,value timestamp`.
+struct Dynatrace {
+    uri: Uri,
+    api_token: Option<String>,
+}
+
+impl LineProtocol for Dynatrace {
+    fn encode_sample(&self, sample: &Sample, output: &mut String) {
+        output.push_str(&sanitize_key(&sample.name));
+        for (key, value) in &sample.tags {
+            output.push(',');
+            output.push_str(&sanitize_key(key).to_lowercase());
+            output.push_str("=\"");
+            for c in value.chars() {
+                match c {
+                    '"' | '\\' => {
+                        output.push('\\');
+                        output.push(c);
+                    }
+                    '\n' => output.push(' '),
+                    c => output.push(c),
+                }
+            }
+            output.push('"');
+        }
+        let payload = match sample.kind {
+            SampleKind::Gauge => "gauge,",
+            SampleKind::Delta => "count,delta=",
+        };
+        output.push_str(&format!(
+            " {}{} {}",
+            payload,
+            sample.value,
+            sample.timestamp.timestamp_millis()
+        ));
+    }
+
+    fn build_request(&self, body: Vec<u8>) -> Request<Vec<u8>> {
+        let mut builder =
+            Request::post(self.uri.clone()).header("Content-Type", "text/plain; charset=utf-8");
+        if let Some(token) = &self.api_token {
+            builder = builder.header("Authorization", format!("Api-Token {}", token));
+        }
+        builder.body(body).unwrap()
+    }
+}
+
+/// Metric and dimension keys may only contain letters, digits, `-`, `_`
+/// and `.`, and have to start with a letter.
+fn sanitize_key(key: &str) -> String {
+    let key = key
+        .chars()
+        .map(|c| match c {
+            '-' | '_' | '.' => c,
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    match key.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => key,
+        _ => format!("m{}", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn sample(kind: SampleKind) -> Sample {
+        let mut tags = std::collections::BTreeMap::new();
+        tags.insert("Host".to_owned(), "web01".to_owned());
+        tags.insert("path".to_owned(), r#"C:\"temp""#.to_owned());
+        Sample {
+            name: "vector.requests total".into(),
+            kind,
+            value: 1.5,
+            tags,
+            timestamp: Utc.timestamp(1591005600, 0),
+        }
+    }
+
+    fn encode(sample: Sample) -> String {
+        let protocol = Dynatrace {
+            uri: "http://localhost:14499/metrics/ingest".parse().unwrap(),
+            api_token: None,
+        };
+        let mut output = String::new();
+        protocol.encode_sample(&sample, &mut output);
+        output
+    }
+
+    #[test]
+    fn dynatrace_encodes_lines() {
+        assert_eq!(
+            encode(sample(SampleKind::Gauge)),
+            r#"vector.requests_total,host="web01",path="C:\\\"temp\"" gauge,1.5 1591005600000"#
+        );
+        assert_eq!(
+            encode(sample(SampleKind::Delta)),
+            r#"vector.requests_total,host="web01",path="C:\\\"temp\"" count,delta=1.5 1591005600000"#
+        );
+    }
+
+    #[test]
+    fn dynatrace_sanitizes_keys() {
+        assert_eq!(sanitize_key("5xx responses"), "m5xx_responses");
+        assert_eq!(sanitize_key("cpu:load"), "cpu_load");
+    }
+
+    #[test]
+    fn dynatrace_authenticates_with_api_token() {
+        let config: DynatraceConfig = toml::from_str(
+            r#"
+            endpoint = "https://abc12345.live.dynatrace.com/api/v2/metrics/ingest"
+            api_token = "secret"
+            "#,
+        )
+        .unwrap();
+        let protocol = Dynatrace {
+            uri: config.endpoint.into(),
+            api_token: config.api_token,
+        };
+        let request = protocol.build_request(Vec::new());
+        assert_eq!(request.headers()["Authorization"], "Api-Token secret");
+        assert_eq!(
+            request.uri(),
+            "https://abc12345.live.dynatrace.com/api/v2/metrics/ingest"
+        );
+    }
+}
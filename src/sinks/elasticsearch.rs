@@ -11,12 +11,13 @@ use crate::{
         service2::TowerRequestConfig,
         BatchBytesConfig, Buffer, Compression,
     },
-    template::Template,
+    template::{Template, TimeZone},
     tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use bytes05::Bytes;
-use futures::{FutureExt, TryFutureExt};
+use chrono::Utc;
+use futures::{compat::Future01CompatExt, FutureExt, TryFutureExt};
 use futures01::Sink;
 use http02::{
     header::{HeaderName, HeaderValue},
@@ -27,19 +28,26 @@ use hyper13::Body;
 use lazy_static::lazy_static;
 use rusoto_core::signature::{SignedRequest, SignedRequestPayload};
 use rusoto_core::{DefaultCredentialsProvider, ProvideAwsCredentials, Region};
-use rusoto_credential::{AwsCredentials, CredentialsError};
+use rusoto_credential::{AwsCredentials, ChainProvider, CredentialsError};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use snafu::{ResultExt, Snafu};
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 #[serde(deny_unknown_fields)]
 pub struct ElasticSearchConfig {
     pub host: String,
     pub index: Option<String>,
+    /// The time zone of the dates in index names, UTC by default.
+    #[serde(default)]
+    pub timezone: TimeZone,
     pub doc_type: Option<String>,
+    #[serde(default)]
+    pub api_version: ApiVersion,
     pub id_key: Option<String>,
     #[serde(default)]
     pub compression: Compression,
@@ -75,11 +83,38 @@ pub enum Encoding {
     Default,
 }
 
+/// The version of the bulk API of the cluster, deciding whether documents are
+/// given a type.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum ApiVersion {
+    /// Documents have a type only if `doc_type` is set, and the healthcheck
+    /// verifies the cluster agrees.
+    #[derivative(Default)]
+    Auto,
+    /// Elasticsearch 6, where documents must have a type.
+    V6,
+    /// Elasticsearch 7 and OpenSearch 1, where types are deprecated.
+    V7,
+    /// Elasticsearch 8 and OpenSearch 2, where types are removed.
+    V8,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case", tag = "strategy")]
 pub enum ElasticSearchAuth {
-    Basic { user: String, password: String },
-    Aws,
+    Basic {
+        user: String,
+        password: String,
+    },
+    /// Requests are signed with AWS Signature Version 4, as Amazon
+    /// Elasticsearch and OpenSearch Service require.
+    Aws {
+        /// The service requests are signed for, `es` by default, or `aoss`
+        /// for OpenSearch Serverless.
+        service: Option<String>,
+    },
 }
 
 impl ElasticSearchAuth {
@@ -100,6 +135,10 @@ inventory::submit! {
 impl SinkConfig for ElasticSearchConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
         let common = ElasticSearchCommon::parse_config(&self)?;
+        if let Some(credentials) = &common.credentials {
+            cx.executor()
+                .spawn_std(refresh_credentials(Arc::clone(credentials)));
+        }
         let healthcheck = healthcheck(cx.resolver(), common.clone()).boxed().compat();
 
         let compression = common.compression;
@@ -135,9 +174,10 @@ pub struct ElasticSearchCommon {
     pub base_url: String,
     bulk_uri: Uri,
     authorization: Option<String>,
-    credentials: Option<AwsCredentials>,
+    credentials: Option<Arc<RwLock<AwsCredentials>>>,
+    service: String,
     index: Template,
-    doc_type: String,
+    doc_type: Option<String>,
     tls_settings: TlsSettings,
     config: ElasticSearchConfig,
     compression: Compression,
@@ -157,6 +197,30 @@ enum ParseError {
     AWSCredentialsGenerateFailed { source: CredentialsError },
     #[snafu(display("Compression can not be used with AWS hosted Elasticsearch"))]
     AWSCompressionNotAllowed,
+    #[snafu(display("doc_type can not be used with api_version v8"))]
+    DocTypeNotSupported,
+}
+
+#[derive(Debug, Snafu, PartialEq)]
+enum CompatibilityError {
+    #[snafu(display(
+        "{} {} requires documents to have a type, set doc_type or api_version = \"v6\"",
+        distribution,
+        version
+    ))]
+    TypeRequired {
+        distribution: String,
+        version: String,
+    },
+    #[snafu(display(
+        "{} {} does not support document types, unset doc_type",
+        distribution,
+        version
+    ))]
+    TypeNotSupported {
+        distribution: String,
+        version: String,
+    },
 }
 
 impl HttpSink for ElasticSearchCommon {
@@ -178,9 +242,11 @@ impl HttpSink for ElasticSearchCommon {
         let mut action = json!({
             "index": {
                 "_index": index,
-                "_type": self.doc_type,
             }
         });
+        if let Some(doc_type) = &self.doc_type {
+            action["index"]["_type"] = json!(doc_type);
+        }
         maybe_set_id(
             self.config.id_key.as_ref(),
             action.pointer_mut("/index").unwrap(),
@@ -217,6 +283,7 @@ impl HttpSink for ElasticSearchCommon {
             request.set_payload(Some(events));
 
             // mut builder?
+            let credentials = credentials.read().unwrap();
             builder = finish_signer(&mut request, &credentials, builder);
 
             // The SignedRequest ends up owning the body, so we have
@@ -317,7 +384,7 @@ impl ElasticSearchCommon {
 
         let credentials = match &config.auth {
             Some(ElasticSearchAuth::Basic { .. }) | None => None,
-            Some(ElasticSearchAuth::Aws) => {
+            Some(ElasticSearchAuth::Aws { .. }) => {
                 let provider =
                     DefaultCredentialsProvider::new().context(AWSCredentialsProviderFailed)?;
 
@@ -327,9 +394,15 @@ impl ElasticSearchCommon {
                     .block_on(provider.credentials())
                     .context(AWSCredentialsGenerateFailed)?;
 
-                Some(credentials)
+                Some(Arc::new(RwLock::new(credentials)))
             }
         };
+        let service = match &config.auth {
+            Some(ElasticSearchAuth::Aws {
+                service: Some(service),
+            }) => service.clone(),
+            _ => "es".into(),
+        };
 
         // Only allow compression if we are running with no AWS credentials.
         let compression = config.compression;
@@ -341,9 +414,17 @@ impl ElasticSearchCommon {
             Template::from(idx.as_str())
         } else {
             Template::from("vector-%Y.%m.%d")
-        };
+        }
+        .with_timezone(config.timezone);
 
-        let doc_type = config.doc_type.clone().unwrap_or("_doc".into());
+        let doc_type = match config.api_version {
+            ApiVersion::V6 => Some(config.doc_type.clone().unwrap_or_else(|| "_doc".into())),
+            ApiVersion::Auto | ApiVersion::V7 => config.doc_type.clone(),
+            ApiVersion::V8 if config.doc_type.is_some() => {
+                return Err(ParseError::DocTypeNotSupported.into())
+            }
+            ApiVersion::V8 => None,
+        };
 
         let request = config.request.unwrap_with(&REQUEST_DEFAULTS);
 
@@ -365,6 +446,7 @@ impl ElasticSearchCommon {
             bulk_uri,
             authorization,
             credentials,
+            service,
             index,
             doc_type,
             tls_settings,
@@ -376,7 +458,7 @@ impl ElasticSearchCommon {
     }
 
     fn signed_request(&self, method: &str, uri: &Uri, use_params: bool) -> SignedRequest {
-        let mut request = SignedRequest::new(method, "es", &self.region, uri.path());
+        let mut request = SignedRequest::new(method, &self.service, &self.region, uri.path());
         if use_params {
             for (key, value) in &self.query_params {
                 request.add_param(key, value);
@@ -384,30 +466,117 @@ impl ElasticSearchCommon {
         }
         request
     }
-}
-
-async fn healthcheck(resolver: Resolver, common: ElasticSearchCommon) -> crate::Result<()> {
-    let mut builder = Request::get(format!("{}/_cluster/health", common.base_url));
 
-    match &common.credentials {
-        None => {
-            if let Some(authorization) = &common.authorization {
-                builder = builder.header("Authorization", authorization.clone());
+    fn get_request(&self, uri: &str) -> crate::Result<Request<Body>> {
+        let mut builder = Request::get(uri);
+        match &self.credentials {
+            None => {
+                if let Some(authorization) = &self.authorization {
+                    builder = builder.header("Authorization", authorization.clone());
+                }
+            }
+            Some(credentials) => {
+                let mut signer = self.signed_request("GET", builder.uri_ref().unwrap(), false);
+                let credentials = credentials.read().unwrap();
+                builder = finish_signer(&mut signer, &credentials, builder);
             }
         }
-        Some(credentials) => {
-            let mut signer = common.signed_request("GET", builder.uri_ref().unwrap(), false);
-            builder = finish_signer(&mut signer, &credentials, builder);
+        Ok(builder.body(Body::empty())?)
+    }
+}
+
+/// Fetches new credentials ahead of the current ones expiring, as those of
+/// instance profiles and assumed roles do within hours.
+async fn refresh_credentials(credentials: Arc<RwLock<AwsCredentials>>) {
+    let provider = ChainProvider::new();
+    loop {
+        let expires_at = match *credentials.read().unwrap().expires_at() {
+            Some(expires_at) => expires_at,
+            None => return,
+        };
+        let refresh_in = (expires_at - Utc::now() - chrono::Duration::minutes(5))
+            .to_std()
+            .unwrap_or_default()
+            .max(Duration::from_secs(10));
+        tokio::time::delay_for(refresh_in).await;
+        // The sink is gone.
+        if Arc::strong_count(&credentials) == 1 {
+            return;
+        }
+
+        match provider.credentials().compat().await {
+            Ok(fresh) => *credentials.write().unwrap() = fresh,
+            Err(error) => error!(message = "Unable to refresh AWS credentials.", %error),
         }
     }
-    let request = builder.body(Body::empty())?;
+}
+
+/// Checks the cluster is healthy, and that its version agrees with whether
+/// documents are given a type.
+async fn healthcheck(resolver: Resolver, common: ElasticSearchCommon) -> crate::Result<()> {
     let mut client = HttpClient::new(resolver, common.tls_settings.clone())?;
+
+    let request = common.get_request(&format!("{}/_cluster/health", common.base_url))?;
     let response = client.send(request).await?;
+    if response.status() != StatusCode::OK {
+        let status = response.status();
+        return Err(super::HealthcheckError::UnexpectedStatus2 { status }.into());
+    }
 
-    match response.status() {
-        StatusCode::OK => Ok(()),
-        status => Err(super::HealthcheckError::UnexpectedStatus2 { status }.into()),
+    let request = common.get_request(&common.base_url)?;
+    let response = client.send(request).await?;
+    if response.status() != StatusCode::OK {
+        let status = response.status();
+        return Err(super::HealthcheckError::UnexpectedStatus2 { status }.into());
+    }
+    let body = hyper13::body::to_bytes(response.into_body()).await?;
+    // Clusters not saying which version they are, like OpenSearch
+    // Serverless, are taken at their word.
+    if let Ok(info) = serde_json::from_slice::<Value>(&body) {
+        check_compatibility(&info, common.doc_type.is_some())?;
     }
+    Ok(())
+}
+
+fn check_compatibility(info: &Value, typed: bool) -> Result<(), CompatibilityError> {
+    let version = match info.pointer("/version/number").and_then(Value::as_str) {
+        Some(version) => version,
+        None => return Ok(()),
+    };
+    let major = version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+        .unwrap_or_default();
+    let opensearch = info
+        .pointer("/version/distribution")
+        .and_then(Value::as_str)
+        == Some("opensearch");
+    let distribution = if opensearch {
+        "OpenSearch"
+    } else {
+        "Elasticsearch"
+    };
+
+    // OpenSearch forked from Elasticsearch 7, and removed types in 2.
+    let (requires_type, supports_type) = if opensearch {
+        (false, major < 2)
+    } else {
+        (major < 7, major < 8)
+    };
+    if requires_type && !typed {
+        return Err(CompatibilityError::TypeRequired {
+            distribution: distribution.into(),
+            version: version.into(),
+        });
+    }
+    if !supports_type && typed {
+        return Err(CompatibilityError::TypeNotSupported {
+            distribution: distribution.into(),
+            version: version.into(),
+        });
+    }
+    Ok(())
 }
 
 fn finish_signer(
@@ -499,6 +668,61 @@ mod tests {
             RetryAction::DontRetry(_)
         ));
     }
+
+    #[test]
+    fn checks_document_types_against_version() {
+        let elasticsearch = |version| json!({"version": {"number": version}});
+        let opensearch =
+            |version| json!({"version": {"number": version, "distribution": "opensearch"}});
+
+        assert!(check_compatibility(&elasticsearch("6.8.0"), true).is_ok());
+        assert_eq!(
+            check_compatibility(&elasticsearch("6.8.0"), false),
+            Err(CompatibilityError::TypeRequired {
+                distribution: "Elasticsearch".into(),
+                version: "6.8.0".into(),
+            })
+        );
+        assert!(check_compatibility(&elasticsearch("7.10.2"), true).is_ok());
+        assert!(check_compatibility(&elasticsearch("8.1.0"), true).is_err());
+        assert!(check_compatibility(&opensearch("1.3.0"), false).is_ok());
+        assert_eq!(
+            check_compatibility(&opensearch("2.5.0"), true),
+            Err(CompatibilityError::TypeNotSupported {
+                distribution: "OpenSearch".into(),
+                version: "2.5.0".into(),
+            })
+        );
+        assert!(check_compatibility(&json!({"status": "ok"}), true).is_ok());
+    }
+
+    #[test]
+    fn omits_document_type_unless_configured() {
+        let config = ElasticSearchConfig {
+            host: "http://localhost:9200".into(),
+            index: Some("vector".into()),
+            ..Default::default()
+        };
+        let common = ElasticSearchCommon::parse_config(&config).unwrap();
+        let body = common.encode_event(Event::from("hello")).unwrap();
+        let action = body.split(|b| *b == b'\n').next().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(action).unwrap(),
+            json!({"index": {"_index": "vector"}})
+        );
+
+        let config = ElasticSearchConfig {
+            api_version: ApiVersion::V6,
+            ..config
+        };
+        let common = ElasticSearchCommon::parse_config(&config).unwrap();
+        let body = common.encode_event(Event::from("hello")).unwrap();
+        let action = body.split(|b| *b == b'\n').next().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(action).unwrap(),
+            json!({"index": {"_index": "vector", "_type": "_doc"}})
+        );
+    }
 }
 
 #[cfg(test)]
@@ -610,7 +834,7 @@ mod integration_tests {
     fn insert_events_on_aws() {
         run_insert_tests(
             ElasticSearchConfig {
-                auth: Some(ElasticSearchAuth::Aws),
+                auth: Some(ElasticSearchAuth::Aws { service: None }),
                 host: "http://localhost:4571".into(),
                 ..config()
             },
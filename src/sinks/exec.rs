@@ -0,0 +1,232 @@
+//! Writes events to the stdin of a process, one per line, running it again
+//! whenever it exits.
+//!
+//! The process is written to as fast as it reads; a full pipe holds back
+//! the events behind it. Events written to a process that exits before
+//! reading them are lost.
+
+use crate::{
+    event::Event,
+    internal_events::{ExecEventSent, ExecProcessExited, ExecProcessFailed},
+    sinks::{
+        streaming_sink::{self, StreamingSink},
+        util::{encode_event, encoding::EncodingConfig, Encoding, StreamSink},
+    },
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{
+    future::{select, Either},
+    pin_mut,
+    stream::{Stream, StreamExt},
+};
+use futures01::future;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{collections::BTreeMap, io, path::PathBuf, process::Stdio, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, Command},
+    time::delay_for,
+};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`command` must name the program to run"))]
+    EmptyCommand,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecSinkConfig {
+    /// The program to run, followed by its arguments.
+    pub command: Vec<String>,
+    pub encoding: EncodingConfig<Encoding>,
+    /// Set for the process, on top of the environment of Vector unless
+    /// `clear_environment`.
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+    #[serde(default)]
+    pub clear_environment: bool,
+    pub working_directory: Option<PathBuf>,
+    #[serde(default = "default_restart_delay_secs")]
+    pub restart_delay_secs: u64,
+}
+
+fn default_restart_delay_secs() -> u64 {
+    5
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<ExecSinkConfig>("exec")
+}
+
+#[typetag::serde(name = "exec")]
+impl SinkConfig for ExecSinkConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        if self.command.is_empty() {
+            return Err(BuildError::EmptyCommand.into());
+        }
+
+        let sink = ExecSink {
+            config: self.clone(),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "exec"
+    }
+}
+
+impl ExecSinkConfig {
+    fn command_line(&self) -> String {
+        self.command.join(" ")
+    }
+
+    /// The output of the process goes where Vector's goes.
+    fn spawn(&self) -> io::Result<Child> {
+        let mut command = Command::new(&self.command[0]);
+        command.args(&self.command[1..]);
+        if self.clear_environment {
+            command.env_clear();
+        }
+        command.envs(&self.environment);
+        if let Some(directory) = &self.working_directory {
+            command.current_dir(directory);
+        }
+        command.stdin(Stdio::piped());
+        // A process still running at shutdown once its stdin is closed is
+        // killed.
+        command.kill_on_drop(true);
+        command.spawn()
+    }
+}
+
+struct ExecSink {
+    config: ExecSinkConfig,
+}
+
+#[async_trait]
+impl StreamingSink for ExecSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        let command = self.config.command_line();
+        let restart_delay = Duration::from_secs(self.config.restart_delay_secs);
+        pin_mut!(input);
+        // The line the last process exited before taking is written to the
+        // next one.
+        let mut pending: Option<Bytes> = None;
+
+        loop {
+            let mut child = match self.config.spawn() {
+                Ok(child) => child,
+                Err(error) => {
+                    emit!(ExecProcessFailed {
+                        command: &command,
+                        error
+                    });
+                    delay_for(restart_delay).await;
+                    continue;
+                }
+            };
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+
+            loop {
+                let line = match pending.take() {
+                    Some(line) => line,
+                    None => {
+                        let next = match select(input.next(), &mut child).await {
+                            Either::Left((event, _)) => Ok(event),
+                            Either::Right((status, _)) => Err(status),
+                        };
+                        match next {
+                            Ok(Some(event)) => match encode_event(event, &self.config.encoding) {
+                                Some(line) => line,
+                                None => continue,
+                            },
+                            Ok(None) => {
+                                // Closing stdin lets the process finish with
+                                // the events written.
+                                drop(stdin);
+                                child.await?;
+                                return Ok(());
+                            }
+                            Err(status) => {
+                                emit!(ExecProcessExited {
+                                    command: &command,
+                                    exit_status: status.ok().and_then(|status| status.code()),
+                                });
+                                break;
+                            }
+                        }
+                    }
+                };
+
+                if stdin.write_all(&line).await.is_err() {
+                    pending = Some(line);
+                    let status = (&mut child).await;
+                    emit!(ExecProcessExited {
+                        command: &command,
+                        exit_status: status.ok().and_then(|status| status.code()),
+                    });
+                    break;
+                }
+                emit!(ExecEventSent {
+                    byte_size: line.len()
+                });
+            }
+
+            delay_for(restart_delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{random_lines, runtime};
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_lines_to_process() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("out.log");
+        let config = ExecSinkConfig {
+            command: vec!["sh".into(), "-c".into(), "cat >> \"$OUT\"".into()],
+            encoding: Encoding::Text.into(),
+            environment: vec![("OUT".to_owned(), path.to_string_lossy().into_owned())]
+                .into_iter()
+                .collect(),
+            clear_environment: false,
+            working_directory: None,
+            restart_delay_secs: 0,
+        };
+
+        let lines = random_lines(20).take(10).collect::<Vec<_>>();
+        let events = lines
+            .iter()
+            .map(|line| Event::from(&line[..]))
+            .collect::<Vec<_>>();
+
+        let mut rt = runtime();
+        rt.block_on_std(async move {
+            let mut sink = ExecSink { config };
+            sink.run(futures::stream::iter(events)).await.unwrap();
+        });
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written.lines().collect::<Vec<_>>(), lines);
+    }
+}
@@ -1,11 +1,12 @@
 use crate::expiring_hash_map::ExpiringHashMap;
 use crate::{
     event::{self, Event},
+    permissions::{Permissions, PermissionsConfig},
     sinks::util::{
         encoding::{EncodingConfigWithDefault, EncodingConfiguration},
         StreamSink,
     },
-    template::Template,
+    template::{Template, TimeZone},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use async_trait::async_trait;
@@ -13,10 +14,15 @@ use bytes::Bytes;
 use futures::pin_mut;
 use futures::stream::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::time::{Duration, Instant};
+use std::{
+    io,
+    path::Path,
+    time::{Duration, Instant},
+};
 use tokio::{
     fs::{self, File},
     io::AsyncWriteExt,
+    task::spawn_blocking,
 };
 
 mod bytes_path;
@@ -28,12 +34,30 @@ use super::streaming_sink::{self, StreamingSink};
 #[serde(deny_unknown_fields)]
 pub struct FileSinkConfig {
     pub path: Template,
+    /// The time zone of the dates in paths, UTC by default.
+    #[serde(
+        default,
+        skip_serializing_if = "crate::serde::skip_serializing_if_default"
+    )]
+    pub timezone: TimeZone,
     pub idle_timeout_secs: Option<u64>,
     #[serde(
         default,
         skip_serializing_if = "crate::serde::skip_serializing_if_default"
     )]
     pub encoding: EncodingConfigWithDefault<Encoding>,
+    /// Given to the files as they are created.
+    #[serde(
+        default,
+        skip_serializing_if = "crate::serde::skip_serializing_if_default"
+    )]
+    pub permissions: PermissionsConfig,
+    /// Given to the directories created for the files.
+    #[serde(
+        default,
+        skip_serializing_if = "crate::serde::skip_serializing_if_default"
+    )]
+    pub directory_permissions: PermissionsConfig,
 }
 
 inventory::submit! {
@@ -56,7 +80,7 @@ impl Default for Encoding {
 #[typetag::serde(name = "file")]
 impl SinkConfig for FileSinkConfig {
     fn build(&self, mut cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let sink = FileSink::new(&self);
+        let sink = FileSink::new(&self)?;
         let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
         let sink = StreamSink::new(sink, cx.acker());
         Ok((Box::new(sink), Box::new(futures01::future::ok(()))))
@@ -76,17 +100,21 @@ pub struct FileSink {
     path: Template,
     encoding: EncodingConfigWithDefault<Encoding>,
     idle_timeout: Duration,
+    permissions: Permissions,
+    directory_permissions: Permissions,
     files: ExpiringHashMap<Bytes, File>,
 }
 
 impl FileSink {
-    pub fn new(config: &FileSinkConfig) -> Self {
-        Self {
-            path: config.path.clone(),
+    pub fn new(config: &FileSinkConfig) -> crate::Result<Self> {
+        Ok(Self {
+            path: config.path.clone().with_timezone(config.timezone),
             encoding: config.encoding.clone(),
             idle_timeout: Duration::from_secs(config.idle_timeout_secs.unwrap_or(30)),
+            permissions: config.permissions.build()?,
+            directory_permissions: config.directory_permissions.build()?,
             files: ExpiringHashMap::new(),
-        }
+        })
     }
 
     /// Uses pass the `event` to `self.path` template to obtain the file path
@@ -170,7 +198,12 @@ impl FileSink {
             file
         } else {
             trace!(message = "Opening new file.", ?path);
-            let file = match open_file(BytesPath::new(path.clone())).await {
+            let file = open_file(
+                BytesPath::new(path.clone()),
+                self.permissions,
+                self.directory_permissions,
+            );
+            let file = match file.await {
                 Ok(file) => file,
                 Err(error) => {
                     // We coundn't open the file for this event.
@@ -191,19 +224,33 @@ impl FileSink {
     }
 }
 
-async fn open_file(path: impl AsRef<std::path::Path>) -> std::io::Result<File> {
-    let parent = path.as_ref().parent();
+async fn open_file(
+    path: impl AsRef<Path>,
+    permissions: Permissions,
+    directory_permissions: Permissions,
+) -> io::Result<File> {
+    let path = path.as_ref().to_owned();
 
-    if let Some(parent) = parent {
-        fs::create_dir_all(parent).await?;
+    if let Some(parent) = path.parent() {
+        if directory_permissions == Permissions::default() {
+            fs::create_dir_all(parent).await?;
+        } else {
+            let parent = parent.to_owned();
+            spawn_blocking(move || directory_permissions.create_dir_all(&parent)).await??;
+        }
     }
 
-    fs::OpenOptions::new()
+    let created = fs::metadata(&path).await.is_err();
+    let file = fs::OpenOptions::new()
         .read(false)
         .write(true)
         .create(true)
-        .open(path)
-        .await
+        .open(&path)
+        .await?;
+    if created && permissions != Permissions::default() {
+        spawn_blocking(move || permissions.apply(&path)).await??;
+    }
+    Ok(file)
 }
 
 pub fn encode_event(encoding: &EncodingConfigWithDefault<Encoding>, mut event: Event) -> Vec<u8> {
@@ -258,11 +305,14 @@ mod tests {
 
         let config = FileSinkConfig {
             path: template.clone().into(),
+            timezone: Default::default(),
             idle_timeout_secs: None,
             encoding: Encoding::Text.into(),
+            permissions: Default::default(),
+            directory_permissions: Default::default(),
         };
 
-        let mut sink = FileSink::new(&config);
+        let mut sink = FileSink::new(&config).unwrap();
         let (input, _) = random_lines_with_stream(100, 64);
 
         let events = stream::iter(input.clone().into_iter().map(Event::from));
@@ -291,11 +341,14 @@ mod tests {
 
         let config = FileSinkConfig {
             path: template.clone().into(),
+            timezone: Default::default(),
             idle_timeout_secs: None,
             encoding: Encoding::Text.into(),
+            permissions: Default::default(),
+            directory_permissions: Default::default(),
         };
 
-        let mut sink = FileSink::new(&config);
+        let mut sink = FileSink::new(&config).unwrap();
 
         let (mut input, _) = random_events_with_stream(32, 8);
         input[0].as_mut_log().insert("date", "2019-26-07");
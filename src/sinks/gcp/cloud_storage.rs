@@ -5,19 +5,25 @@ use crate::{
     sinks::{
         util::{
             encoding::{EncodingConfig, EncodingConfiguration},
-            http::{HttpClient, HttpClientFuture},
+            http::HttpClient,
+            manifest::{
+                event_timestamp, with_timestamp, write_manifests, Manifest, ManifestConfig,
+                ManifestEntry, ObjectStats, StatsBuffer,
+            },
             retries::{RetryAction, RetryLogic},
-            BatchBytesConfig, Buffer, Compression, PartitionBatchSink, PartitionBuffer,
+            watermark::{write_watermarks, WatermarkConfig, Watermarks},
+            BatchBytesConfig, Buffer, Compression, Partition, PartitionBatchSink, PartitionBuffer,
             PartitionInnerBuffer, ServiceBuilderExt, TowerRequestConfig,
         },
         Healthcheck, RouterSink,
     },
-    template::Template,
+    template::{Template, TimeZone},
     tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
 use bytes::Bytes;
 use chrono::Utc;
+use futures::{compat::Future01CompatExt, future::BoxFuture, FutureExt};
 use futures01::{stream::iter_ok, Future, Poll, Sink};
 use http::{Method, StatusCode, Uri};
 use hyper::{
@@ -42,12 +48,19 @@ struct GcsSink {
     creds: Option<GcpCredentials>,
     base_url: String,
     settings: RequestSettings,
+    manifest: Option<Manifest>,
+    watermarks: Option<Watermarks>,
 }
 
 #[derive(Debug, Snafu)]
 enum GcsError {
     #[snafu(display("Bucket {:?} not found", bucket))]
     BucketNotFound { bucket: String },
+    #[snafu(display("Object {:?} not written, status code: {}", key, status))]
+    ObjectNotWritten {
+        key: String,
+        status: http::StatusCode,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -61,6 +74,8 @@ pub struct GcsSinkConfig {
     filename_time_format: Option<String>,
     filename_append_uuid: Option<bool>,
     filename_extension: Option<String>,
+    #[serde(default)]
+    timezone: TimeZone,
     encoding: EncodingConfig<Encoding>,
     #[serde(default)]
     compression: Compression,
@@ -71,6 +86,10 @@ pub struct GcsSinkConfig {
     #[serde(flatten)]
     auth: GcpAuthConfig,
     tls: Option<TlsOptions>,
+    /// Periodically writes manifests listing the objects written.
+    manifest: Option<ManifestConfig>,
+    /// Periodically writes the watermarks of the partitions written to.
+    watermark: Option<WatermarkConfig>,
 }
 
 #[cfg(test)]
@@ -84,12 +103,15 @@ fn default_config(e: Encoding) -> GcsSinkConfig {
         filename_time_format: Default::default(),
         filename_append_uuid: Default::default(),
         filename_extension: Default::default(),
+        timezone: Default::default(),
         encoding: e.into(),
         compression: Compression::Gzip,
         batch: Default::default(),
         request: Default::default(),
         auth: Default::default(),
         tls: Default::default(),
+        manifest: Default::default(),
+        watermark: Default::default(),
     }
 }
 
@@ -188,10 +210,12 @@ impl GcsSink {
             settings,
             base_url,
             bucket,
+            manifest: None,
+            watermarks: None,
         })
     }
 
-    fn service(self, config: &GcsSinkConfig, cx: &SinkContext) -> crate::Result<RouterSink> {
+    fn service(mut self, config: &GcsSinkConfig, cx: &SinkContext) -> crate::Result<RouterSink> {
         let request = config.request.unwrap_with(&REQUEST_DEFAULTS);
         let encoding = config.encoding.clone();
 
@@ -201,20 +225,37 @@ impl GcsSink {
             Template::from(kp.as_str())
         } else {
             Template::from("date=%F/")
-        };
+        }
+        .with_timezone(config.timezone);
 
         let settings = self.settings.clone();
+        self.manifest = config
+            .manifest
+            .as_ref()
+            .map(|manifest| self.spawn_manifests(cx, manifest));
+        self.watermarks = config
+            .watermark
+            .as_ref()
+            .map(|watermark| self.spawn_watermarks(cx, watermark));
+        let watermarks = self.watermarks.clone();
 
         let svc = ServiceBuilder::new()
             .map(move |req| RequestWrapper::new(req, settings.clone()))
             .settings(request, GcsRetryLogic)
             .service(self);
 
-        let buffer = PartitionBuffer::new(Buffer::new(config.compression));
+        let buffer = PartitionBuffer::new(StatsBuffer::new(Buffer::new(config.compression)));
 
         let sink = PartitionBatchSink::new(svc, buffer, batch, cx.acker())
             .sink_map_err(|e| error!("Fatal gcs sink error: {}", e))
-            .with_flat_map(move |e| iter_ok(encode_event(e, &key_prefix, &encoding)));
+            .with_flat_map(move |e| {
+                let timestamp = event_timestamp(&e);
+                let encoded = encode_event(e, &key_prefix, &encoding);
+                if let (Some(watermarks), Some(encoded)) = (&watermarks, &encoded) {
+                    watermarks.received(&encoded.partition(), timestamp);
+                }
+                iter_ok(encoded.map(|encoded| with_timestamp(encoded, timestamp)))
+            });
 
         Ok(Box::new(sink))
     }
@@ -243,12 +284,63 @@ impl GcsSink {
 
         Ok(Box::new(healthcheck))
     }
+
+    /// Writes the manifests of the objects of the sink to its bucket.
+    fn spawn_manifests(&self, cx: &SinkContext, config: &ManifestConfig) -> Manifest {
+        let manifest = Manifest::default();
+        cx.executor().spawn_std(write_manifests(
+            manifest.clone(),
+            config.clone(),
+            self.put_json(),
+        ));
+        manifest
+    }
+
+    /// Writes the watermarks of the partitions of the sink to its bucket.
+    fn spawn_watermarks(&self, cx: &SinkContext, config: &WatermarkConfig) -> Watermarks {
+        let watermarks = Watermarks::default();
+        cx.executor().spawn_std(write_watermarks(
+            watermarks.clone(),
+            config.clone(),
+            NAME,
+            self.put_json(),
+        ));
+        watermarks
+    }
+
+    fn put_json(&self) -> impl Fn(String, Vec<u8>) -> BoxFuture<'static, crate::Result<()>> {
+        let sink = self.clone();
+        move |key, body| {
+            let mut builder = Request::builder();
+            builder.method(Method::PUT);
+            builder.uri(format!("{}{}", sink.base_url, key).as_str());
+            builder.header("content-type", "application/json");
+            let mut request = builder.body(Body::from(body)).unwrap();
+            if let Some(creds) = &sink.creds {
+                creds.apply(&mut request);
+            }
+
+            sink.client
+                .clone()
+                .call(request)
+                .compat()
+                .map(|response| {
+                    let status = response?.status();
+                    if status.is_success() {
+                        Ok(())
+                    } else {
+                        Err(GcsError::ObjectNotWritten { key, status }.into())
+                    }
+                })
+                .boxed()
+        }
+    }
 }
 
 impl Service<RequestWrapper> for GcsSink {
     type Response = hyper::Response<Body>;
     type Error = hyper::Error;
-    type Future = HttpClientFuture;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error> + Send>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         Ok(().into())
@@ -278,29 +370,56 @@ impl Service<RequestWrapper> for GcsSink {
             headers.insert(p, v);
         }
 
+        let written = match &self.manifest {
+            Some(manifest) => {
+                let entry = ManifestEntry::new(request.key, &request.body, request.stats.clone());
+                Some((manifest.clone(), entry))
+            }
+            None => None,
+        };
+        let watermark = match &self.watermarks {
+            Some(watermarks) => Some((watermarks.clone(), request.partition, request.stats)),
+            None => None,
+        };
         let mut request = builder.body(Body::from(request.body)).unwrap();
         if let Some(creds) = &self.creds {
             creds.apply(&mut request);
         }
 
-        self.client.call(request)
+        let response = self.client.call(request).map(move |response| {
+            if response.status().is_success() {
+                if let Some((manifest, entry)) = written {
+                    manifest.record(entry);
+                }
+                if let Some((watermarks, partition, stats)) = watermark {
+                    watermarks.written(&partition, &stats);
+                }
+            }
+            response
+        });
+        Box::new(response)
     }
 }
 
 #[derive(Clone, Debug)]
 struct RequestWrapper {
     body: Vec<u8>,
+    stats: ObjectStats,
+    partition: Bytes,
     key: String,
     settings: RequestSettings,
 }
 
 impl RequestWrapper {
-    fn new(req: PartitionInnerBuffer<Vec<u8>, Bytes>, settings: RequestSettings) -> Self {
-        let (body, key) = req.into_parts();
+    fn new(
+        req: PartitionInnerBuffer<(Vec<u8>, ObjectStats), Bytes>,
+        settings: RequestSettings,
+    ) -> Self {
+        let ((body, stats), partition) = req.into_parts();
 
         // TODO: pull the seconds from the last event
         let filename = {
-            let seconds = Utc::now().format(&settings.time_format);
+            let seconds = settings.timezone.format(Utc::now(), &settings.time_format);
 
             if settings.append_uuid {
                 let uuid = Uuid::new_v4();
@@ -312,7 +431,7 @@ impl RequestWrapper {
 
         let key = format!(
             "{}{}.{}",
-            String::from_utf8_lossy(&key[..]),
+            String::from_utf8_lossy(&partition[..]),
             filename,
             settings.extension
         );
@@ -325,6 +444,8 @@ impl RequestWrapper {
 
         Self {
             body,
+            stats,
+            partition,
             key,
             settings,
         }
@@ -343,6 +464,7 @@ struct RequestSettings {
     metadata: Vec<(HeaderName, HeaderValue)>,
     extension: String,
     time_format: String,
+    timezone: TimeZone,
     append_uuid: bool,
 }
 
@@ -382,6 +504,7 @@ impl RequestSettings {
             metadata,
             extension,
             time_format,
+            timezone: config.timezone,
             append_uuid,
         })
     }
@@ -519,7 +642,8 @@ mod tests {
 
     #[test]
     fn gcs_build_request() {
-        let buf = PartitionInnerBuffer::new(vec![0u8; 10], Bytes::from("key/"));
+        let buf =
+            PartitionInnerBuffer::new((vec![0u8; 10], ObjectStats::default()), Bytes::from("key/"));
 
         let req = RequestWrapper::new(
             buf.clone(),
@@ -1,38 +1,65 @@
 use super::{healthcheck_response2, GcpAuthConfig, GcpCredentials, Scope};
 use crate::{
     event::Event,
+    internal_events::{GcpPubsubMessageTooLarge, GcpPubsubOrderingKeyMissing},
     sinks::{
         util::{
             encoding::{EncodingConfigWithDefault, EncodingConfiguration},
-            http2::{BatchedHttpSink, HttpClient, HttpSink},
+            http2::{BatchedHttpSink, HttpClient, HttpRetryLogic, HttpSink},
+            retries2::{RetryAction, RetryLogic},
             service2::TowerRequestConfig,
-            BatchBytesConfig, BoxedRawValue, JsonArrayBuffer,
+            Batch, BatchBytesConfig, BoxedRawValue,
         },
         Healthcheck, RouterSink, UriParseError2,
     },
+    template::Template,
     tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
+use bytes05::Bytes;
 use futures::{FutureExt, TryFutureExt};
 use futures01::Sink;
-use http02::{Method, Request, Uri};
+use http02::{Method, Request, Response, Uri};
 use hyper13::Body;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
+use serde_json::{json, value::RawValue};
 use snafu::{ResultExt, Snafu};
 
+/// The limits of a publish request.
+const MAX_REQUEST_BYTES: usize = 10_000_000;
+const MAX_MESSAGES: usize = 1000;
+/// The bytes of the request body around the messages, `{"messages":[]}`.
+const ENVELOPE_BYTES: usize = 15;
+
 #[derive(Debug, Snafu)]
 enum HealthcheckError {
     #[snafu(display("Configured topic not found"))]
     TopicNotFound,
 }
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display(
+        "`batch.max_size` is {}, but pubsub accepts at most {} bytes per request",
+        max_size,
+        MAX_REQUEST_BYTES
+    ))]
+    BatchMaxSizeTooLarge { max_size: usize },
+    #[snafu(display(
+        "`request.in_flight_limit` must be 1 with `ordering_key`, so messages are published in order"
+    ))]
+    OrderedInFlightLimit,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 #[serde(deny_unknown_fields)]
 pub struct PubsubConfig {
     pub project: String,
     pub topic: String,
     pub emulator_host: Option<String>,
+    /// Messages with the same key are delivered in order to subscriptions
+    /// having message ordering enabled.
+    pub ordering_key: Option<String>,
     #[serde(flatten)]
     pub auth: GcpAuthConfig,
 
@@ -65,8 +92,27 @@ inventory::submit! {
 impl SinkConfig for PubsubConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
         let sink = PubsubSink::from_config(self)?;
-        let batch_settings = self.batch.unwrap_or(bytesize::mib(10u64), 1);
-        let request_settings = self.request.unwrap_with(&Default::default());
+        let batch_settings = self.batch.unwrap_or(MAX_REQUEST_BYTES as u64, 1);
+        if batch_settings.size > MAX_REQUEST_BYTES {
+            return Err(Box::new(BuildError::BatchMaxSizeTooLarge {
+                max_size: batch_settings.size,
+            }));
+        }
+        let request_settings = if self.ordering_key.is_some() {
+            if self
+                .request
+                .in_flight_limit
+                .map_or(false, |limit| limit > 1)
+            {
+                return Err(Box::new(BuildError::OrderedInFlightLimit));
+            }
+            self.request.unwrap_with(&TowerRequestConfig {
+                in_flight_limit: Some(1),
+                ..Default::default()
+            })
+        } else {
+            self.request.unwrap_with(&Default::default())
+        };
         let tls_settings = TlsSettings::from_options(&self.tls)?;
 
         let healthcheck = healthcheck(
@@ -78,9 +124,10 @@ impl SinkConfig for PubsubConfig {
         .boxed()
         .compat();
 
-        let sink = BatchedHttpSink::new(
+        let sink = BatchedHttpSink::with_retry_logic(
             sink,
-            JsonArrayBuffer::default(),
+            MessageBatch::default(),
+            PubsubRetryLogic,
             request_settings,
             batch_settings,
             Some(tls_settings),
@@ -105,6 +152,7 @@ struct PubsubSink {
     creds: Option<GcpCredentials>,
     uri_base: String,
     encoding: EncodingConfigWithDefault<Encoding>,
+    ordering_key: Option<Template>,
 }
 
 impl PubsubSink {
@@ -127,6 +175,7 @@ impl PubsubSink {
         Ok(Self {
             api_key: config.auth.api_key.clone(),
             encoding: config.encoding.clone(),
+            ordering_key: config.ordering_key.as_deref().map(Template::from),
             creds,
             uri_base,
         })
@@ -144,15 +193,37 @@ impl PubsubSink {
 }
 
 impl HttpSink for PubsubSink {
-    type Input = Value;
+    type Input = BoxedRawValue;
     type Output = Vec<BoxedRawValue>;
 
     fn encode_event(&self, mut event: Event) -> Option<Self::Input> {
+        let ordering_key = self.ordering_key.as_ref().and_then(|template| {
+            template
+                .render_string(&event)
+                .map_err(|missing_keys| {
+                    emit!(GcpPubsubOrderingKeyMissing { missing_keys });
+                })
+                .ok()
+        });
+
         self.encoding.apply_rules(&mut event);
         // Each event needs to be base64 encoded, and put into a JSON object
         // as the `data` item.
         let json = serde_json::to_string(&event.into_log()).unwrap();
-        Some(json!({ "data": base64::encode(&json) }))
+        let message = match ordering_key {
+            Some(key) => json!({ "data": base64::encode(&json), "orderingKey": key }),
+            None => json!({ "data": base64::encode(&json) }),
+        };
+
+        let message = serde_json::to_string(&message).unwrap();
+        if ENVELOPE_BYTES + message.len() > MAX_REQUEST_BYTES {
+            emit!(GcpPubsubMessageTooLarge {
+                byte_size: message.len(),
+                max_byte_size: MAX_REQUEST_BYTES - ENVELOPE_BYTES,
+            });
+            return None;
+        }
+        Some(RawValue::from_string(message).unwrap())
     }
 
     fn build_request(&self, events: Self::Output) -> Request<Vec<u8>> {
@@ -173,6 +244,107 @@ impl HttpSink for PubsubSink {
     }
 }
 
+/// Messages batched within the limits of a publish request.
+#[derive(Debug, Default)]
+struct MessageBatch {
+    messages: Vec<BoxedRawValue>,
+    byte_size: usize,
+}
+
+impl MessageBatch {
+    /// The size of the request body once the message is added.
+    fn request_size(&self, message: &RawValue) -> usize {
+        // The messages are separated by commas.
+        ENVELOPE_BYTES + self.byte_size + self.messages.len() + message.get().len()
+    }
+}
+
+impl Batch for MessageBatch {
+    type Input = BoxedRawValue;
+    type Output = Vec<BoxedRawValue>;
+
+    fn len(&self) -> usize {
+        self.byte_size
+    }
+
+    fn push(&mut self, message: BoxedRawValue) {
+        self.byte_size += message.get().len();
+        self.messages.push(message);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    fn fresh(&self) -> Self {
+        Self::default()
+    }
+
+    fn finish(self) -> Self::Output {
+        self.messages
+    }
+
+    fn num_items(&self) -> usize {
+        self.messages.len()
+    }
+
+    fn would_overflow(&self, message: &BoxedRawValue) -> bool {
+        self.messages.len() >= MAX_MESSAGES || self.request_size(message) > MAX_REQUEST_BYTES
+    }
+}
+
+/// Retries the responses `HttpRetryLogic` does, and those whose error status
+/// is transient, as listed by the pubsub retry guidelines.
+#[derive(Clone)]
+struct PubsubRetryLogic;
+
+const TRANSIENT_STATUSES: &[&str] = &[
+    "ABORTED",
+    "CANCELLED",
+    "DEADLINE_EXCEEDED",
+    "INTERNAL",
+    "RESOURCE_EXHAUSTED",
+    "UNAVAILABLE",
+    "UNKNOWN",
+];
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorStatus,
+}
+
+#[derive(Deserialize)]
+struct ErrorStatus {
+    status: String,
+    #[serde(default)]
+    message: String,
+}
+
+impl RetryLogic for PubsubRetryLogic {
+    type Error = hyper13::Error;
+    type Response = Response<Bytes>;
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        HttpRetryLogic.is_retriable_error(error)
+    }
+
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        match HttpRetryLogic.should_retry_response(response) {
+            RetryAction::DontRetry(reason) => {
+                match serde_json::from_slice::<ErrorResponse>(response.body()) {
+                    Ok(ErrorResponse { error })
+                        if TRANSIENT_STATUSES.contains(&error.status.as_str()) =>
+                    {
+                        RetryAction::Retry(format!("{}: {}", error.status, error.message))
+                    }
+                    _ => RetryAction::DontRetry(reason),
+                }
+            }
+            action => action,
+        }
+    }
+}
+
 async fn healthcheck(
     cx: SinkContext,
     uri: Uri,
@@ -210,6 +382,95 @@ mod tests {
             panic!("config.build failed to error");
         }
     }
+
+    fn emulator_config(extra: &str) -> PubsubConfig {
+        toml::from_str(&format!(
+            r#"
+            project = "project"
+            topic = "topic"
+            emulator_host = "localhost:8681"
+            {}
+            "#,
+            extra
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn encodes_ordering_key() {
+        let sink =
+            PubsubSink::from_config(&emulator_config(r#"ordering_key = "{{ user }}""#)).unwrap();
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("user", "u1");
+        let message = sink.encode_event(event).unwrap();
+        let message: serde_json::Value = serde_json::from_str(message.get()).unwrap();
+        assert_eq!(message["orderingKey"], "u1");
+
+        // Events missing the key's fields are still published.
+        let message = sink.encode_event(Event::from("hello")).unwrap();
+        assert!(!message.get().contains("orderingKey"));
+    }
+
+    #[test]
+    fn ordering_key_requires_in_flight_limit_of_one() {
+        let config = emulator_config(
+            r#"
+            ordering_key = "{{ user }}"
+            request.in_flight_limit = 5
+            "#,
+        );
+        let error = config
+            .build(SinkContext::new_test(runtime().executor()))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("in_flight_limit"));
+    }
+
+    #[test]
+    fn batches_stay_within_request_limits() {
+        let message = |size: usize| {
+            let data = "x".repeat(size - 11);
+            RawValue::from_string(format!(r#"{{"data":"{}"}}"#, data)).unwrap()
+        };
+
+        let mut batch = MessageBatch::default();
+        batch.push(message(MAX_REQUEST_BYTES / 2));
+        let remaining = MAX_REQUEST_BYTES - ENVELOPE_BYTES - MAX_REQUEST_BYTES / 2 - 1;
+        assert!(!batch.would_overflow(&message(remaining)));
+        assert!(batch.would_overflow(&message(remaining + 1)));
+
+        let mut batch = MessageBatch::default();
+        for _ in 0..MAX_MESSAGES {
+            batch.push(message(20));
+        }
+        assert!(batch.would_overflow(&message(20)));
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let response = |status: u16, body: &str| {
+            Response::builder()
+                .status(status)
+                .body(Bytes::from(body.to_owned()))
+                .unwrap()
+        };
+        let retries = |response| match PubsubRetryLogic.should_retry_response(&response) {
+            RetryAction::Retry(_) => true,
+            _ => false,
+        };
+
+        assert!(retries(response(
+            409,
+            r#"{"error":{"code":409,"status":"ABORTED","message":"try again"}}"#
+        )));
+        assert!(retries(response(503, "")));
+        assert!(!retries(response(
+            400,
+            r#"{"error":{"code":400,"status":"INVALID_ARGUMENT"}}"#
+        )));
+        assert!(!retries(response(404, "not found")));
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,228 @@
+use crate::{
+    event::Event,
+    gelf,
+    internal_events::GelfMessageDropped,
+    sinks::util::{
+        tcp::{tcp_healthcheck, TcpSink},
+        udp::UdpSink,
+        Compression, SinkBuildError, StreamSink,
+    },
+    sinks::{Healthcheck, RouterSink},
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes::Bytes;
+use futures01::{future, Async, AsyncSink, Poll, Sink, StartSend};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Deserialize, Serialize, Debug)]
+// TODO: add back when serde-rs/serde#1358 is addressed
+// #[serde(deny_unknown_fields)]
+pub struct GelfSinkConfig {
+    #[serde(flatten)]
+    pub mode: Mode,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Mode {
+    Tcp(TcpConfig),
+    Udp(UdpConfig),
+}
+
+/// Graylog reads uncompressed messages terminated by a null byte over TCP.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TcpConfig {
+    pub address: String,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UdpConfig {
+    pub address: String,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    /// Larger messages are split in chunks, each sent in its own datagram.
+    #[serde(default = "default_max_chunk_size")]
+    pub max_chunk_size: usize,
+}
+
+fn default_max_chunk_size() -> usize {
+    8192
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<GelfSinkConfig>("gelf")
+}
+
+#[typetag::serde(name = "gelf")]
+impl SinkConfig for GelfSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        match &self.mode {
+            Mode::Tcp(config) => config.build(cx),
+            Mode::Udp(config) => config.build(cx),
+        }
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "gelf"
+    }
+}
+
+fn parse_address(address: &str) -> crate::Result<(String, u16)> {
+    let uri = address.parse::<http::Uri>()?;
+    let host = uri.host().ok_or(SinkBuildError::MissingHost)?.to_string();
+    let port = uri.port_u16().ok_or(SinkBuildError::MissingPort)?;
+    Ok((host, port))
+}
+
+impl TcpConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let (host, port) = parse_address(&self.address)?;
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+
+        let tcp = TcpSink::new(host.clone(), port, cx.resolver(), tls);
+        let sink: RouterSink = Box::new(StreamSink::new(MessageSink::new(tcp), cx.acker()).with(
+            |event: Event| {
+                let frames = match gelf::encode(event.as_log()) {
+                    Some(mut message) => {
+                        message.push(b'\0');
+                        vec![message.into()]
+                    }
+                    None => dropped("missing_message"),
+                };
+                future::ok::<_, ()>(frames)
+            },
+        ));
+        let healthcheck = tcp_healthcheck(host, port, cx.resolver());
+
+        Ok((sink, healthcheck))
+    }
+}
+
+impl UdpConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let (host, port) = parse_address(&self.address)?;
+        let compression = self.compression;
+        let max_chunk_size = self.max_chunk_size;
+
+        let udp = UdpSink::new(host, port, cx.resolver())?;
+        let sink: RouterSink = Box::new(StreamSink::new(MessageSink::new(udp), cx.acker()).with(
+            move |event: Event| {
+                let chunks = match gelf::encode(event.as_log()) {
+                    Some(message) => {
+                        let message = match compression {
+                            Compression::None => message,
+                            Compression::Gzip => gelf::compress(&message),
+                        };
+                        gelf::chunk(message, max_chunk_size)
+                            .unwrap_or_else(|| dropped("too_many_chunks"))
+                    }
+                    None => dropped("missing_message"),
+                };
+                future::ok::<_, ()>(chunks)
+            },
+        ));
+
+        let healthcheck: Healthcheck = Box::new(future::ok(()));
+
+        Ok((sink, healthcheck))
+    }
+}
+
+fn dropped(reason: &'static str) -> Vec<Bytes> {
+    emit!(GelfMessageDropped { reason });
+    Vec::new()
+}
+
+/// Sends the frames of each message, so that messages split in several
+/// chunks, or dropped, are acknowledged once.
+struct MessageSink<S> {
+    inner: S,
+    pending: VecDeque<Bytes>,
+}
+
+impl<S> MessageSink<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<S: Sink<SinkItem = Bytes>> Sink for MessageSink<S> {
+    type SinkItem = Vec<Bytes>;
+    type SinkError = S::SinkError;
+
+    fn start_send(&mut self, frames: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        if !self.pending.is_empty() {
+            self.poll_complete()?;
+            if !self.pending.is_empty() {
+                return Ok(AsyncSink::NotReady(frames));
+            }
+        }
+
+        self.pending.extend(frames);
+        self.poll_complete()?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        while let Some(frame) = self.pending.pop_front() {
+            if let AsyncSink::NotReady(frame) = self.inner.start_send(frame)? {
+                self.pending.push_front(frame);
+                return Ok(Async::NotReady);
+            }
+        }
+        self.inner.poll_complete()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::log_schema, test_util::next_addr, test_util::runtime};
+    use std::net::UdpSocket;
+
+    #[test]
+    fn gelf_udp_sends_chunks() {
+        let addr = next_addr();
+        let receiver = UdpSocket::bind(addr).unwrap();
+
+        let config = GelfSinkConfig {
+            mode: Mode::Udp(UdpConfig {
+                address: addr.to_string(),
+                compression: Compression::None,
+                max_chunk_size: 64,
+            }),
+        };
+        let mut rt = runtime();
+        let (sink, _healthcheck) = config.build(SinkContext::new_test(rt.executor())).unwrap();
+
+        let mut event = Event::from("x".repeat(200));
+        event.as_mut_log().insert("host", "web01");
+        rt.block_on(sink.send(event)).unwrap();
+
+        let mut assembler = gelf::ChunkAssembler::default();
+        let mut buf = [0; 64];
+        let message = loop {
+            let (size, _) = receiver.recv_from(&mut buf).unwrap();
+            assert!(size <= 64);
+            if let Some(message) = assembler.push(Bytes::from(&buf[..size])) {
+                break message;
+            }
+        };
+
+        let log = gelf::decode(&message).unwrap();
+        assert_eq!(log[&log_schema().message_key()], "x".repeat(200).into());
+        assert_eq!(log[&log_schema().host_key()], "web01".into());
+    }
+}
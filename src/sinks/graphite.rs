@@ -0,0 +1,368 @@
+use crate::{
+    event::{
+        metric::{Metric, MetricValue},
+        Event,
+    },
+    sinks::{
+        util::{
+            tcp::{tcp_healthcheck, TcpSink},
+            StreamSink,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes::Bytes;
+use futures01::{stream::iter_ok, Sink};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GraphiteSinkConfig {
+    pub address: String,
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub format: Format,
+    /// Appends tags to the paths, as supported since Graphite 1.1.
+    #[serde(default = "default_include_tags")]
+    pub include_tags: bool,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Plaintext,
+    Pickle,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Plaintext
+    }
+}
+
+impl Format {
+    fn default_port(self) -> u16 {
+        match self {
+            Format::Plaintext => 2003,
+            Format::Pickle => 2004,
+        }
+    }
+}
+
+fn default_include_tags() -> bool {
+    true
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<GraphiteSinkConfig>("graphite")
+}
+
+#[typetag::serde(name = "graphite")]
+impl SinkConfig for GraphiteSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let (host, port) = self.host_and_port()?;
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+
+        let tcp = TcpSink::new(host.clone(), port, cx.resolver(), tls);
+        let healthcheck = tcp_healthcheck(host, port, cx.resolver());
+
+        let config = self.clone();
+        let sink = StreamSink::new(tcp, cx.acker()).with_flat_map(move |event: Event| {
+            let datapoints = datapoints(
+                event.into_metric(),
+                config.namespace.as_deref(),
+                config.include_tags,
+            );
+            let bytes = if datapoints.is_empty() {
+                None
+            } else {
+                Some(match config.format {
+                    Format::Plaintext => encode_plaintext(&datapoints),
+                    Format::Pickle => encode_pickle(&datapoints),
+                })
+            };
+            iter_ok(bytes)
+        });
+
+        Ok((Box::new(sink), healthcheck))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "graphite"
+    }
+}
+
+impl GraphiteSinkConfig {
+    fn host_and_port(&self) -> crate::Result<(String, u16)> {
+        let uri = format!("tcp://{}", self.address).parse::<http::Uri>()?;
+        let host = uri
+            .host()
+            .ok_or_else(|| "A host is required for the address".to_string())?;
+        let port = uri.port_u16().unwrap_or_else(|| self.format.default_port());
+        Ok((host.to_string(), port))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Datapoint {
+    path: String,
+    value: f64,
+    timestamp: i64,
+}
+
+/// Graphite stores single values, so aggregated metrics are split into a
+/// path for each statistic, like `latency.count` or `latency.p99`.
+fn datapoints(metric: Metric, namespace: Option<&str>, include_tags: bool) -> Vec<Datapoint> {
+    let name = match namespace {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, metric.name),
+        _ => metric.name,
+    };
+    let name = sanitize(&name);
+    let tags = match metric.tags {
+        Some(tags) if include_tags => encode_tags(&tags),
+        _ => String::new(),
+    };
+    let timestamp = metric
+        .timestamp
+        .unwrap_or_else(chrono::Utc::now)
+        .timestamp();
+
+    let values = match metric.value {
+        MetricValue::Counter { value } | MetricValue::Gauge { value } => vec![(None, value)],
+        MetricValue::Set { values } => vec![(None, values.len() as f64)],
+        MetricValue::Distribution {
+            values,
+            sample_rates,
+        } => {
+            let count = sample_rates.iter().map(|rate| *rate as f64).sum::<f64>();
+            let sum = values
+                .iter()
+                .zip(&sample_rates)
+                .map(|(value, rate)| value * *rate as f64)
+                .sum::<f64>();
+            vec![
+                (Some("count".to_owned()), count),
+                (Some("sum".to_owned()), sum),
+            ]
+        }
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => buckets
+            .iter()
+            .zip(counts)
+            .map(|(bucket, count)| (Some(format!("le_{}", statistic(*bucket))), count as f64))
+            .chain(vec![
+                (Some("count".to_owned()), count as f64),
+                (Some("sum".to_owned()), sum),
+            ])
+            .collect(),
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => quantiles
+            .iter()
+            .zip(values)
+            .map(|(quantile, value)| {
+                let percentile = (quantile * 100.0 * 1e6).round() / 1e6;
+                (Some(format!("p{}", statistic(percentile))), value)
+            })
+            .chain(vec![
+                (Some("count".to_owned()), count as f64),
+                (Some("sum".to_owned()), sum),
+            ])
+            .collect(),
+    };
+
+    values
+        .into_iter()
+        .filter(|(_, value)| value.is_finite())
+        .map(|(suffix, value)| {
+            let path = match suffix {
+                Some(suffix) => format!("{}.{}{}", name, suffix, tags),
+                None => format!("{}{}", name, tags),
+            };
+            Datapoint {
+                path,
+                value,
+                timestamp,
+            }
+        })
+        .collect()
+}
+
+/// Formats a bucket bound or a percentile as a single path part.
+fn statistic(value: f64) -> String {
+    value.to_string().replace('.', "_").replace('-', "minus_")
+}
+
+/// Whitespace and `;` would break the protocol.
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| {
+            if c.is_whitespace() || c == ';' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn encode_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| {
+            format!(
+                ";{}={}",
+                sanitize(key).replace('=', "_"),
+                sanitize(value).trim_start_matches('~')
+            )
+        })
+        .collect()
+}
+
+fn encode_plaintext(datapoints: &[Datapoint]) -> Bytes {
+    let mut output = String::new();
+    for datapoint in datapoints {
+        output.push_str(&format!(
+            "{} {} {}\n",
+            datapoint.path, datapoint.value, datapoint.timestamp
+        ));
+    }
+    output.into()
+}
+
+/// Encodes the datapoints as a list of `(path, (timestamp, value))` tuples
+/// in pickle protocol 2, prefixed by its length.
+fn encode_pickle(datapoints: &[Datapoint]) -> Bytes {
+    let mut pickle = vec![0x80, 2, b']', b'('];
+    for datapoint in datapoints {
+        pickle.push(b'X');
+        pickle.extend(&(datapoint.path.len() as u32).to_le_bytes());
+        pickle.extend(datapoint.path.as_bytes());
+        pickle.push(b'J');
+        pickle.extend(&(datapoint.timestamp as i32).to_le_bytes());
+        pickle.push(b'G');
+        pickle.extend(&datapoint.value.to_be_bytes());
+        // TUPLE2, for `(timestamp, value)` and then `(path, ..)`.
+        pickle.extend(&[0x86, 0x86]);
+    }
+    pickle.extend(b"e.");
+
+    let mut message = Vec::with_capacity(pickle.len() + 4);
+    message.extend(&(pickle.len() as u32).to_be_bytes());
+    message.extend(pickle);
+    message.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::MetricKind;
+    use chrono::{TimeZone, Utc};
+
+    fn metric(value: MetricValue) -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), "web 01".to_owned());
+        Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.timestamp(1591005600, 0)),
+            tags: Some(tags),
+            kind: MetricKind::Absolute,
+            value,
+        }
+    }
+
+    #[test]
+    fn graphite_plaintext_gauge() {
+        let datapoints = datapoints(
+            metric(MetricValue::Gauge { value: 1.5 }),
+            Some("vector"),
+            true,
+        );
+        assert_eq!(
+            encode_plaintext(&datapoints),
+            Bytes::from("vector.requests;host=web_01 1.5 1591005600\n")
+        );
+
+        let datapoints = datapoints(metric(MetricValue::Gauge { value: 1.5 }), None, false);
+        assert_eq!(
+            encode_plaintext(&datapoints),
+            Bytes::from("requests 1.5 1591005600\n")
+        );
+    }
+
+    #[test]
+    fn graphite_splits_summaries() {
+        let datapoints = datapoints(
+            metric(MetricValue::AggregatedSummary {
+                quantiles: vec![0.5, 0.99],
+                values: vec![2.0, 10.0],
+                count: 4,
+                sum: 16.0,
+            }),
+            None,
+            false,
+        );
+        let paths = datapoints
+            .iter()
+            .map(|datapoint| datapoint.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "requests.p50",
+                "requests.p99",
+                "requests.count",
+                "requests.sum"
+            ]
+        );
+    }
+
+    #[test]
+    fn graphite_pickle() {
+        let datapoints = vec![Datapoint {
+            path: "a.b".into(),
+            value: 1.0,
+            timestamp: 1,
+        }];
+        let message = encode_pickle(&datapoints);
+
+        let mut expected = vec![0, 0, 0, 30];
+        expected.extend(&[0x80, 2, b']', b'(', b'X', 3, 0, 0, 0]);
+        expected.extend(b"a.b");
+        expected.extend(&[b'J', 1, 0, 0, 0]);
+        expected.extend(&[b'G', 0x3f, 0xf0, 0, 0, 0, 0, 0, 0]);
+        expected.extend(&[0x86, 0x86, b'e', b'.']);
+        assert_eq!(message, Bytes::from(expected));
+    }
+
+    #[test]
+    fn graphite_default_ports() {
+        let mut config = GraphiteSinkConfig {
+            address: "carbon".into(),
+            namespace: None,
+            format: Format::Plaintext,
+            include_tags: true,
+            tls: None,
+        };
+        assert_eq!(config.host_and_port().unwrap(), ("carbon".into(), 2003));
+        config.format = Format::Pickle;
+        assert_eq!(config.host_and_port().unwrap(), ("carbon".into(), 2004));
+        config.address = "carbon:2014".into();
+        assert_eq!(config.host_and_port().unwrap(), ("carbon".into(), 2014));
+    }
+}
@@ -143,3 +143,28 @@ async fn healthcheck(config: HoneycombConfig, resolver: Resolver) -> crate::Resu
         .into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn honeycomb_sends_keys_unescaped() {
+        let config = HoneycombConfig {
+            api_key: "key".into(),
+            dataset: "dataset".into(),
+            batch: Default::default(),
+            request: Default::default(),
+        };
+        let mut event = Event::from("hello");
+        event
+            .as_mut_log()
+            .insert("labels.app\\.kubernetes\\.io/name", "vector");
+
+        let body = config.encode_event(event).unwrap();
+        assert_eq!(
+            body["data"]["labels.app.kubernetes.io/name"],
+            json!("vector")
+        );
+    }
+}
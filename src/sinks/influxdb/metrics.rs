@@ -30,6 +30,10 @@ struct InfluxDBSvc {
 pub struct InfluxDBConfig {
     pub namespace: String,
     pub endpoint: String,
+    /// Tags of metrics written as fields rather than tags, keeping values of
+    /// high cardinality out of the series index.
+    #[serde(default)]
+    pub fields: Vec<String>,
     #[serde(flatten)]
     pub influxdb1_settings: Option<InfluxDB1Settings>,
     #[serde(flatten)]
@@ -104,7 +108,9 @@ impl InfluxDBSvc {
             builder.body(body).unwrap()
         };
 
-        let http_service = HttpBatchService::new(cx.resolver(), None, build_request);
+        let http_service = HttpBatchService::new(cx.resolver(), None, build_request)
+            .with_capture(cx.capture())
+            .with_dry_run(cx.dry_run());
 
         let influxdb_http_service = InfluxDBSvc {
             config,
@@ -135,76 +141,28 @@ impl Service<Vec<Metric>> for InfluxDBSvc {
     }
 
     fn call(&mut self, items: Vec<Metric>) -> Self::Future {
-        let input = encode_events(items, &self.config.namespace);
+        let input = encode_events(items, &self.config.namespace, &self.config.fields);
         let body: Vec<u8> = input.into_bytes();
 
         self.inner.call(body)
     }
 }
 
-fn encode_events(events: Vec<Metric>, namespace: &str) -> String {
+fn encode_events(events: Vec<Metric>, namespace: &str, tag_fields: &[String]) -> String {
     let mut output = String::new();
     for event in events.into_iter() {
         let fullname = encode_namespace(namespace, &event.name);
         let ts = encode_timestamp(event.timestamp);
-        let tags = event.tags.clone();
-        match event.value {
-            MetricValue::Counter { value } => {
-                let fields = to_fields(value);
-
-                influx_line_protocol(fullname, "counter", tags, Some(fields), ts, &mut output)
-            }
-            MetricValue::Gauge { value } => {
-                let fields = to_fields(value);
-
-                influx_line_protocol(fullname, "gauge", tags, Some(fields), ts, &mut output);
-            }
-            MetricValue::Set { values } => {
-                let fields = to_fields(values.len() as f64);
-
-                influx_line_protocol(fullname, "set", tags, Some(fields), ts, &mut output);
-            }
-            MetricValue::AggregatedHistogram {
-                buckets,
-                counts,
-                count,
-                sum,
-            } => {
-                let mut fields: HashMap<String, Field> = buckets
-                    .iter()
-                    .zip(counts.iter())
-                    .map(|pair| (format!("bucket_{}", pair.0), Field::UnsignedInt(*pair.1)))
-                    .collect();
-                fields.insert("count".to_owned(), Field::UnsignedInt(count));
-                fields.insert("sum".to_owned(), Field::Float(sum));
-
-                influx_line_protocol(fullname, "histogram", tags, Some(fields), ts, &mut output);
-            }
-            MetricValue::AggregatedSummary {
-                quantiles,
-                values,
-                count,
-                sum,
-            } => {
-                let mut fields: HashMap<String, Field> = quantiles
-                    .iter()
-                    .zip(values.iter())
-                    .map(|pair| (format!("quantile_{}", pair.0), Field::Float(*pair.1)))
-                    .collect();
-                fields.insert("count".to_owned(), Field::UnsignedInt(count));
-                fields.insert("sum".to_owned(), Field::Float(sum));
-
-                influx_line_protocol(fullname, "summary", tags, Some(fields), ts, &mut output);
-            }
-            MetricValue::Distribution {
-                values,
-                sample_rates,
-            } => {
-                let fields = encode_distribution(&values, &sample_rates);
-
-                influx_line_protocol(fullname, "distribution", tags, fields, ts, &mut output);
+        let (metric_type, mut fields) = metric_fields(event.value);
+        let mut tags = event.tags;
+        if let (Some(tags), Some(fields)) = (&mut tags, &mut fields) {
+            for name in tag_fields {
+                if let Some(value) = tags.remove(name) {
+                    fields.insert(name.clone(), Field::String(value));
+                }
             }
         }
+        influx_line_protocol(fullname, metric_type, tags, fields, ts, &mut output);
     }
 
     // remove last '\n'
@@ -213,6 +171,53 @@ fn encode_events(events: Vec<Metric>, namespace: &str) -> String {
     return output;
 }
 
+/// The type and fields of a metric value, as written by the line protocol.
+pub(in crate::sinks) fn metric_fields(
+    value: MetricValue,
+) -> (&'static str, Option<HashMap<String, Field>>) {
+    match value {
+        MetricValue::Counter { value } => ("counter", Some(to_fields(value))),
+        MetricValue::Gauge { value } => ("gauge", Some(to_fields(value))),
+        MetricValue::Set { values } => ("set", Some(to_fields(values.len() as f64))),
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => {
+            let mut fields: HashMap<String, Field> = buckets
+                .iter()
+                .zip(counts.iter())
+                .map(|pair| (format!("bucket_{}", pair.0), Field::UnsignedInt(*pair.1)))
+                .collect();
+            fields.insert("count".to_owned(), Field::UnsignedInt(count));
+            fields.insert("sum".to_owned(), Field::Float(sum));
+
+            ("histogram", Some(fields))
+        }
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => {
+            let mut fields: HashMap<String, Field> = quantiles
+                .iter()
+                .zip(values.iter())
+                .map(|pair| (format!("quantile_{}", pair.0), Field::Float(*pair.1)))
+                .collect();
+            fields.insert("count".to_owned(), Field::UnsignedInt(count));
+            fields.insert("sum".to_owned(), Field::Float(sum));
+
+            ("summary", Some(fields))
+        }
+        MetricValue::Distribution {
+            values,
+            sample_rates,
+        } => ("distribution", encode_distribution(&values, &sample_rates)),
+    }
+}
+
 fn encode_distribution(values: &[f64], counts: &[u32]) -> Option<HashMap<String, Field>> {
     if values.len() != counts.len() {
         return None;
@@ -306,7 +311,7 @@ mod tests {
             },
         ];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(
             line_protocols,
             "ns.total,metric_type=counter value=1.5 1542182950000000011\n\
@@ -324,13 +329,34 @@ mod tests {
             value: MetricValue::Gauge { value: -1.5 },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(
             line_protocols,
             "ns.meter,metric_type=gauge,normal_tag=value,true_tag=true value=-1.5 1542182950000000011"
         );
     }
 
+    #[test]
+    fn test_encode_tags_as_fields() {
+        let events = vec![Metric {
+            name: "meter".to_owned(),
+            timestamp: Some(ts()),
+            tags: Some(tags()),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Gauge { value: -1.5 },
+        }];
+
+        let line_protocols = encode_events(events, "ns", &["normal_tag".to_owned()]);
+        let line_protocol = split_line_protocol(&line_protocols);
+        assert_eq!("ns.meter", line_protocol.0);
+        assert_eq!("metric_type=gauge,true_tag=true", line_protocol.1);
+        assert_fields(
+            line_protocol.2.to_string(),
+            ["normal_tag=\"value\"", "value=-1.5"].to_vec(),
+        );
+        assert_eq!("1542182950000000011", line_protocol.3);
+    }
+
     #[test]
     fn test_encode_set() {
         let events = vec![Metric {
@@ -343,7 +369,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(
             line_protocols,
             "ns.users,metric_type=set,normal_tag=value,true_tag=true value=2 1542182950000000011"
@@ -365,7 +391,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         let line_protocols: Vec<&str> = line_protocols.split('\n').collect();
         assert_eq!(line_protocols.len(), 1);
 
@@ -404,7 +430,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         let line_protocols: Vec<&str> = line_protocols.split('\n').collect();
         assert_eq!(line_protocols.len(), 1);
 
@@ -463,7 +489,7 @@ mod tests {
             },
         ];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         let line_protocols: Vec<&str> = line_protocols.split('\n').collect();
         assert_eq!(line_protocols.len(), 3);
 
@@ -538,7 +564,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(line_protocols.len(), 0);
     }
 
@@ -555,7 +581,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(line_protocols.len(), 0);
     }
 
@@ -572,7 +598,7 @@ mod tests {
             },
         }];
 
-        let line_protocols = encode_events(events, "ns");
+        let line_protocols = encode_events(events, "ns", &[]);
         assert_eq!(line_protocols.len(), 0);
     }
 }
@@ -621,6 +647,7 @@ mod integration_tests {
         let config = InfluxDBConfig {
             namespace: "ns".to_string(),
             endpoint: "http://localhost:9999".to_string(),
+            fields: Vec::new(),
             influxdb1_settings: None,
             influxdb2_settings: Some(InfluxDB2Settings {
                 org: ORG.to_string(),
@@ -0,0 +1,402 @@
+use crate::{
+    event::{self, Event, LogEvent, Value},
+    internal_events::{JournaldEventSent, JournaldSendFailed},
+    sinks::util::{
+        encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+        StreamSink,
+    },
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use async_trait::async_trait;
+use futures::{pin_mut, stream::Stream, StreamExt};
+use futures01::future;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+};
+use string_cache::DefaultAtom as Atom;
+
+use super::streaming_sink::{self, StreamingSink};
+
+const DEFAULT_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+/// Journal field names are limited to this many characters.
+const MAX_FIELD_NAME_LEN: usize = 64;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JournaldSinkConfig {
+    #[serde(default = "default_socket_path")]
+    pub socket_path: PathBuf,
+    #[serde(default = "default_syslog_identifier")]
+    pub syslog_identifier: String,
+    pub priority_field: Option<Atom>,
+    #[serde(default = "default_priority")]
+    pub default_priority: u8,
+    /// Renames event fields to journal fields, e.g. `user.id = "USER_ID"`.
+    #[serde(default)]
+    pub field_mapping: IndexMap<String, String>,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub encoding: EncodingConfigWithDefault<Encoding>,
+}
+
+fn default_socket_path() -> PathBuf {
+    DEFAULT_SOCKET_PATH.into()
+}
+
+fn default_syslog_identifier() -> String {
+    "vector".into()
+}
+
+fn default_priority() -> u8 {
+    6
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum Encoding {
+    #[derivative(Default)]
+    Default,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<JournaldSinkConfig>("journald")
+}
+
+#[typetag::serde(name = "journald")]
+impl SinkConfig for JournaldSinkConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        if self.default_priority > 7 {
+            return Err("`default_priority` must be between 0 and 7".into());
+        }
+
+        let sink = JournaldSink {
+            config: self.clone(),
+            socket: UnixDatagram::unbound()?,
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        let healthcheck = healthcheck(self.socket_path.clone());
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "journald"
+    }
+}
+
+fn healthcheck(path: PathBuf) -> super::Healthcheck {
+    if path.exists() {
+        Box::new(future::ok(()))
+    } else {
+        Box::new(future::err(
+            format!("Journal socket {:?} does not exist", path).into(),
+        ))
+    }
+}
+
+struct JournaldSink {
+    config: JournaldSinkConfig,
+    socket: UnixDatagram,
+}
+
+#[async_trait]
+impl StreamingSink for JournaldSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        while let Some(mut event) = input.next().await {
+            self.config.encoding.apply_rules(&mut event);
+            let record = encode_record(&self.config, event.as_log());
+
+            // Sends to the local journal only block while journald is
+            // catching up, so there is no point in moving them off the
+            // executor.
+            match send(&self.socket, &self.config.socket_path, &record) {
+                Ok(()) => emit!(JournaldEventSent {
+                    byte_size: record.len()
+                }),
+                Err(error) => emit!(JournaldSendFailed {
+                    error,
+                    path: &self.config.socket_path,
+                }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the event into the journal's native protocol. Values that
+/// contain newlines use the binary safe, length prefixed form.
+fn encode_record(config: &JournaldSinkConfig, log: &LogEvent) -> Vec<u8> {
+    let message_key = event::log_schema().message_key();
+
+    let mut record = Vec::new();
+    let message = log
+        .get(&message_key)
+        .map(|value| value.as_bytes().to_vec())
+        .unwrap_or_default();
+    write_field(&mut record, "MESSAGE", &message);
+
+    let priority = config
+        .priority_field
+        .as_ref()
+        .and_then(|field| log.get(field))
+        .and_then(parse_priority)
+        .unwrap_or(config.default_priority);
+    write_field(&mut record, "PRIORITY", priority.to_string().as_bytes());
+    write_field(
+        &mut record,
+        "SYSLOG_IDENTIFIER",
+        config.syslog_identifier.as_bytes(),
+    );
+
+    for (key, value) in log.all_fields() {
+        let is_priority = config
+            .priority_field
+            .as_ref()
+            .map_or(false, |field| key == &field[..]);
+        if key == &message_key[..] || is_priority {
+            continue;
+        }
+        let name = match config.field_mapping.get(key.as_str()) {
+            Some(name) => name.clone(),
+            None => journal_field_name(&key),
+        };
+        match name.as_str() {
+            // These were written above and may only appear once.
+            "" | "MESSAGE" | "PRIORITY" | "SYSLOG_IDENTIFIER" => continue,
+            _ => write_field(&mut record, &name, &value.as_bytes()),
+        }
+    }
+
+    record
+}
+
+fn write_field(record: &mut Vec<u8>, name: &str, value: &[u8]) {
+    record.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        record.push(b'\n');
+        record.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        record.extend_from_slice(value);
+    } else {
+        record.push(b'=');
+        record.extend_from_slice(value);
+    }
+    record.push(b'\n');
+}
+
+/// Journal fields are upper case letters, digits and underscores, and may
+/// not start with an underscore, which is reserved for trusted fields.
+fn journal_field_name(key: &str) -> String {
+    let name = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' => c.to_ascii_uppercase(),
+            'A'..='Z' | '0'..='9' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let name = name.trim_start_matches(|c: char| c == '_' || c.is_ascii_digit());
+    name.chars().take(MAX_FIELD_NAME_LEN).collect()
+}
+
+fn parse_priority(value: &Value) -> Option<u8> {
+    let priority = match value {
+        Value::Integer(n) => *n,
+        Value::Bytes(_) => match value.to_string_lossy().to_lowercase().as_str() {
+            "emerg" | "emergency" | "panic" => 0,
+            "alert" => 1,
+            "crit" | "critical" => 2,
+            "err" | "error" => 3,
+            "warning" | "warn" => 4,
+            "notice" => 5,
+            "info" | "informational" => 6,
+            "debug" => 7,
+            other => other.parse().ok()?,
+        },
+        _ => return None,
+    };
+    if (0..=7).contains(&priority) {
+        Some(priority as u8)
+    } else {
+        None
+    }
+}
+
+fn send(socket: &UnixDatagram, path: &Path, record: &[u8]) -> io::Result<()> {
+    match socket.send_to(record, path) {
+        Err(error) if error.raw_os_error() == Some(libc::EMSGSIZE) => {
+            send_memfd(socket, path, record)
+        }
+        result => result.map(|_| ()),
+    }
+}
+
+/// Records too large for a single datagram are handed over as a sealed
+/// memfd, the same way `sd_journal_sendv` does it.
+#[cfg(target_os = "linux")]
+fn send_memfd(socket: &UnixDatagram, path: &Path, record: &[u8]) -> io::Result<()> {
+    use nix::{
+        fcntl::{fcntl, FcntlArg, SealFlag},
+        sys::{
+            memfd::{memfd_create, MemFdCreateFlag},
+            socket::{sendmsg, ControlMessage, MsgFlags, SockAddr},
+        },
+    };
+    use std::{
+        ffi::CString,
+        fs::File,
+        io::Write,
+        os::unix::io::{AsRawFd, FromRawFd},
+    };
+
+    let to_io = |error: nix::Error| io::Error::new(io::ErrorKind::Other, error);
+
+    let name = CString::new("vector-journald").unwrap();
+    let fd = memfd_create(&name, MemFdCreateFlag::MFD_ALLOW_SEALING).map_err(to_io)?;
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(record)?;
+    fcntl(
+        fd,
+        FcntlArg::F_ADD_SEALS(
+            SealFlag::F_SEAL_SHRINK
+                | SealFlag::F_SEAL_GROW
+                | SealFlag::F_SEAL_WRITE
+                | SealFlag::F_SEAL_SEAL,
+        ),
+    )
+    .map_err(to_io)?;
+
+    let addr = SockAddr::new_unix(path).map_err(to_io)?;
+    sendmsg(
+        socket.as_raw_fd(),
+        &[],
+        &[ControlMessage::ScmRights(&[fd])],
+        MsgFlags::empty(),
+        Some(&addr),
+    )
+    .map_err(to_io)?;
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_memfd(_socket: &UnixDatagram, _path: &Path, _record: &[u8]) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::EMSGSIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{self, random_lines_with_stream, runtime},
+        topology::config::SinkConfig,
+    };
+    use futures01::Sink;
+
+    fn config() -> JournaldSinkConfig {
+        toml::from_str("").unwrap()
+    }
+
+    fn fields(record: &[u8]) -> Vec<String> {
+        String::from_utf8_lossy(record)
+            .lines()
+            .map(Into::into)
+            .collect()
+    }
+
+    #[test]
+    fn journald_encodes_record() {
+        let mut config = config();
+        config.priority_field = Some("level".into());
+        config
+            .field_mapping
+            .insert("request.id".into(), "REQUEST_ID".into());
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("level", "warn");
+        event.as_mut_log().insert("request.id", "abc");
+        event.as_mut_log().insert("_private", 1);
+
+        let record = encode_record(&config, event.as_log());
+        let fields = fields(&record);
+
+        assert_eq!(fields[0], "MESSAGE=hello");
+        assert_eq!(fields[1], "PRIORITY=4");
+        assert_eq!(fields[2], "SYSLOG_IDENTIFIER=vector");
+        assert!(fields.contains(&"REQUEST_ID=abc".to_string()));
+        assert!(fields.contains(&"PRIVATE=1".to_string()));
+        assert!(!fields.iter().any(|field| field.starts_with("LEVEL=")));
+    }
+
+    #[test]
+    fn journald_encodes_multiline_values() {
+        let mut record = Vec::new();
+        write_field(&mut record, "MESSAGE", b"one\ntwo");
+
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(b"one\ntwo\n");
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn journald_field_names() {
+        assert_eq!(journal_field_name("host"), "HOST");
+        assert_eq!(journal_field_name("parent.child-key"), "PARENT_CHILD_KEY");
+        assert_eq!(journal_field_name("_1internal"), "INTERNAL");
+        assert_eq!(
+            journal_field_name(&"a".repeat(80)).len(),
+            MAX_FIELD_NAME_LEN
+        );
+    }
+
+    #[test]
+    fn journald_parses_priority() {
+        assert_eq!(parse_priority(&Value::from(3)), Some(3));
+        assert_eq!(parse_priority(&Value::from("ERROR")), Some(3));
+        assert_eq!(parse_priority(&Value::from("7")), Some(7));
+        assert_eq!(parse_priority(&Value::from(8)), None);
+        assert_eq!(parse_priority(&Value::from("verbose")), None);
+    }
+
+    #[test]
+    fn journald_sends_to_socket() {
+        let path = test_util::temp_file();
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        let mut config = config();
+        config.socket_path = path.clone();
+
+        let mut rt = runtime();
+        let (sink, _) = config.build(SinkContext::new_test(rt.executor())).unwrap();
+
+        let (lines, events) = random_lines_with_stream(10, 3);
+        rt.block_on(sink.send_all(events)).unwrap();
+
+        let mut buf = [0; 1024];
+        for line in lines {
+            let size = receiver.recv(&mut buf).unwrap();
+            let fields = fields(&buf[..size]);
+            assert_eq!(fields[0], format!("MESSAGE={}", line));
+        }
+
+        std::fs::remove_file(path).ok();
+    }
+}
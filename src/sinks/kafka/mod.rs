@@ -1,7 +1,7 @@
 use crate::{
     buffers::Acker,
     event::{self, Event},
-    kafka::{KafkaCompression, KafkaTlsConfig},
+    kafka::{KafkaCompression, KafkaEventHubsConfig, KafkaSaslConfig, KafkaTlsConfig},
     serde::to_string,
     sinks::util::encoding::{EncodingConfig, EncodingConfigWithDefault, EncodingConfiguration},
     template::Template,
@@ -15,34 +15,52 @@ use rdkafka::{
     consumer::{BaseConsumer, Consumer},
     producer::{DeliveryFuture, FutureProducer, FutureRecord},
 };
+use schema_registry::{RegisteredSchema, SchemaRegistryConfig};
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 use string_cache::DefaultAtom as Atom;
 
+mod schema_registry;
+
 type MetadataFuture<F, M> = future::Join<F, future::FutureResult<M, <F as Future>::Error>>;
 
 #[derive(Debug, Snafu)]
 enum BuildError {
     #[snafu(display("creating kafka producer failed: {}", source))]
     KafkaCreateFailed { source: rdkafka::error::KafkaError },
+    #[snafu(display("schema_registry requires the json encoding"))]
+    SchemaRegistryEncoding,
+    #[snafu(display("schema_registry.subject must be set when topic is templated"))]
+    SchemaRegistrySubject,
+    #[snafu(display("topic must be set, unless the Event Hubs connection string names one"))]
+    MissingTopic,
+    #[snafu(display("could not resolve schema: {}", source))]
+    SchemaRegistryResolve {
+        source: schema_registry::SchemaRegistryError,
+    },
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct KafkaSinkConfig {
+    #[serde(default)]
     bootstrap_servers: String,
+    #[serde(default)]
     topic: String,
     key_field: Option<Atom>,
     encoding: EncodingConfigWithDefault<Encoding>,
     #[serde(default)]
     compression: KafkaCompression,
     tls: Option<KafkaTlsConfig>,
+    sasl: Option<KafkaSaslConfig>,
+    azure_event_hubs: Option<KafkaEventHubsConfig>,
     #[serde(default = "default_socket_timeout_ms")]
     socket_timeout_ms: u64,
     #[serde(default = "default_message_timeout_ms")]
     message_timeout_ms: u64,
     librdkafka_options: Option<HashMap<String, String>>,
+    schema_registry: Option<SchemaRegistryConfig>,
 }
 
 fn default_socket_timeout_ms() -> u64 {
@@ -67,6 +85,7 @@ pub struct KafkaSink {
     topic: Template,
     key_field: Option<Atom>,
     encoding: EncodingConfig<Encoding>,
+    schema: Option<RegisteredSchema>,
     in_flight: FuturesUnordered<MetadataFuture<Compat<DeliveryFuture>, usize>>,
 
     acker: Acker,
@@ -82,8 +101,9 @@ inventory::submit! {
 #[typetag::serde(name = "kafka")]
 impl SinkConfig for KafkaSinkConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let sink = KafkaSink::new(self.clone(), cx.acker())?;
-        let hc = healthcheck(self.clone());
+        let config = self.resolve()?;
+        let sink = KafkaSink::new(config.clone(), cx.acker())?;
+        let hc = healthcheck(config);
         Ok((Box::new(sink), hc))
     }
 
@@ -97,12 +117,25 @@ impl SinkConfig for KafkaSinkConfig {
 }
 
 impl KafkaSinkConfig {
+    /// Fills in the brokers, and the topic unless set, from `azure_event_hubs`.
+    fn resolve(&self) -> crate::Result<Self> {
+        let mut config = self.clone();
+        let event_hub = crate::kafka::resolve_brokers(
+            &config.azure_event_hubs,
+            &mut config.bootstrap_servers,
+            &mut config.tls,
+            &mut config.sasl,
+        )?;
+        if config.topic.is_empty() {
+            config.topic = event_hub.ok_or(BuildError::MissingTopic)?;
+        }
+        Ok(config)
+    }
+
     fn to_rdkafka(&self) -> crate::Result<rdkafka::ClientConfig> {
         let mut client_config = rdkafka::ClientConfig::new();
         client_config.set("bootstrap.servers", &self.bootstrap_servers);
-        if let Some(tls) = &self.tls {
-            tls.apply(&mut client_config)?;
-        }
+        crate::kafka::apply_security(&mut client_config, &self.tls, &self.sasl)?;
         client_config.set("compression.codec", &to_string(self.compression));
         client_config.set("socket.timeout.ms", &self.socket_timeout_ms.to_string());
         client_config.set("message.timeout.ms", &self.message_timeout_ms.to_string());
@@ -113,16 +146,48 @@ impl KafkaSinkConfig {
         }
         Ok(client_config)
     }
+
+    /// The Schema Registry subject payloads are validated against. Defaults
+    /// to the `<topic>-value` naming strategy used by Confluent clients.
+    fn schema_subject(&self) -> crate::Result<Option<String>> {
+        let registry = match &self.schema_registry {
+            Some(registry) => registry,
+            None => return Ok(None),
+        };
+        if self.encoding.codec() != &Encoding::Json {
+            return Err(Box::new(BuildError::SchemaRegistryEncoding));
+        }
+        match &registry.subject {
+            Some(subject) => Ok(Some(subject.clone())),
+            None if Template::from(self.topic.as_str()).is_dynamic() => {
+                Err(Box::new(BuildError::SchemaRegistrySubject))
+            }
+            None => Ok(Some(format!("{}-value", self.topic))),
+        }
+    }
+
+    fn resolve_schema(&self) -> crate::Result<Option<RegisteredSchema>> {
+        match (&self.schema_registry, self.schema_subject()?) {
+            (Some(registry), Some(subject)) => {
+                let schema = registry.resolve(&subject).context(SchemaRegistryResolve)?;
+                debug!(message = "resolved schema.", %subject, id = schema.id());
+                Ok(Some(schema))
+            }
+            _ => Ok(None),
+        }
+    }
 }
 
 impl KafkaSink {
     fn new(config: KafkaSinkConfig, acker: Acker) -> crate::Result<Self> {
         let producer = config.to_rdkafka()?.create().context(KafkaCreateFailed)?;
+        let schema = config.resolve_schema()?;
         Ok(KafkaSink {
             producer,
             topic: Template::from(config.topic),
             key_field: config.key_field,
             encoding: config.encoding.into(),
+            schema,
             in_flight: FuturesUnordered::new(),
             acker,
             seq_head: 0,
@@ -130,6 +195,17 @@ impl KafkaSink {
             pending_acks: HashSet::new(),
         })
     }
+
+    fn ack_in_order(&mut self, seqno: usize) {
+        self.pending_acks.insert(seqno);
+
+        let mut num_to_ack = 0;
+        while self.pending_acks.remove(&self.seq_tail) {
+            num_to_ack += 1;
+            self.seq_tail += 1
+        }
+        self.acker.ack(num_to_ack);
+    }
 }
 
 impl Sink for KafkaSink {
@@ -142,7 +218,25 @@ impl Sink for KafkaSink {
             ()
         })?;
 
+        if let Some(schema) = &self.schema {
+            if let Err(reason) = schema.check(&item) {
+                warn!(
+                    message = "event does not match schema, dropping.",
+                    %reason,
+                    rate_limit_secs = 30
+                );
+                let seqno = self.seq_head;
+                self.seq_head += 1;
+                self.ack_in_order(seqno);
+                return Ok(AsyncSink::Ready);
+            }
+        }
+
         let (key, body) = encode_event(item.clone(), &self.key_field, &self.encoding);
+        let body = match &self.schema {
+            Some(schema) => schema.frame(&body),
+            None => body,
+        };
 
         let record = FutureRecord::to(&topic).key(&key).payload(&body[..]);
 
@@ -193,14 +287,7 @@ impl Sink for KafkaSink {
                         Err((e, _msg)) => error!("kafka error: {}", e),
                     };
 
-                    self.pending_acks.insert(seqno);
-
-                    let mut num_to_ack = 0;
-                    while self.pending_acks.remove(&self.seq_tail) {
-                        num_to_ack += 1;
-                        self.seq_tail += 1
-                    }
-                    self.acker.ack(num_to_ack);
+                    self.ack_in_order(seqno);
                 }
 
                 // request got canceled (according to docs)
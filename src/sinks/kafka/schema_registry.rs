@@ -0,0 +1,311 @@
+use crate::{
+    event::{Event, Value},
+    sinks::util::http::Auth,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use snafu::{ResultExt, Snafu};
+use std::path::PathBuf;
+
+/// The first byte of every message framed with the Confluent wire format.
+const MAGIC_BYTE: u8 = 0;
+
+const CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+#[derive(Debug, Snafu)]
+pub enum SchemaRegistryError {
+    #[snafu(display("Could not read schema file {:?}: {}", path, source))]
+    ReadSchema {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Schema file {:?} is not valid JSON: {}", path, source))]
+    ParseSchema {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[snafu(display("Schema registry request failed: {}", source))]
+    Request { source: reqwest::Error },
+    #[snafu(display(
+        "Schema registry returned {} for subject {:?}: {}",
+        status,
+        subject,
+        body
+    ))]
+    UnexpectedStatus {
+        status: reqwest::StatusCode,
+        subject: String,
+        body: String,
+    },
+    #[snafu(display(
+        "Subject {:?} uses schema type {:?}, only JSON schemas are supported",
+        subject,
+        schema_type
+    ))]
+    UnsupportedSchemaType {
+        subject: String,
+        schema_type: String,
+    },
+    #[snafu(display(
+        "Registered schema for subject {:?} is not valid JSON: {}",
+        subject,
+        source
+    ))]
+    ParseRegisteredSchema {
+        subject: String,
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemaRegistryConfig {
+    pub url: String,
+    pub subject: Option<String>,
+    pub schema_path: Option<PathBuf>,
+    pub auth: Option<Auth>,
+    #[serde(default = "default_validate")]
+    pub validate: bool,
+}
+
+fn default_validate() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterRequest<'a> {
+    schema_type: &'a str,
+    schema: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterResponse {
+    id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubjectVersion {
+    id: u32,
+    schema: String,
+    // Absent for AVRO subjects, which predate the other schema types.
+    schema_type: Option<String>,
+}
+
+/// A JSON Schema resolved against the registry, ready to frame payloads.
+#[derive(Clone, Debug)]
+pub struct RegisteredSchema {
+    id: u32,
+    schema: JsonValue,
+    validate: bool,
+}
+
+impl SchemaRegistryConfig {
+    /// Registers the configured schema under the subject, or fetches the
+    /// latest registered version when no schema file is given. The registry
+    /// is idempotent about re-registering an identical schema, so this is
+    /// safe to run every time the sink is built.
+    pub fn resolve(&self, subject: &str) -> Result<RegisteredSchema, SchemaRegistryError> {
+        let client = reqwest::Client::new();
+        let base = self.url.trim_end_matches('/');
+
+        let (id, schema) = match &self.schema_path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).context(ReadSchema { path })?;
+                let schema = serde_json::from_str(&text).context(ParseSchema { path })?;
+
+                let body = RegisterRequest {
+                    schema_type: "JSON",
+                    schema: &text,
+                };
+                let request = client
+                    .post(&format!("{}/subjects/{}/versions", base, subject))
+                    .header("Content-Type", CONTENT_TYPE)
+                    .json(&body);
+                let response: RegisterResponse = self.send(request, subject)?;
+                (response.id, schema)
+            }
+            None => {
+                let request = client.get(&format!("{}/subjects/{}/versions/latest", base, subject));
+                let version: SubjectVersion = self.send(request, subject)?;
+                let schema_type = version.schema_type.unwrap_or_else(|| "AVRO".into());
+                if schema_type != "JSON" {
+                    return Err(SchemaRegistryError::UnsupportedSchemaType {
+                        subject: subject.into(),
+                        schema_type,
+                    });
+                }
+                let schema = serde_json::from_str(&version.schema)
+                    .context(ParseRegisteredSchema { subject })?;
+                (version.id, schema)
+            }
+        };
+
+        Ok(RegisteredSchema {
+            id,
+            schema,
+            validate: self.validate,
+        })
+    }
+
+    fn send<T>(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        subject: &str,
+    ) -> Result<T, SchemaRegistryError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        request = match &self.auth {
+            Some(Auth::Basic { user, password }) => request.basic_auth(user, Some(password)),
+            Some(Auth::Bearer { token }) => request.bearer_auth(token),
+            None => request,
+        };
+
+        let mut response = request.send().context(Request)?;
+        if !response.status().is_success() {
+            return Err(SchemaRegistryError::UnexpectedStatus {
+                status: response.status(),
+                subject: subject.into(),
+                body: response.text().unwrap_or_default(),
+            });
+        }
+        response.json().context(Request)
+    }
+}
+
+impl RegisteredSchema {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Checks the event against the `required` and top level `properties`
+    /// constraints of the schema. Returns a description of the first
+    /// violation found, if any. Deeper validation is left to the consumers
+    /// of the topic.
+    pub fn check(&self, event: &Event) -> Result<(), String> {
+        if !self.validate {
+            return Ok(());
+        }
+
+        let log = event.as_log();
+
+        if let Some(required) = self.schema.get("required").and_then(JsonValue::as_array) {
+            for field in required.iter().filter_map(JsonValue::as_str) {
+                if !log.contains(&field.into()) {
+                    return Err(format!("missing required field {:?}", field));
+                }
+            }
+        }
+
+        if let Some(properties) = self.schema.get("properties").and_then(JsonValue::as_object) {
+            for (field, spec) in properties {
+                let expected = match spec.get("type") {
+                    Some(expected) => expected,
+                    None => continue,
+                };
+                if let Some(value) = log.get(&field.as_str().into()) {
+                    if !type_matches(expected, value) {
+                        return Err(format!(
+                            "field {:?} does not match schema type {}",
+                            field, expected
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prefixes the payload with the magic byte and the big-endian schema id.
+    pub fn frame(&self, payload: &[u8]) -> Vec<u8> {
+        let mut framed = Vec::with_capacity(payload.len() + 5);
+        framed.push(MAGIC_BYTE);
+        framed.extend_from_slice(&self.id.to_be_bytes());
+        framed.extend_from_slice(payload);
+        framed
+    }
+}
+
+fn type_matches(expected: &JsonValue, value: &Value) -> bool {
+    match expected {
+        JsonValue::String(name) => value_is(name, value),
+        JsonValue::Array(names) => names
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .any(|name| value_is(name, value)),
+        _ => true,
+    }
+}
+
+fn value_is(name: &str, value: &Value) -> bool {
+    match (name, value) {
+        ("string", Value::Bytes(_)) => true,
+        // Timestamps are serialized as RFC3339 strings.
+        ("string", Value::Timestamp(_)) => true,
+        ("integer", Value::Integer(_)) => true,
+        ("number", Value::Integer(_)) | ("number", Value::Float(_)) => true,
+        ("boolean", Value::Boolean(_)) => true,
+        ("object", Value::Map(_)) => true,
+        ("array", Value::Array(_)) => true,
+        ("null", Value::Null) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(schema: JsonValue) -> RegisteredSchema {
+        RegisteredSchema {
+            id: 42,
+            schema,
+            validate: true,
+        }
+    }
+
+    #[test]
+    fn schema_registry_frames_payload() {
+        let framed = schema(serde_json::json!({})).frame(b"{}");
+        assert_eq!(&framed[..], &[0, 0, 0, 0, 42, b'{', b'}'][..]);
+    }
+
+    #[test]
+    fn schema_registry_checks_required_fields() {
+        let schema = schema(serde_json::json!({ "required": ["user_id"] }));
+
+        let mut event = Event::from("hello");
+        assert!(schema.check(&event).is_err());
+
+        event.as_mut_log().insert("user_id", 7);
+        assert!(schema.check(&event).is_ok());
+    }
+
+    #[test]
+    fn schema_registry_checks_property_types() {
+        let schema = schema(serde_json::json!({
+            "properties": {
+                "count": { "type": "integer" },
+                "name": { "type": ["string", "null"] }
+            }
+        }));
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("count", 3);
+        event.as_mut_log().insert("name", "vector");
+        assert!(schema.check(&event).is_ok());
+
+        event.as_mut_log().insert("count", "three");
+        assert!(schema.check(&event).is_err());
+    }
+
+    #[test]
+    fn schema_registry_validation_can_be_disabled() {
+        let mut schema = schema(serde_json::json!({ "required": ["user_id"] }));
+        schema.validate = false;
+        assert!(schema.check(&Event::from("hello")).is_ok());
+    }
+}
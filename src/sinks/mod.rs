@@ -3,6 +3,12 @@ use snafu::Snafu;
 
 pub mod streaming_sink;
 
+#[cfg(feature = "sinks-alerts")]
+pub mod alerts;
+#[cfg(feature = "sinks-amqp")]
+pub mod amqp;
+#[cfg(feature = "sinks-arrow_flight")]
+pub mod arrow_flight;
 #[cfg(feature = "sinks-aws_cloudwatch_logs")]
 pub mod aws_cloudwatch_logs;
 #[cfg(feature = "sinks-aws_cloudwatch_metrics")]
@@ -13,6 +19,8 @@ pub mod aws_kinesis_firehose;
 pub mod aws_kinesis_streams;
 #[cfg(feature = "sinks-aws_s3")]
 pub mod aws_s3;
+#[cfg(feature = "sinks-azure_event_hubs")]
+pub mod azure;
 #[cfg(feature = "sinks-blackhole")]
 pub mod blackhole;
 #[cfg(feature = "sinks-clickhouse")]
@@ -21,12 +29,22 @@ pub mod clickhouse;
 pub mod console;
 #[cfg(feature = "sinks-datadog")]
 pub mod datadog;
+#[cfg(feature = "sinks-delta_lake")]
+pub mod delta_lake;
+#[cfg(feature = "sinks-dynatrace")]
+pub mod dynatrace;
 #[cfg(feature = "sinks-elasticsearch")]
 pub mod elasticsearch;
+#[cfg(feature = "sinks-exec")]
+pub mod exec;
 #[cfg(feature = "sinks-file")]
 pub mod file;
 #[cfg(feature = "sinks-gcp")]
 pub mod gcp;
+#[cfg(feature = "sinks-gelf")]
+pub mod gelf;
+#[cfg(feature = "sinks-graphite")]
+pub mod graphite;
 #[cfg(feature = "sinks-honeycomb")]
 pub mod honeycomb;
 #[cfg(feature = "sinks-http")]
@@ -35,30 +53,58 @@ pub mod http;
 pub mod humio_logs;
 #[cfg(feature = "sinks-influxdb")]
 pub mod influxdb;
+#[cfg(all(feature = "sinks-journald", feature = "unix"))]
+pub mod journald;
 #[cfg(all(feature = "sinks-kafka", feature = "rdkafka"))]
 pub mod kafka;
 #[cfg(feature = "sinks-logdna")]
 pub mod logdna;
 #[cfg(feature = "sinks-loki")]
 pub mod loki;
+#[cfg(feature = "sinks-nats")]
+pub mod nats;
+#[cfg(feature = "sinks-new_relic")]
+pub mod new_relic;
 #[cfg(feature = "sinks-new_relic_logs")]
 pub mod new_relic_logs;
+#[cfg(feature = "sinks-opentelemetry")]
+pub mod opentelemetry;
+#[cfg(feature = "sinks-opentsdb")]
+pub mod opentsdb;
 #[cfg(feature = "sinks-papertrail")]
 pub mod papertrail;
+#[cfg(feature = "sinks-postgres")]
+pub mod postgres;
 #[cfg(feature = "sinks-prometheus")]
 pub mod prometheus;
 #[cfg(feature = "sinks-pulsar")]
 pub mod pulsar;
+#[cfg(feature = "sinks-questdb")]
+pub mod questdb;
+#[cfg(feature = "sinks-redis")]
+pub mod redis;
 #[cfg(feature = "sinks-sematext_logs")]
 pub mod sematext_logs;
+#[cfg(feature = "sinks-smtp")]
+pub mod smtp;
+#[cfg(feature = "sinks-snowflake")]
+pub mod snowflake;
 #[cfg(feature = "sinks-socket")]
 pub mod socket;
 #[cfg(feature = "sinks-splunk_hec")]
 pub mod splunk_hec;
 #[cfg(feature = "sinks-statsd")]
 pub mod statsd;
+#[cfg(feature = "sinks-syslog")]
+pub mod syslog;
+#[cfg(feature = "sinks-timescaledb")]
+pub mod timescaledb;
 #[cfg(feature = "sinks-vector")]
 pub mod vector;
+#[cfg(feature = "sinks-victoriametrics")]
+pub mod victoriametrics;
+#[cfg(feature = "sinks-wavefront")]
+pub mod wavefront;
 
 pub mod util;
 
@@ -0,0 +1,295 @@
+use crate::{
+    event::{self, Event},
+    internal_events::NatsEventSent,
+    nats::{self as connection, NatsAuthConfig, NatsTlsConfig},
+    sinks::util::{
+        encoding::{EncodingConfig, EncodingConfiguration},
+        retries::RetryLogic,
+        BatchEventsConfig, TowerRequestConfig,
+    },
+    template::Template,
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures::future::{FutureExt, TryFutureExt};
+use futures01::{stream::iter_ok, Future, Poll, Sink};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::spawn_blocking;
+use tower::Service;
+
+#[derive(Debug, Snafu)]
+pub enum PublishError {
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: crate::Error },
+    #[snafu(display("Publishing failed: {}", source))]
+    Publish { source: io::Error },
+    #[snafu(display("JetStream rejected the message: {}", reason))]
+    Rejected { reason: String },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NatsSinkConfig {
+    pub url: String,
+    pub subject: Template,
+    #[serde(default = "connection::default_connection_name")]
+    pub connection_name: String,
+    pub encoding: EncodingConfig<Encoding>,
+    /// Waits for each message to be stored by the JetStream stream of its
+    /// subject, rather than only for the server to have received it.
+    #[serde(default)]
+    pub jetstream: bool,
+    pub auth: Option<NatsAuthConfig>,
+    pub tls: Option<NatsTlsConfig>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        rate_limit_num: Some(1000),
+        timeout_secs: Some(30),
+        ..Default::default()
+    };
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Text,
+    Json,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<NatsSinkConfig>("nats")
+}
+
+#[typetag::serde(name = "nats")]
+impl SinkConfig for NatsSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let batch = self.batch.unwrap_or(100, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+        let subject = self.subject.clone();
+        let encoding = self.encoding.clone();
+
+        let service = NatsService {
+            config: self.clone(),
+            ack_timeout: request.timeout,
+            connection: Arc::new(Mutex::new(None)),
+        };
+        let healthcheck = service.clone().healthcheck().boxed().compat();
+        let sink = request
+            .batch_sink(NatsRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|error| error!("Fatal nats sink error: {}", error))
+            .with_flat_map(move |event| iter_ok(encode_event(event, &subject, &encoding)));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "nats"
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NatsMessage {
+    subject: String,
+    payload: Vec<u8>,
+}
+
+/// Publishes the batches from a blocking thread, as the client is blocking.
+#[derive(Clone)]
+struct NatsService {
+    config: NatsSinkConfig,
+    ack_timeout: Duration,
+    /// Connected on first use. The client reconnects on its own after,
+    /// buffering what is published meanwhile.
+    connection: Arc<Mutex<Option<nats::Connection>>>,
+}
+
+impl NatsService {
+    fn connection(&self) -> Result<nats::Connection, PublishError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(
+                connection::connect(
+                    &self.config.url,
+                    &self.config.connection_name,
+                    &self.config.auth,
+                    &self.config.tls,
+                )
+                .context(Connect)?,
+            );
+        }
+        Ok(connection.clone().expect("connected above"))
+    }
+
+    fn publish(&self, messages: &[NatsMessage]) -> Result<(), PublishError> {
+        let connection = self.connection()?;
+        for message in messages {
+            if self.config.jetstream {
+                let reply = connection
+                    .request_timeout(&message.subject, &message.payload, self.ack_timeout)
+                    .context(Publish)?;
+                check_ack(&reply.data)?;
+            } else {
+                connection
+                    .publish(&message.subject, &message.payload)
+                    .context(Publish)?;
+            }
+        }
+        // Waits for the server to have received the batch.
+        connection.flush().context(Publish)
+    }
+
+    async fn healthcheck(self) -> crate::Result<()> {
+        spawn_blocking(move || self.connection()?.flush().context(Publish)).await??;
+        Ok(())
+    }
+}
+
+impl Service<Vec<NatsMessage>> for NatsService {
+    type Response = ();
+    type Error = PublishError;
+    type Future = Box<dyn Future<Item = (), Error = PublishError> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, messages: Vec<NatsMessage>) -> Self::Future {
+        debug!(message = "publishing messages.", count = %messages.len());
+
+        let service = self.clone();
+        let publish = async move {
+            spawn_blocking(move || service.publish(&messages))
+                .await
+                .map_err(|error| PublishError::Publish {
+                    source: io::Error::new(io::ErrorKind::Other, error.to_string()),
+                })?
+        };
+        Box::new(publish.boxed().compat())
+    }
+}
+
+impl fmt::Debug for NatsService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NatsService")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PubAck {
+    error: Option<ApiError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    description: String,
+}
+
+fn check_ack(reply: &[u8]) -> Result<(), PublishError> {
+    match serde_json::from_slice::<PubAck>(reply) {
+        Ok(PubAck { error: None }) => Ok(()),
+        Ok(PubAck { error: Some(error) }) => Err(PublishError::Rejected {
+            reason: error.description,
+        }),
+        Err(_) => Err(PublishError::Rejected {
+            reason: String::from_utf8_lossy(reply).into_owned(),
+        }),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NatsRetryLogic;
+
+impl RetryLogic for NatsRetryLogic {
+    type Error = PublishError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        match error {
+            PublishError::Connect { .. } | PublishError::Publish { .. } => true,
+            PublishError::Rejected { .. } => false,
+        }
+    }
+}
+
+fn encode_event(
+    mut event: Event,
+    subject: &Template,
+    encoding: &EncodingConfig<Encoding>,
+) -> Option<NatsMessage> {
+    let subject = subject
+        .render_string(&event)
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event; dropping event.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()?;
+
+    encoding.apply_rules(&mut event);
+    let log = event.into_log();
+    let payload = match encoding.codec() {
+        Encoding::Json => serde_json::to_vec(&log).expect("Error encoding event as json."),
+        Encoding::Text => log
+            .get(&event::log_schema().message_key())
+            .map(|value| value.as_bytes().to_vec())
+            .unwrap_or_default(),
+    };
+
+    emit!(NatsEventSent {
+        byte_size: payload.len()
+    });
+    Some(NatsMessage { subject, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nats_encodes_to_templated_subjects() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("app", "web");
+        let subject = Template::from("logs.{{ app }}");
+
+        let message = encode_event(event.clone(), &subject, &Encoding::Text.into()).unwrap();
+        assert_eq!(message.subject, "logs.web");
+        assert_eq!(message.payload, b"hello");
+
+        let message = encode_event(event, &subject, &Encoding::Json.into()).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&message.payload).unwrap();
+        assert_eq!(json["app"], "web");
+
+        assert!(encode_event(Event::from("hello"), &subject, &Encoding::Text.into()).is_none());
+    }
+
+    #[test]
+    fn nats_checks_jetstream_acks() {
+        assert!(check_ack(br#"{"stream":"LOGS","seq":42}"#).is_ok());
+        match check_ack(br#"{"error":{"code":503,"description":"no responders"}}"#) {
+            Err(PublishError::Rejected { reason }) => assert_eq!(reason, "no responders"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(check_ack(b"-ERR").is_err());
+    }
+}
@@ -0,0 +1,495 @@
+use crate::{
+    event::{
+        log_schema,
+        metric::{Metric, MetricKind, MetricValue},
+        Event, Value,
+    },
+    sinks::{
+        util::{
+            http2::{BatchedHttpSink, HttpSink},
+            service2::TowerRequestConfig,
+            BatchBytesConfig, BoxedRawValue, Compression, JsonArrayBuffer,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use futures01::{future, Sink};
+use http02::{Request, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeMap, io::Write};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("The events API needs the `account_id` to send events to"))]
+    MissingAccountId,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewRelicConfig {
+    pub license_key: String,
+    #[serde(default)]
+    pub api: NewRelicApi,
+    #[serde(default)]
+    pub region: NewRelicRegion,
+    /// The account events are recorded in, needed by the events API.
+    pub account_id: Option<String>,
+    /// The type of the events, unless they have an `eventType` field.
+    #[serde(default = "default_event_type")]
+    pub event_type: String,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    #[serde(default)]
+    pub flatten: FlattenConfig,
+    #[serde(default)]
+    pub batch: BatchBytesConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NewRelicApi {
+    Logs,
+    Metrics,
+    Events,
+}
+
+impl Default for NewRelicApi {
+    fn default() -> Self {
+        NewRelicApi::Logs
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NewRelicRegion {
+    Us,
+    Eu,
+}
+
+impl Default for NewRelicRegion {
+    fn default() -> Self {
+        NewRelicRegion::Us
+    }
+}
+
+/// How nested fields become attributes, which New Relic only has flat.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FlattenConfig {
+    /// Joins the names of nested fields.
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    #[serde(default)]
+    pub arrays: ArrayFlattening,
+    /// Longer string values are truncated, as New Relic would.
+    #[serde(default = "default_max_value_length")]
+    pub max_value_length: usize,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> Self {
+        Self {
+            separator: default_separator(),
+            arrays: ArrayFlattening::default(),
+            max_value_length: default_max_value_length(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArrayFlattening {
+    /// An attribute per element, named after its index.
+    Index,
+    /// A single attribute holding the array as JSON.
+    Json,
+}
+
+impl Default for ArrayFlattening {
+    fn default() -> Self {
+        ArrayFlattening::Index
+    }
+}
+
+fn default_event_type() -> String {
+    "VectorEvent".into()
+}
+
+fn default_separator() -> String {
+    ".".into()
+}
+
+fn default_max_value_length() -> usize {
+    4096
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<NewRelicConfig>("new_relic")
+}
+
+#[typetag::serde(name = "new_relic")]
+impl SinkConfig for NewRelicConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        // Every API takes at most 1MB per request.
+        let batch = self.batch.unwrap_or(bytesize::mib(1u64), 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig {
+            in_flight_limit: Some(100),
+            rate_limit_num: Some(100),
+            ..Default::default()
+        });
+
+        let sink = NewRelicSink {
+            uri: self.uri()?,
+            interval_ms: batch.timeout.as_millis() as u64,
+            config: self.clone(),
+        };
+        let sink = BatchedHttpSink::new(sink, JsonArrayBuffer::default(), request, batch, tls, &cx)
+            .sink_map_err(|e| error!("Fatal new_relic sink error: {}", e));
+
+        // License keys can only write, so there is nothing to check them with.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        match self.api {
+            NewRelicApi::Metrics => DataType::Metric,
+            NewRelicApi::Logs | NewRelicApi::Events => DataType::Log,
+        }
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "new_relic"
+    }
+}
+
+impl NewRelicConfig {
+    fn uri(&self) -> crate::Result<Uri> {
+        let uri = match (self.api, self.region) {
+            (NewRelicApi::Logs, NewRelicRegion::Us) => "https://log-api.newrelic.com/log/v1".into(),
+            (NewRelicApi::Logs, NewRelicRegion::Eu) => {
+                "https://log-api.eu.newrelic.com/log/v1".into()
+            }
+            (NewRelicApi::Metrics, NewRelicRegion::Us) => {
+                "https://metric-api.newrelic.com/metric/v1".into()
+            }
+            (NewRelicApi::Metrics, NewRelicRegion::Eu) => {
+                "https://metric-api.eu.newrelic.com/metric/v1".into()
+            }
+            (NewRelicApi::Events, region) => {
+                let account_id = self
+                    .account_id
+                    .as_ref()
+                    .ok_or(BuildError::MissingAccountId)?;
+                let host = match region {
+                    NewRelicRegion::Us => "insights-collector.newrelic.com",
+                    NewRelicRegion::Eu => "insights-collector.eu01.nr-data.net",
+                };
+                format!("https://{}/v1/accounts/{}/events", host, account_id)
+            }
+        };
+        Ok(uri.parse::<Uri>().context(super::UriParseError2)?)
+    }
+}
+
+struct NewRelicSink {
+    config: NewRelicConfig,
+    uri: Uri,
+    /// The window counts are reported over.
+    interval_ms: u64,
+}
+
+impl HttpSink for NewRelicSink {
+    type Input = serde_json::Value;
+    type Output = Vec<BoxedRawValue>;
+
+    fn encode_event(&self, event: Event) -> Option<Self::Input> {
+        match (self.config.api, event) {
+            (NewRelicApi::Logs, Event::Log(mut log)) => {
+                let message = log
+                    .remove(&log_schema().message_key())
+                    .map(|message| message.to_string_lossy());
+                let timestamp = timestamp_millis(log.remove(&log_schema().timestamp_key()));
+
+                let mut attributes = Map::new();
+                for (key, value) in log {
+                    flatten(&key, &value, &self.config.flatten, &mut attributes);
+                }
+
+                Some(json!({
+                    "message": message.unwrap_or_default(),
+                    "timestamp": timestamp,
+                    "attributes": attributes,
+                }))
+            }
+            (NewRelicApi::Events, Event::Log(mut log)) => {
+                let timestamp = timestamp_millis(log.remove(&log_schema().timestamp_key()));
+
+                let mut attributes = Map::new();
+                for (key, value) in log {
+                    flatten(&key, &value, &self.config.flatten, &mut attributes);
+                }
+                attributes
+                    .entry("eventType")
+                    .or_insert_with(|| self.config.event_type.clone().into());
+                attributes.insert("timestamp".into(), timestamp.into());
+
+                Some(serde_json::Value::Object(attributes))
+            }
+            (NewRelicApi::Metrics, Event::Metric(metric)) => self.encode_metric(metric),
+            _ => None,
+        }
+    }
+
+    fn build_request(&self, events: Self::Output) -> Request<Vec<u8>> {
+        let body = match self.config.api {
+            NewRelicApi::Logs => serde_json::to_vec(&json!([{ "logs": events }])),
+            NewRelicApi::Metrics => serde_json::to_vec(&json!([{ "metrics": events }])),
+            NewRelicApi::Events => serde_json::to_vec(&events),
+        }
+        .expect("payloads serialize to JSON");
+
+        let key_header = match self.config.api {
+            NewRelicApi::Metrics => "Api-Key",
+            NewRelicApi::Logs | NewRelicApi::Events => "X-License-Key",
+        };
+        let mut builder = Request::post(self.uri.clone())
+            .header("Content-Type", "application/json")
+            .header(key_header, self.config.license_key.as_str());
+        let body = match self.config.compression {
+            Compression::None => body,
+            Compression::Gzip => {
+                builder = builder.header("Content-Encoding", "gzip");
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&body)
+                    .expect("Writing to a Vec can't fail");
+                encoder.finish().expect("Writing to a Vec can't fail")
+            }
+        };
+        builder.body(body).unwrap()
+    }
+}
+
+impl NewRelicSink {
+    fn encode_metric(&self, metric: Metric) -> Option<serde_json::Value> {
+        let timestamp = metric.timestamp.unwrap_or_else(Utc::now).timestamp_millis();
+        let attributes = metric.tags.unwrap_or_else(BTreeMap::new);
+
+        let (kind, value) = match (metric.kind, metric.value) {
+            (MetricKind::Incremental, MetricValue::Counter { value })
+            | (MetricKind::Incremental, MetricValue::Gauge { value }) => ("count", json!(value)),
+            (MetricKind::Absolute, MetricValue::Counter { value })
+            | (MetricKind::Absolute, MetricValue::Gauge { value }) => ("gauge", json!(value)),
+            (_, MetricValue::Set { values }) => ("gauge", json!(values.len())),
+            (
+                _,
+                MetricValue::Distribution {
+                    values,
+                    sample_rates,
+                },
+            ) if !values.is_empty() => {
+                let count = sample_rates.iter().map(|rate| *rate as f64).sum::<f64>();
+                let sum = values
+                    .iter()
+                    .zip(&sample_rates)
+                    .map(|(value, rate)| value * *rate as f64)
+                    .sum::<f64>();
+                let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                (
+                    "summary",
+                    json!({ "count": count, "sum": sum, "min": min, "max": max }),
+                )
+            }
+            _ => {
+                debug!(
+                    message = "Unsupported metric type; dropping metric.",
+                    name = %metric.name,
+                    rate_limit_secs = 30,
+                );
+                return None;
+            }
+        };
+
+        let mut encoded = json!({
+            "name": metric.name,
+            "type": kind,
+            "value": value,
+            "timestamp": timestamp,
+            "attributes": attributes,
+        });
+        if kind != "gauge" {
+            encoded["interval.ms"] = self.interval_ms.max(1).into();
+        }
+        Some(encoded)
+    }
+}
+
+fn timestamp_millis(timestamp: Option<Value>) -> i64 {
+    match timestamp {
+        Some(Value::Timestamp(timestamp)) => timestamp.timestamp_millis(),
+        _ => Utc::now().timestamp_millis(),
+    }
+}
+
+/// Adds the value as attributes named after its path, following the rules.
+fn flatten(
+    name: &str,
+    value: &Value,
+    rules: &FlattenConfig,
+    attributes: &mut Map<String, serde_json::Value>,
+) {
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map {
+                let name = format!("{}{}{}", name, rules.separator, key);
+                flatten(&name, value, rules, attributes);
+            }
+        }
+        Value::Array(array) => match rules.arrays {
+            ArrayFlattening::Index => {
+                for (index, value) in array.iter().enumerate() {
+                    let name = format!("{}{}{}", name, rules.separator, index);
+                    flatten(&name, value, rules, attributes);
+                }
+            }
+            ArrayFlattening::Json => {
+                let json = serde_json::to_string(value).expect("values serialize to JSON");
+                attributes.insert(name.into(), truncate(json, rules).into());
+            }
+        },
+        Value::Bytes(_) => {
+            attributes.insert(name.into(), truncate(value.to_string_lossy(), rules).into());
+        }
+        Value::Integer(integer) => {
+            attributes.insert(name.into(), (*integer).into());
+        }
+        Value::Float(float) => {
+            attributes.insert(name.into(), (*float).into());
+        }
+        Value::Boolean(boolean) => {
+            attributes.insert(name.into(), (*boolean).into());
+        }
+        Value::Timestamp(timestamp) => {
+            attributes.insert(name.into(), timestamp.timestamp_millis().into());
+        }
+        Value::Null => (),
+    }
+}
+
+fn truncate(mut value: String, rules: &FlattenConfig) -> String {
+    if value.len() > rules.max_value_length {
+        let mut end = rules.max_value_length;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+    use chrono::TimeZone;
+
+    fn sink(config: &str) -> NewRelicSink {
+        let (config, _, _) = load_sink::<NewRelicConfig>(config).unwrap();
+        NewRelicSink {
+            uri: config.uri().unwrap(),
+            interval_ms: 1000,
+            config,
+        }
+    }
+
+    fn body(sink: &NewRelicSink, encoded: serde_json::Value) -> serde_json::Value {
+        let raw = serde_json::value::RawValue::from_string(encoded.to_string()).unwrap();
+        let request = sink.build_request(vec![raw]);
+        serde_json::from_slice(request.body()).unwrap()
+    }
+
+    #[test]
+    fn new_relic_flattens_log_attributes() {
+        let sink = sink(
+            r#"
+            license_key = "abc"
+            region = "eu"
+            compression = "none"
+            flatten.separator = "_"
+            flatten.max_value_length = 3
+        "#,
+        );
+
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("http.status", 200);
+        event.as_mut_log().insert("tags[0]", "abcdef");
+        let encoded = sink.encode_event(event).unwrap();
+        assert_eq!(encoded["message"], "hello");
+        assert_eq!(encoded["attributes"]["http_status"], 200);
+        assert_eq!(encoded["attributes"]["tags_0"], "abc");
+
+        let raw = serde_json::value::RawValue::from_string(encoded.to_string()).unwrap();
+        let request = sink.build_request(vec![raw]);
+        assert_eq!(
+            request.uri(),
+            &Uri::from_static("https://log-api.eu.newrelic.com/log/v1")
+        );
+        assert_eq!(request.headers()["X-License-Key"], "abc");
+        let body: serde_json::Value = serde_json::from_slice(request.body()).unwrap();
+        assert_eq!(body[0]["logs"][0]["message"], "hello");
+    }
+
+    #[test]
+    fn new_relic_encodes_metrics() {
+        let sink = sink(
+            r#"
+            license_key = "abc"
+            api = "metrics"
+            compression = "none"
+        "#,
+        );
+
+        let metric = Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.ymd(2020, 8, 1).and_hms(0, 0, 0)),
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 3.0 },
+        };
+        let encoded = sink.encode_event(Event::Metric(metric)).unwrap();
+        assert_eq!(
+            body(&sink, encoded),
+            json!([{ "metrics": [{
+                "name": "requests",
+                "type": "count",
+                "value": 3.0,
+                "timestamp": 1_596_240_000_000i64,
+                "interval.ms": 1000,
+                "attributes": {},
+            }]}])
+        );
+    }
+
+    #[test]
+    fn new_relic_events_need_an_account() {
+        let (config, _, _) = load_sink::<NewRelicConfig>(
+            r#"
+            license_key = "abc"
+            api = "events"
+        "#,
+        )
+        .unwrap();
+        assert!(config.uri().is_err());
+    }
+}
@@ -0,0 +1,505 @@
+//! Conversion of Vector events into OTLP payloads.
+
+use crate::event::{
+    self,
+    metric::{Metric, MetricKind, MetricValue},
+    LogEvent, Value,
+};
+use crate::opentelemetry::proto::{
+    collector::{logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest},
+    common::v1::{any_value, AnyValue, ArrayValue, InstrumentationLibrary, KeyValue, KeyValueList},
+    logs::v1::{InstrumentationLibraryLogs, LogRecord, ResourceLogs},
+    metrics::v1::{
+        metric::Data, number_data_point, summary_data_point::ValueAtQuantile,
+        AggregationTemporality, Gauge, Histogram, HistogramDataPoint,
+        InstrumentationLibraryMetrics, Metric as OtlpMetric, NumberDataPoint, ResourceMetrics, Sum,
+        Summary, SummaryDataPoint,
+    },
+    resource::v1::Resource,
+};
+use chrono::Utc;
+use std::collections::BTreeMap;
+
+/// Groups the logs by resource and instrumentation scope. `resource` holds
+/// the configured attributes, which the ones found on the event override.
+pub fn logs_to_request(logs: Vec<LogEvent>, resource: &[KeyValue]) -> ExportLogsServiceRequest {
+    let mut resource_logs: Vec<ResourceLogs> = Vec::new();
+
+    for log in logs {
+        let (attributes, library, record) = encode_log(log);
+        let resource = Some(make_resource(attributes, resource));
+
+        let index = match resource_logs.iter().position(|r| r.resource == resource) {
+            Some(index) => index,
+            None => {
+                resource_logs.push(ResourceLogs {
+                    resource,
+                    ..Default::default()
+                });
+                resource_logs.len() - 1
+            }
+        };
+        let libraries = &mut resource_logs[index].instrumentation_library_logs;
+
+        match libraries
+            .iter_mut()
+            .find(|l| l.instrumentation_library == library)
+        {
+            Some(library_logs) => library_logs.log_records.push(record),
+            None => libraries.push(InstrumentationLibraryLogs {
+                instrumentation_library: library,
+                log_records: vec![record],
+                ..Default::default()
+            }),
+        }
+    }
+
+    ExportLogsServiceRequest { resource_logs }
+}
+
+/// Groups the metrics by resource. Tags listed in `resource_tags` become
+/// resource attributes, all others are attributes of the data point.
+pub fn metrics_to_request(
+    metrics: Vec<Metric>,
+    resource: &[KeyValue],
+    resource_tags: &[String],
+) -> ExportMetricsServiceRequest {
+    let mut resource_metrics: Vec<ResourceMetrics> = Vec::new();
+
+    for mut metric in metrics {
+        let (resource_attributes, attributes) = metric
+            .tags
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| key_value(key, value.into()))
+            .partition::<Vec<_>, _>(|kv| resource_tags.contains(&kv.key));
+        let resource = Some(make_resource(resource_attributes, resource));
+        let metric = encode_metric(metric, attributes);
+
+        match resource_metrics.iter_mut().find(|r| r.resource == resource) {
+            Some(resource_metrics) => {
+                resource_metrics.instrumentation_library_metrics[0]
+                    .metrics
+                    .push(metric);
+            }
+            None => resource_metrics.push(ResourceMetrics {
+                resource,
+                instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                    metrics: vec![metric],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+        }
+    }
+
+    ExportMetricsServiceRequest { resource_metrics }
+}
+
+fn make_resource(mut attributes: Vec<KeyValue>, defaults: &[KeyValue]) -> Resource {
+    for default in defaults {
+        if !attributes.iter().any(|kv| kv.key == default.key) {
+            attributes.push(default.clone());
+        }
+    }
+    attributes.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Resource {
+        attributes,
+        ..Default::default()
+    }
+}
+
+/// Maps the fields written by the `opentelemetry` source back onto the log
+/// record, every other field becomes an attribute.
+fn encode_log(log: LogEvent) -> (Vec<KeyValue>, Option<InstrumentationLibrary>, LogRecord) {
+    let message_key = event::log_schema().message_key();
+    let timestamp_key = event::log_schema().timestamp_key();
+    let source_type_key = event::log_schema().source_type_key();
+
+    let mut resource = Vec::new();
+    let mut library = None;
+    let mut record = LogRecord {
+        observed_time_unix_nano: Utc::now().timestamp_nanos() as u64,
+        ..Default::default()
+    };
+
+    for (key, value) in log {
+        match (key.as_str(), value) {
+            (key, value) if key == &message_key[..] => {
+                record.body = Some(value_to_any_value(value));
+            }
+            (key, Value::Timestamp(timestamp)) if key == &timestamp_key[..] => {
+                record.time_unix_nano = timestamp.timestamp_nanos() as u64;
+            }
+            (key, _) if key == &source_type_key[..] => (),
+            ("severity_text", value) => record.severity_text = value.to_string_lossy(),
+            ("severity_number", Value::Integer(number)) => record.severity_number = number as i32,
+            ("trace_id", Value::Bytes(id)) => match from_hex(&id) {
+                Some(id) => record.trace_id = id,
+                None => record
+                    .attributes
+                    .push(key_value("trace_id".into(), Value::Bytes(id))),
+            },
+            ("span_id", Value::Bytes(id)) => match from_hex(&id) {
+                Some(id) => record.span_id = id,
+                None => record
+                    .attributes
+                    .push(key_value("span_id".into(), Value::Bytes(id))),
+            },
+            ("attributes", Value::Map(map)) => record.attributes.extend(map_to_key_values(map)),
+            ("resources", Value::Map(map)) => resource.extend(map_to_key_values(map)),
+            ("scope", Value::Map(map)) => {
+                let field = |name: &str| {
+                    map.get(name)
+                        .map(Value::to_string_lossy)
+                        .unwrap_or_default()
+                };
+                library = Some(InstrumentationLibrary {
+                    name: field("name"),
+                    version: field("version"),
+                });
+            }
+            (key, value) => record.attributes.push(key_value(key.into(), value)),
+        }
+    }
+
+    (resource, library, record)
+}
+
+fn encode_metric(metric: Metric, attributes: Vec<KeyValue>) -> OtlpMetric {
+    let time_unix_nano = metric.timestamp.unwrap_or_else(Utc::now).timestamp_nanos() as u64;
+    let temporality = match metric.kind {
+        MetricKind::Incremental => AggregationTemporality::Delta,
+        MetricKind::Absolute => AggregationTemporality::Cumulative,
+    } as i32;
+
+    let data = match metric.value {
+        MetricValue::Counter { value } => Data::Sum(Sum {
+            data_points: vec![number_point(attributes, time_unix_nano, value)],
+            aggregation_temporality: temporality,
+            is_monotonic: true,
+        }),
+        // OTLP gauges are always absolute, a change of a gauge is a non
+        // monotonic delta sum.
+        MetricValue::Gauge { value } if metric.kind == MetricKind::Incremental => Data::Sum(Sum {
+            data_points: vec![number_point(attributes, time_unix_nano, value)],
+            aggregation_temporality: temporality,
+            is_monotonic: false,
+        }),
+        MetricValue::Gauge { value } => Data::Gauge(Gauge {
+            data_points: vec![number_point(attributes, time_unix_nano, value)],
+        }),
+        MetricValue::Set { values } => Data::Gauge(Gauge {
+            data_points: vec![number_point(
+                attributes,
+                time_unix_nano,
+                values.len() as f64,
+            )],
+        }),
+        MetricValue::Distribution {
+            values,
+            sample_rates,
+        } => {
+            let count = sample_rates.iter().map(|rate| *rate as u64).sum();
+            let sum = values
+                .iter()
+                .zip(sample_rates.iter())
+                .map(|(value, rate)| value * *rate as f64)
+                .sum();
+            Data::Summary(Summary {
+                data_points: vec![SummaryDataPoint {
+                    attributes,
+                    time_unix_nano,
+                    count,
+                    sum,
+                    ..Default::default()
+                }],
+            })
+        }
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => {
+            // Vector's bucket counts are cumulative, OTLP's are per bucket
+            // with an additional one for everything above the last bound.
+            let mut bucket_counts: Vec<u64> = counts
+                .iter()
+                .scan(0, |previous, count| {
+                    let bucket = count.saturating_sub(*previous);
+                    *previous = *count;
+                    Some(bucket as u64)
+                })
+                .collect();
+            let last = counts.last().copied().unwrap_or(0);
+            bucket_counts.push(count.saturating_sub(last) as u64);
+
+            Data::Histogram(Histogram {
+                data_points: vec![HistogramDataPoint {
+                    attributes,
+                    time_unix_nano,
+                    count: count as u64,
+                    sum,
+                    bucket_counts,
+                    explicit_bounds: buckets,
+                    ..Default::default()
+                }],
+                aggregation_temporality: temporality,
+            })
+        }
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => Data::Summary(Summary {
+            data_points: vec![SummaryDataPoint {
+                attributes,
+                time_unix_nano,
+                count: count as u64,
+                sum,
+                quantile_values: quantiles
+                    .into_iter()
+                    .zip(values.into_iter())
+                    .map(|(quantile, value)| ValueAtQuantile { quantile, value })
+                    .collect(),
+                ..Default::default()
+            }],
+        }),
+    };
+
+    OtlpMetric {
+        name: metric.name,
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+fn number_point(attributes: Vec<KeyValue>, time_unix_nano: u64, value: f64) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        time_unix_nano,
+        value: Some(number_data_point::Value::AsDouble(value)),
+        ..Default::default()
+    }
+}
+
+fn from_hex(hex: &[u8]) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    hex.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok()?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+pub fn key_value(key: String, value: Value) -> KeyValue {
+    KeyValue {
+        key,
+        value: Some(value_to_any_value(value)),
+    }
+}
+
+fn map_to_key_values(map: BTreeMap<String, Value>) -> Vec<KeyValue> {
+    map.into_iter()
+        .map(|(key, value)| key_value(key, value))
+        .collect()
+}
+
+fn value_to_any_value(value: Value) -> AnyValue {
+    let value = match value {
+        Value::Bytes(bytes) => Some(any_value::Value::StringValue(
+            String::from_utf8_lossy(&bytes).into_owned(),
+        )),
+        Value::Integer(i) => Some(any_value::Value::IntValue(i)),
+        Value::Float(f) => Some(any_value::Value::DoubleValue(f)),
+        Value::Boolean(b) => Some(any_value::Value::BoolValue(b)),
+        Value::Timestamp(timestamp) => Some(any_value::Value::StringValue(timestamp.to_rfc3339())),
+        Value::Map(map) => Some(any_value::Value::KvlistValue(KeyValueList {
+            values: map_to_key_values(map),
+        })),
+        Value::Array(array) => Some(any_value::Value::ArrayValue(ArrayValue {
+            values: array.into_iter().map(value_to_any_value).collect(),
+        })),
+        Value::Null => None,
+    };
+    AnyValue { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use chrono::{offset::TimeZone, Utc};
+
+    fn string(value: &str) -> Option<AnyValue> {
+        Some(value_to_any_value(value.into()))
+    }
+
+    #[test]
+    fn opentelemetry_encodes_logs() {
+        let mut event = Event::from("payment failed");
+        let log = event.as_mut_log();
+        log.insert(
+            event::log_schema().timestamp_key().clone(),
+            Utc.timestamp(1_500_000_000, 1),
+        );
+        log.insert("severity_text", "ERROR");
+        log.insert("severity_number", 17);
+        log.insert("trace_id", "5b8efff798038103d269b633813fc60c");
+        log.insert("attributes.user", "alice");
+        log.insert("resources.service.name", "checkout");
+        log.insert("scope.name", "payments");
+        log.insert("host", "web-1");
+
+        let resource = [key_value("deployment".into(), "prod".into())];
+        let request = logs_to_request(vec![event.into_log()], &resource);
+
+        assert_eq!(request.resource_logs.len(), 1);
+        let resource_logs = &request.resource_logs[0];
+        assert_eq!(
+            resource_logs.resource.as_ref().unwrap().attributes,
+            vec![
+                key_value("deployment".into(), "prod".into()),
+                key_value(
+                    "service".into(),
+                    Value::Map(
+                        vec![("name".to_string(), "checkout".into())]
+                            .into_iter()
+                            .collect()
+                    )
+                ),
+            ]
+        );
+
+        let library_logs = &resource_logs.instrumentation_library_logs[0];
+        assert_eq!(
+            library_logs.instrumentation_library.as_ref().unwrap().name,
+            "payments"
+        );
+
+        let record = &library_logs.log_records[0];
+        assert_eq!(record.body, string("payment failed"));
+        assert_eq!(record.time_unix_nano, 1_500_000_000_000_000_001);
+        assert_eq!(record.severity_text, "ERROR");
+        assert_eq!(record.severity_number, 17);
+        assert_eq!(record.trace_id.len(), 16);
+        assert_eq!(record.trace_id[0], 0x5b);
+        assert_eq!(
+            record.attributes,
+            vec![
+                key_value("user".into(), "alice".into()),
+                key_value("host".into(), "web-1".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn opentelemetry_groups_logs_by_resource() {
+        let logs = ["a", "b", "a"]
+            .iter()
+            .map(|service| {
+                let mut event = Event::from("message");
+                event
+                    .as_mut_log()
+                    .insert("resources.service", service.to_string());
+                event.into_log()
+            })
+            .collect();
+
+        let request = logs_to_request(logs, &[]);
+
+        assert_eq!(request.resource_logs.len(), 2);
+        assert_eq!(
+            request.resource_logs[0].instrumentation_library_logs[0]
+                .log_records
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn opentelemetry_encodes_histograms() {
+        let metric = Metric {
+            name: "latency".into(),
+            timestamp: None,
+            tags: Some(
+                vec![
+                    ("host".to_owned(), "web-1".to_owned()),
+                    ("route".to_owned(), "/".to_owned()),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            kind: MetricKind::Absolute,
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![0.1, 1.0],
+                counts: vec![2, 5],
+                count: 6,
+                sum: 3.5,
+            },
+        };
+
+        let request = metrics_to_request(vec![metric], &[], &["host".to_owned()]);
+
+        let resource_metrics = &request.resource_metrics[0];
+        assert_eq!(
+            resource_metrics.resource.as_ref().unwrap().attributes,
+            vec![key_value("host".into(), "web-1".into())]
+        );
+
+        let metric = &resource_metrics.instrumentation_library_metrics[0].metrics[0];
+        assert_eq!(metric.name, "latency");
+        match &metric.data {
+            Some(Data::Histogram(histogram)) => {
+                assert_eq!(
+                    histogram.aggregation_temporality,
+                    AggregationTemporality::Cumulative as i32
+                );
+                let point = &histogram.data_points[0];
+                assert_eq!(point.bucket_counts, vec![2, 3, 1]);
+                assert_eq!(point.explicit_bounds, vec![0.1, 1.0]);
+                assert_eq!(point.count, 6);
+                assert_eq!(
+                    point.attributes,
+                    vec![key_value("route".into(), "/".into())]
+                );
+            }
+            data => panic!("Unexpected metric data {:?}", data),
+        }
+    }
+
+    #[test]
+    fn opentelemetry_encodes_incremental_counters_as_delta_sums() {
+        let metric = Metric {
+            name: "requests".into(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 3.0 },
+        };
+
+        let request = metrics_to_request(vec![metric], &[], &[]);
+
+        match &request.resource_metrics[0].instrumentation_library_metrics[0].metrics[0].data {
+            Some(Data::Sum(sum)) => {
+                assert!(sum.is_monotonic);
+                assert_eq!(
+                    sum.aggregation_temporality,
+                    AggregationTemporality::Delta as i32
+                );
+                assert_eq!(
+                    sum.data_points[0].value,
+                    Some(number_data_point::Value::AsDouble(3.0))
+                );
+            }
+            data => panic!("Unexpected metric data {:?}", data),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    event::Event,
+    opentelemetry::proto::common::v1::KeyValue,
+    sinks::{
+        util::{
+            grpc::{self, GrpcRetryLogic},
+            http2::HttpClient,
+            retries2::RetryLogic,
+            service2::TowerRequestConfig,
+            BatchEventsConfig, Compression, UriSerde,
+        },
+        UriParseError2,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes05::Bytes;
+use flate2::write::GzEncoder;
+use futures::future::BoxFuture;
+use futures01::{future, Sink};
+use http02::{
+    header::{self, HeaderName, HeaderValue},
+    Method, Request, Response, Uri,
+};
+use hyper13::Body;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    io::Write,
+    task::{Context, Poll},
+};
+use tower03::Service;
+
+mod encode;
+
+const LOGS_GRPC_PATH: &str = "/opentelemetry.proto.collector.logs.v1.LogsService/Export";
+const METRICS_GRPC_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("{}: {}", source, name))]
+    InvalidHeaderName {
+        name: String,
+        source: header::InvalidHeaderName,
+    },
+    #[snafu(display("{}: {}", source, value))]
+    InvalidHeaderValue {
+        value: String,
+        source: header::InvalidHeaderValue,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OpentelemetrySinkConfig {
+    pub endpoint: UriSerde,
+    #[serde(default)]
+    pub headers: IndexMap<String, String>,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    #[serde(default)]
+    pub resource: IndexMap<String, String>,
+    #[serde(default)]
+    pub resource_tags: Vec<String>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(5),
+        timeout_secs: Some(30),
+        rate_limit_num: Some(10),
+        ..Default::default()
+    };
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<OpentelemetrySinkConfig>("opentelemetry")
+}
+
+#[typetag::serde(name = "opentelemetry")]
+impl SinkConfig for OpentelemetrySinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let client = HttpClient::new_http2(cx.resolver(), Some(tls))?;
+
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| InvalidHeaderName { name })?;
+                let value =
+                    HeaderValue::from_str(value).with_context(|| InvalidHeaderValue { value })?;
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+
+        let service = OpentelemetryService {
+            client,
+            logs_uri: self.uri(LOGS_GRPC_PATH)?,
+            metrics_uri: self.uri(METRICS_GRPC_PATH)?,
+            headers,
+            compression: self.compression,
+            resource: self
+                .resource
+                .iter()
+                .map(|(key, value)| encode::key_value(key.clone(), value.clone().into()))
+                .collect(),
+            resource_tags: self.resource_tags.clone(),
+        };
+
+        let batch = self.batch.unwrap_or(1000, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+
+        let sink = request
+            .batch_sink(GrpcRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|e| error!("Fatal opentelemetry sink error: {}", e));
+
+        // gRPC has no request we could send without side effects, the
+        // health service is optional and rarely exposed by collectors.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "opentelemetry"
+    }
+}
+
+impl OpentelemetrySinkConfig {
+    fn uri(&self, path: &str) -> crate::Result<Uri> {
+        let endpoint = self.endpoint.to_string();
+        let uri = format!("{}{}", endpoint.trim_end_matches('/'), path)
+            .parse::<Uri>()
+            .context(UriParseError2)?;
+        Ok(uri)
+    }
+}
+
+#[derive(Clone)]
+struct OpentelemetryService {
+    client: HttpClient,
+    logs_uri: Uri,
+    metrics_uri: Uri,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    compression: Compression,
+    resource: Vec<KeyValue>,
+    resource_tags: Vec<String>,
+}
+
+impl OpentelemetryService {
+    fn build_request(&self, uri: &Uri, message: impl Message) -> Request<Body> {
+        let mut body = Vec::with_capacity(message.encoded_len());
+        message
+            .encode(&mut body)
+            .expect("Encoding into a Vec can't fail");
+
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(uri.clone())
+            .header("Content-Type", "application/grpc")
+            .header("TE", "trailers");
+
+        let body = match self.compression {
+            Compression::None => grpc::frame(false, &body),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&body)
+                    .and_then(|_| encoder.finish())
+                    .map(|body| grpc::frame(true, &body))
+                    .expect("Writing to a Vec can't fail")
+            }
+        };
+        if let Some(encoding) = self.compression.content_encoding() {
+            builder = builder.header("grpc-encoding", encoding);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+
+        builder.body(body.into()).unwrap()
+    }
+}
+
+impl Service<Vec<Event>> for OpentelemetryService {
+    type Response = Response<Bytes>;
+    type Error = hyper13::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, events: Vec<Event>) -> Self::Future {
+        let (logs, metrics): (Vec<_>, Vec<_>) = events
+            .into_iter()
+            .partition(|event| matches!(event, Event::Log(_)));
+
+        let mut requests = Vec::new();
+        if !logs.is_empty() {
+            let logs = logs.into_iter().map(Event::into_log).collect();
+            let message = encode::logs_to_request(logs, &self.resource);
+            requests.push(self.build_request(&self.logs_uri, message));
+        }
+        if !metrics.is_empty() {
+            let metrics = metrics.into_iter().map(Event::into_metric).collect();
+            let message = encode::metrics_to_request(metrics, &self.resource, &self.resource_tags);
+            requests.push(self.build_request(&self.metrics_uri, message));
+        }
+
+        let mut client = self.client.clone();
+        Box::pin(async move {
+            // A batch holding both logs and metrics is exported with two
+            // calls. The first failure is returned, so that a retry sends the
+            // whole batch again.
+            let mut response = Response::new(Bytes::new());
+            for request in requests {
+                response = grpc::call(&mut client, request).await?;
+                if !GrpcRetryLogic
+                    .should_retry_response(&response)
+                    .is_successful()
+                {
+                    break;
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    #[test]
+    fn opentelemetry_builds_uris() {
+        let (config, _, _) = load_sink::<OpentelemetrySinkConfig>(
+            r#"
+            endpoint = "http://localhost:4317/"
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.uri(LOGS_GRPC_PATH).unwrap(),
+            "http://localhost:4317/opentelemetry.proto.collector.logs.v1.LogsService/Export"
+        );
+        assert_eq!(config.compression, Compression::Gzip);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{
+    dns::Resolver,
+    event::{
+        metric::{Metric, MetricValue},
+        Event,
+    },
+    sinks::{
+        util::{
+            http2::{Auth, BatchedHttpSink, HttpClient, HttpSink},
+            retries2::{RetryAction, RetryLogic},
+            service2::TowerRequestConfig,
+            BatchEventsConfig, Compression, UriSerde,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes05::Bytes;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use futures::{FutureExt, TryFutureExt};
+use futures01::Sink;
+use http02::{Request, StatusCode, Uri};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::ResultExt;
+use std::{collections::BTreeMap, io::Write};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OpenTSDBConfig {
+    pub endpoint: UriSerde,
+    pub namespace: Option<String>,
+    /// Renames metric tags, from their name to the one used in OpenTSDB.
+    #[serde(default)]
+    pub tag_map: IndexMap<String, String>,
+    /// Tags added to every datapoint, unless the metric has them already.
+    #[serde(default)]
+    pub tags: IndexMap<String, String>,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub auth: Option<Auth>,
+    pub tls: Option<TlsOptions>,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<OpenTSDBConfig>("opentsdb")
+}
+
+#[typetag::serde(name = "opentsdb")]
+impl SinkConfig for OpenTSDBConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let healthcheck = healthcheck(self.clone(), cx.resolver(), tls.clone())
+            .boxed()
+            .compat();
+
+        // OpenTSDB recommends keeping puts to about 50 datapoints.
+        let batch = self.batch.unwrap_or(50, 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig::default());
+
+        let sink = OpenTSDBSink {
+            uri: self.uri("/api/put?summary&details")?,
+            config: self.clone(),
+        };
+        let sink = BatchedHttpSink::with_retry_logic(
+            sink,
+            Vec::new(),
+            OpenTSDBRetryLogic,
+            request,
+            batch,
+            tls,
+            &cx,
+        )
+        .sink_map_err(|e| error!("Fatal opentsdb sink error: {}", e));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "opentsdb"
+    }
+}
+
+impl OpenTSDBConfig {
+    fn uri(&self, path: &str) -> crate::Result<Uri> {
+        let endpoint = self.endpoint.to_string();
+        let uri = format!("{}{}", endpoint.trim_end_matches('/'), path);
+        Ok(uri.parse::<Uri>().context(super::UriParseError2)?)
+    }
+}
+
+struct OpenTSDBSink {
+    config: OpenTSDBConfig,
+    uri: Uri,
+}
+
+impl HttpSink for OpenTSDBSink {
+    type Input = Metric;
+    type Output = Vec<Metric>;
+
+    fn encode_event(&self, event: Event) -> Option<Self::Input> {
+        let mut metric = event.into_metric();
+        if metric.timestamp.is_none() {
+            metric.timestamp = Some(Utc::now());
+        }
+        Some(metric)
+    }
+
+    fn build_request(&self, metrics: Self::Output) -> Request<Vec<u8>> {
+        let datapoints = metrics
+            .into_iter()
+            .flat_map(|metric| datapoints(metric, &self.config))
+            .collect::<Vec<_>>();
+        let body = serde_json::to_vec(&datapoints).expect("datapoints serialize to JSON");
+
+        let mut builder =
+            Request::post(self.uri.clone()).header("Content-Type", "application/json");
+        let body = match self.config.compression {
+            Compression::None => body,
+            Compression::Gzip => {
+                builder = builder.header("Content-Encoding", "gzip");
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&body)
+                    .expect("Writing to a Vec can't fail");
+                encoder.finish().expect("Writing to a Vec can't fail")
+            }
+        };
+
+        let mut request = builder.body(body).unwrap();
+        if let Some(auth) = &self.config.auth {
+            auth.apply(&mut request);
+        }
+        request
+    }
+}
+
+/// The suffix of a series, an extra tag and its value.
+type Statistic = (&'static str, Option<(&'static str, f64)>, f64);
+
+/// OpenTSDB stores single values, so aggregated metrics are split into a
+/// series for each statistic, like `latency.count`, or `latency.bucket`
+/// tagged with the bucket bound.
+fn datapoints(metric: Metric, config: &OpenTSDBConfig) -> Vec<serde_json::Value> {
+    let name = match &config.namespace {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, metric.name),
+        _ => metric.name,
+    };
+    let name = sanitize(&name);
+    let timestamp = metric.timestamp.unwrap_or_else(Utc::now).timestamp_millis();
+
+    let mut tags = BTreeMap::new();
+    for (key, value) in metric.tags.unwrap_or_default() {
+        let key = config.tag_map.get(&key).cloned().unwrap_or(key);
+        if !value.is_empty() {
+            tags.insert(sanitize(&key), sanitize(&value));
+        }
+    }
+    for (key, value) in &config.tags {
+        tags.entry(sanitize(key)).or_insert_with(|| sanitize(value));
+    }
+
+    let count_and_sum = |count: f64, sum: f64| -> Vec<Statistic> {
+        vec![(".count", None, count), (".sum", None, sum)]
+    };
+    let values: Vec<Statistic> = match metric.value {
+        MetricValue::Counter { value } | MetricValue::Gauge { value } => vec![("", None, value)],
+        MetricValue::Set { values } => vec![("", None, values.len() as f64)],
+        MetricValue::Distribution {
+            values,
+            sample_rates,
+        } => count_and_sum(
+            sample_rates.iter().map(|rate| *rate as f64).sum(),
+            values
+                .iter()
+                .zip(&sample_rates)
+                .map(|(value, rate)| value * *rate as f64)
+                .sum(),
+        ),
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => buckets
+            .iter()
+            .zip(counts)
+            .map(|(bucket, count)| (".bucket", Some(("le", *bucket)), count as f64))
+            .chain(count_and_sum(count as f64, sum))
+            .collect(),
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => quantiles
+            .iter()
+            .zip(values)
+            .map(|(quantile, value)| ("", Some(("quantile", *quantile)), value))
+            .chain(count_and_sum(count as f64, sum))
+            .collect(),
+    };
+
+    values
+        .into_iter()
+        // JSON has no representation for these.
+        .filter(|(_, _, value)| value.is_finite())
+        .map(|(suffix, extra, value)| {
+            let mut tags = tags.clone();
+            if let Some((tag, bound)) = extra {
+                tags.insert(tag.to_owned(), bound.to_string());
+            }
+            json!({
+                "metric": format!("{}{}", name, suffix),
+                "timestamp": timestamp,
+                "value": value,
+                "tags": tags,
+            })
+        })
+        .collect()
+}
+
+/// Replaces the characters OpenTSDB doesn't accept in metric names and
+/// tags.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '-' | '_' | '.' | '/' => c,
+            c if c.is_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// The summary OpenTSDB responds with when asked for `summary` and
+/// `details`.
+#[derive(Deserialize, Debug)]
+struct PutSummary {
+    failed: u64,
+    success: u64,
+    #[serde(default)]
+    errors: Vec<PutError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PutError {
+    error: String,
+}
+
+#[derive(Debug, Clone)]
+struct OpenTSDBRetryLogic;
+
+impl RetryLogic for OpenTSDBRetryLogic {
+    type Error = hyper13::Error;
+    type Response = hyper13::Response<Bytes>;
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        error.is_connect() || error.is_closed()
+    }
+
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        let status = response.status();
+
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => RetryAction::Retry("Too many requests".into()),
+            _ if status.is_server_error() => RetryAction::Retry(
+                format!("{}: {}", status, String::from_utf8_lossy(response.body())).into(),
+            ),
+            _ if status.is_success() => RetryAction::Successful,
+            // Datapoints OpenTSDB doesn't accept fail the request, but the
+            // others are still stored, retrying would store them twice.
+            StatusCode::BAD_REQUEST => {
+                match serde_json::from_slice::<PutSummary>(response.body()) {
+                    Ok(summary) => RetryAction::DontRetry(format!(
+                        "{} of {} datapoints failed, first error: {}",
+                        summary.failed,
+                        summary.failed + summary.success,
+                        summary
+                            .errors
+                            .first()
+                            .map_or("unknown", |error| error.error.as_str())
+                    )),
+                    Err(_) => RetryAction::DontRetry(format!(
+                        "response status: {}: {}",
+                        status,
+                        String::from_utf8_lossy(response.body())
+                    )),
+                }
+            }
+            _ => RetryAction::DontRetry(format!("response status: {}", status)),
+        }
+    }
+}
+
+async fn healthcheck(
+    config: OpenTSDBConfig,
+    resolver: Resolver,
+    tls: TlsSettings,
+) -> crate::Result<()> {
+    let mut request = Request::get(config.uri("/api/version")?)
+        .body(hyper13::Body::empty())
+        .unwrap();
+    if let Some(auth) = &config.auth {
+        auth.apply(&mut request);
+    }
+
+    let mut client = HttpClient::new(resolver, tls)?;
+    let response = client.send(request).await?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        status => Err(super::HealthcheckError::UnexpectedStatus2 { status }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::MetricKind;
+    use chrono::TimeZone;
+
+    fn config() -> OpenTSDBConfig {
+        toml::from_str(
+            r#"
+            endpoint = "http://opentsdb:4242"
+            namespace = "vector"
+            tag_map.hostname = "host"
+            tags.env = "production"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn metric(value: MetricValue) -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("hostname".to_owned(), "web 01".to_owned());
+        Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.timestamp(1591005600, 0)),
+            tags: Some(tags),
+            kind: MetricKind::Absolute,
+            value,
+        }
+    }
+
+    #[test]
+    fn opentsdb_maps_tags() {
+        let datapoints = datapoints(metric(MetricValue::Gauge { value: 1.5 }), &config());
+        assert_eq!(
+            datapoints,
+            vec![json!({
+                "metric": "vector.requests",
+                "timestamp": 1591005600000i64,
+                "value": 1.5,
+                "tags": {"env": "production", "host": "web_01"},
+            })]
+        );
+    }
+
+    #[test]
+    fn opentsdb_splits_histograms() {
+        let datapoints = datapoints(
+            metric(MetricValue::AggregatedHistogram {
+                buckets: vec![0.5, std::f64::INFINITY],
+                counts: vec![1, 2],
+                count: 3,
+                sum: 2.5,
+            }),
+            &config(),
+        );
+        let series = datapoints
+            .iter()
+            .map(|datapoint| {
+                (
+                    datapoint["metric"].as_str().unwrap(),
+                    datapoint["tags"]["le"].as_str(),
+                    datapoint["value"].as_f64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![
+                ("vector.requests.bucket", Some("0.5"), 1.0),
+                ("vector.requests.bucket", Some("inf"), 2.0),
+                ("vector.requests.count", None, 3.0),
+                ("vector.requests.sum", None, 2.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn opentsdb_does_not_retry_failed_datapoints() {
+        let response = http02::Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Bytes::from(
+                r#"{"errors":[{"datapoint":{},"error":"Unknown metric"}],"failed":1,"success":49}"#,
+            ))
+            .unwrap();
+        match OpenTSDBRetryLogic.should_retry_response(&response) {
+            RetryAction::DontRetry(reason) => assert_eq!(
+                reason,
+                "1 of 50 datapoints failed, first error: Unknown metric"
+            ),
+            _ => panic!("should not retry"),
+        }
+    }
+}
@@ -0,0 +1,544 @@
+use crate::{
+    event::{Event, Value},
+    internal_events::{PostgresEventSent, PostgresFieldInvalid},
+    sinks::{
+        util::{
+            columns::{to_bool, to_f64, to_i64, to_timestamp_micros},
+            postgres::{encode_float, encode_text, is_transient, quote_identifier, quote_table},
+            retries2::RetryLogic,
+            service2::TowerRequestConfig,
+            BatchEventsConfig,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{tls_connector_builder, MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes05::{BufMut, Bytes, BytesMut};
+use futures::{
+    future::{try_join_all, BoxFuture},
+    pin_mut, FutureExt, SinkExt as _, TryFutureExt,
+};
+use futures01::{stream::iter_ok, Sink};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use postgres_openssl::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    convert::TryFrom,
+    error::Error,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use string_cache::DefaultAtom as Atom;
+use tokio::sync::Mutex;
+use tokio_postgres::{
+    types::{to_sql_checked, IsNull, ToSql, Type},
+    Client, Statement,
+};
+use tower03::Service;
+
+/// Microseconds from the Unix epoch to the PostgreSQL one, 2000-01-01.
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PostgresConfig {
+    /// A connection string, either `key=value` pairs or a
+    /// `postgresql://` URL.
+    pub endpoint: String,
+    pub table: String,
+    /// The columns written, each to the field filling it.
+    pub columns: IndexMap<String, String>,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Insert,
+    Copy,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Insert
+    }
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        ..Default::default()
+    };
+}
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("At least one column must be configured"))]
+    NoColumns,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<PostgresConfig>("postgres")
+}
+
+#[typetag::serde(name = "postgres")]
+impl SinkConfig for PostgresConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        if self.columns.is_empty() {
+            return Err(BuildError::NoColumns.into());
+        }
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+        let connector = MakeTlsConnector::new(tls_connector_builder(&tls)?.build());
+
+        let table = quote_table(&self.table);
+        let columns = self
+            .columns
+            .keys()
+            .map(|column| quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let service = PostgresService {
+            endpoint: self.endpoint.clone(),
+            connector,
+            table,
+            columns,
+            names: self.columns.keys().cloned().collect(),
+            mode: self.mode,
+            connection: Arc::new(Mutex::new(None)),
+        };
+
+        let healthcheck = healthcheck(service.clone()).boxed().compat();
+
+        let batch = self.batch.unwrap_or(1000, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+        let fields = self
+            .columns
+            .values()
+            .map(|field| Atom::from(field.as_str()))
+            .collect::<Vec<_>>();
+
+        let sink = request
+            .batch_sink(PostgresRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|e| error!("Fatal postgres sink error: {}", e))
+            .with_flat_map(move |event| iter_ok(Some(encode_event(event, &fields))));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "postgres"
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum PostgresError {
+    #[snafu(display("Failed to connect: {}", source))]
+    Connect { source: tokio_postgres::Error },
+    #[snafu(display("Failed to prepare the insert statement: {}", source))]
+    Prepare { source: tokio_postgres::Error },
+    #[snafu(display("Column {:?} has the unsupported type {}", column, column_type))]
+    UnsupportedType { column: String, column_type: Type },
+    #[snafu(display("Failed to insert rows: {}", source))]
+    Insert { source: tokio_postgres::Error },
+    #[snafu(display("Failed to copy rows: {}", source))]
+    Copy { source: tokio_postgres::Error },
+}
+
+/// The values of the fields mapped to columns, in column order.
+type Row = Vec<Option<Value>>;
+
+struct Connection {
+    client: Client,
+    /// Prepared on connect in the `insert` mode, with the types of its
+    /// columns.
+    insert: Option<(Statement, Vec<ColumnType>)>,
+}
+
+#[derive(Clone)]
+struct PostgresService {
+    endpoint: String,
+    connector: MakeTlsConnector,
+    table: String,
+    columns: String,
+    names: Vec<String>,
+    mode: Mode,
+    connection: Arc<Mutex<Option<Connection>>>,
+}
+
+impl Service<Vec<Row>> for PostgresService {
+    type Response = ();
+    type Error = PostgresError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, rows: Vec<Row>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            match service.mode {
+                Mode::Insert => service.insert(rows).await,
+                Mode::Copy => service.copy(rows).await,
+            }
+        })
+    }
+}
+
+impl PostgresService {
+    async fn connect(&self) -> Result<Connection, PostgresError> {
+        let (client, connection) = tokio_postgres::connect(&self.endpoint, self.connector.clone())
+            .await
+            .context(Connect)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                error!(message = "connection failed.", %error);
+            }
+        });
+
+        let insert = match self.mode {
+            Mode::Insert => Some(self.prepare(&client).await?),
+            Mode::Copy => None,
+        };
+        Ok(Connection { client, insert })
+    }
+
+    async fn prepare(
+        &self,
+        client: &Client,
+    ) -> Result<(Statement, Vec<ColumnType>), PostgresError> {
+        let placeholders = (1..=self.names.len())
+            .map(|index| format!("${}", index))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.table, self.columns, placeholders
+        );
+        let statement = client.prepare(&statement).await.context(Prepare)?;
+
+        let mut types = Vec::with_capacity(self.names.len());
+        for (column, column_type) in self.names.iter().zip(statement.params()) {
+            match ColumnType::of(column_type) {
+                Some(column_type) => types.push(column_type),
+                None => {
+                    return Err(PostgresError::UnsupportedType {
+                        column: column.clone(),
+                        column_type: column_type.clone(),
+                    })
+                }
+            }
+        }
+        Ok((statement, types))
+    }
+
+    /// Inserts the rows in a single transaction, with the statement
+    /// prepared on connect.
+    async fn insert(self, rows: Vec<Row>) -> Result<(), PostgresError> {
+        let mut connection = self.connection.lock().await;
+        if connection
+            .as_ref()
+            .map_or(true, |connection| connection.client.is_closed())
+        {
+            *connection = Some(self.connect().await?);
+        }
+        let Connection { client, insert } = connection.as_mut().expect("connected above");
+        let (statement, types) = insert.as_ref().expect("prepared on connect");
+
+        let rows = rows
+            .into_iter()
+            .filter_map(|row| self.params(row, types))
+            .collect::<Vec<_>>();
+        let params = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|param| param as &(dyn ToSql + Sync))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let transaction = client.transaction().await.context(Insert)?;
+        // The statements are pipelined on the connection.
+        try_join_all(
+            params
+                .iter()
+                .map(|params| transaction.execute(statement, params)),
+        )
+        .await
+        .context(Insert)?;
+        transaction.commit().await.context(Insert)?;
+        Ok(())
+    }
+
+    /// Converts a row to the types of its columns, or drops it if one of
+    /// its values can't be.
+    fn params(&self, row: Row, types: &[ColumnType]) -> Option<Vec<Param>> {
+        let mut params = Vec::with_capacity(row.len());
+        for ((value, column_type), column) in row.iter().zip(types).zip(&self.names) {
+            match column_type.param(value.as_ref()) {
+                Some(param) => params.push(param),
+                None => {
+                    emit!(PostgresFieldInvalid {
+                        column,
+                        column_type: *column_type,
+                    });
+                    return None;
+                }
+            }
+        }
+        Some(params)
+    }
+
+    async fn copy(self, rows: Vec<Row>) -> Result<(), PostgresError> {
+        let mut output = String::new();
+        for row in rows {
+            encode_row(&row, &mut output);
+        }
+
+        let mut connection = self.connection.lock().await;
+        if connection
+            .as_ref()
+            .map_or(true, |connection| connection.client.is_closed())
+        {
+            *connection = Some(self.connect().await?);
+        }
+        let client = &connection.as_ref().expect("connected above").client;
+
+        let statement = format!("COPY {} ({}) FROM STDIN", self.table, self.columns);
+        let sink = client.copy_in(statement.as_str()).await.context(Copy)?;
+        pin_mut!(sink);
+        sink.send(Bytes::from(output)).await.context(Copy)?;
+        sink.finish().await.context(Copy)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PostgresRetryLogic;
+
+impl RetryLogic for PostgresRetryLogic {
+    type Error = PostgresError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        match error {
+            PostgresError::Connect { source }
+            | PostgresError::Prepare { source }
+            | PostgresError::Insert { source }
+            | PostgresError::Copy { source } => is_transient(source),
+            PostgresError::UnsupportedType { .. } => false,
+        }
+    }
+}
+
+async fn healthcheck(service: PostgresService) -> crate::Result<()> {
+    let connection = service.connect().await?;
+    connection
+        .client
+        .simple_query(&format!(
+            "SELECT {} FROM {} LIMIT 0",
+            service.columns, service.table
+        ))
+        .await?;
+    Ok(())
+}
+
+fn encode_event(event: Event, fields: &[Atom]) -> Row {
+    let log = event.into_log();
+    let row = fields
+        .iter()
+        .map(|field| log.get(field).cloned())
+        .collect::<Vec<_>>();
+    emit!(PostgresEventSent {
+        byte_size: row
+            .iter()
+            .flatten()
+            .map(|value| value.as_bytes().len())
+            .sum(),
+    });
+    row
+}
+
+/// Appends a row in the text format of `COPY`, which PostgreSQL parses into
+/// the types of the columns itself.
+fn encode_row(row: &[Option<Value>], output: &mut String) {
+    for (index, value) in row.iter().enumerate() {
+        if index > 0 {
+            output.push('\t');
+        }
+        match value {
+            None | Some(Value::Null) => output.push_str("\\N"),
+            Some(Value::Float(value)) => output.push_str(&encode_float(*value)),
+            Some(value) => encode_text(&value.to_string_lossy(), output),
+        }
+    }
+    output.push('\n');
+}
+
+/// The column types values are converted to for inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Int2,
+    Int4,
+    Int8,
+    Float4,
+    Float8,
+    Text,
+    Json,
+    Jsonb,
+    Timestamp,
+}
+
+impl ColumnType {
+    fn of(column_type: &Type) -> Option<Self> {
+        let types = [
+            (Type::BOOL, ColumnType::Boolean),
+            (Type::INT2, ColumnType::Int2),
+            (Type::INT4, ColumnType::Int4),
+            (Type::INT8, ColumnType::Int8),
+            (Type::FLOAT4, ColumnType::Float4),
+            (Type::FLOAT8, ColumnType::Float8),
+            (Type::TEXT, ColumnType::Text),
+            (Type::VARCHAR, ColumnType::Text),
+            (Type::BPCHAR, ColumnType::Text),
+            (Type::NAME, ColumnType::Text),
+            (Type::JSON, ColumnType::Json),
+            (Type::JSONB, ColumnType::Jsonb),
+            (Type::TIMESTAMP, ColumnType::Timestamp),
+            (Type::TIMESTAMPTZ, ColumnType::Timestamp),
+        ];
+        types
+            .iter()
+            .find(|(candidate, _)| candidate == column_type)
+            .map(|(_, column_type)| *column_type)
+    }
+
+    /// Missing fields are inserted as `NULL`.
+    fn param(self, value: Option<&Value>) -> Option<Param> {
+        let value = match value {
+            None | Some(Value::Null) => return Some(Param::Null),
+            Some(value) => value,
+        };
+        match self {
+            ColumnType::Boolean => to_bool(value).map(Param::Boolean),
+            ColumnType::Int2 => to_i64(value)
+                .and_then(|value| i16::try_from(value).ok())
+                .map(Param::Int2),
+            ColumnType::Int4 => to_i64(value)
+                .and_then(|value| i32::try_from(value).ok())
+                .map(Param::Int4),
+            ColumnType::Int8 => to_i64(value).map(Param::Int8),
+            ColumnType::Float4 => to_f64(value).map(|value| Param::Float4(value as f32)),
+            ColumnType::Float8 => to_f64(value).map(Param::Float8),
+            ColumnType::Text => Some(Param::Text(value.to_string_lossy())),
+            ColumnType::Json => serde_json::to_string(value).ok().map(Param::Text),
+            ColumnType::Jsonb => serde_json::to_string(value).ok().map(Param::Jsonb),
+            ColumnType::Timestamp => to_timestamp_micros(value)
+                .map(|micros| Param::Timestamp(micros - POSTGRES_EPOCH_MICROS)),
+        }
+    }
+}
+
+/// A value in the binary format of its column.
+#[derive(Debug, Clone, PartialEq)]
+enum Param {
+    Null,
+    Boolean(bool),
+    Int2(i16),
+    Int4(i32),
+    Int8(i64),
+    Float4(f32),
+    Float8(f64),
+    Text(String),
+    Jsonb(String),
+    /// Microseconds since the PostgreSQL epoch.
+    Timestamp(i64),
+}
+
+impl ToSql for Param {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match self {
+            Param::Null => Ok(IsNull::Yes),
+            Param::Boolean(value) => value.to_sql(ty, out),
+            Param::Int2(value) => value.to_sql(ty, out),
+            Param::Int4(value) => value.to_sql(ty, out),
+            Param::Int8(value) | Param::Timestamp(value) => value.to_sql(ty, out),
+            Param::Float4(value) => value.to_sql(ty, out),
+            Param::Float8(value) => value.to_sql(ty, out),
+            Param::Text(value) => value.to_sql(ty, out),
+            Param::Jsonb(value) => {
+                // The version of the format, followed by the JSON text.
+                out.put_u8(1);
+                out.put_slice(value.as_bytes());
+                Ok(IsNull::No)
+            }
+        }
+    }
+
+    // The values are converted for the types of their columns beforehand.
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn postgres_encode_copy_row() {
+        let mut output = String::new();
+        let row = vec![
+            Some(Value::from(Utc.ymd(2020, 6, 1).and_hms(10, 0, 0))),
+            Some(Value::from("a\tb")),
+            None,
+            Some(Value::Float(std::f64::INFINITY)),
+            Some(Value::from(true)),
+        ];
+        encode_row(&row, &mut output);
+        assert_eq!(output, "2020-06-01T10:00:00Z\ta\\tb\t\\N\tInfinity\ttrue\n");
+    }
+
+    #[test]
+    fn postgres_convert_params() {
+        assert_eq!(ColumnType::of(&Type::VARCHAR), Some(ColumnType::Text));
+        assert_eq!(ColumnType::of(&Type::UUID), None);
+
+        assert_eq!(ColumnType::Int4.param(None), Some(Param::Null));
+        assert_eq!(
+            ColumnType::Int4.param(Some(&Value::from("42"))),
+            Some(Param::Int4(42))
+        );
+        assert_eq!(ColumnType::Int2.param(Some(&Value::Integer(100_000))), None);
+        assert_eq!(
+            ColumnType::Timestamp.param(Some(&Value::from("2000-01-01T00:00:01Z"))),
+            Some(Param::Timestamp(1_000_000))
+        );
+        assert_eq!(
+            ColumnType::Jsonb.param(Some(&Value::from("text"))),
+            Some(Param::Jsonb("\"text\"".into()))
+        );
+    }
+}
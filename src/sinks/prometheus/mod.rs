@@ -21,6 +21,10 @@ use std::{
 use stream_cancel::{Trigger, Tripwire};
 use tracing::field;
 
+pub mod pushgateway;
+pub mod remote_write;
+mod snappy;
+
 const MIN_FLUSH_PERIOD_SECS: u64 = 1;
 
 #[derive(Debug, Snafu)]
@@ -93,7 +97,7 @@ struct PrometheusSink {
     acker: Acker,
 }
 
-fn encode_namespace(namespace: &str, name: &str) -> String {
+pub(in crate::sinks) fn encode_namespace(namespace: &str, name: &str) -> String {
     if !namespace.is_empty() {
         format!("{}_{}", namespace, name)
     } else {
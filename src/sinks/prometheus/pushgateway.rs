@@ -0,0 +1,423 @@
+//! Pushes metrics to a Prometheus Pushgateway, for where remote write isn't
+//! available but a Pushgateway already is.
+
+use super::{
+    default_histogram_buckets, encode_metric_datum, encode_metric_header, remote_write::histogram,
+};
+use crate::{
+    dns::Resolver,
+    event::metric::{Metric, MetricValue},
+    internal_events::{PrometheusPushFailed, PrometheusPushed},
+    sinks::{
+        streaming_sink::{self, StreamingSink},
+        util::{
+            http2::{Auth, HttpClient},
+            StreamSink, UriSerde,
+        },
+        Healthcheck, HealthcheckError, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+    Event,
+};
+use async_trait::async_trait;
+use futures::{pin_mut, stream::Stream, FutureExt, StreamExt, TryFutureExt};
+use http02::{header::CONTENT_TYPE, Method, Request, StatusCode};
+use hyper13::Body;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, mem, time::Duration};
+use tokio::time::{interval, timeout};
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PushgatewayConfig {
+    pub endpoint: UriSerde,
+    pub job: String,
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default = "default_histogram_buckets")]
+    pub buckets: Vec<f64>,
+    /// Labels of the group every metric is pushed to, besides the job.
+    #[serde(default)]
+    pub grouping_labels: BTreeMap<String, String>,
+    /// Tags taken out of the metrics to group them by, such as `instance`.
+    #[serde(default)]
+    pub grouping_tags: Vec<String>,
+    #[serde(default)]
+    pub method: PushMethod,
+    #[serde(default = "default_flush_period_secs")]
+    pub flush_period_secs: u64,
+    /// Deletes the groups pushed to once the sink stops, so that the
+    /// Pushgateway doesn't keep serving their last values.
+    #[serde(default)]
+    pub delete_on_shutdown: bool,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    pub auth: Option<Auth>,
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PushMethod {
+    /// Replaces the metrics of the same names in the group.
+    Post,
+    /// Replaces all the metrics of the group.
+    Put,
+}
+
+impl Default for PushMethod {
+    fn default() -> Self {
+        PushMethod::Post
+    }
+}
+
+fn default_flush_period_secs() -> u64 {
+    15
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<PushgatewayConfig>("prometheus_pushgateway")
+}
+
+#[typetag::serde(name = "prometheus_pushgateway")]
+impl SinkConfig for PushgatewayConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        if self.flush_period_secs == 0 {
+            return Err("`flush_period_secs` must be at least 1".into());
+        }
+
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let healthcheck = healthcheck(self.clone(), cx.resolver(), tls.clone())
+            .boxed()
+            .compat();
+
+        let sink = PushgatewaySink {
+            config: self.clone(),
+            client: HttpClient::new(cx.resolver(), tls)?,
+            groups: Groups::default(),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "prometheus_pushgateway"
+    }
+}
+
+impl PushgatewayConfig {
+    fn url(&self, path: &str) -> String {
+        let endpoint = self.endpoint.to_string();
+        format!("{}{}", endpoint.trim_end_matches('/'), path)
+    }
+}
+
+/// The labels of a group besides the job, in the order they are pushed in.
+type GroupKey = Vec<(String, String)>;
+
+type SeriesKey = (String, BTreeMap<String, String>);
+
+struct PushgatewaySink {
+    config: PushgatewayConfig,
+    client: HttpClient,
+    groups: Groups,
+}
+
+/// The current value of every series by group, as the Pushgateway serves
+/// absolute values: incremental metrics are added up, distributions are
+/// turned into histograms.
+#[derive(Default)]
+struct Groups {
+    series: BTreeMap<GroupKey, BTreeMap<SeriesKey, Metric>>,
+}
+
+impl Groups {
+    fn update(&mut self, config: &PushgatewayConfig, mut metric: Metric) {
+        if let MetricValue::Distribution {
+            values,
+            sample_rates,
+        } = &metric.value
+        {
+            metric.value = histogram(values, sample_rates, &config.buckets);
+        }
+
+        let group = group_of(config, &mut metric);
+        let key = (metric.name.clone(), metric.tags.clone().unwrap_or_default());
+        let series = self.series.entry(group).or_default();
+        match series.get_mut(&key) {
+            Some(current)
+                if metric.kind.is_incremental()
+                    && mem::discriminant(&current.value) == mem::discriminant(&metric.value) =>
+            {
+                current.add(&metric);
+            }
+            _ => {
+                series.insert(key, metric.into_absolute());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl StreamingSink for PushgatewaySink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        let mut flush = interval(Duration::from_secs(self.config.flush_period_secs));
+        loop {
+            tokio::select! {
+                event = input.next() => match event {
+                    Some(event) => self.groups.update(&self.config, event.into_metric()),
+                    None => break,
+                },
+                _ = flush.tick() => self.flush().await,
+            }
+        }
+
+        self.flush().await;
+        if self.config.delete_on_shutdown {
+            let groups = mem::replace(&mut self.groups.series, BTreeMap::new());
+            for group in groups.keys() {
+                let path = group_path(&self.config.job, group);
+                if let Err(reason) = self.send(Method::DELETE, &path, Vec::new()).await {
+                    emit!(PrometheusPushFailed {
+                        group: &path,
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PushgatewaySink {
+    /// Pushes every group. A push that fails isn't retried, the next flush
+    /// pushes the latest values anyway.
+    async fn flush(&mut self) {
+        let mut pushes = Vec::new();
+        for (group, series) in &mut self.groups.series {
+            let body = encode_group(&self.config.namespace, &self.config.buckets, series);
+            pushes.push((group_path(&self.config.job, group), series.len(), body));
+
+            // Sets count the distinct values seen within a flush period.
+            for metric in series.values_mut() {
+                if let MetricValue::Set { values } = &mut metric.value {
+                    values.clear();
+                }
+            }
+        }
+
+        let method = match self.config.method {
+            PushMethod::Post => Method::POST,
+            PushMethod::Put => Method::PUT,
+        };
+        for (path, series, body) in pushes {
+            let byte_size = body.len();
+            match self.send(method.clone(), &path, body.into_bytes()).await {
+                Ok(()) => emit!(PrometheusPushed {
+                    group: &path,
+                    series,
+                    byte_size,
+                }),
+                Err(reason) => emit!(PrometheusPushFailed {
+                    group: &path,
+                    reason,
+                }),
+            }
+        }
+    }
+
+    async fn send(&mut self, method: Method, path: &str, body: Vec<u8>) -> Result<(), String> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(self.config.url(path))
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(body))
+            .map_err(|error| error.to_string())?;
+        if let Some(auth) = &self.config.auth {
+            auth.apply(&mut request);
+        }
+
+        let request_timeout = Duration::from_secs(self.config.timeout_secs);
+        match timeout(request_timeout, self.client.send(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => Ok(()),
+            Ok(Ok(response)) => Err(format!("unexpected status: {}", response.status())),
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err("request timed out".into()),
+        }
+    }
+}
+
+/// The group of a metric, its grouping tags taken out of its labels: the
+/// Pushgateway doesn't accept metrics labeled like their group.
+fn group_of(config: &PushgatewayConfig, metric: &mut Metric) -> GroupKey {
+    let mut group = config
+        .grouping_labels
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<Vec<_>>();
+    for tag in &config.grouping_tags {
+        let value = metric.tags.as_mut().and_then(|tags| tags.remove(tag));
+        group.push((tag.clone(), value.unwrap_or_default()));
+    }
+    if metric.tags.as_ref().map_or(false, |tags| tags.is_empty()) {
+        metric.tags = None;
+    }
+    group
+}
+
+fn group_path(job: &str, group: &[(String, String)]) -> String {
+    let mut path = format!("/metrics/{}", path_segments("job", job));
+    for (name, value) in group {
+        path.push('/');
+        path.push_str(&path_segments(name, value));
+    }
+    path
+}
+
+/// Label values that a path segment can't hold are base64 encoded.
+fn path_segments(name: &str, value: &str) -> String {
+    if value.is_empty() {
+        format!("{}@base64/=", name)
+    } else if value.contains('/') {
+        let value = base64::encode_config(value, base64::URL_SAFE);
+        format!("{}@base64/{}", name, value)
+    } else {
+        let value = utf8_percent_encode(value, PATH_SEGMENT_ENCODE_SET);
+        format!("{}/{}", name, value)
+    }
+}
+
+/// Encodes the series of a group in the text exposition format, the series
+/// of a metric family following its header.
+fn encode_group(namespace: &str, buckets: &[f64], series: &BTreeMap<SeriesKey, Metric>) -> String {
+    let mut body = String::new();
+    let mut family = None;
+    for metric in series.values() {
+        if family != Some(&metric.name) {
+            body.push_str(&encode_metric_header(namespace, metric));
+            family = Some(&metric.name);
+        }
+        body.push_str(&encode_metric_datum(namespace, buckets, false, metric));
+    }
+    body
+}
+
+async fn healthcheck(
+    config: PushgatewayConfig,
+    resolver: Resolver,
+    tls: TlsSettings,
+) -> crate::Result<()> {
+    let mut request = Request::get(config.url("/-/healthy"))
+        .body(Body::empty())
+        .unwrap();
+    if let Some(auth) = &config.auth {
+        auth.apply(&mut request);
+    }
+
+    let mut client = HttpClient::new(resolver, tls)?;
+    let response = client.send(request).await?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        status => Err(HealthcheckError::UnexpectedStatus2 { status }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::MetricKind;
+
+    fn config() -> PushgatewayConfig {
+        toml::from_str(
+            r#"
+            endpoint = "http://localhost:9091"
+            job = "batch"
+            grouping_labels = { env = "prod" }
+            grouping_tags = ["instance"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn counter(instance: &str, value: f64) -> Metric {
+        Metric {
+            name: "jobs".into(),
+            timestamp: None,
+            tags: Some(
+                vec![
+                    ("instance".to_owned(), instance.to_owned()),
+                    ("queue".to_owned(), "high".to_owned()),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value },
+        }
+    }
+
+    #[test]
+    fn pushgateway_group_paths() {
+        let group = vec![
+            ("instance".to_owned(), "host:9100".to_owned()),
+            ("path".to_owned(), "/var/tmp".to_owned()),
+            ("empty".to_owned(), "".to_owned()),
+        ];
+        assert_eq!(
+            group_path("batch job", &group),
+            "/metrics/job/batch%20job/instance/host:9100/path@base64/L3Zhci90bXA=/empty@base64/="
+        );
+    }
+
+    #[test]
+    fn pushgateway_groups_and_adds_up_series() {
+        let config = config();
+        let mut groups = Groups::default();
+        groups.update(&config, counter("a", 1.0));
+        groups.update(&config, counter("a", 2.0));
+        groups.update(&config, counter("b", 5.0));
+
+        let bodies = groups
+            .series
+            .iter()
+            .map(|(group, series)| {
+                (
+                    group_path("batch", group),
+                    encode_group("vector", &[], series),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            vec![
+                (
+                    "/metrics/job/batch/env/prod/instance/a".to_owned(),
+                    "# HELP vector_jobs jobs\n# TYPE vector_jobs counter\nvector_jobs{queue=\"high\"} 3\n"
+                        .to_owned()
+                ),
+                (
+                    "/metrics/job/batch/env/prod/instance/b".to_owned(),
+                    "# HELP vector_jobs jobs\n# TYPE vector_jobs counter\nvector_jobs{queue=\"high\"} 5\n"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+}
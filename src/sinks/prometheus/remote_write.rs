@@ -0,0 +1,699 @@
+use super::{default_histogram_buckets, encode_namespace, snappy};
+use crate::{
+    event::metric::{Metric, MetricValue},
+    internal_events::{
+        PrometheusRemoteWriteFailed, PrometheusRemoteWriteSent, PrometheusStaleSeries,
+    },
+    sinks::{
+        streaming_sink::{self, StreamingSink},
+        util::{
+            http2::{Auth, HttpClient, HttpRetryLogic},
+            retries2::{RetryAction, RetryLogic},
+            StreamSink, UriSerde,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+    Event,
+};
+use async_trait::async_trait;
+use bytes05::Bytes;
+use chrono::Utc;
+use futures::{pin_mut, stream::Stream, StreamExt};
+use futures01::future;
+use http02::{header::RETRY_AFTER, Request, Response, Uri};
+use hyper13::Body;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, interval, timeout};
+use tower03::Service;
+
+#[allow(clippy::all)]
+mod proto {
+    include!(concat!(env!("OUT_DIR"), "/prometheus.rs"));
+}
+
+use proto::{metric_metadata::MetricType, Label, MetricMetadata, Sample, TimeSeries, WriteRequest};
+
+/// The NaN Prometheus uses to mark a series as stale.
+/// See https://github.com/prometheus/prometheus/blob/master/pkg/value/value.go
+const STALE_NAN: u64 = 0x7ff0_0000_0000_0002;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteWriteConfig {
+    pub endpoint: UriSerde,
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default = "default_histogram_buckets")]
+    pub buckets: Vec<f64>,
+    #[serde(default = "default_flush_period_secs")]
+    pub flush_period_secs: u64,
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
+    #[serde(default = "default_send_metadata")]
+    pub send_metadata: bool,
+    #[serde(default = "default_metadata_interval_secs")]
+    pub metadata_interval_secs: u64,
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: usize,
+    #[serde(default = "default_retry_max_backoff_secs")]
+    pub retry_max_backoff_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    pub auth: Option<Auth>,
+    pub tls: Option<TlsOptions>,
+}
+
+fn default_flush_period_secs() -> u64 {
+    15
+}
+
+fn default_stale_after_secs() -> u64 {
+    300
+}
+
+fn default_send_metadata() -> bool {
+    true
+}
+
+fn default_metadata_interval_secs() -> u64 {
+    60
+}
+
+fn default_retry_attempts() -> usize {
+    10
+}
+
+fn default_retry_max_backoff_secs() -> u64 {
+    30
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<RemoteWriteConfig>("prometheus_remote_write")
+}
+
+#[typetag::serde(name = "prometheus_remote_write")]
+impl SinkConfig for RemoteWriteConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        if self.flush_period_secs == 0 {
+            return Err("`flush_period_secs` must be at least 1".into());
+        }
+
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let client = HttpClient::new(cx.resolver(), tls)?;
+
+        let sink = RemoteWriteSink {
+            config: self.clone(),
+            endpoint: Uri::from(self.endpoint.clone()),
+            client,
+            series: Series::new(&self.namespace, &self.buckets),
+            metadata_sent: None,
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        // Remote write endpoints only accept writes, and an empty write is
+        // not accepted by all of them.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "prometheus_remote_write"
+    }
+}
+
+struct RemoteWriteSink {
+    config: RemoteWriteConfig,
+    endpoint: Uri,
+    client: HttpClient,
+    series: Series,
+    metadata_sent: Option<Instant>,
+}
+
+#[async_trait]
+impl StreamingSink for RemoteWriteSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        let mut flush = interval(Duration::from_secs(self.config.flush_period_secs));
+        loop {
+            tokio::select! {
+                event = input.next() => match event {
+                    Some(event) => self.series.update(event.into_metric(), Instant::now()),
+                    None => break,
+                },
+                _ = flush.tick() => self.flush(false).await,
+            }
+        }
+
+        // The series end with the sink, let Prometheus know right away
+        // instead of after its lookback period.
+        self.flush(true).await;
+        Ok(())
+    }
+}
+
+impl RemoteWriteSink {
+    async fn flush(&mut self, shutdown: bool) {
+        let now = Instant::now();
+        let stale_after = Duration::from_secs(self.config.stale_after_secs);
+        let (timeseries, stale) =
+            self.series
+                .collect(now, Utc::now().timestamp_millis(), stale_after, shutdown);
+        if stale > 0 {
+            emit!(PrometheusStaleSeries { count: stale });
+        }
+
+        let metadata_due = self.series.new_families
+            || self.metadata_sent.map_or(true, |sent| {
+                now.duration_since(sent) >= Duration::from_secs(self.config.metadata_interval_secs)
+            });
+        let metadata = if self.config.send_metadata && metadata_due && !shutdown {
+            self.metadata_sent = Some(now);
+            self.series.new_families = false;
+            self.series.metadata()
+        } else {
+            Vec::new()
+        };
+
+        if timeseries.is_empty() && metadata.is_empty() {
+            return;
+        }
+
+        let request = WriteRequest {
+            timeseries,
+            metadata,
+        };
+        let series = request.timeseries.len();
+        let mut body = Vec::with_capacity(request.encoded_len());
+        request
+            .encode(&mut body)
+            .expect("Encoding into a Vec can't fail");
+
+        self.send(Bytes::from(snappy::compress(&body)), series)
+            .await;
+    }
+
+    /// Sends the request, retrying in memory with exponential backoff. The
+    /// series keep their latest values meanwhile, so a request that is
+    /// given up on only loses the samples of one flush.
+    async fn send(&mut self, body: Bytes, series: usize) {
+        let request_timeout = Duration::from_secs(self.config.timeout_secs);
+        let max_backoff = Duration::from_secs(self.config.retry_max_backoff_secs);
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let mut request = Request::post(self.endpoint.clone())
+                .header("Content-Encoding", "snappy")
+                .header("Content-Type", "application/x-protobuf")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                .body(Body::from(body.clone()))
+                .expect("Building a request from a valid URI can't fail");
+            if let Some(auth) = &self.config.auth {
+                auth.apply(&mut request);
+            }
+
+            let mut retry_after = None;
+            let action = match timeout(request_timeout, call(&mut self.client, request)).await {
+                Ok(Ok(response)) => {
+                    retry_after = parse_retry_after(&response);
+                    HttpRetryLogic.should_retry_response(&response)
+                }
+                Ok(Err(error)) if HttpRetryLogic.is_retriable_error(&error) => {
+                    RetryAction::Retry(error.to_string())
+                }
+                Ok(Err(error)) => RetryAction::DontRetry(error.to_string()),
+                Err(_) => RetryAction::Retry("request timed out".into()),
+            };
+
+            match action {
+                RetryAction::Successful => {
+                    emit!(PrometheusRemoteWriteSent {
+                        series,
+                        byte_size: body.len(),
+                    });
+                    return;
+                }
+                RetryAction::Retry(reason) if attempt < self.config.retry_attempts => {
+                    emit!(PrometheusRemoteWriteFailed {
+                        reason,
+                        will_retry: true,
+                    });
+                    delay_for(retry_after.unwrap_or(backoff).min(max_backoff)).await;
+                    backoff = (backoff * 2).min(max_backoff);
+                    attempt += 1;
+                }
+                RetryAction::Retry(reason) | RetryAction::DontRetry(reason) => {
+                    emit!(PrometheusRemoteWriteFailed {
+                        reason,
+                        will_retry: false,
+                    });
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn call(
+    client: &mut HttpClient,
+    request: Request<Body>,
+) -> Result<Response<Bytes>, hyper13::Error> {
+    let response = client.call(request).await?;
+    let (parts, body) = response.into_parts();
+    let body = hyper13::body::to_bytes(body).await?;
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Only the delay in seconds is supported, which is what rate limiting
+/// proxies in front of Prometheus compatible stores send.
+fn parse_retry_after(response: &Response<Bytes>) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    name: String,
+    tags: BTreeMap<String, String>,
+}
+
+struct Entry {
+    metric: Metric,
+    updated: Instant,
+}
+
+/// The current value of every series, as remote write expects absolute
+/// values: incremental metrics are added up, distributions are turned into
+/// histograms.
+struct Series {
+    namespace: String,
+    buckets: Vec<f64>,
+    entries: HashMap<SeriesKey, Entry>,
+    new_families: bool,
+}
+
+impl Series {
+    fn new(namespace: &str, buckets: &[f64]) -> Self {
+        Self {
+            namespace: namespace.into(),
+            buckets: buckets.into(),
+            entries: HashMap::new(),
+            new_families: false,
+        }
+    }
+
+    fn update(&mut self, mut metric: Metric, now: Instant) {
+        if let MetricValue::Distribution {
+            values,
+            sample_rates,
+        } = &metric.value
+        {
+            metric.value = histogram(values, sample_rates, &self.buckets);
+        }
+
+        let key = SeriesKey {
+            name: metric.name.clone(),
+            tags: metric.tags.clone().unwrap_or_default(),
+        };
+        match self.entries.get_mut(&key) {
+            Some(entry)
+                if metric.kind.is_incremental()
+                    && mem::discriminant(&entry.metric.value)
+                        == mem::discriminant(&metric.value) =>
+            {
+                entry.metric.add(&metric);
+                entry.updated = now;
+            }
+            _ => {
+                self.new_families |= !self.entries.keys().any(|key| key.name == metric.name);
+                self.entries.insert(
+                    key,
+                    Entry {
+                        metric: metric.into_absolute(),
+                        updated: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the samples of all series and the number of series that went
+    /// stale. These get a staleness marker as their last sample and are
+    /// forgotten.
+    fn collect(
+        &mut self,
+        now: Instant,
+        timestamp: i64,
+        stale_after: Duration,
+        shutdown: bool,
+    ) -> (Vec<TimeSeries>, usize) {
+        let mut timeseries = Vec::new();
+        let mut stale = 0;
+        let namespace = &self.namespace;
+
+        self.entries.retain(|_, entry| {
+            let is_stale = shutdown || now.duration_since(entry.updated) >= stale_after;
+            for (name, extra, value) in expand(&entry.metric.value) {
+                let value = if is_stale {
+                    f64::from_bits(STALE_NAN)
+                } else {
+                    value
+                };
+                timeseries.push(TimeSeries {
+                    labels: labels(
+                        &encode_namespace(namespace, &entry.metric.name),
+                        name,
+                        &entry.metric.tags,
+                        extra,
+                    ),
+                    samples: vec![Sample { value, timestamp }],
+                });
+            }
+
+            // Sets count the distinct values seen within a flush period.
+            if let MetricValue::Set { values } = &mut entry.metric.value {
+                values.clear();
+            }
+
+            stale += is_stale as usize;
+            !is_stale
+        });
+
+        (timeseries, stale)
+    }
+
+    fn metadata(&self) -> Vec<MetricMetadata> {
+        let mut families = BTreeMap::new();
+        for entry in self.entries.values() {
+            let r#type = match &entry.metric.value {
+                MetricValue::Counter { .. } => MetricType::Counter,
+                MetricValue::Gauge { .. } | MetricValue::Set { .. } => MetricType::Gauge,
+                MetricValue::Distribution { .. } | MetricValue::AggregatedHistogram { .. } => {
+                    MetricType::Histogram
+                }
+                MetricValue::AggregatedSummary { .. } => MetricType::Summary,
+            };
+            families.insert(entry.metric.name.as_str(), r#type);
+        }
+
+        families
+            .into_iter()
+            .map(|(name, r#type)| MetricMetadata {
+                r#type: r#type as i32,
+                metric_family_name: encode_namespace(&self.namespace, name),
+                help: name.into(),
+                unit: String::new(),
+            })
+            .collect()
+    }
+}
+
+/// Buckets the samples of a distribution, the way the `prometheus` sink
+/// does.
+pub(in crate::sinks) fn histogram(
+    values: &[f64],
+    sample_rates: &[u32],
+    buckets: &[f64],
+) -> MetricValue {
+    let mut counts = vec![0; buckets.len()];
+    let mut count = 0;
+    let mut sum = 0.0;
+    for (value, rate) in values.iter().zip(sample_rates) {
+        for (bucket, bucket_count) in buckets.iter().zip(counts.iter_mut()) {
+            if value <= bucket {
+                *bucket_count += rate;
+            }
+        }
+        count += rate;
+        sum += value * f64::from(*rate);
+    }
+
+    MetricValue::AggregatedHistogram {
+        buckets: buckets.into(),
+        counts,
+        count,
+        sum,
+    }
+}
+
+/// Splits a value into the series Prometheus stores it as: a name suffix,
+/// an extra label and the sample value.
+pub(in crate::sinks) fn expand(
+    value: &MetricValue,
+) -> Vec<(&'static str, Option<(&'static str, String)>, f64)> {
+    match value {
+        MetricValue::Counter { value } | MetricValue::Gauge { value } => vec![("", None, *value)],
+        MetricValue::Set { values } => vec![("", None, values.len() as f64)],
+        MetricValue::Distribution { .. } => Vec::new(),
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => buckets
+            .iter()
+            .zip(counts)
+            .map(|(bucket, bucket_count)| {
+                (
+                    "_bucket",
+                    Some(("le", bucket.to_string())),
+                    f64::from(*bucket_count),
+                )
+            })
+            .chain(vec![
+                ("_bucket", Some(("le", "+Inf".into())), f64::from(*count)),
+                ("_sum", None, *sum),
+                ("_count", None, f64::from(*count)),
+            ])
+            .collect(),
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => quantiles
+            .iter()
+            .zip(values)
+            .map(|(quantile, value)| ("", Some(("quantile", quantile.to_string())), *value))
+            .chain(vec![
+                ("_sum", None, *sum),
+                ("_count", None, f64::from(*count)),
+            ])
+            .collect(),
+    }
+}
+
+/// Remote write requires the labels sorted by name.
+fn labels(
+    name: &str,
+    suffix: &str,
+    tags: &Option<BTreeMap<String, String>>,
+    extra: Option<(&str, String)>,
+) -> Vec<Label> {
+    let mut labels = tags
+        .iter()
+        .flatten()
+        .map(|(name, value)| Label {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .chain(extra.map(|(name, value)| Label {
+            name: name.into(),
+            value,
+        }))
+        .chain(Some(Label {
+            name: "__name__".into(),
+            value: format!("{}{}", name, suffix),
+        }))
+        .collect::<Vec<_>>();
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::MetricKind;
+
+    fn metric(name: &str, kind: MetricKind, value: MetricValue) -> Metric {
+        Metric {
+            name: name.into(),
+            timestamp: None,
+            tags: Some(
+                vec![("code".to_owned(), "200".to_owned())]
+                    .into_iter()
+                    .collect(),
+            ),
+            kind,
+            value,
+        }
+    }
+
+    fn label_pairs(series: &TimeSeries) -> Vec<(&str, &str)> {
+        series
+            .labels
+            .iter()
+            .map(|label| (label.name.as_str(), label.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn remote_write_adds_up_incremental_counters() {
+        let mut series = Series::new("vector", &[]);
+        let now = Instant::now();
+        for _ in 0..3 {
+            series.update(
+                metric(
+                    "hits",
+                    MetricKind::Incremental,
+                    MetricValue::Counter { value: 2.0 },
+                ),
+                now,
+            );
+        }
+
+        let (timeseries, stale) = series.collect(now, 1000, Duration::from_secs(300), false);
+        assert_eq!(stale, 0);
+        assert_eq!(timeseries.len(), 1);
+        assert_eq!(
+            label_pairs(&timeseries[0]),
+            vec![("__name__", "vector_hits"), ("code", "200")]
+        );
+        assert_eq!(
+            timeseries[0].samples,
+            vec![Sample {
+                value: 6.0,
+                timestamp: 1000
+            }]
+        );
+    }
+
+    #[test]
+    fn remote_write_expands_distributions() {
+        let mut series = Series::new("", &[1.0, 5.0]);
+        let now = Instant::now();
+        series.update(
+            metric(
+                "latency",
+                MetricKind::Incremental,
+                MetricValue::Distribution {
+                    values: vec![0.5, 3.0, 10.0],
+                    sample_rates: vec![1, 2, 1],
+                },
+            ),
+            now,
+        );
+
+        let (timeseries, _) = series.collect(now, 1000, Duration::from_secs(300), false);
+        let samples = timeseries
+            .iter()
+            .map(|series| {
+                let labels = label_pairs(series);
+                (
+                    labels
+                        .iter()
+                        .find(|(name, _)| *name == "__name__")
+                        .unwrap()
+                        .1
+                        .to_owned(),
+                    labels
+                        .iter()
+                        .find(|(name, _)| *name == "le")
+                        .map(|(_, value)| value.to_string()),
+                    series.samples[0].value,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            samples,
+            vec![
+                ("latency_bucket".into(), Some("1".into()), 1.0),
+                ("latency_bucket".into(), Some("5".into()), 3.0),
+                ("latency_bucket".into(), Some("+Inf".into()), 4.0),
+                ("latency_sum".into(), None, 16.5),
+                ("latency_count".into(), None, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn remote_write_marks_stale_series() {
+        let mut series = Series::new("", &[]);
+        let start = Instant::now();
+        series.update(
+            metric(
+                "temperature",
+                MetricKind::Absolute,
+                MetricValue::Gauge { value: 21.0 },
+            ),
+            start,
+        );
+
+        let later = start + Duration::from_secs(301);
+        let (timeseries, stale) = series.collect(later, 1000, Duration::from_secs(300), false);
+        assert_eq!(stale, 1);
+        assert_eq!(timeseries[0].samples[0].value.to_bits(), STALE_NAN);
+
+        let (timeseries, stale) = series.collect(later, 2000, Duration::from_secs(300), false);
+        assert_eq!(stale, 0);
+        assert!(timeseries.is_empty());
+    }
+
+    #[test]
+    fn remote_write_metadata() {
+        let mut series = Series::new("vector", &[]);
+        let now = Instant::now();
+        series.update(
+            metric(
+                "hits",
+                MetricKind::Absolute,
+                MetricValue::Counter { value: 1.0 },
+            ),
+            now,
+        );
+        assert!(series.new_families);
+
+        let metadata = series.metadata();
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].r#type, MetricType::Counter as i32);
+        assert_eq!(metadata[0].metric_family_name, "vector_hits");
+    }
+
+    #[test]
+    fn remote_write_parses_retry_after() {
+        let mut response = Response::new(Bytes::new());
+        assert_eq!(parse_retry_after(&response), None);
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(parse_retry_after(&response), Some(Duration::from_secs(7)));
+    }
+}
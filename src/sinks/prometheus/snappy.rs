@@ -0,0 +1,206 @@
+//! Compression in the Snappy block format, which remote write requires.
+//!
+//! This is a plain greedy LZ77 matcher: it finds repeated four byte
+//! sequences through a hash table and emits them as copies. It compresses
+//! less than the reference implementation, but the label sets repeated
+//! throughout a write request are easy to find.
+//!
+//! See https://github.com/google/snappy/blob/master/format_description.txt
+
+const HASH_BITS: u32 = 14;
+const MAX_OFFSET: usize = 65_535;
+
+const TAG_LITERAL: u8 = 0;
+const TAG_COPY_1: u8 = 1;
+const TAG_COPY_2: u8 = 2;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len() / 2 + 16);
+    write_varint(&mut output, input.len() as u64);
+
+    // Positions of earlier sequences plus one, zero marks an empty slot.
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut position = 0;
+
+    while position + 4 <= input.len() {
+        let sequence = load_u32(input, position);
+        let hash = (sequence.wrapping_mul(0x1e35_a7bd) >> (32 - HASH_BITS)) as usize;
+        let candidate = table[hash];
+        table[hash] = position + 1;
+
+        if candidate > 0 {
+            let candidate = candidate - 1;
+            let offset = position - candidate;
+            if offset <= MAX_OFFSET && load_u32(input, candidate) == sequence {
+                let mut len = 4;
+                while position + len < input.len()
+                    && input[candidate + len] == input[position + len]
+                {
+                    len += 1;
+                }
+
+                emit_literal(&mut output, &input[literal_start..position]);
+                emit_copy(&mut output, offset, len);
+                position += len;
+                literal_start = position;
+                continue;
+            }
+        }
+
+        position += 1;
+    }
+
+    emit_literal(&mut output, &input[literal_start..]);
+    output
+}
+
+fn load_u32(input: &[u8], at: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&input[at..at + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn emit_literal(output: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+
+    let n = literal.len() - 1;
+    if n < 60 {
+        output.push((n as u8) << 2 | TAG_LITERAL);
+    } else {
+        // Tags 60 to 63 are followed by the length in 1 to 4 bytes.
+        let bytes = (n as u32).to_le_bytes();
+        let width = match n {
+            n if n < 1 << 8 => 1,
+            n if n < 1 << 16 => 2,
+            n if n < 1 << 24 => 3,
+            _ => 4,
+        };
+        output.push((59 + width as u8) << 2 | TAG_LITERAL);
+        output.extend_from_slice(&bytes[..width]);
+    }
+    output.extend_from_slice(literal);
+}
+
+fn emit_copy(output: &mut Vec<u8>, offset: usize, mut len: usize) {
+    // A single copy covers at most 64 bytes. Leaving at least four for the
+    // last one allows it to use the shorter encoding.
+    while len >= 68 {
+        emit_copy_2(output, offset, 64);
+        len -= 64;
+    }
+    if len > 64 {
+        emit_copy_2(output, offset, 60);
+        len -= 60;
+    }
+
+    if len < 12 && offset < 2048 {
+        output.push(((offset >> 8) as u8) << 5 | ((len - 4) as u8) << 2 | TAG_COPY_1);
+        output.push(offset as u8);
+    } else {
+        emit_copy_2(output, offset, len);
+    }
+}
+
+fn emit_copy_2(output: &mut Vec<u8>, offset: usize, len: usize) {
+    output.push(((len - 1) as u8) << 2 | TAG_COPY_2);
+    output.extend_from_slice(&(offset as u16).to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A straightforward decoder, to check that compressed data decodes to
+    /// the input again.
+    fn decompress(input: &[u8]) -> Vec<u8> {
+        let mut position = 0;
+        let mut len = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = input[position];
+            position += 1;
+            len |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte < 0x80 {
+                break;
+            }
+        }
+
+        let mut output = Vec::with_capacity(len as usize);
+        while position < input.len() {
+            let tag = input[position];
+            position += 1;
+            let (offset, len) = match tag & 0b11 {
+                TAG_LITERAL => {
+                    let mut len = (tag >> 2) as usize;
+                    if len >= 60 {
+                        let width = len - 59;
+                        let mut bytes = [0; 4];
+                        bytes[..width].copy_from_slice(&input[position..position + width]);
+                        len = u32::from_le_bytes(bytes) as usize;
+                        position += width;
+                    }
+                    output.extend_from_slice(&input[position..position + len + 1]);
+                    position += len + 1;
+                    continue;
+                }
+                TAG_COPY_1 => {
+                    let offset = ((tag as usize >> 5) << 8) | input[position] as usize;
+                    position += 1;
+                    (offset, ((tag >> 2) & 0b111) as usize + 4)
+                }
+                TAG_COPY_2 => {
+                    let offset = input[position] as usize | (input[position + 1] as usize) << 8;
+                    position += 2;
+                    (offset, (tag >> 2) as usize + 1)
+                }
+                _ => panic!("unexpected tag {}", tag),
+            };
+            for _ in 0..len {
+                let byte = output[output.len() - offset];
+                output.push(byte);
+            }
+        }
+
+        assert_eq!(output.len() as u64, len);
+        output
+    }
+
+    #[test]
+    fn snappy_empty() {
+        assert_eq!(compress(b""), vec![0]);
+    }
+
+    #[test]
+    fn snappy_literal() {
+        assert_eq!(compress(b"abc"), vec![3, 2 << 2, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn snappy_round_trips() {
+        let mut input = Vec::new();
+        for i in 0..2000 {
+            input.extend_from_slice(
+                format!("http_requests_total{{code=\"{}\",method=\"GET\"}} ", i % 7).as_bytes(),
+            );
+            input.push((i % 251) as u8);
+        }
+        input.extend(vec![b'x'; 300]);
+        input.extend((0..70_000).map(|i| (i * 7919 % 256) as u8));
+
+        let compressed = compress(&input);
+        assert!(compressed.len() < input.len());
+        assert_eq!(decompress(&compressed), input);
+    }
+}
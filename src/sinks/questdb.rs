@@ -0,0 +1,189 @@
+use crate::{
+    event::{metric::Metric, Event},
+    sinks::{
+        influxdb::{encode_timestamp, influx_line_protocol, metrics::metric_fields, Field},
+        util::{
+            tcp::{tcp_healthcheck, TcpSink},
+            StreamSink,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes::Bytes;
+use futures01::{stream::iter_ok, Sink};
+use serde::{Deserialize, Serialize};
+
+/// The port QuestDB accepts the line protocol on over TCP.
+const DEFAULT_PORT: u16 = 9009;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuestDBConfig {
+    pub address: String,
+    pub namespace: Option<String>,
+    /// The table all metrics are written to. Every metric has a table of
+    /// its own otherwise.
+    pub table: Option<String>,
+    pub tls: Option<TlsConfig>,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<QuestDBConfig>("questdb")
+}
+
+#[typetag::serde(name = "questdb")]
+impl SinkConfig for QuestDBConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let (host, port) = self.host_and_port()?;
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+
+        let tcp = TcpSink::new(host.clone(), port, cx.resolver(), tls);
+        let healthcheck = tcp_healthcheck(host, port, cx.resolver());
+
+        let namespace = self.namespace.clone();
+        let table = self.table.clone();
+        let sink = StreamSink::new(tcp, cx.acker()).with_flat_map(move |event: Event| {
+            let line = encode_metric(event.into_metric(), namespace.as_deref(), table.as_deref());
+            iter_ok(line.map(Bytes::from))
+        });
+
+        Ok((Box::new(sink), healthcheck))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "questdb"
+    }
+}
+
+impl QuestDBConfig {
+    fn host_and_port(&self) -> crate::Result<(String, u16)> {
+        let uri = format!("tcp://{}", self.address).parse::<http::Uri>()?;
+        let host = uri
+            .host()
+            .ok_or_else(|| "A host is required for the address".to_string())?;
+        Ok((host.to_string(), uri.port_u16().unwrap_or(DEFAULT_PORT)))
+    }
+}
+
+/// Maps a metric to a row of its table, as a line of the InfluxDB line
+/// protocol. QuestDB creates missing tables and columns as rows arrive,
+/// with tags as symbol columns.
+fn encode_metric(metric: Metric, namespace: Option<&str>, table: Option<&str>) -> Option<String> {
+    let name = match namespace {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, metric.name),
+        _ => metric.name,
+    };
+    let (measurement, tags) = match table {
+        Some(table) => {
+            let mut tags = metric.tags.unwrap_or_default();
+            tags.insert("name".to_owned(), name);
+            (table.to_owned(), Some(tags))
+        }
+        None => (name, metric.tags),
+    };
+
+    let timestamp = encode_timestamp(metric.timestamp);
+    let (metric_type, fields) = metric_fields(metric.value);
+    // QuestDB has no unsigned integers.
+    let fields = fields.map(|fields| {
+        fields
+            .into_iter()
+            .map(|(key, value)| match value {
+                Field::UnsignedInt(value) => (key, Field::Int(value.into())),
+                value => (key, value),
+            })
+            .collect()
+    });
+
+    let mut line = String::new();
+    influx_line_protocol(measurement, metric_type, tags, fields, timestamp, &mut line);
+    if line.is_empty() {
+        None
+    } else {
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::{MetricKind, MetricValue};
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    fn counter() -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), "my host".to_owned());
+        Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.ymd(2020, 6, 1).and_hms_nano(10, 0, 0, 5)),
+            tags: Some(tags),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.5 },
+        }
+    }
+
+    #[test]
+    fn questdb_table_per_metric() {
+        assert_eq!(
+            encode_metric(counter(), Some("vector"), None).unwrap(),
+            "vector.requests,host=my\\ host,metric_type=counter value=1.5 1591005600000000005\n"
+        );
+    }
+
+    #[test]
+    fn questdb_single_table() {
+        assert_eq!(
+            encode_metric(counter(), None, Some("metrics")).unwrap(),
+            "metrics,host=my\\ host,metric_type=counter,name=requests value=1.5 1591005600000000005\n"
+        );
+    }
+
+    #[test]
+    fn questdb_signed_counts() {
+        let metric = Metric {
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![],
+                counts: vec![],
+                count: 3,
+                sum: 1.0,
+            },
+            ..counter()
+        };
+        let line = encode_metric(metric, None, None).unwrap();
+        assert!(line.contains("count=3i"));
+        assert!(!line.contains("3u"));
+    }
+
+    #[test]
+    fn questdb_skips_metrics_without_fields() {
+        let metric = Metric {
+            value: MetricValue::Distribution {
+                values: vec![],
+                sample_rates: vec![],
+            },
+            ..counter()
+        };
+        assert_eq!(encode_metric(metric, None, None), None);
+    }
+
+    #[test]
+    fn questdb_default_port() {
+        let config = QuestDBConfig {
+            address: "questdb".into(),
+            namespace: None,
+            table: None,
+            tls: None,
+        };
+        assert_eq!(
+            config.host_and_port().unwrap(),
+            ("questdb".to_owned(), DEFAULT_PORT)
+        );
+    }
+}
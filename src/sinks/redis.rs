@@ -0,0 +1,398 @@
+use crate::{
+    event::{self, Event},
+    internal_events::RedisEventSent,
+    sinks::util::{
+        encoding::{EncodingConfig, EncodingConfiguration},
+        retries::RetryLogic,
+        BatchEventsConfig, TowerRequestConfig,
+    },
+    template::Template,
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures::future::{FutureExt, TryFutureExt};
+use futures01::{stream::iter_ok, Future, Poll, Sink};
+use lazy_static::lazy_static;
+use redis::{
+    cluster::{cluster_pipe, ClusterClient, ClusterConnection},
+    Cmd, ErrorKind, RedisError,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::spawn_blocking;
+use tower::Service;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("At least one endpoint must be configured"))]
+    NoEndpoints,
+    #[snafu(display("Several endpoints are only supported in cluster mode"))]
+    SeveralEndpoints,
+}
+
+#[derive(Debug, Snafu)]
+pub enum SendError {
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: RedisError },
+    #[snafu(display("Sending failed: {}", source))]
+    Send { source: RedisError },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RedisSinkConfig {
+    /// `redis://` URLs, of the server, or of the nodes of the cluster the
+    /// others are discovered from.
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub cluster: bool,
+    /// The list, stream or channel each event is sent to.
+    pub key: Template,
+    #[serde(default)]
+    pub mode: Mode,
+    pub encoding: EncodingConfig<Encoding>,
+    /// The field of the stream entries holding the encoded event.
+    #[serde(default = "default_stream_field")]
+    pub stream_field: String,
+    /// Trims streams to about this many entries.
+    pub stream_max_length: Option<u64>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+}
+
+fn default_stream_field() -> String {
+    "message".into()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// `RPUSH` to a list.
+    List,
+    /// `XADD` to a stream.
+    Stream,
+    /// `PUBLISH` to a channel.
+    Channel,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::List
+    }
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        rate_limit_num: Some(1000),
+        timeout_secs: Some(30),
+        ..Default::default()
+    };
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Text,
+    Json,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<RedisSinkConfig>("redis")
+}
+
+#[typetag::serde(name = "redis")]
+impl SinkConfig for RedisSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        if self.endpoints.is_empty() {
+            return Err(BuildError::NoEndpoints.into());
+        }
+        if self.endpoints.len() > 1 && !self.cluster {
+            return Err(BuildError::SeveralEndpoints.into());
+        }
+        let client = if self.cluster {
+            RedisClient::Cluster(ClusterClient::open(self.endpoints.clone())?)
+        } else {
+            RedisClient::Single(redis::Client::open(self.endpoints[0].as_str())?)
+        };
+
+        let batch = self.batch.unwrap_or(100, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+        let key = self.key.clone();
+        let encoding = self.encoding.clone();
+
+        let service = RedisService {
+            client: Arc::new(client),
+            mode: self.mode,
+            stream_field: self.stream_field.clone(),
+            stream_max_length: self.stream_max_length,
+            timeout: request.timeout,
+            connection: Arc::new(Mutex::new(None)),
+        };
+        let healthcheck = service.clone().healthcheck().boxed().compat();
+        let sink = request
+            .batch_sink(RedisRetryLogic, service, Vec::new(), batch, cx.acker())
+            .sink_map_err(|error| error!("Fatal redis sink error: {}", error))
+            .with_flat_map(move |event| iter_ok(encode_event(event, &key, &encoding)));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "redis"
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RedisMessage {
+    key: String,
+    payload: Vec<u8>,
+}
+
+enum RedisClient {
+    Single(redis::Client),
+    Cluster(ClusterClient),
+}
+
+enum RedisConnection {
+    Single(redis::Connection),
+    Cluster(ClusterConnection),
+}
+
+/// Sends the batches from a blocking thread, as the client is blocking.
+#[derive(Clone)]
+struct RedisService {
+    client: Arc<RedisClient>,
+    mode: Mode,
+    stream_field: String,
+    stream_max_length: Option<u64>,
+    timeout: Duration,
+    /// Connected on first use, and again after failing.
+    connection: Arc<Mutex<Option<RedisConnection>>>,
+}
+
+impl RedisService {
+    fn connect(&self) -> Result<RedisConnection, RedisError> {
+        let connection = match &*self.client {
+            RedisClient::Single(client) => {
+                let connection = client.get_connection_with_timeout(self.timeout)?;
+                connection.set_read_timeout(Some(self.timeout))?;
+                connection.set_write_timeout(Some(self.timeout))?;
+                RedisConnection::Single(connection)
+            }
+            RedisClient::Cluster(client) => {
+                let connection = client.get_connection()?;
+                connection.set_read_timeout(Some(self.timeout))?;
+                connection.set_write_timeout(Some(self.timeout))?;
+                RedisConnection::Cluster(connection)
+            }
+        };
+        Ok(connection)
+    }
+
+    fn command(&self, message: &RedisMessage) -> Cmd {
+        let mut command = match self.mode {
+            Mode::List => redis::cmd("RPUSH"),
+            Mode::Stream => redis::cmd("XADD"),
+            Mode::Channel => redis::cmd("PUBLISH"),
+        };
+        command.arg(&message.key);
+        if self.mode == Mode::Stream {
+            if let Some(max_length) = self.stream_max_length {
+                command.arg("MAXLEN").arg("~").arg(max_length);
+            }
+            command.arg("*").arg(&self.stream_field);
+        }
+        command.arg(&message.payload);
+        command
+    }
+
+    /// Sends the batch as a single pipeline, which succeeds once every
+    /// command was acknowledged.
+    fn send(&self, messages: &[RedisMessage]) -> Result<(), SendError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(self.connect().context(Connect)?);
+        }
+
+        let commands = messages.iter().map(|message| self.command(message));
+        let result = match connection.as_mut().expect("connected above") {
+            RedisConnection::Single(connection) => {
+                let mut pipeline = redis::pipe();
+                commands.for_each(|command| {
+                    pipeline.add_command(command);
+                });
+                pipeline.query::<()>(connection)
+            }
+            RedisConnection::Cluster(connection) => {
+                let mut pipeline = cluster_pipe();
+                commands.for_each(|command| {
+                    pipeline.add_command(command);
+                });
+                pipeline.query::<()>(connection)
+            }
+        };
+        if result.is_err() {
+            // The replies to the pipeline may still be pending on it.
+            *connection = None;
+        }
+        result.context(Send)
+    }
+
+    async fn healthcheck(self) -> crate::Result<()> {
+        spawn_blocking(move || {
+            let mut connection = self.connect()?;
+            match &mut connection {
+                RedisConnection::Single(connection) => redis::cmd("PING").query::<()>(connection),
+                RedisConnection::Cluster(connection) => redis::cmd("PING").query::<()>(connection),
+            }
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+impl Service<Vec<RedisMessage>> for RedisService {
+    type Response = ();
+    type Error = SendError;
+    type Future = Box<dyn Future<Item = (), Error = SendError> + Send>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, messages: Vec<RedisMessage>) -> Self::Future {
+        debug!(message = "sending messages.", count = %messages.len());
+
+        let service = self.clone();
+        let send = async move {
+            spawn_blocking(move || service.send(&messages))
+                .await
+                .map_err(|error| SendError::Send {
+                    source: io::Error::new(io::ErrorKind::Other, error.to_string()).into(),
+                })?
+        };
+        Box::new(send.boxed().compat())
+    }
+}
+
+impl fmt::Debug for RedisService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisService")
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RedisRetryLogic;
+
+impl RetryLogic for RedisRetryLogic {
+    type Error = SendError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        match error {
+            SendError::Connect { .. } => true,
+            SendError::Send { source } => {
+                source.is_io_error()
+                    || source.is_connection_dropped()
+                    || match source.kind() {
+                        ErrorKind::TryAgain
+                        | ErrorKind::ClusterDown
+                        | ErrorKind::BusyLoadingError => true,
+                        _ => false,
+                    }
+            }
+        }
+    }
+}
+
+fn encode_event(
+    mut event: Event,
+    key: &Template,
+    encoding: &EncodingConfig<Encoding>,
+) -> Option<RedisMessage> {
+    let key = key
+        .render_string(&event)
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event; dropping event.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()?;
+
+    encoding.apply_rules(&mut event);
+    let log = event.into_log();
+    let payload = match encoding.codec() {
+        Encoding::Json => serde_json::to_vec(&log).expect("Error encoding event as json."),
+        Encoding::Text => log
+            .get(&event::log_schema().message_key())
+            .map(|value| value.as_bytes().to_vec())
+            .unwrap_or_default(),
+    };
+
+    emit!(RedisEventSent {
+        byte_size: payload.len()
+    });
+    Some(RedisMessage { key, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redis_builds_commands_per_mode() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("app", "web");
+        let key = Template::from("logs:{{ app }}");
+        let message = encode_event(event, &key, &Encoding::Text.into()).unwrap();
+        assert_eq!(message.key, "logs:web");
+
+        let service = RedisService {
+            client: Arc::new(RedisClient::Single(
+                redis::Client::open("redis://127.0.0.1:6379").unwrap(),
+            )),
+            mode: Mode::Stream,
+            stream_field: "message".into(),
+            stream_max_length: Some(1000),
+            timeout: Duration::from_secs(1),
+            connection: Arc::new(Mutex::new(None)),
+        };
+        let expected = redis::cmd("XADD")
+            .arg("logs:web")
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(1000)
+            .arg("*")
+            .arg("message")
+            .arg("hello")
+            .get_packed_command();
+        assert_eq!(service.command(&message).get_packed_command(), expected);
+
+        let service = RedisService {
+            mode: Mode::List,
+            ..service
+        };
+        let expected = redis::cmd("RPUSH")
+            .arg("logs:web")
+            .arg("hello")
+            .get_packed_command();
+        assert_eq!(service.command(&message).get_packed_command(), expected);
+
+        assert!(encode_event(Event::from("hello"), &key, &Encoding::Text.into()).is_none());
+    }
+}
@@ -0,0 +1,518 @@
+//! A minimal, blocking SMTP client. It covers what is needed to hand a
+//! message to a relay: EHLO, STARTTLS or implicit TLS, `AUTH PLAIN` and
+//! `AUTH LOGIN`, and a single transaction per connection.
+
+use crate::tls::{tls_connector_builder, MaybeTlsSettings, TlsError, TlsSettings};
+use openssl::{
+    error::ErrorStack,
+    ssl::{HandshakeError, SslStream},
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TlsMode {
+    /// Plain connection upgraded with `STARTTLS`, which must be offered.
+    #[derivative(Default)]
+    Starttls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+    /// No encryption at all.
+    None,
+}
+
+impl TlsMode {
+    pub fn default_port(self) -> u16 {
+        match self {
+            TlsMode::Starttls => 587,
+            TlsMode::Implicit => 465,
+            TlsMode::None => 25,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum SmtpError {
+    #[snafu(display("Failed to connect to {}: {}", address, source))]
+    Connect { address: String, source: io::Error },
+    #[snafu(display("Connection failed: {}", source))]
+    Io { source: io::Error },
+    #[snafu(display("TLS setup failed: {}", source))]
+    TlsSetup { source: TlsError },
+    #[snafu(display("TLS setup failed: {}", source))]
+    TlsConfigure { source: ErrorStack },
+    #[snafu(display("TLS handshake failed: {}", message))]
+    TlsHandshake { message: String },
+    #[snafu(display("Server does not offer STARTTLS"))]
+    StartTlsUnsupported,
+    #[snafu(display("Server offers no supported authentication mechanism"))]
+    AuthUnsupported,
+    #[snafu(display("{} was rejected with {}: {}", command, code, message))]
+    Rejected {
+        command: &'static str,
+        code: u16,
+        message: String,
+    },
+    #[snafu(display("Malformed reply {:?}", line))]
+    MalformedReply { line: String },
+}
+
+impl SmtpError {
+    /// Whether trying again later may succeed. Permanent (5xx) replies and
+    /// configuration problems will fail the same way every time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SmtpError::Connect { .. } | SmtpError::Io { .. } => true,
+            SmtpError::Rejected { code, .. } => *code >= 400 && *code < 500,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+#[derive(Clone)]
+pub struct ClientSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls_mode: TlsMode,
+    pub tls: TlsSettings,
+    pub credentials: Option<Credentials>,
+    pub hello_name: String,
+    pub timeout: Duration,
+}
+
+pub struct Envelope<'a> {
+    pub from: &'a str,
+    pub to: &'a [String],
+}
+
+/// Delivers one message, from connecting to `QUIT`.
+pub fn send(
+    settings: &ClientSettings,
+    envelope: &Envelope<'_>,
+    message: &[u8],
+) -> Result<(), SmtpError> {
+    let mut connection = Connection::establish(settings)?;
+
+    connection.command("MAIL FROM", &format!("MAIL FROM:<{}>", envelope.from), 250)?;
+    for recipient in envelope.to {
+        connection.command("RCPT TO", &format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    connection.command("DATA", "DATA", 354)?;
+    connection.write_data(message)?;
+    connection.expect("DATA", 250)?;
+
+    // The message is accepted at this point, a failing QUIT changes nothing.
+    let _ = connection.command("QUIT", "QUIT", 221);
+    Ok(())
+}
+
+/// Goes as far as a delivery would without starting a transaction, which
+/// verifies the connection, TLS setup and credentials.
+pub fn check(settings: &ClientSettings) -> Result<(), SmtpError> {
+    let mut connection = Connection::establish(settings)?;
+    connection.command("QUIT", "QUIT", 221)?;
+    Ok(())
+}
+
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+struct Connection {
+    stream: BufReader<Stream>,
+}
+
+impl Connection {
+    /// Connects, greets and if configured upgrades to TLS and logs in.
+    fn establish(settings: &ClientSettings) -> Result<Self, SmtpError> {
+        let mut connection = Connection::open(settings)?;
+
+        connection.expect("connect", 220)?;
+        let mut capabilities = connection.hello(&settings.hello_name)?;
+
+        if settings.tls_mode == TlsMode::Starttls {
+            if !has_capability(&capabilities, "STARTTLS") {
+                return Err(SmtpError::StartTlsUnsupported);
+            }
+            connection.command("STARTTLS", "STARTTLS", 220)?;
+            connection = connection.upgrade(settings)?;
+            capabilities = connection.hello(&settings.hello_name)?;
+        }
+
+        if let Some(credentials) = &settings.credentials {
+            connection.authenticate(&capabilities, credentials)?;
+        }
+
+        Ok(connection)
+    }
+
+    fn open(settings: &ClientSettings) -> Result<Self, SmtpError> {
+        let address = format!("{}:{}", settings.host, settings.port);
+        let stream = connect(&address, settings.timeout).context(Connect { address })?;
+
+        let stream = match settings.tls_mode {
+            TlsMode::Implicit => tls_handshake(settings, stream)?,
+            TlsMode::Starttls | TlsMode::None => Stream::Plain(stream),
+        };
+
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    fn upgrade(self, settings: &ClientSettings) -> Result<Self, SmtpError> {
+        match self.stream.into_inner() {
+            Stream::Plain(stream) => Ok(Self {
+                stream: BufReader::new(tls_handshake(settings, stream)?),
+            }),
+            stream @ Stream::Tls(_) => Ok(Self {
+                stream: BufReader::new(stream),
+            }),
+        }
+    }
+
+    /// Sends EHLO and returns the advertised extensions.
+    fn hello(&mut self, name: &str) -> Result<Vec<String>, SmtpError> {
+        let lines = self.command("EHLO", &format!("EHLO {}", name), 250)?;
+        Ok(lines.into_iter().skip(1).collect())
+    }
+
+    fn authenticate(
+        &mut self,
+        capabilities: &[String],
+        credentials: &Credentials,
+    ) -> Result<(), SmtpError> {
+        let mechanisms = capabilities
+            .iter()
+            .find(|line| line.to_uppercase().starts_with("AUTH "))
+            .map(|line| line[5..].to_uppercase())
+            .unwrap_or_default();
+        let mechanisms = mechanisms.split_whitespace().collect::<Vec<_>>();
+
+        if mechanisms.contains(&"PLAIN") {
+            let token =
+                base64::encode(&format!("\0{}\0{}", credentials.user, credentials.password));
+            self.command("AUTH", &format!("AUTH PLAIN {}", token), 235)?;
+        } else if mechanisms.contains(&"LOGIN") {
+            self.command("AUTH", "AUTH LOGIN", 334)?;
+            self.command("AUTH", &base64::encode(&credentials.user), 334)?;
+            self.command("AUTH", &base64::encode(&credentials.password), 235)?;
+        } else {
+            return Err(SmtpError::AuthUnsupported);
+        }
+        Ok(())
+    }
+
+    fn command(
+        &mut self,
+        name: &'static str,
+        line: &str,
+        expected: u16,
+    ) -> Result<Vec<String>, SmtpError> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .and_then(|_| stream.flush())
+            .context(Io)?;
+        self.expect(name, expected)
+    }
+
+    /// Writes the message with dot stuffing and the terminating `.` line.
+    fn write_data(&mut self, message: &[u8]) -> Result<(), SmtpError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(&dot_stuff(message)).context(Io)?;
+        stream.write_all(b".\r\n").context(Io)?;
+        stream.flush().context(Io)
+    }
+
+    fn expect(&mut self, command: &'static str, expected: u16) -> Result<Vec<String>, SmtpError> {
+        let (code, lines) = self.read_reply()?;
+        if code == expected {
+            Ok(lines)
+        } else {
+            Err(SmtpError::Rejected {
+                command,
+                code,
+                message: lines.join(" "),
+            })
+        }
+    }
+
+    /// Reads a possibly multi-line reply, where all but the last line have
+    /// a `-` after the code.
+    fn read_reply(&mut self) -> Result<(u16, Vec<String>), SmtpError> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).context(Io)? == 0 {
+                return Err(SmtpError::Io {
+                    source: io::ErrorKind::UnexpectedEof.into(),
+                });
+            }
+            let line = line.trim_end();
+
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| SmtpError::MalformedReply { line: line.into() })?;
+            let last = line.get(3..4) != Some("-");
+            lines.push(line.get(4..).unwrap_or_default().to_owned());
+
+            if last {
+                return Ok((code, lines));
+            }
+        }
+    }
+}
+
+fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::ErrorKind::AddrNotAvailable.into()))
+}
+
+fn tls_handshake(settings: &ClientSettings, stream: TcpStream) -> Result<Stream, SmtpError> {
+    let mut configuration = tls_connector_builder(&MaybeTlsSettings::Tls(settings.tls.clone()))
+        .context(TlsSetup)?
+        .build()
+        .configure()
+        .context(TlsConfigure)?;
+    settings.tls.apply_connect_configuration(&mut configuration);
+
+    match configuration.connect(&settings.host, stream) {
+        Ok(stream) => Ok(Stream::Tls(Box::new(stream))),
+        Err(HandshakeError::Failure(stream)) => Err(SmtpError::TlsHandshake {
+            message: stream.error().to_string(),
+        }),
+        Err(error) => Err(SmtpError::TlsHandshake {
+            message: error.to_string(),
+        }),
+    }
+}
+
+fn has_capability(capabilities: &[String], name: &str) -> bool {
+    capabilities
+        .iter()
+        .any(|line| line.split_whitespace().next() == Some(name))
+}
+
+/// Doubles the leading dot of every line, so that no line of the message
+/// can end the DATA section early.
+fn dot_stuff(message: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(message.len() + 8);
+    let mut line_start = true;
+    for byte in message {
+        if line_start && *byte == b'.' {
+            stuffed.push(b'.');
+        }
+        stuffed.push(*byte);
+        line_start = *byte == b'\n';
+    }
+    if !stuffed.ends_with(b"\r\n") {
+        stuffed.extend_from_slice(b"\r\n");
+    }
+    stuffed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::next_addr;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn smtp_dot_stuffing() {
+        assert_eq!(
+            dot_stuff(b"a\r\n.b\r\n.\r\n"),
+            b"a\r\n..b\r\n..\r\n".to_vec()
+        );
+        assert_eq!(dot_stuff(b".a"), b"..a\r\n".to_vec());
+    }
+
+    /// Plays the server side of a conversation, replying to every line that
+    /// is read with the next scripted reply.
+    fn serve(
+        listener: TcpListener,
+        replies: &'static [&'static str],
+    ) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut transcript = String::new();
+
+            writer.write_all(b"220 mx.example.com ESMTP\r\n").unwrap();
+            let mut replies = replies.iter();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                if in_data && line != ".\r\n" {
+                    continue;
+                }
+                in_data = line == "DATA\r\n";
+                match replies.next() {
+                    Some(reply) => writer.write_all(reply.as_bytes()).unwrap(),
+                    None => break,
+                }
+            }
+            transcript
+        })
+    }
+
+    fn settings(port: u16) -> ClientSettings {
+        ClientSettings {
+            host: "127.0.0.1".into(),
+            port,
+            tls_mode: TlsMode::None,
+            tls: TlsSettings::from_options(&None).unwrap(),
+            credentials: Some(Credentials {
+                user: "vector".into(),
+                password: "secret".into(),
+            }),
+            hello_name: "localhost".into(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn smtp_sends_message() {
+        let address = next_addr();
+        let listener = TcpListener::bind(address).unwrap();
+        let server = serve(
+            listener,
+            &[
+                "250-mx.example.com\r\n250-AUTH LOGIN PLAIN\r\n250 8BITMIME\r\n",
+                "235 ok\r\n",
+                "250 ok\r\n",
+                "250 ok\r\n",
+                "354 go ahead\r\n",
+                "250 queued\r\n",
+                "221 bye\r\n",
+            ],
+        );
+
+        let to = vec!["ops@example.com".to_owned()];
+        let envelope = Envelope {
+            from: "vector@example.com",
+            to: &to,
+        };
+        send(
+            &settings(address.port()),
+            &envelope,
+            b"Subject: hi\r\n\r\n.hello\r\n",
+        )
+        .unwrap();
+
+        let transcript = server.join().unwrap();
+        assert_eq!(
+            transcript,
+            "EHLO localhost\r\n\
+             AUTH PLAIN AHZlY3RvcgBzZWNyZXQ=\r\n\
+             MAIL FROM:<vector@example.com>\r\n\
+             RCPT TO:<ops@example.com>\r\n\
+             DATA\r\n\
+             Subject: hi\r\n\
+             \r\n\
+             ..hello\r\n\
+             .\r\n\
+             QUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn smtp_reports_rejections() {
+        let address = next_addr();
+        let listener = TcpListener::bind(address).unwrap();
+        let _server = serve(
+            listener,
+            &["250 mx.example.com\r\n", "550 no such user\r\n"],
+        );
+
+        let to = vec!["nobody@example.com".to_owned()];
+        let envelope = Envelope {
+            from: "vector@example.com",
+            to: &to,
+        };
+        let mut settings = settings(address.port());
+        settings.credentials = None;
+
+        let error = send(&settings, &envelope, b"hello").unwrap_err();
+        assert!(!error.is_transient());
+        assert_eq!(
+            error.to_string(),
+            "MAIL FROM was rejected with 550: no such user"
+        );
+    }
+
+    #[test]
+    fn smtp_requires_starttls_when_configured() {
+        let address = next_addr();
+        let listener = TcpListener::bind(address).unwrap();
+        let _server = serve(listener, &["250 mx.example.com\r\n"]);
+
+        let to = vec!["ops@example.com".to_owned()];
+        let envelope = Envelope {
+            from: "vector@example.com",
+            to: &to,
+        };
+        let mut settings = settings(address.port());
+        settings.tls_mode = TlsMode::Starttls;
+
+        match send(&settings, &envelope, b"hello") {
+            Err(SmtpError::StartTlsUnsupported) => (),
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
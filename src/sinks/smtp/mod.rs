@@ -0,0 +1,314 @@
+use crate::{
+    event::Event,
+    internal_events::{SmtpEventRateLimited, SmtpEventSent, SmtpSendFailed},
+    sinks::util::{rate_limit::RateLimiter, StreamSink},
+    template::Template,
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::{pin_mut, stream::Stream, FutureExt, StreamExt, TryFutureExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{task::spawn_blocking, time::delay_for};
+
+use super::streaming_sink::{self, StreamingSink};
+
+mod client;
+
+pub use client::TlsMode;
+use client::{ClientSettings, Credentials, Envelope};
+
+const RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpSinkConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls_mode: TlsMode,
+    pub tls: Option<TlsOptions>,
+    pub auth: Option<SmtpAuth>,
+    pub hello_name: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: Template,
+    pub body: Option<Template>,
+    #[serde(default = "default_rate_limit_num")]
+    pub rate_limit_num: u64,
+    #[serde(default = "default_rate_limit_duration_secs")]
+    pub rate_limit_duration_secs: u64,
+    #[serde(default = "default_retry_attempts")]
+    pub retry_attempts: usize,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpAuth {
+    pub user: String,
+    pub password: String,
+}
+
+fn default_rate_limit_num() -> u64 {
+    10
+}
+
+fn default_rate_limit_duration_secs() -> u64 {
+    60
+}
+
+fn default_retry_attempts() -> usize {
+    3
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<SmtpSinkConfig>("smtp")
+}
+
+#[typetag::serde(name = "smtp")]
+impl SinkConfig for SmtpSinkConfig {
+    fn build(&self, mut cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
+        if self.to.is_empty() {
+            return Err("`to` must list at least one recipient".into());
+        }
+
+        let settings = Arc::new(ClientSettings {
+            host: self.host.clone(),
+            port: self.port.unwrap_or_else(|| self.tls_mode.default_port()),
+            tls_mode: self.tls_mode,
+            tls: TlsSettings::from_options(&self.tls)?,
+            credentials: self.auth.as_ref().map(|auth| Credentials {
+                user: auth.user.clone(),
+                password: auth.password.clone(),
+            }),
+            hello_name: self
+                .hello_name
+                .clone()
+                .or_else(hostname::get_hostname)
+                .unwrap_or_else(|| "localhost".into()),
+            timeout: Duration::from_secs(self.timeout_secs),
+        });
+
+        let sink = SmtpSink {
+            config: self.clone(),
+            settings: Arc::clone(&settings),
+            limiter: RateLimiter::new(
+                self.rate_limit_num,
+                Duration::from_secs(self.rate_limit_duration_secs),
+            ),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+
+        let healthcheck = healthcheck(settings).boxed().compat();
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "smtp"
+    }
+}
+
+async fn healthcheck(settings: Arc<ClientSettings>) -> crate::Result<()> {
+    spawn_blocking(move || client::check(&settings)).await??;
+    Ok(())
+}
+
+struct SmtpSink {
+    config: SmtpSinkConfig,
+    settings: Arc<ClientSettings>,
+    limiter: RateLimiter,
+}
+
+#[async_trait]
+impl StreamingSink for SmtpSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        pin_mut!(input);
+        while let Some(event) = input.next().await {
+            if !self.limiter.allow(Instant::now()) {
+                emit!(SmtpEventRateLimited);
+                continue;
+            }
+
+            let message = match build_message(&self.config, &event) {
+                Some(message) => Arc::new(message),
+                None => continue,
+            };
+
+            let mut attempt = 0;
+            loop {
+                let settings = Arc::clone(&self.settings);
+                let from = self.config.from.clone();
+                let to = self.config.to.clone();
+                let data = Arc::clone(&message);
+
+                let result = spawn_blocking(move || {
+                    let envelope = Envelope {
+                        from: &from,
+                        to: &to,
+                    };
+                    client::send(&settings, &envelope, &data)
+                })
+                .await?;
+
+                match result {
+                    Ok(()) => {
+                        emit!(SmtpEventSent {
+                            byte_size: message.len()
+                        });
+                        break;
+                    }
+                    Err(error) => {
+                        let will_retry =
+                            error.is_transient() && attempt < self.config.retry_attempts;
+                        emit!(SmtpSendFailed { error, will_retry });
+                        if !will_retry {
+                            break;
+                        }
+                        attempt += 1;
+                        delay_for(RETRY_BACKOFF * attempt as u32).await;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders an RFC 5322 message. The body is sent base64 encoded, which any
+/// server accepts regardless of the character set.
+fn build_message(config: &SmtpSinkConfig, event: &Event) -> Option<Vec<u8>> {
+    let subject = render(&config.subject, event)?;
+    let body = match &config.body {
+        Some(body) => render(body, event)?,
+        None => serde_json::to_string_pretty(event.as_log())
+            .map_err(|error| error!(message = "Unable to encode.", %error))
+            .ok()?,
+    };
+
+    let mut message = String::new();
+    let mut header = |name: &str, value: &str| {
+        message.push_str(name);
+        message.push_str(": ");
+        message.push_str(value);
+        message.push_str("\r\n");
+    };
+    header("Date", &Utc::now().to_rfc2822());
+    header("From", &config.from);
+    header("To", &config.to.join(", "));
+    header("Subject", &encode_header_value(&subject));
+    header("MIME-Version", "1.0");
+    header("Content-Type", "text/plain; charset=utf-8");
+    header("Content-Transfer-Encoding", "base64");
+    message.push_str("\r\n");
+
+    let body = base64::encode(&body);
+    for line in body.as_bytes().chunks(76) {
+        message.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        message.push_str("\r\n");
+    }
+
+    Some(message.into_bytes())
+}
+
+fn render(template: &Template, event: &Event) -> Option<String> {
+    template
+        .render_string(event)
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event. Dropping event.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()
+}
+
+/// Keeps the value on a single line, so a field can't inject headers, and
+/// uses an RFC 2047 encoded word for anything beyond printable ASCII.
+fn encode_header_value(value: &str) -> String {
+    let value = value.replace(|c| c == '\r' || c == '\n', " ");
+    if value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::encode(&value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    fn config() -> SmtpSinkConfig {
+        let (config, _, _) = load_sink::<SmtpSinkConfig>(
+            r#"
+            host = "smtp.example.com"
+            from = "vector@example.com"
+            to = ["ops@example.com", "oncall@example.com"]
+            subject = "[{{ level }}] {{ host }}"
+        "#,
+        )
+        .unwrap();
+        config
+    }
+
+    #[test]
+    fn smtp_builds_message() {
+        let mut event = Event::from("disk full");
+        event.as_mut_log().insert("level", "alert");
+        event.as_mut_log().insert("host", "db-1");
+
+        let mut config = config();
+        config.body = Some("{{ host }}: {{ message }}".into());
+        let message = String::from_utf8(build_message(&config, &event).unwrap()).unwrap();
+
+        assert!(message.contains("From: vector@example.com\r\n"));
+        assert!(message.contains("To: ops@example.com, oncall@example.com\r\n"));
+        assert!(message.contains("Subject: [alert] db-1\r\n"));
+        let body = message.split("\r\n\r\n").nth(1).unwrap().trim_end();
+        assert_eq!(base64::decode(body).unwrap(), b"db-1: disk full");
+    }
+
+    #[test]
+    fn smtp_drops_events_missing_subject_fields() {
+        let event = Event::from("disk full");
+        assert!(build_message(&config(), &event).is_none());
+    }
+
+    #[test]
+    fn smtp_encodes_header_values() {
+        assert_eq!(encode_header_value("disk full"), "disk full");
+        assert_eq!(
+            encode_header_value("a\r\nBcc: x@example.com"),
+            "a  Bcc: x@example.com"
+        );
+        assert_eq!(encode_header_value("Störung"), "=?UTF-8?B?U3TDtnJ1bmc=?=");
+    }
+
+    #[test]
+    fn smtp_defaults() {
+        let config = config();
+        assert_eq!(config.tls_mode, TlsMode::Starttls);
+        assert_eq!(config.tls_mode.default_port(), 587);
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{
+    event::Event,
+    region::RegionOrEndpoint,
+    sinks::{
+        aws_s3::S3Sink,
+        util::{
+            encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+            http::HttpClient,
+            retries::RetryLogic,
+            BatchBytesConfig, Buffer, Compression, PartitionBatchSink, PartitionBuffer,
+            PartitionInnerBuffer, ServiceBuilderExt, TowerRequestConfig,
+        },
+        Healthcheck, RouterSink,
+    },
+    template::Template,
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use chrono::Utc;
+use futures::{compat::Future01CompatExt, FutureExt, TryFutureExt};
+use futures01::{future, stream::iter_ok, Future, Poll, Sink};
+use http::{Method, StatusCode, Uri};
+use hyper::{Body, Request};
+use lazy_static::lazy_static;
+use rusoto_core::RusotoError;
+use rusoto_s3::{HeadBucketRequest, PutObjectError, PutObjectRequest, S3Client, S3};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use std::{convert::TryInto, path::PathBuf};
+use tower::{Service, ServiceBuilder};
+use tracing::field;
+use uuid::Uuid;
+
+mod jwt;
+
+use jwt::KeyPair;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SnowflakeSinkConfig {
+    pub account: String,
+    pub user: String,
+    pub private_key_path: PathBuf,
+    pub private_key_passphrase: Option<String>,
+    /// Overrides the Snowpipe REST API endpoint derived from the account.
+    pub snowpipe_endpoint: Option<String>,
+    /// The fully qualified name of the pipe that loads the files.
+    pub pipe: String,
+    /// The bucket of the external stage the pipe copies from.
+    pub bucket: String,
+    /// The path within the bucket that the stage URL points to.
+    pub stage_prefix: Option<String>,
+    pub key_prefix: Option<String>,
+    #[serde(flatten)]
+    pub region: RegionOrEndpoint,
+    pub assume_role: Option<String>,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub encoding: EncodingConfigWithDefault<Encoding>,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    #[serde(default)]
+    pub batch: BatchBytesConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum Encoding {
+    #[derivative(Default)]
+    Ndjson,
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(10),
+        rate_limit_num: Some(50),
+        ..Default::default()
+    };
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<SnowflakeSinkConfig>("snowflake")
+}
+
+#[typetag::serde(name = "snowflake")]
+impl SinkConfig for SnowflakeSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let keys = KeyPair::load(
+            &self.account,
+            &self.user,
+            &self.private_key_path,
+            self.private_key_passphrase.as_deref(),
+        )?;
+        let endpoint = self.endpoint();
+        endpoint.parse::<Uri>().context(super::UriParseError)?;
+
+        let s3 = S3Sink::create_client(
+            self.region.clone().try_into()?,
+            self.assume_role.clone(),
+            cx.resolver(),
+        )?;
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let http = HttpClient::new(cx.resolver(), tls)?;
+
+        let service = SnowflakeService {
+            s3,
+            http,
+            endpoint,
+            keys,
+        };
+
+        let healthcheck = healthcheck(
+            service.clone(),
+            self.bucket.clone(),
+            Template::from(self.pipe.as_str()),
+        )
+        .boxed()
+        .compat();
+
+        let sink = self.sink(service, cx)?;
+
+        Ok((sink, Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "snowflake"
+    }
+}
+
+impl SnowflakeSinkConfig {
+    fn endpoint(&self) -> String {
+        match &self.snowpipe_endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').into(),
+            None => format!("https://{}.snowflakecomputing.com", self.account),
+        }
+    }
+
+    fn sink(&self, service: SnowflakeService, cx: SinkContext) -> crate::Result<RouterSink> {
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+        let batch = self.batch.unwrap_or(bytesize::mib(10u64), 300);
+
+        let stage = Stage {
+            bucket: self.bucket.clone(),
+            prefix: self.stage_prefix.clone().unwrap_or_default(),
+            compression: self.compression,
+        };
+        let svc = ServiceBuilder::new()
+            .map(move |req| stage.build_request(req))
+            .settings(request, SnowflakeRetryLogic)
+            .service(service);
+
+        let pipe = Template::from(self.pipe.as_str());
+        let key_prefix = Template::from(self.key_prefix.as_deref().unwrap_or("date=%F/"));
+        let encoding = self.encoding.clone();
+
+        let buffer = PartitionBuffer::new(Buffer::new(self.compression));
+        let sink = PartitionBatchSink::new(svc, buffer, batch, cx.acker())
+            .with_flat_map(move |e| iter_ok(encode_event(e, &pipe, &key_prefix, &encoding)))
+            .sink_map_err(|error| error!("Sink failed to flush: {}", error));
+
+        Ok(Box::new(sink))
+    }
+}
+
+/// Events are batched per pipe, as every pipe loads into its own table,
+/// and per key prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PartitionKey {
+    pipe: String,
+    key_prefix: String,
+}
+
+fn encode_event(
+    mut event: Event,
+    pipe: &Template,
+    key_prefix: &Template,
+    encoding: &EncodingConfigWithDefault<Encoding>,
+) -> Option<PartitionInnerBuffer<Vec<u8>, PartitionKey>> {
+    let key = pipe
+        .render_string(&event)
+        .and_then(|pipe| {
+            key_prefix
+                .render_string(&event)
+                .map(|key_prefix| PartitionKey { pipe, key_prefix })
+        })
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event. Dropping event.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            );
+        })
+        .ok()?;
+
+    encoding.apply_rules(&mut event);
+
+    let mut bytes = serde_json::to_vec(&event.into_log())
+        .expect("Failed to encode event as json, this is a bug!");
+    bytes.push(b'\n');
+
+    Some(PartitionInnerBuffer::new(bytes, key))
+}
+
+struct Stage {
+    bucket: String,
+    prefix: String,
+    compression: Compression,
+}
+
+impl Stage {
+    fn build_request(&self, req: PartitionInnerBuffer<Vec<u8>, PartitionKey>) -> StagedFile {
+        let (body, key) = req.into_parts();
+
+        // Snowpipe skips files it has loaded before, so names must be unique.
+        let extension = match self.compression {
+            Compression::None => "json",
+            Compression::Gzip => "json.gz",
+        };
+        let path = format!(
+            "{}{}-{}.{}",
+            key.key_prefix,
+            Utc::now().format("%s"),
+            Uuid::new_v4().to_hyphenated(),
+            extension
+        );
+
+        debug!(
+            message = "staging events.",
+            bytes = &field::debug(body.len()),
+            pipe = &field::debug(&key.pipe),
+            path = &field::debug(&path)
+        );
+
+        StagedFile {
+            body,
+            bucket: self.bucket.clone(),
+            key: format!("{}{}", self.prefix, path),
+            path,
+            pipe: key.pipe,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StagedFile {
+    body: Vec<u8>,
+    bucket: String,
+    /// The object key within the bucket.
+    key: String,
+    /// The path relative to the stage, which is how Snowpipe names files.
+    path: String,
+    pipe: String,
+}
+
+#[derive(Debug, Snafu)]
+enum SnowflakeError {
+    #[snafu(display("Failed to stage file: {}", source))]
+    Stage { source: RusotoError<PutObjectError> },
+    #[snafu(display("Failed to sign token: {}", source))]
+    Token { source: openssl::error::ErrorStack },
+    #[snafu(display("Invalid ingest URI: {}", source))]
+    IngestUri { source: http::Error },
+    #[snafu(display("Failed to notify Snowpipe: {}", source))]
+    Ingest { source: hyper::Error },
+    #[snafu(display("Snowpipe responded with {}", status))]
+    IngestStatus { status: StatusCode },
+}
+
+#[derive(Clone)]
+struct SnowflakeService {
+    s3: S3Client,
+    http: HttpClient,
+    endpoint: String,
+    keys: KeyPair,
+}
+
+impl SnowflakeService {
+    fn pipe_request(
+        &self,
+        pipe: &str,
+        path: &str,
+        body: Body,
+    ) -> Result<Request<Body>, SnowflakeError> {
+        let token = self.keys.token().context(Token)?;
+        let method = if path == "insertFiles" {
+            Method::POST
+        } else {
+            Method::GET
+        };
+
+        Request::builder()
+            .method(method)
+            .uri(format!(
+                "{}/v1/data/pipes/{}/{}?requestId={}",
+                self.endpoint,
+                pipe,
+                path,
+                Uuid::new_v4().to_hyphenated()
+            ))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("X-Snowflake-Authorization-Token-Type", "KEYPAIR_JWT")
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json")
+            .body(body)
+            .context(IngestUri)
+    }
+}
+
+impl Service<StagedFile> for SnowflakeService {
+    type Response = ();
+    type Error = SnowflakeError;
+    type Future = Box<dyn Future<Item = (), Error = SnowflakeError> + Send + 'static>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(().into())
+    }
+
+    fn call(&mut self, file: StagedFile) -> Self::Future {
+        let body = json!({ "files": [{ "path": file.path }] }).to_string();
+        let ingest = match self.pipe_request(&file.pipe, "insertFiles", Body::from(body)) {
+            Ok(request) => request,
+            Err(error) => return Box::new(future::err(error)),
+        };
+
+        let upload = self
+            .s3
+            .put_object(PutObjectRequest {
+                body: Some(file.body.into()),
+                bucket: file.bucket,
+                key: file.key,
+                ..Default::default()
+            })
+            .map_err(|source| SnowflakeError::Stage { source });
+
+        // The file is only loaded once Snowpipe is told about it, so the
+        // batch succeeds when both steps do. Retries upload it again under
+        // the same name.
+        let mut http = self.http.clone();
+        Box::new(
+            upload
+                .and_then(move |_| {
+                    http.call(ingest)
+                        .map_err(|source| SnowflakeError::Ingest { source })
+                })
+                .and_then(|response| {
+                    let status = response.status();
+                    if status.is_success() {
+                        Ok(())
+                    } else {
+                        Err(SnowflakeError::IngestStatus { status })
+                    }
+                }),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SnowflakeRetryLogic;
+
+impl RetryLogic for SnowflakeRetryLogic {
+    type Error = SnowflakeError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        match error {
+            SnowflakeError::Stage {
+                source: RusotoError::HttpDispatch(_),
+            } => true,
+            SnowflakeError::Stage {
+                source: RusotoError::Unknown(response),
+            } => response.status.is_server_error(),
+            SnowflakeError::Ingest { .. } => true,
+            SnowflakeError::IngestStatus { status } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum HealthcheckError {
+    #[snafu(display("Unknown stage bucket: {:?}", bucket))]
+    UnknownBucket { bucket: String },
+    #[snafu(display("Invalid credentials for Snowpipe"))]
+    InvalidCredentials,
+    #[snafu(display("Unknown pipe: {:?}", pipe))]
+    UnknownPipe { pipe: String },
+}
+
+/// Checks the stage bucket, and the pipe if its name doesn't depend on
+/// the events.
+async fn healthcheck(
+    mut service: SnowflakeService,
+    bucket: String,
+    pipe: Template,
+) -> crate::Result<()> {
+    let response = service
+        .s3
+        .head_bucket(HeadBucketRequest {
+            bucket: bucket.clone(),
+        })
+        .compat()
+        .await;
+    match response {
+        Ok(_) => {}
+        Err(RusotoError::Unknown(response)) if response.status == StatusCode::NOT_FOUND => {
+            return Err(HealthcheckError::UnknownBucket { bucket }.into())
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    if pipe.is_dynamic() {
+        return Ok(());
+    }
+    let pipe = String::from_utf8_lossy(pipe.get_ref()).into_owned();
+    let request = service.pipe_request(&pipe, "insertReport", Body::empty())?;
+    let response = service.http.send(request).await?;
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err(HealthcheckError::InvalidCredentials.into())
+        }
+        StatusCode::NOT_FOUND => Err(HealthcheckError::UnknownPipe { pipe }.into()),
+        status => Err(super::HealthcheckError::UnexpectedStatus { status }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::test::load_sink;
+
+    #[test]
+    fn snowflake_partitions_by_pipe_and_prefix() {
+        let mut event = Event::from("hello");
+        event.as_mut_log().insert("table", "nginx");
+
+        let pipe = Template::from("logs.public.{{ table }}_pipe");
+        let key_prefix = Template::from("{{ table }}/");
+        let encoded = encode_event(event, &pipe, &key_prefix, &Default::default()).unwrap();
+        let (bytes, key) = encoded.into_parts();
+
+        assert_eq!(
+            key,
+            PartitionKey {
+                pipe: "logs.public.nginx_pipe".into(),
+                key_prefix: "nginx/".into(),
+            }
+        );
+        assert_eq!(bytes.last(), Some(&b'\n'));
+        let log: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(log["message"], "hello");
+
+        let missing = encode_event(
+            Event::from("hello"),
+            &pipe,
+            &key_prefix,
+            &Default::default(),
+        );
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn snowflake_stages_below_prefix() {
+        let stage = Stage {
+            bucket: "landing".into(),
+            prefix: "snowflake/".into(),
+            compression: Compression::Gzip,
+        };
+        let key = PartitionKey {
+            pipe: "logs.public.pipe".into(),
+            key_prefix: "date=2020-06-01/".into(),
+        };
+        let file = stage.build_request(PartitionInnerBuffer::new(vec![1, 2, 3], key));
+
+        assert_eq!(file.bucket, "landing");
+        assert_eq!(file.pipe, "logs.public.pipe");
+        assert!(file.path.starts_with("date=2020-06-01/"));
+        assert!(file.path.ends_with(".json.gz"));
+        assert_eq!(file.key, format!("snowflake/{}", file.path));
+    }
+
+    #[test]
+    fn snowflake_endpoint() {
+        let (config, _, _) = load_sink::<SnowflakeSinkConfig>(
+            r#"
+            account = "xy12345.us-east-1"
+            user = "vector"
+            private_key_path = "/etc/vector/snowflake.p8"
+            pipe = "logs.public.pipe"
+            bucket = "landing"
+            region = "us-east-1"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.endpoint(),
+            "https://xy12345.us-east-1.snowflakecomputing.com"
+        );
+    }
+}
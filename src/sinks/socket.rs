@@ -22,6 +22,8 @@ pub enum Mode {
     Udp(UdpSinkConfig),
     #[cfg(unix)]
     Unix(UnixSinkConfig),
+    #[cfg(unix)]
+    UnixDatagram(UnixSinkConfig),
 }
 
 inventory::submit! {
@@ -71,6 +73,8 @@ impl SinkConfig for SocketSinkConfig {
             Mode::Udp(config) => config.build(cx),
             #[cfg(unix)]
             Mode::Unix(config) => config.build(cx),
+            #[cfg(unix)]
+            Mode::UnixDatagram(config) => config.build_datagram(cx),
         }
     }
 
@@ -4,23 +4,36 @@ use crate::{
     internal_events::{SplunkEventEncodeError, SplunkEventSent},
     sinks::util::{
         encoding::{EncodingConfigWithDefault, EncodingConfiguration},
-        http2::{BatchedHttpSink, HttpClient, HttpSink},
+        http2::{
+            BatchedHttpSink, Error as HttpError, HttpBatchService, HttpClient, HttpRetryLogic,
+            HttpSink, Response as HttpResponse,
+        },
         service2::TowerRequestConfig,
         BatchBytesConfig, Buffer, Compression,
     },
     tls::{TlsOptions, TlsSettings},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
-use futures::{FutureExt, TryFutureExt};
-use futures01::Sink;
+use bytes05::Bytes;
+use futures::{future::BoxFuture, FutureExt, TryFutureExt};
+use futures01::{stream::iter_ok, Sink};
 use http02::{Request, StatusCode, Uri};
 use hyper13::Body;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use snafu::{ResultExt, Snafu};
-use std::convert::TryFrom;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
 use string_cache::DefaultAtom as Atom;
+use tokio::time::delay_for;
+use tower03::Service;
+use uuid::Uuid;
 
 #[derive(Debug, Snafu)]
 pub enum BuildError {
@@ -50,6 +63,44 @@ pub struct HecSinkConfig {
     #[serde(default)]
     pub request: TowerRequestConfig,
     pub tls: Option<TlsOptions>,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub acknowledgements: HecAcknowledgementsConfig,
+}
+
+/// Indexer acknowledgements, which hold each batch back until Splunk
+/// confirms its events were indexed, and retry it when that is not
+/// confirmed in time.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HecAcknowledgementsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_query_interval_secs")]
+    pub query_interval_secs: u64,
+    /// The number of queries after which the batch is retried.
+    #[serde(default = "default_retry_limit")]
+    pub retry_limit: u32,
+}
+
+impl Default for HecAcknowledgementsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            query_interval_secs: default_query_interval_secs(),
+            retry_limit: default_retry_limit(),
+        }
+    }
+}
+
+fn default_query_interval_secs() -> u64 {
+    10
+}
+
+fn default_retry_limit() -> u32 {
+    30
 }
 
 lazy_static! {
@@ -86,6 +137,25 @@ impl SinkConfig for HecSinkConfig {
         let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
         let tls_settings = TlsSettings::from_options(&self.tls)?;
 
+        let healthcheck = healthcheck(self.clone(), cx.resolver()).boxed().compat();
+
+        if self.acknowledgements.enabled {
+            let service = HecAckService::new(self.clone(), tls_settings, &cx)?;
+            let config = self.clone();
+            let sink = request
+                .batch_sink(
+                    HttpRetryLogic,
+                    service,
+                    Buffer::new(self.compression),
+                    batch,
+                    cx.acker(),
+                )
+                .sink_map_err(|e| error!("Fatal splunk_hec sink error: {}", e))
+                .with_flat_map(move |event| iter_ok(config.encode_event(event)));
+
+            return Ok((Box::new(sink), Box::new(healthcheck)));
+        }
+
         let sink = BatchedHttpSink::new(
             self.clone(),
             Buffer::new(self.compression),
@@ -96,8 +166,6 @@ impl SinkConfig for HecSinkConfig {
         )
         .sink_map_err(|e| error!("Fatal splunk_hec sink error: {}", e));
 
-        let healthcheck = healthcheck(self.clone(), cx.resolver()).boxed().compat();
-
         Ok((Box::new(sink), Box::new(healthcheck)))
     }
 
@@ -192,6 +260,147 @@ impl HttpSink for HecSinkConfig {
     }
 }
 
+const CHANNEL_HEADER: &str = "X-Splunk-Request-Channel";
+
+#[derive(Debug, Snafu)]
+enum AckError {
+    #[snafu(display("Unexpected status querying acknowledgements: {}", status))]
+    UnexpectedStatus { status: StatusCode },
+}
+
+#[derive(Deserialize, Debug)]
+struct HecAckId {
+    #[serde(rename = "ackId")]
+    ack_id: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct HecAckStatus {
+    acks: HashMap<u64, bool>,
+}
+
+/// Sends the batches on a channel of its own and resolves each once
+/// Splunk has indexed it, so the batch is only acknowledged then. When
+/// indexing is not confirmed within the retry limit, the response is
+/// replaced by a `503` for the batch to be retried.
+#[derive(Clone)]
+struct HecAckService {
+    inner: HttpBatchService<Vec<u8>>,
+    client: HttpClient,
+    uri: Uri,
+    token: String,
+    channel: String,
+    query_interval: Duration,
+    retry_limit: u32,
+}
+
+impl HecAckService {
+    fn new(
+        config: HecSinkConfig,
+        tls_settings: TlsSettings,
+        cx: &SinkContext,
+    ) -> crate::Result<Self> {
+        let uri =
+            build_uri(&config.host, "/services/collector/ack").context(super::UriParseError2)?;
+        let client = HttpClient::new(cx.resolver(), tls_settings.clone())?;
+
+        let channel = Uuid::new_v4().to_hyphenated().to_string();
+        let header = http02::HeaderValue::from_str(&channel)?;
+        let token = config.token.clone();
+        let acknowledgements = config.acknowledgements.clone();
+        let config = Arc::new(config);
+        let inner = HttpBatchService::new(cx.resolver(), tls_settings, move |body| {
+            let mut request = config.build_request(body);
+            request.headers_mut().insert(CHANNEL_HEADER, header.clone());
+            request
+        })
+        .with_capture(cx.capture())
+        .with_dry_run(cx.dry_run());
+
+        Ok(Self {
+            inner,
+            client,
+            uri,
+            token,
+            channel,
+            query_interval: Duration::from_secs(acknowledgements.query_interval_secs),
+            retry_limit: acknowledgements.retry_limit,
+        })
+    }
+
+    async fn wait_for_ack(
+        mut self,
+        ack_id: u64,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, HttpError> {
+        for _ in 0..self.retry_limit {
+            delay_for(self.query_interval).await;
+            match self.query_ack(ack_id).await {
+                Ok(true) => return Ok(response),
+                Ok(false) => (),
+                Err(error) => warn!(
+                    message = "failed querying indexer acknowledgement.",
+                    %error,
+                    rate_limit_secs = 30
+                ),
+            }
+        }
+
+        let mut response = HttpResponse::new(Bytes::from(format!(
+            "indexing of the events was not acknowledged after {} queries",
+            self.retry_limit
+        )));
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        Ok(response)
+    }
+
+    async fn query_ack(&mut self, ack_id: u64) -> crate::Result<bool> {
+        let request = Request::post(self.uri.clone())
+            .header("Content-Type", "application/json")
+            .header("Authorization", format!("Splunk {}", self.token))
+            .header(CHANNEL_HEADER, self.channel.as_str())
+            .body(Body::from(json!({ "acks": [ack_id] }).to_string()))
+            .unwrap();
+
+        let response = self.client.send(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(AckError::UnexpectedStatus { status }.into());
+        }
+
+        let body = hyper13::body::to_bytes(response.into_body()).await?;
+        let acks = serde_json::from_slice::<HecAckStatus>(&body)?.acks;
+        Ok(acks.get(&ack_id).cloned().unwrap_or(false))
+    }
+}
+
+impl Service<Vec<u8>> for HecAckService {
+    type Response = HttpResponse;
+    type Error = HttpError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, body: Vec<u8>) -> Self::Future {
+        let response = self.inner.call(body);
+        let service = self.clone();
+
+        Box::pin(async move {
+            let response = response.await?;
+            if !response.status().is_success() {
+                return Ok(response);
+            }
+            // Dry runs and servers with acknowledgements disabled return no id.
+            match serde_json::from_slice::<HecAckId>(response.body()) {
+                Ok(HecAckId { ack_id }) => service.wait_for_ack(ack_id, response).await,
+                Err(_) => Ok(response),
+            }
+        })
+    }
+}
+
 #[derive(Debug, Snafu)]
 enum HealthcheckError {
     #[snafu(display("Invalid HEC token"))]
@@ -353,6 +562,33 @@ mod tests {
         assert!(uri.is_ok());
         assert_eq!(format!("{}", uri.unwrap()), "http://test.com/a");
     }
+
+    #[test]
+    fn splunk_parse_acknowledgements() {
+        let config: HecSinkConfig = toml::from_str(
+            r#"
+            host = "http://localhost:8088"
+            token = "token"
+            acknowledgements.enabled = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.acknowledgements,
+            HecAcknowledgementsConfig {
+                enabled: true,
+                ..Default::default()
+            }
+        );
+
+        let id: HecAckId =
+            serde_json::from_str(r#"{"text":"Success","code":0,"ackId":7}"#).unwrap();
+        assert_eq!(id.ack_id, 7);
+        let status: HecAckStatus =
+            serde_json::from_str(r#"{"acks":{"7":true,"8":false}}"#).unwrap();
+        assert_eq!(status.acks.get(&7), Some(&true));
+        assert_eq!(status.acks.get(&8), Some(&false));
+    }
 }
 
 #[cfg(test)]
@@ -1,15 +1,32 @@
+#[cfg(unix)]
+use crate::sinks::util::unix::{unix_healthcheck, UnixSink};
 use crate::{
     buffers::Acker,
     event::metric::{MetricKind, MetricValue},
     event::Event,
-    sinks::util::{BatchBytesConfig, BatchSink, Buffer, Compression},
+    sinks::util::{
+        tcp::{tcp_healthcheck, TcpSink},
+        Batch, BatchBytesConfig, BatchSink, Buffer, Compression, MetricBuffer, SinkBuildError,
+        StreamSink,
+    },
+    tls::{MaybeTlsSettings, TlsConfig},
     topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
 };
-use futures01::{future, stream::iter_ok, Future, Poll, Sink};
+use bytes::Bytes;
+use futures01::{
+    future, stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend,
+};
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use std::collections::BTreeMap;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::collections::{BTreeMap, VecDeque};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::{
+    mem,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+use tokio01::timer::Delay;
 use tower::{Service, ServiceBuilder};
 
 #[derive(Debug, Snafu)]
@@ -43,13 +60,46 @@ impl Client {
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
-#[serde(deny_unknown_fields)]
+// TODO: add back when serde-rs/serde#1358 is addressed
+// #[serde(deny_unknown_fields)]
 pub struct StatsdSinkConfig {
     pub namespace: String,
-    #[serde(default = "default_address")]
-    pub address: SocketAddr,
+    #[serde(flatten)]
+    pub mode: Mode,
     #[serde(default)]
     pub batch: BatchBytesConfig,
+    /// Metrics are added up over the window, and sent once at its end.
+    pub aggregation_window_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Mode {
+    Tcp(TcpConfig),
+    Udp(UdpConfig),
+    #[cfg(unix)]
+    Unix(UnixConfig),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TcpConfig {
+    pub address: String,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UdpConfig {
+    #[serde(default = "default_address")]
+    pub address: SocketAddr,
+}
+
+#[cfg(unix)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UnixConfig {
+    pub path: PathBuf,
 }
 
 pub fn default_address() -> SocketAddr {
@@ -63,8 +113,46 @@ inventory::submit! {
 #[typetag::serde(name = "statsd")]
 impl SinkConfig for StatsdSinkConfig {
     fn build(&self, cx: SinkContext) -> crate::Result<(super::RouterSink, super::Healthcheck)> {
-        let sink = StatsdSvc::new(self.clone(), cx.acker())?;
-        let healthcheck = StatsdSvc::healthcheck(self.clone())?;
+        // Events added up are acked once the window they fall in ends.
+        let acker = match self.aggregation_window_secs {
+            Some(_) => Acker::Null,
+            None => cx.acker(),
+        };
+        let namespace = self.namespace.clone();
+
+        let (sink, healthcheck) = match &self.mode {
+            Mode::Tcp(config) => {
+                let uri = config.address.parse::<http::Uri>()?;
+                let host = uri.host().ok_or(SinkBuildError::MissingHost)?.to_string();
+                let port = uri.port_u16().ok_or(SinkBuildError::MissingPort)?;
+                let tls = MaybeTlsSettings::from_config(&config.tls, false)?;
+
+                let tcp = TcpSink::new(host.clone(), port, cx.resolver(), tls);
+                let healthcheck = tcp_healthcheck(host, port, cx.resolver());
+                (stream_sink(tcp, acker, namespace), healthcheck)
+            }
+            Mode::Udp(config) => {
+                let sink = StatsdSvc::new(config.address, &self.batch, namespace, acker)?;
+                let healthcheck: super::Healthcheck = Box::new(future::ok(()));
+                (sink, healthcheck)
+            }
+            #[cfg(unix)]
+            Mode::Unix(config) => {
+                let unix = UnixSink::new(config.path.clone());
+                let healthcheck = unix_healthcheck(config.path.clone());
+                (stream_sink(unix, acker, namespace), healthcheck)
+            }
+        };
+
+        let sink: super::RouterSink = match self.aggregation_window_secs {
+            Some(secs) => Box::new(AggregatedSink::new(
+                sink,
+                Duration::from_secs(secs),
+                cx.acker(),
+            )),
+            None => sink,
+        };
+
         Ok((sink, healthcheck))
     }
 
@@ -78,16 +166,20 @@ impl SinkConfig for StatsdSinkConfig {
 }
 
 impl StatsdSvc {
-    pub fn new(config: StatsdSinkConfig, acker: Acker) -> crate::Result<super::RouterSink> {
+    pub fn new(
+        address: SocketAddr,
+        batch: &BatchBytesConfig,
+        namespace: String,
+        acker: Acker,
+    ) -> crate::Result<super::RouterSink> {
         // 1432 bytes is a recommended packet size to fit into MTU
         // https://github.com/statsd/statsd/blob/master/docs/metric_types.md#multi-metric-packets
         // However we need to leave some space for +1 extra trailing event in the buffer.
         // Also one might keep an eye on server side limitations, like
         // mentioned here https://github.com/DataDog/dd-agent/issues/2638
-        let batch = config.batch.unwrap_or(1300, 1);
-        let namespace = config.namespace.clone();
+        let batch = batch.unwrap_or(1300, 1);
 
-        let client = Client::new(config.address)?;
+        let client = Client::new(address)?;
         let service = StatsdSvc { client };
 
         let svc = ServiceBuilder::new().service(service);
@@ -98,9 +190,100 @@ impl StatsdSvc {
 
         Ok(Box::new(sink))
     }
+}
+
+/// Streams are written to line by line, with no batching.
+fn stream_sink<S>(sink: S, acker: Acker, namespace: String) -> super::RouterSink
+where
+    S: Sink<SinkItem = Bytes, SinkError = ()> + Send + 'static,
+{
+    let sink = StreamSink::new(sink, acker);
+    Box::new(
+        sink.with_flat_map(move |event| iter_ok(encode_event(event, &namespace).map(Bytes::from))),
+    )
+}
+
+/// Adds up the metrics received over a window, and sends them on at its end,
+/// cutting down on the packets a statsd server has to handle.
+struct AggregatedSink<S> {
+    inner: S,
+    buffer: MetricBuffer,
+    /// The events added up in the window.
+    events: usize,
+    window: Duration,
+    window_end: Delay,
+    pending: VecDeque<Event>,
+    acker: Acker,
+}
+
+impl<S> AggregatedSink<S>
+where
+    S: Sink<SinkItem = Event, SinkError = ()>,
+{
+    fn new(inner: S, window: Duration, acker: Acker) -> Self {
+        Self {
+            inner,
+            buffer: MetricBuffer::new(),
+            events: 0,
+            window,
+            window_end: Delay::new(Instant::now() + window),
+            pending: VecDeque::new(),
+            acker,
+        }
+    }
+
+    fn end_window(&mut self) {
+        let fresh = self.buffer.fresh();
+        let buffer = mem::replace(&mut self.buffer, fresh);
+        self.pending
+            .extend(buffer.finish().into_iter().map(Event::Metric));
+        self.acker.ack(self.events);
+        self.events = 0;
+    }
 
-    fn healthcheck(_config: StatsdSinkConfig) -> crate::Result<super::Healthcheck> {
-        Ok(Box::new(future::ok(())))
+    fn send_pending(&mut self) -> Poll<(), ()> {
+        while let Some(event) = self.pending.pop_front() {
+            if let AsyncSink::NotReady(event) = self.inner.start_send(event)? {
+                self.pending.push_front(event);
+                return Ok(Async::NotReady);
+            }
+        }
+        self.inner.poll_complete()
+    }
+}
+
+impl<S> Sink for AggregatedSink<S>
+where
+    S: Sink<SinkItem = Event, SinkError = ()>,
+{
+    type SinkItem = Event;
+    type SinkError = ();
+
+    fn start_send(&mut self, event: Event) -> StartSend<Event, ()> {
+        self.buffer.push(event);
+        self.events += 1;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), ()> {
+        while self.window_end.poll().expect("timer error").is_ready() {
+            self.end_window();
+            self.window_end.reset(Instant::now() + self.window);
+        }
+        try_ready!(self.send_pending());
+        if self.events == 0 {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn close(&mut self) -> Poll<(), ()> {
+        if self.events > 0 {
+            self.end_window();
+        }
+        try_ready!(self.send_pending());
+        self.inner.close()
     }
 }
 
@@ -322,15 +505,19 @@ mod test {
 
         let config = StatsdSinkConfig {
             namespace: "vector".into(),
-            address: default_address(),
+            mode: Mode::Udp(UdpConfig {
+                address: default_address(),
+            }),
             batch: BatchBytesConfig {
                 max_size: Some(512),
                 timeout_secs: Some(1),
             },
+            aggregation_window_secs: None,
         };
 
         let mut rt = runtime();
-        let sink = StatsdSvc::new(config, Acker::Null).unwrap();
+        let context = SinkContext::new_test(rt.executor());
+        let (sink, _healthcheck) = config.build(context).unwrap();
 
         let mut events = Vec::new();
         let event = Event::Metric(Metric {
@@ -390,4 +577,37 @@ mod test {
             Bytes::from("vector.counter:1.5|c|#empty_tag:,normal_tag:value,true_tag\nvector.histogram:2|h|@0.01")
         );
     }
+
+    #[test]
+    fn aggregates_metrics_over_window() {
+        let counter = |value| {
+            Event::Metric(Metric {
+                name: "counter".to_owned(),
+                timestamp: None,
+                tags: Some(tags()),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value },
+            })
+        };
+        let gauge = |value| {
+            Event::Metric(Metric {
+                name: "gauge".to_owned(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value },
+            })
+        };
+        let events = vec![counter(1.0), gauge(5.0), counter(2.5), gauge(3.0)];
+
+        let mut rt = runtime();
+        let sink = AggregatedSink::new(Vec::new(), Duration::from_secs(60), Acker::Null);
+        let (sink, _) = rt.block_on(sink.send_all(stream::iter_ok(events))).unwrap();
+
+        let mut sent: Vec<_> = sink.inner.into_iter().map(Event::into_metric).collect();
+        sent.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].value, MetricValue::Counter { value: 3.5 });
+        assert_eq!(sent[1].value, MetricValue::Gauge { value: 3.0 });
+    }
 }
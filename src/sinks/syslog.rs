@@ -0,0 +1,514 @@
+use crate::{
+    event::{log_schema, Event, Value},
+    sinks::util::{
+        encoding::{EncodingConfigWithDefault, EncodingConfiguration},
+        tcp::{tcp_healthcheck, TcpSink},
+        udp::UdpSink,
+        SinkBuildError, StreamSink,
+    },
+    sinks::{Healthcheck, RouterSink},
+    template::Template,
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes::Bytes;
+use chrono::Utc;
+use futures01::{future, stream::iter_ok, Sink};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Deserialize, Serialize, Debug)]
+// TODO: add back when serde-rs/serde#1358 is addressed
+// #[serde(deny_unknown_fields)]
+pub struct SyslogSinkConfig {
+    #[serde(flatten)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub format: Format,
+    #[serde(default)]
+    pub facility: Facility,
+    #[serde(default)]
+    pub severity: Severity,
+    /// The field holding the severity of each event, as a keyword or a
+    /// number, instead of `severity`.
+    pub severity_key: Option<String>,
+    #[serde(default = "default_app_name")]
+    pub app_name: Template,
+    pub proc_id: Option<Template>,
+    pub msg_id: Option<Template>,
+    /// The field holding the structured data of each event, a map of SD-IDs
+    /// to maps of parameters.
+    pub structured_data_key: Option<String>,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub encoding: EncodingConfigWithDefault<Encoding>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Mode {
+    Tcp {
+        address: String,
+        tls: Option<TlsConfig>,
+        #[serde(default)]
+        framing: Framing,
+    },
+    Udp {
+        address: String,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// Each message is prefixed by its length, as specified by RFC 6587.
+    OctetCounting,
+    /// Each message is terminated by a newline, which messages can't contain.
+    NonTransparent,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::OctetCounting
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    Rfc5424,
+    Rfc3164,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Rfc5424
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// The message field.
+    Text,
+    /// The whole event as JSON.
+    Json,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Text
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Facility {
+    Kern,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Ntp,
+    Security,
+    Console,
+    SolarisCron,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl Default for Facility {
+    fn default() -> Self {
+        Facility::User
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Informational,
+    Debug,
+}
+
+impl Default for Severity {
+    fn default() -> Self {
+        Severity::Informational
+    }
+}
+
+impl Severity {
+    const ALL: [Severity; 8] = [
+        Severity::Emergency,
+        Severity::Alert,
+        Severity::Critical,
+        Severity::Error,
+        Severity::Warning,
+        Severity::Notice,
+        Severity::Informational,
+        Severity::Debug,
+    ];
+
+    /// Parses the keywords of RFC 5424 and their usual abbreviations, or
+    /// numbers.
+    fn parse(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(number) => Self::ALL.get(*number as usize).copied(),
+            value => match value.to_string_lossy().to_lowercase().as_str() {
+                "emerg" | "emergency" | "panic" => Some(Severity::Emergency),
+                "alert" => Some(Severity::Alert),
+                "crit" | "critical" => Some(Severity::Critical),
+                "err" | "error" => Some(Severity::Error),
+                "warn" | "warning" => Some(Severity::Warning),
+                "notice" => Some(Severity::Notice),
+                "info" | "informational" => Some(Severity::Informational),
+                "debug" | "trace" => Some(Severity::Debug),
+                number => number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| Self::ALL.get(number).copied()),
+            },
+        }
+    }
+}
+
+fn default_app_name() -> Template {
+    "vector".into()
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<SyslogSinkConfig>("syslog")
+}
+
+#[typetag::serde(name = "syslog")]
+impl SinkConfig for SyslogSinkConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let encoder = Encoder {
+            format: self.format,
+            framing: match &self.mode {
+                Mode::Tcp { framing, .. } => Some(*framing),
+                Mode::Udp { .. } => None,
+            },
+            facility: self.facility,
+            severity: self.severity,
+            severity_key: self.severity_key.as_ref().map(|key| key.as_str().into()),
+            app_name: self.app_name.clone(),
+            proc_id: self.proc_id.clone(),
+            msg_id: self.msg_id.clone(),
+            structured_data_key: self
+                .structured_data_key
+                .as_ref()
+                .map(|key| key.as_str().into()),
+            encoding: self.encoding.clone(),
+            local_hostname: hostname::get_hostname(),
+        };
+
+        match &self.mode {
+            Mode::Tcp { address, tls, .. } => {
+                let (host, port) = parse_address(address)?;
+                let tls = MaybeTlsSettings::from_config(tls, false)?;
+                let tcp = TcpSink::new(host.clone(), port, cx.resolver(), tls);
+                let sink = StreamSink::new(tcp, cx.acker())
+                    .with_flat_map(move |event| iter_ok(Some(encoder.encode(event))));
+                let healthcheck = tcp_healthcheck(host, port, cx.resolver());
+
+                Ok((Box::new(sink), healthcheck))
+            }
+            Mode::Udp { address } => {
+                let (host, port) = parse_address(address)?;
+                let udp = UdpSink::new(host, port, cx.resolver())?;
+                let sink = StreamSink::new(udp, cx.acker())
+                    .with_flat_map(move |event| iter_ok(Some(encoder.encode(event))));
+
+                Ok((Box::new(sink), Box::new(future::ok(()))))
+            }
+        }
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "syslog"
+    }
+}
+
+fn parse_address(address: &str) -> crate::Result<(String, u16)> {
+    let uri = address.parse::<http::Uri>()?;
+    let host = uri.host().ok_or(SinkBuildError::MissingHost)?.to_string();
+    let port = uri.port_u16().ok_or(SinkBuildError::MissingPort)?;
+    Ok((host, port))
+}
+
+struct Encoder {
+    format: Format,
+    /// Datagrams aren't framed.
+    framing: Option<Framing>,
+    facility: Facility,
+    severity: Severity,
+    severity_key: Option<Atom>,
+    app_name: Template,
+    proc_id: Option<Template>,
+    msg_id: Option<Template>,
+    structured_data_key: Option<Atom>,
+    encoding: EncodingConfigWithDefault<Encoding>,
+    local_hostname: Option<String>,
+}
+
+impl Encoder {
+    fn encode(&self, mut event: Event) -> Bytes {
+        let severity = self
+            .severity_key
+            .as_ref()
+            .and_then(|key| event.as_log().get(key))
+            .and_then(Severity::parse)
+            .unwrap_or(self.severity);
+        let priority = self.facility as u8 * 8 + severity as u8;
+
+        let timestamp = match event.as_log().get(&log_schema().timestamp_key()) {
+            Some(Value::Timestamp(timestamp)) => *timestamp,
+            _ => Utc::now(),
+        };
+        let hostname = event
+            .as_log()
+            .get(&log_schema().host_key())
+            .map(Value::to_string_lossy)
+            .or_else(|| self.local_hostname.clone());
+        let app_name = render(&self.app_name, &event);
+        let proc_id = self.proc_id.as_ref().and_then(|id| render(id, &event));
+        let msg_id = self.msg_id.as_ref().and_then(|id| render(id, &event));
+        let structured_data = self
+            .structured_data_key
+            .as_ref()
+            .and_then(|key| event.as_mut_log().remove(key));
+
+        self.encoding.apply_rules(&mut event);
+        let log = event.into_log();
+        let message = match self.encoding.codec() {
+            Encoding::Text => log
+                .get(&log_schema().message_key())
+                .map(Value::to_string_lossy)
+                .unwrap_or_default(),
+            Encoding::Json => serde_json::to_string(&log).unwrap_or_default(),
+        };
+
+        let mut line = match self.format {
+            Format::Rfc5424 => format!(
+                "<{}>1 {} {} {} {} {} {} {}",
+                priority,
+                timestamp.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+                header_field(hostname, 255),
+                header_field(app_name, 48),
+                header_field(proc_id, 128),
+                header_field(msg_id, 32),
+                encode_structured_data(structured_data.as_ref()),
+                message
+            ),
+            Format::Rfc3164 => format!(
+                "<{}>{} {} {}: {}",
+                priority,
+                timestamp.format("%b %e %H:%M:%S"),
+                header_field(hostname, 255),
+                tag(app_name, proc_id),
+                message
+            ),
+        };
+
+        match self.framing {
+            Some(Framing::OctetCounting) => format!("{} {}", line.len(), line).into(),
+            Some(Framing::NonTransparent) => {
+                line = line.replace('\n', " ");
+                line.push('\n');
+                line.into()
+            }
+            None => line.into(),
+        }
+    }
+}
+
+fn render(template: &Template, event: &Event) -> Option<String> {
+    template
+        .render_string(event)
+        .map_err(|missing_keys| {
+            warn!(
+                message = "Keys do not exist on the event; using the nil value.",
+                ?missing_keys,
+                rate_limit_secs = 30,
+            )
+        })
+        .ok()
+        .filter(|rendered| !rendered.is_empty())
+}
+
+/// Header fields are limited to printable ASCII without spaces, and are `-`
+/// when missing.
+fn header_field(value: Option<String>, max_length: usize) -> String {
+    let value = value
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_length)
+        .collect::<String>();
+    if value.is_empty() {
+        "-".into()
+    } else {
+        value
+    }
+}
+
+/// The 3164 tag is alphanumeric, with the process id appended.
+fn tag(app_name: Option<String>, proc_id: Option<String>) -> String {
+    let mut tag = app_name
+        .unwrap_or_else(|| "vector".into())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(32)
+        .collect::<String>();
+    if let Some(proc_id) = proc_id {
+        let _ = write!(tag, "[{}]", proc_id);
+    }
+    tag
+}
+
+fn encode_structured_data(value: Option<&Value>) -> String {
+    let elements = match value {
+        Some(Value::Map(elements)) => elements,
+        _ => return "-".into(),
+    };
+
+    let mut encoded = String::new();
+    for (id, params) in elements {
+        let id = sd_name(id);
+        if id.is_empty() {
+            continue;
+        }
+        encoded.push('[');
+        encoded.push_str(&id);
+        if let Value::Map(params) = params {
+            for (name, value) in params {
+                let name = sd_name(name);
+                if !name.is_empty() {
+                    let _ = write!(encoded, " {}=\"{}\"", name, escape_param(value));
+                }
+            }
+        }
+        encoded.push(']');
+    }
+
+    if encoded.is_empty() {
+        "-".into()
+    } else {
+        encoded
+    }
+}
+
+/// SD-IDs and parameter names are up to 32 printable characters, excluding
+/// `=`, space, `]` and `"`.
+fn sd_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect()
+}
+
+fn escape_param(value: &Value) -> String {
+    let mut escaped = String::new();
+    for c in value.to_string_lossy().chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    fn encoder(format: Format, framing: Option<Framing>) -> Encoder {
+        Encoder {
+            format,
+            framing,
+            facility: Facility::Local4,
+            severity: Severity::Notice,
+            severity_key: Some("level".into()),
+            app_name: "{{ app }}".into(),
+            proc_id: Some("{{ pid }}".into()),
+            msg_id: None,
+            structured_data_key: Some("sd".into()),
+            encoding: Default::default(),
+            local_hostname: Some("local".into()),
+        }
+    }
+
+    fn event() -> Event {
+        let mut event = Event::from("hello\nworld");
+        let log = event.as_mut_log();
+        log.insert(
+            log_schema().timestamp_key().clone(),
+            "2020-03-05T13:04:05.123456Z"
+                .parse::<DateTime<Utc>>()
+                .unwrap(),
+        );
+        log.insert(log_schema().host_key().clone(), "web 01");
+        log.insert("app", "nginx");
+        log.insert("pid", 42);
+        log.insert("level", "err");
+        log.insert("sd.origin@32473.ip", "10.0.0.1");
+        log.insert("sd.origin@32473.note", "say \"hi\"");
+        event
+    }
+
+    #[test]
+    fn syslog_encodes_rfc5424_with_octet_counting() {
+        let encoded = encoder(Format::Rfc5424, Some(Framing::OctetCounting)).encode(event());
+        let line = concat!(
+            "<163>1 2020-03-05T13:04:05.123456Z web01 nginx 42 - ",
+            "[origin@32473 ip=\"10.0.0.1\" note=\"say \\\"hi\\\"\"] hello\nworld"
+        );
+        assert_eq!(encoded, Bytes::from(format!("{} {}", line.len(), line)));
+    }
+
+    #[test]
+    fn syslog_encodes_rfc3164_with_newlines() {
+        let mut event = event();
+        event.as_mut_log().remove(&"level".into());
+        let encoded = encoder(Format::Rfc3164, Some(Framing::NonTransparent)).encode(event);
+        assert_eq!(
+            encoded,
+            Bytes::from("<165>Mar  5 13:04:05 web01 nginx[42]: hello world\n")
+        );
+    }
+
+    #[test]
+    fn syslog_parses_severities() {
+        assert_eq!(Severity::parse(&"WARN".into()), Some(Severity::Warning));
+        assert_eq!(Severity::parse(&3.into()), Some(Severity::Error));
+        assert_eq!(Severity::parse(&"7".into()), Some(Severity::Debug));
+        assert_eq!(Severity::parse(&"verbose".into()), None);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{
+    event::metric::{Metric, MetricKind, MetricValue},
+    sinks::{
+        util::{
+            postgres::{encode_float, encode_text, is_transient, quote_table},
+            retries2::RetryLogic,
+            service2::TowerRequestConfig,
+            BatchEventsConfig, MetricBuffer,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{tls_connector_builder, MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use bytes05::Bytes;
+use futures::{future::BoxFuture, pin_mut, FutureExt, SinkExt as _, TryFutureExt};
+use futures01::Sink;
+use lazy_static::lazy_static;
+use postgres_openssl::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+use tower03::Service;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TimescaleDBConfig {
+    /// A connection string, either `key=value` pairs or a
+    /// `postgresql://` URL.
+    pub endpoint: String,
+    #[serde(default = "default_table")]
+    pub table: String,
+    pub namespace: Option<String>,
+    /// Creates the table as a hypertable if it doesn't exist.
+    #[serde(default)]
+    pub create_table: bool,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_table() -> String {
+    "metrics".into()
+}
+
+lazy_static! {
+    static ref REQUEST_DEFAULTS: TowerRequestConfig = TowerRequestConfig {
+        in_flight_limit: Some(1),
+        ..Default::default()
+    };
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<TimescaleDBConfig>("timescaledb")
+}
+
+#[typetag::serde(name = "timescaledb")]
+impl SinkConfig for TimescaleDBConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+        let connector = MakeTlsConnector::new(tls_connector_builder(&tls)?.build());
+
+        let service = TimescaleDBService {
+            endpoint: self.endpoint.clone(),
+            connector,
+            table: quote_table(&self.table),
+            namespace: self.namespace.clone(),
+            create_table: self.create_table,
+            client: Arc::new(Mutex::new(None)),
+        };
+
+        let healthcheck = healthcheck(service.clone()).boxed().compat();
+
+        let batch = self.batch.unwrap_or(1000, 1);
+        let request = self.request.unwrap_with(&REQUEST_DEFAULTS);
+
+        let sink = request
+            .batch_sink(
+                TimescaleDBRetryLogic,
+                service,
+                MetricBuffer::new(),
+                batch,
+                cx.acker(),
+            )
+            .sink_map_err(|e| error!("Fatal timescaledb sink error: {}", e));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "timescaledb"
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum TimescaleDBError {
+    #[snafu(display("Failed to connect: {}", source))]
+    Connect { source: tokio_postgres::Error },
+    #[snafu(display("Failed to create table: {}", source))]
+    CreateTable { source: tokio_postgres::Error },
+    #[snafu(display("Failed to copy rows: {}", source))]
+    Copy { source: tokio_postgres::Error },
+}
+
+#[derive(Clone)]
+struct TimescaleDBService {
+    endpoint: String,
+    connector: MakeTlsConnector,
+    table: String,
+    namespace: Option<String>,
+    create_table: bool,
+    client: Arc<Mutex<Option<Client>>>,
+}
+
+impl Service<Vec<Metric>> for TimescaleDBService {
+    type Response = ();
+    type Error = TimescaleDBError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Ok(()).into()
+    }
+
+    fn call(&mut self, metrics: Vec<Metric>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move { service.copy(metrics).await })
+    }
+}
+
+impl TimescaleDBService {
+    async fn connect(&self) -> Result<Client, TimescaleDBError> {
+        let (client, connection) = tokio_postgres::connect(&self.endpoint, self.connector.clone())
+            .await
+            .context(Connect)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                error!(message = "connection failed.", %error);
+            }
+        });
+
+        if self.create_table {
+            client
+                .batch_execute(&create_table_statement(&self.table))
+                .await
+                .context(CreateTable)?;
+        }
+        Ok(client)
+    }
+
+    async fn copy(self, metrics: Vec<Metric>) -> Result<(), TimescaleDBError> {
+        let mut rows = String::new();
+        for metric in metrics {
+            encode_row(metric, self.namespace.as_deref(), &mut rows);
+        }
+
+        let mut client = self.client.lock().await;
+        if client.as_ref().map_or(true, Client::is_closed) {
+            *client = Some(self.connect().await?);
+        }
+        let client = client.as_ref().expect("connected above");
+
+        let statement = format!(
+            "COPY {} (time, name, kind, type, tags, value, data) FROM STDIN",
+            self.table
+        );
+        let sink = client.copy_in(statement.as_str()).await.context(Copy)?;
+        pin_mut!(sink);
+        sink.send(Bytes::from(rows)).await.context(Copy)?;
+        sink.finish().await.context(Copy)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TimescaleDBRetryLogic;
+
+impl RetryLogic for TimescaleDBRetryLogic {
+    type Error = TimescaleDBError;
+    type Response = ();
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        let source = match error {
+            TimescaleDBError::Connect { source }
+            | TimescaleDBError::CreateTable { source }
+            | TimescaleDBError::Copy { source } => source,
+        };
+        is_transient(source)
+    }
+}
+
+async fn healthcheck(service: TimescaleDBService) -> crate::Result<()> {
+    let client = service.connect().await?;
+    client
+        .simple_query(&format!("SELECT * FROM {} LIMIT 0", service.table))
+        .await?;
+    Ok(())
+}
+
+fn create_table_statement(table: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+            time TIMESTAMPTZ NOT NULL,
+            name TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            type TEXT NOT NULL,
+            tags JSONB,
+            value DOUBLE PRECISION,
+            data JSONB
+        );
+        SELECT create_hypertable('{regclass}', 'time', if_not_exists => TRUE);",
+        table = table,
+        regclass = table.replace('\'', "''"),
+    )
+}
+
+/// Appends a metric as a row in the text format of `COPY`. Counters,
+/// gauges and sets have a `value`, the other types are kept as JSON in
+/// `data`.
+fn encode_row(metric: Metric, namespace: Option<&str>, output: &mut String) {
+    let name = match namespace {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, metric.name),
+        _ => metric.name,
+    };
+    let timestamp = metric.timestamp.unwrap_or_else(chrono::Utc::now);
+    let kind = match metric.kind {
+        MetricKind::Incremental => "incremental",
+        MetricKind::Absolute => "absolute",
+    };
+    let (metric_type, value) = match &metric.value {
+        MetricValue::Counter { value } => ("counter", Some(*value)),
+        MetricValue::Gauge { value } => ("gauge", Some(*value)),
+        MetricValue::Set { values } => ("set", Some(values.len() as f64)),
+        MetricValue::Distribution { .. } => ("distribution", None),
+        MetricValue::AggregatedHistogram { .. } => ("histogram", None),
+        MetricValue::AggregatedSummary { .. } => ("summary", None),
+    };
+    let tags = metric
+        .tags
+        .map(|tags| serde_json::to_string(&tags).expect("tags serialize to JSON"));
+    let data = match value {
+        Some(_) => None,
+        None => Some(serde_json::to_string(&metric.value).expect("values serialize to JSON")),
+    };
+
+    let columns = [
+        Some(timestamp.to_rfc3339()),
+        Some(name),
+        Some(kind.to_owned()),
+        Some(metric_type.to_owned()),
+        tags,
+        value.map(encode_float),
+        data,
+    ];
+    for (index, column) in columns.iter().enumerate() {
+        if index > 0 {
+            output.push('\t');
+        }
+        match column {
+            Some(column) => encode_text(column, output),
+            None => output.push_str("\\N"),
+        }
+    }
+    output.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn timescaledb_encode_counter() {
+        let mut tags = BTreeMap::new();
+        tags.insert("path".to_owned(), "C:\\tmp\tx".to_owned());
+        let metric = Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.ymd(2020, 6, 1).and_hms(10, 0, 0)),
+            tags: Some(tags),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.5 },
+        };
+
+        let mut output = String::new();
+        encode_row(metric, Some("vector"), &mut output);
+        assert_eq!(
+            output,
+            "2020-06-01T10:00:00+00:00\tvector.requests\tincremental\tcounter\t\
+             {\"path\":\"C:\\\\\\\\tmp\\\\tx\"}\t1.5\t\\N\n"
+        );
+    }
+
+    #[test]
+    fn timescaledb_encode_histogram() {
+        let metric = Metric {
+            name: "latency".into(),
+            timestamp: Some(Utc.ymd(2020, 6, 1).and_hms(10, 0, 0)),
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![1.0],
+                counts: vec![2],
+                count: 2,
+                sum: 1.5,
+            },
+        };
+
+        let mut output = String::new();
+        encode_row(metric, None, &mut output);
+        let columns = output.trim_end().split('\t').collect::<Vec<_>>();
+        assert_eq!(columns[3], "histogram");
+        assert_eq!(columns[4], "\\N");
+        assert_eq!(columns[5], "\\N");
+        let data: serde_json::Value = serde_json::from_str(columns[6]).unwrap();
+        assert_eq!(data["aggregated_histogram"]["count"], 2);
+    }
+
+    #[test]
+    fn timescaledb_encode_float() {
+        assert_eq!(encode_float(2.0), "2");
+        assert_eq!(encode_float(std::f64::NEG_INFINITY), "-Infinity");
+    }
+}
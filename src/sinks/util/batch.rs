@@ -1,3 +1,4 @@
+use crate::topology::config::defaults_profile;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -10,7 +11,9 @@ pub struct BatchBytesConfig {
 impl BatchBytesConfig {
     pub fn unwrap_or(&self, size: u64, timeout: u64) -> BatchSettings {
         BatchSettings {
-            size: self.max_size.unwrap_or(size as usize),
+            size: self
+                .max_size
+                .unwrap_or_else(|| defaults_profile().batch_size(size) as usize),
             timeout: Duration::from_secs(self.timeout_secs.unwrap_or(timeout)),
         }
     }
@@ -25,7 +28,9 @@ pub struct BatchEventsConfig {
 impl BatchEventsConfig {
     pub fn unwrap_or(&self, size: u64, timeout: u64) -> BatchSettings {
         BatchSettings {
-            size: self.max_events.unwrap_or(size as usize),
+            size: self
+                .max_events
+                .unwrap_or_else(|| defaults_profile().batch_size(size) as usize),
             timeout: Duration::from_secs(self.timeout_secs.unwrap_or(timeout)),
         }
     }
@@ -47,6 +52,13 @@ pub trait Batch {
     fn finish(self) -> Self::Output;
     fn num_items(&self) -> usize;
 
+    /// Whether pushing the item would take the batch over a limit besides its
+    /// size, like the bytes a service accepts per request. The batch is then
+    /// sent before the item is pushed.
+    fn would_overflow(&self, _item: &Self::Input) -> bool {
+        false
+    }
+
     /// Replace the current batch with a fresh one, returning the old one.
     fn fresh_replace(&mut self) -> Self
     where
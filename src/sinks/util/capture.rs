@@ -0,0 +1,142 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Headers whose values are never written to a capture, matched ignoring
+/// case. Any header naming a key, token, secret or password is redacted too.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+const SENSITIVE_WORDS: &[&str] = &["key", "token", "secret", "password", "signature"];
+
+pub const REDACTED: &str = "<redacted>";
+
+/// Writes the first requests a sink sends to files, one per request, so
+/// exactly what was sent can be inspected.
+///
+/// A capture starts whenever the sink is built, that is on startup and on
+/// each reload changing the sink's configuration, replacing the files of the
+/// previous one.
+#[derive(Debug, Clone)]
+pub struct PayloadCapture {
+    dir: PathBuf,
+    limit: usize,
+    next: Arc<AtomicUsize>,
+}
+
+impl PayloadCapture {
+    pub fn new(dir: PathBuf, limit: usize) -> io::Result<Self> {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(false, |extension| extension == "http")
+            {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(Self {
+            dir,
+            limit,
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Writes the request unless `limit` were already captured.
+    pub fn capture<'a>(
+        &self,
+        method: &str,
+        uri: &str,
+        headers: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+        body: &[u8],
+    ) {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        if index >= self.limit {
+            return;
+        }
+
+        let mut head = format!("{} {}\n", method, uri);
+        for (name, value) in headers {
+            let value = if is_sensitive(name) {
+                REDACTED.into()
+            } else {
+                String::from_utf8_lossy(value)
+            };
+            let _ = writeln!(head, "{}: {}", name, value);
+        }
+        head.push('\n');
+
+        let mut contents = head.into_bytes();
+        contents.extend_from_slice(body);
+
+        let path = self.dir.join(format!("{:04}.http", index));
+        match fs::write(&path, contents) {
+            Ok(()) => debug!(message = "captured request payload.", path = ?path),
+            Err(error) => warn!(
+                message = "failed to capture request payload.",
+                path = ?path,
+                %error,
+                rate_limit_secs = 10
+            ),
+        }
+    }
+}
+
+/// Whether the value of the header is never to be shown.
+pub fn is_sensitive(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE_HEADERS.contains(&name.as_str())
+        || SENSITIVE_WORDS.iter().any(|word| name.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_writes_the_first_requests_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("0007.http"), "stale").unwrap();
+
+        let capture = PayloadCapture::new(dir.path().into(), 2).unwrap();
+        for body in &["one", "two", "three"] {
+            capture.capture(
+                "POST",
+                "http://localhost/logs",
+                vec![
+                    ("Content-Type", &b"application/json"[..]),
+                    ("Authorization", &b"Bearer secret"[..]),
+                    ("DD-API-KEY", &b"abc"[..]),
+                ],
+                body.as_bytes(),
+            );
+        }
+
+        let mut files = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, vec!["0000.http", "0001.http"]);
+
+        let first = fs::read_to_string(dir.path().join("0000.http")).unwrap();
+        assert_eq!(
+            first,
+            "POST http://localhost/logs\n\
+             Content-Type: application/json\n\
+             Authorization: <redacted>\n\
+             DD-API-KEY: <redacted>\n\
+             \n\
+             one"
+        );
+    }
+}
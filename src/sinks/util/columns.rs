@@ -0,0 +1,96 @@
+//! Typed columns for sinks that write logs in a columnar format.
+
+use crate::event::{self, Value};
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    Utf8,
+    Int64,
+    Float64,
+    Boolean,
+    Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: Atom,
+    pub column_type: ColumnType,
+}
+
+/// Uses the declared schema, or the fields of the global log schema if
+/// none is declared.
+pub fn columns(schema: &IndexMap<String, ColumnType>) -> Vec<Column> {
+    if schema.is_empty() {
+        let schema = event::log_schema();
+        return vec![
+            Column {
+                name: schema.timestamp_key().clone(),
+                column_type: ColumnType::Timestamp,
+            },
+            Column {
+                name: schema.host_key().clone(),
+                column_type: ColumnType::Utf8,
+            },
+            Column {
+                name: schema.message_key().clone(),
+                column_type: ColumnType::Utf8,
+            },
+        ];
+    }
+
+    schema
+        .iter()
+        .map(|(name, column_type)| Column {
+            name: name.as_str().into(),
+            column_type: *column_type,
+        })
+        .collect()
+}
+
+pub fn to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        value => Some(value.to_string_lossy()),
+    }
+}
+
+pub fn to_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(value) => Some(*value),
+        Value::Bytes(bytes) => std::str::from_utf8(bytes).ok()?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(value) => Some(*value),
+        Value::Integer(value) => Some(*value as f64),
+        Value::Bytes(bytes) => std::str::from_utf8(bytes).ok()?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn to_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Boolean(value) => Some(*value),
+        Value::Bytes(bytes) => std::str::from_utf8(bytes).ok()?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+pub fn to_timestamp_micros(value: &Value) -> Option<i64> {
+    let timestamp = match value {
+        Value::Timestamp(timestamp) => *timestamp,
+        Value::Bytes(bytes) => DateTime::parse_from_rfc3339(std::str::from_utf8(bytes).ok()?)
+            .ok()?
+            .with_timezone(&Utc),
+        _ => return None,
+    };
+    Some(timestamp.timestamp() * 1_000_000 + i64::from(timestamp.timestamp_subsec_micros()))
+}
@@ -0,0 +1,48 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+/// Intercepts the requests of sinks built for a dry run, so they are built
+/// but never sent. The services supporting it mark it when created, which
+/// tells sinks that would actually send apart.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    intercepting: Arc<AtomicBool>,
+    requests: Arc<Mutex<Vec<InterceptedRequest>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterceptedRequest {
+    pub method: String,
+    pub uri: String,
+    pub byte_size: usize,
+}
+
+impl DryRun {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called by the services which intercept their requests.
+    pub fn intercept(&self) {
+        self.intercepting.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_intercepting(&self) -> bool {
+        self.intercepting.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, method: &str, uri: &str, byte_size: usize) {
+        debug!(message = "intercepted request.", %method, %uri, %byte_size);
+        self.requests.lock().unwrap().push(InterceptedRequest {
+            method: method.into(),
+            uri: uri.into(),
+            byte_size,
+        });
+    }
+
+    pub fn requests(&self) -> Vec<InterceptedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
@@ -99,7 +99,7 @@ pub trait EncodingConfiguration<E> {
                     match timestamp_format {
                         TimestampFormat::Unix => {
                             let mut unix_timestamps = Vec::new();
-                            for (k, v) in log_event.all_fields() {
+                            for (k, v) in log_event.all_fields_escaped() {
                                 if let Value::Timestamp(ts) = v {
                                     unix_timestamps
                                         .push((k.clone(), Value::Integer(ts.timestamp())));
@@ -0,0 +1,109 @@
+//! Lets the batches of the sinks be flushed on demand, like before draining a
+//! node, and reports how full they were and how long they waited when sent.
+
+use crate::internal_events::BatchFlushed;
+use futures01::task::AtomicTask;
+use once_cell::sync::Lazy;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Instant,
+};
+
+static HANDLES: Lazy<Mutex<Vec<Weak<FlushHandle>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+thread_local! {
+    /// The sink being built, which the batch sinks created meanwhile belong to.
+    static BUILDING: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Builds the sink, attributing the batch sinks it creates to it.
+pub fn building<T>(name: &str, build: impl FnOnce() -> T) -> T {
+    let previous = BUILDING.with(|building| building.replace(Some(name.to_owned())));
+    let built = build();
+    BUILDING.with(|building| building.replace(previous));
+    built
+}
+
+/// Flushes the batches of every sink, returning how many batch sinks were asked.
+pub fn flush_all() -> usize {
+    request_flushes(|_| true)
+}
+
+/// Flushes the batches of the sink, returning how many batch sinks were asked.
+pub fn flush_sink(name: &str) -> usize {
+    request_flushes(|handle| handle.name.as_deref() == Some(name))
+}
+
+fn request_flushes(filter: impl Fn(&FlushHandle) -> bool) -> usize {
+    let mut handles = HANDLES.lock().unwrap();
+    handles.retain(|handle| handle.upgrade().is_some());
+    handles
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|handle| filter(handle))
+        .map(|handle| {
+            handle.requested.store(true, Ordering::SeqCst);
+            handle.task.notify();
+        })
+        .count()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlushReason {
+    Size,
+    Timeout,
+    Overflow,
+    Closing,
+    Requested,
+}
+
+impl FlushReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FlushReason::Size => "size",
+            FlushReason::Timeout => "timeout",
+            FlushReason::Overflow => "overflow",
+            FlushReason::Closing => "closing",
+            FlushReason::Requested => "requested",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FlushHandle {
+    name: Option<String>,
+    requested: AtomicBool,
+    task: AtomicTask,
+}
+
+impl FlushHandle {
+    pub(super) fn register() -> Arc<Self> {
+        let handle = Arc::new(Self {
+            name: BUILDING.with(|building| building.borrow().clone()),
+            requested: AtomicBool::new(false),
+            task: AtomicTask::new(),
+        });
+        HANDLES.lock().unwrap().push(Arc::downgrade(&handle));
+        handle
+    }
+
+    /// Whether a flush was requested since the last call, which has to be
+    /// made from the task polling the sink for it to be woken up.
+    pub(super) fn poll_requested(&self) -> bool {
+        self.task.register();
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+
+    pub(super) fn flushed(&self, len: usize, size: usize, opened: Instant, reason: FlushReason) {
+        emit!(BatchFlushed {
+            component_name: self.name.as_deref(),
+            fill_ratio: len as f64 / size.max(1) as f64,
+            time_to_flush: opened.elapsed(),
+            reason,
+        });
+    }
+}
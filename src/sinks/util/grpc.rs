@@ -0,0 +1,130 @@
+//! Helpers for gRPC calls made over `HttpClient`, for sinks that speak a
+//! gRPC protocol without generated clients.
+
+use super::{
+    http2::{HttpClient, HttpRetryLogic},
+    retries2::{RetryAction, RetryLogic},
+};
+use bytes05::{Bytes, BytesMut};
+use http02::{header::HeaderValue, Request, Response};
+use http_body::Body as HttpBody;
+use hyper13::Body;
+use tower03::Service;
+
+/// Wraps a message in the length-prefixed framing used by gRPC.
+pub fn frame(compressed: bool, message: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(compressed as u8);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+/// Sends the request and reads the whole response, including the trailers
+/// that carry the gRPC status. These are merged into the response headers,
+/// where they already are for errors sent as a "Trailers-Only" response.
+pub async fn call(
+    client: &mut HttpClient,
+    request: Request<Body>,
+) -> Result<Response<Bytes>, hyper13::Error> {
+    let response = client.call(request).await?;
+    let (mut parts, mut body) = response.into_parts();
+
+    let mut data = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk?);
+    }
+    if let Some(trailers) = body.trailers().await? {
+        parts.headers.extend(trailers);
+    }
+
+    Ok(Response::from_parts(parts, data.freeze()))
+}
+
+#[derive(Clone)]
+pub struct GrpcRetryLogic;
+
+impl RetryLogic for GrpcRetryLogic {
+    type Error = hyper13::Error;
+    type Response = Response<Bytes>;
+
+    fn is_retriable_error(&self, error: &Self::Error) -> bool {
+        HttpRetryLogic.is_retriable_error(error)
+    }
+
+    /// See https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+    fn should_retry_response(&self, response: &Self::Response) -> RetryAction {
+        if !response.status().is_success() {
+            return HttpRetryLogic.should_retry_response(response);
+        }
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .unwrap_or_default()
+        };
+        let message = header("grpc-message");
+
+        match header("grpc-status") {
+            // A missing status means the server never finished the call,
+            // an empty batch sends no request and is fine as well.
+            "" if response.headers().contains_key("content-type") => {
+                RetryAction::Retry("missing grpc-status".into())
+            }
+            "" | "0" => RetryAction::Successful,
+            // CANCELLED, DEADLINE_EXCEEDED, RESOURCE_EXHAUSTED, ABORTED,
+            // OUT_OF_RANGE, UNAVAILABLE and DATA_LOSS
+            code @ "1"
+            | code @ "4"
+            | code @ "8"
+            | code @ "10"
+            | code @ "11"
+            | code @ "14"
+            | code @ "15" => RetryAction::Retry(format!("grpc-status {}: {}", code, message)),
+            code => RetryAction::DontRetry(format!("grpc-status {}: {}", code, message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(headers: &[(&'static str, &'static str)]) -> Response<Bytes> {
+        let mut response = Response::new(Bytes::new());
+        for (name, value) in headers {
+            response
+                .headers_mut()
+                .insert(*name, HeaderValue::from_static(value));
+        }
+        response
+    }
+
+    #[test]
+    fn grpc_frames() {
+        assert_eq!(frame(false, b"abc"), vec![0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(frame(true, b"")[0], 1);
+    }
+
+    #[test]
+    fn grpc_retry_logic() {
+        let logic = GrpcRetryLogic;
+        let grpc = ("content-type", "application/grpc");
+
+        assert!(logic
+            .should_retry_response(&response(&[grpc, ("grpc-status", "0")]))
+            .is_successful());
+        assert!(logic
+            .should_retry_response(&response(&[grpc, ("grpc-status", "14")]))
+            .is_retryable());
+        assert!(logic
+            .should_retry_response(&response(&[grpc, ("grpc-status", "3")]))
+            .is_not_retryable());
+        assert!(logic
+            .should_retry_response(&response(&[grpc]))
+            .is_retryable());
+        assert!(logic.should_retry_response(&response(&[])).is_successful());
+    }
+}
@@ -1,4 +1,6 @@
 use super::{
+    capture::PayloadCapture,
+    dry_run::DryRun,
     retries::{RetryAction, RetryLogic},
     service::{TowerBatchedSink, TowerRequestSettings},
     Batch, BatchSettings,
@@ -109,7 +111,9 @@ where
         let sink = Arc::new(sink);
         let sink1 = sink.clone();
         let svc =
-            HttpBatchService::new(cx.resolver(), tls_settings, move |b| sink1.build_request(b));
+            HttpBatchService::new(cx.resolver(), tls_settings, move |b| sink1.build_request(b))
+                .with_capture(cx.capture())
+                .with_dry_run(cx.dry_run());
 
         let inner = request_settings.batch_sink(logic, svc, batch, batch_settings, cx.acker());
 
@@ -275,6 +279,8 @@ impl<B> fmt::Debug for HttpClient<B> {
 pub struct HttpBatchService<B = Vec<u8>> {
     inner: HttpClient<Body>,
     request_builder: Arc<dyn Fn(B) -> hyper::Request<Vec<u8>> + Sync + Send>,
+    capture: Option<PayloadCapture>,
+    dry_run: Option<DryRun>,
 }
 
 impl<B> HttpBatchService<B> {
@@ -289,8 +295,23 @@ impl<B> HttpBatchService<B> {
         HttpBatchService {
             inner,
             request_builder: Arc::new(Box::new(request_builder)),
+            capture: None,
+            dry_run: None,
         }
     }
+
+    pub fn with_capture(mut self, capture: Option<PayloadCapture>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: Option<DryRun>) -> Self {
+        if let Some(dry_run) = &dry_run {
+            dry_run.intercept();
+        }
+        self.dry_run = dry_run;
+        self
+    }
 }
 
 impl<B> Service<B> for HttpBatchService<B> {
@@ -303,7 +324,27 @@ impl<B> Service<B> for HttpBatchService<B> {
     }
 
     fn call(&mut self, body: B) -> Self::Future {
-        let request = (self.request_builder)(body).map(Body::from);
+        let request = (self.request_builder)(body);
+        if let Some(capture) = &self.capture {
+            capture.capture(
+                request.method().as_str(),
+                &request.uri().to_string(),
+                request
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_bytes())),
+                request.body(),
+            );
+        }
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.record(
+                request.method().as_str(),
+                &request.uri().to_string(),
+                request.body().len(),
+            );
+            return Box::new(futures01::future::ok(Response::new(Bytes::new())));
+        }
+        let request = request.map(Body::from);
         let fut = self.inner.call(request).and_then(|r| {
             let (parts, body) = r.into_parts();
             body.concat2()
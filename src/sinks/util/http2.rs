@@ -1,4 +1,6 @@
 use super::{
+    capture::PayloadCapture,
+    dry_run::DryRun,
     retries2::{RetryAction, RetryLogic},
     service2::{TowerBatchedSink, TowerRequestSettings},
     Batch, BatchSettings,
@@ -113,7 +115,9 @@ where
         let sink = Arc::new(sink);
         let sink1 = sink.clone();
         let svc =
-            HttpBatchService::new(cx.resolver(), tls_settings, move |b| sink1.build_request(b));
+            HttpBatchService::new(cx.resolver(), tls_settings, move |b| sink1.build_request(b))
+                .with_capture(cx.capture())
+                .with_dry_run(cx.dry_run());
 
         let inner = request_settings.batch_sink(logic, svc, batch, batch_settings, cx.acker());
 
@@ -178,12 +182,32 @@ where
     pub fn new(
         resolver: Resolver,
         tls_settings: impl Into<MaybeTlsSettings>,
+    ) -> crate::Result<HttpClient<B>> {
+        Self::build(resolver, tls_settings.into(), false)
+    }
+
+    /// Creates a client that only speaks HTTP/2, as gRPC services expect.
+    /// It is negotiated through ALPN on TLS connections and assumed with
+    /// prior knowledge on plain ones.
+    pub fn new_http2(
+        resolver: Resolver,
+        tls_settings: impl Into<MaybeTlsSettings>,
+    ) -> crate::Result<HttpClient<B>> {
+        Self::build(resolver, tls_settings.into(), true)
+    }
+
+    fn build(
+        resolver: Resolver,
+        settings: MaybeTlsSettings,
+        http2_only: bool,
     ) -> crate::Result<HttpClient<B>> {
         let mut http = HttpConnector::new_with_resolver(resolver.clone());
         http.enforce_http(false);
 
-        let settings = tls_settings.into();
-        let tls = tls_connector_builder(&settings)?;
+        let mut tls = tls_connector_builder(&settings)?;
+        if http2_only {
+            tls.set_alpn_protos(b"\x02h2")?;
+        }
         let mut https = HttpsConnector::with_connector(http, tls)?;
 
         let settings = settings.tls().cloned();
@@ -195,7 +219,7 @@ where
             Ok(())
         });
 
-        let client = Client::builder().build(https);
+        let client = Client::builder().http2_only(http2_only).build(https);
 
         let version = crate::get_version();
         let user_agent = HeaderValue::from_str(&format!("Vector/{}", version))
@@ -280,6 +304,8 @@ impl<B> fmt::Debug for HttpClient<B> {
 pub struct HttpBatchService<B = Vec<u8>> {
     inner: HttpClient<Body>,
     request_builder: Arc<dyn Fn(B) -> hyper13::Request<Vec<u8>> + Sync + Send>,
+    capture: Option<PayloadCapture>,
+    dry_run: Option<DryRun>,
 }
 
 impl<B> HttpBatchService<B> {
@@ -294,8 +320,23 @@ impl<B> HttpBatchService<B> {
         HttpBatchService {
             inner,
             request_builder: Arc::new(Box::new(request_builder)),
+            capture: None,
+            dry_run: None,
         }
     }
+
+    pub fn with_capture(mut self, capture: Option<PayloadCapture>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: Option<DryRun>) -> Self {
+        if let Some(dry_run) = &dry_run {
+            dry_run.intercept();
+        }
+        self.dry_run = dry_run;
+        self
+    }
 }
 
 impl<B> Service<B> for HttpBatchService<B> {
@@ -308,7 +349,27 @@ impl<B> Service<B> for HttpBatchService<B> {
     }
 
     fn call(&mut self, body: B) -> Self::Future {
-        let request = (self.request_builder)(body).map(Body::from);
+        let request = (self.request_builder)(body);
+        if let Some(capture) = &self.capture {
+            capture.capture(
+                request.method().as_str(),
+                &request.uri().to_string(),
+                request
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_bytes())),
+                request.body(),
+            );
+        }
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.record(
+                request.method().as_str(),
+                &request.uri().to_string(),
+                request.body().len(),
+            );
+            return Box::pin(futures::future::ok(Response::new(Bytes::new())));
+        }
+        let request = request.map(Body::from);
 
         let response = self.inner.call(request);
         let fut = async move {
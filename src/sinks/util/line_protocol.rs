@@ -0,0 +1,256 @@
+//! A shared encoding for metric stores ingesting a single value per line,
+//! tagged with a set of dimensions, like Wavefront and Dynatrace. Each store
+//! only has to format a line and build the request carrying them.
+
+use crate::event::{
+    metric::{Metric, MetricKind, MetricValue},
+    Event,
+};
+use crate::sinks::util::http2::HttpSink;
+use chrono::{DateTime, Utc};
+use http02::Request;
+use indexmap::IndexMap;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleKind {
+    Gauge,
+    /// An increment since the previous sample of the series.
+    Delta,
+}
+
+/// A single value of a series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub kind: SampleKind,
+    pub value: f64,
+    pub tags: BTreeMap<String, String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+pub trait LineProtocol: Send + Sync + 'static {
+    /// Appends the line of a sample, without a trailing newline.
+    fn encode_sample(&self, sample: &Sample, output: &mut String);
+
+    fn build_request(&self, body: Vec<u8>) -> Request<Vec<u8>>;
+}
+
+/// Sends batches of metrics as newline separated samples.
+pub struct LineProtocolSink<P> {
+    protocol: P,
+    namespace: Option<String>,
+    tags: IndexMap<String, String>,
+}
+
+impl<P: LineProtocol> LineProtocolSink<P> {
+    pub fn new(protocol: P, namespace: Option<String>, tags: IndexMap<String, String>) -> Self {
+        Self {
+            protocol,
+            namespace,
+            tags,
+        }
+    }
+
+    fn encode(&self, metrics: Vec<Metric>) -> String {
+        let mut output = String::new();
+        for metric in metrics {
+            for sample in samples(metric, self.namespace.as_deref(), &self.tags) {
+                self.protocol.encode_sample(&sample, &mut output);
+                output.push('\n');
+            }
+        }
+        output
+    }
+}
+
+impl<P: LineProtocol> HttpSink for LineProtocolSink<P> {
+    type Input = Metric;
+    type Output = Vec<Metric>;
+
+    fn encode_event(&self, event: Event) -> Option<Self::Input> {
+        let mut metric = event.into_metric();
+        if metric.timestamp.is_none() {
+            metric.timestamp = Some(Utc::now());
+        }
+        Some(metric)
+    }
+
+    fn build_request(&self, metrics: Self::Output) -> Request<Vec<u8>> {
+        self.protocol
+            .build_request(self.encode(metrics).into_bytes())
+    }
+}
+
+/// The suffix of a series, an extra tag and its value.
+type Statistic = (&'static str, Option<(&'static str, f64)>, SampleKind, f64);
+
+/// Splits a metric into a sample for each of its statistics, like
+/// `latency.count`, or `latency.bucket` tagged with the bucket bound.
+/// Counts are deltas when the metric is incremental, the configured tags are
+/// added unless the metric has them already.
+pub fn samples(
+    metric: Metric,
+    namespace: Option<&str>,
+    default_tags: &IndexMap<String, String>,
+) -> Vec<Sample> {
+    let name = match namespace {
+        Some(namespace) if !namespace.is_empty() => format!("{}.{}", namespace, metric.name),
+        _ => metric.name,
+    };
+    let timestamp = metric.timestamp.unwrap_or_else(Utc::now);
+    let count_kind = match metric.kind {
+        MetricKind::Incremental => SampleKind::Delta,
+        MetricKind::Absolute => SampleKind::Gauge,
+    };
+
+    let mut tags = metric.tags.unwrap_or_default();
+    tags.retain(|_, value| !value.is_empty());
+    for (key, value) in default_tags {
+        tags.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+
+    let count_and_sum = |count: f64, sum: f64| -> Vec<Statistic> {
+        vec![
+            (".count", None, count_kind, count),
+            (".sum", None, count_kind, sum),
+        ]
+    };
+    let values: Vec<Statistic> = match metric.value {
+        MetricValue::Counter { value } => vec![("", None, count_kind, value)],
+        MetricValue::Gauge { value } => vec![("", None, SampleKind::Gauge, value)],
+        MetricValue::Set { values } => vec![("", None, SampleKind::Gauge, values.len() as f64)],
+        MetricValue::Distribution {
+            values,
+            sample_rates,
+        } => count_and_sum(
+            sample_rates.iter().map(|rate| *rate as f64).sum(),
+            values
+                .iter()
+                .zip(&sample_rates)
+                .map(|(value, rate)| value * *rate as f64)
+                .sum(),
+        ),
+        MetricValue::AggregatedHistogram {
+            buckets,
+            counts,
+            count,
+            sum,
+        } => buckets
+            .iter()
+            .zip(counts)
+            .map(|(bucket, count)| (".bucket", Some(("le", *bucket)), count_kind, count as f64))
+            .chain(count_and_sum(count as f64, sum))
+            .collect(),
+        MetricValue::AggregatedSummary {
+            quantiles,
+            values,
+            count,
+            sum,
+        } => quantiles
+            .iter()
+            .zip(values)
+            .map(|(quantile, value)| ("", Some(("quantile", *quantile)), SampleKind::Gauge, value))
+            .chain(count_and_sum(count as f64, sum))
+            .collect(),
+    };
+
+    values
+        .into_iter()
+        .filter(|(_, _, _, value)| value.is_finite())
+        .map(|(suffix, extra, kind, value)| {
+            let mut tags = tags.clone();
+            if let Some((tag, bound)) = extra {
+                tags.insert(tag.to_owned(), bound.to_string());
+            }
+            Sample {
+                name: format!("{}{}", name, suffix),
+                kind,
+                value,
+                tags,
+                timestamp,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metric(kind: MetricKind, value: MetricValue) -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), "web01".to_owned());
+        tags.insert("empty".to_owned(), "".to_owned());
+        Metric {
+            name: "requests".into(),
+            timestamp: Some(Utc.timestamp(1591005600, 0)),
+            tags: Some(tags),
+            kind,
+            value,
+        }
+    }
+
+    #[test]
+    fn line_protocol_adds_namespace_and_tags() {
+        let mut default_tags = IndexMap::new();
+        default_tags.insert("env".to_owned(), "production".to_owned());
+        default_tags.insert("host".to_owned(), "unknown".to_owned());
+
+        let samples = samples(
+            metric(MetricKind::Incremental, MetricValue::Counter { value: 2.0 }),
+            Some("vector"),
+            &default_tags,
+        );
+        let mut tags = BTreeMap::new();
+        tags.insert("env".to_owned(), "production".to_owned());
+        tags.insert("host".to_owned(), "web01".to_owned());
+        assert_eq!(
+            samples,
+            vec![Sample {
+                name: "vector.requests".into(),
+                kind: SampleKind::Delta,
+                value: 2.0,
+                tags,
+                timestamp: Utc.timestamp(1591005600, 0),
+            }]
+        );
+    }
+
+    #[test]
+    fn line_protocol_splits_summaries() {
+        let samples = samples(
+            metric(
+                MetricKind::Absolute,
+                MetricValue::AggregatedSummary {
+                    quantiles: vec![0.5, 0.99],
+                    values: vec![2.0, std::f64::NAN],
+                    count: 4,
+                    sum: 16.0,
+                },
+            ),
+            None,
+            &IndexMap::new(),
+        );
+        let series = samples
+            .iter()
+            .map(|sample| {
+                (
+                    sample.name.as_str(),
+                    sample.tags.get("quantile").map(String::as_str),
+                    sample.kind,
+                    sample.value,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            series,
+            vec![
+                ("requests", Some("0.5"), SampleKind::Gauge, 2.0),
+                ("requests.count", None, SampleKind::Gauge, 4.0),
+                ("requests.sum", None, SampleKind::Gauge, 16.0),
+            ]
+        );
+    }
+}
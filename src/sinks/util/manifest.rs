@@ -0,0 +1,286 @@
+//! Manifests of the objects written by object storage sinks, listing them
+//! with the events they hold, so that loaders can find and check new data
+//! without listing the whole bucket.
+
+use super::{Batch, Buffer, PartitionInnerBuffer};
+use crate::event::{self, Event, Value};
+use chrono::{DateTime, Utc};
+use openssl::sha::Sha256;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::interval;
+use uuid::Uuid;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestConfig {
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_key_prefix() -> String {
+    "manifests/".into()
+}
+
+fn default_interval_secs() -> u64 {
+    300
+}
+
+/// The events an object holds.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ObjectStats {
+    pub events: usize,
+    pub min_timestamp: Option<DateTime<Utc>>,
+    pub max_timestamp: Option<DateTime<Utc>>,
+    /// The events by the second of their timestamps, for watermarks.
+    #[serde(skip)]
+    pub seconds: BTreeMap<i64, usize>,
+}
+
+impl ObjectStats {
+    pub fn add(&mut self, timestamp: Option<DateTime<Utc>>) {
+        self.events += 1;
+        if let Some(timestamp) = timestamp {
+            self.min_timestamp = Some(self.min_timestamp.map_or(timestamp, |t| t.min(timestamp)));
+            self.max_timestamp = Some(self.max_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+            *self.seconds.entry(timestamp.timestamp()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// An event encoded for an object, with its timestamp.
+#[derive(Debug, Clone)]
+pub struct EncodedEvent {
+    pub bytes: Vec<u8>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+pub fn event_timestamp(event: &Event) -> Option<DateTime<Utc>> {
+    match event.as_log().get(&event::log_schema().timestamp_key()) {
+        Some(Value::Timestamp(timestamp)) => Some(*timestamp),
+        _ => None,
+    }
+}
+
+pub fn with_timestamp<K>(
+    encoded: PartitionInnerBuffer<Vec<u8>, K>,
+    timestamp: Option<DateTime<Utc>>,
+) -> PartitionInnerBuffer<EncodedEvent, K> {
+    let (bytes, key) = encoded.into_parts();
+    PartitionInnerBuffer::new(EncodedEvent { bytes, timestamp }, key)
+}
+
+/// A `Buffer` keeping the stats of the events pushed to it.
+#[derive(Debug)]
+pub struct StatsBuffer {
+    buffer: Buffer,
+    stats: ObjectStats,
+}
+
+impl StatsBuffer {
+    pub fn new(buffer: Buffer) -> Self {
+        Self {
+            buffer,
+            stats: ObjectStats::default(),
+        }
+    }
+}
+
+impl Batch for StatsBuffer {
+    type Input = EncodedEvent;
+    type Output = (Vec<u8>, ObjectStats);
+
+    fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn push(&mut self, item: Self::Input) {
+        self.stats.add(item.timestamp);
+        self.buffer.push(&item.bytes)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    fn fresh(&self) -> Self {
+        Self::new(self.buffer.fresh())
+    }
+
+    fn finish(self) -> Self::Output {
+        (self.buffer.finish(), self.stats)
+    }
+
+    fn num_items(&self) -> usize {
+        self.buffer.num_items()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub key: String,
+    pub byte_size: usize,
+    /// Of the object as stored, compressed or not.
+    pub sha256: String,
+    #[serde(flatten)]
+    pub stats: ObjectStats,
+}
+
+impl ManifestEntry {
+    pub fn new(key: String, body: &[u8], stats: ObjectStats) -> Self {
+        let mut checksum = Checksum::default();
+        checksum.update(body);
+        checksum.finish(key, body.len(), stats)
+    }
+}
+
+/// The checksum of an object written in parts.
+pub struct Checksum(Sha256);
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Checksum(Sha256::new())
+    }
+}
+
+impl Checksum {
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    pub fn finish(self, key: String, byte_size: usize, stats: ObjectStats) -> ManifestEntry {
+        let sha256 = self
+            .0
+            .finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        ManifestEntry {
+            key,
+            byte_size,
+            sha256,
+            stats,
+        }
+    }
+}
+
+/// The objects written since the last manifest, shared by the sink and the
+/// task writing manifests.
+#[derive(Clone, Default)]
+pub struct Manifest {
+    entries: Arc<Mutex<Vec<ManifestEntry>>>,
+}
+
+impl Manifest {
+    pub fn record(&self, entry: ManifestEntry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    fn take(&self) -> Vec<ManifestEntry> {
+        mem::replace(&mut *self.entries.lock().unwrap(), Vec::new())
+    }
+
+    /// Puts back the entries of a manifest that couldn't be written, ahead
+    /// of those recorded since.
+    fn restore(&self, mut entries: Vec<ManifestEntry>) {
+        let mut recorded = self.entries.lock().unwrap();
+        entries.append(&mut recorded);
+        *recorded = entries;
+    }
+
+    /// Whether the sink, and the requests it sent, are gone.
+    fn is_orphaned(&self) -> bool {
+        Arc::strong_count(&self.entries) == 1
+    }
+}
+
+/// Manifests are named after the time they are written at, so that their
+/// keys sort in the order they were written in.
+fn manifest_key(prefix: &str) -> String {
+    format!(
+        "{}{}-{}.json",
+        prefix,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+        Uuid::new_v4().to_hyphenated()
+    )
+}
+
+#[derive(Serialize)]
+struct ManifestBody<'a> {
+    objects: &'a [ManifestEntry],
+}
+
+fn encode_manifest(entries: &[ManifestEntry]) -> Vec<u8> {
+    serde_json::to_vec(&ManifestBody { objects: entries }).expect("manifest serializes")
+}
+
+/// Writes a manifest of the objects recorded every interval, with `write`
+/// putting it at the key given. A last manifest is written once the sink is
+/// gone.
+pub async fn write_manifests<F, Fut>(manifest: Manifest, config: ManifestConfig, write: F)
+where
+    F: Fn(String, Vec<u8>) -> Fut,
+    Fut: Future<Output = crate::Result<()>>,
+{
+    let mut ticks = interval(Duration::from_secs(config.interval_secs));
+    loop {
+        ticks.tick().await;
+        let last = manifest.is_orphaned();
+
+        let entries = manifest.take();
+        if !entries.is_empty() {
+            let key = manifest_key(&config.key_prefix);
+            match write(key.clone(), encode_manifest(&entries)).await {
+                Ok(()) => debug!(message = "wrote manifest.", %key, objects = entries.len()),
+                Err(error) => {
+                    error!(message = "Unable to write manifest; retrying with the next one.", %key, %error);
+                    manifest.restore(entries);
+                }
+            }
+        }
+
+        if last {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sinks::util::Compression;
+    use chrono::TimeZone;
+
+    #[test]
+    fn stats_buffer_keeps_event_counts_and_time_range() {
+        let mut buffer = StatsBuffer::new(Buffer::new(Compression::None));
+        for (second, line) in &[(20, "b\n"), (10, "a\n"), (30, "c\n")] {
+            buffer.push(EncodedEvent {
+                bytes: line.as_bytes().to_vec(),
+                timestamp: Some(Utc.timestamp(*second, 0)),
+            });
+        }
+        let (body, stats) = buffer.finish();
+        let entry = ManifestEntry::new("key".into(), &body, stats);
+
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap(),
+            serde_json::json!({
+                "key": "key",
+                "byte_size": 6,
+                "sha256": "af8fcee01ae24dc6c3e667d5f3aaba900637223e1cf618b92c4c548cf97e81f5",
+                "events": 3,
+                "min_timestamp": "1970-01-01T00:00:10Z",
+                "max_timestamp": "1970-01-01T00:00:30Z",
+            })
+        );
+    }
+}
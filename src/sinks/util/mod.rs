@@ -1,8 +1,20 @@
 pub mod batch;
 pub mod buffer;
+pub mod capture;
+pub mod columns;
+pub mod dry_run;
 pub mod encoding;
+pub mod flush;
+pub mod grpc;
 pub mod http;
 pub mod http2;
+#[cfg(any(feature = "sinks-dynatrace", feature = "sinks-wavefront"))]
+pub mod line_protocol;
+#[cfg(any(feature = "sinks-aws_s3", feature = "sinks-gcp"))]
+pub mod manifest;
+#[cfg(any(feature = "sinks-postgres", feature = "sinks-timescaledb"))]
+pub mod postgres;
+pub mod rate_limit;
 pub mod retries;
 pub mod retries2;
 #[cfg(feature = "rusoto_core")]
@@ -16,9 +28,11 @@ pub mod tcp;
 #[cfg(test)]
 pub mod test;
 pub mod udp;
-#[cfg(all(feature = "sinks-socket", unix))]
+#[cfg(all(any(feature = "sinks-socket", feature = "sinks-statsd"), unix))]
 pub mod unix;
 pub mod uri;
+#[cfg(any(feature = "sinks-aws_s3", feature = "sinks-gcp"))]
+pub mod watermark;
 
 use crate::event::{self, Event};
 use bytes::Bytes;
@@ -36,7 +50,7 @@ pub use sink::{BatchSink, PartitionBatchSink, StreamSink};
 pub use uri::UriSerde;
 
 #[derive(Debug, Snafu)]
-enum SinkBuildError {
+pub(crate) enum SinkBuildError {
     #[snafu(display("Missing host in address field"))]
     MissingHost,
     #[snafu(display("Missing port in address field"))]
@@ -0,0 +1,70 @@
+//! Helpers shared by the sinks writing to PostgreSQL.
+
+/// Quotes an identifier, such as a column name.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quotes every part of a possibly schema qualified table name.
+pub fn quote_table(table: &str) -> String {
+    table
+        .split('.')
+        .map(quote_identifier)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Formats a float the way PostgreSQL reads it, which spells infinities
+/// out.
+pub fn encode_float(value: f64) -> String {
+    if value.is_infinite() {
+        if value > 0.0 { "Infinity" } else { "-Infinity" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Appends a value escaped for the text format of `COPY`.
+pub fn encode_text(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '\\' => output.push_str("\\\\"),
+            '\t' => output.push_str("\\t"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// Whether the error may go away on its own, so the request is worth
+/// retrying.
+pub fn is_transient(error: &tokio_postgres::Error) -> bool {
+    match error.code() {
+        // Errors without a state are IO and protocol errors.
+        None => true,
+        Some(state) => {
+            let code = state.code();
+            // Connection exceptions, insufficient resources, operator
+            // intervention, and serialization failures.
+            code.starts_with("08")
+                || code.starts_with("53")
+                || code.starts_with("57P")
+                || code == "40001"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postgres_quote_table() {
+        assert_eq!(quote_table("metrics"), "\"metrics\"");
+        assert_eq!(
+            quote_table("public.my\"table"),
+            "\"public\".\"my\"\"table\""
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+/// Allows `limit` events per fixed window. For sinks that notify people,
+/// where events over the limit are better dropped than delayed: a flood of
+/// stale notifications would not be read anyway.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: u64,
+    window: Duration,
+    window_start: Option<Instant>,
+    sent: u64,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u64, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            window_start: None,
+            sent: 0,
+        }
+    }
+
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.window_start {
+            Some(start) if now.duration_since(start) < self.window => (),
+            _ => {
+                self.window_start = Some(now);
+                self.sent = 0;
+            }
+        }
+
+        if self.sent < self.limit {
+            self.sent += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_resets_per_window() {
+        let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(limiter.allow(start));
+        assert!(limiter.allow(start + Duration::from_secs(1)));
+        assert!(!limiter.allow(start + Duration::from_secs(2)));
+        assert!(limiter.allow(start + Duration::from_secs(61)));
+    }
+}
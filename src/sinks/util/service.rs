@@ -2,7 +2,7 @@ use super::{
     retries::{FixedRetryPolicy, RetryLogic},
     Batch, BatchSettings, BatchSink,
 };
-use crate::buffers::Acker;
+use crate::{buffers::Acker, topology::config::defaults_profile};
 use futures01::{Async, Future, Poll};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -67,18 +67,21 @@ pub struct TowerRequestConfig {
 
 impl TowerRequestConfig {
     pub fn unwrap_with(&self, defaults: &TowerRequestConfig) -> TowerRequestSettings {
+        // The defaults profile only adjusts what isn't set on the sink.
+        let profile = defaults_profile();
         TowerRequestSettings {
             in_flight_limit: self
                 .in_flight_limit
-                .or(defaults.in_flight_limit)
-                .unwrap_or(5),
+                .unwrap_or_else(|| profile.in_flight_limit(defaults.in_flight_limit.unwrap_or(5))),
             timeout: Duration::from_secs(self.timeout_secs.or(defaults.timeout_secs).unwrap_or(60)),
             rate_limit_duration: Duration::from_secs(
                 self.rate_limit_duration_secs
                     .or(defaults.rate_limit_duration_secs)
                     .unwrap_or(1),
             ),
-            rate_limit_num: self.rate_limit_num.or(defaults.rate_limit_num).unwrap_or(5),
+            rate_limit_num: self
+                .rate_limit_num
+                .unwrap_or_else(|| profile.rate_limit_num(defaults.rate_limit_num.unwrap_or(5))),
             retry_attempts: self
                 .retry_attempts
                 .or(defaults.retry_attempts)
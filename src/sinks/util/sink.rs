@@ -34,6 +34,7 @@
 
 use super::batch::{Batch, BatchSettings};
 use super::buffer::partition::Partition;
+use super::flush::{FlushHandle, FlushReason};
 use crate::buffers::Acker;
 use futures01::{
     future::Either,
@@ -46,6 +47,7 @@ use std::{
     fmt,
     hash::Hash,
     marker::PhantomData,
+    sync::Arc,
     time::Instant,
 };
 use tokio01::{
@@ -139,7 +141,13 @@ pub struct BatchSink<S, B, Request, E = DefaultExecutor> {
     batch: B,
     settings: BatchSettings,
     linger: Option<Delay>,
+    /// When the first item of the batch was pushed.
+    opened: Instant,
     closing: bool,
+    /// The next item doesn't fit in the batch, which has to be sent first.
+    overflowed: bool,
+    flush: Arc<FlushHandle>,
+    flushing: bool,
     exec: E,
     _pd: PhantomData<Request>,
 }
@@ -180,14 +188,30 @@ where
             batch,
             settings,
             linger: None,
+            opened: Instant::now(),
             closing: false,
+            overflowed: false,
+            flush: FlushHandle::register(),
+            flushing: false,
             exec,
             _pd: PhantomData,
         }
     }
 
-    fn should_send(&mut self) -> bool {
-        self.closing || self.batch.len() >= self.settings.size || self.linger_elapsed()
+    fn should_send(&mut self) -> Option<FlushReason> {
+        if self.closing {
+            Some(FlushReason::Closing)
+        } else if self.overflowed {
+            Some(FlushReason::Overflow)
+        } else if self.batch.len() >= self.settings.size {
+            Some(FlushReason::Size)
+        } else if self.flushing {
+            Some(FlushReason::Requested)
+        } else if self.linger_elapsed() {
+            Some(FlushReason::Timeout)
+        } else {
+            None
+        }
     }
 
     fn linger_elapsed(&mut self) -> bool {
@@ -212,11 +236,14 @@ where
     type SinkError = crate::Error;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        if self.batch.len() >= self.settings.size {
+        if !self.batch.is_empty() && self.batch.would_overflow(&item) {
+            self.overflowed = true;
+        }
+        if self.overflowed || self.batch.len() >= self.settings.size {
             trace!("batch full.");
             self.poll_complete()?;
 
-            if self.batch.len() > self.settings.size {
+            if self.overflowed || self.batch.len() > self.settings.size {
                 debug!(message = "Batch full; applying back pressure.", size = %self.settings.size, rate_limit_secs = 10);
                 return Ok(AsyncSink::NotReady(item));
             }
@@ -226,7 +253,8 @@ where
             trace!("Creating new batch.");
             // We just inserted the first item of a new batch, so set our delay to the longest time
             // we want to allow that item to linger in the batch before being flushed.
-            let deadline = Instant::now() + self.settings.timeout;
+            self.opened = Instant::now();
+            let deadline = self.opened + self.settings.timeout;
             self.linger = Some(Delay::new(deadline));
         }
 
@@ -236,18 +264,25 @@ where
     }
 
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        if self.flush.poll_requested() {
+            self.flushing = true;
+        }
+
         loop {
             if self.batch.is_empty() {
                 trace!("no batches; driving service to completion.");
+                self.flushing = false;
                 return self.service.poll_complete();
             } else {
                 // We have data to send, so check if we should send it and either attempt the send
                 // or return that we're not ready to send. If we send and it works, loop to poll or
                 // close inner instead of prematurely returning Ready
-                if self.should_send() {
+                if let Some(reason) = self.should_send() {
                     try_ready!(self.service.poll_ready());
 
                     trace!("Service ready; Sending batch.");
+                    self.flush
+                        .flushed(self.batch.len(), self.settings.size, self.opened, reason);
                     let batch = self.batch.fresh_replace();
 
                     let batch_size = batch.num_items();
@@ -259,6 +294,7 @@ where
 
                     // Disable linger timeout
                     self.linger.take();
+                    self.overflowed = false;
                 } else {
                     // We have a batch but we can't send any items
                     // most likely because we have not hit either
@@ -321,8 +357,11 @@ pub struct PartitionBatchSink<B, S, K, Request, E = DefaultExecutor> {
     service: ServiceSink<S, Request>,
     exec: E,
     partitions: HashMap<K, B>,
+    /// When the first item of each batch was pushed.
+    opened: HashMap<K, Instant>,
     settings: BatchSettings,
     closing: bool,
+    flush: Arc<FlushHandle>,
     sending: VecDeque<B>,
     lingers: FuturesUnordered<LingerDelay<K>>,
     linger_handles: HashMap<K, oneshot::Sender<K>>,
@@ -379,14 +418,23 @@ where
             service,
             exec,
             partitions: HashMap::new(),
+            opened: HashMap::new(),
             settings,
             closing: false,
+            flush: FlushHandle::register(),
             sending: VecDeque::new(),
             lingers: FuturesUnordered::new(),
             linger_handles: HashMap::new(),
         }
     }
 
+    fn flushed(&mut self, partition: &K, batch: &B, reason: FlushReason) {
+        if let Some(opened) = self.opened.remove(partition) {
+            self.flush
+                .flushed(batch.len(), self.settings.size, opened, reason);
+        }
+    }
+
     fn set_linger(&mut self, partition: K) {
         let (tx, rx) = oneshot::channel();
         let partition_clone = partition.clone();
@@ -496,6 +544,7 @@ where
         batch.push(item);
         self.set_linger(partition.clone());
 
+        self.opened.insert(partition.clone(), Instant::now());
         self.partitions.insert(partition, batch);
 
         Ok(AsyncSink::Ready)
@@ -509,6 +558,7 @@ where
         }
 
         let closing = self.closing;
+        let flushing = self.flush.poll_requested();
         let max_size = self.settings.size;
 
         let mut partitions = Vec::new();
@@ -520,6 +570,7 @@ where
                 self.linger_handles.remove(&partition);
 
                 if let Some(batch) = self.partitions.remove(&partition) {
+                    self.flushed(&partition, &batch, FlushReason::Timeout);
                     partitions.push(batch);
                 }
             }
@@ -528,7 +579,7 @@ where
         let ready = self
             .partitions
             .iter()
-            .filter(|(_, b)| closing || b.len() >= max_size)
+            .filter(|(_, b)| closing || flushing || b.len() >= max_size)
             .map(|(p, _)| p.clone())
             .collect::<Vec<_>>();
 
@@ -541,6 +592,15 @@ where
                     let _ = linger_cancel.send(partition.clone());
                 }
 
+                let reason = if closing {
+                    FlushReason::Closing
+                } else if batch.len() >= max_size {
+                    FlushReason::Size
+                } else {
+                    FlushReason::Requested
+                };
+                self.flushed(&partition, &batch, reason);
+
                 ready_batches.push(batch);
             }
         }
@@ -710,7 +770,9 @@ where
 mod tests {
     use super::*;
     use crate::buffers::Acker;
-    use crate::sinks::util::{buffer::partition::Partition, BatchSettings, Buffer, Compression};
+    use crate::sinks::util::{
+        buffer::partition::Partition, flush, BatchSettings, Buffer, Compression,
+    };
     use crate::test_util::runtime;
     use bytes::Bytes;
     use futures01::{future, Sink};
@@ -869,6 +931,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn batch_sink_flushes_when_requested() {
+        let rt = runtime();
+        let mut clock = MockClock::new();
+
+        let (acker, _) = Acker::new_for_testing();
+        let sent_requests = Arc::new(Mutex::new(Vec::new()));
+
+        let svc = tower::service_fn(|req| {
+            let sent_requests = sent_requests.clone();
+
+            sent_requests.lock().unwrap().push(req);
+
+            future::ok::<_, std::io::Error>(())
+        });
+        let mut buffered = flush::building("batch_sink_flush", || {
+            BatchSink::with_executor(svc, Vec::new(), SETTINGS, acker, rt.executor())
+        });
+
+        clock.enter(|_| {
+            future::lazy(|| {
+                assert!(buffered.start_send(0).unwrap().is_ready());
+                assert!(buffered.start_send(1).unwrap().is_ready());
+                assert!(buffered.poll_complete().unwrap().is_not_ready());
+                assert!(sent_requests.lock().unwrap().is_empty());
+
+                assert_eq!(flush::flush_sink("batch_sink_flush"), 1);
+                buffered.poll_complete().unwrap();
+                assert_eq!(*sent_requests.lock().unwrap(), vec![vec![0, 1]]);
+
+                Ok::<_, ()>(())
+            })
+            .wait()
+            .unwrap()
+        });
+    }
+
+    /// A batch holding items up to a total of 20.
+    #[derive(Debug)]
+    struct SumBatch(Vec<usize>);
+
+    impl Batch for SumBatch {
+        type Input = usize;
+        type Output = Vec<usize>;
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn push(&mut self, item: usize) {
+            self.0.push(item)
+        }
+
+        fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn fresh(&self) -> Self {
+            SumBatch(Vec::new())
+        }
+
+        fn finish(self) -> Vec<usize> {
+            self.0
+        }
+
+        fn num_items(&self) -> usize {
+            self.0.len()
+        }
+
+        fn would_overflow(&self, item: &usize) -> bool {
+            self.0.iter().sum::<usize>() + item > 20
+        }
+    }
+
+    #[test]
+    fn batch_sink_sends_batch_before_overflowing() {
+        let rt = runtime();
+        let mut clock = MockClock::new();
+
+        let (acker, _) = Acker::new_for_testing();
+        let sent_requests = Arc::new(Mutex::new(Vec::new()));
+
+        let svc = tower::service_fn(|req| {
+            let sent_requests = sent_requests.clone();
+
+            sent_requests.lock().unwrap().push(req);
+
+            future::ok::<_, std::io::Error>(())
+        });
+        let buffered =
+            BatchSink::with_executor(svc, SumBatch(Vec::new()), SETTINGS, acker, rt.executor());
+
+        let _ = clock.enter(|_| {
+            buffered
+                .sink_map_err(drop)
+                .send_all(futures01::stream::iter_ok(vec![5, 10, 6, 30, 1]))
+                .wait()
+                .unwrap()
+        });
+
+        let output = sent_requests.lock().unwrap();
+        assert_eq!(&*output, &vec![vec![5, 10], vec![6], vec![30], vec![1]]);
+    }
+
     #[test]
     fn batch_sink_flushes_below_min_on_close() {
         let rt = runtime();
@@ -1,13 +1,22 @@
 use crate::{
+    event::Event,
     internal_events::{
         UnixSocketConnectionEstablished, UnixSocketConnectionFailure, UnixSocketError,
         UnixSocketEventSent,
     },
     sinks::util::{encode_event, encoding::EncodingConfig, Encoding, StreamSink},
-    sinks::{Healthcheck, RouterSink},
+    sinks::{
+        streaming_sink::{self, StreamingSink},
+        Healthcheck, RouterSink,
+    },
     topology::config::SinkContext,
 };
+use async_trait::async_trait;
 use bytes::Bytes;
+use futures::{
+    pin_mut,
+    stream::{Stream, StreamExt},
+};
 use futures01::{
     future, stream::iter_ok, try_ready, Async, AsyncSink, Future, Poll, Sink, StartSend,
 };
@@ -16,6 +25,7 @@ use snafu::Snafu;
 use std::io;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
+use tokio::net::UnixDatagram;
 use tokio01::codec::{BytesCodec, FramedWrite};
 use tokio01::timer::Delay;
 use tokio_retry::strategy::ExponentialBackoff;
@@ -45,6 +55,19 @@ impl UnixSinkConfig {
 
         Ok((sink, healthcheck))
     }
+
+    /// Sends every event in a datagram of its own.
+    pub fn build_datagram(&self, mut cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let sink = UnixDatagramSink {
+            path: self.path.clone(),
+            encoding: self.encoding.clone(),
+        };
+        let sink = streaming_sink::compat::adapt_to_topology(&mut cx, sink);
+        let sink = StreamSink::new(sink, cx.acker());
+        let healthcheck = unix_datagram_healthcheck(self.path.clone());
+
+        Ok((Box::new(sink), healthcheck))
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -53,7 +76,7 @@ enum HealthcheckError {
     ConnectError { source: std::io::Error },
 }
 
-fn unix_healthcheck(path: PathBuf) -> Healthcheck {
+pub fn unix_healthcheck(path: PathBuf) -> Healthcheck {
     // Lazy to avoid immediately connecting
     let check = future::lazy(move || {
         UnixStream::connect(&path)
@@ -64,6 +87,52 @@ fn unix_healthcheck(path: PathBuf) -> Healthcheck {
     Box::new(check)
 }
 
+pub fn unix_datagram_healthcheck(path: PathBuf) -> Healthcheck {
+    let check = future::lazy(move || {
+        std::os::unix::net::UnixDatagram::unbound()
+            .and_then(|socket| socket.connect(&path))
+            .map_err(|source| HealthcheckError::ConnectError { source }.into())
+    });
+
+    Box::new(check)
+}
+
+/// Datagrams sent while no one is bound to the path are dropped, as with
+/// UDP.
+struct UnixDatagramSink {
+    path: PathBuf,
+    encoding: EncodingConfig<Encoding>,
+}
+
+#[async_trait]
+impl StreamingSink for UnixDatagramSink {
+    async fn run(
+        &mut self,
+        input: impl Stream<Item = Event> + Send + Sync + 'static,
+    ) -> crate::Result<()> {
+        let mut socket = UnixDatagram::unbound()?;
+        pin_mut!(input);
+
+        while let Some(event) = input.next().await {
+            let datagram = match encode_event(event, &self.encoding) {
+                Some(datagram) => datagram,
+                None => continue,
+            };
+            match socket.send_to(&datagram, &self.path).await {
+                Ok(_) => emit!(UnixSocketEventSent {
+                    byte_size: datagram.len()
+                }),
+                Err(error) => emit!(UnixSocketError {
+                    error,
+                    path: &self.path
+                }),
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct UnixSink {
     path: PathBuf,
     state: UnixSinkState,
@@ -262,4 +331,32 @@ mod tests {
         assert_eq!(num_lines, output_lines.len());
         assert_eq!(input_lines, output_lines);
     }
+
+    #[test]
+    fn unix_datagram_sink() {
+        let out_path = temp_uds_path("unix_datagram_test");
+        let receiver = std::os::unix::net::UnixDatagram::bind(&out_path).unwrap();
+
+        let config = UnixSinkConfig::new(out_path.clone(), Encoding::Text.into());
+        let mut rt = runtime();
+        let cx = SinkContext::new_test(rt.executor());
+        let (sink, healthcheck) = config.build_datagram(cx).unwrap();
+        rt.block_on(healthcheck).unwrap();
+
+        let (input_lines, events) = random_lines_with_stream(100, 10);
+        let _ = rt.block_on(sink.send_all(events)).unwrap();
+
+        let mut buf = [0; 256];
+        let output_lines = (0..input_lines.len())
+            .map(|_| {
+                let size = receiver.recv(&mut buf).unwrap();
+                String::from_utf8(buf[..size].to_vec()).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // Every line is a datagram of its own, newline included.
+        for (input, output) in input_lines.iter().zip(output_lines) {
+            assert_eq!(format!("{}\n", input), output);
+        }
+    }
 }
@@ -0,0 +1,210 @@
+//! Watermarks of the partitions of object storage sinks: the time before
+//! which all the events received for a partition have been written, so that
+//! batch jobs know when to process it.
+//!
+//! Watermarks follow the timestamps of the events received. Events older
+//! than a watermark written may still arrive late; the watermark then holds
+//! until they are written too.
+
+use super::manifest::ObjectStats;
+use crate::internal_events::WatermarkAdvanced;
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::interval;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WatermarkConfig {
+    /// Appended to the prefix of a partition to name its watermark object.
+    #[serde(default = "default_key_suffix")]
+    pub key_suffix: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_key_suffix() -> String {
+    "_watermark.json".into()
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Default)]
+struct PartitionState {
+    /// The events received but not written yet, by the second of their
+    /// timestamps.
+    pending: BTreeMap<i64, usize>,
+    /// The latest second of the events written.
+    written: Option<i64>,
+    /// The last watermark put in the bucket.
+    published: Option<i64>,
+}
+
+impl PartitionState {
+    /// All the events received before the watermark are written.
+    fn watermark(&self) -> Option<i64> {
+        match self.pending.keys().next() {
+            Some(oldest) => Some(*oldest),
+            None => self.written.map(|second| second + 1),
+        }
+    }
+}
+
+/// The state of the partitions of a sink, shared by the sink and the task
+/// writing watermarks.
+#[derive(Clone, Default)]
+pub struct Watermarks {
+    partitions: Arc<Mutex<HashMap<Bytes, PartitionState>>>,
+}
+
+impl Watermarks {
+    pub fn received(&self, partition: &Bytes, timestamp: Option<DateTime<Utc>>) {
+        if let Some(timestamp) = timestamp {
+            let mut partitions = self.partitions.lock().unwrap();
+            let state = partitions.entry(partition.clone()).or_default();
+            *state.pending.entry(timestamp.timestamp()).or_insert(0) += 1;
+        }
+    }
+
+    /// Events of objects that fail to be written stay pending, keeping the
+    /// watermark from claiming data that isn't there.
+    pub fn written(&self, partition: &Bytes, stats: &ObjectStats) {
+        let mut partitions = self.partitions.lock().unwrap();
+        let state = partitions.entry(partition.clone()).or_default();
+        for (second, count) in &stats.seconds {
+            if let Some(pending) = state.pending.get_mut(second) {
+                *pending = pending.saturating_sub(*count);
+                if *pending == 0 {
+                    state.pending.remove(second);
+                }
+            }
+        }
+        if let Some(max) = stats.max_timestamp {
+            let second = max.timestamp();
+            state.written = Some(state.written.map_or(second, |written| written.max(second)));
+        }
+    }
+
+    /// The partitions whose watermarks moved past the ones published.
+    fn advanced(&self) -> Vec<(Bytes, i64)> {
+        self.partitions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(partition, state)| {
+                let watermark = state.watermark()?;
+                match state.published {
+                    Some(published) if watermark <= published => None,
+                    _ => Some((partition.clone(), watermark)),
+                }
+            })
+            .collect()
+    }
+
+    fn published(&self, partition: &Bytes, watermark: i64) {
+        if let Some(state) = self.partitions.lock().unwrap().get_mut(partition) {
+            state.published = Some(watermark);
+        }
+    }
+
+    /// Whether the sink, and the requests it sent, are gone.
+    fn is_orphaned(&self) -> bool {
+        Arc::strong_count(&self.partitions) == 1
+    }
+}
+
+#[derive(Serialize)]
+struct WatermarkBody<'a> {
+    partition: &'a str,
+    watermark: DateTime<Utc>,
+}
+
+/// Writes the watermarks that advanced every interval, with `write` putting
+/// each at the key given. Last watermarks are written once the sink is gone.
+pub async fn write_watermarks<F, Fut>(
+    watermarks: Watermarks,
+    config: WatermarkConfig,
+    sink_type: &'static str,
+    write: F,
+) where
+    F: Fn(String, Vec<u8>) -> Fut,
+    Fut: Future<Output = crate::Result<()>>,
+{
+    let mut ticks = interval(Duration::from_secs(config.interval_secs));
+    loop {
+        ticks.tick().await;
+        let last = watermarks.is_orphaned();
+
+        for (partition, second) in watermarks.advanced() {
+            let prefix = String::from_utf8_lossy(&partition);
+            let key = format!("{}{}", prefix, config.key_suffix);
+            let body = WatermarkBody {
+                partition: &prefix,
+                watermark: Utc.timestamp(second, 0),
+            };
+            let body = serde_json::to_vec(&body).expect("watermark serializes");
+            match write(key.clone(), body).await {
+                Ok(()) => {
+                    emit!(WatermarkAdvanced {
+                        sink_type,
+                        partition: &prefix,
+                        watermark: second,
+                    });
+                    watermarks.published(&partition, second);
+                }
+                Err(error) => {
+                    error!(message = "Unable to write watermark; retrying later.", %key, %error);
+                }
+            }
+        }
+
+        if last {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(seconds: &[i64]) -> ObjectStats {
+        let mut stats = ObjectStats::default();
+        for second in seconds {
+            stats.add(Some(Utc.timestamp(*second, 0)));
+        }
+        stats
+    }
+
+    #[test]
+    fn watermark_holds_at_oldest_pending_event() {
+        let watermarks = Watermarks::default();
+        let partition = Bytes::from("date=2020-07-01/");
+        for second in &[10, 20, 30] {
+            watermarks.received(&partition, Some(Utc.timestamp(*second, 0)));
+        }
+        assert_eq!(watermarks.advanced(), vec![(partition.clone(), 10)]);
+        watermarks.published(&partition, 10);
+
+        // An object written out of order doesn't move the watermark past
+        // the events still pending.
+        watermarks.written(&partition, &stats(&[20, 30]));
+        assert_eq!(watermarks.advanced(), vec![]);
+
+        watermarks.written(&partition, &stats(&[10]));
+        assert_eq!(watermarks.advanced(), vec![(partition.clone(), 31)]);
+        watermarks.published(&partition, 31);
+
+        // Late events don't move it back.
+        watermarks.received(&partition, Some(Utc.timestamp(5, 0)));
+        assert_eq!(watermarks.advanced(), vec![]);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::{
+    dns::Resolver,
+    event::{
+        metric::{Metric, MetricValue},
+        Event,
+    },
+    sinks::{
+        prometheus::{
+            default_histogram_buckets, encode_namespace,
+            remote_write::{expand, histogram},
+        },
+        util::{
+            http2::{Auth, BatchedHttpSink, HttpClient, HttpSink},
+            service2::TowerRequestConfig,
+            BatchEventsConfig, Compression, UriSerde,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use futures::{FutureExt, TryFutureExt};
+use futures01::Sink;
+use http02::{Request, StatusCode, Uri};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::ResultExt;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    io::Write,
+    mem,
+    sync::Mutex,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VictoriaMetricsConfig {
+    pub endpoint: UriSerde,
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default = "default_histogram_buckets")]
+    pub buckets: Vec<f64>,
+    /// Labels added to every series by VictoriaMetrics.
+    #[serde(default)]
+    pub extra_labels: IndexMap<String, String>,
+    #[serde(default = "Compression::default_gzip")]
+    pub compression: Compression,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub auth: Option<Auth>,
+    pub tls: Option<TlsOptions>,
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<VictoriaMetricsConfig>("victoriametrics")
+}
+
+#[typetag::serde(name = "victoriametrics")]
+impl SinkConfig for VictoriaMetricsConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let healthcheck = healthcheck(self.clone(), cx.resolver(), tls.clone())
+            .boxed()
+            .compat();
+
+        let batch = self.batch.unwrap_or(10_000, 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig::default());
+
+        let sink = VictoriaMetricsSink {
+            uri: self.import_uri()?,
+            config: self.clone(),
+            totals: Mutex::new(Totals::default()),
+        };
+        let sink = BatchedHttpSink::new(sink, Vec::new(), request, batch, tls, &cx)
+            .sink_map_err(|e| error!("Fatal victoriametrics sink error: {}", e));
+
+        Ok((Box::new(sink), Box::new(healthcheck)))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "victoriametrics"
+    }
+}
+
+impl VictoriaMetricsConfig {
+    fn uri(&self, path: &str, query: Option<String>) -> crate::Result<Uri> {
+        let endpoint = self.endpoint.to_string();
+        let mut uri = format!("{}{}", endpoint.trim_end_matches('/'), path);
+        if let Some(query) = query {
+            uri.push('?');
+            uri.push_str(&query);
+        }
+        Ok(uri.parse::<Uri>().context(super::UriParseError2)?)
+    }
+
+    fn import_uri(&self) -> crate::Result<Uri> {
+        let query = if self.extra_labels.is_empty() {
+            None
+        } else {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            for (name, value) in &self.extra_labels {
+                query.append_pair("extra_label", &format!("{}={}", name, value));
+            }
+            Some(query.finish())
+        };
+        self.uri("/api/v1/import", query)
+    }
+}
+
+struct VictoriaMetricsSink {
+    config: VictoriaMetricsConfig,
+    uri: Uri,
+    totals: Mutex<Totals>,
+}
+
+impl HttpSink for VictoriaMetricsSink {
+    type Input = Metric;
+    type Output = Vec<Metric>;
+
+    fn encode_event(&self, event: Event) -> Option<Self::Input> {
+        let mut metric = event.into_metric();
+        if let MetricValue::Distribution {
+            values,
+            sample_rates,
+        } = &metric.value
+        {
+            metric.value = histogram(values, sample_rates, &self.config.buckets);
+        }
+        if metric.timestamp.is_none() {
+            metric.timestamp = Some(Utc::now());
+        }
+
+        // Batches are encoded again when retried, so incremental metrics are
+        // added up here, once per event.
+        Some(self.totals.lock().unwrap().absolute(metric))
+    }
+
+    fn build_request(&self, metrics: Self::Output) -> Request<Vec<u8>> {
+        let body = encode_metrics(&self.config.namespace, metrics);
+
+        let mut builder =
+            Request::post(self.uri.clone()).header("Content-Type", "application/json");
+        let body = match self.config.compression {
+            Compression::None => body,
+            Compression::Gzip => {
+                builder = builder.header("Content-Encoding", "gzip");
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(&body)
+                    .expect("Writing to a Vec can't fail");
+                encoder.finish().expect("Writing to a Vec can't fail")
+            }
+        };
+
+        let mut request = builder.body(body).unwrap();
+        if let Some(auth) = &self.config.auth {
+            auth.apply(&mut request);
+        }
+        request
+    }
+}
+
+/// The totals of incremental metrics, as VictoriaMetrics stores counters
+/// and histograms the way Prometheus does: as values that only reset when
+/// the process reporting them restarts.
+#[derive(Default)]
+struct Totals {
+    series: HashMap<(String, BTreeMap<String, String>), Metric>,
+}
+
+impl Totals {
+    fn absolute(&mut self, metric: Metric) -> Metric {
+        // Sets are reported as the number of distinct values seen by the
+        // source, there's nothing to add up.
+        if metric.kind.is_absolute() || metric.value.is_set() {
+            return metric.into_absolute();
+        }
+
+        let key = (metric.name.clone(), metric.tags.clone().unwrap_or_default());
+        match self.series.entry(key) {
+            Entry::Occupied(mut entry)
+                if mem::discriminant(&entry.get().value) == mem::discriminant(&metric.value) =>
+            {
+                let total = entry.get_mut();
+                total.add(&metric);
+                total.timestamp = metric.timestamp;
+                total.clone()
+            }
+            Entry::Occupied(mut entry) => {
+                let total = metric.into_absolute();
+                entry.insert(total.clone());
+                total
+            }
+            Entry::Vacant(entry) => entry.insert(metric.into_absolute()).clone(),
+        }
+    }
+}
+
+/// Encodes the metrics in the JSON line format of `/api/v1/import`: one
+/// line per series, with all of its samples.
+fn encode_metrics(namespace: &str, metrics: Vec<Metric>) -> Vec<u8> {
+    let mut series = BTreeMap::<BTreeMap<String, String>, (Vec<f64>, Vec<i64>)>::new();
+    for metric in metrics {
+        let timestamp = metric.timestamp.unwrap_or_else(Utc::now).timestamp_millis();
+        let name = encode_namespace(namespace, &metric.name);
+        for (suffix, extra, value) in expand(&metric.value) {
+            // JSON has no representation for these.
+            if !value.is_finite() {
+                continue;
+            }
+
+            let mut labels = metric.tags.clone().unwrap_or_default();
+            if let Some((label, label_value)) = extra {
+                labels.insert(label.into(), label_value);
+            }
+            labels.insert("__name__".into(), format!("{}{}", name, suffix));
+
+            let (values, timestamps) = series.entry(labels).or_default();
+            values.push(value);
+            timestamps.push(timestamp);
+        }
+    }
+
+    let mut body = Vec::new();
+    for (labels, (values, timestamps)) in series {
+        let line = json!({
+            "metric": labels,
+            "values": values,
+            "timestamps": timestamps,
+        });
+        serde_json::to_writer(&mut body, &line).expect("Writing to a Vec can't fail");
+        body.push(b'\n');
+    }
+    body
+}
+
+async fn healthcheck(
+    config: VictoriaMetricsConfig,
+    resolver: Resolver,
+    tls: TlsSettings,
+) -> crate::Result<()> {
+    let mut request = Request::get(config.uri("/health", None)?)
+        .body(hyper13::Body::empty())
+        .unwrap();
+    if let Some(auth) = &config.auth {
+        auth.apply(&mut request);
+    }
+
+    let mut client = HttpClient::new(resolver, tls)?;
+    let response = client.send(request).await?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        status => Err(super::HealthcheckError::UnexpectedStatus2 { status }.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::MetricKind;
+    use chrono::{TimeZone, Utc};
+    use serde_json::Value;
+
+    fn metric(name: &str, kind: MetricKind, value: MetricValue, secs: i64) -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), "a".to_owned());
+        Metric {
+            name: name.into(),
+            timestamp: Some(Utc.timestamp(secs, 0)),
+            tags: Some(tags),
+            kind,
+            value,
+        }
+    }
+
+    fn lines(body: &[u8]) -> Vec<Value> {
+        body.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn victoriametrics_adds_up_incremental_counters() {
+        let mut totals = Totals::default();
+        let counter = |value| {
+            metric(
+                "requests",
+                MetricKind::Incremental,
+                MetricValue::Counter { value },
+                1,
+            )
+        };
+
+        assert_eq!(
+            totals.absolute(counter(1.0)).value,
+            MetricValue::Counter { value: 1.0 }
+        );
+        let total = totals.absolute(counter(2.0));
+        assert_eq!(total.value, MetricValue::Counter { value: 3.0 });
+        assert!(total.kind.is_absolute());
+
+        let gauge = metric(
+            "requests",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value: 7.0 },
+            1,
+        );
+        assert_eq!(
+            totals.absolute(gauge).value,
+            MetricValue::Gauge { value: 7.0 }
+        );
+        assert_eq!(
+            totals.absolute(counter(1.0)).value,
+            MetricValue::Counter { value: 4.0 }
+        );
+    }
+
+    #[test]
+    fn victoriametrics_groups_samples_by_series() {
+        let body = encode_metrics(
+            "vector",
+            vec![
+                metric(
+                    "requests",
+                    MetricKind::Absolute,
+                    MetricValue::Counter { value: 1.0 },
+                    1,
+                ),
+                metric(
+                    "requests",
+                    MetricKind::Absolute,
+                    MetricValue::Counter { value: 3.0 },
+                    2,
+                ),
+            ],
+        );
+
+        let lines = lines(&body);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["metric"]["__name__"], "vector_requests");
+        assert_eq!(lines[0]["metric"]["host"], "a");
+        assert_eq!(lines[0]["values"], json!([1.0, 3.0]));
+        assert_eq!(lines[0]["timestamps"], json!([1000, 2000]));
+    }
+
+    #[test]
+    fn victoriametrics_expands_histograms() {
+        let body = encode_metrics(
+            "",
+            vec![metric(
+                "latency",
+                MetricKind::Absolute,
+                MetricValue::AggregatedHistogram {
+                    buckets: vec![1.0],
+                    counts: vec![2],
+                    count: 3,
+                    sum: 4.0,
+                },
+                1,
+            )],
+        );
+
+        let lines = lines(&body);
+        let names = lines
+            .iter()
+            .map(|line| {
+                format!(
+                    "{}{{le={}}}",
+                    line["metric"]["__name__"].as_str().unwrap(),
+                    line["metric"]["le"].as_str().unwrap_or("")
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "latency_bucket{le=+Inf}",
+                "latency_bucket{le=1}",
+                "latency_count{le=}",
+                "latency_sum{le=}",
+            ]
+        );
+    }
+
+    #[test]
+    fn victoriametrics_extra_labels() {
+        let mut extra_labels = IndexMap::new();
+        extra_labels.insert("env".to_owned(), "prod".to_owned());
+        let config = VictoriaMetricsConfig {
+            endpoint: "http://localhost:8428/".parse::<Uri>().unwrap().into(),
+            namespace: String::new(),
+            buckets: default_histogram_buckets(),
+            extra_labels,
+            compression: Compression::None,
+            batch: Default::default(),
+            request: Default::default(),
+            auth: None,
+            tls: None,
+        };
+
+        assert_eq!(
+            config.import_uri().unwrap().to_string(),
+            "http://localhost:8428/api/v1/import?extra_label=env%3Dprod"
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{
+    sinks::{
+        util::{
+            http2::BatchedHttpSink,
+            line_protocol::{LineProtocol, LineProtocolSink, Sample, SampleKind},
+            service2::TowerRequestConfig,
+            BatchEventsConfig, UriSerde,
+        },
+        Healthcheck, RouterSink,
+    },
+    tls::{TlsOptions, TlsSettings},
+    topology::config::{DataType, SinkConfig, SinkContext, SinkDescription},
+};
+use futures01::{future, Sink};
+use http02::{Request, Uri};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WavefrontConfig {
+    /// Either a Wavefront proxy or the cluster for direct ingestion.
+    pub endpoint: UriSerde,
+    /// The API token, only needed for direct ingestion.
+    pub token: Option<String>,
+    pub namespace: Option<String>,
+    /// Tags added to every point, unless the metric has them already.
+    #[serde(default)]
+    pub tags: IndexMap<String, String>,
+    /// The tag used as the source of the points.
+    #[serde(default = "default_source_tag")]
+    pub source_tag: String,
+    /// The source of points without the `source_tag`.
+    pub source: Option<String>,
+    #[serde(default)]
+    pub batch: BatchEventsConfig,
+    #[serde(default)]
+    pub request: TowerRequestConfig,
+    pub tls: Option<TlsOptions>,
+}
+
+fn default_source_tag() -> String {
+    "host".into()
+}
+
+inventory::submit! {
+    SinkDescription::new_without_default::<WavefrontConfig>("wavefront")
+}
+
+#[typetag::serde(name = "wavefront")]
+impl SinkConfig for WavefrontConfig {
+    fn build(&self, cx: SinkContext) -> crate::Result<(RouterSink, Healthcheck)> {
+        let tls = TlsSettings::from_options(&self.tls)?;
+        let batch = self.batch.unwrap_or(1000, 1);
+        let request = self.request.unwrap_with(&TowerRequestConfig::default());
+
+        let protocol = Wavefront {
+            uri: self.uri()?,
+            token: self.token.clone(),
+            source_tag: self.source_tag.clone(),
+            source: self
+                .source
+                .clone()
+                .or_else(hostname::get_hostname)
+                .unwrap_or_else(|| "vector".into()),
+        };
+        let sink = LineProtocolSink::new(protocol, self.namespace.clone(), self.tags.clone());
+        let sink = BatchedHttpSink::new(sink, Vec::new(), request, batch, tls, &cx)
+            .sink_map_err(|e| error!("Fatal wavefront sink error: {}", e));
+
+        // Neither proxies nor direct ingestion have an endpoint to check
+        // that points would be accepted.
+        Ok((Box::new(sink), Box::new(future::ok(()))))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn sink_type(&self) -> &'static str {
+        "wavefront"
+    }
+}
+
+impl WavefrontConfig {
+    fn uri(&self) -> crate::Result<Uri> {
+        let endpoint = self.endpoint.to_string();
+        let uri = format!("{}/report?f=wavefront", endpoint.trim_end_matches('/'));
+        Ok(uri.parse::<Uri>().context(super::UriParseError2)?)
+    }
+}
+
+/// The Wavefront data format, `name value timestamp source=source tags`.
+struct Wavefront {
+    uri: Uri,
+    token: Option<String>,
+    source_tag: String,
+    source: String,
+}
+
+impl LineProtocol for Wavefront {
+    fn encode_sample(&self, sample: &Sample, output: &mut String) {
+        if sample.kind == SampleKind::Delta {
+            output.push('∆');
+        }
+        output.push_str(&sanitize(&sample.name));
+        output.push_str(&format!(
+            " {} {} source=",
+            sample.value,
+            sample.timestamp.timestamp()
+        ));
+        let source = sample.tags.get(&self.source_tag).unwrap_or(&self.source);
+        encode_string(source, output);
+
+        for (key, value) in &sample.tags {
+            if key == &self.source_tag {
+                continue;
+            }
+            output.push(' ');
+            output.push_str(&sanitize(key));
+            output.push('=');
+            encode_string(value, output);
+        }
+    }
+
+    fn build_request(&self, body: Vec<u8>) -> Request<Vec<u8>> {
+        let mut builder = Request::post(self.uri.clone()).header("Content-Type", "text/plain");
+        if let Some(token) = &self.token {
+            builder = builder.header("Authorization", format!("Bearer {}", token));
+        }
+        builder.body(body).unwrap()
+    }
+}
+
+/// Replaces the characters Wavefront doesn't accept in metric names and tag
+/// keys.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '-' | '_' | '.' => c,
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn encode_string(value: &str, output: &mut String) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\n' => output.push_str("\\n"),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::collections::BTreeMap;
+
+    fn wavefront() -> Wavefront {
+        Wavefront {
+            uri: "http://proxy:2878/report?f=wavefront".parse().unwrap(),
+            token: None,
+            source_tag: "host".into(),
+            source: "vector".into(),
+        }
+    }
+
+    fn sample(kind: SampleKind, tags: &[(&str, &str)]) -> Sample {
+        Sample {
+            name: "vector.requests total".into(),
+            kind,
+            value: 1.5,
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            timestamp: Utc.timestamp(1591005600, 0),
+        }
+    }
+
+    fn encode(sample: Sample) -> String {
+        let mut output = String::new();
+        wavefront().encode_sample(&sample, &mut output);
+        output
+    }
+
+    #[test]
+    fn wavefront_encodes_points() {
+        assert_eq!(
+            encode(sample(
+                SampleKind::Gauge,
+                &[("host", "web01"), ("path", "/\"home\"")]
+            )),
+            r#"vector.requests_total 1.5 1591005600 source="web01" path="/\"home\"""#
+        );
+        assert_eq!(
+            encode(sample(SampleKind::Delta, &[])),
+            r#"∆vector.requests_total 1.5 1591005600 source="vector""#
+        );
+    }
+
+    #[test]
+    fn wavefront_authenticates_direct_ingestion() {
+        let config: WavefrontConfig = toml::from_str(
+            r#"
+            endpoint = "https://example.wavefront.com/"
+            token = "secret"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.uri().unwrap(),
+            "https://example.wavefront.com/report?f=wavefront"
+        );
+
+        let mut protocol = wavefront();
+        protocol.token = config.token;
+        let request = protocol.build_request(Vec::new());
+        assert_eq!(request.headers()["Authorization"], "Bearer secret");
+        assert!(wavefront()
+            .build_request(Vec::new())
+            .headers()
+            .get("Authorization")
+            .is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{
+    amqp,
+    event::{self, Event},
+    internal_events::{AmqpAckFailed, AmqpConsumeFailed, AmqpMessageInvalid, AmqpMessageReceived},
+    shutdown::ShutdownSignal,
+    tls::TlsOptions,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+    stream::StreamExt,
+};
+use futures01::{sync::mpsc, Sink};
+use lapin::{
+    message::Delivery,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions},
+    types::FieldTable,
+};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::time::Duration;
+use tokio::time::delay_for;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`prefetch_count` must be at least 1"))]
+    NoPrefetch,
+}
+
+#[derive(Debug, Snafu)]
+pub enum ConsumeError {
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: crate::Error },
+    #[snafu(display("Unable to consume from the queue: {}", source))]
+    Consume { source: lapin::Error },
+    #[snafu(display("The broker cancelled the consumer"))]
+    Cancelled,
+    #[snafu(display("The pipeline closed"))]
+    PipelineClosed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AmqpSourceConfig {
+    pub uri: String,
+    pub queue: String,
+    #[serde(default = "default_consumer_tag")]
+    pub consumer_tag: String,
+    /// How many messages the broker delivers ahead of their
+    /// acknowledgement.
+    #[serde(default = "default_prefetch_count")]
+    pub prefetch_count: u16,
+    #[serde(default)]
+    pub decoding: Decoding,
+    #[serde(default = "default_exchange_key")]
+    pub exchange_key: String,
+    #[serde(default = "default_routing_key_key")]
+    pub routing_key_key: String,
+    pub tls: Option<TlsOptions>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoding {
+    /// The message is the body as is.
+    Text,
+    /// The body is a JSON object, whose fields become those of the event.
+    Json,
+}
+
+impl Default for Decoding {
+    fn default() -> Self {
+        Decoding::Text
+    }
+}
+
+fn default_consumer_tag() -> String {
+    "vector".into()
+}
+
+fn default_prefetch_count() -> u16 {
+    100
+}
+
+fn default_exchange_key() -> String {
+    "exchange".into()
+}
+
+fn default_routing_key_key() -> String {
+    "routing_key".into()
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<AmqpSourceConfig>("amqp")
+}
+
+#[typetag::serde(name = "amqp")]
+impl SourceConfig for AmqpSourceConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.prefetch_count == 0 {
+            return Err(Box::new(BuildError::NoPrefetch));
+        }
+        Ok(Box::new(run(self.clone(), shutdown, out).boxed().compat()))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "amqp"
+    }
+}
+
+async fn run(
+    config: AmqpSourceConfig,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut shutdown = shutdown.compat();
+    loop {
+        let consume = Box::pin(consume(&config, out.clone()));
+        match select(consume, &mut shutdown).await {
+            // The messages not acknowledged yet are redelivered once the
+            // channel closes.
+            Either::Right(_) => return Ok(()),
+            Either::Left((Err(ConsumeError::PipelineClosed), _)) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Either::Left((Err(error), _)) => emit!(AmqpConsumeFailed { error }),
+            Either::Left((Ok(()), _)) => unreachable!("consuming only ends with an error"),
+        }
+
+        let wait = Box::pin(delay_for(Duration::from_secs(1)));
+        if let Either::Right(_) = select(wait, &mut shutdown).await {
+            return Ok(());
+        }
+    }
+}
+
+/// Consumes until the connection fails, acknowledging each message once
+/// its event was sent downstream.
+async fn consume(
+    config: &AmqpSourceConfig,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ConsumeError> {
+    let connection = amqp::connect(&config.uri, &config.tls)
+        .await
+        .context(Connect)?;
+    let channel = connection.create_channel().await.context(Consume)?;
+    channel
+        .basic_qos(config.prefetch_count, BasicQosOptions::default())
+        .await
+        .context(Consume)?;
+    let mut consumer = channel
+        .basic_consume(
+            &config.queue,
+            &config.consumer_tag,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .context(Consume)?;
+    info!(message = "consuming.", queue = %config.queue);
+
+    while let Some(delivery) = consumer.next().await {
+        let (channel, delivery) = delivery.context(Consume)?;
+        emit!(AmqpMessageReceived {
+            byte_size: delivery.data.len(),
+        });
+
+        match create_event(&delivery, config) {
+            Ok(event) => {
+                out = out
+                    .send(event)
+                    .compat()
+                    .await
+                    .map_err(|_| ConsumeError::PipelineClosed)?;
+                let ack = channel.basic_ack(delivery.delivery_tag, BasicAckOptions::default());
+                if let Err(error) = ack.await {
+                    emit!(AmqpAckFailed { error });
+                }
+            }
+            // Rejected without requeueing, so the broker dead-letters it
+            // if the queue has a dead letter exchange.
+            Err(error) => {
+                emit!(AmqpMessageInvalid { error });
+                let options = BasicNackOptions {
+                    multiple: false,
+                    requeue: false,
+                };
+                if let Err(error) = channel.basic_nack(delivery.delivery_tag, options).await {
+                    emit!(AmqpAckFailed { error });
+                }
+            }
+        }
+    }
+
+    Err(ConsumeError::Cancelled)
+}
+
+fn create_event(
+    delivery: &Delivery,
+    config: &AmqpSourceConfig,
+) -> Result<Event, serde_json::Error> {
+    let mut event = match config.decoding {
+        Decoding::Text => Event::from(Bytes::from(delivery.data.clone())),
+        Decoding::Json => {
+            let object: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(&delivery.data)?;
+            let mut event = Event::new_empty_log();
+            let log = event.as_mut_log();
+            for (key, value) in object {
+                log.insert(key, value);
+            }
+            event
+        }
+    };
+
+    let log = event.as_mut_log();
+    let timestamp = match delivery.properties.timestamp() {
+        Some(secs) => Utc.timestamp(*secs as i64, 0),
+        None => Utc::now(),
+    };
+    log.insert(event::log_schema().timestamp_key(), timestamp);
+    log.insert(event::log_schema().source_type_key(), "amqp");
+    log.insert(&config.exchange_key, delivery.exchange.as_str());
+    log.insert(&config.routing_key_key, delivery.routing_key.as_str());
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lapin::{types::ShortString, BasicProperties};
+
+    fn config(decoding: &str) -> AmqpSourceConfig {
+        toml::from_str(&format!(
+            r#"
+            uri = "amqp://localhost:5672/%2f"
+            queue = "logs"
+            decoding = "{}"
+            "#,
+            decoding
+        ))
+        .unwrap()
+    }
+
+    fn delivery(data: &[u8]) -> Delivery {
+        Delivery {
+            delivery_tag: 1,
+            exchange: ShortString::from("logs"),
+            routing_key: ShortString::from("app.web"),
+            redelivered: false,
+            properties: BasicProperties::default().with_timestamp(1_590_000_000),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn amqp_creates_events() {
+        let event = create_event(&delivery(b"hello"), &config("text")).unwrap();
+        let log = event.as_log();
+        assert_eq!(log[&event::log_schema().message_key()], "hello".into());
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            Utc.timestamp(1_590_000_000, 0).into()
+        );
+        assert_eq!(log[&"exchange".into()], "logs".into());
+        assert_eq!(log[&"routing_key".into()], "app.web".into());
+        assert_eq!(log[&event::log_schema().source_type_key()], "amqp".into());
+
+        let event = create_event(&delivery(br#"{"status":200}"#), &config("json")).unwrap();
+        assert_eq!(event.as_log()[&"status".into()], 200.into());
+
+        assert!(create_event(&delivery(b"hello"), &config("json")).is_err());
+        assert!(create_event(&delivery(b"[1, 2]"), &config("json")).is_err());
+    }
+}
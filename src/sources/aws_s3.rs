@@ -0,0 +1,570 @@
+use crate::{
+    event::{self, Event},
+    internal_events::{
+        AwsS3ObjectFailed, AwsS3ObjectProcessed, AwsSqsMessageDeleteFailed,
+        AwsSqsNotificationInvalid, AwsSqsReceiveFailed,
+    },
+    region::RegionOrEndpoint,
+    replay::{Range, TimeWindow},
+    shutdown::ShutdownSignal,
+    sinks::util::rusoto::AwsCredentialsProvider,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use futures::{
+    compat::Future01CompatExt,
+    future::{FutureExt, TryFutureExt},
+};
+use futures01::{stream::iter_ok, sync::mpsc, Future, Sink, Stream};
+use rusoto_core::{HttpClient, Region};
+use rusoto_s3::{GetObjectRequest, ListObjectsV2Request, S3Client, S3};
+use rusoto_sqs::{DeleteMessageRequest, Message, ReceiveMessageRequest, Sqs, SqsClient};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{convert::TryInto, io::Read, time::Duration};
+use tokio::time::delay_for;
+use url::percent_encoding::percent_decode;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("The framing delimiter must be an ASCII character"))]
+    NonAsciiDelimiter,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AwsS3Config {
+    #[serde(flatten)]
+    pub region: RegionOrEndpoint,
+    pub assume_role: Option<String>,
+    pub sqs: SqsConfig,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub framing: Framing,
+    /// Objects whose notifications are timestamped outside of these times
+    /// are skipped, and their notifications deleted.
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SqsConfig {
+    /// The queue receiving the object created notifications of the buckets.
+    pub queue_url: String,
+    /// How long a receive waits for messages, at most 20 seconds.
+    #[serde(default = "default_poll_secs")]
+    pub poll_secs: u32,
+    /// How long received messages are hidden from other consumers. Messages
+    /// whose objects aren't processed by then are received again.
+    #[serde(default = "default_visibility_timeout_secs")]
+    pub visibility_timeout_secs: u32,
+}
+
+fn default_poll_secs() -> u32 {
+    20
+}
+
+fn default_visibility_timeout_secs() -> u32 {
+    300
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// Detected from the `Content-Encoding` of the object, its extension or
+    /// its first bytes.
+    Auto,
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Auto
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Framing {
+    NewlineDelimited,
+    CharacterDelimited {
+        delimiter: char,
+    },
+    /// The whole object is a single event.
+    Bytes,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::NewlineDelimited
+    }
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<AwsS3Config>("aws_s3")
+}
+
+#[typetag::serde(name = "aws_s3")]
+impl SourceConfig for AwsS3Config {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if let Framing::CharacterDelimited { delimiter } = self.framing {
+            if !delimiter.is_ascii() {
+                return Err(Box::new(BuildError::NonAsciiDelimiter));
+            }
+        }
+
+        let source = AwsS3Source::new(self.clone())?;
+        Ok(Box::new(source.run(shutdown, out).boxed().compat()))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "aws_s3"
+    }
+}
+
+/// The parts of the S3 event notifications needed to read the objects.
+#[derive(Deserialize, Debug)]
+struct S3EventNotification {
+    /// Missing from the test events S3 sends when notifications are set up.
+    #[serde(rename = "Records", default)]
+    records: Vec<S3EventRecord>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct S3EventRecord {
+    event_name: String,
+    event_time: Option<DateTime<Utc>>,
+    aws_region: String,
+    s3: S3Entity,
+}
+
+#[derive(Deserialize, Debug)]
+struct S3Entity {
+    bucket: S3Bucket,
+    object: S3Object,
+}
+
+#[derive(Deserialize, Debug)]
+struct S3Bucket {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct S3Object {
+    /// URL encoded, with spaces as `+`.
+    key: String,
+}
+
+struct AwsS3Source {
+    config: AwsS3Config,
+    window: TimeWindow,
+    region: Region,
+    s3: S3Client,
+    sqs: SqsClient,
+}
+
+impl AwsS3Source {
+    fn new(config: AwsS3Config) -> crate::Result<Self> {
+        let region: Region = (&config.region).try_into()?;
+        let s3 = S3Client::new_with(
+            HttpClient::new()?,
+            AwsCredentialsProvider::new(&region, config.assume_role.clone())?,
+            region.clone(),
+        );
+        let sqs = SqsClient::new_with(
+            HttpClient::new()?,
+            AwsCredentialsProvider::new(&region, config.assume_role.clone())?,
+            region.clone(),
+        );
+
+        Ok(Self {
+            window: TimeWindow::new(config.since, config.until),
+            config,
+            region,
+            s3,
+            sqs,
+        })
+    }
+
+    async fn run(
+        self,
+        mut shutdown: ShutdownSignal,
+        mut out: mpsc::Sender<Event>,
+    ) -> Result<(), ()> {
+        loop {
+            if shutdown.poll().expect("polling shutdown").is_ready() {
+                break;
+            }
+
+            let request = ReceiveMessageRequest {
+                queue_url: self.config.sqs.queue_url.clone(),
+                max_number_of_messages: Some(10),
+                wait_time_seconds: Some(i64::from(self.config.sqs.poll_secs)),
+                visibility_timeout: Some(i64::from(self.config.sqs.visibility_timeout_secs)),
+                ..Default::default()
+            };
+            let messages = match self.sqs.receive_message(request).compat().await {
+                Ok(result) => result.messages.unwrap_or_default(),
+                Err(error) => {
+                    emit!(AwsSqsReceiveFailed { error });
+                    delay_for(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            for message in messages {
+                out = self.handle_message(message, out).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the events of the objects the message notifies about, then
+    /// deletes it. Messages whose objects fail are left to be received again.
+    async fn handle_message(
+        &self,
+        message: Message,
+        mut out: mpsc::Sender<Event>,
+    ) -> Result<mpsc::Sender<Event>, ()> {
+        let body = message.body.unwrap_or_default();
+        let notification = match serde_json::from_str::<S3EventNotification>(&body) {
+            Ok(notification) => notification,
+            Err(error) => {
+                emit!(AwsSqsNotificationInvalid { error });
+                return Ok(out);
+            }
+        };
+
+        for record in notification.records {
+            if !record.event_name.starts_with("ObjectCreated:") {
+                continue;
+            }
+            if !self.window.contains(record.event_time) {
+                continue;
+            }
+
+            let key = decode_key(&record.s3.object.key);
+            let events = match self
+                .read_object(&record.s3.bucket.name, &record.aws_region, &key)
+                .await
+            {
+                Ok(events) => events,
+                Err(error) => {
+                    emit!(AwsS3ObjectFailed {
+                        bucket: &record.s3.bucket.name,
+                        key: &key,
+                        error,
+                    });
+                    return Ok(out);
+                }
+            };
+
+            // The events are only deleted once the pipeline accepted them.
+            let (sink, _) = out
+                .send_all(iter_ok(events))
+                .compat()
+                .await
+                .map_err(|error| error!(message = "error sending events", %error))?;
+            out = sink;
+        }
+
+        if let Some(receipt_handle) = message.receipt_handle {
+            let request = DeleteMessageRequest {
+                queue_url: self.config.sqs.queue_url.clone(),
+                receipt_handle,
+            };
+            if let Err(error) = self.sqs.delete_message(request).compat().await {
+                emit!(AwsSqsMessageDeleteFailed { error });
+            }
+        }
+
+        Ok(out)
+    }
+
+    async fn read_object(
+        &self,
+        bucket: &str,
+        region: &str,
+        key: &str,
+    ) -> crate::Result<Vec<Event>> {
+        let request = GetObjectRequest {
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            ..Default::default()
+        };
+        let object = self.s3.get_object(request).compat().await?;
+        let body = match object.body {
+            Some(body) => body.concat2().compat().await?,
+            None => Bytes::new(),
+        };
+        let byte_size = body.len();
+
+        let compression = match self.config.compression {
+            Compression::Auto => detect_compression(object.content_encoding.as_deref(), key, &body),
+            compression => compression,
+        };
+        let body = decompress(compression, body)?;
+
+        let events = frame(&self.config.framing, body)
+            .into_iter()
+            .map(|frame| {
+                let mut event = Event::from(frame);
+                let log = event.as_mut_log();
+                log.insert(event::log_schema().source_type_key(), "aws_s3");
+                log.insert("bucket", bucket);
+                log.insert("object", key);
+                log.insert("region", region);
+                event
+            })
+            .collect::<Vec<_>>();
+
+        emit!(AwsS3ObjectProcessed {
+            bucket,
+            key,
+            count: events.len(),
+            byte_size,
+        });
+        Ok(events)
+    }
+}
+
+/// Sends the events of the objects of the bucket under the prefix that were
+/// last modified within the range, in the order S3 lists them.
+pub(crate) async fn replay(
+    config: AwsS3Config,
+    bucket: String,
+    range: Range,
+    mut out: mpsc::Sender<Event>,
+) -> crate::Result<()> {
+    let source = AwsS3Source::new(config)?;
+    let region = source.region.name().to_owned();
+
+    let mut continuation_token = None;
+    loop {
+        let request = ListObjectsV2Request {
+            bucket: bucket.clone(),
+            prefix: range.prefix.clone(),
+            continuation_token,
+            ..Default::default()
+        };
+        let listing = source.s3.list_objects_v2(request).compat().await?;
+
+        for object in listing.contents.unwrap_or_default() {
+            let key = match object.key {
+                Some(key) => key,
+                None => continue,
+            };
+            let modified = object
+                .last_modified
+                .and_then(|modified| DateTime::parse_from_rfc3339(&modified).ok())
+                .map(|modified| modified.with_timezone(&Utc));
+            if !range.window.contains(modified) {
+                continue;
+            }
+
+            let events = match source.read_object(&bucket, &region, &key).await {
+                Ok(events) => events,
+                Err(error) => {
+                    emit!(AwsS3ObjectFailed {
+                        bucket: &bucket,
+                        key: &key,
+                        error,
+                    });
+                    continue;
+                }
+            };
+            out = out.send_all(iter_ok(events)).compat().await?.0;
+        }
+
+        continuation_token = listing.next_continuation_token;
+        if continuation_token.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+fn decode_key(key: &str) -> String {
+    percent_decode(key.replace('+', " ").as_bytes())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+fn detect_compression(content_encoding: Option<&str>, key: &str, body: &[u8]) -> Compression {
+    match content_encoding {
+        Some("gzip") => return Compression::Gzip,
+        Some("zstd") => return Compression::Zstd,
+        _ => (),
+    }
+    if key.ends_with(".gz") || body.starts_with(&[0x1f, 0x8b]) {
+        Compression::Gzip
+    } else if key.ends_with(".zst") || body.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+fn decompress(compression: Compression, body: Bytes) -> std::io::Result<Bytes> {
+    let mut decompressed = Vec::new();
+    match compression {
+        Compression::Auto | Compression::None => return Ok(body),
+        Compression::Gzip => MultiGzDecoder::new(&body[..]).read_to_end(&mut decompressed)?,
+        Compression::Zstd => zstd::Decoder::new(&body[..])?.read_to_end(&mut decompressed)?,
+    };
+    Ok(decompressed.into())
+}
+
+fn frame(framing: &Framing, body: Bytes) -> Vec<Bytes> {
+    let delimiter = match framing {
+        Framing::NewlineDelimited => b'\n',
+        Framing::CharacterDelimited { delimiter } => *delimiter as u8,
+        Framing::Bytes if body.is_empty() => return Vec::new(),
+        Framing::Bytes => return vec![body],
+    };
+
+    let mut frames = Vec::new();
+    let mut start = 0;
+    for end in body
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == delimiter)
+        .map(|(position, _)| position)
+        .chain(std::iter::once(body.len()))
+    {
+        let mut frame_end = end;
+        if delimiter == b'\n' && frame_end > start && body[frame_end - 1] == b'\r' {
+            frame_end -= 1;
+        }
+        if frame_end > start {
+            frames.push(body.slice(start, frame_end));
+        }
+        start = end + 1;
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Write;
+
+    #[test]
+    fn aws_s3_parses_notifications() {
+        let notification: S3EventNotification = serde_json::from_str(
+            r#"{"Records":[{
+                "eventVersion":"2.1",
+                "eventSource":"aws:s3",
+                "awsRegion":"us-east-1",
+                "eventName":"ObjectCreated:Put",
+                "eventTime":"2020-06-01T12:00:00.123Z",
+                "s3":{
+                    "bucket":{"name":"logs","arn":"arn:aws:s3:::logs"},
+                    "object":{"key":"2020/06/01/app+logs%281%29.log.gz","size":1024}
+                }
+            }]}"#,
+        )
+        .unwrap();
+        let record = &notification.records[0];
+        assert_eq!(record.event_name, "ObjectCreated:Put");
+        assert_eq!(
+            record.event_time,
+            Some(Utc.ymd(2020, 6, 1).and_hms_milli(12, 0, 0, 123))
+        );
+        assert_eq!(record.s3.bucket.name, "logs");
+        assert_eq!(
+            decode_key(&record.s3.object.key),
+            "2020/06/01/app logs(1).log.gz"
+        );
+
+        let test_event: S3EventNotification = serde_json::from_str(
+            r#"{"Service":"Amazon S3","Event":"s3:TestEvent","Bucket":"logs"}"#,
+        )
+        .unwrap();
+        assert!(test_event.records.is_empty());
+    }
+
+    #[test]
+    fn aws_s3_decompresses_objects() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(b"gzipped").unwrap();
+        let gzipped = Bytes::from(encoder.finish().unwrap());
+        assert_eq!(
+            detect_compression(None, "object", &gzipped),
+            Compression::Gzip
+        );
+        assert_eq!(decompress(Compression::Gzip, gzipped).unwrap(), "gzipped");
+
+        let zstd = Bytes::from(zstd::encode_all(&b"zstd"[..], 0).unwrap());
+        assert_eq!(detect_compression(None, "object", &zstd), Compression::Zstd);
+        assert_eq!(decompress(Compression::Zstd, zstd).unwrap(), "zstd");
+
+        assert_eq!(
+            detect_compression(Some("gzip"), "object", b"plain"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            detect_compression(None, "object.log", b"plain"),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn aws_s3_frames_objects() {
+        let body = Bytes::from("one\r\ntwo\n\nthree");
+        assert_eq!(
+            frame(&Framing::NewlineDelimited, body.clone()),
+            vec!["one", "two", "three"]
+        );
+        assert_eq!(frame(&Framing::Bytes, body.clone()), vec![body]);
+        assert_eq!(
+            frame(
+                &Framing::CharacterDelimited { delimiter: '\0' },
+                Bytes::from("a\0b\0")
+            ),
+            vec!["a", "b"]
+        );
+        assert!(frame(&Framing::Bytes, Bytes::new()).is_empty());
+    }
+
+    #[test]
+    fn aws_s3_parses_framing_config() {
+        let config: AwsS3Config = toml::from_str(
+            r#"
+            region = "us-east-1"
+
+            [sqs]
+            queue_url = "https://sqs.us-east-1.amazonaws.com/123456789012/logs"
+
+            [framing]
+            method = "character_delimited"
+            delimiter = "|"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.framing,
+            Framing::CharacterDelimited { delimiter: '|' }
+        );
+        assert_eq!(config.compression, Compression::Auto);
+        assert_eq!(config.sqs.poll_secs, 20);
+    }
+}
@@ -0,0 +1,1226 @@
+//! A minimal AMQP 1.0 client, enough to receive from Event Hubs: an
+//! anonymous SASL exchange, a single session, links, and the request and
+//! response pattern of the claims-based security and management nodes.
+//!
+//! Values are always encoded in their widest form, which every peer has to
+//! accept, and decoded in any form.
+
+use crate::tls::{MaybeTlsSettings, MaybeTlsStream, TlsError};
+use bytes::{Bytes, BytesMut};
+use futures::{
+    compat::{Compat01As03Sink, Future01CompatExt, Sink01CompatExt},
+    SinkExt, StreamExt,
+};
+use snafu::{ResultExt, Snafu};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+use tokio01::net::TcpStream;
+use tokio_codec::{Decoder, Encoder, Framed};
+
+const AMQPS_PORT: u16 = 5671;
+
+/// The largest frame the peer may send, larger messages are split across
+/// transfers.
+const MAX_FRAME_SIZE: u32 = 256 * 1024;
+
+/// The peer has to send a frame at least this often, or is considered gone.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Sends an empty frame when nothing else was sent for this long, so the
+/// peer doesn't consider us gone.
+pub const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// The transfers either end of the session may have in flight, large
+/// enough for link credit to be the limit.
+const SESSION_WINDOW: u32 = 0x7fff_ffff;
+
+const AMQP_PROTOCOL: u8 = 0;
+const SASL_PROTOCOL: u8 = 3;
+
+const AMQP_FRAME: u8 = 0;
+const SASL_FRAME: u8 = 1;
+
+const SASL_MECHANISMS: u64 = 0x40;
+const SASL_INIT: u64 = 0x41;
+const SASL_OUTCOME: u64 = 0x44;
+
+const OPEN: u64 = 0x10;
+const BEGIN: u64 = 0x11;
+const ATTACH: u64 = 0x12;
+const FLOW: u64 = 0x13;
+const TRANSFER: u64 = 0x14;
+const DISPOSITION: u64 = 0x15;
+const DETACH: u64 = 0x16;
+const END: u64 = 0x17;
+const CLOSE: u64 = 0x18;
+
+const ACCEPTED: u64 = 0x24;
+const SOURCE: u64 = 0x28;
+const TARGET: u64 = 0x29;
+
+const PROPERTIES: u64 = 0x73;
+const MESSAGE_ANNOTATIONS: u64 = 0x72;
+const APPLICATION_PROPERTIES: u64 = 0x74;
+const DATA: u64 = 0x75;
+const AMQP_SEQUENCE: u64 = 0x76;
+const AMQP_VALUE: u64 = 0x77;
+
+/// The sender settles its deliveries when sending them.
+const SETTLED: u8 = 1;
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum DecodeError {
+    #[snafu(display("Value ends early"))]
+    Truncated,
+    #[snafu(display("Unknown type constructor {:#04x}", constructor))]
+    UnknownType { constructor: u8 },
+    #[snafu(display("String isn't valid UTF-8"))]
+    InvalidUtf8,
+    #[snafu(display("Invalid character {:#x}", code))]
+    InvalidChar { code: u32 },
+    #[snafu(display("Invalid frame size {}", size))]
+    InvalidFrameSize { size: usize },
+    #[snafu(display("Frame body isn't a performative"))]
+    InvalidPerformative,
+    #[snafu(display("Message section isn't described"))]
+    InvalidSection,
+}
+
+#[derive(Debug, Snafu)]
+pub enum AmqpError {
+    #[snafu(display("Unable to resolve {}: {}", host, source))]
+    Resolve { host: String, source: io::Error },
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: TlsError },
+    #[snafu(display("Connection failed: {}", source))]
+    Io { source: io::Error },
+    #[snafu(display("Invalid frame: {}", source))]
+    Decode { source: DecodeError },
+    #[snafu(display("Connection closed"))]
+    Disconnected,
+    #[snafu(display("No frame received for {:?}", IDLE_TIMEOUT))]
+    IdleTimeout,
+    #[snafu(display("Authentication failed with SASL outcome {}", code))]
+    SaslFailed { code: u8 },
+    #[snafu(display("Expected {} from the peer", expected))]
+    Unexpected { expected: &'static str },
+    #[snafu(display("Closed by the peer: {}", error))]
+    Closed { error: String },
+    #[snafu(display("Link detached by the peer: {}", error))]
+    Detached { error: String },
+    #[snafu(display("Request failed with status {}: {}", status, description))]
+    Status { status: i64, description: String },
+}
+
+impl From<io::Error> for AmqpError {
+    fn from(source: io::Error) -> Self {
+        AmqpError::Io { source }
+    }
+}
+
+impl From<DecodeError> for AmqpError {
+    fn from(source: DecodeError) -> Self {
+        AmqpError::Decode { source }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Ubyte(u8),
+    Ushort(u16),
+    Uint(u32),
+    Ulong(u64),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Char(char),
+    /// Milliseconds since the Unix epoch.
+    Timestamp(i64),
+    Uuid([u8; 16]),
+    Binary(Bytes),
+    String(String),
+    Symbol(String),
+    List(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Array(Vec<Value>),
+    Described(Box<Value>, Box<Value>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) | Value::Symbol(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::Ubyte(value) => Some((*value).into()),
+            Value::Ushort(value) => Some((*value).into()),
+            Value::Uint(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Byte(value) => Some((*value).into()),
+            Value::Short(value) => Some((*value).into()),
+            Value::Int(value) => Some((*value).into()),
+            Value::Long(value) => Some(*value),
+            _ => self.as_u32().map(Into::into),
+        }
+    }
+
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.constructor());
+        self.encode_body(buf);
+    }
+
+    fn constructor(&self) -> u8 {
+        match self {
+            Value::Null => 0x40,
+            Value::Bool(true) => 0x41,
+            Value::Bool(false) => 0x42,
+            Value::Ubyte(_) => 0x50,
+            Value::Ushort(_) => 0x60,
+            Value::Uint(_) => 0x70,
+            Value::Ulong(_) => 0x80,
+            Value::Byte(_) => 0x51,
+            Value::Short(_) => 0x61,
+            Value::Int(_) => 0x71,
+            Value::Long(_) => 0x81,
+            Value::Float(_) => 0x72,
+            Value::Double(_) => 0x82,
+            Value::Char(_) => 0x73,
+            Value::Timestamp(_) => 0x83,
+            Value::Uuid(_) => 0x98,
+            Value::Binary(_) => 0xb0,
+            Value::String(_) => 0xb1,
+            Value::Symbol(_) => 0xb3,
+            Value::List(_) => 0xd0,
+            Value::Map(_) => 0xd1,
+            Value::Array(_) => 0xf0,
+            Value::Described(..) => 0x00,
+        }
+    }
+
+    fn encode_body(&self, buf: &mut Vec<u8>) {
+        match self {
+            Value::Null | Value::Bool(_) => (),
+            Value::Ubyte(value) => buf.push(*value),
+            Value::Ushort(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Uint(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Ulong(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Byte(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Short(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Int(value) => buf.extend_from_slice(&value.to_be_bytes()),
+            Value::Long(value) | Value::Timestamp(value) => {
+                buf.extend_from_slice(&value.to_be_bytes())
+            }
+            Value::Float(value) => buf.extend_from_slice(&value.to_bits().to_be_bytes()),
+            Value::Double(value) => buf.extend_from_slice(&value.to_bits().to_be_bytes()),
+            Value::Char(value) => buf.extend_from_slice(&(*value as u32).to_be_bytes()),
+            Value::Uuid(value) => buf.extend_from_slice(value),
+            Value::Binary(value) => encode_variable(buf, value),
+            Value::String(value) | Value::Symbol(value) => encode_variable(buf, value.as_bytes()),
+            Value::List(values) => encode_compound(buf, values.len(), |buf| {
+                values.iter().for_each(|value| value.encode(buf))
+            }),
+            Value::Map(pairs) => encode_compound(buf, pairs.len() * 2, |buf| {
+                for (key, value) in pairs {
+                    key.encode(buf);
+                    value.encode(buf);
+                }
+            }),
+            // Arrays share the constructor of their first element.
+            Value::Array(values) => encode_compound(buf, values.len(), |buf| {
+                buf.push(values.first().map_or(0x40, Value::constructor));
+                values.iter().for_each(|value| value.encode_body(buf));
+            }),
+            Value::Described(descriptor, value) => {
+                descriptor.encode(buf);
+                value.encode(buf);
+            }
+        }
+    }
+}
+
+fn encode_variable(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn encode_compound(buf: &mut Vec<u8>, count: usize, encode: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&(count as u32).to_be_bytes());
+    encode(buf);
+    let size = (buf.len() - start - 4) as u32;
+    buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn described(code: u64, value: Value) -> Value {
+    Value::Described(Box::new(Value::Ulong(code)), Box::new(value))
+}
+
+fn decode(reader: &mut Reader) -> Result<Value, DecodeError> {
+    let constructor = reader.u8()?;
+    if constructor == 0x00 {
+        let descriptor = decode(reader)?;
+        let value = decode(reader)?;
+        return Ok(Value::Described(Box::new(descriptor), Box::new(value)));
+    }
+    decode_body(constructor, reader)
+}
+
+fn decode_body(constructor: u8, reader: &mut Reader) -> Result<Value, DecodeError> {
+    let value = match constructor {
+        0x40 => Value::Null,
+        0x41 => Value::Bool(true),
+        0x42 => Value::Bool(false),
+        0x56 => Value::Bool(reader.u8()? != 0),
+        0x50 => Value::Ubyte(reader.u8()?),
+        0x60 => Value::Ushort(reader.u16()?),
+        0x70 => Value::Uint(reader.u32()?),
+        0x52 => Value::Uint(reader.u8()?.into()),
+        0x43 => Value::Uint(0),
+        0x80 => Value::Ulong(reader.u64()?),
+        0x53 => Value::Ulong(reader.u8()?.into()),
+        0x44 => Value::Ulong(0),
+        0x51 => Value::Byte(reader.u8()? as i8),
+        0x61 => Value::Short(reader.u16()? as i16),
+        0x71 => Value::Int(reader.u32()? as i32),
+        0x54 => Value::Int((reader.u8()? as i8).into()),
+        0x81 => Value::Long(reader.u64()? as i64),
+        0x55 => Value::Long((reader.u8()? as i8).into()),
+        0x72 => Value::Float(f32::from_bits(reader.u32()?)),
+        0x82 => Value::Double(f64::from_bits(reader.u64()?)),
+        0x73 => {
+            let code = reader.u32()?;
+            Value::Char(std::char::from_u32(code).ok_or(DecodeError::InvalidChar { code })?)
+        }
+        0x83 => Value::Timestamp(reader.u64()? as i64),
+        0x98 => {
+            let mut uuid = [0; 16];
+            uuid.copy_from_slice(reader.take(16)?);
+            Value::Uuid(uuid)
+        }
+        // Decimals are kept as their raw IEEE 754 bytes.
+        0x74 => Value::Binary(Bytes::from(reader.take(4)?)),
+        0x84 => Value::Binary(Bytes::from(reader.take(8)?)),
+        0x94 => Value::Binary(Bytes::from(reader.take(16)?)),
+        0xa0 | 0xb0 => {
+            let size = reader.size(constructor)?;
+            Value::Binary(Bytes::from(reader.take(size)?))
+        }
+        0xa1 | 0xb1 => Value::String(reader.string(constructor)?),
+        0xa3 | 0xb3 => Value::Symbol(reader.string(constructor)?),
+        0x45 => Value::List(Vec::new()),
+        0xc0 | 0xd0 => {
+            let (count, mut items) = reader.compound(constructor)?;
+            Value::List(
+                (0..count)
+                    .map(|_| decode(&mut items))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        0xc1 | 0xd1 => {
+            let (count, mut items) = reader.compound(constructor)?;
+            Value::Map(
+                (0..count / 2)
+                    .map(|_| Ok::<_, DecodeError>((decode(&mut items)?, decode(&mut items)?)))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        0xe0 | 0xf0 => {
+            let (count, mut items) = reader.compound(constructor)?;
+            let mut element = items.u8()?;
+            let descriptor = if element == 0x00 {
+                let descriptor = decode(&mut items)?;
+                element = items.u8()?;
+                Some(descriptor)
+            } else {
+                None
+            };
+            Value::Array(
+                (0..count)
+                    .map(|_| {
+                        let value = decode_body(element, &mut items)?;
+                        Ok::<_, DecodeError>(match &descriptor {
+                            Some(descriptor) => {
+                                Value::Described(Box::new(descriptor.clone()), Box::new(value))
+                            }
+                            None => value,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        constructor => return Err(DecodeError::UnknownType { constructor }),
+    };
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < count {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// Sizes and counts take one byte in the short forms of variable width
+    /// and compound types, `0xa_`, `0xc_` and `0xe_`, and four otherwise.
+    fn size(&mut self, constructor: u8) -> Result<usize, DecodeError> {
+        Ok(if constructor & 0x10 == 0 {
+            self.u8()?.into()
+        } else {
+            self.u32()? as usize
+        })
+    }
+
+    fn string(&mut self, constructor: u8) -> Result<String, DecodeError> {
+        let size = self.size(constructor)?;
+        String::from_utf8(self.take(size)?.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    /// The count of items of a list, map or array, and a reader of them.
+    fn compound(&mut self, constructor: u8) -> Result<(usize, Reader<'a>), DecodeError> {
+        let size = self.size(constructor)?;
+        let mut items = Reader::new(self.take(size)?);
+        let count = items.size(constructor)?;
+        Ok((count, items))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    /// Starts the SASL or AMQP protocol.
+    Header(u8),
+    Sasl(Value),
+    /// An empty frame is a heartbeat.
+    Amqp {
+        performative: Option<Value>,
+        payload: Bytes,
+    },
+}
+
+/// Frames of channel 0, the only one used.
+pub struct Codec;
+
+impl Decoder for Codec {
+    type Item = Frame;
+    type Error = AmqpError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>, AmqpError> {
+        if buf.len() < 8 {
+            return Ok(None);
+        }
+        // No frame is as large as these bytes would make it.
+        if buf.starts_with(b"AMQP") {
+            let header = buf.split_to(8);
+            return Ok(Some(Frame::Header(header[4])));
+        }
+
+        let size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        let offset = usize::from(buf[4]) * 4;
+        if size > MAX_FRAME_SIZE as usize || offset < 8 || offset > size {
+            return Err(DecodeError::InvalidFrameSize { size }.into());
+        }
+        if buf.len() < size {
+            buf.reserve(size - buf.len());
+            return Ok(None);
+        }
+
+        let frame = buf.split_to(size);
+        let mut reader = Reader::new(&frame[offset..]);
+        let performative = if reader.is_empty() {
+            None
+        } else {
+            Some(decode(&mut reader)?)
+        };
+        Ok(Some(match (frame[5], performative) {
+            (SASL_FRAME, Some(performative)) => Frame::Sasl(performative),
+            (SASL_FRAME, None) => return Err(DecodeError::InvalidPerformative.into()),
+            (_, performative) => Frame::Amqp {
+                performative,
+                payload: Bytes::from(reader.data),
+            },
+        }))
+    }
+}
+
+impl Encoder for Codec {
+    type Item = Frame;
+    type Error = AmqpError;
+
+    fn encode(&mut self, frame: Frame, buf: &mut BytesMut) -> Result<(), AmqpError> {
+        let (kind, performative, payload) = match frame {
+            Frame::Header(protocol) => {
+                buf.extend_from_slice(&[b'A', b'M', b'Q', b'P', protocol, 1, 0, 0]);
+                return Ok(());
+            }
+            Frame::Sasl(performative) => (SASL_FRAME, Some(performative), Bytes::new()),
+            Frame::Amqp {
+                performative,
+                payload,
+            } => (AMQP_FRAME, performative, payload),
+        };
+
+        let mut body = Vec::new();
+        if let Some(performative) = performative {
+            performative.encode(&mut body);
+        }
+        body.extend_from_slice(&payload);
+        buf.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+        buf.extend_from_slice(&[2, kind, 0, 0]);
+        buf.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+/// A performative received from the peer, with the payload of transfers.
+#[derive(Debug)]
+pub struct Performative {
+    pub code: u64,
+    fields: Vec<Value>,
+    pub payload: Bytes,
+}
+
+impl Performative {
+    /// Fields left out at the end are null.
+    pub fn field(&self, index: usize) -> &Value {
+        self.fields.get(index).unwrap_or(&NULL)
+    }
+
+    fn from_value(value: Value) -> Result<(u64, Vec<Value>), DecodeError> {
+        match value {
+            Value::Described(descriptor, fields) => match (*descriptor, *fields) {
+                (Value::Ulong(code), Value::List(fields)) => Ok((code, fields)),
+                _ => Err(DecodeError::InvalidPerformative),
+            },
+            _ => Err(DecodeError::InvalidPerformative),
+        }
+    }
+}
+
+/// The `condition: description` of an error sent by the peer.
+fn error_text(error: &Value) -> String {
+    let fields = match error {
+        Value::Described(_, fields) => match &**fields {
+            Value::List(fields) => fields,
+            _ => return "unknown error".into(),
+        },
+        _ => return "no error".into(),
+    };
+    let condition = fields.get(0).and_then(Value::as_str).unwrap_or("unknown");
+    match fields.get(1).and_then(Value::as_str) {
+        Some(description) => format!("{}: {}", condition, description),
+        None => condition.into(),
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Message {
+    pub message_annotations: Vec<(Value, Value)>,
+    pub application_properties: Vec<(Value, Value)>,
+    /// The data sections, concatenated.
+    pub data: Vec<u8>,
+    /// The body of messages sent as an AMQP value or sequence.
+    pub value: Option<Value>,
+}
+
+impl Message {
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(data);
+        let mut message = Message::default();
+        while !reader.is_empty() {
+            let (descriptor, section) = match decode(&mut reader)? {
+                Value::Described(descriptor, section) => (*descriptor, *section),
+                _ => return Err(DecodeError::InvalidSection),
+            };
+            match (descriptor, section) {
+                (Value::Ulong(MESSAGE_ANNOTATIONS), Value::Map(map)) => {
+                    message.message_annotations = map
+                }
+                (Value::Ulong(APPLICATION_PROPERTIES), Value::Map(map)) => {
+                    message.application_properties = map
+                }
+                (Value::Ulong(DATA), Value::Binary(data)) => message.data.extend_from_slice(&data),
+                (Value::Ulong(AMQP_SEQUENCE), Value::List(values)) => {
+                    message.value = Some(Value::List(values))
+                }
+                (Value::Ulong(AMQP_VALUE), value) => message.value = Some(value),
+                // The header, delivery annotations, properties and footer.
+                _ => (),
+            }
+        }
+        Ok(message)
+    }
+
+    pub fn annotation(&self, name: &str) -> Option<&Value> {
+        lookup(&self.message_annotations, name)
+    }
+
+    pub fn application_property(&self, name: &str) -> Option<&Value> {
+        lookup(&self.application_properties, name)
+    }
+}
+
+pub fn lookup<'a>(map: &'a [(Value, Value)], name: &str) -> Option<&'a Value> {
+    map.iter()
+        .find(|(key, _)| key.as_str() == Some(name))
+        .map(|(_, value)| value)
+}
+
+/// One end of a link.
+#[derive(Debug)]
+pub struct Link {
+    handle: u32,
+    /// The handle the peer refers to the link with.
+    remote_handle: u32,
+    /// The deliveries received, link credit is granted on top of them.
+    delivery_count: u32,
+}
+
+impl Link {
+    /// Whether the performative is a transfer on this link.
+    pub fn is_transfer(&self, performative: &Performative) -> bool {
+        performative.code == TRANSFER && performative.field(0).as_u32() == Some(self.remote_handle)
+    }
+}
+
+/// A node answering requests, like the claims-based security node `$cbs`,
+/// through a pair of links.
+#[derive(Debug)]
+pub struct Node {
+    reply_to: String,
+    sender: Link,
+    receiver: Link,
+}
+
+impl Node {
+    pub fn is_response(&self, performative: &Performative) -> bool {
+        self.receiver.is_transfer(performative)
+    }
+
+    /// The response carried by the transfer, if it succeeded.
+    pub fn response(&mut self, performative: &Performative) -> Result<Message, AmqpError> {
+        self.receiver.delivery_count = self.receiver.delivery_count.wrapping_add(1);
+        let message = Message::decode(&performative.payload)?;
+        let status = message
+            .application_property("status-code")
+            .and_then(Value::as_i64);
+        match status {
+            Some(200..=299) => Ok(message),
+            status => Err(AmqpError::Status {
+                status: status.unwrap_or_default(),
+                description: message
+                    .application_property("status-description")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .into(),
+            }),
+        }
+    }
+}
+
+type Transport = Compat01As03Sink<Framed<MaybeTlsStream<TcpStream>, Codec>, Frame>;
+
+/// A connection with a single session.
+pub struct Connection {
+    transport: Transport,
+    /// The ids of the next transfer from the peer and of ours.
+    next_incoming_id: u32,
+    next_outgoing_id: u32,
+    next_handle: u32,
+    next_message_id: u64,
+    last_received: Instant,
+    last_sent: Instant,
+}
+
+impl Connection {
+    /// Connects to `host` over TLS and begins the session.
+    pub async fn open(
+        host: &str,
+        container_id: &str,
+        tls: &MaybeTlsSettings,
+    ) -> Result<Self, AmqpError> {
+        let address = resolve(host).await?;
+        let stream = tls
+            .connect(host.into(), address)
+            .context(Connect)?
+            .compat()
+            .await
+            .context(Connect)?;
+        let mut connection = Self {
+            transport: Codec.framed(stream).sink_compat(),
+            next_incoming_id: 0,
+            next_outgoing_id: 0,
+            next_handle: 0,
+            next_message_id: 0,
+            last_received: Instant::now(),
+            last_sent: Instant::now(),
+        };
+
+        // Event Hubs authorizes links with the tokens put to its
+        // claims-based security node, the connection itself is anonymous.
+        connection.send_frame(Frame::Header(SASL_PROTOCOL)).await?;
+        connection.expect_header(SASL_PROTOCOL).await?;
+        connection.expect_sasl(SASL_MECHANISMS).await?;
+        let init = described(
+            SASL_INIT,
+            Value::List(vec![Value::Symbol("ANONYMOUS".into())]),
+        );
+        connection.send_frame(Frame::Sasl(init)).await?;
+        let outcome = connection.expect_sasl(SASL_OUTCOME).await?;
+        match outcome.get(0) {
+            Some(Value::Ubyte(0)) => (),
+            code => {
+                let code = code.and_then(Value::as_u32).unwrap_or_default() as u8;
+                return Err(AmqpError::SaslFailed { code });
+            }
+        }
+
+        connection.send_frame(Frame::Header(AMQP_PROTOCOL)).await?;
+        connection.expect_header(AMQP_PROTOCOL).await?;
+        let open = vec![
+            Value::String(container_id.into()),
+            Value::String(host.into()),
+            Value::Uint(MAX_FRAME_SIZE),
+            Value::Ushort(0),
+            Value::Uint(IDLE_TIMEOUT.as_millis() as u32),
+        ];
+        connection.send(OPEN, open, Bytes::new()).await?;
+        let begin = vec![
+            Value::Null,
+            Value::Uint(0),
+            Value::Uint(SESSION_WINDOW),
+            Value::Uint(SESSION_WINDOW),
+        ];
+        connection.send(BEGIN, begin, Bytes::new()).await?;
+
+        connection.expect(OPEN, "open").await?;
+        let begin = connection.expect(BEGIN, "begin").await?;
+        connection.next_incoming_id = begin.field(1).as_u32().unwrap_or_default();
+        Ok(connection)
+    }
+
+    /// Attaches a link to receive from `address`, only the messages
+    /// matching the selector `filter` if given.
+    pub async fn attach_receiver(
+        &mut self,
+        name: String,
+        address: &str,
+        filter: Option<String>,
+    ) -> Result<Link, AmqpError> {
+        let mut source = vec![Value::String(address.into())];
+        if let Some(filter) = filter {
+            let name = Value::Symbol("apache.org:selector-filter:string".into());
+            let filter = Value::Described(Box::new(name.clone()), Box::new(Value::String(filter)));
+            // The filter set comes after the durability, expiry policy,
+            // timeout, dynamic flags and distribution mode.
+            source.extend(vec![Value::Null; 6]);
+            source.push(Value::Map(vec![(name, filter)]));
+        }
+        let source = described(SOURCE, Value::List(source));
+        let target = described(TARGET, Value::List(vec![Value::String(name.clone())]));
+        self.attach(name, true, source, target).await
+    }
+
+    /// Attaches the pair of links to a node answering requests.
+    pub async fn open_node(&mut self, address: &str) -> Result<Node, AmqpError> {
+        let reply_to = format!("{}-reply", address);
+        let sender = self
+            .attach(
+                format!("{}-sender", address),
+                false,
+                described(SOURCE, Value::List(Vec::new())),
+                described(TARGET, Value::List(vec![Value::String(address.into())])),
+            )
+            .await?;
+        let receiver = self
+            .attach(
+                format!("{}-receiver", address),
+                true,
+                described(SOURCE, Value::List(vec![Value::String(address.into())])),
+                described(TARGET, Value::List(vec![Value::String(reply_to.clone())])),
+            )
+            .await?;
+        Ok(Node {
+            reply_to,
+            sender,
+            receiver,
+        })
+    }
+
+    /// Attaches a link, and for senders waits for credit to send.
+    async fn attach(
+        &mut self,
+        name: String,
+        receiver: bool,
+        source: Value,
+        target: Value,
+    ) -> Result<Link, AmqpError> {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        let attach = vec![
+            Value::String(name.clone()),
+            Value::Uint(handle),
+            Value::Bool(receiver),
+            // Event Hubs tracks positions with offsets rather than
+            // settlements, so messages are settled when sent.
+            Value::Ubyte(SETTLED),
+            Value::Ubyte(0),
+            source,
+            target,
+            Value::Null,
+            Value::Bool(false),
+            if receiver {
+                Value::Null
+            } else {
+                Value::Uint(0)
+            },
+        ];
+        self.send(ATTACH, attach, Bytes::new()).await?;
+
+        // A refused link is attached without its source or target, and
+        // detached right after with the error.
+        let mut remote_handle = None;
+        loop {
+            let performative = self.next().await?;
+            let ready = match performative.code {
+                ATTACH if performative.field(0).as_str() == Some(name.as_str()) => {
+                    remote_handle = Some(
+                        performative
+                            .field(1)
+                            .as_u32()
+                            .ok_or(AmqpError::Unexpected { expected: "handle" })?,
+                    );
+                    receiver
+                }
+                FLOW => {
+                    remote_handle.is_some()
+                        && performative.field(4).as_u32() == remote_handle
+                        && performative.field(6).as_u32().unwrap_or_default() > 0
+                }
+                _ => false,
+            };
+            if let (true, Some(remote_handle)) = (ready, remote_handle) {
+                return Ok(Link {
+                    handle,
+                    remote_handle,
+                    delivery_count: 0,
+                });
+            }
+        }
+    }
+
+    /// Grants a receiving link credit for `credit` more messages.
+    pub async fn flow(&mut self, link: &Link, credit: u32) -> Result<(), AmqpError> {
+        let flow = vec![
+            Value::Uint(self.next_incoming_id),
+            Value::Uint(SESSION_WINDOW),
+            Value::Uint(self.next_outgoing_id),
+            Value::Uint(SESSION_WINDOW),
+            Value::Uint(link.handle),
+            Value::Uint(link.delivery_count),
+            Value::Uint(credit),
+        ];
+        self.send(FLOW, flow, Bytes::new()).await
+    }
+
+    /// Counts a complete delivery on a receiving link, and accepts it if
+    /// the peer didn't settle it already.
+    pub async fn delivered(
+        &mut self,
+        link: &mut Link,
+        transfer: &Performative,
+    ) -> Result<(), AmqpError> {
+        link.delivery_count = link.delivery_count.wrapping_add(1);
+        if transfer.field(4) == &Value::Bool(true) {
+            return Ok(());
+        }
+        let delivery_id = match transfer.field(1).as_u32() {
+            Some(delivery_id) => delivery_id,
+            None => return Ok(()),
+        };
+        let disposition = vec![
+            Value::Bool(true),
+            Value::Uint(delivery_id),
+            Value::Null,
+            Value::Bool(true),
+            described(ACCEPTED, Value::List(Vec::new())),
+        ];
+        self.send(DISPOSITION, disposition, Bytes::new()).await
+    }
+
+    /// Sends a request to the node, its response arrives as a transfer on
+    /// the receiver of the node.
+    pub async fn send_request(
+        &mut self,
+        node: &Node,
+        properties: Vec<(&str, Value)>,
+        body: Value,
+    ) -> Result<(), AmqpError> {
+        self.flow(&node.receiver, 1).await?;
+
+        let mut message = Vec::new();
+        let message_properties = vec![
+            Value::Ulong(self.next_message_id),
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            Value::String(node.reply_to.clone()),
+        ];
+        self.next_message_id += 1;
+        described(PROPERTIES, Value::List(message_properties)).encode(&mut message);
+        let properties = properties
+            .into_iter()
+            .map(|(name, value)| (Value::String(name.into()), value))
+            .collect();
+        described(APPLICATION_PROPERTIES, Value::Map(properties)).encode(&mut message);
+        described(AMQP_VALUE, body).encode(&mut message);
+
+        let delivery_id = self.next_outgoing_id;
+        let transfer = vec![
+            Value::Uint(node.sender.handle),
+            Value::Uint(delivery_id),
+            Value::Binary(Bytes::from(&delivery_id.to_be_bytes()[..])),
+            Value::Uint(0),
+            Value::Bool(true),
+        ];
+        self.send(TRANSFER, transfer, Bytes::from(message)).await?;
+        self.next_outgoing_id = delivery_id.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Sends a request to the node and waits for its response.
+    pub async fn request(
+        &mut self,
+        node: &mut Node,
+        properties: Vec<(&str, Value)>,
+        body: Value,
+    ) -> Result<Message, AmqpError> {
+        self.send_request(node, properties, body).await?;
+        loop {
+            let performative = self.next().await?;
+            if node.is_response(&performative) {
+                return node.response(&performative);
+            }
+        }
+    }
+
+    /// Sends a heartbeat if nothing was sent for a while, and fails if
+    /// nothing was received for too long.
+    pub async fn keep_alive(&mut self) -> Result<(), AmqpError> {
+        if self.last_received.elapsed() > IDLE_TIMEOUT {
+            return Err(AmqpError::IdleTimeout);
+        }
+        if self.last_sent.elapsed() >= HEARTBEAT {
+            let heartbeat = Frame::Amqp {
+                performative: None,
+                payload: Bytes::new(),
+            };
+            self.send_frame(heartbeat).await?;
+        }
+        Ok(())
+    }
+
+    /// The next performative from the peer, skipping heartbeats. Closing
+    /// the connection, ending the session or detaching a link are errors.
+    pub async fn next(&mut self) -> Result<Performative, AmqpError> {
+        loop {
+            let frame = self.receive().await?;
+            let (performative, payload) = match frame {
+                Frame::Amqp {
+                    performative: None, ..
+                } => continue,
+                Frame::Amqp {
+                    performative: Some(performative),
+                    payload,
+                } => (performative, payload),
+                _ => return Err(AmqpError::Unexpected { expected: "frame" }),
+            };
+            let (code, fields) = Performative::from_value(performative)?;
+            let performative = Performative {
+                code,
+                fields,
+                payload,
+            };
+            match code {
+                TRANSFER => self.next_incoming_id = self.next_incoming_id.wrapping_add(1),
+                DETACH => {
+                    let error = error_text(performative.field(2));
+                    return Err(AmqpError::Detached { error });
+                }
+                END | CLOSE => {
+                    let error = error_text(performative.field(0));
+                    return Err(AmqpError::Closed { error });
+                }
+                _ => (),
+            }
+            return Ok(performative);
+        }
+    }
+
+    async fn expect(
+        &mut self,
+        code: u64,
+        expected: &'static str,
+    ) -> Result<Performative, AmqpError> {
+        let performative = self.next().await?;
+        if performative.code != code {
+            return Err(AmqpError::Unexpected { expected });
+        }
+        Ok(performative)
+    }
+
+    async fn expect_header(&mut self, protocol: u8) -> Result<(), AmqpError> {
+        match self.receive().await? {
+            Frame::Header(received) if received == protocol => Ok(()),
+            _ => Err(AmqpError::Unexpected {
+                expected: "protocol header",
+            }),
+        }
+    }
+
+    async fn expect_sasl(&mut self, code: u64) -> Result<Vec<Value>, AmqpError> {
+        match self.receive().await? {
+            Frame::Sasl(performative) => match Performative::from_value(performative)? {
+                (received, fields) if received == code => Ok(fields),
+                _ => Err(AmqpError::Unexpected { expected: "SASL" }),
+            },
+            _ => Err(AmqpError::Unexpected { expected: "SASL" }),
+        }
+    }
+
+    async fn send(
+        &mut self,
+        code: u64,
+        fields: Vec<Value>,
+        payload: Bytes,
+    ) -> Result<(), AmqpError> {
+        let performative = described(code, Value::List(fields));
+        self.send_frame(Frame::Amqp {
+            performative: Some(performative),
+            payload,
+        })
+        .await
+    }
+
+    async fn send_frame(&mut self, frame: Frame) -> Result<(), AmqpError> {
+        self.transport.send(frame).await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Frame, AmqpError> {
+        let frame = self
+            .transport
+            .next()
+            .await
+            .ok_or(AmqpError::Disconnected)??;
+        self.last_received = Instant::now();
+        Ok(frame)
+    }
+}
+
+async fn resolve(host: &str) -> Result<SocketAddr, AmqpError> {
+    let address = (host.to_owned(), AMQPS_PORT);
+    let resolved = tokio::task::spawn_blocking(move || -> io::Result<SocketAddr> {
+        address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses found"))
+    })
+    .await
+    .unwrap_or_else(|error| Err(io::Error::new(io::ErrorKind::Other, error)));
+    resolved.context(Resolve { host })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(value: Value) {
+        let mut buf = Vec::new();
+        value.encode(&mut buf);
+        let mut reader = Reader::new(&buf);
+        assert_eq!(decode(&mut reader), Ok(value));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn azure_event_hubs_amqp_values_roundtrip() {
+        roundtrip(Value::Null);
+        roundtrip(Value::Bool(true));
+        roundtrip(Value::Ulong(u64::max_value()));
+        roundtrip(Value::Long(-2));
+        roundtrip(Value::Double(1.5));
+        roundtrip(Value::Timestamp(1_600_000_000_000));
+        roundtrip(Value::Binary(Bytes::from(&b"data"[..])));
+        roundtrip(Value::Array(vec![
+            Value::Symbol("a".into()),
+            Value::Symbol("bc".into()),
+        ]));
+        roundtrip(described(
+            ATTACH,
+            Value::List(vec![
+                Value::String("link".into()),
+                Value::Map(vec![(Value::Symbol("key".into()), Value::Int(7))]),
+            ]),
+        ));
+    }
+
+    #[test]
+    fn azure_event_hubs_amqp_decodes_short_forms() {
+        // A described list of a smallulong descriptor, holding uint0,
+        // smalluint, a str8 and a sym8 array.
+        let data = [
+            0x00, 0x53, 0x10, 0xc0, 0x0f, 0x04, 0x43, 0x52, 0x05, 0xa1, 0x02, b'h', b'i', 0xe0,
+            0x05, 0x02, 0xa3, 0x01, b'x', 0x00,
+        ];
+        let value = decode(&mut Reader::new(&data)).unwrap();
+        assert_eq!(
+            value,
+            described(
+                OPEN,
+                Value::List(vec![
+                    Value::Uint(0),
+                    Value::Uint(5),
+                    Value::String("hi".into()),
+                    Value::Array(vec![Value::Symbol("x".into()), Value::Symbol("".into())]),
+                ])
+            )
+        );
+
+        assert_eq!(
+            decode(&mut Reader::new(&[0xb1, 0x00, 0x00])),
+            Err(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn azure_event_hubs_amqp_frames_roundtrip() {
+        let frames = vec![
+            Frame::Header(SASL_PROTOCOL),
+            Frame::Sasl(described(SASL_OUTCOME, Value::List(vec![Value::Ubyte(0)]))),
+            Frame::Amqp {
+                performative: Some(described(TRANSFER, Value::List(vec![Value::Uint(2)]))),
+                payload: Bytes::from(&b"payload"[..]),
+            },
+            Frame::Amqp {
+                performative: None,
+                payload: Bytes::new(),
+            },
+        ];
+
+        let mut encoded = BytesMut::new();
+        for frame in &frames {
+            Codec.encode(frame.clone(), &mut encoded).unwrap();
+        }
+
+        // Frames split across reads are only decoded once complete.
+        let mut buf = BytesMut::from(&encoded[..12]);
+        assert_eq!(
+            Codec.decode(&mut buf).unwrap(),
+            Some(Frame::Header(SASL_PROTOCOL))
+        );
+        assert_eq!(Codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&encoded[12..]);
+
+        let mut decoded = Vec::new();
+        while let Some(frame) = Codec.decode(&mut buf).unwrap() {
+            decoded.push(frame);
+        }
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn azure_event_hubs_amqp_decodes_messages() {
+        let mut data = Vec::new();
+        described(
+            MESSAGE_ANNOTATIONS,
+            Value::Map(vec![(
+                Value::Symbol("x-opt-offset".into()),
+                Value::String("4096".into()),
+            )]),
+        )
+        .encode(&mut data);
+        described(PROPERTIES, Value::List(vec![Value::Ulong(1)])).encode(&mut data);
+        described(DATA, Value::Binary(Bytes::from(&b"hello "[..]))).encode(&mut data);
+        described(DATA, Value::Binary(Bytes::from(&b"world"[..]))).encode(&mut data);
+
+        let message = Message::decode(&data).unwrap();
+        assert_eq!(
+            message.annotation("x-opt-offset"),
+            Some(&Value::String("4096".into()))
+        );
+        assert_eq!(message.data, b"hello world");
+        assert_eq!(message.value, None);
+
+        assert_eq!(Message::decode(&[0x40]), Err(DecodeError::InvalidSection));
+    }
+
+    #[test]
+    fn azure_event_hubs_amqp_error_text() {
+        let error = described(
+            0x1d,
+            Value::List(vec![
+                Value::Symbol("amqp:unauthorized-access".into()),
+                Value::String("Unauthorized".into()),
+            ]),
+        );
+        assert_eq!(error_text(&error), "amqp:unauthorized-access: Unauthorized");
+        assert_eq!(error_text(&Value::Null), "no error");
+    }
+}
@@ -0,0 +1,253 @@
+//! Checkpoints of the partitions kept in Azure Blob Storage, in the layout
+//! of the checkpoint stores of the Azure SDKs, so consumers built with
+//! either can take over from the other.
+
+use crate::{
+    azure::{self, ManagedIdentity, STORAGE_RESOURCE},
+    sources::util::{https_client, HttpsClient},
+    tls::TlsOptions,
+};
+use chrono::Utc;
+use futures::compat::Future01CompatExt;
+use http::{header::AUTHORIZATION, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use hyper::Body;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::time::Duration;
+
+const STORAGE_API_VERSION: &str = "2019-02-02";
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("One of `account` or `connection_string` must be set"))]
+    MissingAccount,
+    #[snafu(display("`AccountKey` isn't valid base64: {}", source))]
+    InvalidAccountKey { source: base64::DecodeError },
+    #[snafu(display("Invalid checkpoint URI {:?}: {}", uri, source))]
+    InvalidUri {
+        uri: String,
+        source: http::uri::InvalidUri,
+    },
+}
+
+#[derive(Debug, Snafu)]
+pub enum CheckpointError {
+    #[snafu(display("Request failed: {}", source))]
+    Http { source: hyper::Error },
+    #[snafu(display("Request failed with status {}", status))]
+    Status { status: StatusCode },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CheckpointStoreConfig {
+    /// A storage account connection string, with its `AccountName` and
+    /// `AccountKey`.
+    pub connection_string: Option<String>,
+    /// The storage account accessed with the managed identity of the host,
+    /// when there is no connection string.
+    pub account: Option<String>,
+    pub container: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+enum Credentials {
+    SharedKey { account: String, key: Vec<u8> },
+    ManagedIdentity(ManagedIdentity),
+}
+
+pub struct CheckpointStore {
+    client: HttpsClient,
+    /// The URL of the checkpoint blobs, up to the partition id.
+    prefix: String,
+    credentials: Credentials,
+    /// How often the position of each partition is saved.
+    pub interval: Duration,
+}
+
+impl CheckpointStore {
+    pub fn new(
+        config: &CheckpointStoreConfig,
+        namespace_host: &str,
+        event_hub_name: &str,
+        consumer_group: &str,
+        managed_identity_client_id: Option<String>,
+        tls: &Option<TlsOptions>,
+    ) -> crate::Result<Self> {
+        let (account, endpoint_suffix, credentials) =
+            match (&config.connection_string, &config.account) {
+                (Some(connection_string), _) => {
+                    let parts = azure::connection_string_parts(connection_string);
+                    let account = azure::connection_string_part(&parts, "AccountName")?;
+                    let key = azure::connection_string_part(&parts, "AccountKey")?;
+                    let key = base64::decode(&key).context(InvalidAccountKey)?;
+                    let endpoint_suffix = parts
+                        .get("EndpointSuffix")
+                        .unwrap_or(&"core.windows.net")
+                        .to_string();
+                    let credentials = Credentials::SharedKey {
+                        account: account.clone(),
+                        key,
+                    };
+                    (account, endpoint_suffix, credentials)
+                }
+                (None, Some(account)) => {
+                    let identity =
+                        ManagedIdentity::new(managed_identity_client_id, STORAGE_RESOURCE)?;
+                    (
+                        account.clone(),
+                        "core.windows.net".into(),
+                        Credentials::ManagedIdentity(identity),
+                    )
+                }
+                (None, None) => return Err(BuildError::MissingAccount.into()),
+            };
+
+        let prefix = format!(
+            "https://{}.blob.{}/{}/{}/{}/{}/checkpoint/",
+            account,
+            endpoint_suffix,
+            config.container,
+            namespace_host,
+            event_hub_name,
+            consumer_group
+        )
+        .to_lowercase();
+        let uri = format!("{}0", prefix);
+        uri.parse::<Uri>()
+            .context(InvalidUri { uri: uri.clone() })?;
+
+        Ok(Self {
+            client: https_client(tls)?,
+            prefix,
+            credentials,
+            interval: Duration::from_secs(config.interval_secs),
+        })
+    }
+
+    pub fn spawn_regenerate_token(&self) {
+        if let Credentials::ManagedIdentity(identity) = &self.credentials {
+            identity.spawn_regenerate_token();
+        }
+    }
+
+    /// The offset of the last message checkpointed for the partition.
+    pub async fn load(&self, partition_id: &str) -> Result<Option<String>, CheckpointError> {
+        let response = self.send(Method::HEAD, partition_id, Vec::new()).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(response
+                .headers()
+                .get("x-ms-meta-offset")
+                .and_then(|offset| offset.to_str().ok())
+                .map(Into::into)),
+            status => Err(CheckpointError::Status { status }),
+        }
+    }
+
+    pub async fn save(
+        &self,
+        partition_id: &str,
+        offset: &str,
+        sequence_number: i64,
+    ) -> Result<(), CheckpointError> {
+        let headers = vec![
+            ("x-ms-blob-type", "BlockBlob".to_owned()),
+            ("x-ms-meta-offset", offset.to_owned()),
+            ("x-ms-meta-sequencenumber", sequence_number.to_string()),
+        ];
+        let response = self.send(Method::PUT, partition_id, headers).await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(CheckpointError::Status { status }),
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        partition_id: &str,
+        headers: Vec<(&'static str, String)>,
+    ) -> Result<Response<Body>, CheckpointError> {
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let mut builder = Request::builder();
+        builder
+            .method(method)
+            .uri(format!("{}{}", self.prefix, partition_id).as_str())
+            .header("x-ms-date", date.as_str())
+            .header("x-ms-version", STORAGE_API_VERSION);
+        for (name, value) in &headers {
+            builder.header(*name, value.as_str());
+        }
+        let mut request = builder
+            .body(Body::empty())
+            .expect("the URI was validated when building");
+
+        let authorization = match &self.credentials {
+            Credentials::SharedKey { account, key } => {
+                let to_sign = string_to_sign(&request, account);
+                let signature = azure::hmac_sha256(key, to_sign.as_bytes());
+                format!("SharedKey {}:{}", account, base64::encode(&signature))
+            }
+            Credentials::ManagedIdentity(identity) => format!("Bearer {}", identity.access_token()),
+        };
+        let authorization =
+            HeaderValue::from_str(&authorization).expect("tokens are valid header values");
+        request.headers_mut().insert(AUTHORIZATION, authorization);
+
+        self.client.request(request).compat().await.context(Http)
+    }
+}
+
+/// The string signed with the account key, for requests without a body or
+/// standard headers.
+fn string_to_sign(request: &Request<Body>, account: &str) -> String {
+    let mut headers = request
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| format!("{}:{}\n", name, value.to_str().unwrap_or_default()))
+        .collect::<Vec<_>>();
+    headers.sort();
+    // The verb is followed by the standard headers, from `Content-Encoding`
+    // to `Range`.
+    format!(
+        "{}\n{}{}/{}{}",
+        request.method(),
+        "\n".repeat(11),
+        headers.concat(),
+        account,
+        request.uri().path()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_event_hubs_checkpoint_string_to_sign() {
+        let request = Request::put(
+            "https://vector.blob.core.windows.net/checkpoints/ns.servicebus.windows.net/logs/$default/checkpoint/0",
+        )
+        .header("x-ms-version", STORAGE_API_VERSION)
+        .header("x-ms-meta-offset", "4096")
+        .header("x-ms-date", "Mon, 01 Jun 2020 12:00:00 GMT")
+        .body(Body::empty())
+        .unwrap();
+
+        assert_eq!(
+            string_to_sign(&request, "vector"),
+            "PUT\n\n\n\n\n\n\n\n\n\n\n\n\
+             x-ms-date:Mon, 01 Jun 2020 12:00:00 GMT\n\
+             x-ms-meta-offset:4096\n\
+             x-ms-version:2019-02-02\n\
+             /vector/checkpoints/ns.servicebus.windows.net/logs/$default/checkpoint/0"
+        );
+    }
+}
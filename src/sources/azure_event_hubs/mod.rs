@@ -0,0 +1,657 @@
+//! Receives the events of an event hub over AMQP, with a connection per
+//! partition, and checkpoints the position in each partition to Azure Blob
+//! Storage.
+//!
+//! There is no ownership of partitions between consumers: every instance
+//! receives from all the partitions configured, so instances sharing a
+//! consumer group must be given distinct `partition_ids`.
+
+mod amqp;
+mod checkpoint;
+
+use self::{
+    amqp::{AmqpError, Connection, Message, Node},
+    checkpoint::{CheckpointStore, CheckpointStoreConfig},
+};
+use crate::{
+    azure::{AzureAuthConfig, AzureCredentials},
+    event::{self, Event, Value},
+    internal_events::{
+        AzureEventHubsCheckpointFailed, AzureEventHubsMessageReceived,
+        AzureEventHubsPartitionsFailed, AzureEventHubsReceiveFailed,
+    },
+    shutdown::ShutdownSignal,
+    tls::{MaybeTlsSettings, TlsOptions, TlsSettings},
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    future::{join_all, select, Either, FutureExt, TryFutureExt},
+};
+use futures01::{sync::mpsc, Sink};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::time::{delay_for, timeout};
+
+pub use self::checkpoint::CheckpointError;
+
+/// How often the token authorizing a connection is put again, well before
+/// shared access signatures and managed identity tokens expire.
+const TOKEN_REFRESH: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`prefetch_count` must be at least 1"))]
+    NoPrefetch,
+}
+
+#[derive(Debug, Snafu)]
+pub enum ReceiveError {
+    #[snafu(display("{}", source))]
+    Amqp { source: AmqpError },
+    #[snafu(display("Unable to load the checkpoint: {}", source))]
+    LoadCheckpoint { source: CheckpointError },
+    #[snafu(display("The event hub description has no valid `partition_ids`"))]
+    InvalidPartitionIds,
+    #[snafu(display("The pipeline closed"))]
+    PipelineClosed,
+}
+
+impl From<AmqpError> for ReceiveError {
+    fn from(source: AmqpError) -> Self {
+        ReceiveError::Amqp { source }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EventHubsConfig {
+    /// The namespace, without `.servicebus.windows.net`. Defaults to the
+    /// endpoint of the connection string.
+    pub namespace: Option<String>,
+    /// Defaults to the `EntityPath` of the connection string.
+    pub event_hub_name: Option<String>,
+    #[serde(flatten)]
+    pub auth: AzureAuthConfig,
+    #[serde(default = "default_consumer_group")]
+    pub consumer_group: String,
+    /// Receives from every partition of the event hub when empty.
+    #[serde(default)]
+    pub partition_ids: Vec<String>,
+    /// Where partitions without a checkpoint are received from.
+    #[serde(default)]
+    pub start_position: StartPosition,
+    /// How many events each partition may be sent ahead of being received.
+    #[serde(default = "default_prefetch_count")]
+    pub prefetch_count: u32,
+    pub checkpoint_store: Option<CheckpointStoreConfig>,
+    pub tls: Option<TlsOptions>,
+}
+
+fn default_consumer_group() -> String {
+    "$Default".into()
+}
+
+fn default_prefetch_count() -> u32 {
+    300
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Derivative)]
+#[serde(rename_all = "snake_case")]
+#[derivative(Default)]
+pub enum StartPosition {
+    Earliest,
+    #[derivative(Default)]
+    Latest,
+}
+
+impl StartPosition {
+    /// The offset the events received come after.
+    fn offset(self) -> &'static str {
+        match self {
+            StartPosition::Earliest => "-1",
+            StartPosition::Latest => "@latest",
+        }
+    }
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<EventHubsConfig>("azure_event_hubs")
+}
+
+#[typetag::serde(name = "azure_event_hubs")]
+impl SourceConfig for EventHubsConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.prefetch_count == 0 {
+            return Err(Box::new(BuildError::NoPrefetch));
+        }
+        let (host, name) = self
+            .auth
+            .event_hub(self.namespace.as_deref(), self.event_hub_name.as_deref())?;
+        let checkpoints = match &self.checkpoint_store {
+            Some(config) => Some(CheckpointStore::new(
+                config,
+                &host,
+                &name,
+                &self.consumer_group,
+                self.auth.managed_identity_client_id.clone(),
+                &self.tls,
+            )?),
+            None => None,
+        };
+        let hub = EventHub {
+            credentials: self.auth.make_credentials()?,
+            tls: MaybeTlsSettings::from(TlsSettings::from_options(&self.tls)?),
+            host,
+            name,
+            consumer_group: self.consumer_group.clone(),
+            start_position: self.start_position,
+            prefetch_count: self.prefetch_count,
+            checkpoints,
+        };
+        Ok(Box::new(
+            run(hub, self.partition_ids.clone(), shutdown, out)
+                .boxed()
+                .compat(),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "azure_event_hubs"
+    }
+}
+
+struct EventHub {
+    host: String,
+    name: String,
+    consumer_group: String,
+    credentials: AzureCredentials,
+    tls: MaybeTlsSettings,
+    start_position: StartPosition,
+    prefetch_count: u32,
+    checkpoints: Option<CheckpointStore>,
+}
+
+impl EventHub {
+    /// The properties and body of a request putting a token authorizing
+    /// access to the event hub.
+    fn put_token(&self) -> (Vec<(&'static str, amqp::Value)>, amqp::Value) {
+        let audience = format!("sb://{}/{}", self.host, self.name);
+        let (token_type, token) = self.credentials.cbs_token(&audience);
+        let properties = vec![
+            ("operation", amqp::Value::String("put-token".into())),
+            ("type", amqp::Value::String(token_type.into())),
+            ("name", amqp::Value::String(audience)),
+        ];
+        (properties, amqp::Value::String(token))
+    }
+
+    /// Opens a connection authorized to access the event hub, and the
+    /// claims-based security node tokens are put to.
+    async fn connect(&self, container_id: &str) -> Result<(Connection, Node), AmqpError> {
+        let mut connection = Connection::open(&self.host, container_id, &self.tls).await?;
+        let mut cbs = connection.open_node("$cbs").await?;
+        let (properties, token) = self.put_token();
+        connection.request(&mut cbs, properties, token).await?;
+        Ok((connection, cbs))
+    }
+
+    /// The ids of all the partitions, from the management node.
+    async fn partition_ids(&self) -> Result<Vec<String>, ReceiveError> {
+        let (mut connection, _) = self.connect("vector-management").await?;
+        let mut management = connection.open_node("$management").await?;
+        let (_, token) = self.put_token();
+        let properties = vec![
+            ("operation", amqp::Value::String("READ".into())),
+            ("name", amqp::Value::String(self.name.clone())),
+            ("type", amqp::Value::String("com.microsoft:eventhub".into())),
+            ("security_token", token),
+        ];
+        let description = connection
+            .request(&mut management, properties, amqp::Value::Null)
+            .await?;
+        let partition_ids = match &description.value {
+            Some(amqp::Value::Map(description)) => amqp::lookup(description, "partition_ids"),
+            _ => None,
+        };
+        match partition_ids {
+            Some(amqp::Value::Array(ids)) => ids
+                .iter()
+                .map(|id| id.as_str().map(Into::into))
+                .collect::<Option<Vec<String>>>()
+                .ok_or(ReceiveError::InvalidPartitionIds),
+            _ => Err(ReceiveError::InvalidPartitionIds),
+        }
+    }
+}
+
+async fn run(
+    hub: EventHub,
+    partition_ids: Vec<String>,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    hub.credentials.spawn_regenerate_token();
+    if let Some(checkpoints) = &hub.checkpoints {
+        checkpoints.spawn_regenerate_token();
+    }
+
+    let partition_ids = if partition_ids.is_empty() {
+        let mut shutdown = shutdown.clone().compat();
+        loop {
+            match select(Box::pin(hub.partition_ids()), &mut shutdown).await {
+                Either::Left((Ok(partition_ids), _)) => break partition_ids,
+                Either::Left((Err(error), _)) => emit!(AzureEventHubsPartitionsFailed { error }),
+                Either::Right(_) => return Ok(()),
+            }
+
+            let wait = Box::pin(delay_for(Duration::from_secs(1)));
+            if let Either::Right(_) = select(wait, &mut shutdown).await {
+                return Ok(());
+            }
+        }
+    } else {
+        partition_ids
+    };
+
+    let hub = Arc::new(hub);
+    let receivers = partition_ids.into_iter().map(|partition_id| {
+        receive_partition(
+            Arc::clone(&hub),
+            partition_id,
+            shutdown.clone(),
+            out.clone(),
+        )
+    });
+    let results = join_all(receivers).await;
+    results.into_iter().collect()
+}
+
+/// Receives from the partition until shutdown, reconnecting on errors, and
+/// checkpoints it one last time.
+async fn receive_partition(
+    hub: Arc<EventHub>,
+    partition_id: String,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut position = Position::new();
+    let mut shutdown = shutdown.compat();
+    loop {
+        let receive = Box::pin(receive(&hub, &partition_id, &mut position, out.clone()));
+        match select(receive, &mut shutdown).await {
+            Either::Right(_) => break,
+            Either::Left((Err(ReceiveError::PipelineClosed), _)) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Either::Left((Err(error), _)) => emit!(AzureEventHubsReceiveFailed {
+                partition_id: partition_id.clone(),
+                error
+            }),
+            Either::Left((Ok(()), _)) => unreachable!("receiving only ends with an error"),
+        }
+
+        let wait = Box::pin(delay_for(Duration::from_secs(1)));
+        if let Either::Right(_) = select(wait, &mut shutdown).await {
+            break;
+        }
+    }
+
+    position.checkpoint(&hub, &partition_id, true).await;
+    Ok(())
+}
+
+/// Where the receiver of a partition is at.
+struct Position {
+    /// The offset and sequence number of the last event sent downstream,
+    /// `None` until one is or a checkpoint is loaded.
+    offset: Option<String>,
+    sequence_number: i64,
+    /// The offset last checkpointed, and when that was attempted.
+    checkpointed: Option<String>,
+    checkpointed_at: Instant,
+}
+
+impl Position {
+    fn new() -> Self {
+        Self {
+            offset: None,
+            sequence_number: 0,
+            checkpointed: None,
+            checkpointed_at: Instant::now(),
+        }
+    }
+
+    /// Saves the offset if it moved since the last checkpoint, and either
+    /// `force` is set or the checkpoint interval passed.
+    async fn checkpoint(&mut self, hub: &EventHub, partition_id: &str, force: bool) {
+        let checkpoints = match &hub.checkpoints {
+            Some(checkpoints) => checkpoints,
+            None => return,
+        };
+        let offset = match &self.offset {
+            Some(offset) if self.checkpointed.as_ref() != Some(offset) => offset.clone(),
+            _ => return,
+        };
+        if !force && self.checkpointed_at.elapsed() < checkpoints.interval {
+            return;
+        }
+
+        self.checkpointed_at = Instant::now();
+        match checkpoints
+            .save(partition_id, &offset, self.sequence_number)
+            .await
+        {
+            Ok(()) => self.checkpointed = Some(offset),
+            Err(error) => emit!(AzureEventHubsCheckpointFailed {
+                partition_id: partition_id.into(),
+                error
+            }),
+        }
+    }
+}
+
+/// Receives from the partition until the connection fails.
+async fn receive(
+    hub: &EventHub,
+    partition_id: &str,
+    position: &mut Position,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ReceiveError> {
+    if position.offset.is_none() {
+        if let Some(checkpoints) = &hub.checkpoints {
+            position.offset = checkpoints
+                .load(partition_id)
+                .await
+                .context(LoadCheckpoint)?;
+            position.checkpointed = position.offset.clone();
+        }
+    }
+
+    let container_id = format!("vector-{}", partition_id);
+    let (mut connection, mut cbs) = hub.connect(&container_id).await?;
+    let address = format!(
+        "{}/ConsumerGroups/{}/Partitions/{}",
+        hub.name, hub.consumer_group, partition_id
+    );
+    // Offsets are exclusive, so the last event sent downstream isn't
+    // received again.
+    let offset = position
+        .offset
+        .as_deref()
+        .unwrap_or_else(|| hub.start_position.offset());
+    let filter = format!("amqp.annotation.x-opt-offset > '{}'", offset);
+    let mut link = connection
+        .attach_receiver(format!("{}-receiver", container_id), &address, Some(filter))
+        .await?;
+    connection.flow(&link, hub.prefetch_count).await?;
+    let mut credit = hub.prefetch_count;
+    info!(message = "receiving from partition.", partition_id = %partition_id, offset = %offset);
+
+    let mut refresh_token_at = Instant::now() + TOKEN_REFRESH;
+    // The payload of a delivery split across transfers.
+    let mut payload = Vec::new();
+    loop {
+        position.checkpoint(hub, partition_id, false).await;
+        connection.keep_alive().await?;
+        if Instant::now() >= refresh_token_at {
+            let (properties, token) = hub.put_token();
+            connection.send_request(&cbs, properties, token).await?;
+            refresh_token_at = Instant::now() + TOKEN_REFRESH;
+        }
+
+        // Wake up regularly to send heartbeats and checkpoints while the
+        // partition is quiet.
+        let performative = match timeout(amqp::HEARTBEAT / 2, connection.next()).await {
+            Ok(performative) => performative?,
+            Err(_) => continue,
+        };
+        if cbs.is_response(&performative) {
+            cbs.response(&performative)?;
+            continue;
+        }
+        if !link.is_transfer(&performative) {
+            continue;
+        }
+
+        payload.extend_from_slice(&performative.payload);
+        // The `more` flag is set on all but the last transfer of a delivery.
+        if performative.field(5) == &amqp::Value::Bool(true) {
+            continue;
+        }
+        connection.delivered(&mut link, &performative).await?;
+        credit = credit.saturating_sub(1);
+        if credit <= hub.prefetch_count / 2 {
+            connection.flow(&link, hub.prefetch_count).await?;
+            credit = hub.prefetch_count;
+        }
+
+        let message = Message::decode(&payload).map_err(AmqpError::from)?;
+        emit!(AzureEventHubsMessageReceived {
+            byte_size: payload.len()
+        });
+        payload.clear();
+
+        let offset = message
+            .annotation("x-opt-offset")
+            .and_then(amqp::Value::as_str)
+            .map(String::from);
+        let sequence_number = message
+            .annotation("x-opt-sequence-number")
+            .and_then(amqp::Value::as_i64);
+        let event = message_event(message, partition_id);
+        out = out
+            .send(event)
+            .compat()
+            .await
+            .map_err(|_| ReceiveError::PipelineClosed)?;
+        if let Some(offset) = offset {
+            position.offset = Some(offset);
+            position.sequence_number = sequence_number.unwrap_or_default();
+        }
+    }
+}
+
+fn message_event(message: Message, partition_id: &str) -> Event {
+    let mut event = match message.value {
+        Some(amqp::Value::String(text)) => Event::from(text),
+        Some(amqp::Value::Binary(data)) => Event::from(data),
+        Some(value) => {
+            let mut event = Event::from(Bytes::new());
+            let log = event.as_mut_log();
+            log.insert(event::log_schema().message_key(), event_value(value));
+            event
+        }
+        None => Event::from(Bytes::from(message.data)),
+    };
+
+    let log = event.as_mut_log();
+    log.insert(event::log_schema().source_type_key(), "azure_event_hubs");
+    log.insert("partition_id", partition_id);
+    if let Some(amqp::Value::Timestamp(millis)) = lookup(&message, "x-opt-enqueued-time") {
+        log.insert(
+            event::log_schema().timestamp_key(),
+            Utc.timestamp_millis(*millis),
+        );
+    }
+    for (field, annotation) in &[
+        ("offset", "x-opt-offset"),
+        ("sequence_number", "x-opt-sequence-number"),
+        ("partition_key", "x-opt-partition-key"),
+    ] {
+        if let Some(value) = lookup(&message, annotation) {
+            log.insert(*field, event_value(value.clone()));
+        }
+    }
+    for (name, value) in message.application_properties {
+        if let Some(name) = name.as_str() {
+            log.insert(format!("properties.{}", name), event_value(value));
+        }
+    }
+    event
+}
+
+fn lookup<'a>(message: &'a Message, annotation: &str) -> Option<&'a amqp::Value> {
+    amqp::lookup(&message.message_annotations, annotation)
+}
+
+fn event_value(value: amqp::Value) -> Value {
+    use self::amqp::Value as V;
+
+    match value {
+        V::Null => Value::Null,
+        V::Bool(value) => Value::Boolean(value),
+        V::Ubyte(value) => Value::Integer(value.into()),
+        V::Ushort(value) => Value::Integer(value.into()),
+        V::Uint(value) => Value::Integer(value.into()),
+        V::Ulong(value) => Value::Integer(value as i64),
+        V::Byte(value) => Value::Integer(value.into()),
+        V::Short(value) => Value::Integer(value.into()),
+        V::Int(value) => Value::Integer(value.into()),
+        V::Long(value) => Value::Integer(value),
+        V::Float(value) => Value::Float(value.into()),
+        V::Double(value) => Value::Float(value),
+        V::Char(value) => Value::from(value.to_string()),
+        V::Timestamp(millis) => Value::Timestamp(Utc.timestamp_millis(millis)),
+        V::Uuid(uuid) => {
+            let hex = uuid
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>();
+            Value::from(format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            ))
+        }
+        V::Binary(data) => Value::Bytes(data),
+        V::String(value) | V::Symbol(value) => Value::from(value),
+        V::List(values) | V::Array(values) => {
+            Value::Array(values.into_iter().map(event_value).collect())
+        }
+        V::Map(pairs) => Value::Map(
+            pairs
+                .into_iter()
+                .map(|(key, value)| (event_value(key).to_string_lossy(), event_value(value)))
+                .collect(),
+        ),
+        V::Described(_, value) => event_value(*value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotated(value: Option<amqp::Value>, data: &[u8]) -> Message {
+        Message {
+            message_annotations: vec![
+                (
+                    amqp::Value::Symbol("x-opt-offset".into()),
+                    amqp::Value::String("4096".into()),
+                ),
+                (
+                    amqp::Value::Symbol("x-opt-sequence-number".into()),
+                    amqp::Value::Long(42),
+                ),
+                (
+                    amqp::Value::Symbol("x-opt-enqueued-time".into()),
+                    amqp::Value::Timestamp(1_590_969_600_000),
+                ),
+            ],
+            application_properties: vec![(
+                amqp::Value::String("app".into()),
+                amqp::Value::String("web".into()),
+            )],
+            data: data.to_vec(),
+            value,
+        }
+    }
+
+    fn log_field(event: &Event, field: &str) -> Value {
+        event.as_log()[&field.into()].clone()
+    }
+
+    #[test]
+    fn azure_event_hubs_message_event() {
+        let event = message_event(annotated(None, b"hello"), "3");
+
+        assert_eq!(
+            event.as_log()[&event::log_schema().message_key()],
+            "hello".into()
+        );
+        assert_eq!(
+            event.as_log()[&event::log_schema().timestamp_key()],
+            Utc.timestamp(1_590_969_600, 0).into()
+        );
+        assert_eq!(
+            event.as_log()[&event::log_schema().source_type_key()],
+            "azure_event_hubs".into()
+        );
+        assert_eq!(log_field(&event, "partition_id"), "3".into());
+        assert_eq!(log_field(&event, "offset"), "4096".into());
+        assert_eq!(log_field(&event, "sequence_number"), 42.into());
+        assert_eq!(log_field(&event, "properties.app"), "web".into());
+        assert!(event.as_log().get(&"partition_key".into()).is_none());
+    }
+
+    #[test]
+    fn azure_event_hubs_message_event_from_value() {
+        let event = message_event(
+            annotated(Some(amqp::Value::String("hello".into())), b""),
+            "0",
+        );
+        assert_eq!(
+            event.as_log()[&event::log_schema().message_key()],
+            "hello".into()
+        );
+
+        let value = amqp::Value::Map(vec![(
+            amqp::Value::Symbol("level".into()),
+            amqp::Value::Ubyte(3),
+        )]);
+        let event = message_event(annotated(Some(value), b""), "0");
+        assert_eq!(
+            event.as_log()[&event::log_schema().message_key()],
+            Value::Map(vec![("level".to_owned(), 3.into())].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn azure_event_hubs_event_value_uuid() {
+        let uuid = [
+            0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc,
+            0xde, 0xf0,
+        ];
+        assert_eq!(
+            event_value(amqp::Value::Uuid(uuid)),
+            "12345678-9abc-def0-1234-56789abcdef0".into()
+        );
+    }
+
+    #[test]
+    fn azure_event_hubs_start_position_offsets() {
+        assert_eq!(StartPosition::default().offset(), "@latest");
+        assert_eq!(StartPosition::Earliest.offset(), "-1");
+    }
+}
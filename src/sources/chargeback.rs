@@ -0,0 +1,305 @@
+use crate::{
+    event::{self, Event, LogEvent},
+    shutdown::ShutdownSignal,
+    topology::{
+        accounting::{self, Volume},
+        config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+    stream::StreamExt,
+};
+use futures01::{stream::iter_ok, sync::mpsc, Sink};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{collections::HashMap, time::Duration};
+use tokio::time::interval;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("the chargeback source requires `accounting.enabled`"))]
+    AccountingDisabled,
+    #[snafu(display("`interval_secs` must be at least 1"))]
+    IntervalTooShort,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ChargebackConfig {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub format: ReportFormat,
+}
+
+impl Default for ChargebackConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_interval_secs(),
+            format: ReportFormat::default(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    3600
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// A field per column, for sinks encoding events as JSON.
+    Json,
+    /// The columns as a CSV row, in the message.
+    Csv,
+}
+
+impl Default for ReportFormat {
+    fn default() -> Self {
+        ReportFormat::Json
+    }
+}
+
+const COLUMNS: &[&str] = &[
+    "period_start",
+    "period_end",
+    "component",
+    "component_kind",
+    "tenant",
+    "events",
+    "bytes",
+];
+
+inventory::submit! {
+    SourceDescription::new::<ChargebackConfig>("chargeback")
+}
+
+#[typetag::serde(name = "chargeback")]
+impl SourceConfig for ChargebackConfig {
+    fn build(
+        &self,
+        _name: &str,
+        globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if !globals.accounting.enabled {
+            return Err(Box::new(BuildError::AccountingDisabled));
+        }
+        if self.interval_secs == 0 {
+            return Err(Box::new(BuildError::IntervalTooShort));
+        }
+        Ok(Box::new(run(self.clone(), shutdown, out).boxed().compat()))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "chargeback"
+    }
+}
+
+/// The totals the previous report counted up to, by component and tenant.
+type Reported = HashMap<(String, Option<String>), (u64, u64)>;
+
+/// Reports the volumes handled during each interval, and before shutdown
+/// the volumes since the last report.
+async fn run(
+    config: ChargebackConfig,
+    shutdown: ShutdownSignal,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    // The volumes from before the start were reported by the previous run.
+    let mut reported = accounting::volumes()
+        .into_iter()
+        .map(|volume| {
+            let key = (volume.component, volume.tenant);
+            (key, (volume.events, volume.bytes))
+        })
+        .collect::<Reported>();
+    let mut period_start = Utc::now();
+
+    let period = Duration::from_secs(config.interval_secs);
+    // The first tick completes immediately.
+    let mut ticks = interval(period).skip(1).map(|_| ());
+    let mut shutdown = shutdown.compat();
+
+    loop {
+        // The token is held until the last report is sent.
+        let (shutting_down, _token) = match select(ticks.next(), &mut shutdown).await {
+            Either::Left(_) => (false, None),
+            Either::Right((token, _)) => (true, token.ok()),
+        };
+
+        let now = Utc::now();
+        let volumes = accounting::volumes();
+        let events = report(&mut reported, volumes, period_start, now, config.format);
+        period_start = now;
+
+        let (sink, _) = out
+            .send_all(iter_ok(events))
+            .compat()
+            .await
+            .map_err(|error| error!(message = "Error sending chargeback report.", %error))?;
+        out = sink;
+
+        if shutting_down {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds an event for each component and tenant having handled events
+/// since the previous report.
+fn report(
+    reported: &mut Reported,
+    volumes: Vec<Volume>,
+    period_start: DateTime<Utc>,
+    period_end: DateTime<Utc>,
+    format: ReportFormat,
+) -> Vec<Event> {
+    let mut events = Vec::new();
+    for volume in volumes {
+        let key = (volume.component.clone(), volume.tenant.clone());
+        let (events_before, bytes_before) = reported
+            .insert(key, (volume.events, volume.bytes))
+            .unwrap_or((0, 0));
+        let events_delta = volume.events.saturating_sub(events_before);
+        if events_delta == 0 {
+            continue;
+        }
+        let bytes_delta = volume.bytes.saturating_sub(bytes_before);
+
+        let mut log = LogEvent::new();
+        let columns = vec![
+            period_start.to_rfc3339(),
+            period_end.to_rfc3339(),
+            volume.component,
+            volume.component_kind.unwrap_or("").to_owned(),
+            volume.tenant.unwrap_or_default(),
+            events_delta.to_string(),
+            bytes_delta.to_string(),
+        ];
+        match format {
+            ReportFormat::Json => {
+                for (column, value) in COLUMNS.iter().zip(columns) {
+                    match *column {
+                        "events" => log.insert(*column, events_delta as i64),
+                        "bytes" => log.insert(*column, bytes_delta as i64),
+                        _ => log.insert(*column, value),
+                    };
+                }
+            }
+            ReportFormat::Csv => {
+                let row = columns
+                    .iter()
+                    .map(|value| csv_escape(value))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                log.insert(event::log_schema().message_key().clone(), row);
+            }
+        }
+        log.insert(event::log_schema().timestamp_key().clone(), period_end);
+        log.insert(event::log_schema().source_type_key().clone(), "chargeback");
+        events.push(Event::Log(log));
+    }
+    events
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(component: &str, tenant: &str, events: u64, bytes: u64) -> Volume {
+        Volume {
+            component: component.into(),
+            component_kind: Some("sink"),
+            tenant: Some(tenant.into()),
+            events,
+            bytes,
+        }
+    }
+
+    #[test]
+    fn chargeback_reports_volume_since_previous_report() {
+        let mut reported = Reported::new();
+        let start = Utc::now();
+        let events = report(
+            &mut reported,
+            vec![volume("out", "payments", 10, 1000)],
+            start,
+            Utc::now(),
+            ReportFormat::Json,
+        );
+        assert_eq!(events.len(), 1);
+
+        let events = report(
+            &mut reported,
+            vec![
+                volume("out", "payments", 15, 1200),
+                volume("out", "search", 1, 50),
+                volume("in", "search", 0, 0),
+            ],
+            start,
+            Utc::now(),
+            ReportFormat::Json,
+        );
+        assert_eq!(events.len(), 2);
+        let log = events[0].as_log();
+        assert_eq!(log[&"component".into()], "out".into());
+        assert_eq!(log[&"component_kind".into()], "sink".into());
+        assert_eq!(log[&"tenant".into()], "payments".into());
+        assert_eq!(log[&"events".into()], 5.into());
+        assert_eq!(log[&"bytes".into()], 200.into());
+        assert_eq!(log[&"period_start".into()], start.to_rfc3339().into());
+        assert_eq!(events[1].as_log()[&"tenant".into()], "search".into());
+    }
+
+    #[test]
+    fn chargeback_csv_rows() {
+        let mut reported = Reported::new();
+        let start = Utc::now();
+        let events = report(
+            &mut reported,
+            vec![volume("out", "team \"a\", b", 3, 30)],
+            start,
+            Utc::now(),
+            ReportFormat::Csv,
+        );
+        let row = events[0].as_log()[&event::log_schema().message_key()].to_string_lossy();
+        assert!(row.starts_with(&start.to_rfc3339()));
+        assert!(row.ends_with(",out,sink,\"team \"\"a\"\", b\",3,30"));
+    }
+
+    #[test]
+    fn chargeback_requires_accounting() {
+        let error = ChargebackConfig::default()
+            .build(
+                "chargeback",
+                &GlobalOptions::default(),
+                ShutdownSignal::noop(),
+                mpsc::channel(1).0,
+            )
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the chargeback source requires `accounting.enabled`"
+        );
+    }
+}
@@ -6,20 +6,22 @@ use crate::{
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
 };
 use bytes::{Bytes, BytesMut};
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use futures01::{
     sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender},
     Async, Future, Sink, Stream,
 };
+use glob::{Pattern, PatternError};
 use http::StatusCode;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use shiplift::{
-    builder::{ContainerFilter, EventFilter, LogsOptions},
+    builder::{EventFilter, LogsOptions},
     rep::ContainerDetails,
     tty::{Chunk, StreamType},
     Docker, Error,
 };
+use snafu::{ResultExt, Snafu};
 use std::borrow::Borrow;
 use std::sync::Arc;
 use std::{collections::HashMap, env};
@@ -37,16 +39,33 @@ lazy_static! {
     static ref NAME: Atom = Atom::from("container_name");
     static ref STREAM: Atom = Atom::from("stream");
     static ref CONTAINER: Atom = Atom::from("container_id");
+    static ref COMPOSE_PROJECT: Atom = Atom::from("compose_project");
+    static ref COMPOSE_SERVICE: Atom = Atom::from("compose_service");
 }
 
+/// Labels set by docker-compose on the containers it creates.
+const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
 type DockerEvent = shiplift::rep::Event;
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Invalid pattern {:?}: {}", pattern, source))]
+    InvalidPattern {
+        pattern: String,
+        source: PatternError,
+    },
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(deny_unknown_fields, default)]
 pub struct DockerConfig {
     include_containers: Option<Vec<String>>,
     include_labels: Option<Vec<String>>,
+    exclude_labels: Option<Vec<String>>,
     include_images: Option<Vec<String>>,
+    exclude_images: Option<Vec<String>>,
     partial_event_marker_field: Option<Atom>,
     auto_partial_merge: bool,
 }
@@ -56,7 +75,9 @@ impl Default for DockerConfig {
         Self {
             include_containers: None,
             include_labels: None,
+            exclude_labels: None,
             include_images: None,
+            exclude_images: None,
             partial_event_marker_field: Some(event::PARTIAL.clone()),
             auto_partial_merge: true,
         }
@@ -96,6 +117,84 @@ impl DockerConfig {
     }
 }
 
+/// Image and label filters, checked against the details of each container.
+struct ContainerFilters {
+    include_labels: Vec<LabelPattern>,
+    exclude_labels: Vec<LabelPattern>,
+    include_images: Vec<Pattern>,
+    exclude_images: Vec<Pattern>,
+}
+
+impl ContainerFilters {
+    fn new(config: &DockerConfig) -> Result<Self, BuildError> {
+        fn patterns<T>(
+            list: &Option<Vec<String>>,
+            parse: impl Fn(&str) -> Result<T, PatternError>,
+        ) -> Result<Vec<T>, BuildError> {
+            list.iter()
+                .flatten()
+                .map(|pattern| parse(pattern).context(InvalidPattern { pattern }))
+                .collect()
+        }
+
+        Ok(ContainerFilters {
+            include_labels: patterns(&config.include_labels, LabelPattern::new)?,
+            exclude_labels: patterns(&config.exclude_labels, LabelPattern::new)?,
+            include_images: patterns(&config.include_images, Pattern::new)?,
+            exclude_images: patterns(&config.exclude_images, Pattern::new)?,
+        })
+    }
+
+    /// A container is included if its image matches any of the included
+    /// images, and it has all of the included labels, unless its image or
+    /// any of its labels are excluded.
+    fn included(&self, image: &str, labels: &HashMap<String, String>) -> bool {
+        (self.include_images.is_empty()
+            || self
+                .include_images
+                .iter()
+                .any(|pattern| pattern.matches(image)))
+            && !self
+                .exclude_images
+                .iter()
+                .any(|pattern| pattern.matches(image))
+            && self
+                .include_labels
+                .iter()
+                .all(|pattern| pattern.matches(labels))
+            && !self
+                .exclude_labels
+                .iter()
+                .any(|pattern| pattern.matches(labels))
+    }
+}
+
+/// `key` or `key=value`, where both the key and the value are globs.
+/// Without a value, labels with any value match.
+struct LabelPattern {
+    key: Pattern,
+    value: Option<Pattern>,
+}
+
+impl LabelPattern {
+    fn new(label: &str) -> Result<Self, PatternError> {
+        let mut parts = label.splitn(2, '=');
+        let key = Pattern::new(parts.next().unwrap_or_default())?;
+        let value = parts.next().map(Pattern::new).transpose()?;
+        Ok(LabelPattern { key, value })
+    }
+
+    fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        labels.iter().any(|(key, value)| {
+            self.key.matches(key)
+                && self
+                    .value
+                    .as_ref()
+                    .map_or(true, |pattern| pattern.matches(value))
+        })
+    }
+}
+
 inventory::submit! {
     SourceDescription::new::<DockerConfig>("docker")
 }
@@ -130,12 +229,15 @@ impl SourceConfig for DockerConfig {
 struct DockerSourceCore {
     config: DockerConfig,
     docker: Docker,
+    filters: ContainerFilters,
     /// Only logs created at, or after this moment are logged.
     now_timestamp: DateTime<Utc>,
 }
 
 impl DockerSourceCore {
     fn new(config: DockerConfig) -> crate::Result<Self> {
+        let filters = ContainerFilters::new(&config)?;
+
         // ?NOTE: Constructs a new Docker instance for a docker host listening at url specified by an env var DOCKER_HOST.
         // ?      Otherwise connects to unix socket which requires sudo privileges, or docker group membership.
         let docker = Docker::new();
@@ -150,6 +252,7 @@ impl DockerSourceCore {
         Ok(DockerSourceCore {
             config,
             docker,
+            filters,
             now_timestamp: now.into(),
         })
     }
@@ -176,27 +279,18 @@ impl DockerSourceCore {
                 .collect(),
         );
 
-        // Apply include filters
-        let mut filters = Vec::new();
-
+        // Apply include filters. Images and labels are matched as globs,
+        // which docker doesn't support, so they are checked once the
+        // container details are fetched.
         if let Some(include_containers) = &self.config.include_containers {
-            filters.extend(
+            options.filter(
                 include_containers
                     .iter()
-                    .map(|s| EventFilter::Container(s.clone())),
+                    .map(|s| EventFilter::Container(s.clone()))
+                    .collect(),
             );
         }
 
-        if let Some(include_labels) = &self.config.include_labels {
-            filters.extend(include_labels.iter().map(|l| EventFilter::Label(l.clone())));
-        }
-
-        if let Some(include_images) = &self.config.include_images {
-            filters.extend(include_images.iter().map(|l| EventFilter::Image(l.clone())));
-        }
-
-        options.filter(filters);
-
         self.docker.events(&options.build())
     }
 }
@@ -281,30 +375,15 @@ impl DockerSource {
 
     /// Future that captures currently running containers, and starts event streams for them.
     fn running_containers(mut self) -> impl Future<Item = Self, Error = ()> {
-        let mut options = shiplift::ContainerListOptions::builder();
-
-        // by docker API, using both type of include results in AND between them
         // TODO: missing feature in shiplift to include ContainerFilter::Name
-
-        // Include-label
-        options.filter(
-            self.esb
-                .core
-                .config
-                .include_labels
-                .clone()
-                .unwrap_or_default()
-                .iter()
-                .map(|s| ContainerFilter::LabelName(s.clone()))
-                .collect(),
-        );
+        let options = shiplift::ContainerListOptions::builder().build();
 
         // Future
         self.esb
             .core
             .docker
             .containers()
-            .list(&options.build())
+            .list(&options)
             .map(move |list| {
                 for container in list {
                     trace!(
@@ -338,17 +417,9 @@ impl DockerSource {
                         continue;
                     }
 
-                    // Include image check
-                    if let Some(images) = self.esb.core.config.include_images.as_ref() {
-                        let image_check = images.iter().any(|image| &container.image == image);
-                        if !images.is_empty() && !image_check {
-                            continue;
-                        }
-                    }
-
                     let id = ContainerId::new(container.id);
 
-                    self.containers.insert(id.clone(), self.esb.start(id));
+                    self.containers.insert(id.clone(), self.esb.start(id, None));
                 }
 
                 self
@@ -449,9 +520,12 @@ impl Future for DockerSource {
                                             );
 
                                             if include_name && self_check {
-                                                // Included
-                                                self.containers
-                                                    .insert(id.clone(), self.esb.start(id));
+                                                // Included, with all of its logs since it started.
+                                                let started = Utc.timestamp(event.time as i64, 0);
+                                                self.containers.insert(
+                                                    id.clone(),
+                                                    self.esb.start(id, Some(started)),
+                                                );
                                             } else {
                                                 // Ignore
                                             }
@@ -514,8 +588,15 @@ impl EventStreamBuilder {
         }
     }
 
-    /// Constructs and runs event stream until shutdown.
-    fn start(&self, id: ContainerId) -> ContainerState {
+    /// Constructs and runs event stream until shutdown, if the container
+    /// passes the filters.
+    ///
+    /// Logs are read from `since`, or from the creation of the core.
+    /// Docker timestamps the logs with its own clock, so the time docker
+    /// reported the container started at is used for containers started
+    /// later, to not miss their first lines.
+    fn start(&self, id: ContainerId, since: Option<DateTime<Utc>>) -> ContainerState {
+        let core = Arc::clone(&self.core);
         let metadata_fetch = self
             .core
             .docker
@@ -523,14 +604,25 @@ impl EventStreamBuilder {
             .get(id.as_str())
             .inspect()
             .map_err(|error| error!(message="Fetching container details failed",%error))
-            .and_then(|details| {
+            .and_then(move |details| {
+                let empty = HashMap::new();
+                let labels = details.config.labels.as_ref().unwrap_or(&empty);
+                if !core.filters.included(&details.config.image, labels) {
+                    trace!(
+                        message = "Container excluded",
+                        id = field::display(&details.id)
+                    );
+                    return Err(());
+                }
+
                 ContainerMetadata::from_details(&details)
                     .map_err(|error| error!(message="Metadata extraction failed",%error))
             });
 
         let this = self.clone();
         let task = metadata_fetch.and_then(move |metadata| {
-            this.start_event_stream(ContainerLogInfo::new(id, metadata, this.core.now_timestamp))
+            let since = since.unwrap_or(this.core.now_timestamp);
+            this.start_event_stream(ContainerLogInfo::new(id, metadata, since))
         });
 
         tokio01::spawn(task);
@@ -838,6 +930,14 @@ impl ContainerLogInfo {
             // Container name.
             log_event.insert(NAME.clone(), self.metadata.name.clone());
 
+            // Compose project and service, of containers made by docker-compose.
+            if let Some(project) = &self.metadata.compose_project {
+                log_event.insert(COMPOSE_PROJECT.clone(), project.clone());
+            }
+            if let Some(service) = &self.metadata.compose_service {
+                log_event.insert(COMPOSE_SERVICE.clone(), service.clone());
+            }
+
             // Container image.
             log_event.insert(IMAGE.clone(), self.metadata.image.clone());
 
@@ -908,6 +1008,10 @@ struct ContainerMetadata {
     name: Value,
     /// image -> String
     image: Value,
+    /// compose_project -> String
+    compose_project: Option<Value>,
+    /// compose_service -> String
+    compose_service: Option<Value>,
     /// created_at
     created_at: DateTime<Utc>,
 }
@@ -924,11 +1028,21 @@ impl ContainerMetadata {
                     .collect()
             })
             .unwrap_or_default();
+        let compose_label = |key: &str| {
+            details
+                .config
+                .labels
+                .as_ref()
+                .and_then(|map| map.get(key))
+                .map(|value| value.as_str().into())
+        };
 
         Ok(ContainerMetadata {
             labels,
             name: remove_slash(details.name.as_str()).into(),
             image: details.config.image.as_str().into(),
+            compose_project: compose_label(COMPOSE_PROJECT_LABEL),
+            compose_service: compose_label(COMPOSE_SERVICE_LABEL),
             created_at: DateTime::parse_from_rfc3339(details.created.as_str())?
                 .with_timezone(&Utc)
                 .into(),
@@ -1416,4 +1530,60 @@ mod tests {
 
         assert!(rt.block_on(is_empty(exclude_out)).unwrap());
     }
+
+    #[test]
+    fn include_image_glob() {
+        let message = "18";
+        let name = "vector_test_include_image_glob";
+        let config = DockerConfig {
+            include_containers: Some(vec![name.to_owned()]),
+            include_images: Some(vec!["busy*".to_owned()]),
+            ..DockerConfig::default()
+        };
+
+        let mut rt = test_util::runtime();
+        let out = source_with_config(config, &mut rt);
+        let docker = docker();
+
+        let id = container_log_n(1, name, None, message, &docker, &mut rt);
+
+        let events = rt.block_on(collect_n(out, 1)).ok().unwrap();
+
+        container_remove(&id, &docker, &mut rt);
+
+        assert_eq!(
+            events[0].as_log()[&event::log_schema().message_key()],
+            message.into()
+        )
+    }
+
+    #[test]
+    fn exclude_labels() {
+        let message = "19";
+        let name0 = "vector_test_exclude_labels_0";
+        let name1 = "vector_test_exclude_labels_1";
+        let label = "vector_test_exclude_label";
+        let config = DockerConfig {
+            include_containers: Some(vec![name0.to_owned(), name1.to_owned()]),
+            exclude_labels: Some(vec!["vector_test_exclude_*".to_owned()]),
+            ..DockerConfig::default()
+        };
+
+        let mut rt = test_util::runtime();
+        let out = source_with_config(config, &mut rt);
+        let docker = docker();
+
+        let id0 = container_log_n(1, name0, label, "20", &docker, &mut rt);
+        let id1 = container_log_n(1, name1, None, message, &docker, &mut rt);
+
+        let events = rt.block_on(collect_n(out, 1)).ok().unwrap();
+
+        container_remove(&id0, &docker, &mut rt);
+        container_remove(&id1, &docker, &mut rt);
+
+        assert_eq!(
+            events[0].as_log()[&event::log_schema().message_key()],
+            message.into()
+        )
+    }
 }
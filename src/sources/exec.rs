@@ -0,0 +1,482 @@
+//! Runs a command and turns the lines it outputs into events: either on an
+//! interval, with the output of each run captured whole, or kept running,
+//! with its output streamed as it comes.
+
+use crate::{
+    event::{self, Event},
+    internal_events::{ExecCommandExited, ExecEventsReceived, ExecFailed, ExecReadFailed},
+    shutdown::ShutdownSignal,
+    topology::{
+        config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+        scheduler,
+    },
+};
+use bytes::Bytes;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    future::{select, Either, FutureExt, TryFutureExt},
+    stream::{self, BoxStream, StreamExt},
+};
+use futures01::{stream::iter_ok, sync::mpsc, Sink};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    time::delay_for,
+};
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`command` must name the program to run"))]
+    EmptyCommand,
+    #[snafu(display("`exec_interval_secs` must be at least 1"))]
+    IntervalTooShort,
+    #[snafu(display("`exec_timeout_secs` must be at least 1"))]
+    TimeoutTooShort,
+}
+
+#[derive(Debug, Snafu)]
+pub enum ExecError {
+    #[snafu(display("Unable to start command: {}", source))]
+    Spawn { source: io::Error },
+    #[snafu(display("Unable to wait for command: {}", source))]
+    Wait { source: io::Error },
+    #[snafu(display("Pipeline closed"))]
+    PipelineClosed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecConfig {
+    /// The program to run, followed by its arguments.
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default = "default_exec_interval_secs")]
+    pub exec_interval_secs: u64,
+    /// Runs not done by then are killed, defaults to the interval.
+    pub exec_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    #[serde(default = "default_restart_delay_secs")]
+    pub restart_delay_secs: u64,
+    /// Set for the command, on top of the environment of Vector unless
+    /// `clear_environment`.
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+    #[serde(default)]
+    pub clear_environment: bool,
+    pub working_directory: Option<PathBuf>,
+    /// Otherwise stderr is left to go where Vector's goes.
+    #[serde(default = "crate::serde::default_true")]
+    pub include_stderr: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Scheduled,
+    Streaming,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Scheduled
+    }
+}
+
+/// When the command is run again after exiting, in streaming mode.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    Always,
+    OnFailure,
+    Never,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Always
+    }
+}
+
+impl RestartPolicy {
+    /// `status` is `None` when the command couldn't be run at all.
+    fn restarts(self, status: Option<ExitStatus>) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => status.map_or(true, |status| !status.success()),
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+fn default_exec_interval_secs() -> u64 {
+    60
+}
+
+fn default_restart_delay_secs() -> u64 {
+    5
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<ExecConfig>("exec")
+}
+
+#[typetag::serde(name = "exec")]
+impl SourceConfig for ExecConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.command.is_empty() {
+            return Err(BuildError::EmptyCommand.into());
+        }
+        if self.mode == Mode::Scheduled && self.exec_interval_secs == 0 {
+            return Err(BuildError::IntervalTooShort.into());
+        }
+        if self.mode == Mode::Scheduled && self.exec_timeout_secs == Some(0) {
+            return Err(BuildError::TimeoutTooShort.into());
+        }
+
+        let run = match self.mode {
+            Mode::Scheduled => run_scheduled(self.clone(), shutdown, out).boxed(),
+            Mode::Streaming => run_streaming(self.clone(), shutdown, out).boxed(),
+        };
+        Ok(Box::new(run.compat()))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "exec"
+    }
+}
+
+impl ExecConfig {
+    fn command_line(&self) -> String {
+        self.command.join(" ")
+    }
+
+    fn spawn(&self) -> Result<Child, ExecError> {
+        let mut command = Command::new(&self.command[0]);
+        command.args(&self.command[1..]);
+        if self.clear_environment {
+            command.env_clear();
+        }
+        command.envs(&self.environment);
+        if let Some(directory) = &self.working_directory {
+            command.current_dir(directory);
+        }
+        command.stdin(Stdio::null()).stdout(Stdio::piped());
+        if self.include_stderr {
+            command.stderr(Stdio::piped());
+        }
+        // Commands still running at shutdown are killed.
+        command.kill_on_drop(true);
+
+        command.spawn().context(Spawn)
+    }
+}
+
+/// Runs the command on every interval, as scheduled with the scraping
+/// sources. The events of a run are sent once the command exits, with its
+/// exit status.
+async fn run_scheduled(
+    config: ExecConfig,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let command = config.command_line();
+    let deadline = Duration::from_secs(
+        config
+            .exec_timeout_secs
+            .unwrap_or(config.exec_interval_secs),
+    );
+    let mut ticks = scheduler::interval(Duration::from_secs(config.exec_interval_secs)).compat();
+    let mut shutdown = shutdown.compat();
+
+    loop {
+        match select(ticks.next(), &mut shutdown).await {
+            Either::Left((Some(Ok(_)), _)) => (),
+            Either::Left((Some(Err(error)), _)) => {
+                error!(message = "Timer error.", %error);
+                return Err(());
+            }
+            Either::Left((None, _)) | Either::Right(_) => return Ok(()),
+        }
+
+        let run = {
+            let (config, command, out) = (config.clone(), command.clone(), out.clone());
+            async move { run_once(&config, &command, out).await }
+        };
+        // Killed when not done by the deadline, as the child is dropped.
+        let run = scheduler::scrape("exec", deadline, run.boxed().compat()).compat();
+        match select(run, &mut shutdown).await {
+            Either::Right(_) => return Ok(()),
+            Either::Left((Err(ExecError::PipelineClosed), _)) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Either::Left((Err(error), _)) => emit!(ExecFailed {
+                command: &command,
+                error
+            }),
+            Either::Left((Ok(_), _)) => (),
+        }
+    }
+}
+
+async fn run_once(
+    config: &ExecConfig,
+    command: &str,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ExecError> {
+    let started = Instant::now();
+    let mut child = config.spawn()?;
+    let pid = child.id();
+
+    let lines = output_lines(&mut child).collect::<Vec<_>>().await;
+    let status = child.await.context(Wait)?;
+    emit!(ExecCommandExited {
+        command,
+        exit_status: status.code(),
+        elapsed: started.elapsed(),
+    });
+
+    emit!(ExecEventsReceived {
+        count: lines.len(),
+        byte_size: lines.iter().map(|line| line.bytes.len()).sum(),
+    });
+    let events = lines.into_iter().map(|line| {
+        let mut event = line.into_event(command, pid);
+        if let Some(code) = status.code() {
+            event.as_mut_log().insert("exit_status", i64::from(code));
+        }
+        event
+    });
+
+    out.send_all(iter_ok(events))
+        .compat()
+        .await
+        .map_err(|_| ExecError::PipelineClosed)?;
+    Ok(())
+}
+
+/// Keeps the command running, sending the lines it outputs as they come,
+/// and restarting it as the restart policy says once it exits.
+async fn run_streaming(
+    config: ExecConfig,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let command = config.command_line();
+    let mut shutdown = shutdown.compat();
+
+    loop {
+        let stream = Box::pin(stream_output(&config, &command, out.clone()));
+        let status = match select(stream, &mut shutdown).await {
+            Either::Right(_) => return Ok(()),
+            Either::Left((Err(ExecError::PipelineClosed), _)) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Either::Left((Err(error), _)) => {
+                emit!(ExecFailed {
+                    command: &command,
+                    error
+                });
+                None
+            }
+            Either::Left((Ok(status), _)) => Some(status),
+        };
+
+        if !config.restart.restarts(status) {
+            return Ok(());
+        }
+        let delay = Box::pin(delay_for(Duration::from_secs(config.restart_delay_secs)));
+        if let Either::Right(_) = select(delay, &mut shutdown).await {
+            return Ok(());
+        }
+    }
+}
+
+async fn stream_output(
+    config: &ExecConfig,
+    command: &str,
+    mut out: mpsc::Sender<Event>,
+) -> Result<ExitStatus, ExecError> {
+    let started = Instant::now();
+    let mut child = config.spawn()?;
+    let pid = child.id();
+
+    let mut lines = output_lines(&mut child);
+    while let Some(line) = lines.next().await {
+        emit!(ExecEventsReceived {
+            count: 1,
+            byte_size: line.bytes.len(),
+        });
+        out = out
+            .send(line.into_event(command, pid))
+            .compat()
+            .await
+            .map_err(|_| ExecError::PipelineClosed)?;
+    }
+
+    let status = child.await.context(Wait)?;
+    emit!(ExecCommandExited {
+        command,
+        exit_status: status.code(),
+        elapsed: started.elapsed(),
+    });
+    Ok(status)
+}
+
+struct Line {
+    stream: &'static str,
+    bytes: Bytes,
+}
+
+impl Line {
+    fn into_event(self, command: &str, pid: u32) -> Event {
+        let mut event = Event::from(self.bytes);
+        let log = event.as_mut_log();
+        log.insert(event::log_schema().source_type_key(), "exec");
+        log.insert("command", command);
+        log.insert("pid", i64::from(pid));
+        log.insert("stream", self.stream);
+        event
+    }
+}
+
+/// The lines of stdout, and of stderr when piped, as they come.
+fn output_lines(child: &mut Child) -> BoxStream<'static, Line> {
+    let stdout = child.stdout.take().map(|pipe| read_lines(pipe, "stdout"));
+    let stderr = child.stderr.take().map(|pipe| read_lines(pipe, "stderr"));
+    stream::select(
+        stream::iter(stdout).flatten(),
+        stream::iter(stderr).flatten(),
+    )
+    .boxed()
+}
+
+/// Empty lines are skipped.
+fn read_lines<R>(pipe: R, stream: &'static str) -> BoxStream<'static, Line>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    stream::unfold(BufReader::new(pipe), move |mut reader| async move {
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            match reader.read_until(b'\n', &mut bytes).await {
+                Ok(0) => return None,
+                Ok(_) => {
+                    while bytes.ends_with(b"\n") || bytes.ends_with(b"\r") {
+                        bytes.pop();
+                    }
+                    if !bytes.is_empty() {
+                        let line = Line {
+                            stream,
+                            bytes: bytes.into(),
+                        };
+                        return Some((line, reader));
+                    }
+                }
+                Err(error) => {
+                    emit!(ExecReadFailed { stream, error });
+                    return None;
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::Value,
+        test_util::{collect_n, runtime},
+    };
+
+    fn config(command: &[&str], mode: Mode) -> ExecConfig {
+        ExecConfig {
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            mode,
+            exec_interval_secs: 60,
+            exec_timeout_secs: None,
+            restart: RestartPolicy::Never,
+            restart_delay_secs: 0,
+            environment: vec![("GREETING".to_owned(), "hello".to_owned())]
+                .into_iter()
+                .collect(),
+            clear_environment: false,
+            working_directory: None,
+            include_stderr: true,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scheduled_run_captures_output_and_exit_status() {
+        let mut rt = runtime();
+        let (tx, rx) = mpsc::channel(10);
+        let config = config(
+            &["sh", "-c", "echo $GREETING; echo oops >&2; exit 3"],
+            Mode::Scheduled,
+        );
+        let command = config.command_line();
+
+        rt.block_on_std(async move { run_once(&config, &command, tx).await.unwrap() });
+        let mut events = rt.block_on(collect_n(rx, 2)).unwrap();
+        events.sort_by_key(|event| event.as_log()[&"stream".into()].to_string_lossy());
+
+        assert_eq!(events.len(), 2);
+        let stderr = events[0].as_log();
+        assert_eq!(stderr[&event::log_schema().message_key()], "oops".into());
+        assert_eq!(stderr[&"exit_status".into()], Value::Integer(3));
+        let stdout = events[1].as_log();
+        assert_eq!(stdout[&event::log_schema().message_key()], "hello".into());
+        assert_eq!(stdout[&"stream".into()], "stdout".into());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn streaming_run_ends_without_restart() {
+        let mut rt = runtime();
+        let (tx, rx) = mpsc::channel(10);
+        let config = config(&["sh", "-c", "echo one; echo two"], Mode::Streaming);
+
+        let source = config
+            .build(
+                "exec",
+                &GlobalOptions::default(),
+                ShutdownSignal::noop(),
+                tx,
+            )
+            .unwrap();
+        rt.block_on(source).unwrap();
+        let events = rt.block_on(collect_n(rx, 2)).unwrap();
+
+        let messages = events
+            .iter()
+            .map(|event| event.as_log()[&event::log_schema().message_key()].to_string_lossy())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, vec!["one", "two"]);
+    }
+}
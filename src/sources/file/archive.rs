@@ -0,0 +1,278 @@
+//! Reading of archive directories, where files are dropped off complete and
+//! are read once, oldest first, rather than followed as they grow.
+
+use crate::internal_events::{FileArchiveRead, FileArchiveReadFailed};
+use bytes::Bytes;
+use file_source::paths_provider::PathsProvider;
+use flate2::read::MultiGzDecoder;
+use futures::{
+    executor::block_on,
+    future::{select, Either},
+    stream, Future, FutureExt, Sink, SinkExt,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::delay_for;
+
+/// How many lines are read before being sent on, and shutdown checked for.
+const LINES_PER_BATCH: usize = 100;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AfterReadConfig {
+    /// Files are left in place, and remembered as read in the data directory.
+    Keep,
+    Delete,
+    Move {
+        directory: PathBuf,
+    },
+}
+
+impl Default for AfterReadConfig {
+    fn default() -> Self {
+        AfterReadConfig::Keep
+    }
+}
+
+pub struct ArchiveReader<PP>
+where
+    PP: PathsProvider,
+{
+    pub paths_provider: PP,
+    pub max_line_bytes: usize,
+    pub ignore_before: Option<SystemTime>,
+    pub data_dir: PathBuf,
+    pub glob_minimum_cooldown: Duration,
+    pub after_read: AfterReadConfig,
+}
+
+impl<PP> ArchiveReader<PP>
+where
+    PP: PathsProvider,
+{
+    /// Reads the files found, one after the other, until shutdown. A file
+    /// being read at shutdown is read again from the start next time.
+    pub fn run<C>(
+        self,
+        mut chans: C,
+        mut shutdown: impl Future + Unpin,
+    ) -> Result<(), <C as Sink<(Bytes, String)>>::Error>
+    where
+        C: Sink<(Bytes, String)> + Unpin,
+        <C as Sink<(Bytes, String)>>::Error: std::error::Error,
+    {
+        let mut read = ReadFiles::load(&self.data_dir);
+
+        loop {
+            for (file, path) in self.files_to_read(&read) {
+                let mut lines = match open(&path) {
+                    Ok(reader) => Lines::new(reader, self.max_line_bytes),
+                    Err(error) => {
+                        emit!(FileArchiveReadFailed { path: &path, error });
+                        continue;
+                    }
+                };
+                let name = path.to_string_lossy().into_owned();
+
+                loop {
+                    if (&mut shutdown).now_or_never().is_some() {
+                        return Ok(());
+                    }
+                    let batch = match lines.next_batch() {
+                        Ok(batch) => batch,
+                        Err(error) => {
+                            // Corrupt files are left as they are, but not
+                            // read again.
+                            emit!(FileArchiveReadFailed { path: &path, error });
+                            read.insert(file);
+                            break;
+                        }
+                    };
+                    if batch.is_empty() {
+                        emit!(FileArchiveRead {
+                            path: &path,
+                            lines: lines.count,
+                        });
+                        self.finish(&path, file, &mut read);
+                        break;
+                    }
+                    let mut batch =
+                        stream::iter(batch.into_iter().map(|line| Ok((line, name.clone()))));
+                    block_on(chans.send_all(&mut batch))?;
+                }
+            }
+
+            match block_on(select(shutdown, delay_for(self.glob_minimum_cooldown))) {
+                Either::Left(_) => return Ok(()),
+                Either::Right((_, future)) => shutdown = future,
+            }
+        }
+    }
+
+    /// The files not read yet, by their modification times.
+    fn files_to_read(&self, read: &ReadFiles) -> Vec<(ReadFile, PathBuf)> {
+        let mut files = self
+            .paths_provider
+            .paths()
+            .into_iter()
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let modified = metadata.modified().ok()?;
+                if self.ignore_before.map_or(false, |before| modified < before) {
+                    return None;
+                }
+                let file = ReadFile {
+                    path: path.clone(),
+                    size: metadata.len(),
+                    modified: modified
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |modified| modified.as_nanos() as u64),
+                };
+                if read.contains(&file) {
+                    None
+                } else {
+                    Some((file, path))
+                }
+            })
+            .collect::<Vec<_>>();
+        files.sort_by_key(|(file, _)| file.modified);
+        files
+    }
+
+    /// Remembers the file as read, unless it is moved out of the way.
+    fn finish(&self, path: &Path, file: ReadFile, read: &mut ReadFiles) {
+        let result = match &self.after_read {
+            AfterReadConfig::Keep => {
+                read.insert(file);
+                return;
+            }
+            AfterReadConfig::Delete => fs::remove_file(path),
+            AfterReadConfig::Move { directory } => fs::create_dir_all(directory).and_then(|()| {
+                let name = path.file_name().unwrap_or_default();
+                fs::rename(path, directory.join(name))
+            }),
+        };
+        if let Err(error) = result {
+            error!(
+                message = "Unable to remove read file, it won't be read again.",
+                ?path,
+                %error
+            );
+            read.insert(file);
+        }
+    }
+}
+
+/// Files are told apart by their size and modification time too, as a file
+/// of the same name may be dropped off again.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+struct ReadFile {
+    path: PathBuf,
+    size: u64,
+    modified: u64,
+}
+
+/// The files read, kept one per line in the data directory.
+struct ReadFiles {
+    path: PathBuf,
+    files: HashSet<ReadFile>,
+}
+
+impl ReadFiles {
+    fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join("archived_files");
+        let files = match fs::read_to_string(&path) {
+            Ok(text) => text
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(error) => {
+                error!(message = "Unable to load the files read.", ?path, %error);
+                HashSet::new()
+            }
+        };
+        Self { path, files }
+    }
+
+    fn contains(&self, file: &ReadFile) -> bool {
+        self.files.contains(file)
+    }
+
+    fn insert(&mut self, file: ReadFile) {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut record| {
+                let line = serde_json::to_string(&file).expect("file serializes");
+                writeln!(record, "{}", line)
+            });
+        if let Err(error) = result {
+            error!(message = "Unable to record file as read.", path = ?self.path, %error);
+        }
+        self.files.insert(file);
+    }
+}
+
+/// Opens the file, decompressing it if it is gzip or zstd compressed.
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let header = reader.fill_buf()?;
+    let reader: Box<dyn Read> = if header.starts_with(&[0x1f, 0x8b]) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        return Ok(Box::new(reader));
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+struct Lines {
+    reader: Box<dyn BufRead>,
+    max_line_bytes: usize,
+    count: usize,
+}
+
+impl Lines {
+    fn new(reader: Box<dyn BufRead>, max_line_bytes: usize) -> Self {
+        Self {
+            reader,
+            max_line_bytes,
+            count: 0,
+        }
+    }
+
+    /// The next lines of the file, none at its end. Empty lines, and lines
+    /// longer than `max_line_bytes`, are skipped.
+    fn next_batch(&mut self) -> io::Result<Vec<Bytes>> {
+        let mut batch = Vec::new();
+        let mut line = Vec::new();
+        while batch.len() < LINES_PER_BATCH {
+            line.clear();
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if line.len() > self.max_line_bytes {
+                warn!(
+                    message = "Found line that exceeds max_line_bytes; discarding.",
+                    rate_limit_secs = 30
+                );
+            } else if !line.is_empty() {
+                batch.push(Bytes::from(&line[..]));
+            }
+        }
+        self.count += batch.len();
+        Ok(batch)
+    }
+}
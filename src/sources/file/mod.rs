@@ -1,11 +1,14 @@
 use crate::{
-    event::{self, Event},
+    event::{self, Event, Value},
     internal_events::FileEventReceived,
+    replay::TimeWindow,
     shutdown::ShutdownSignal,
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
     trace::{current_span, Instrument},
+    types::Conversion,
 };
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use file_source::{
     paths_provider::glob::{Glob, MatchOptions},
     FileServer, Fingerprinter,
@@ -23,7 +26,10 @@ use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 use tokio::task::spawn_blocking;
 
+mod archive;
 mod line_agg;
+pub use archive::AfterReadConfig;
+use archive::ArchiveReader;
 use line_agg::LineAgg;
 
 #[derive(Debug, Snafu)]
@@ -71,6 +77,8 @@ enum BuildError {
         condition_pattern: String,
         source: regex::Error,
     },
+    #[snafu(display("after_read is only supported in archive mode"))]
+    AfterReadWithoutArchive,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -92,6 +100,25 @@ pub struct FileConfig {
     pub multiline: Option<MultilineConfig>,
     pub max_read_bytes: usize,
     pub oldest_first: bool,
+    pub use_notifications: bool,
+    /// Only lines whose time is within these are read, files last modified
+    /// before `since` are skipped altogether.
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// The format of the time starting each line, guessed when unset.
+    pub time_format: Option<String>,
+    pub mode: ReadMode,
+    /// What is done with files once read in archive mode.
+    pub after_read: AfterReadConfig,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadMode {
+    /// Files are followed as they grow, and as they are rotated.
+    Tail,
+    /// Files are read once, oldest first, decompressed if need be.
+    Archive,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -181,6 +208,12 @@ impl Default for FileConfig {
             multiline: None,
             max_read_bytes: 2048,
             oldest_first: false,
+            use_notifications: true,
+            since: None,
+            until: None,
+            time_format: None,
+            mode: ReadMode::Tail,
+            after_read: AfterReadConfig::Keep,
         }
     }
 }
@@ -212,6 +245,10 @@ impl SourceConfig for FileConfig {
             Regex::new(indicator).with_context(|| InvalidMessageStartIndicator { indicator })?;
         }
 
+        if self.mode == ReadMode::Tail && self.after_read != AfterReadConfig::Keep {
+            return Err(BuildError::AfterReadWithoutArchive.into());
+        }
+
         Ok(file_source(self, data_dir, shutdown, out))
     }
 
@@ -230,24 +267,39 @@ pub fn file_source(
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> super::Source {
-    let ignore_before = config
+    let ignore_older = config
         .ignore_older
         .map(|secs| SystemTime::now() - Duration::from_secs(secs));
+    let ignore_before = match (ignore_older, config.since.map(SystemTime::from)) {
+        (Some(older), Some(since)) => Some(older.max(since)),
+        (older, since) => older.or(since),
+    };
     let glob_minimum_cooldown = Duration::from_millis(config.glob_minimum_cooldown);
 
     let paths_provider = Glob::new(&config.include, &config.exclude, MatchOptions::default())
         .expect("invalid glob patterns");
 
-    let file_server = FileServer {
-        paths_provider,
-        max_read_bytes: config.max_read_bytes,
-        start_at_beginning: config.start_at_beginning,
-        ignore_before,
-        max_line_bytes: config.max_line_bytes,
-        data_dir,
-        glob_minimum_cooldown,
-        fingerprinter: config.fingerprinting.clone().into(),
-        oldest_first: config.oldest_first,
+    let reader = match config.mode {
+        ReadMode::Tail => Reader::Tail(FileServer {
+            paths_provider,
+            max_read_bytes: config.max_read_bytes,
+            start_at_beginning: config.start_at_beginning,
+            ignore_before,
+            max_line_bytes: config.max_line_bytes,
+            data_dir,
+            glob_minimum_cooldown,
+            fingerprinter: config.fingerprinting.clone().into(),
+            oldest_first: config.oldest_first,
+            use_notifications: config.use_notifications,
+        }),
+        ReadMode::Archive => Reader::Archive(ArchiveReader {
+            paths_provider,
+            max_line_bytes: config.max_line_bytes,
+            ignore_before,
+            data_dir,
+            glob_minimum_cooldown,
+            after_read: config.after_read.clone(),
+        }),
     };
 
     let file_key = config.file_key.clone();
@@ -262,6 +314,8 @@ pub fn file_source(
     let multiline_config = config.multiline.clone();
     let message_start_indicator = config.message_start_indicator.clone();
     let multi_line_timeout = config.multi_line_timeout;
+    let window = TimeWindow::new(config.since, config.until);
+    let line_time = LineTime::new(config.time_format.as_deref());
     Box::new(future::lazy(move || {
         info!(message = "Starting file server.", ?include, ?exclude);
 
@@ -292,6 +346,7 @@ pub fn file_source(
         let span2 = span.clone();
         tokio01::spawn(
             messages
+                .filter(move |(msg, _)| window.is_empty() || window.contains(line_time.parse(msg)))
                 .map(move |(msg, file): (Bytes, String)| {
                     let _enter = span2.enter();
                     emit!(FileEventReceived {
@@ -308,11 +363,18 @@ pub fn file_source(
         let span = info_span!("file_server");
         spawn_blocking(move || {
             let _enter = span.enter();
-            let result = file_server.run(Compat01As03Sink::new(tx), shutdown.compat());
+            let tx = Compat01As03Sink::new(tx);
             // Panic if we encounter any error originating from the file server.
             // We're at the `spawn_blocking` call, the panic will be caught and
             // passed to the `JoinHandle` error, similar to the usual threads.
-            result.unwrap();
+            match reader {
+                Reader::Tail(file_server) => {
+                    file_server.run(tx, shutdown.compat()).unwrap();
+                }
+                Reader::Archive(archive_reader) => {
+                    archive_reader.run(tx, shutdown.compat()).unwrap();
+                }
+            }
         })
         .boxed()
         .compat()
@@ -320,6 +382,47 @@ pub fn file_source(
     }))
 }
 
+enum Reader {
+    Tail(FileServer<Glob>),
+    Archive(ArchiveReader<Glob>),
+}
+
+/// Parses the time starting a line, from as many words as its format has.
+struct LineTime {
+    conversion: Conversion,
+    words: usize,
+}
+
+impl LineTime {
+    fn new(format: Option<&str>) -> Self {
+        match format {
+            Some(format) => Self {
+                conversion: format!("timestamp|{}", format)
+                    .parse()
+                    .expect("timestamp conversions always parse"),
+                words: format.split_whitespace().count(),
+            },
+            None => Self {
+                conversion: Conversion::Timestamp,
+                words: 1,
+            },
+        }
+    }
+
+    fn parse(&self, line: &[u8]) -> Option<DateTime<Utc>> {
+        let line = String::from_utf8_lossy(line);
+        let time = line
+            .split_whitespace()
+            .take(self.words)
+            .collect::<Vec<_>>()
+            .join(" ");
+        match self.conversion.convert(Value::from(time)) {
+            Ok(Value::Timestamp(time)) => Some(time),
+            _ => None,
+        }
+    }
+}
+
 fn create_event(
     line: Bytes,
     file: String,
@@ -478,6 +581,20 @@ mod tests {
         assert_eq!(log[event::log_schema().source_type_key()], "file".into());
     }
 
+    #[test]
+    fn file_line_time() {
+        let time = "2020-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert_eq!(
+            LineTime::new(None).parse(b"2020-06-01T12:00:00Z GET /"),
+            Some(time)
+        );
+        assert_eq!(
+            LineTime::new(Some("%Y-%m-%d %H:%M:%S%z")).parse(b"2020-06-01 12:00:00+0000 GET /"),
+            Some(time)
+        );
+        assert_eq!(LineTime::new(None).parse(b"    at Main.main"), None);
+    }
+
     #[test]
     fn file_happy_path() {
         let n = 5;
@@ -540,6 +657,44 @@ mod tests {
         assert_eq!(goodbye_i, n);
     }
 
+    #[test]
+    fn file_polling_without_notifications() {
+        let (tx, rx) = futures01::sync::mpsc::channel(10);
+        let (trigger_shutdown, shutdown, _) = ShutdownSignal::new_wired();
+
+        let dir = tempdir().unwrap();
+        let config = file::FileConfig {
+            include: vec![dir.path().join("*")],
+            use_notifications: false,
+            ..test_default_file_config(&dir)
+        };
+
+        let source = file::file_source(&config, config.data_dir.clone().unwrap(), shutdown, tx);
+
+        let mut rt = runtime();
+
+        rt.spawn(source);
+
+        let path = dir.path().join("file");
+        let mut file = File::create(&path).unwrap();
+
+        sleep(); // The file must be observed at its original length before writing to it
+
+        writeln!(&mut file, "hello polling").unwrap();
+
+        sleep();
+
+        drop(trigger_shutdown);
+        shutdown_on_idle(rt);
+
+        let received = wait_with_timeout(rx.collect());
+        let lines = received
+            .into_iter()
+            .map(|event| event.as_log()[&event::log_schema().message_key()].to_string_lossy())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["hello polling"]);
+    }
+
     #[test]
     fn file_truncate() {
         let n = 5;
@@ -1483,4 +1638,60 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn file_archive_mode_reads_files_once_by_age() {
+        let (tx, rx) = futures01::sync::mpsc::channel(10);
+        let (trigger_shutdown, shutdown, _) = ShutdownSignal::new_wired();
+
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("archive");
+        fs::create_dir(&archive).unwrap();
+        let mut gzipped = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+        gzipped.write_all(b"oldest\ngzipped\n").unwrap();
+        fs::write(archive.join("c.log.gz"), gzipped.finish().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        let zstd = zstd::encode_all(&b"zstd compressed\n"[..], 0).unwrap();
+        fs::write(archive.join("b.log.zst"), zstd).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(archive.join("a.log"), "newest\n").unwrap();
+
+        let config = file::FileConfig {
+            include: vec![archive.join("*")],
+            mode: ReadMode::Archive,
+            after_read: AfterReadConfig::Delete,
+            ..test_default_file_config(&dir)
+        };
+
+        let source = file::file_source(&config, config.data_dir.clone().unwrap(), shutdown, tx);
+        let mut rt = runtime();
+        rt.spawn(source);
+
+        sleep();
+
+        drop(trigger_shutdown);
+        shutdown_on_idle(rt);
+
+        let received = wait_with_timeout(
+            rx.map(|event| {
+                event
+                    .as_log()
+                    .get(&event::log_schema().message_key())
+                    .unwrap()
+                    .clone()
+            })
+            .collect(),
+        );
+
+        assert_eq!(
+            received,
+            vec![
+                "oldest".into(),
+                "gzipped".into(),
+                "zstd compressed".into(),
+                "newest".into(),
+            ]
+        );
+        assert_eq!(fs::read_dir(&archive).unwrap().count(), 0);
+    }
 }
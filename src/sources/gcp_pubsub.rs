@@ -0,0 +1,426 @@
+use crate::{
+    event::{self, Event},
+    gcp::{GcpAuthConfig, GcpCredentials},
+    internal_events::{
+        GcpPubsubAckFailed, GcpPubsubMessageInvalid, GcpPubsubMessagesReceived, GcpPubsubPullFailed,
+    },
+    shutdown::ShutdownSignal,
+    sources::util::{https_client, HttpsClient},
+    tls::TlsOptions,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+};
+use futures01::{stream::iter_ok, sync::mpsc, Future, Sink, Stream};
+use goauth::scopes::Scope;
+use http::{Request, StatusCode, Uri};
+use hyper::Body;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::delay_for;
+
+/// The most messages a single pull returns.
+const MAX_PULL_MESSAGES: usize = 1000;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Invalid subscription URI {:?}: {}", uri, source))]
+    InvalidUri {
+        uri: String,
+        source: http::uri::InvalidUri,
+    },
+    #[snafu(display("`max_outstanding_messages` and `max_outstanding_bytes` must be above zero"))]
+    NoOutstanding,
+}
+
+#[derive(Debug, Snafu)]
+pub enum RequestError {
+    #[snafu(display("Request failed: {}", source))]
+    Http { source: hyper::Error },
+    #[snafu(display("Request failed with status {}: {}", status, body))]
+    Status { status: StatusCode, body: String },
+    #[snafu(display("Invalid response: {}", source))]
+    Json { source: serde_json::Error },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PubsubConfig {
+    pub project: String,
+    pub subscription: String,
+    /// Pulls from the Pub/Sub emulator at this address, without authentication.
+    pub emulator_host: Option<String>,
+    #[serde(flatten)]
+    pub auth: GcpAuthConfig,
+    /// How many messages may be pulled without being acknowledged yet.
+    #[serde(default = "default_max_outstanding_messages")]
+    pub max_outstanding_messages: usize,
+    /// How many bytes of message data may be pulled without being
+    /// acknowledged yet.
+    #[serde(default = "default_max_outstanding_bytes")]
+    pub max_outstanding_bytes: usize,
+    pub tls: Option<TlsOptions>,
+}
+
+fn default_max_outstanding_messages() -> usize {
+    1000
+}
+
+fn default_max_outstanding_bytes() -> usize {
+    100 * 1024 * 1024
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<PubsubConfig>("gcp_pubsub")
+}
+
+#[typetag::serde(name = "gcp_pubsub")]
+impl SourceConfig for PubsubConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.max_outstanding_messages == 0 || self.max_outstanding_bytes == 0 {
+            return Err(Box::new(BuildError::NoOutstanding));
+        }
+
+        let (base, creds) = match &self.emulator_host {
+            Some(host) => (format!("http://{}", host), None),
+            None => (
+                "https://pubsub.googleapis.com".to_owned(),
+                self.auth.make_credentials(Scope::PubSub)?,
+            ),
+        };
+        let uri = format!(
+            "{}/v1/projects/{}/subscriptions/{}",
+            base, self.project, self.subscription
+        );
+        uri.parse::<Uri>()
+            .context(InvalidUri { uri: uri.clone() })?;
+
+        let subscription = Subscription {
+            client: https_client(&self.tls)?,
+            uri,
+            api_key: self.auth.api_key.clone(),
+            creds,
+        };
+        let limits = FlowControl {
+            max_messages: self.max_outstanding_messages,
+            max_bytes: self.max_outstanding_bytes,
+            messages: Arc::new(AtomicUsize::new(0)),
+            bytes: Arc::new(AtomicUsize::new(0)),
+        };
+        Ok(Box::new(
+            run(Arc::new(subscription), limits, shutdown, out)
+                .boxed()
+                .compat(),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "gcp_pubsub"
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PullResponse {
+    /// Missing when no messages were available.
+    #[serde(default)]
+    received_messages: Vec<ReceivedMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReceivedMessage {
+    ack_id: String,
+    message: PubsubMessage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PubsubMessage {
+    /// Base64 encoded, missing when the message only has attributes.
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    attributes: HashMap<String, String>,
+    message_id: String,
+    publish_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    ordering_key: String,
+}
+
+impl PubsubMessage {
+    fn into_event(self) -> Result<Event, base64::DecodeError> {
+        let data = base64::decode(&self.data)?;
+        let mut event = Event::from(Bytes::from(data));
+        let log = event.as_mut_log();
+        if let Some(publish_time) = self.publish_time {
+            log.insert(event::log_schema().timestamp_key(), publish_time);
+        }
+        log.insert(event::log_schema().source_type_key(), "gcp_pubsub");
+        log.insert("message_id", self.message_id);
+        if !self.ordering_key.is_empty() {
+            log.insert("ordering_key", self.ordering_key);
+        }
+        for (name, value) in self.attributes {
+            log.insert(format!("attributes.{}", name), value);
+        }
+        Ok(event)
+    }
+}
+
+struct Subscription {
+    client: HttpsClient,
+    uri: String,
+    api_key: Option<String>,
+    creds: Option<GcpCredentials>,
+}
+
+impl Subscription {
+    async fn pull(&self, max_messages: usize) -> Result<Vec<ReceivedMessage>, RequestError> {
+        let response: PullResponse = self
+            .call("pull", json!({ "maxMessages": max_messages }))
+            .await?;
+        Ok(response.received_messages)
+    }
+
+    async fn acknowledge(&self, ack_ids: Vec<String>) -> Result<(), RequestError> {
+        self.call::<serde_json::Value>("acknowledge", json!({ "ackIds": ack_ids }))
+            .await
+            .map(|_| ())
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: serde_json::Value,
+    ) -> Result<T, RequestError> {
+        let mut uri = format!("{}:{}", self.uri, method);
+        if let Some(key) = &self.api_key {
+            uri = format!("{}?key={}", uri, key);
+        }
+        let mut request = Request::post(uri.as_str())
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("the URI was validated when building");
+        if let Some(creds) = &self.creds {
+            creds.apply(&mut request);
+        }
+
+        let response = self.client.request(request).compat().await.context(Http)?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .concat2()
+            .compat()
+            .await
+            .context(Http)?;
+        if !status.is_success() {
+            return Err(RequestError::Status {
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        serde_json::from_slice(&body).context(Json)
+    }
+}
+
+/// Counts the messages pulled and not acknowledged yet, and their bytes.
+#[derive(Clone)]
+struct FlowControl {
+    max_messages: usize,
+    max_bytes: usize,
+    messages: Arc<AtomicUsize>,
+    bytes: Arc<AtomicUsize>,
+}
+
+impl FlowControl {
+    /// How many messages the next pull may return, `None` while either
+    /// limit is reached.
+    fn available(&self) -> Option<usize> {
+        let messages = self.messages.load(Ordering::Acquire);
+        if messages >= self.max_messages || self.bytes.load(Ordering::Acquire) >= self.max_bytes {
+            None
+        } else {
+            Some((self.max_messages - messages).min(MAX_PULL_MESSAGES))
+        }
+    }
+
+    fn add(&self, messages: usize, bytes: usize) {
+        self.messages.fetch_add(messages, Ordering::AcqRel);
+        self.bytes.fetch_add(bytes, Ordering::AcqRel);
+    }
+
+    fn remove(&self, messages: usize, bytes: usize) {
+        self.messages.fetch_sub(messages, Ordering::AcqRel);
+        self.bytes.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+async fn run(
+    subscription: Arc<Subscription>,
+    limits: FlowControl,
+    shutdown: ShutdownSignal,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    if let Some(creds) = &subscription.creds {
+        creds.spawn_regenerate_token();
+    }
+
+    let mut shutdown = shutdown.compat();
+    loop {
+        let max_messages = match limits.available() {
+            Some(max_messages) => max_messages,
+            None => {
+                let wait = Box::pin(delay_for(Duration::from_millis(100)));
+                match select(wait, &mut shutdown).await {
+                    Either::Left(_) => continue,
+                    Either::Right(_) => break,
+                }
+            }
+        };
+
+        let pull = Box::pin(subscription.pull(max_messages));
+        let messages = match select(pull, &mut shutdown).await {
+            // Messages pulled but not delivered are redelivered once their
+            // acknowledgement deadline expires.
+            Either::Right(_) => break,
+            Either::Left((Ok(messages), _)) => messages,
+            Either::Left((Err(error), _)) => {
+                emit!(GcpPubsubPullFailed { error });
+                delay_for(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        if messages.is_empty() {
+            continue;
+        }
+
+        let count = messages.len();
+        let byte_size = messages
+            .iter()
+            .map(|received| received.message.data.len())
+            .sum();
+        limits.add(count, byte_size);
+        emit!(GcpPubsubMessagesReceived { count, byte_size });
+
+        let mut ack_ids = Vec::with_capacity(count);
+        let mut events = Vec::with_capacity(count);
+        for received in messages {
+            ack_ids.push(received.ack_id);
+            match received.message.into_event() {
+                Ok(event) => events.push(event),
+                // Acknowledged as well, as it would never decode.
+                Err(error) => emit!(GcpPubsubMessageInvalid { error }),
+            }
+        }
+
+        // The messages are only acknowledged once the pipeline accepted
+        // their events, meanwhile the next ones are pulled.
+        let (sink, _) = out
+            .send_all(iter_ok(events))
+            .compat()
+            .await
+            .map_err(|error| error!(message = "error sending events", %error))?;
+        out = sink;
+
+        let subscription = Arc::clone(&subscription);
+        let limits = limits.clone();
+        tokio::spawn(async move {
+            if let Err(error) = subscription.acknowledge(ack_ids).await {
+                emit!(GcpPubsubAckFailed { error });
+            }
+            limits.remove(count, byte_size);
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcp_pubsub_decodes_messages() {
+        let response: PullResponse = serde_json::from_str(
+            r#"{"receivedMessages":[{
+                "ackId":"ack-1",
+                "message":{
+                    "data":"aGVsbG8gd29ybGQ=",
+                    "attributes":{"origin":"test"},
+                    "messageId":"1234",
+                    "publishTime":"2020-06-01T12:00:00.123Z",
+                    "orderingKey":"key"
+                }
+            }]}"#,
+        )
+        .unwrap();
+        let received = response.received_messages.into_iter().next().unwrap();
+        assert_eq!(received.ack_id, "ack-1");
+
+        let event = received.message.into_event().unwrap();
+        let log = event.as_log();
+        assert_eq!(
+            log[&event::log_schema().message_key()],
+            "hello world".into()
+        );
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            "2020-06-01T12:00:00.123Z"
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+                .into()
+        );
+        assert_eq!(log[&"message_id".into()], "1234".into());
+        assert_eq!(log[&"ordering_key".into()], "key".into());
+        assert_eq!(log[&"attributes.origin".into()], "test".into());
+        assert_eq!(
+            log[&event::log_schema().source_type_key()],
+            "gcp_pubsub".into()
+        );
+
+        let empty: PullResponse = serde_json::from_str("{}").unwrap();
+        assert!(empty.received_messages.is_empty());
+    }
+
+    #[test]
+    fn gcp_pubsub_limits_outstanding_messages() {
+        let limits = FlowControl {
+            max_messages: 1500,
+            max_bytes: 1000,
+            messages: Arc::new(AtomicUsize::new(0)),
+            bytes: Arc::new(AtomicUsize::new(0)),
+        };
+        assert_eq!(limits.available(), Some(MAX_PULL_MESSAGES));
+
+        limits.add(1000, 10);
+        assert_eq!(limits.available(), Some(500));
+        limits.add(10, 990);
+        assert_eq!(limits.available(), None);
+        limits.remove(10, 990);
+        assert_eq!(limits.available(), Some(500));
+    }
+}
@@ -0,0 +1,237 @@
+use super::util::{SocketListenAddr, TcpSource};
+use crate::{
+    internal_events::{GraphiteEventReceived, GraphiteParseError, GraphiteUdpReadError},
+    shutdown::ShutdownSignal,
+    stream::StreamExt,
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+    Event,
+};
+use bytes::Bytes;
+use futures01::{future, stream, sync::mpsc, Future, Sink, Stream};
+use parser::{parse, Template};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, sync::Arc};
+use tokio01::{
+    codec::{BytesCodec, LinesCodec},
+    net::{UdpFramed, UdpSocket},
+};
+use tracing::field;
+
+pub mod parser;
+
+#[derive(Deserialize, Serialize, Debug)]
+// TODO: add back when serde-rs/serde#1358 is addressed
+// #[serde(deny_unknown_fields)]
+pub struct GraphiteConfig {
+    #[serde(flatten)]
+    pub mode: Mode,
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+    /// Applied in order, the first one matching a path is used.
+    #[serde(default)]
+    pub templates: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum Mode {
+    Tcp {
+        address: SocketListenAddr,
+        tls: Option<TlsConfig>,
+    },
+    Udp {
+        address: SocketAddr,
+    },
+}
+
+fn default_max_length() -> usize {
+    bytesize::kib(100u64) as usize
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<GraphiteConfig>("graphite")
+}
+
+#[typetag::serde(name = "graphite")]
+impl SourceConfig for GraphiteConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let templates = self
+            .templates
+            .iter()
+            .map(|template| Template::parse(template))
+            .collect::<Result<Vec<_>, _>>()?;
+        let templates = Arc::new(templates);
+
+        match self.mode.clone() {
+            Mode::Tcp { address, tls } => {
+                let source = GraphiteTcpSource {
+                    max_length: self.max_length,
+                    templates,
+                };
+                let shutdown_secs = 30;
+                let tls = MaybeTlsSettings::from_config(&tls, true)?;
+                source.run(
+                    address,
+                    shutdown_secs,
+                    tls,
+                    Default::default(),
+                    None,
+                    shutdown,
+                    out,
+                )
+            }
+            Mode::Udp { address } => Ok(udp(address, templates, shutdown, out)),
+        }
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn source_type(&self) -> &'static str {
+        "graphite"
+    }
+}
+
+fn parse_line(line: &str, templates: &[Template]) -> Option<Event> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    emit!(GraphiteEventReceived {
+        byte_size: line.len()
+    });
+    match parse(line, templates) {
+        Ok(metric) => Some(Event::Metric(metric)),
+        Err(error) => {
+            emit!(GraphiteParseError { error });
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GraphiteTcpSource {
+    max_length: usize,
+    templates: Arc<Vec<Template>>,
+}
+
+impl TcpSource for GraphiteTcpSource {
+    type Decoder = LinesCodec;
+
+    fn decoder(&self) -> Self::Decoder {
+        LinesCodec::new_with_max_length(self.max_length)
+    }
+
+    fn build_event(&self, frame: String, _host: Bytes) -> Option<Event> {
+        parse_line(&frame, &self.templates)
+    }
+}
+
+fn udp(
+    addr: SocketAddr,
+    templates: Arc<Vec<Template>>,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> super::Source {
+    let out = out.sink_map_err(|e| error!("error sending metric: {:?}", e));
+
+    Box::new(
+        future::lazy(move || {
+            let socket = UdpSocket::bind(&addr).expect("failed to bind to udp listener socket");
+
+            info!(
+                message = "listening.",
+                addr = &field::display(addr),
+                r#type = "udp"
+            );
+
+            future::ok(socket)
+        })
+        .and_then(move |socket| {
+            let metrics_in = UdpFramed::new(socket, BytesCodec::new())
+                .take_until(shutdown)
+                .map(move |(bytes, _addr)| {
+                    let packet = String::from_utf8_lossy(bytes.as_ref());
+                    let metrics = packet
+                        .lines()
+                        .filter_map(|line| parse_line(line, &templates))
+                        .collect::<Vec<_>>();
+                    stream::iter_ok::<_, std::io::Error>(metrics)
+                })
+                .flatten()
+                .map_err(|error| emit!(GraphiteUdpReadError { error }));
+
+            metrics_in.forward(out).map(|_| info!("finished sending"))
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        event::metric::{MetricKind, MetricValue},
+        test_util::{collect_n, next_addr, runtime, send_lines, wait_for_tcp},
+    };
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn graphite_tcp() {
+        let address = next_addr();
+        let config: GraphiteConfig = toml::from_str(&format!(
+            r#"
+            mode = "tcp"
+            address = "{}"
+            templates = ["servers.* .host.measurement*"]
+            "#,
+            address
+        ))
+        .unwrap();
+
+        let mut rt = runtime();
+        let (tx, rx) = mpsc::channel(10);
+        let source = config
+            .build("in", &GlobalOptions::default(), ShutdownSignal::noop(), tx)
+            .unwrap();
+        rt.spawn(source);
+        wait_for_tcp(address);
+
+        let lines = vec![
+            "servers.web01.cpu.load 0.5 1591005600".to_owned(),
+            "not a metric".to_owned(),
+            "disk.used;mount=/ 42 1591005600".to_owned(),
+        ];
+        rt.block_on(send_lines(address, lines.into_iter())).unwrap();
+
+        let events = rt.block_on(collect_n(rx, 2)).unwrap();
+        let metric = events[0].as_metric();
+        assert_eq!(metric.name, "cpu.load");
+        assert_eq!(metric.tags.as_ref().unwrap()["host"], "web01");
+        assert_eq!(metric.timestamp, Some(Utc.timestamp(1591005600, 0)));
+        assert_eq!(metric.kind, MetricKind::Absolute);
+        assert_eq!(metric.value, MetricValue::Gauge { value: 0.5 });
+        assert_eq!(events[1].as_metric().name, "disk.used");
+    }
+
+    #[test]
+    fn graphite_rejects_invalid_templates() {
+        let config = GraphiteConfig {
+            mode: Mode::Udp {
+                address: next_addr(),
+            },
+            max_length: default_max_length(),
+            templates: vec!["measurement*.host".into()],
+        };
+        let (tx, _rx) = mpsc::channel(10);
+        assert!(config
+            .build("in", &GlobalOptions::default(), ShutdownSignal::noop(), tx)
+            .is_err());
+    }
+}
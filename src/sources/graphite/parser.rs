@@ -0,0 +1,298 @@
+use crate::event::metric::{Metric, MetricKind, MetricValue};
+use chrono::{DateTime, TimeZone, Utc};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeMap, num::ParseFloatError};
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum TemplateError {
+    #[snafu(display("Template {:?} is empty", template))]
+    Empty { template: String },
+    #[snafu(display("Template {:?} has too many parts", template))]
+    TooManyParts { template: String },
+    #[snafu(display("Tag {:?} of template {:?} has no value", tag, template))]
+    InvalidTag { template: String, tag: String },
+    #[snafu(display("\"measurement*\" must be the last part of template {:?}", template))]
+    MisplacedWildcard { template: String },
+}
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum ParseError {
+    #[snafu(display("Line should have a path, value and optional timestamp: {:?}", line))]
+    Malformed { line: String },
+    #[snafu(display("Invalid value {:?}: {}", value, source))]
+    InvalidValue {
+        value: String,
+        source: ParseFloatError,
+    },
+    #[snafu(display("Invalid timestamp {:?}", timestamp))]
+    InvalidTimestamp { timestamp: String },
+    #[snafu(display("Invalid tag {:?}", tag))]
+    InvalidPathTag { tag: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Skip,
+    Measurement,
+    /// Takes all the remaining parts of the path.
+    MeasurementRest,
+    Tag(String),
+}
+
+/// Maps the parts of a metric path to its name and tags, like
+/// `servers.*.cpu host.measurement* region=eu`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    /// Paths starting with these parts use the template, `*` matches any part.
+    filter: Option<Vec<String>>,
+    parts: Vec<Part>,
+    tags: BTreeMap<String, String>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let fields = template.split_whitespace().collect::<Vec<_>>();
+        let (filter, parts, tags) = match fields.as_slice() {
+            [] => {
+                return Err(TemplateError::Empty {
+                    template: template.into(),
+                })
+            }
+            [parts] => (None, *parts, None),
+            // The last field is either the tags or the template.
+            [parts, tags] if tags.contains('=') => (None, *parts, Some(*tags)),
+            [filter, parts] => (Some(*filter), *parts, None),
+            [filter, parts, tags] => (Some(*filter), *parts, Some(*tags)),
+            _ => {
+                return Err(TemplateError::TooManyParts {
+                    template: template.into(),
+                })
+            }
+        };
+
+        let parts = parts
+            .split('.')
+            .map(|part| match part {
+                "" => Part::Skip,
+                "measurement" => Part::Measurement,
+                "measurement*" => Part::MeasurementRest,
+                tag => Part::Tag(tag.into()),
+            })
+            .collect::<Vec<_>>();
+        if parts[..parts.len() - 1].contains(&Part::MeasurementRest) {
+            return Err(TemplateError::MisplacedWildcard {
+                template: template.into(),
+            });
+        }
+
+        let tags = match tags {
+            Some(tags) => tags
+                .split(',')
+                .map(|tag| match split_tag(tag) {
+                    Some((key, value)) => Ok((key.to_owned(), value.to_owned())),
+                    None => Err(TemplateError::InvalidTag {
+                        template: template.into(),
+                        tag: tag.into(),
+                    }),
+                })
+                .collect::<Result<_, _>>()?,
+            None => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            filter: filter.map(|filter| filter.split('.').map(Into::into).collect()),
+            parts,
+            tags,
+        })
+    }
+
+    fn matches(&self, path: &[&str]) -> bool {
+        match &self.filter {
+            Some(filter) => {
+                filter.len() <= path.len()
+                    && filter
+                        .iter()
+                        .zip(path)
+                        .all(|(filter, part)| filter == "*" || filter == part)
+            }
+            None => true,
+        }
+    }
+
+    /// The name and tags of a path. The path is kept as the name if the
+    /// template has no measurement parts.
+    fn apply(&self, path: &[&str], tags: &mut BTreeMap<String, String>) -> String {
+        let mut measurement = Vec::new();
+        for (index, part) in self.parts.iter().enumerate() {
+            match (part, path.get(index)) {
+                (Part::MeasurementRest, Some(_)) => measurement.extend(&path[index..]),
+                (Part::Measurement, Some(value)) => measurement.push(*value),
+                (Part::Tag(tag), Some(value)) => {
+                    tags.insert(tag.clone(), (*value).to_owned());
+                }
+                _ => (),
+            }
+        }
+        for (key, value) in &self.tags {
+            tags.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+
+        if measurement.is_empty() {
+            path.join(".")
+        } else {
+            measurement.join(".")
+        }
+    }
+}
+
+/// Parses a line of the plaintext protocol, `path value timestamp`, where
+/// the path may carry tags like `path;tag=value`. The first template
+/// matching the path is applied to it.
+pub fn parse(line: &str, templates: &[Template]) -> Result<Metric, ParseError> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    let (path, value, timestamp) = match fields.as_slice() {
+        [path, value] => (*path, *value, None),
+        [path, value, timestamp] => (*path, *value, Some(*timestamp)),
+        _ => return Err(ParseError::Malformed { line: line.into() }),
+    };
+
+    let mut path_tags = path.split(';');
+    let path = path_tags.next().unwrap_or_default();
+    let mut tags = BTreeMap::new();
+    for tag in path_tags {
+        let (key, value) =
+            split_tag(tag).ok_or_else(|| ParseError::InvalidPathTag { tag: tag.into() })?;
+        tags.insert(key.to_owned(), value.to_owned());
+    }
+
+    let path = path.split('.').collect::<Vec<_>>();
+    let name = match templates.iter().find(|template| template.matches(&path)) {
+        Some(template) => template.apply(&path, &mut tags),
+        None => path.join("."),
+    };
+
+    let value = value.parse::<f64>().with_context(|| InvalidValue {
+        value: value.to_owned(),
+    })?;
+    let timestamp = match timestamp {
+        Some(timestamp) => parse_timestamp(timestamp)?,
+        None => Utc::now(),
+    };
+
+    Ok(Metric {
+        name,
+        timestamp: Some(timestamp),
+        tags: if tags.is_empty() { None } else { Some(tags) },
+        kind: MetricKind::Absolute,
+        value: MetricValue::Gauge { value },
+    })
+}
+
+fn split_tag(tag: &str) -> Option<(&str, &str)> {
+    let mut parts = tag.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) if !key.is_empty() && !value.is_empty() => Some((key, value)),
+        _ => None,
+    }
+}
+
+/// Timestamps are in seconds since the epoch, a negative one stands for
+/// the time it's received at.
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, ParseError> {
+    let seconds = timestamp
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| ParseError::InvalidTimestamp {
+            timestamp: timestamp.into(),
+        })?;
+    if seconds < 0.0 {
+        return Ok(Utc::now());
+    }
+    let nanos = (seconds.fract() * 1e9) as u32;
+    Utc.timestamp_opt(seconds.trunc() as i64, nanos)
+        .single()
+        .ok_or_else(|| ParseError::InvalidTimestamp {
+            timestamp: timestamp.into(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[(&str, &str)]) -> Option<BTreeMap<String, String>> {
+        Some(
+            tags.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parse_plain_line() {
+        let metric = parse("servers.web01.cpu.load 0.5 1591005600", &[]).unwrap();
+        assert_eq!(
+            metric,
+            Metric {
+                name: "servers.web01.cpu.load".into(),
+                timestamp: Some(Utc.timestamp(1591005600, 0)),
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: 0.5 },
+            }
+        );
+    }
+
+    #[test]
+    fn parse_path_tags() {
+        let metric = parse("disk.used;host=web01;mount=/ 42 1591005600", &[]).unwrap();
+        assert_eq!(metric.name, "disk.used");
+        assert_eq!(metric.tags, tags(&[("host", "web01"), ("mount", "/")]));
+    }
+
+    #[test]
+    fn parse_with_templates() {
+        let templates = vec![
+            Template::parse("servers.* .host.measurement* dc=eu").unwrap(),
+            Template::parse("measurement.measurement.field").unwrap(),
+        ];
+
+        let metric = parse("servers.web01.cpu.load 0.5 1591005600", &templates).unwrap();
+        assert_eq!(metric.name, "cpu.load");
+        assert_eq!(metric.tags, tags(&[("dc", "eu"), ("host", "web01")]));
+
+        let metric = parse("app.requests.total 3 1591005600", &templates).unwrap();
+        assert_eq!(metric.name, "app.requests");
+        assert_eq!(metric.tags, tags(&[("field", "total")]));
+    }
+
+    #[test]
+    fn parse_template_errors() {
+        assert!(Template::parse("").is_err());
+        assert!(Template::parse("measurement*.host").is_err());
+        assert!(Template::parse("a b c=d e").is_err());
+        assert!(Template::parse("servers.* host.measurement dc").is_err());
+    }
+
+    #[test]
+    fn parse_invalid_lines() {
+        assert!(parse("foo", &[]).is_err());
+        assert!(parse("foo bar 1591005600", &[]).is_err());
+        assert!(parse("foo 1 yesterday", &[]).is_err());
+        assert!(parse("foo;bar 1 1591005600", &[]).is_err());
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        let metric = parse("foo 1 1591005600.5", &[]).unwrap();
+        assert_eq!(
+            metric.timestamp,
+            Some(Utc.timestamp(1591005600, 500_000_000))
+        );
+
+        let before = Utc::now();
+        let metric = parse("foo 1 -1", &[]).unwrap();
+        assert!(metric.timestamp.unwrap() >= before);
+    }
+}
@@ -1,14 +1,14 @@
 use crate::{
     event::{self, Event},
     shutdown::ShutdownSignal,
-    sources::util::{ErrorMessage, HttpSource},
+    sources::util::{AccessConfig, ErrorMessage, EventSender, HttpSource, QuotaConfig, WalConfig},
     tls::TlsConfig,
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
 };
 use bytes::{Buf, Bytes, BytesMut};
 use chrono::Utc;
 use codec::{self, BytesDelimitedCodec};
-use futures01::sync::mpsc;
+use futures01::{sync::mpsc, Future};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::net::SocketAddr;
@@ -24,6 +24,11 @@ pub struct SimpleHttpConfig {
     #[serde(default)]
     headers: Vec<String>,
     tls: Option<TlsConfig>,
+    #[serde(default)]
+    access: AccessConfig,
+    quota: Option<QuotaConfig>,
+    /// Records the events of each request before answering it.
+    wal: Option<WalConfig>,
 }
 
 inventory::submit! {
@@ -69,8 +74,8 @@ impl HttpSource for SimpleHttpSource {
 impl SourceConfig for SimpleHttpConfig {
     fn build(
         &self,
-        _: &str,
-        _: &GlobalOptions,
+        name: &str,
+        globals: &GlobalOptions,
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
@@ -78,7 +83,17 @@ impl SourceConfig for SimpleHttpConfig {
             encoding: self.encoding,
             headers: self.headers.clone(),
         };
-        source.run(self.address, "", &self.tls, out, shutdown)
+        let (out, forward) = EventSender::new(&self.wal, globals, name, out, shutdown.clone())?;
+        let server = source.run(
+            self.address,
+            "",
+            &self.tls,
+            &self.access,
+            &self.quota,
+            out,
+            shutdown,
+        )?;
+        Ok(Box::new(server.join(forward).map(|_| ())))
     }
 
     fn output_type(&self) -> DataType {
@@ -201,7 +216,7 @@ fn json_value_to_type_string(value: &JsonValue) -> &'static str {
 
 #[cfg(test)]
 mod tests {
-    use super::{Encoding, SimpleHttpConfig};
+    use super::{AccessConfig, Encoding, QuotaConfig, SimpleHttpConfig, WalConfig};
     use warp::http::HeaderMap;
 
     use crate::shutdown::ShutdownSignal;
@@ -221,6 +236,17 @@ mod tests {
         rt: &mut Runtime,
         encoding: Encoding,
         headers: Vec<String>,
+    ) -> (mpsc::Receiver<Event>, SocketAddr) {
+        source_with(rt, encoding, headers, None, None, &GlobalOptions::default())
+    }
+
+    fn source_with(
+        rt: &mut Runtime,
+        encoding: Encoding,
+        headers: Vec<String>,
+        quota: Option<QuotaConfig>,
+        wal: Option<WalConfig>,
+        globals: &GlobalOptions,
     ) -> (mpsc::Receiver<Event>, SocketAddr) {
         test_util::trace_init();
         let (sender, recv) = mpsc::channel(100);
@@ -231,13 +257,11 @@ mod tests {
                 encoding,
                 headers,
                 tls: None,
+                access: AccessConfig::default(),
+                quota,
+                wal,
             }
-            .build(
-                "default",
-                &GlobalOptions::default(),
-                ShutdownSignal::noop(),
-                sender,
-            )
+            .build("default", globals, ShutdownSignal::noop(), sender)
             .unwrap(),
         );
         (recv, address)
@@ -293,6 +317,69 @@ mod tests {
         }
     }
 
+    #[test]
+    fn http_forwards_through_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let globals = GlobalOptions {
+            data_dir: Some(dir.path().into()),
+            ..Default::default()
+        };
+        let wal = toml::from_str("max_size = 1000000").unwrap();
+
+        let mut rt = runtime();
+        let (rx, addr) = source_with(
+            &mut rt,
+            Encoding::default(),
+            vec![],
+            None,
+            Some(wal),
+            &globals,
+        );
+
+        assert_eq!(200, send(addr, "test body\ntest body 2"));
+
+        let events = rt.block_on(collect_n(rx, 2)).unwrap();
+        assert_eq!(
+            events[1].as_log()[&event::log_schema().message_key()],
+            "test body 2".into()
+        );
+        assert!(dir.path().join("default_wal").exists());
+    }
+
+    #[test]
+    fn http_refuses_requests_over_quota() {
+        let quota = toml::from_str("max_events = 2\nwindow_secs = 60").unwrap();
+        let mut rt = runtime();
+        let (rx, addr) = source_with(
+            &mut rt,
+            Encoding::default(),
+            vec![],
+            Some(quota),
+            None,
+            &GlobalOptions::default(),
+        );
+
+        assert_eq!(200, send(addr, "one\ntwo"));
+        let response = reqwest::Client::new()
+            .post(&format!("http://{}/", addr))
+            .body("three")
+            .send()
+            .unwrap();
+        assert_eq!(429, response.status().as_u16());
+        let retry_after: u64 = response.headers()["Retry-After"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 60);
+
+        let events = rt.block_on(collect_n(rx, 2)).unwrap();
+        assert_eq!(
+            events[1].as_log()[&event::log_schema().message_key()],
+            "two".into()
+        );
+    }
+
     #[test]
     fn http_multiline_text2() {
         //same as above test but with a newline at the end
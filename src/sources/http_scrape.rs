@@ -0,0 +1,401 @@
+use crate::{
+    event::{self, Event},
+    internal_events::{
+        HttpScrapeDecodeError, HttpScrapeEventsReceived, HttpScrapeHttpError,
+        HttpScrapeResponseError,
+    },
+    shutdown::ShutdownSignal,
+    sources::util::{https_client, HttpsClient},
+    stream::StreamExt,
+    tls::TlsOptions,
+    topology::{
+        config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+        scheduler,
+    },
+};
+use bytes::Bytes;
+use chrono::Utc;
+use futures01::{future, stream, sync::mpsc, Future, Sink, Stream};
+use http::{header::HeaderName, HeaderValue, Request, Uri};
+use hyper::Body;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use snafu::{ResultExt, Snafu};
+use std::time::Duration;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Invalid endpoint {:?}: {}", endpoint, source))]
+    InvalidEndpoint {
+        endpoint: String,
+        source: http::uri::InvalidUri,
+    },
+    #[snafu(display("Invalid header {:?}", name))]
+    InvalidHeader { name: String },
+    #[snafu(display("`scrape_interval_secs` must be at least 1"))]
+    IntervalTooShort,
+    #[snafu(display("`scrape_timeout_secs` must be at least 1"))]
+    TimeoutTooShort,
+}
+
+#[derive(Debug, Snafu)]
+pub enum DecodeError {
+    #[snafu(display("Invalid JSON: {}", source))]
+    InvalidJson { source: serde_json::Error },
+    #[snafu(display("Expected a JSON object, got {}", kind))]
+    NotAnObject { kind: &'static str },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HttpScrapeConfig {
+    endpoints: Vec<String>,
+    #[serde(default = "default_scrape_interval_secs")]
+    scrape_interval_secs: u64,
+    /// Scrapes not done by then are skipped, defaults to the interval.
+    scrape_timeout_secs: Option<u64>,
+    #[serde(default)]
+    encoding: Encoding,
+    /// Sent with every request, e.g. to authenticate.
+    #[serde(default)]
+    headers: IndexMap<String, String>,
+    tls: Option<TlsOptions>,
+}
+
+fn default_scrape_interval_secs() -> u64 {
+    15
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// An event per line.
+    Text,
+    /// An event per line, each being a JSON object.
+    Ndjson,
+    /// An event per object of a JSON array, or a single object.
+    Json,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Text
+    }
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<HttpScrapeConfig>("http_scrape")
+}
+
+#[typetag::serde(name = "http_scrape")]
+impl SourceConfig for HttpScrapeConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.scrape_interval_secs == 0 {
+            return Err(Box::new(BuildError::IntervalTooShort));
+        }
+        if self.scrape_timeout_secs == Some(0) {
+            return Err(Box::new(BuildError::TimeoutTooShort));
+        }
+        let endpoints = self
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                endpoint
+                    .parse::<Uri>()
+                    .context(InvalidEndpoint { endpoint })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                match (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    HeaderValue::from_str(value),
+                ) {
+                    (Ok(name), Ok(value)) => Ok((name, value)),
+                    _ => Err(BuildError::InvalidHeader { name: name.clone() }),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(scrape(
+            https_client(&self.tls)?,
+            endpoints,
+            headers,
+            self.encoding,
+            self.scrape_interval_secs,
+            self.scrape_timeout_secs
+                .unwrap_or(self.scrape_interval_secs),
+            shutdown,
+            out,
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "http_scrape"
+    }
+}
+
+fn scrape(
+    client: HttpsClient,
+    endpoints: Vec<Uri>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    encoding: Encoding,
+    interval: u64,
+    timeout: u64,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> super::Source {
+    let out = out.sink_map_err(|error| error!(message = "Error sending event.", ?error));
+    let deadline = Duration::from_secs(timeout);
+
+    let task = scheduler::interval(Duration::from_secs(interval))
+        .map_err(|error| error!(message = "Timer error.", %error))
+        .take_until(shutdown)
+        .map(move |_| stream::iter_ok(endpoints.clone()))
+        .flatten()
+        .map(move |endpoint| {
+            let mut request = Request::get(endpoint.clone())
+                .body(Body::empty())
+                .expect("error creating request");
+            request.headers_mut().extend(headers.clone());
+
+            let endpoint = endpoint.to_string();
+            let scrape = client
+                .request(request)
+                .map_err({
+                    let endpoint = endpoint.clone();
+                    move |error| {
+                        emit!(HttpScrapeHttpError {
+                            error,
+                            endpoint: &endpoint,
+                        });
+                    }
+                })
+                .and_then(move |response| {
+                    let status = response.status();
+                    if !status.is_success() {
+                        emit!(HttpScrapeResponseError {
+                            status,
+                            endpoint: &endpoint,
+                        });
+                        return future::Either::A(future::ok(Vec::new()));
+                    }
+                    future::Either::B(
+                        response
+                            .into_body()
+                            .concat2()
+                            .map_err({
+                                let endpoint = endpoint.clone();
+                                move |error| {
+                                    emit!(HttpScrapeHttpError {
+                                        error,
+                                        endpoint: &endpoint,
+                                    });
+                                }
+                            })
+                            .map(move |body| {
+                                let body = body.into_bytes();
+                                match decode_body(&body, encoding) {
+                                    Ok(events) => {
+                                        emit!(HttpScrapeEventsReceived {
+                                            count: events.len(),
+                                            byte_size: body.len(),
+                                        });
+                                        add_metadata(events, &endpoint, status.as_u16())
+                                    }
+                                    Err(error) => {
+                                        emit!(HttpScrapeDecodeError {
+                                            error,
+                                            endpoint: &endpoint,
+                                        });
+                                        Vec::new()
+                                    }
+                                }
+                            }),
+                    )
+                });
+
+            scheduler::scrape("http_scrape", deadline, scrape)
+                .map(Option::unwrap_or_default)
+                // Failed scrapes are retried on the next interval.
+                .or_else(|_| Ok(Vec::new()))
+                .map(stream::iter_ok)
+                .flatten_stream()
+        })
+        .flatten()
+        .forward(out)
+        .map(|_| info!("finished sending"));
+
+    Box::new(task)
+}
+
+fn add_metadata(mut events: Vec<Event>, endpoint: &str, status_code: u16) -> Vec<Event> {
+    let now = Utc::now();
+    for event in events.iter_mut() {
+        let log = event.as_mut_log();
+        log.try_insert(&event::log_schema().timestamp_key(), now);
+        log.try_insert(&"endpoint".into(), endpoint);
+        log.try_insert(&"status_code".into(), status_code as i64);
+        log.insert(event::log_schema().source_type_key(), "http_scrape");
+    }
+    events
+}
+
+fn decode_body(body: &[u8], encoding: Encoding) -> Result<Vec<Event>, DecodeError> {
+    match encoding {
+        Encoding::Text => Ok(lines(body).map(Event::from).collect()),
+        Encoding::Ndjson => lines(body)
+            .map(|line| {
+                let value = serde_json::from_slice(&line).context(InvalidJson)?;
+                json_object_to_event(value)
+            })
+            .collect(),
+        Encoding::Json => match serde_json::from_slice(body).context(InvalidJson)? {
+            JsonValue::Array(values) => values.into_iter().map(json_object_to_event).collect(),
+            value => Ok(vec![json_object_to_event(value)?]),
+        },
+    }
+}
+
+/// The non empty lines of the body, without their line endings.
+fn lines(body: &[u8]) -> impl Iterator<Item = Bytes> + '_ {
+    body.split(|&b| b == b'\n')
+        .map(|line| match line.last() {
+            Some(b'\r') => &line[..line.len() - 1],
+            _ => line,
+        })
+        .filter(|line| !line.is_empty())
+        .map(Bytes::from)
+}
+
+fn json_object_to_event(value: JsonValue) -> Result<Event, DecodeError> {
+    match value {
+        JsonValue::Object(map) => {
+            let mut event = Event::new_empty_log();
+            let log = event.as_mut_log();
+            for (key, value) in map {
+                log.insert(key, value);
+            }
+            Ok(event)
+        }
+        value => Err(DecodeError::NotAnObject {
+            kind: json_kind(&value),
+        }),
+    }
+}
+
+fn json_kind(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Object(_) => "an object",
+        JsonValue::Array(_) => "an array",
+        JsonValue::String(_) => "a string",
+        JsonValue::Number(_) => "a number",
+        JsonValue::Bool(_) => "a boolean",
+        JsonValue::Null => "null",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{collect_n, next_addr, runtime};
+    use hyper::{
+        service::{make_service_fn, service_fn_ok},
+        Response, Server,
+    };
+
+    #[test]
+    fn http_scrape_decodes_bodies() {
+        let events = decode_body(b"one\r\n\ntwo\n", Encoding::Text).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1].as_log()[&event::log_schema().message_key()],
+            "two".into()
+        );
+
+        let events = decode_body(b"{\"a\":1}\n{\"a\":2}\n", Encoding::Ndjson).unwrap();
+        assert_eq!(events[1].as_log()[&"a".into()], 2.into());
+
+        let events = decode_body(b"[{\"a\":1},{\"b\":true}]", Encoding::Json).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].as_log()[&"b".into()], true.into());
+
+        let events = decode_body(b"{\"a\":1}", Encoding::Json).unwrap();
+        assert_eq!(events.len(), 1);
+
+        let error = decode_body(b"[1]", Encoding::Json).unwrap_err();
+        assert_eq!(error.to_string(), "Expected a JSON object, got a number");
+        assert!(decode_body(b"{", Encoding::Ndjson).is_err());
+    }
+
+    #[test]
+    fn http_scrape_polls_endpoints() {
+        let mut rt = runtime();
+        let addr = next_addr();
+
+        let make_svc = make_service_fn(|_| {
+            service_fn_ok(|request: Request<Body>| {
+                let token = request
+                    .headers()
+                    .get("Authorization")
+                    .map(|value| value.to_str().unwrap().to_owned())
+                    .unwrap_or_default();
+                Response::new(Body::from(format!(
+                    r#"[{{"id":1,"token":"{}"}},{{"id":2,"token":"{}"}}]"#,
+                    token, token
+                )))
+            })
+        });
+        rt.spawn(
+            Server::bind(&addr)
+                .serve(make_svc)
+                .map_err(|error| panic!("server error: {}", error)),
+        );
+
+        let config = toml::from_str::<HttpScrapeConfig>(&format!(
+            r#"
+            endpoints = ["http://{}/items"]
+            scrape_interval_secs = 1
+            encoding = "json"
+            headers.Authorization = "Bearer secret"
+            "#,
+            addr
+        ))
+        .unwrap();
+        let (tx, rx) = mpsc::channel(10);
+        let source = config
+            .build("in", &GlobalOptions::default(), ShutdownSignal::noop(), tx)
+            .unwrap();
+        rt.spawn(source);
+
+        let events = rt.block_on(collect_n(rx, 3)).unwrap();
+        let log = events[0].as_log();
+        assert_eq!(log[&"id".into()], 1.into());
+        assert_eq!(log[&"token".into()], "Bearer secret".into());
+        assert_eq!(
+            log[&"endpoint".into()],
+            format!("http://{}/items", addr).into()
+        );
+        assert_eq!(log[&"status_code".into()], 200.into());
+        assert_eq!(
+            log[&event::log_schema().source_type_key()],
+            "http_scrape".into()
+        );
+        assert!(log.get(&event::log_schema().timestamp_key()).is_some());
+        // The endpoint is polled again on the next interval.
+        assert_eq!(events[2].as_log()[&"id".into()], 1.into());
+    }
+}
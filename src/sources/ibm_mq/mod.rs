@@ -0,0 +1,301 @@
+//! Gets messages from an IBM MQ queue as a client of the queue manager.
+//!
+//! Messages are got under syncpoint, and each batch is committed once its
+//! events were sent downstream. Until then they stay on the queue, and are
+//! backed out if Vector stops or the connection breaks.
+//!
+//! The MQI client isn't distributed with Vector, building with the
+//! `sources-ibm_mq` feature needs an install of the IBM MQ client at
+//! `MQ_INSTALLATION_PATH`, `/opt/mqm` by default.
+
+mod mqi;
+
+use self::mqi::{ConnectOptions, Connection, Descriptor};
+use crate::{
+    event::{self, Event},
+    internal_events::{
+        IbmMqCommitFailed, IbmMqConnectFailed, IbmMqGetFailed, IbmMqMessagesReceived,
+    },
+    shutdown::ShutdownSignal,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    executor::block_on,
+    future::{select, Either, FutureExt, TryFutureExt},
+};
+use futures01::{stream::iter_ok, sync::mpsc, Future, Sink};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::time::Duration;
+use tokio::{task::spawn_blocking, time::delay_for};
+use tracing::dispatcher;
+
+pub use self::mqi::MqError;
+
+/// How long a get waits for a message, and so how long shutting down may
+/// wait for the thread getting them.
+const GET_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`batch_size` must be at least 1"))]
+    NoBatch,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IbmMqConfig {
+    pub queue_manager: String,
+    /// The server-connection channel of the queue manager.
+    pub channel: String,
+    /// The `host(port)` of the queue manager, or several separated by
+    /// commas to try in turn.
+    pub connection_name: String,
+    pub queue: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// The most messages got in a unit of work, committed once all their
+    /// events were sent downstream.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    pub tls: Option<IbmMqTlsConfig>,
+}
+
+/// TLS is configured through the key repository of the MQ client, rather
+/// than the usual `tls` options.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IbmMqTlsConfig {
+    /// The CipherSpec of the channel, as defined on the queue manager.
+    pub cipher_spec: String,
+    /// The key repository, without its `.kdb` extension. Defaults to the
+    /// one in the client configuration.
+    pub key_repository: Option<String>,
+    pub certificate_label: Option<String>,
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<IbmMqConfig>("ibm_mq")
+}
+
+#[typetag::serde(name = "ibm_mq")]
+impl SourceConfig for IbmMqConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.batch_size == 0 {
+            return Err(Box::new(BuildError::NoBatch));
+        }
+        let options = ConnectOptions::new(self)?;
+        Ok(Box::new(
+            run(options, self.queue.clone(), self.batch_size, shutdown, out)
+                .boxed()
+                .compat(),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "ibm_mq"
+    }
+}
+
+async fn run(
+    options: ConnectOptions,
+    queue: String,
+    batch_size: usize,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let span = info_span!("ibm_mq", queue = %queue);
+    let dispatcher = dispatcher::get_default(|dispatcher| dispatcher.clone());
+    spawn_blocking(move || {
+        dispatcher::with_default(&dispatcher, || {
+            span.in_scope(|| get_messages(options, &queue, batch_size, shutdown, out))
+        })
+    })
+    .await
+    .map_err(|error| error!(message = "IBM MQ source stopped unexpectedly.", %error))?
+}
+
+/// Gets messages until shutdown, reconnecting when the connection fails.
+/// MQI connections belong to the thread that opened them, so this runs on
+/// a thread of its own.
+fn get_messages(
+    options: ConnectOptions,
+    queue: &str,
+    batch_size: usize,
+    shutdown: ShutdownSignal,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut shutdown = shutdown.compat();
+    loop {
+        let mut connection = match Connection::open(&options) {
+            Ok(connection) => {
+                info!(message = "connected to queue manager.");
+                connection
+            }
+            Err(error) => {
+                emit!(IbmMqConnectFailed { error });
+                match block_on(select(&mut shutdown, delay_for(Duration::from_secs(5)))) {
+                    Either::Left(_) => return Ok(()),
+                    Either::Right(_) => continue,
+                }
+            }
+        };
+
+        loop {
+            // Dropping the connection backs out the messages got since the
+            // last commit.
+            if (&mut shutdown).now_or_never().is_some() {
+                return Ok(());
+            }
+
+            let mut events = Vec::new();
+            let mut byte_size = 0;
+            let error = loop {
+                match connection.get(GET_WAIT) {
+                    Ok(Some(message)) => {
+                        byte_size += message.data.len();
+                        events.push(message_event(message.data, &message.descriptor, queue));
+                        if events.len() >= batch_size {
+                            break None;
+                        }
+                    }
+                    Ok(None) => break None,
+                    Err(error) => break Some(error),
+                }
+            };
+            if let Some(error) = error {
+                emit!(IbmMqGetFailed { error });
+                break;
+            }
+            if events.is_empty() {
+                continue;
+            }
+
+            emit!(IbmMqMessagesReceived {
+                count: events.len(),
+                byte_size
+            });
+            out = match out.send_all(iter_ok(events)).wait() {
+                Ok((out, _)) => out,
+                Err(_) => {
+                    error!(message = "error sending event.");
+                    return Err(());
+                }
+            };
+
+            // The messages are got again if the commit fails, as a unit of
+            // work in doubt is backed out.
+            if let Err(error) = connection.commit() {
+                emit!(IbmMqCommitFailed { error });
+                break;
+            }
+        }
+
+        match block_on(select(&mut shutdown, delay_for(Duration::from_secs(1)))) {
+            Either::Left(_) => return Ok(()),
+            Either::Right(_) => (),
+        }
+    }
+}
+
+fn message_event(data: Vec<u8>, descriptor: &Descriptor, queue: &str) -> Event {
+    let mut event = Event::from(Bytes::from(data));
+    let log = event.as_mut_log();
+    log.insert(event::log_schema().source_type_key().clone(), "ibm_mq");
+    log.insert("queue", queue);
+    log.insert("message_id", hex(&descriptor.message_id));
+    if descriptor.correlation_id.iter().any(|byte| *byte != 0) {
+        log.insert("correlation_id", hex(&descriptor.correlation_id));
+    }
+    log.insert("format", text(&descriptor.format));
+    log.insert("put_application", text(&descriptor.put_application));
+    log.insert("backout_count", descriptor.backout_count as i64);
+    if let Some(timestamp) = put_timestamp(&descriptor.put_date, &descriptor.put_time) {
+        log.insert(event::log_schema().timestamp_key().clone(), timestamp);
+    }
+    event
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// MQ pads fixed length text fields with blanks or NULs.
+fn text(field: &[u8]) -> String {
+    String::from_utf8_lossy(field)
+        .trim_end_matches(|c| c == ' ' || c == '\0')
+        .into()
+}
+
+/// The time the message was put, in UTC from a `YYYYMMDD` date and a
+/// `HHMMSSTH` time, down to hundredths of a second.
+fn put_timestamp(date: &[u8], time: &[u8]) -> Option<DateTime<Utc>> {
+    let date = std::str::from_utf8(date).ok()?;
+    let time = std::str::from_utf8(time).ok()?;
+    let hundredths = time.get(6..8)?.parse::<u32>().ok()?;
+    let timestamp = Utc
+        .datetime_from_str(&format!("{}{}", date, time.get(..6)?), "%Y%m%d%H%M%S")
+        .ok()?;
+    Some(timestamp + chrono::Duration::milliseconds(i64::from(hundredths) * 10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor() -> Descriptor {
+        let mut descriptor = Descriptor::default();
+        descriptor.message_id[..4].copy_from_slice(b"AMQ ");
+        descriptor.format.copy_from_slice(b"MQSTR   ");
+        descriptor.put_application[..7].copy_from_slice(b"payroll");
+        descriptor.put_date.copy_from_slice(b"20200601");
+        descriptor.put_time.copy_from_slice(b"12300450");
+        descriptor.backout_count = 1;
+        descriptor
+    }
+
+    #[test]
+    fn ibm_mq_message_event() {
+        let event = message_event(b"hello".to_vec(), &descriptor(), "DEV.QUEUE.1");
+        let log = event.as_log();
+
+        assert_eq!(log[&event::log_schema().message_key()], "hello".into());
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            Utc.ymd(2020, 6, 1).and_hms_milli(12, 30, 4, 500).into()
+        );
+        assert_eq!(log[&event::log_schema().source_type_key()], "ibm_mq".into());
+        assert_eq!(log[&"queue".into()], "DEV.QUEUE.1".into());
+        assert_eq!(
+            log[&"message_id".into()],
+            "414d51200000000000000000000000000000000000000000".into()
+        );
+        assert_eq!(log[&"format".into()], "MQSTR".into());
+        assert_eq!(log[&"put_application".into()], "payroll".into());
+        assert_eq!(log[&"backout_count".into()], 1.into());
+        assert!(log.get(&"correlation_id".into()).is_none());
+    }
+
+    #[test]
+    fn ibm_mq_invalid_put_time() {
+        assert_eq!(put_timestamp(b"20200601", b"        "), None);
+        assert_eq!(put_timestamp(b"\0\0\0\0\0\0\0\0", b"12300450"), None);
+    }
+}
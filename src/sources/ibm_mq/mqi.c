@@ -0,0 +1,141 @@
+/*
+ * The calls of the MQI made by the `ibm_mq` source, taking plain arguments
+ * instead of the MQI structures, which are initialized from the defaults of
+ * the MQ headers here.
+ */
+
+#include <string.h>
+
+#include <cmqc.h>
+#include <cmqxc.h>
+
+/* The fields of the message descriptor the source keeps. */
+struct vector_mq_descriptor {
+    MQBYTE24 message_id;
+    MQBYTE24 correlation_id;
+    MQCHAR8 format;
+    MQCHAR28 put_application;
+    MQCHAR8 put_date;
+    MQCHAR8 put_time;
+    MQLONG backout_count;
+};
+
+static void copy_name(MQCHAR *field, const char *value, size_t length)
+{
+    if (value != NULL) {
+        strncpy(field, value, length);
+    }
+}
+
+void vector_mq_connect(
+    const char *queue_manager,
+    const char *channel,
+    const char *connection_name,
+    const char *cipher_spec,
+    const char *key_repository,
+    const char *certificate_label,
+    const char *user,
+    const char *password,
+    MQHCONN *hconn,
+    MQLONG *compcode,
+    MQLONG *reason)
+{
+    MQCNO cno = {MQCNO_DEFAULT};
+    MQCD cd = {MQCD_CLIENT_CONN_DEFAULT};
+    MQSCO sco = {MQSCO_DEFAULT};
+    MQCSP csp = {MQCSP_DEFAULT};
+    MQCHAR48 queue_manager_name = {0};
+
+    copy_name(queue_manager_name, queue_manager, MQ_Q_MGR_NAME_LENGTH);
+
+    cd.Version = MQCD_CURRENT_VERSION;
+    copy_name(cd.ChannelName, channel, MQ_CHANNEL_NAME_LENGTH);
+    copy_name(cd.ConnectionName, connection_name, MQ_CONN_NAME_LENGTH);
+
+    cno.Version = MQCNO_CURRENT_VERSION;
+    cno.Options = MQCNO_HANDLE_SHARE_NONE;
+    cno.ClientConnPtr = &cd;
+
+    if (cipher_spec != NULL) {
+        copy_name(cd.SSLCipherSpec, cipher_spec, MQ_SSL_CIPHER_SPEC_LENGTH);
+        sco.Version = MQSCO_CURRENT_VERSION;
+        copy_name(sco.KeyRepository, key_repository, MQ_SSL_KEY_REPOSITORY_LENGTH);
+        copy_name(sco.CertificateLabel, certificate_label, MQ_CERT_LABEL_LENGTH);
+        cno.SSLConfigPtr = &sco;
+    }
+
+    if (user != NULL) {
+        csp.AuthenticationType = MQCSP_AUTH_USER_ID_AND_PWD;
+        csp.CSPUserIdPtr = (MQPTR)user;
+        csp.CSPUserIdLength = (MQLONG)strlen(user);
+        if (password != NULL) {
+            csp.CSPPasswordPtr = (MQPTR)password;
+            csp.CSPPasswordLength = (MQLONG)strlen(password);
+        }
+        cno.SecurityParmsPtr = &csp;
+    }
+
+    MQCONNX(queue_manager_name, &cno, hconn, compcode, reason);
+}
+
+void vector_mq_open(
+    MQHCONN hconn,
+    const char *queue,
+    MQHOBJ *hobj,
+    MQLONG *compcode,
+    MQLONG *reason)
+{
+    MQOD od = {MQOD_DEFAULT};
+
+    copy_name(od.ObjectName, queue, MQ_Q_NAME_LENGTH);
+    MQOPEN(hconn, &od, MQOO_INPUT_AS_Q_DEF | MQOO_FAIL_IF_QUIESCING, hobj, compcode, reason);
+}
+
+/*
+ * Gets the next message under syncpoint, waiting up to `wait_interval`
+ * milliseconds for one. Text messages are converted to UTF-8.
+ */
+void vector_mq_get(
+    MQHCONN hconn,
+    MQHOBJ hobj,
+    MQLONG wait_interval,
+    MQBYTE *buffer,
+    MQLONG buffer_length,
+    MQLONG *data_length,
+    struct vector_mq_descriptor *descriptor,
+    MQLONG *compcode,
+    MQLONG *reason)
+{
+    MQMD md = {MQMD_DEFAULT};
+    MQGMO gmo = {MQGMO_DEFAULT};
+
+    md.Encoding = MQENC_NATIVE;
+    md.CodedCharSetId = 1208;
+    gmo.Options = MQGMO_WAIT | MQGMO_SYNCPOINT | MQGMO_CONVERT | MQGMO_FAIL_IF_QUIESCING;
+    gmo.WaitInterval = wait_interval;
+
+    MQGET(hconn, hobj, &md, &gmo, buffer_length, buffer, data_length, compcode, reason);
+
+    memcpy(descriptor->message_id, md.MsgId, sizeof(md.MsgId));
+    memcpy(descriptor->correlation_id, md.CorrelId, sizeof(md.CorrelId));
+    memcpy(descriptor->format, md.Format, sizeof(md.Format));
+    memcpy(descriptor->put_application, md.PutApplName, sizeof(md.PutApplName));
+    memcpy(descriptor->put_date, md.PutDate, sizeof(md.PutDate));
+    memcpy(descriptor->put_time, md.PutTime, sizeof(md.PutTime));
+    descriptor->backout_count = md.BackoutCount;
+}
+
+void vector_mq_commit(MQHCONN hconn, MQLONG *compcode, MQLONG *reason)
+{
+    MQCMIT(hconn, compcode, reason);
+}
+
+void vector_mq_backout(MQHCONN hconn, MQLONG *compcode, MQLONG *reason)
+{
+    MQBACK(hconn, compcode, reason);
+}
+
+void vector_mq_disconnect(MQHCONN *hconn, MQLONG *compcode, MQLONG *reason)
+{
+    MQDISC(hconn, compcode, reason);
+}
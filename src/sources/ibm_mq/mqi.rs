@@ -0,0 +1,296 @@
+//! The calls of the MQI the source makes, through the shim in `mqi.c`.
+//!
+//! MQI handles belong to the thread that connected, so a `Connection` is
+//! only ever used from the thread it was opened on.
+
+use snafu::{ResultExt, Snafu};
+use std::{
+    ffi::{CString, NulError},
+    os::raw::c_char,
+    ptr,
+    time::Duration,
+};
+
+type MqLong = i32;
+
+const MQCC_OK: MqLong = 0;
+const MQCC_WARNING: MqLong = 1;
+const MQRC_NO_MSG_AVAILABLE: MqLong = 2033;
+const MQRC_TRUNCATED_MSG_FAILED: MqLong = 2080;
+/// The largest message a queue manager accepts.
+const MAX_MESSAGE_LENGTH: usize = 100 * 1024 * 1024;
+
+#[derive(Debug, Snafu)]
+pub enum MqError {
+    #[snafu(display("{} failed with reason code {}{}", call, reason, reason_name(*reason)))]
+    Call { call: &'static str, reason: MqLong },
+    #[snafu(display("{} contains a NUL byte", option))]
+    InvalidOption {
+        option: &'static str,
+        source: NulError,
+    },
+}
+
+/// The names of the reason codes that usually need the attention of the
+/// operator.
+fn reason_name(reason: MqLong) -> &'static str {
+    match reason {
+        2009 => " (MQRC_CONNECTION_BROKEN)",
+        2035 => " (MQRC_NOT_AUTHORIZED)",
+        2058 => " (MQRC_Q_MGR_NAME_ERROR)",
+        2059 => " (MQRC_Q_MGR_NOT_AVAILABLE)",
+        2085 => " (MQRC_UNKNOWN_OBJECT_NAME)",
+        2161 => " (MQRC_Q_MGR_QUIESCING)",
+        2162 => " (MQRC_Q_MGR_STOPPING)",
+        2393 => " (MQRC_SSL_INITIALIZATION_ERROR)",
+        2397 => " (MQRC_JSSE_ERROR)",
+        2538 => " (MQRC_HOST_NOT_AVAILABLE)",
+        2540 => " (MQRC_UNKNOWN_CHANNEL_NAME)",
+        _ => "",
+    }
+}
+
+/// The fields of a message descriptor kept by the source, laid out as
+/// `struct vector_mq_descriptor`.
+#[repr(C)]
+pub struct Descriptor {
+    pub message_id: [u8; 24],
+    pub correlation_id: [u8; 24],
+    pub format: [u8; 8],
+    pub put_application: [u8; 28],
+    pub put_date: [u8; 8],
+    pub put_time: [u8; 8],
+    pub backout_count: MqLong,
+}
+
+impl Default for Descriptor {
+    fn default() -> Self {
+        Self {
+            message_id: [0; 24],
+            correlation_id: [0; 24],
+            format: [0; 8],
+            put_application: [0; 28],
+            put_date: [0; 8],
+            put_time: [0; 8],
+            backout_count: 0,
+        }
+    }
+}
+
+pub struct Message {
+    pub data: Vec<u8>,
+    pub descriptor: Descriptor,
+}
+
+extern "C" {
+    fn vector_mq_connect(
+        queue_manager: *const c_char,
+        channel: *const c_char,
+        connection_name: *const c_char,
+        cipher_spec: *const c_char,
+        key_repository: *const c_char,
+        certificate_label: *const c_char,
+        user: *const c_char,
+        password: *const c_char,
+        hconn: *mut MqLong,
+        compcode: *mut MqLong,
+        reason: *mut MqLong,
+    );
+    fn vector_mq_open(
+        hconn: MqLong,
+        queue: *const c_char,
+        hobj: *mut MqLong,
+        compcode: *mut MqLong,
+        reason: *mut MqLong,
+    );
+    fn vector_mq_get(
+        hconn: MqLong,
+        hobj: MqLong,
+        wait_interval: MqLong,
+        buffer: *mut u8,
+        buffer_length: MqLong,
+        data_length: *mut MqLong,
+        descriptor: *mut Descriptor,
+        compcode: *mut MqLong,
+        reason: *mut MqLong,
+    );
+    fn vector_mq_commit(hconn: MqLong, compcode: *mut MqLong, reason: *mut MqLong);
+    fn vector_mq_backout(hconn: MqLong, compcode: *mut MqLong, reason: *mut MqLong);
+    fn vector_mq_disconnect(hconn: *mut MqLong, compcode: *mut MqLong, reason: *mut MqLong);
+}
+
+/// The options of the connection, checked to be valid C strings.
+pub struct ConnectOptions {
+    queue_manager: CString,
+    channel: CString,
+    connection_name: CString,
+    queue: CString,
+    cipher_spec: Option<CString>,
+    key_repository: Option<CString>,
+    certificate_label: Option<CString>,
+    user: Option<CString>,
+    password: Option<CString>,
+}
+
+impl ConnectOptions {
+    pub fn new(config: &super::IbmMqConfig) -> Result<Self, MqError> {
+        let tls = config.tls.as_ref();
+        Ok(Self {
+            queue_manager: c_string("queue_manager", &config.queue_manager)?,
+            channel: c_string("channel", &config.channel)?,
+            connection_name: c_string("connection_name", &config.connection_name)?,
+            queue: c_string("queue", &config.queue)?,
+            cipher_spec: tls
+                .map(|tls| c_string("tls.cipher_spec", &tls.cipher_spec))
+                .transpose()?,
+            key_repository: tls
+                .and_then(|tls| tls.key_repository.as_ref())
+                .map(|path| c_string("tls.key_repository", path))
+                .transpose()?,
+            certificate_label: tls
+                .and_then(|tls| tls.certificate_label.as_ref())
+                .map(|label| c_string("tls.certificate_label", label))
+                .transpose()?,
+            user: config
+                .user
+                .as_ref()
+                .map(|user| c_string("user", user))
+                .transpose()?,
+            password: config
+                .password
+                .as_ref()
+                .map(|password| c_string("password", password))
+                .transpose()?,
+        })
+    }
+}
+
+fn c_string(option: &'static str, value: &str) -> Result<CString, MqError> {
+    CString::new(value).context(InvalidOption { option })
+}
+
+fn as_ptr(value: &Option<CString>) -> *const c_char {
+    value.as_ref().map_or(ptr::null(), |value| value.as_ptr())
+}
+
+fn check(call: &'static str, compcode: MqLong, reason: MqLong) -> Result<(), MqError> {
+    match compcode {
+        MQCC_OK | MQCC_WARNING => Ok(()),
+        _ => Err(MqError::Call { call, reason }),
+    }
+}
+
+/// A client connection to a queue manager, with the queue open for input.
+pub struct Connection {
+    hconn: MqLong,
+    hobj: MqLong,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub fn open(options: &ConnectOptions) -> Result<Self, MqError> {
+        let (mut hconn, mut compcode, mut reason) = (0, 0, 0);
+        unsafe {
+            vector_mq_connect(
+                options.queue_manager.as_ptr(),
+                options.channel.as_ptr(),
+                options.connection_name.as_ptr(),
+                as_ptr(&options.cipher_spec),
+                as_ptr(&options.key_repository),
+                as_ptr(&options.certificate_label),
+                as_ptr(&options.user),
+                as_ptr(&options.password),
+                &mut hconn,
+                &mut compcode,
+                &mut reason,
+            );
+        }
+        check("MQCONNX", compcode, reason)?;
+        // Disconnects if opening the queue fails.
+        let mut connection = Self {
+            hconn,
+            hobj: 0,
+            buffer: vec![0; 64 * 1024],
+        };
+
+        unsafe {
+            vector_mq_open(
+                hconn,
+                options.queue.as_ptr(),
+                &mut connection.hobj,
+                &mut compcode,
+                &mut reason,
+            );
+        }
+        check("MQOPEN", compcode, reason)?;
+        Ok(connection)
+    }
+
+    /// Gets the next message in the unit of work, `None` if none arrived
+    /// within `wait`.
+    pub fn get(&mut self, wait: Duration) -> Result<Option<Message>, MqError> {
+        loop {
+            let mut descriptor = Descriptor::default();
+            let (mut length, mut compcode, mut reason) = (0, 0, 0);
+            unsafe {
+                vector_mq_get(
+                    self.hconn,
+                    self.hobj,
+                    wait.as_millis() as MqLong,
+                    self.buffer.as_mut_ptr(),
+                    self.buffer.len() as MqLong,
+                    &mut length,
+                    &mut descriptor,
+                    &mut compcode,
+                    &mut reason,
+                );
+            }
+            match (compcode, reason) {
+                (_, MQRC_NO_MSG_AVAILABLE) => return Ok(None),
+                // The message stays on the queue, get it again with room
+                // for all of it.
+                (MQCC_WARNING, MQRC_TRUNCATED_MSG_FAILED) => {
+                    let length = (length.max(0) as usize).min(MAX_MESSAGE_LENGTH);
+                    if length <= self.buffer.len() {
+                        return Err(MqError::Call {
+                            call: "MQGET",
+                            reason,
+                        });
+                    }
+                    self.buffer.resize(length, 0);
+                }
+                // Warnings, like messages that couldn't be converted, still
+                // deliver the message.
+                _ => {
+                    check("MQGET", compcode, reason)?;
+                    let data = self.buffer[..length.max(0) as usize].to_vec();
+                    return Ok(Some(Message { data, descriptor }));
+                }
+            }
+        }
+    }
+
+    /// Removes the messages got in the unit of work from the queue.
+    pub fn commit(&self) -> Result<(), MqError> {
+        let (mut compcode, mut reason) = (0, 0);
+        unsafe { vector_mq_commit(self.hconn, &mut compcode, &mut reason) };
+        check("MQCMIT", compcode, reason)
+    }
+
+    /// Puts the messages got in the unit of work back on the queue.
+    pub fn backout(&self) -> Result<(), MqError> {
+        let (mut compcode, mut reason) = (0, 0);
+        unsafe { vector_mq_backout(self.hconn, &mut compcode, &mut reason) };
+        check("MQBACK", compcode, reason)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // The messages got since the last commit weren't all sent
+        // downstream, they must stay on the queue.
+        let _ = self.backout();
+        let (mut compcode, mut reason) = (0, 0);
+        unsafe { vector_mq_disconnect(&mut self.hconn, &mut compcode, &mut reason) };
+    }
+}
@@ -1,16 +1,18 @@
 use crate::{
     event,
     event::{Event, LogEvent, Value},
+    replay::TimeWindow,
     shutdown::ShutdownSignal,
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
 };
-use chrono::TimeZone;
+use chrono::{DateTime, TimeZone, Utc};
 use futures::{
     compat::Future01CompatExt,
     executor::block_on,
     future::{select, Either, FutureExt, TryFutureExt},
 };
 use futures01::{future, sync::mpsc, Future, Sink};
+use glob::{Pattern, PatternError};
 use lazy_static::lazy_static;
 use nix::{
     sys::signal::{kill, Signal},
@@ -25,6 +27,10 @@ use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::iter::FromIterator;
 use std::path::PathBuf;
 use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc,
+};
 use std::time;
 use string_cache::DefaultAtom as Atom;
 use tokio::{task::spawn_blocking, time::delay_for};
@@ -36,6 +42,7 @@ lazy_static! {
     static ref CURSOR: Atom = Atom::from("__CURSOR");
     static ref HOSTNAME: Atom = Atom::from("_HOSTNAME");
     static ref MESSAGE: Atom = Atom::from("MESSAGE");
+    static ref PRIORITY: Atom = Atom::from("PRIORITY");
     static ref SYSTEMD_UNIT: Atom = Atom::from("_SYSTEMD_UNIT");
     static ref SOURCE_TIMESTAMP: Atom = Atom::from("_SOURCE_REALTIME_TIMESTAMP");
     static ref RECEIVED_TIMESTAMP: Atom = Atom::from("__REALTIME_TIMESTAMP");
@@ -53,6 +60,11 @@ enum BuildError {
         unit
     ))]
     DuplicatedUnit { unit: String },
+    #[snafu(display("Invalid unit pattern {:?}: {}", pattern, source))]
+    InvalidUnitPattern {
+        pattern: String,
+        source: PatternError,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug, Default)]
@@ -65,6 +77,25 @@ pub struct JournaldConfig {
     pub data_dir: Option<PathBuf>,
     pub batch_size: Option<usize>,
     pub journalctl_path: Option<PathBuf>,
+    /// Records less severe than this are skipped.
+    pub priority: Option<Priority>,
+    /// Records timestamped outside of these times are skipped.
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// The syslog severities, named as `journalctl --priority` names them.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    Notice,
+    Info,
+    Debug,
 }
 
 inventory::submit! {
@@ -94,12 +125,12 @@ impl SourceConfig for JournaldConfig {
             (false, _) => &self.include_units,
         };
 
-        let include_units: HashSet<String> = include_units.iter().map(fixup_unit).collect();
-        let exclude_units: HashSet<String> = self.exclude_units.iter().map(fixup_unit).collect();
+        let include_units = Units::new(include_units.iter().map(fixup_unit))?;
+        let exclude_units = Units::new(self.exclude_units.iter().map(fixup_unit))?;
         if let Some(unit) = include_units
+            .names
             .iter()
-            .filter(|unit| exclude_units.contains(&unit[..]))
-            .next()
+            .find(|unit| exclude_units.names.contains(*unit))
         {
             let unit = unit.into();
             return Err(BuildError::DuplicatedUnit { unit }.into());
@@ -133,8 +164,8 @@ impl JournaldConfig {
         out: mpsc::Sender<Event>,
         shutdown: ShutdownSignal,
         mut checkpointer: Checkpointer,
-        include_units: HashSet<String>,
-        exclude_units: HashSet<String>,
+        include_units: Units,
+        exclude_units: Units,
         batch_size: usize,
     ) -> crate::Result<super::Source>
     where
@@ -145,8 +176,8 @@ impl JournaldConfig {
             .with(|record: Record| future::ok(create_event(record)));
 
         // Retrieve the saved checkpoint, and use it to seek forward in the journald log
-        let cursor = match checkpointer.get() {
-            Ok(cursor) => cursor,
+        let checkpoint = match checkpointer.get() {
+            Ok(checkpoint) => checkpoint,
             Err(err) => {
                 error!(
                     message = "Could not retrieve saved journald checkpoint",
@@ -156,7 +187,9 @@ impl JournaldConfig {
             }
         };
 
-        let (journal, close) = J::new(self, cursor)?;
+        let (journal, close) = J::new(self, checkpoint)?;
+        let window = TimeWindow::new(self.since, self.until);
+        let priority = self.priority;
 
         Ok(Box::new(future::lazy(move || {
             info!(message = "Starting journald server.",);
@@ -165,10 +198,12 @@ impl JournaldConfig {
                 journal,
                 include_units,
                 exclude_units,
+                priority,
                 channel: out,
                 shutdown: shutdown.clone(),
                 checkpointer,
                 batch_size,
+                window,
             };
             let span = info_span!("journald-server");
             let dispatcher = dispatcher::get_default(|d| d.clone());
@@ -186,6 +221,7 @@ impl JournaldConfig {
 }
 
 fn create_event(record: Record) -> Event {
+    let timestamp = record_time(&record);
     let mut log = LogEvent::from_iter(record);
     // Convert some journald-specific field names into Vector standard ones.
     if let Some(message) = log.remove(&MESSAGE) {
@@ -195,22 +231,11 @@ fn create_event(record: Record) -> Event {
         log.insert(event::log_schema().host_key().clone(), host);
     }
     // Translate the timestamp, and so leave both old and new names.
-    if let Some(timestamp) = log
-        .get(&SOURCE_TIMESTAMP)
-        .or_else(|| log.get(&RECEIVED_TIMESTAMP))
-    {
-        if let Value::Bytes(timestamp) = timestamp {
-            if let Ok(timestamp) = String::from_utf8_lossy(timestamp).parse::<u64>() {
-                let timestamp = chrono::Utc.timestamp(
-                    (timestamp / 1_000_000) as i64,
-                    (timestamp % 1_000_000) as u32 * 1_000,
-                );
-                log.insert(
-                    event::log_schema().timestamp_key().clone(),
-                    Value::Timestamp(timestamp),
-                );
-            }
-        }
+    if let Some(timestamp) = timestamp {
+        log.insert(
+            event::log_schema().timestamp_key().clone(),
+            Value::Timestamp(timestamp),
+        );
     }
     // Add source type
     log.try_insert(event::log_schema().source_type_key(), "journald");
@@ -218,15 +243,63 @@ fn create_event(record: Record) -> Event {
     log.into()
 }
 
+/// The time the record was logged at, or else received by journald.
+fn record_time(record: &Record) -> Option<DateTime<Utc>> {
+    let timestamp = record
+        .get(&SOURCE_TIMESTAMP)
+        .or_else(|| record.get(&RECEIVED_TIMESTAMP))?
+        .parse::<u64>()
+        .ok()?;
+    Some(Utc.timestamp(
+        (timestamp / 1_000_000) as i64,
+        (timestamp % 1_000_000) as u32 * 1_000,
+    ))
+}
+
 /// Map the given unit name into a valid systemd unit
 /// by appending ".service" if no extension is present.
+/// Patterns are left as they are.
 fn fixup_unit(unit: &String) -> String {
-    match unit.contains('.') {
+    match unit.contains('.') || is_pattern(unit) {
         true => unit.into(),
         false => format!("{}.service", unit),
     }
 }
 
+fn is_pattern(unit: &str) -> bool {
+    unit.contains(|c| c == '*' || c == '?' || c == '[')
+}
+
+/// Unit names, matched exactly, or as glob patterns such as `nginx@*.service`.
+#[derive(Debug, Default)]
+struct Units {
+    names: HashSet<String>,
+    patterns: Vec<Pattern>,
+}
+
+impl Units {
+    fn new(units: impl IntoIterator<Item = String>) -> Result<Self, BuildError> {
+        let mut names = HashSet::new();
+        let mut patterns = Vec::new();
+        for unit in units {
+            if is_pattern(&unit) {
+                patterns.push(Pattern::new(&unit).context(InvalidUnitPattern { pattern: unit })?);
+            } else {
+                names.insert(unit);
+            }
+        }
+        Ok(Units { names, patterns })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.patterns.is_empty()
+    }
+
+    fn matches(&self, unit: &str) -> bool {
+        self.names.contains(unit) || self.patterns.iter().any(|pattern| pattern.matches(unit))
+    }
+}
+
 /// A `JournalSource` is a data source that works as an `Iterator`
 /// producing lines that resemble journald JSON format records. These
 /// trait functions is an addition to the standard iteration methods for
@@ -235,78 +308,161 @@ trait JournalSource: Iterator<Item = Result<String, io::Error>> + Sized {
     /// (source, close_underlying_stream)
     fn new(
         config: &JournaldConfig,
-        cursor: Option<String>,
+        checkpoint: Option<Checkpoint>,
     ) -> crate::Result<(Self, Box<dyn FnOnce() + Send>)>;
 }
 
 struct Journalctl {
-    #[allow(dead_code)]
     child: Child,
     stdout: BufReader<ChildStdout>,
+    /// Started instead when journalctl can't seek to the cursor of the
+    /// checkpoint, as when the journal file holding it was rotated away.
+    fallback: Option<Command>,
+    /// Of the running journalctl, for closing to signal it.
+    pid: Arc<AtomicI32>,
+}
+
+/// Where journalctl starts reading from.
+enum Start<'a> {
+    AfterCursor(&'a str),
+    /// In seconds since the epoch.
+    Since(i64),
+    Beginning,
 }
 
 impl JournalSource for Journalctl {
     fn new(
         config: &JournaldConfig,
-        cursor: Option<String>,
+        checkpoint: Option<Checkpoint>,
     ) -> crate::Result<(Self, Box<dyn FnOnce() + Send>)> {
-        let journalctl = config.journalctl_path.as_ref().unwrap_or(&JOURNALCTL);
-        let mut command = Command::new(journalctl);
-        command.stdout(Stdio::piped());
-        command.arg("--follow");
-        command.arg("--all");
-        command.arg("--show-cursor");
-        command.arg("--output=json");
-
-        let current_boot = config.current_boot_only.unwrap_or(true);
-        if current_boot {
-            command.arg("--boot");
-        }
-
-        if let Some(cursor) = cursor {
-            command.arg(format!("--after-cursor={}", cursor));
-        } else {
-            // journalctl --follow only outputs a few lines without a starting point
-            command.arg("--since=2000-01-01");
-        }
-
-        let mut child = command.spawn().context(JournalctlSpawn)?;
-        let stdout = child.stdout.take().unwrap();
-        let stdout = BufReader::new(stdout);
+        let (mut command, fallback) = match &checkpoint {
+            Some(checkpoint) => (
+                journalctl_command(config, Start::AfterCursor(&checkpoint.cursor)),
+                // Records received in the same second as the checkpointed
+                // one are read again.
+                checkpoint.realtime.map(|realtime| {
+                    journalctl_command(config, Start::Since((realtime / 1_000_000) as i64))
+                }),
+            ),
+            None => {
+                let start = config
+                    .since
+                    .map_or(Start::Beginning, |since| Start::Since(since.timestamp()));
+                (journalctl_command(config, start), None)
+            }
+        };
 
-        let pid = Pid::from_raw(child.id() as i32);
+        let (child, stdout) = spawn_journalctl(&mut command).context(JournalctlSpawn)?;
+        let pid = Arc::new(AtomicI32::new(child.id() as i32));
+        let running = Arc::clone(&pid);
         Ok((
-            Journalctl { child, stdout },
+            Journalctl {
+                child,
+                stdout,
+                fallback,
+                pid,
+            },
             Box::new(move || {
                 // Signal the child process to terminate so that the
                 // blocking future can be unblocked sooner rather
                 // than later.
+                let pid = Pid::from_raw(running.load(Ordering::Relaxed));
                 let _ = kill(pid, Signal::SIGTERM);
             }),
         ))
     }
 }
 
+impl Journalctl {
+    /// Starts the fallback if journalctl failed before outputting any
+    /// record, returning whether it was.
+    fn start_fallback(&mut self) -> bool {
+        let mut command = match self.fallback.take() {
+            Some(command) => command,
+            None => return false,
+        };
+        match self.child.wait() {
+            Ok(status) if !status.success() => (),
+            _ => return false,
+        }
+
+        warn!(message = "journalctl could not seek to the checkpointed cursor, resuming from its timestamp.");
+        match spawn_journalctl(&mut command) {
+            Ok((child, stdout)) => {
+                self.pid.store(child.id() as i32, Ordering::Relaxed);
+                self.child = child;
+                self.stdout = stdout;
+                true
+            }
+            Err(error) => {
+                error!(message = "journalctl failed to execute.", %error);
+                false
+            }
+        }
+    }
+}
+
+fn journalctl_command(config: &JournaldConfig, start: Start) -> Command {
+    let journalctl = config.journalctl_path.as_ref().unwrap_or(&JOURNALCTL);
+    let mut command = Command::new(journalctl);
+    command.stdout(Stdio::piped());
+    command.arg("--follow");
+    command.arg("--all");
+    command.arg("--show-cursor");
+    command.arg("--output=json");
+
+    let current_boot = config.current_boot_only.unwrap_or(true);
+    if current_boot {
+        command.arg("--boot");
+    }
+
+    match start {
+        Start::AfterCursor(cursor) => command.arg(format!("--after-cursor={}", cursor)),
+        Start::Since(since) => command.arg(format!("--since=@{}", since)),
+        // journalctl --follow only outputs a few lines without a starting point
+        Start::Beginning => command.arg("--since=2000-01-01"),
+    };
+    command
+}
+
+fn spawn_journalctl(command: &mut Command) -> io::Result<(Child, BufReader<ChildStdout>)> {
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    Ok((child, BufReader::new(stdout)))
+}
+
 impl Iterator for Journalctl {
     type Item = Result<String, io::Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        let mut line = Vec::<u8>::new();
-        match self.stdout.read_until(b'\n', &mut line) {
-            Ok(0) => None,
-            Ok(_) => Some(Ok(String::from_utf8_lossy(&line).into())),
-            Err(err) => Some(Err(err)),
+        loop {
+            let mut line = Vec::<u8>::new();
+            match self.stdout.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    if !self.start_fallback() {
+                        return None;
+                    }
+                }
+                Ok(_) => {
+                    // journalctl found the cursor.
+                    self.fallback = None;
+                    return Some(Ok(String::from_utf8_lossy(&line).into()));
+                }
+                Err(err) => return Some(Err(err)),
+            }
         }
     }
 }
 
 struct JournaldServer<J, T> {
     journal: J,
-    include_units: HashSet<String>,
-    exclude_units: HashSet<String>,
+    include_units: Units,
+    exclude_units: Units,
+    priority: Option<Priority>,
     channel: T,
     shutdown: ShutdownSignal,
     checkpointer: Checkpointer,
     batch_size: usize,
+    window: TimeWindow,
 }
 
 impl<J, T> JournaldServer<J, T>
@@ -322,7 +478,7 @@ where
         loop {
             let mut saw_record = false;
             let mut at_end = false;
-            let mut cursor: Option<String> = None;
+            let mut checkpoint: Option<Checkpoint> = None;
 
             for _ in 0..self.batch_size {
                 let text = match self.journal.next() {
@@ -347,8 +503,11 @@ where
                         continue;
                     }
                 };
-                if let Some(tmp) = record.remove(&CURSOR) {
-                    cursor = Some(tmp);
+                if let Some(cursor) = record.remove(&CURSOR) {
+                    let realtime = record
+                        .get(&RECEIVED_TIMESTAMP)
+                        .and_then(|realtime| realtime.parse().ok());
+                    checkpoint = Some(Checkpoint { cursor, realtime });
                 }
 
                 saw_record = true;
@@ -357,6 +516,12 @@ where
                 if filter_unit(unit, &self.include_units, &self.exclude_units) {
                     continue;
                 }
+                if !has_priority(&record, self.priority) {
+                    continue;
+                }
+                if !self.window.contains(record_time(&record)) {
+                    continue;
+                }
 
                 match channel.send(record).wait() {
                     Ok(_) => {}
@@ -365,8 +530,8 @@ where
             }
 
             if saw_record {
-                if let Some(cursor) = cursor {
-                    if let Err(err) = self.checkpointer.set(&cursor) {
+                if let Some(checkpoint) = checkpoint {
+                    if let Err(err) = self.checkpointer.set(&checkpoint) {
                         error!(
                             message = "Could not set journald checkpoint.",
                             error = field::display(&err)
@@ -420,22 +585,34 @@ fn decode_array(array: &Vec<JsonValue>) -> Option<JsonValue> {
 }
 
 /// Should the given unit name be filtered (excluded)?
-fn filter_unit(
-    unit: Option<&String>,
-    includes: &HashSet<String>,
-    excludes: &HashSet<String>,
-) -> bool {
-    match (unit, includes.is_empty(), excludes.is_empty()) {
-        (None, empty, _) => !empty,
-        (Some(_), true, true) => false,
-        (Some(unit), false, true) => !includes.contains(unit),
-        (Some(unit), true, false) => excludes.contains(unit),
-        (Some(unit), false, false) => !includes.contains(unit) || excludes.contains(unit),
+fn filter_unit(unit: Option<&String>, includes: &Units, excludes: &Units) -> bool {
+    match unit {
+        None => !includes.is_empty(),
+        Some(unit) => (!includes.is_empty() && !includes.matches(unit)) || excludes.matches(unit),
+    }
+}
+
+/// Records without a priority are kept.
+fn has_priority(record: &Record, priority: Option<Priority>) -> bool {
+    match (priority, record.get(&PRIORITY)) {
+        (Some(priority), Some(value)) => value
+            .parse::<u8>()
+            .map_or(true, |value| value <= priority as u8),
+        _ => true,
     }
 }
 
 const CHECKPOINT_FILENAME: &str = "checkpoint.txt";
 
+/// The position read up to: the cursor of the last record, and the time
+/// journald received it at, in microseconds, to resume from when the cursor
+/// can't be found.
+#[derive(Debug, Clone, PartialEq)]
+struct Checkpoint {
+    cursor: String,
+    realtime: Option<u64>,
+}
+
 struct Checkpointer {
     file: File,
 }
@@ -451,25 +628,31 @@ impl Checkpointer {
         Ok(Checkpointer { file })
     }
 
-    fn set(&mut self, token: &str) -> Result<(), io::Error> {
+    fn set(&mut self, checkpoint: &Checkpoint) -> Result<(), io::Error> {
+        let contents = match checkpoint.realtime {
+            Some(realtime) => format!("{}\n{}\n", checkpoint.cursor, realtime),
+            None => format!("{}\n", checkpoint.cursor),
+        };
         self.file.seek(SeekFrom::Start(0))?;
-        self.file.write_all(format!("{}\n", token).as_bytes())?;
+        self.file.write_all(contents.as_bytes())?;
+        self.file.set_len(contents.len() as u64)?;
         Ok(())
     }
 
-    fn get(&mut self) -> Result<Option<String>, io::Error> {
+    /// Checkpoints written by earlier versions hold only the cursor.
+    fn get(&mut self) -> Result<Option<Checkpoint>, io::Error> {
         let mut buf = Vec::<u8>::new();
         self.file.seek(SeekFrom::Start(0))?;
         self.file.read_to_end(&mut buf)?;
-        match buf.len() {
-            0 => Ok(None),
-            _ => {
-                let text = String::from_utf8_lossy(&buf);
-                match text.find('\n') {
-                    Some(nl) => Ok(Some(String::from(&text[..nl]))),
-                    None => Ok(None), // Maybe return an error?
-                }
-            }
+        let text = String::from_utf8_lossy(&buf);
+        let mut lines = text.split_terminator('\n');
+        match lines.next() {
+            // Maybe return an error if it isn't terminated?
+            Some(cursor) if !cursor.is_empty() && text.contains('\n') => Ok(Some(Checkpoint {
+                cursor: cursor.into(),
+                realtime: lines.next().and_then(|realtime| realtime.parse().ok()),
+            })),
+            _ => Ok(None),
         }
     }
 }
@@ -501,19 +684,25 @@ mod checkpointer_tests {
 
         assert!(checkpointer.get().unwrap().is_none());
 
-        checkpointer
-            .set("first test")
-            .expect("Setting checkpoint failed");
-        assert_eq!(checkpointer.get().unwrap().unwrap(), "first test");
+        let first = Checkpoint {
+            cursor: "first test".into(),
+            realtime: Some(1578529839140001),
+        };
+        checkpointer.set(&first).expect("Setting checkpoint failed");
+        assert_eq!(checkpointer.get().unwrap().unwrap(), first);
         let contents = open_read_close(&filename);
         assert!(String::from_utf8_lossy(&contents).starts_with("first test\n"));
 
+        let second = Checkpoint {
+            cursor: "second".into(),
+            realtime: None,
+        };
         checkpointer
-            .set("second")
+            .set(&second)
             .expect("Setting checkpoint failed");
-        assert_eq!(checkpointer.get().unwrap().unwrap(), "second");
+        assert_eq!(checkpointer.get().unwrap().unwrap(), second);
         let contents = open_read_close(&filename);
-        assert!(String::from_utf8_lossy(&contents).starts_with("second\n"));
+        assert_eq!(String::from_utf8_lossy(&contents), "second\n");
     }
 }
 
@@ -523,13 +712,12 @@ mod tests {
     use crate::test_util::{block_on, runtime, shutdown_on_idle};
     use futures01::stream::Stream;
     use std::io::{self, BufReader, Cursor};
-    use std::iter::FromIterator;
     use std::time::Duration;
     use tempfile::tempdir;
     use tokio01::util::FutureExt;
 
-    const FAKE_JOURNAL: &str = r#"{"_SYSTEMD_UNIT":"sysinit.target","MESSAGE":"System Initialization","__CURSOR":"1","_SOURCE_REALTIME_TIMESTAMP":"1578529839140001"}
-{"_SYSTEMD_UNIT":"unit.service","MESSAGE":"unit message","__CURSOR":"2","_SOURCE_REALTIME_TIMESTAMP":"1578529839140002"}
+    const FAKE_JOURNAL: &str = r#"{"_SYSTEMD_UNIT":"sysinit.target","MESSAGE":"System Initialization","PRIORITY":"6","__CURSOR":"1","_SOURCE_REALTIME_TIMESTAMP":"1578529839140001"}
+{"_SYSTEMD_UNIT":"unit.service","MESSAGE":"unit message","PRIORITY":"3","__CURSOR":"2","_SOURCE_REALTIME_TIMESTAMP":"1578529839140002"}
 {"_SYSTEMD_UNIT":"badunit.service","MESSAGE":[194,191,72,101,108,108,111,63],"__CURSOR":"2","_SOURCE_REALTIME_TIMESTAMP":"1578529839140003"}
 {"_SYSTEMD_UNIT":"stdout","MESSAGE":"Missing timestamp","__CURSOR":"3","__REALTIME_TIMESTAMP":"1578529839140004"}
 {"_SYSTEMD_UNIT":"stdout","MESSAGE":"Different timestamps","__CURSOR":"4","_SOURCE_REALTIME_TIMESTAMP":"1578529839140005","__REALTIME_TIMESTAMP":"1578529839140004"}
@@ -557,15 +745,15 @@ mod tests {
     impl JournalSource for FakeJournal {
         fn new(
             _: &JournaldConfig,
-            checkpoint: Option<String>,
+            checkpoint: Option<Checkpoint>,
         ) -> crate::Result<(Self, Box<dyn FnOnce() + Send>)> {
             let cursor = Cursor::new(FAKE_JOURNAL);
             let reader = BufReader::new(cursor);
             let mut journal = FakeJournal { reader };
 
             // The cursors are simply line numbers
-            if let Some(cursor) = checkpoint {
-                let cursor = cursor.parse::<usize>().expect("Invalid cursor");
+            if let Some(checkpoint) = checkpoint {
+                let cursor = checkpoint.cursor.parse::<usize>().expect("Invalid cursor");
                 for _ in 0..cursor {
                     journal.next();
                 }
@@ -576,19 +764,33 @@ mod tests {
     }
 
     fn run_journal(iunits: &[&str], xunits: &[&str], cursor: Option<&str>) -> Vec<Event> {
+        run_journal_config(JournaldConfig::default(), iunits, xunits, cursor)
+    }
+
+    fn run_journal_config(
+        config: JournaldConfig,
+        iunits: &[&str],
+        xunits: &[&str],
+        cursor: Option<&str>,
+    ) -> Vec<Event> {
         let (tx, rx) = futures01::sync::mpsc::channel(10);
         let (trigger, shutdown, _) = ShutdownSignal::new_wired();
         let tempdir = tempdir().unwrap();
         let mut checkpointer =
             Checkpointer::new(tempdir.path().to_path_buf()).expect("Creating checkpointer failed!");
-        let include_units = HashSet::<String>::from_iter(iunits.into_iter().map(|&s| s.into()));
-        let exclude_units = HashSet::<String>::from_iter(xunits.into_iter().map(|&s| s.into()));
+        let include_units = units(iunits);
+        let exclude_units = units(xunits);
 
         if let Some(cursor) = cursor {
-            checkpointer.set(cursor).expect("Could not set checkpoint");
+            let checkpoint = Checkpoint {
+                cursor: cursor.into(),
+                realtime: None,
+            };
+            checkpointer
+                .set(&checkpoint)
+                .expect("Could not set checkpoint");
         }
 
-        let config = JournaldConfig::default();
         let source = config
             .source::<FakeJournal>(
                 tx,
@@ -675,11 +877,50 @@ mod tests {
         assert_eq!(timestamp(&received[1]), value_ts(1578529839, 140005000));
     }
 
+    #[test]
+    fn skips_records_outside_window() {
+        let config = JournaldConfig {
+            since: Some(Utc.timestamp(1578529839, 140002000)),
+            until: Some(Utc.timestamp(1578529839, 140004000)),
+            ..Default::default()
+        };
+        let received = run_journal_config(config, &[], &[], None);
+        assert_eq!(received.len(), 3);
+        assert_eq!(message(&received[0]), Value::Bytes("unit message".into()));
+        assert_eq!(
+            message(&received[2]),
+            Value::Bytes("Missing timestamp".into())
+        );
+    }
+
+    #[test]
+    fn includes_and_excludes_unit_patterns() {
+        let received = run_journal(&["*.service", "std?ut"], &["bad*"], None);
+        assert_eq!(received.len(), 3);
+        assert_eq!(message(&received[0]), Value::Bytes("unit message".into()));
+        assert_eq!(
+            message(&received[1]),
+            Value::Bytes("Missing timestamp".into())
+        );
+    }
+
+    #[test]
+    fn skips_records_less_severe_than_priority() {
+        let config = JournaldConfig {
+            priority: Some(Priority::Warning),
+            ..Default::default()
+        };
+        let received = run_journal_config(config, &[], &[], None);
+        // Records without a priority are kept.
+        assert_eq!(received.len(), 4);
+        assert_eq!(message(&received[0]), Value::Bytes("unit message".into()));
+    }
+
     #[test]
     fn filter_unit_works_correctly() {
-        let empty: HashSet<String> = vec![].into_iter().collect();
-        let includes: HashSet<String> = vec!["one", "two"].into_iter().map(Into::into).collect();
-        let excludes: HashSet<String> = vec!["foo", "bar"].into_iter().map(Into::into).collect();
+        let empty = units(&[]);
+        let includes = units(&["one", "two"]);
+        let excludes = units(&["foo", "bar"]);
 
         assert_eq!(filter_unit(None, &empty, &empty), false);
         assert_eq!(filter_unit(None, &includes, &empty), true);
@@ -697,6 +938,10 @@ mod tests {
         assert_eq!(filter_unit(Some(&bar), &includes, &excludes), true);
     }
 
+    fn units(units: &[&str]) -> Units {
+        Units::new(units.iter().map(|&unit| unit.into())).unwrap()
+    }
+
     fn message(event: &Event) -> Value {
         event.as_log()[&event::log_schema().message_key()].clone()
     }
@@ -1,24 +1,36 @@
 use crate::{
     event::{self, Event},
-    kafka::{KafkaCompression, KafkaTlsConfig},
+    kafka::{KafkaCompression, KafkaEventHubsConfig, KafkaSaslConfig, KafkaTlsConfig},
+    replay::{Range, TimeWindow},
     shutdown::ShutdownSignal,
     stream::StreamExt,
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
 };
 use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
 use futures::compat::Compat;
 use futures01::{future, sync::mpsc, Future, Poll, Sink, Stream};
 use owning_ref::OwningHandle;
 use rdkafka::{
     config::ClientConfig,
-    consumer::{Consumer, DefaultConsumerContext, MessageStream, StreamConsumer},
+    consumer::{BaseConsumer, Consumer, DefaultConsumerContext, MessageStream, StreamConsumer},
     error::KafkaError,
     message::{BorrowedMessage, Message},
+    Offset, TopicPartitionList,
 };
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use std::{collections::HashMap, sync::Arc};
-use tokio::task::block_in_place;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use stream_cancel::{Trigger, Tripwire};
+use tokio::task::{block_in_place, spawn_blocking};
+
+const OFFSETS_FILE_NAME: &str = "offsets.json";
 
 #[derive(Debug, Snafu)]
 enum BuildError {
@@ -26,12 +38,29 @@ enum BuildError {
     KafkaCreateError { source: rdkafka::error::KafkaError },
     #[snafu(display("Could not subscribe to Kafka topics: {}", source))]
     KafkaSubscribeError { source: rdkafka::error::KafkaError },
+    #[snafu(display("`topics` must be set, unless the Event Hubs connection string names one"))]
+    MissingTopics,
+    #[snafu(display("Could not read the partitions of topic {:?}: {}", topic, source))]
+    KafkaMetadataError {
+        topic: String,
+        source: rdkafka::error::KafkaError,
+    },
+    #[snafu(display("Could not assign Kafka partitions: {}", source))]
+    KafkaAssignError { source: rdkafka::error::KafkaError },
+    #[snafu(display("Topic patterns can't be assigned partitions, found {:?}", topic))]
+    AssignedTopicPattern { topic: String },
+    #[snafu(display("Could not read the persisted offsets: {}", source))]
+    ReadOffsets { source: io::Error },
+    #[snafu(display("Could not look up the Kafka offsets of the start time: {}", source))]
+    KafkaOffsetsForTimes { source: rdkafka::error::KafkaError },
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct KafkaSourceConfig {
+    #[serde(default)]
     bootstrap_servers: String,
+    #[serde(default)]
     topics: Vec<String>,
     group_id: String,
     compression: Option<KafkaCompression>,
@@ -49,6 +78,17 @@ pub struct KafkaSourceConfig {
     key_field: Option<String>,
     librdkafka_options: Option<HashMap<String, String>>,
     tls: Option<KafkaTlsConfig>,
+    sasl: Option<KafkaSaslConfig>,
+    azure_event_hubs: Option<KafkaEventHubsConfig>,
+    /// Statically assigns these partitions of every topic, all of them if
+    /// empty, rather than joining the consumer group. Offsets are then
+    /// persisted in the `data_dir` instead of committed.
+    partitions: Option<Vec<i32>>,
+    data_dir: Option<PathBuf>,
+    /// Messages timestamped outside of these times are skipped. Partitions
+    /// are sought to `since` and paused after `until`.
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
 }
 
 fn default_session_timeout_ms() -> u64 {
@@ -79,12 +119,20 @@ inventory::submit! {
 impl SourceConfig for KafkaSourceConfig {
     fn build(
         &self,
-        _name: &str,
-        _globals: &GlobalOptions,
+        name: &str,
+        globals: &GlobalOptions,
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
-        kafka_source(self.clone(), shutdown, out)
+        let offsets = match self.partitions {
+            Some(_) => {
+                let data_dir =
+                    globals.resolve_and_make_data_subdir(self.data_dir.as_ref(), name)?;
+                Some(data_dir.join(OFFSETS_FILE_NAME))
+            }
+            None => None,
+        };
+        kafka_source(self.clone(), offsets, shutdown, out)
     }
 
     fn output_type(&self) -> DataType {
@@ -98,12 +146,41 @@ impl SourceConfig for KafkaSourceConfig {
 
 fn kafka_source(
     config: KafkaSourceConfig,
+    offsets_path: Option<PathBuf>,
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> crate::Result<super::Source> {
-    let consumer = Arc::new(create_consumer(config.clone())?);
+    let offsets = match offsets_path {
+        Some(path) => {
+            let interval = Duration::from_millis(config.commit_interval_ms);
+            Some(Arc::new(
+                Offsets::load(path, interval).context(ReadOffsets)?,
+            ))
+        }
+        None => None,
+    };
+    let consumer = Arc::new(create_consumer(config.clone(), offsets.as_deref())?);
+    let final_offsets = offsets.clone();
+    let (trigger, finished) = Tripwire::new();
+    let mut window = WindowFilter {
+        window: TimeWindow::new(config.since, config.until),
+        consumer: Arc::clone(&consumer),
+        offsets: offsets.clone(),
+        timeout: Duration::from_millis(config.socket_timeout_ms),
+        sought: HashSet::new(),
+        paused: HashSet::new(),
+        trigger: Some(trigger),
+    };
     let source = future::lazy(move || {
         let consumer_ref = Arc::clone(&consumer);
+        let stopped_consumer = Arc::clone(&consumer);
+        let stop = shutdown
+            .map(|_| ())
+            .select(finished.map(|_| ()).map_err(|_| ()))
+            .then(move |_| {
+                block_in_place(|| stopped_consumer.stop());
+                Ok::<_, ()>(())
+            });
 
         // See https://github.com/fede1024/rust-rdkafka/issues/85#issuecomment-439141656
         let stream = OwnedConsumerStream {
@@ -114,52 +191,191 @@ fn kafka_source(
         };
 
         stream
-            .take_until(shutdown.map(move |_| block_in_place(|| consumer.stop())))
-            .then(move |message| {
-                match message {
-                    Err(e) => Err(error!(message = "Error reading message from Kafka", error = ?e)),
-                    Ok(msg) => {
-                        let payload = match msg.payload_view::<[u8]>() {
-                            None => return Err(()), // skip messages with empty payload
-                            Some(Err(e)) => {
-                                return Err(error!(message = "Cannot extract payload", error = ?e))
-                            }
-                            Some(Ok(payload)) => Bytes::from(payload),
-                        };
-                        let mut event = Event::from(payload);
-
-                        // Add source type
-                        event
-                            .as_mut_log()
-                            .insert(event::log_schema().source_type_key(), "kafka");
-
-                        if let Some(key_field) = &config.key_field {
-                            match msg.key_view::<[u8]>() {
-                                None => (),
-                                Some(Err(e)) => {
-                                    return Err(error!(message = "Cannot extract key", error = ?e))
-                                }
-                                Some(Ok(key)) => {
-                                    event.as_mut_log().insert(key_field.clone(), key);
-                                }
-                            }
-                        }
-
-                        consumer_ref.store_offset(&msg).map_err(
-                            |e| error!(message = "Cannot store offset for the message", error = ?e),
-                        )?;
-                        Ok(event)
-                    }
+            .take_until(stop)
+            .filter(move |msg| window.keep(msg))
+            .then(move |message| match message {
+                Err(e) => Err(error!(message = "Error reading message from Kafka", error = ?e)),
+                Ok(msg) => {
+                    let event = message_to_event(&msg, config.key_field.as_ref())?;
+                    store_offset(&consumer_ref, offsets.as_deref(), &msg)?;
+                    Ok(event)
                 }
             })
             .forward(out.sink_map_err(|e| error!(message = "Error sending to sink", error = ?e)))
             .map(|_| ())
+            .then(move |result| {
+                if let Some(offsets) = final_offsets {
+                    offsets.persist();
+                }
+                result
+            })
     });
 
     Ok(Box::new(source))
 }
 
-fn create_consumer(config: KafkaSourceConfig) -> crate::Result<StreamConsumer> {
+/// Keeps the messages timestamped within the window. A partition is sought
+/// to `since` the first time a message before it is read, and paused once
+/// one after `until` is. The source finishes when all the partitions
+/// assigned to it are paused.
+struct WindowFilter {
+    window: TimeWindow,
+    consumer: Arc<StreamConsumer>,
+    offsets: Option<Arc<Offsets>>,
+    timeout: Duration,
+    sought: HashSet<(String, i32)>,
+    paused: HashSet<(String, i32)>,
+    trigger: Option<Trigger>,
+}
+
+impl WindowFilter {
+    fn keep(&mut self, msg: &BorrowedMessage<'_>) -> bool {
+        let time = message_time(msg);
+        if self.window.contains(time) {
+            return true;
+        }
+
+        let partition = (msg.topic().to_owned(), msg.partition());
+        match self.window.since {
+            Some(since) if time.map_or(false, |time| time < since) => {
+                // Skipped messages still count as read.
+                let _ = store_offset(&self.consumer, self.offsets.as_deref(), msg);
+                if self.sought.insert(partition.clone()) {
+                    self.seek(&partition, since);
+                }
+            }
+            _ => {
+                if self.paused.insert(partition.clone()) {
+                    self.pause(&partition);
+                }
+            }
+        }
+        false
+    }
+
+    fn seek(&self, (topic, partition): &(String, i32), since: DateTime<Utc>) {
+        let result = block_in_place(|| {
+            let partitions = [(topic.clone(), *partition)];
+            let start = offsets_since(&*self.consumer, &partitions, since, self.timeout)
+                .map_err(|error| error.to_string())?;
+            self.consumer
+                .seek(topic, *partition, start[0], self.timeout)
+                .map_err(|error| error.to_string())
+        });
+        if let Err(error) = result {
+            error!(message = "Unable to seek to `since`.", %topic, %partition, %error);
+        }
+    }
+
+    fn pause(&mut self, (topic, partition): &(String, i32)) {
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition(topic, *partition);
+        if let Err(error) = self.consumer.pause(&partitions) {
+            error!(message = "Unable to pause partition past `until`.", %topic, %partition, %error);
+        }
+
+        let all_paused = self.consumer.assignment().ok().map_or(false, |assignment| {
+            assignment.elements().iter().all(|element| {
+                self.paused
+                    .contains(&(element.topic().to_owned(), element.partition()))
+            })
+        });
+        if all_paused {
+            info!(message = "Read all assigned partitions up to `until`.");
+            self.trigger.take();
+        }
+    }
+}
+
+fn store_offset(
+    consumer: &StreamConsumer,
+    offsets: Option<&Offsets>,
+    msg: &BorrowedMessage<'_>,
+) -> Result<(), ()> {
+    match offsets {
+        Some(offsets) => {
+            offsets.record(msg.topic(), msg.partition(), msg.offset());
+            Ok(())
+        }
+        None => consumer
+            .store_offset(msg)
+            .map_err(|e| error!(message = "Cannot store offset for the message", error = ?e)),
+    }
+}
+
+fn message_time(msg: &BorrowedMessage<'_>) -> Option<DateTime<Utc>> {
+    msg.timestamp()
+        .to_millis()
+        .map(|millis| Utc.timestamp_millis(millis))
+}
+
+fn message_to_event(msg: &BorrowedMessage<'_>, key_field: Option<&String>) -> Result<Event, ()> {
+    let payload = match msg.payload_view::<[u8]>() {
+        None => return Err(()), // skip messages with empty payload
+        Some(Err(e)) => return Err(error!(message = "Cannot extract payload", error = ?e)),
+        Some(Ok(payload)) => Bytes::from(payload),
+    };
+    let mut event = Event::from(payload);
+
+    // Add source type
+    event
+        .as_mut_log()
+        .insert(event::log_schema().source_type_key(), "kafka");
+
+    if let Some(key_field) = key_field {
+        match msg.key_view::<[u8]>() {
+            None => (),
+            Some(Err(e)) => return Err(error!(message = "Cannot extract key", error = ?e)),
+            Some(Ok(key)) => {
+                event.as_mut_log().insert(key_field.clone(), key);
+            }
+        }
+    }
+
+    Ok(event)
+}
+
+fn create_consumer(
+    mut config: KafkaSourceConfig,
+    offsets: Option<&Offsets>,
+) -> crate::Result<StreamConsumer> {
+    let client_config = client_config(&mut config, offsets.is_none())?;
+
+    let consumer: StreamConsumer = client_config.create().context(KafkaCreateError)?;
+    match (&config.partitions, offsets) {
+        (Some(partitions), Some(offsets)) => {
+            let timeout = Duration::from_millis(config.socket_timeout_ms);
+            let assignment = assignment(
+                &consumer,
+                &config.topics,
+                partitions,
+                offsets,
+                config.since,
+                timeout,
+            )?;
+            consumer.assign(&assignment).context(KafkaAssignError)?;
+        }
+        _ => {
+            let topics: Vec<&str> = config.topics.iter().map(|s| s.as_str()).collect();
+            consumer.subscribe(&topics).context(KafkaSubscribeError)?;
+        }
+    }
+
+    Ok(consumer)
+}
+
+/// Resolves the brokers and topics of Event Hubs into the config as well.
+fn client_config(config: &mut KafkaSourceConfig, auto_commit: bool) -> crate::Result<ClientConfig> {
+    let event_hub = crate::kafka::resolve_brokers(
+        &config.azure_event_hubs,
+        &mut config.bootstrap_servers,
+        &mut config.tls,
+        &mut config.sasl,
+    )?;
+    if config.topics.is_empty() {
+        config.topics = vec![event_hub.ok_or(BuildError::MissingTopics)?];
+    }
+
     let mut client_config = ClientConfig::new();
     client_config
         .set("group.id", &config.group_id)
@@ -169,7 +385,10 @@ fn create_consumer(config: KafkaSourceConfig) -> crate::Result<StreamConsumer> {
         .set("socket.timeout.ms", &config.socket_timeout_ms.to_string())
         .set("fetch.wait.max.ms", &config.fetch_wait_max_ms.to_string())
         .set("enable.partition.eof", "false")
-        .set("enable.auto.commit", "true")
+        .set(
+            "enable.auto.commit",
+            if auto_commit { "true" } else { "false" },
+        )
         .set(
             "auto.commit.interval.ms",
             &config.commit_interval_ms.to_string(),
@@ -177,21 +396,302 @@ fn create_consumer(config: KafkaSourceConfig) -> crate::Result<StreamConsumer> {
         .set("enable.auto.offset.store", "false")
         .set("client.id", "vector");
 
-    if let Some(tls) = &config.tls {
-        tls.apply(&mut client_config)?;
-    }
+    crate::kafka::apply_security(&mut client_config, &config.tls, &config.sasl)?;
 
-    if let Some(librdkafka_options) = config.librdkafka_options {
-        for (key, value) in librdkafka_options.into_iter() {
+    if let Some(librdkafka_options) = &config.librdkafka_options {
+        for (key, value) in librdkafka_options.iter() {
             client_config.set(key.as_str(), value.as_str());
         }
     }
 
-    let consumer: StreamConsumer = client_config.create().context(KafkaCreateError)?;
-    let topics: Vec<&str> = config.topics.iter().map(|s| s.as_str()).collect();
-    consumer.subscribe(&topics).context(KafkaSubscribeError)?;
+    Ok(client_config)
+}
 
-    Ok(consumer)
+/// The partitions of each topic, starting after their persisted offsets.
+/// Partitions without one start at `since`, if set, or else where
+/// `auto_offset_reset` says.
+fn assignment(
+    consumer: &StreamConsumer,
+    topics: &[String],
+    partitions: &[i32],
+    offsets: &Offsets,
+    since: Option<DateTime<Utc>>,
+    timeout: Duration,
+) -> Result<TopicPartitionList, BuildError> {
+    let mut assignment = TopicPartitionList::new();
+    let mut unread = Vec::new();
+    for topic in topics {
+        if topic.starts_with('^') {
+            return Err(BuildError::AssignedTopicPattern {
+                topic: topic.clone(),
+            });
+        }
+        let topic_partitions = if partitions.is_empty() {
+            let metadata = consumer
+                .fetch_metadata(Some(topic), timeout)
+                .context(KafkaMetadataError { topic })?;
+            metadata
+                .topics()
+                .iter()
+                .flat_map(|topic| topic.partitions())
+                .map(|partition| partition.id())
+                .collect()
+        } else {
+            partitions.to_vec()
+        };
+        for partition in topic_partitions {
+            match offsets.next(topic, partition) {
+                Some(offset) => {
+                    assignment.add_partition_offset(topic, partition, Offset::Offset(offset))
+                }
+                None => unread.push((topic.clone(), partition)),
+            }
+        }
+    }
+
+    let starts = match since {
+        Some(since) => offsets_since(consumer, &unread, since, timeout)?,
+        None => vec![Offset::Invalid; unread.len()],
+    };
+    for ((topic, partition), start) in unread.iter().zip(starts) {
+        assignment.add_partition_offset(topic, *partition, start);
+    }
+    Ok(assignment)
+}
+
+/// The offsets of the first messages of the partitions timestamped at or
+/// after `since`, `Offset::End` for partitions without any.
+fn offsets_since<C: Consumer>(
+    consumer: &C,
+    partitions: &[(String, i32)],
+    since: DateTime<Utc>,
+    timeout: Duration,
+) -> Result<Vec<Offset>, BuildError> {
+    if partitions.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut timestamps = TopicPartitionList::new();
+    for (topic, partition) in partitions {
+        timestamps.add_partition_offset(
+            topic,
+            *partition,
+            Offset::Offset(since.timestamp_millis()),
+        );
+    }
+    let found = consumer
+        .offsets_for_times(timestamps, timeout)
+        .context(KafkaOffsetsForTimes)?
+        .elements()
+        .iter()
+        .filter_map(|element| match element.offset() {
+            Offset::Offset(offset) => {
+                Some(((element.topic().to_owned(), element.partition()), offset))
+            }
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+    Ok(partitions
+        .iter()
+        .map(|key| {
+            found
+                .get(key)
+                .map_or(Offset::End, |offset| Offset::Offset(*offset))
+        })
+        .collect())
+}
+
+/// Reads the messages of the configured partitions within the range, up to
+/// the end of each partition when starting, then returns. The partitions are
+/// assigned rather than subscribed to, leaving the offsets of the group as
+/// they are. A partition ends at its first message past `until`.
+pub(crate) async fn replay(
+    config: KafkaSourceConfig,
+    range: Range,
+    out: mpsc::Sender<Event>,
+) -> crate::Result<()> {
+    spawn_blocking(move || replay_blocking(config, &range, out)).await?
+}
+
+fn replay_blocking(
+    mut config: KafkaSourceConfig,
+    range: &Range,
+    mut out: mpsc::Sender<Event>,
+) -> crate::Result<()> {
+    let mut client_config = client_config(&mut config, false)?;
+    client_config.set("enable.partition.eof", "true");
+    let consumer: BaseConsumer = client_config.create().context(KafkaCreateError)?;
+    let timeout = Duration::from_millis(config.socket_timeout_ms);
+    let wanted = config.partitions.clone().unwrap_or_default();
+
+    // The offsets each partition is read from and up to.
+    let mut bounds = BTreeMap::new();
+    for topic in &config.topics {
+        if topic.starts_with('^') {
+            return Err(BuildError::AssignedTopicPattern {
+                topic: topic.clone(),
+            }
+            .into());
+        }
+        let metadata = consumer
+            .fetch_metadata(Some(topic), timeout)
+            .context(KafkaMetadataError { topic })?;
+        let partitions = metadata
+            .topics()
+            .iter()
+            .flat_map(|topic| topic.partitions())
+            .map(|partition| partition.id())
+            .filter(|partition| wanted.is_empty() || wanted.contains(partition))
+            .collect::<Vec<_>>();
+        for partition in partitions {
+            let (low, high) = consumer
+                .fetch_watermarks(topic, partition, timeout)
+                .context(KafkaMetadataError { topic })?;
+            let start = range.start_offset.map_or(low, |start| start.max(low));
+            let end = range.end_offset.map_or(high, |end| end.min(high));
+            bounds.insert((topic.clone(), partition), (start, end));
+        }
+    }
+
+    if let Some(since) = range.window.since {
+        let partitions = bounds.keys().cloned().collect::<Vec<_>>();
+        let starts = offsets_since(&consumer, &partitions, since, timeout)?;
+        for (key, offset) in partitions.into_iter().zip(starts) {
+            if let Some((start, end)) = bounds.get_mut(&key) {
+                match offset {
+                    Offset::Offset(offset) => *start = (*start).max(offset),
+                    // No message since then.
+                    _ => *start = *end,
+                }
+            }
+        }
+    }
+
+    let mut bounds = bounds
+        .into_iter()
+        .filter(|(_, (start, end))| start < end)
+        .collect::<BTreeMap<_, _>>();
+    let mut assignment = TopicPartitionList::new();
+    for ((topic, partition), (start, _)) in &bounds {
+        assignment.add_partition_offset(topic, *partition, Offset::Offset(*start));
+    }
+    consumer.assign(&assignment).context(KafkaAssignError)?;
+
+    let until = range.window.until.map(|until| until.timestamp_millis());
+    while !bounds.is_empty() {
+        let msg = match consumer.poll(timeout) {
+            None => continue,
+            // Transaction markers can keep the last offsets from being read.
+            Some(Err(KafkaError::PartitionEOF(_))) => {
+                let position = consumer.position().context(KafkaAssignError)?;
+                for element in position.elements() {
+                    let key = (element.topic().to_owned(), element.partition());
+                    match (bounds.get(&key), element.offset()) {
+                        (Some((_, end)), Offset::Offset(offset)) if offset >= *end => {
+                            bounds.remove(&key);
+                        }
+                        _ => (),
+                    }
+                }
+                continue;
+            }
+            Some(Err(e)) => {
+                error!(message = "Error reading message from Kafka", error = ?e);
+                continue;
+            }
+            Some(Ok(msg)) => msg,
+        };
+
+        let key = (msg.topic().to_owned(), msg.partition());
+        let end = match bounds.get(&key) {
+            Some((_, end)) => *end,
+            None => continue,
+        };
+        let past_until = match (until, msg.timestamp().to_millis()) {
+            (Some(until), Some(timestamp)) => timestamp > until,
+            _ => false,
+        };
+        if msg.offset() >= end || past_until {
+            bounds.remove(&key);
+            continue;
+        }
+        if msg.offset() + 1 >= end {
+            bounds.remove(&key);
+        }
+
+        if let Ok(event) = message_to_event(&msg, config.key_field.as_ref()) {
+            out = out.send(event).wait()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The offsets to resume the assigned partitions from, next to the last
+/// message read of each.
+struct Offsets {
+    path: PathBuf,
+    interval: Duration,
+    state: Mutex<OffsetsState>,
+}
+
+struct OffsetsState {
+    next: BTreeMap<String, BTreeMap<i32, i64>>,
+    persisted_at: Instant,
+}
+
+impl Offsets {
+    fn load(path: PathBuf, interval: Duration) -> io::Result<Self> {
+        let next = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error),
+        };
+        Ok(Self {
+            path,
+            interval,
+            state: Mutex::new(OffsetsState {
+                next,
+                persisted_at: Instant::now(),
+            }),
+        })
+    }
+
+    fn next(&self, topic: &str, partition: i32) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        state.next.get(topic)?.get(&partition).copied()
+    }
+
+    /// Records a message as read, persisting the offsets every `interval`.
+    fn record(&self, topic: &str, partition: i32, offset: i64) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .next
+            .entry(topic.to_owned())
+            .or_insert_with(BTreeMap::new)
+            .insert(partition, offset + 1);
+        if state.persisted_at.elapsed() >= self.interval {
+            write_offsets(&self.path, &state.next);
+            state.persisted_at = Instant::now();
+        }
+    }
+
+    fn persist(&self) {
+        let state = self.state.lock().unwrap();
+        write_offsets(&self.path, &state.next);
+    }
+}
+
+/// Replaces the file as a whole, so a crash can't leave it half written.
+fn write_offsets(path: &Path, offsets: &BTreeMap<String, BTreeMap<i32, i64>>) {
+    let tmp_path = path.with_extension("json.tmp");
+    let result = serde_json::to_vec(offsets)
+        .map_err(io::Error::from)
+        .and_then(|bytes| fs::write(&tmp_path, bytes))
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(error) = result {
+        error!(message = "Unable to persist Kafka offsets.", %error);
+    }
 }
 
 struct OwnedConsumerStream {
@@ -212,9 +712,11 @@ impl Stream for OwnedConsumerStream {
 
 #[cfg(test)]
 mod test {
-    use super::{kafka_source, KafkaSourceConfig};
+    use super::{create_consumer, kafka_source, KafkaSourceConfig, Offsets, OFFSETS_FILE_NAME};
     use crate::shutdown::ShutdownSignal;
     use futures01::sync::mpsc;
+    use rdkafka::{consumer::Consumer, Offset};
+    use std::time::Duration;
 
     fn make_config() -> KafkaSourceConfig {
         KafkaSourceConfig {
@@ -234,7 +736,7 @@ mod test {
     #[test]
     fn kafka_source_create_ok() {
         let config = make_config();
-        assert!(kafka_source(config, ShutdownSignal::noop(), mpsc::channel(1).0).is_ok());
+        assert!(kafka_source(config, None, ShutdownSignal::noop(), mpsc::channel(1).0).is_ok());
     }
 
     #[test]
@@ -243,7 +745,40 @@ mod test {
             auto_offset_reset: "incorrect-auto-offset-reset".to_string(),
             ..make_config()
         };
-        assert!(kafka_source(config, ShutdownSignal::noop(), mpsc::channel(1).0).is_err());
+        assert!(kafka_source(config, None, ShutdownSignal::noop(), mpsc::channel(1).0).is_err());
+    }
+
+    #[test]
+    fn kafka_source_resumes_assigned_partitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(OFFSETS_FILE_NAME);
+        let offsets = Offsets::load(path.clone(), Duration::from_secs(60)).unwrap();
+        assert_eq!(offsets.next("my-topic", 0), None);
+        offsets.record("my-topic", 0, 41);
+        offsets.record("my-topic", 2, 7);
+        offsets.persist();
+
+        let resumed = Offsets::load(path.clone(), Duration::from_secs(60)).unwrap();
+        assert_eq!(resumed.next("my-topic", 0), Some(42));
+        assert_eq!(resumed.next("my-topic", 2), Some(8));
+
+        let config = KafkaSourceConfig {
+            partitions: Some(vec![0, 1]),
+            ..make_config()
+        };
+        let consumer = create_consumer(config, Some(&resumed)).unwrap();
+        let assignment = consumer.assignment().unwrap();
+        let elements = assignment.elements();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].offset(), Offset::Offset(42));
+        assert_eq!(elements[1].offset(), Offset::Invalid);
+
+        let config = KafkaSourceConfig {
+            topics: vec!["^my-.*".into()],
+            partitions: Some(vec![0]),
+            ..make_config()
+        };
+        assert!(create_consumer(config, Some(&resumed)).is_err());
     }
 }
 
@@ -307,7 +842,7 @@ mod integration_test {
             .unwrap();
         println!("Receiving event...");
         let (tx, rx) = mpsc::channel(1);
-        rt.spawn(kafka_source(config, ShutdownSignal::noop(), tx).unwrap());
+        rt.spawn(kafka_source(config, None, ShutdownSignal::noop(), tx).unwrap());
         let events = rt.block_on(collect_n(rx, 1)).ok().unwrap();
         assert_eq!(
             events[0].as_log()[&event::log_schema().message_key()],
@@ -1,13 +1,13 @@
 use crate::{
     event::{self, Event},
     shutdown::ShutdownSignal,
-    sources::util::{ErrorMessage, HttpSource},
+    sources::util::{AccessConfig, ErrorMessage, EventSender, HttpSource, QuotaConfig, WalConfig},
     tls::TlsConfig,
     topology::config::{DataType, GlobalOptions, SourceConfig},
 };
 use bytes::Buf;
 use chrono::{DateTime, Utc};
-use futures01::sync::mpsc;
+use futures01::{sync::mpsc, Future};
 use serde::{Deserialize, Serialize};
 use std::{
     io::{BufRead, BufReader},
@@ -21,6 +21,10 @@ use warp::http::{HeaderMap, StatusCode};
 pub struct LogplexConfig {
     address: SocketAddr,
     tls: Option<TlsConfig>,
+    #[serde(default)]
+    access: AccessConfig,
+    quota: Option<QuotaConfig>,
+    wal: Option<WalConfig>,
 }
 
 #[derive(Clone, Default)]
@@ -40,13 +44,23 @@ impl HttpSource for LogplexSource {
 impl SourceConfig for LogplexConfig {
     fn build(
         &self,
-        _: &str,
-        _: &GlobalOptions,
+        name: &str,
+        globals: &GlobalOptions,
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
         let source = LogplexSource::default();
-        source.run(self.address, "events", &self.tls, out, shutdown)
+        let (out, forward) = EventSender::new(&self.wal, globals, name, out, shutdown.clone())?;
+        let server = source.run(
+            self.address,
+            "events",
+            &self.tls,
+            &self.access,
+            &self.quota,
+            out,
+            shutdown,
+        )?;
+        Ok(Box::new(server.join(forward).map(|_| ())))
     }
 
     fn output_type(&self) -> DataType {
@@ -178,14 +192,20 @@ mod tests {
         let (sender, recv) = mpsc::channel(100);
         let address = test_util::next_addr();
         rt.spawn(
-            LogplexConfig { address, tls: None }
-                .build(
-                    "default",
-                    &GlobalOptions::default(),
-                    ShutdownSignal::noop(),
-                    sender,
-                )
-                .unwrap(),
+            LogplexConfig {
+                address,
+                tls: None,
+                access: Default::default(),
+                quota: None,
+                wal: None,
+            }
+            .build(
+                "default",
+                &GlobalOptions::default(),
+                ShutdownSignal::noop(),
+                sender,
+            )
+            .unwrap(),
         );
         (recv, address)
     }
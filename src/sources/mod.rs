@@ -1,14 +1,32 @@
 use futures01::Future;
 use snafu::Snafu;
 
+#[cfg(feature = "sources-amqp")]
+pub mod amqp;
+#[cfg(feature = "sources-aws_s3")]
+pub mod aws_s3;
+#[cfg(feature = "sources-azure_event_hubs")]
+pub mod azure_event_hubs;
+#[cfg(feature = "sources-chargeback")]
+pub mod chargeback;
 #[cfg(feature = "sources-docker")]
 pub mod docker;
+#[cfg(feature = "sources-exec")]
+pub mod exec;
 #[cfg(feature = "sources-file")]
 pub mod file;
+#[cfg(feature = "sources-gcp_pubsub")]
+pub mod gcp_pubsub;
 #[cfg(feature = "sources-generator")]
 pub mod generator;
+#[cfg(feature = "sources-graphite")]
+pub mod graphite;
 #[cfg(feature = "sources-http")]
 pub mod http;
+#[cfg(feature = "sources-http_scrape")]
+pub mod http_scrape;
+#[cfg(feature = "sources-ibm_mq")]
+pub mod ibm_mq;
 #[cfg(feature = "sources-internal_metrics")]
 pub mod internal_metrics;
 #[cfg(all(feature = "sources-journald", feature = "unix"))]
@@ -17,8 +35,18 @@ pub mod journald;
 pub mod kafka;
 #[cfg(feature = "sources-logplex")]
 pub mod logplex;
+#[cfg(feature = "sources-nats")]
+pub mod nats;
+#[cfg(feature = "sources-opentelemetry")]
+pub mod opentelemetry;
+#[cfg(feature = "sources-postgres_cdc")]
+pub mod postgres_cdc;
 #[cfg(feature = "sources-prometheus")]
 pub mod prometheus;
+#[cfg(feature = "sources-redis")]
+pub mod redis;
+#[cfg(feature = "sources-snmp_trap")]
+pub mod snmp_trap;
 #[cfg(feature = "sources-socket")]
 pub mod socket;
 #[cfg(feature = "sources-splunk_hec")]
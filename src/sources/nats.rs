@@ -0,0 +1,240 @@
+use crate::{
+    event::{self, Event},
+    internal_events::{NatsMessageReceived, NatsReceiveFailed},
+    nats::{self as connection, NatsAuthConfig, NatsTlsConfig},
+    shutdown::ShutdownSignal,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use futures::future::{FutureExt, TryFutureExt};
+use futures01::{sync::mpsc, Future, Sink};
+use nats::jetstream::{AckPolicy, Consumer, ConsumerConfig};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::task::spawn_blocking;
+
+/// How long receiving waits for a message before checking for shutdown.
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display(
+        "`queue` can't be set along with `jetstream`, share the durable consumer instead"
+    ))]
+    QueueWithJetStream,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NatsSourceConfig {
+    pub url: String,
+    /// May contain the `*` and `>` wildcards. With JetStream, filters the
+    /// messages of the stream.
+    pub subject: String,
+    /// Shares the messages among the subscribers of the queue group.
+    pub queue: Option<String>,
+    #[serde(default = "connection::default_connection_name")]
+    pub connection_name: String,
+    pub jetstream: Option<JetStreamConsumerConfig>,
+    pub auth: Option<NatsAuthConfig>,
+    pub tls: Option<NatsTlsConfig>,
+    #[serde(default = "default_subject_key")]
+    pub subject_key: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JetStreamConsumerConfig {
+    pub stream: String,
+    /// Created when missing. Keeps the position in the stream across
+    /// restarts, and is shared by the instances using the same name.
+    pub durable_name: String,
+}
+
+fn default_subject_key() -> String {
+    "subject".into()
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<NatsSourceConfig>("nats")
+}
+
+#[typetag::serde(name = "nats")]
+impl SourceConfig for NatsSourceConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.jetstream.is_some() && self.queue.is_some() {
+            return Err(Box::new(BuildError::QueueWithJetStream));
+        }
+
+        let config = self.clone();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&stopped);
+        Ok(Box::new(
+            async move {
+                // The client is blocking, so it runs on its own thread,
+                // which sees the shutdown through `stopped`.
+                match spawn_blocking(move || nats_source(config, stopped, out)).await {
+                    Ok(result) => result,
+                    Err(error) => {
+                        error!(message = "nats source unexpectedly stopped.", %error);
+                        Err(())
+                    }
+                }
+            }
+            .boxed()
+            .compat()
+            .select(shutdown.map(move |_| stop.store(true, Ordering::Release)))
+            .map(|_| ())
+            .map_err(|_| ()),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "nats"
+    }
+}
+
+enum Receiver {
+    Core(nats::Subscription),
+    JetStream(Consumer),
+}
+
+impl Receiver {
+    fn new(config: &NatsSourceConfig) -> crate::Result<Self> {
+        let connection = connection::connect(
+            &config.url,
+            &config.connection_name,
+            &config.auth,
+            &config.tls,
+        )?;
+        let receiver = match (&config.jetstream, &config.queue) {
+            (Some(jetstream), _) => {
+                let mut consumer = Consumer::create_or_open(
+                    connection,
+                    &jetstream.stream,
+                    ConsumerConfig {
+                        durable_name: Some(jetstream.durable_name.clone()),
+                        ack_policy: AckPolicy::Explicit,
+                        filter_subject: config.subject.clone(),
+                        ..Default::default()
+                    },
+                )?;
+                consumer.timeout = POLL_TIMEOUT;
+                Receiver::JetStream(consumer)
+            }
+            (None, Some(queue)) => {
+                Receiver::Core(connection.queue_subscribe(&config.subject, queue)?)
+            }
+            (None, None) => Receiver::Core(connection.subscribe(&config.subject)?),
+        };
+        Ok(receiver)
+    }
+
+    /// Hands the next message to `handle`, if one arrives before the poll
+    /// timeout. JetStream messages are acknowledged once handled, and
+    /// redelivered if handling fails.
+    fn receive(
+        &mut self,
+        mut handle: impl FnMut(&nats::Message) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let result = match self {
+            Receiver::Core(subscription) => subscription
+                .next_timeout(POLL_TIMEOUT)
+                .and_then(|message| handle(&message)),
+            Receiver::JetStream(consumer) => consumer.process_timeout(handle),
+        };
+        match result {
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => Ok(()),
+            result => result,
+        }
+    }
+}
+
+fn nats_source(
+    config: NatsSourceConfig,
+    stopped: Arc<AtomicBool>,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut receiver =
+        Receiver::new(&config).map_err(|error| error!(message = "unable to subscribe.", %error))?;
+    info!(message = "subscribed.", subject = %config.subject);
+
+    let mut out = out.wait();
+    let mut closed = false;
+    while !stopped.load(Ordering::Acquire) {
+        let result = receiver.receive(|message| {
+            emit!(NatsMessageReceived {
+                subject: &message.subject,
+                byte_size: message.data.len(),
+            });
+            let event = create_event(message, &config.subject_key);
+            out.send(event).and_then(|()| out.flush()).map_err(|_| {
+                closed = true;
+                io::Error::new(io::ErrorKind::Other, "the pipeline closed")
+            })
+        });
+        match result {
+            Ok(()) => (),
+            Err(error) if closed => {
+                error!(message = "error sending event.", %error);
+                return Err(());
+            }
+            // The client reconnects on its own meanwhile.
+            Err(error) => {
+                emit!(NatsReceiveFailed { error });
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn create_event(message: &nats::Message, subject_key: &str) -> Event {
+    let mut event = Event::from(Bytes::from(message.data.clone()));
+    let log = event.as_mut_log();
+    log.insert(event::log_schema().source_type_key(), "nats");
+    log.insert(subject_key, message.subject.clone());
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nats_rejects_queue_with_jetstream() {
+        let config: NatsSourceConfig = toml::from_str(
+            r#"
+            url = "nats://localhost:4222"
+            subject = "logs.>"
+            queue = "vector"
+            [jetstream]
+            stream = "LOGS"
+            durable_name = "vector"
+            "#,
+        )
+        .unwrap();
+        let (tx, _rx) = mpsc::channel(1);
+        let result = config.build("in", &GlobalOptions::default(), ShutdownSignal::noop(), tx);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,451 @@
+//! Conversion of OTLP payloads into Vector events.
+
+use crate::event::{
+    self,
+    metric::{Metric, MetricKind, MetricValue},
+    Event, LogEvent, Value,
+};
+use crate::opentelemetry::proto::{
+    collector::{logs::v1::ExportLogsServiceRequest, metrics::v1::ExportMetricsServiceRequest},
+    common::v1::{any_value, AnyValue, InstrumentationLibrary, KeyValue},
+    metrics::v1::{metric, number_data_point, AggregationTemporality},
+    resource::v1::Resource,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+
+pub fn logs_to_events(request: ExportLogsServiceRequest) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    for resource_logs in request.resource_logs {
+        let resource = resource_attributes(resource_logs.resource.as_ref());
+
+        for library_logs in resource_logs.instrumentation_library_logs {
+            for record in library_logs.log_records {
+                let mut event = Event::new_empty_log();
+                let log = event.as_mut_log();
+
+                log.insert(
+                    event::log_schema().message_key().clone(),
+                    record.body.map(any_value_to_value).unwrap_or(Value::Null),
+                );
+
+                let timestamp = [record.time_unix_nano, record.observed_time_unix_nano]
+                    .iter()
+                    .find(|nanos| **nanos != 0)
+                    .map(|nanos| timestamp(*nanos))
+                    .unwrap_or_else(Utc::now);
+                log.insert(event::log_schema().timestamp_key().clone(), timestamp);
+
+                if !record.severity_text.is_empty() {
+                    log.insert("severity_text", record.severity_text);
+                }
+                if record.severity_number != 0 {
+                    log.insert("severity_number", record.severity_number);
+                }
+                if !record.trace_id.is_empty() {
+                    log.insert("trace_id", to_hex(&record.trace_id));
+                }
+                if !record.span_id.is_empty() {
+                    log.insert("span_id", to_hex(&record.span_id));
+                }
+                if !record.attributes.is_empty() {
+                    log.insert("attributes", key_values_to_value(record.attributes));
+                }
+                if let Some(resource) = &resource {
+                    log.insert("resources", resource.clone());
+                }
+                insert_library(log, library_logs.instrumentation_library.as_ref());
+
+                log.insert(
+                    event::log_schema().source_type_key().clone(),
+                    "opentelemetry",
+                );
+
+                events.push(event);
+            }
+        }
+    }
+
+    events
+}
+
+pub fn metrics_to_events(request: ExportMetricsServiceRequest) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    for resource_metrics in request.resource_metrics {
+        let resource_tags = resource_metrics
+            .resource
+            .map(|resource| key_values_to_tags(&resource.attributes))
+            .unwrap_or_default();
+
+        for library_metrics in resource_metrics.instrumentation_library_metrics {
+            for otlp_metric in library_metrics.metrics {
+                let name = otlp_metric.name;
+                let tags = |attributes: &[KeyValue]| {
+                    let mut tags = resource_tags.clone();
+                    tags.extend(key_values_to_tags(attributes));
+                    if tags.is_empty() {
+                        None
+                    } else {
+                        Some(tags)
+                    }
+                };
+                let mut push = |tags, time_unix_nano, kind, value| {
+                    events.push(Event::Metric(Metric {
+                        name: name.clone(),
+                        timestamp: Some(timestamp(time_unix_nano)),
+                        tags,
+                        kind,
+                        value,
+                    }))
+                };
+
+                match otlp_metric.data {
+                    Some(metric::Data::Gauge(gauge)) => {
+                        for point in gauge.data_points {
+                            let value = MetricValue::Gauge {
+                                value: number_value(&point.value),
+                            };
+                            push(
+                                tags(&point.attributes),
+                                point.time_unix_nano,
+                                MetricKind::Absolute,
+                                value,
+                            );
+                        }
+                    }
+                    Some(metric::Data::Sum(sum)) => {
+                        let kind = kind(sum.aggregation_temporality);
+                        for point in sum.data_points {
+                            let value = number_value(&point.value);
+                            // Non monotonic sums can go down, which counters can't.
+                            let value = if sum.is_monotonic {
+                                MetricValue::Counter { value }
+                            } else {
+                                MetricValue::Gauge { value }
+                            };
+                            push(
+                                tags(&point.attributes),
+                                point.time_unix_nano,
+                                kind.clone(),
+                                value,
+                            );
+                        }
+                    }
+                    Some(metric::Data::Histogram(histogram)) => {
+                        let kind = kind(histogram.aggregation_temporality);
+                        for point in histogram.data_points {
+                            // OTLP counts are per bucket and end with the
+                            // overflow bucket, Vector's are cumulative like
+                            // Prometheus' `le` buckets.
+                            let counts = point
+                                .bucket_counts
+                                .iter()
+                                .take(point.explicit_bounds.len())
+                                .scan(0u64, |total, count| {
+                                    *total += count;
+                                    Some(*total as u32)
+                                })
+                                .collect();
+                            let value = MetricValue::AggregatedHistogram {
+                                buckets: point.explicit_bounds,
+                                counts,
+                                count: point.count as u32,
+                                sum: point.sum,
+                            };
+                            push(
+                                tags(&point.attributes),
+                                point.time_unix_nano,
+                                kind.clone(),
+                                value,
+                            );
+                        }
+                    }
+                    Some(metric::Data::Summary(summary)) => {
+                        for point in summary.data_points {
+                            let (quantiles, values) = point
+                                .quantile_values
+                                .iter()
+                                .map(|quantile| (quantile.quantile, quantile.value))
+                                .unzip();
+                            let value = MetricValue::AggregatedSummary {
+                                quantiles,
+                                values,
+                                count: point.count as u32,
+                                sum: point.sum,
+                            };
+                            push(
+                                tags(&point.attributes),
+                                point.time_unix_nano,
+                                MetricKind::Absolute,
+                                value,
+                            );
+                        }
+                    }
+                    None => {
+                        debug!(
+                            message = "Skipping metric with unsupported data type.",
+                            %name,
+                            rate_limit_secs = 30,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    events
+}
+
+fn timestamp(nanos: u64) -> DateTime<Utc> {
+    Utc.timestamp(
+        (nanos / 1_000_000_000) as i64,
+        (nanos % 1_000_000_000) as u32,
+    )
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn kind(temporality: i32) -> MetricKind {
+    if temporality == AggregationTemporality::Delta as i32 {
+        MetricKind::Incremental
+    } else {
+        MetricKind::Absolute
+    }
+}
+
+fn number_value(value: &Option<number_data_point::Value>) -> f64 {
+    match value {
+        Some(number_data_point::Value::AsDouble(value)) => *value,
+        Some(number_data_point::Value::AsInt(value)) => *value as f64,
+        None => 0.0,
+    }
+}
+
+fn resource_attributes(resource: Option<&Resource>) -> Option<Value> {
+    resource
+        .filter(|resource| !resource.attributes.is_empty())
+        .map(|resource| key_values_to_value(resource.attributes.clone()))
+}
+
+fn insert_library(log: &mut LogEvent, library: Option<&InstrumentationLibrary>) {
+    if let Some(library) = library {
+        if !library.name.is_empty() {
+            log.insert("scope.name", library.name.clone());
+        }
+        if !library.version.is_empty() {
+            log.insert("scope.version", library.version.clone());
+        }
+    }
+}
+
+fn key_values_to_value(key_values: Vec<KeyValue>) -> Value {
+    Value::Map(
+        key_values
+            .into_iter()
+            .map(|kv| {
+                let value = kv.value.map(any_value_to_value).unwrap_or(Value::Null);
+                (kv.key, value)
+            })
+            .collect(),
+    )
+}
+
+fn key_values_to_tags(key_values: &[KeyValue]) -> BTreeMap<String, String> {
+    key_values
+        .iter()
+        .map(|kv| {
+            let value = kv
+                .value
+                .clone()
+                .map(any_value_to_value)
+                .map(|value| value.to_string_lossy())
+                .unwrap_or_default();
+            (kv.key.clone(), value)
+        })
+        .collect()
+}
+
+fn any_value_to_value(value: AnyValue) -> Value {
+    match value.value {
+        Some(any_value::Value::StringValue(s)) => Value::from(s),
+        Some(any_value::Value::BoolValue(b)) => Value::from(b),
+        Some(any_value::Value::IntValue(i)) => Value::from(i),
+        Some(any_value::Value::DoubleValue(d)) => Value::from(d),
+        Some(any_value::Value::BytesValue(b)) => Value::from(b),
+        Some(any_value::Value::ArrayValue(array)) => {
+            Value::Array(array.values.into_iter().map(any_value_to_value).collect())
+        }
+        Some(any_value::Value::KvlistValue(list)) => key_values_to_value(list.values),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opentelemetry::proto::{
+        common::v1::KeyValueList,
+        logs::v1::{InstrumentationLibraryLogs, LogRecord, ResourceLogs},
+        metrics::v1::{
+            self as otlp, HistogramDataPoint, InstrumentationLibraryMetrics, NumberDataPoint,
+            ResourceMetrics,
+        },
+    };
+
+    fn string_value(s: &str) -> Option<AnyValue> {
+        Some(AnyValue {
+            value: Some(any_value::Value::StringValue(s.into())),
+        })
+    }
+
+    fn key_value(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.into(),
+            value: string_value(value),
+        }
+    }
+
+    fn resource() -> Option<Resource> {
+        Some(Resource {
+            attributes: vec![key_value("service.name", "checkout")],
+            dropped_attributes_count: 0,
+        })
+    }
+
+    #[test]
+    fn opentelemetry_converts_logs() {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: resource(),
+                instrumentation_library_logs: vec![InstrumentationLibraryLogs {
+                    instrumentation_library: Some(InstrumentationLibrary {
+                        name: "checkout-lib".into(),
+                        version: "1.2.0".into(),
+                    }),
+                    log_records: vec![LogRecord {
+                        time_unix_nano: 1_500_000_000_000_000_001,
+                        severity_number: 17,
+                        severity_text: "ERROR".into(),
+                        body: string_value("payment failed"),
+                        attributes: vec![KeyValue {
+                            key: "order".into(),
+                            value: Some(AnyValue {
+                                value: Some(any_value::Value::KvlistValue(KeyValueList {
+                                    values: vec![key_value("id", "42")],
+                                })),
+                            }),
+                        }],
+                        trace_id: vec![0xab; 16],
+                        ..Default::default()
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+
+        let events = logs_to_events(request);
+        assert_eq!(events.len(), 1);
+        let log = events[0].as_log();
+
+        assert_eq!(
+            log[&event::log_schema().message_key()],
+            "payment failed".into()
+        );
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            Utc.timestamp(1_500_000_000, 1).into()
+        );
+        assert_eq!(log[&"severity_text".into()], "ERROR".into());
+        assert_eq!(log[&"severity_number".into()], 17.into());
+        assert_eq!(log[&"trace_id".into()], "ab".repeat(16).into());
+        assert_eq!(log[&"attributes.order.id".into()], "42".into());
+        assert_eq!(log[&"resources.service.name".into()], "checkout".into());
+        assert_eq!(log[&"scope.name".into()], "checkout-lib".into());
+        assert_eq!(log[&"scope.version".into()], "1.2.0".into());
+    }
+
+    fn metrics_request(data: metric::Data) -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: resource(),
+                instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                    instrumentation_library: None,
+                    metrics: vec![otlp::Metric {
+                        name: "requests".into(),
+                        description: String::new(),
+                        unit: String::new(),
+                        data: Some(data),
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn opentelemetry_converts_sums() {
+        let request = metrics_request(metric::Data::Sum(otlp::Sum {
+            data_points: vec![NumberDataPoint {
+                attributes: vec![key_value("code", "200")],
+                time_unix_nano: 1_000_000_000,
+                value: Some(number_data_point::Value::AsInt(7)),
+                ..Default::default()
+            }],
+            aggregation_temporality: AggregationTemporality::Delta as i32,
+            is_monotonic: true,
+        }));
+
+        let events = metrics_to_events(request);
+        assert_eq!(
+            events,
+            vec![Event::Metric(Metric {
+                name: "requests".into(),
+                timestamp: Some(Utc.timestamp(1, 0)),
+                tags: Some(
+                    vec![
+                        ("code".to_owned(), "200".to_owned()),
+                        ("service.name".to_owned(), "checkout".to_owned()),
+                    ]
+                    .into_iter()
+                    .collect()
+                ),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 7.0 },
+            })]
+        );
+    }
+
+    #[test]
+    fn opentelemetry_converts_histograms() {
+        let request = metrics_request(metric::Data::Histogram(otlp::Histogram {
+            data_points: vec![HistogramDataPoint {
+                time_unix_nano: 1_000_000_000,
+                count: 10,
+                sum: 12.5,
+                bucket_counts: vec![1, 2, 3, 4],
+                explicit_bounds: vec![0.5, 1.0, 5.0],
+                ..Default::default()
+            }],
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+        }));
+
+        let events = metrics_to_events(request);
+        let metric = events[0].as_metric();
+        assert_eq!(metric.kind, MetricKind::Absolute);
+        assert_eq!(
+            metric.value,
+            MetricValue::AggregatedHistogram {
+                buckets: vec![0.5, 1.0, 5.0],
+                counts: vec![1, 3, 6],
+                count: 10,
+                sum: 12.5,
+            }
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    event::Event,
+    internal_events::{OpentelemetryEventsReceived, OpentelemetryRequestError},
+    opentelemetry::proto::collector::{
+        logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse},
+        metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse},
+    },
+    shutdown::ShutdownSignal,
+    sinks::util::grpc,
+    tls::{MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use flate2::read::GzDecoder;
+use futures01::{future, stream, sync::mpsc, Async, Future, Poll, Sink, Stream};
+use hyper::{
+    body::Payload,
+    header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE},
+    service::service_fn,
+    Body, Chunk, Method, Request, Response, Server, StatusCode,
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    io::{self, Read},
+    net::{Ipv4Addr, SocketAddr},
+};
+
+mod convert;
+
+const LOGS_HTTP_PATH: &str = "/v1/logs";
+const METRICS_HTTP_PATH: &str = "/v1/metrics";
+const LOGS_GRPC_PATH: &str = "/opentelemetry.proto.collector.logs.v1.LogsService/Export";
+const METRICS_GRPC_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OpentelemetryConfig {
+    #[serde(default = "default_address")]
+    pub address: SocketAddr,
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for OpentelemetryConfig {
+    fn default() -> Self {
+        Self {
+            address: default_address(),
+            tls: None,
+        }
+    }
+}
+
+fn default_address() -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::new(0, 0, 0, 0).into(), 4317)
+}
+
+inventory::submit! {
+    SourceDescription::new::<OpentelemetryConfig>("opentelemetry")
+}
+
+#[typetag::serde(name = "opentelemetry")]
+impl SourceConfig for OpentelemetryConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let tls = MaybeTlsSettings::from_config(&self.tls, true)?;
+        let incoming = tls.bind(&self.address)?.incoming();
+
+        // gRPC needs HTTP/2, which hyper picks up from the connection
+        // preface, so both protocols are served from the same port.
+        let new_service = move || {
+            let out = out.clone();
+            service_fn(move |request| handle(request, out.clone()))
+        };
+
+        info!(message = "building OTLP server", addr = %self.address);
+
+        let server = Server::builder(incoming)
+            .serve(new_service)
+            .with_graceful_shutdown(shutdown.clone().map(|_| ()))
+            .map_err(|error| error!(message = "OTLP server error", %error));
+
+        // We need to drop the last copy of ShutdownSignalToken only after server has shut down.
+        Ok(Box::new(server.map(|_| drop(shutdown))))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn source_type(&self) -> &'static str {
+        "opentelemetry"
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Signal {
+    Logs,
+    Metrics,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Protocol {
+    Http,
+    Grpc,
+}
+
+#[derive(Debug, Snafu)]
+enum RequestError {
+    #[snafu(display("Unsupported content type {:?}", content_type))]
+    UnsupportedContentType { content_type: String },
+    #[snafu(display("Unsupported encoding {:?}", encoding))]
+    UnsupportedEncoding { encoding: String },
+    #[snafu(display("Malformed gRPC message frame"))]
+    MalformedFrame,
+    #[snafu(display("Failed to decompress request: {}", source))]
+    Decompress { source: io::Error },
+    #[snafu(display("Failed to decode request: {}", source))]
+    Decode { source: prost::DecodeError },
+    #[snafu(display("Downstream is closed"))]
+    ShuttingDown,
+}
+
+impl RequestError {
+    fn http_status(&self) -> StatusCode {
+        match self {
+            RequestError::UnsupportedContentType { .. }
+            | RequestError::UnsupportedEncoding { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            RequestError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// See https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+    fn grpc_status(&self) -> u16 {
+        match self {
+            RequestError::UnsupportedEncoding { .. } => 12, // UNIMPLEMENTED
+            RequestError::ShuttingDown => 14,               // UNAVAILABLE
+            _ => 3,                                         // INVALID_ARGUMENT
+        }
+    }
+}
+
+type ResponseFuture = Box<dyn Future<Item = Response<ResponseBody>, Error = hyper::Error> + Send>;
+
+fn handle(request: Request<Body>, out: mpsc::Sender<Event>) -> ResponseFuture {
+    let (signal, protocol) = match (request.method(), request.uri().path()) {
+        (&Method::POST, LOGS_HTTP_PATH) => (Signal::Logs, Protocol::Http),
+        (&Method::POST, METRICS_HTTP_PATH) => (Signal::Metrics, Protocol::Http),
+        (&Method::POST, LOGS_GRPC_PATH) => (Signal::Logs, Protocol::Grpc),
+        (&Method::POST, METRICS_GRPC_PATH) => (Signal::Metrics, Protocol::Grpc),
+        _ => {
+            let mut response = Response::new(ResponseBody::empty());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            return Box::new(future::ok(response));
+        }
+    };
+
+    let headers = request.headers().clone();
+    let response = request.into_body().concat2().and_then(move |body| {
+        let byte_size = body.len();
+        future::result(decode(signal, protocol, &headers, &body))
+            .and_then(move |events| {
+                emit!(OpentelemetryEventsReceived {
+                    count: events.len(),
+                    byte_size,
+                });
+                out.send_all(stream::iter_ok(events))
+                    .map_err(|_| RequestError::ShuttingDown)
+            })
+            .then(move |result| {
+                Ok::<_, hyper::Error>(match result {
+                    Ok(_) => success(signal, protocol),
+                    Err(error) => {
+                        emit!(OpentelemetryRequestError { error: &error });
+                        failure(protocol, &error)
+                    }
+                })
+            })
+    });
+
+    Box::new(response)
+}
+
+fn decode(
+    signal: Signal,
+    protocol: Protocol,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Vec<Event>, RequestError> {
+    let content_type = header(headers, CONTENT_TYPE.as_str());
+    let message = match protocol {
+        Protocol::Http => {
+            if !content_type.starts_with(PROTOBUF_CONTENT_TYPE) {
+                return Err(RequestError::UnsupportedContentType { content_type });
+            }
+            decompress(&header(headers, CONTENT_ENCODING.as_str()), body.to_vec())?
+        }
+        Protocol::Grpc => {
+            if !content_type.starts_with(GRPC_CONTENT_TYPE) {
+                return Err(RequestError::UnsupportedContentType { content_type });
+            }
+            let (compressed, message) = grpc_unframe(body)?;
+            if compressed {
+                decompress(&header(headers, "grpc-encoding"), message.to_vec())?
+            } else {
+                message.to_vec()
+            }
+        }
+    };
+
+    Ok(match signal {
+        Signal::Logs => {
+            convert::logs_to_events(ExportLogsServiceRequest::decode(message).context(Decode)?)
+        }
+        Signal::Metrics => convert::metrics_to_events(
+            ExportMetricsServiceRequest::decode(message).context(Decode)?,
+        ),
+    })
+}
+
+fn header(headers: &HeaderMap, name: &str) -> String {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn decompress(encoding: &str, body: Vec<u8>) -> Result<Vec<u8>, RequestError> {
+    match encoding {
+        "" | "identity" => Ok(body),
+        "gzip" => {
+            let mut decoded = Vec::new();
+            GzDecoder::new(&body[..])
+                .read_to_end(&mut decoded)
+                .context(Decompress)?;
+            Ok(decoded)
+        }
+        encoding => Err(RequestError::UnsupportedEncoding {
+            encoding: encoding.into(),
+        }),
+    }
+}
+
+/// Splits a unary gRPC request into its compression flag and message.
+fn grpc_unframe(body: &[u8]) -> Result<(bool, &[u8]), RequestError> {
+    if body.len() < 5 {
+        return Err(RequestError::MalformedFrame);
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&body[1..5]);
+    let len = u32::from_be_bytes(len) as usize;
+    let message = body.get(5..5 + len).ok_or(RequestError::MalformedFrame)?;
+    Ok((body[0] == 1, message))
+}
+
+fn success(signal: Signal, protocol: Protocol) -> Response<ResponseBody> {
+    let mut message = Vec::new();
+    match signal {
+        Signal::Logs => ExportLogsServiceResponse::default().encode(&mut message),
+        Signal::Metrics => ExportMetricsServiceResponse::default().encode(&mut message),
+    }
+    .expect("Encoding into a Vec can't fail");
+
+    let (content_type, body) = match protocol {
+        Protocol::Http => (PROTOBUF_CONTENT_TYPE, ResponseBody::new(message, None)),
+        Protocol::Grpc => {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            (
+                GRPC_CONTENT_TYPE,
+                ResponseBody::new(grpc::frame(false, &message), Some(trailers)),
+            )
+        }
+    };
+
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+fn failure(protocol: Protocol, error: &RequestError) -> Response<ResponseBody> {
+    match protocol {
+        Protocol::Http => {
+            let mut response = Response::new(ResponseBody::new(error.to_string().into(), None));
+            *response.status_mut() = error.http_status();
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+            response
+        }
+        // Errors are sent as a "Trailers-Only" response, with the status in
+        // the headers and no body at all.
+        Protocol::Grpc => {
+            let mut response = Response::new(ResponseBody::empty());
+            let headers = response.headers_mut();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+            headers.insert("grpc-status", HeaderValue::from(error.grpc_status()));
+            if let Ok(message) = HeaderValue::from_str(&error.to_string()) {
+                headers.insert("grpc-message", message);
+            }
+            response
+        }
+    }
+}
+
+/// A response body that can carry trailers, which gRPC uses for the status
+/// of the call. `hyper::Body` has no way of attaching them.
+struct ResponseBody {
+    data: Option<Chunk>,
+    trailers: Option<HeaderMap>,
+}
+
+impl ResponseBody {
+    fn new(data: Vec<u8>, trailers: Option<HeaderMap>) -> Self {
+        Self {
+            data: Some(data.into()),
+            trailers,
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            data: None,
+            trailers: None,
+        }
+    }
+}
+
+impl Payload for ResponseBody {
+    type Data = Chunk;
+    type Error = io::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(Async::Ready(self.data.take()))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opentelemetry::proto::{
+        common::v1::{any_value, AnyValue},
+        logs::v1::{InstrumentationLibraryLogs, LogRecord, ResourceLogs},
+    };
+    use crate::{
+        event,
+        test_util::{self, collect_n, runtime},
+    };
+
+    fn logs_request() -> Vec<u8> {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: None,
+                instrumentation_library_logs: vec![InstrumentationLibraryLogs {
+                    instrumentation_library: None,
+                    log_records: vec![LogRecord {
+                        body: Some(AnyValue {
+                            value: Some(any_value::Value::StringValue("hello".into())),
+                        }),
+                        ..Default::default()
+                    }],
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        let mut body = Vec::new();
+        request.encode(&mut body).unwrap();
+        body
+    }
+
+    #[test]
+    fn opentelemetry_grpc_frames() {
+        let frame = grpc::frame(false, b"abc");
+        assert_eq!(frame, vec![0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(grpc_unframe(&frame).unwrap(), (false, &b"abc"[..]));
+
+        assert!(grpc_unframe(&[0, 0, 0]).is_err());
+        assert!(grpc_unframe(&[0, 0, 0, 0, 9, b'a']).is_err());
+    }
+
+    #[test]
+    fn opentelemetry_decodes_grpc_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+
+        let events = decode(
+            Signal::Logs,
+            Protocol::Grpc,
+            &headers,
+            &grpc::frame(false, &logs_request()),
+        )
+        .unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].as_log()[&event::log_schema().message_key()],
+            "hello".into()
+        );
+    }
+
+    #[test]
+    fn opentelemetry_rejects_unknown_content_type() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+        let error = decode(Signal::Logs, Protocol::Http, &headers, b"{}").unwrap_err();
+        assert_eq!(error.http_status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn opentelemetry_http_logs() {
+        test_util::trace_init();
+        let mut rt = runtime();
+        let (sender, rx) = mpsc::channel(100);
+        let address = test_util::next_addr();
+
+        let source = OpentelemetryConfig { address, tls: None }
+            .build(
+                "default",
+                &GlobalOptions::default(),
+                ShutdownSignal::noop(),
+                sender,
+            )
+            .unwrap();
+        rt.spawn(source);
+
+        let response = reqwest::Client::new()
+            .post(&format!("http://{}{}", address, LOGS_HTTP_PATH))
+            .header("Content-Type", PROTOBUF_CONTENT_TYPE)
+            .body(logs_request())
+            .send()
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let events = rt.block_on(collect_n(rx, 1)).unwrap();
+        let log = events[0].as_log();
+        assert_eq!(log[&event::log_schema().message_key()], "hello".into());
+        assert_eq!(
+            log[event::log_schema().source_type_key()],
+            "opentelemetry".into()
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Streams the changes to the tables of PostgreSQL publications, decoded by
+//! `pgoutput` from a logical replication slot.
+//!
+//! The slot is read through the SQL interface of logical decoding, and
+//! advanced past each transaction once its changes were sent downstream,
+//! so the slot itself keeps the position to resume from.
+
+mod pgoutput;
+
+use self::pgoutput::{DecodeError, Message, Relation, Tuple, TupleValue};
+use crate::{
+    event::{self, Event, Value},
+    internal_events::{PostgresCdcChangesReceived, PostgresCdcReplicateFailed},
+    shutdown::ShutdownSignal,
+    tls::{tls_connector_builder, MaybeTlsSettings, TlsConfig},
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::{
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+};
+use futures01::{stream::iter_ok, sync::mpsc, Sink};
+use postgres_openssl::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+use tokio::time::delay_for;
+use tokio_postgres::Client;
+
+/// Microseconds from the Unix epoch to the PostgreSQL one, 2000-01-01.
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("At least one publication must be configured"))]
+    NoPublications,
+}
+
+#[derive(Debug, Snafu)]
+pub enum ReplicateError {
+    #[snafu(display("Unable to connect: {}", source))]
+    Connect { source: tokio_postgres::Error },
+    #[snafu(display("Unable to create the replication slot: {}", source))]
+    CreateSlot { source: tokio_postgres::Error },
+    #[snafu(display("Unable to read changes: {}", source))]
+    ReadChanges { source: tokio_postgres::Error },
+    #[snafu(display("Unable to decode a change: {}", source))]
+    Decode { source: DecodeError },
+    #[snafu(display("A change refers to the unknown relation {}", id))]
+    UnknownRelation { id: u32 },
+    #[snafu(display("Unable to advance the replication slot: {}", source))]
+    AdvanceSlot { source: tokio_postgres::Error },
+    #[snafu(display("The pipeline closed"))]
+    PipelineClosed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PostgresCdcConfig {
+    /// A connection string, either `key=value` pairs or a
+    /// `postgresql://` URL.
+    pub endpoint: String,
+    pub slot: String,
+    pub publications: Vec<String>,
+    /// Creates the slot if it doesn't exist.
+    #[serde(default)]
+    pub create_slot: bool,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// How many changes are read at once, rounded up to whole
+    /// transactions.
+    #[serde(default = "default_max_changes")]
+    pub max_changes: u32,
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_poll_interval_secs() -> u64 {
+    1
+}
+
+fn default_max_changes() -> u32 {
+    1000
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<PostgresCdcConfig>("postgres_cdc")
+}
+
+#[typetag::serde(name = "postgres_cdc")]
+impl SourceConfig for PostgresCdcConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.publications.is_empty() {
+            return Err(Box::new(BuildError::NoPublications));
+        }
+        let tls = MaybeTlsSettings::from_config(&self.tls, false)?;
+        let connector = MakeTlsConnector::new(tls_connector_builder(&tls)?.build());
+        Ok(Box::new(
+            run(self.clone(), connector, shutdown, out).boxed().compat(),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "postgres_cdc"
+    }
+}
+
+async fn run(
+    config: PostgresCdcConfig,
+    connector: MakeTlsConnector,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut shutdown = shutdown.compat();
+    loop {
+        let replicate = Box::pin(replicate(&config, connector.clone(), out.clone()));
+        match select(replicate, &mut shutdown).await {
+            // The changes of the transactions not sent yet are read again
+            // on the next start, as the slot wasn't advanced past them.
+            Either::Right(_) => return Ok(()),
+            Either::Left((Err(ReplicateError::PipelineClosed), _)) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Either::Left((Err(error), _)) => emit!(PostgresCdcReplicateFailed { error }),
+            Either::Left((Ok(()), _)) => unreachable!("replicating only ends with an error"),
+        }
+
+        let wait = Box::pin(delay_for(Duration::from_secs(1)));
+        if let Either::Right(_) = select(wait, &mut shutdown).await {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads changes from the slot until the connection fails.
+async fn replicate(
+    config: &PostgresCdcConfig,
+    connector: MakeTlsConnector,
+    mut out: mpsc::Sender<Event>,
+) -> Result<(), ReplicateError> {
+    let (client, connection) = tokio_postgres::connect(&config.endpoint, connector)
+        .await
+        .context(Connect)?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            error!(message = "connection failed.", %error);
+        }
+    });
+
+    if config.create_slot {
+        create_slot(&client, &config.slot).await?;
+    }
+    info!(message = "reading changes.", slot = %config.slot);
+
+    let publications = config.publications.join(",");
+    let max_changes = config.max_changes.min(i32::max_value() as u32) as i32;
+    // `pgoutput` describes each relation once per session, before its
+    // first change.
+    let mut decoder = Decoder::default();
+    loop {
+        let rows = client
+            .query(
+                "SELECT lsn::text, data FROM pg_logical_slot_peek_binary_changes(
+                    $1, NULL, $2, 'proto_version', '1', 'publication_names', $3
+                )",
+                &[&config.slot, &max_changes, &publications],
+            )
+            .await
+            .context(ReadChanges)?;
+        if rows.is_empty() {
+            delay_for(Duration::from_secs(config.poll_interval_secs)).await;
+            continue;
+        }
+
+        let mut events = Vec::new();
+        let mut end_lsn = None;
+        let mut byte_size = 0;
+        for row in rows {
+            let lsn: String = row.get(0);
+            let data: Vec<u8> = row.get(1);
+            byte_size += data.len();
+            let message = pgoutput::decode(&data).context(Decode)?;
+            if let Message::Commit { end_lsn: end } = message {
+                end_lsn = Some(end);
+            }
+            decoder.decode(message, &lsn, &mut events)?;
+        }
+        emit!(PostgresCdcChangesReceived {
+            count: events.len(),
+            byte_size,
+        });
+
+        out = out
+            .send_all(iter_ok(events))
+            .compat()
+            .await
+            .map_err(|_| ReplicateError::PipelineClosed)?
+            .0;
+        if let Some(end_lsn) = end_lsn {
+            client
+                .query(
+                    "SELECT pg_replication_slot_advance($1, $2::text::pg_lsn)",
+                    &[&config.slot, &pgoutput::format_lsn(end_lsn)],
+                )
+                .await
+                .context(AdvanceSlot)?;
+        }
+    }
+}
+
+async fn create_slot(client: &Client, slot: &str) -> Result<(), ReplicateError> {
+    client
+        .query(
+            "SELECT pg_create_logical_replication_slot($1, 'pgoutput')
+            WHERE NOT EXISTS (SELECT 1 FROM pg_replication_slots WHERE slot_name = $1)",
+            &[&slot],
+        )
+        .await
+        .context(CreateSlot)?;
+    Ok(())
+}
+
+/// Turns the changes into events, with the relations and the transaction
+/// they belong to.
+#[derive(Default)]
+struct Decoder {
+    relations: HashMap<u32, Relation>,
+    xid: u32,
+    commit_timestamp: i64,
+}
+
+impl Decoder {
+    fn decode(
+        &mut self,
+        message: Message,
+        lsn: &str,
+        events: &mut Vec<Event>,
+    ) -> Result<(), ReplicateError> {
+        match message {
+            Message::Begin {
+                commit_timestamp,
+                xid,
+                ..
+            } => {
+                self.xid = xid;
+                self.commit_timestamp = commit_timestamp;
+            }
+            Message::Relation(relation) => {
+                self.relations.insert(relation.id, relation);
+            }
+            Message::Insert { relation, new } => {
+                let relation = self.relation(relation)?;
+                let mut event = self.event("insert", relation, lsn);
+                event.as_mut_log().insert("after", row(relation, &new));
+                events.push(event);
+            }
+            Message::Update { relation, old, new } => {
+                let relation = self.relation(relation)?;
+                let mut event = self.event("update", relation, lsn);
+                if let Some(old) = old {
+                    event.as_mut_log().insert("before", row(relation, &old));
+                }
+                event.as_mut_log().insert("after", row(relation, &new));
+                events.push(event);
+            }
+            Message::Delete { relation, old } => {
+                let relation = self.relation(relation)?;
+                let mut event = self.event("delete", relation, lsn);
+                event.as_mut_log().insert("before", row(relation, &old));
+                events.push(event);
+            }
+            Message::Truncate { relations } => {
+                for relation in relations {
+                    let relation = self.relation(relation)?;
+                    events.push(self.event("truncate", relation, lsn));
+                }
+            }
+            Message::Commit { .. } | Message::Ignored => (),
+        }
+        Ok(())
+    }
+
+    fn relation(&self, id: u32) -> Result<&Relation, ReplicateError> {
+        self.relations
+            .get(&id)
+            .ok_or(ReplicateError::UnknownRelation { id })
+    }
+
+    fn event(&self, operation: &str, relation: &Relation, lsn: &str) -> Event {
+        let mut event = Event::new_empty_log();
+        let log = event.as_mut_log();
+        log.insert(
+            event::log_schema().timestamp_key(),
+            timestamp(self.commit_timestamp),
+        );
+        log.insert(event::log_schema().source_type_key(), "postgres_cdc");
+        log.insert("operation", operation);
+        log.insert("schema", relation.namespace.as_str());
+        log.insert("table", relation.name.as_str());
+        log.insert("xid", i64::from(self.xid));
+        log.insert("lsn", lsn);
+        event
+    }
+}
+
+/// The values of a row by column, leaving out the TOASTed values that
+/// weren't sent.
+fn row(relation: &Relation, tuple: &Tuple) -> Value {
+    let mut row = BTreeMap::new();
+    for (column, value) in relation.columns.iter().zip(tuple) {
+        let value = match value {
+            TupleValue::Null => Value::Null,
+            TupleValue::Unchanged => continue,
+            TupleValue::Text(text) => column_value(column.type_oid, text),
+        };
+        row.insert(column.name.clone(), value);
+    }
+    Value::Map(row)
+}
+
+/// Parses the text format of booleans, numbers and JSON, keeping other
+/// values as strings.
+fn column_value(type_oid: u32, text: &[u8]) -> Value {
+    let parsed = std::str::from_utf8(text)
+        .ok()
+        .and_then(|text| match type_oid {
+            // bool
+            16 => Some(Value::Boolean(text == "t")),
+            // int8, int2, int4
+            20 | 21 | 23 => text.parse().ok().map(Value::Integer),
+            // float4, float8
+            700 | 701 => text.parse().ok().map(Value::Float),
+            // json, jsonb
+            114 | 3802 => serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .map(Value::from),
+            _ => None,
+        });
+    parsed.unwrap_or_else(|| Value::from(text.to_vec()))
+}
+
+fn timestamp(micros: i64) -> DateTime<Utc> {
+    let micros = micros + POSTGRES_EPOCH_MICROS;
+    Utc.timestamp(
+        micros.div_euclid(1_000_000),
+        (micros.rem_euclid(1_000_000) * 1000) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::postgres_cdc::pgoutput::Column;
+
+    #[test]
+    fn postgres_cdc_decodes_changes() {
+        let mut decoder = Decoder::default();
+        let mut events = Vec::new();
+        let messages = vec![
+            Message::Begin {
+                final_lsn: 0x16B_3748,
+                commit_timestamp: 1_000_000,
+                xid: 42,
+            },
+            Message::Relation(Relation {
+                id: 1,
+                namespace: "public".into(),
+                name: "users".into(),
+                columns: vec![
+                    Column {
+                        name: "id".into(),
+                        type_oid: 23,
+                    },
+                    Column {
+                        name: "profile".into(),
+                        type_oid: 3802,
+                    },
+                    Column {
+                        name: "name".into(),
+                        type_oid: 25,
+                    },
+                ],
+            }),
+            Message::Update {
+                relation: 1,
+                old: Some(vec![
+                    TupleValue::Text(b"1".to_vec()),
+                    TupleValue::Null,
+                    TupleValue::Null,
+                ]),
+                new: vec![
+                    TupleValue::Text(b"2".to_vec()),
+                    TupleValue::Unchanged,
+                    TupleValue::Text(b"bob".to_vec()),
+                ],
+            },
+            Message::Commit {
+                end_lsn: 0x16B_3780,
+            },
+        ];
+        for message in messages {
+            decoder.decode(message, "0/16B3748", &mut events).unwrap();
+        }
+
+        assert_eq!(events.len(), 1);
+        let log = events[0].as_log();
+        assert_eq!(log[&"operation".into()], "update".into());
+        assert_eq!(log[&"table".into()], "users".into());
+        assert_eq!(log[&"xid".into()], Value::Integer(42));
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            Value::from(Utc.ymd(2000, 1, 1).and_hms(0, 0, 1))
+        );
+        assert_eq!(log[&"before.id".into()], Value::Integer(1));
+        assert_eq!(log[&"after.id".into()], Value::Integer(2));
+        assert_eq!(log[&"after.name".into()], "bob".into());
+        assert!(log.get(&"after.profile".into()).is_none());
+
+        let unknown = Message::Delete {
+            relation: 2,
+            old: vec![],
+        };
+        assert!(decoder.decode(unknown, "0/16B3790", &mut events).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+//! Decoding of the messages of `pgoutput`, the logical decoding plugin
+//! built into PostgreSQL, in version 1 of its protocol.
+
+use snafu::Snafu;
+use std::str;
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum DecodeError {
+    #[snafu(display("Message ends early"))]
+    Truncated,
+    #[snafu(display("Unknown message type {:?}", tag as char))]
+    UnknownMessage { tag: u8 },
+    #[snafu(display("Unknown tuple type {:?}", tag as char))]
+    UnknownTuple { tag: u8 },
+    #[snafu(display("Name isn't valid UTF-8"))]
+    InvalidName,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Begin {
+        /// The position of the commit record.
+        final_lsn: u64,
+        /// Microseconds since the PostgreSQL epoch, 2000-01-01.
+        commit_timestamp: i64,
+        xid: u32,
+    },
+    Commit {
+        /// The position right after the commit record, which the slot is
+        /// advanced to once the transaction was forwarded.
+        end_lsn: u64,
+    },
+    Relation(Relation),
+    Insert {
+        relation: u32,
+        new: Tuple,
+    },
+    Update {
+        relation: u32,
+        /// The old key, or whole row for tables with a full replica
+        /// identity, if the key changed.
+        old: Option<Tuple>,
+        new: Tuple,
+    },
+    Delete {
+        relation: u32,
+        /// The old key, or whole row for tables with a full replica
+        /// identity.
+        old: Tuple,
+    },
+    Truncate {
+        relations: Vec<u32>,
+    },
+    /// Origins and types, which changes don't need.
+    Ignored,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub id: u32,
+    pub namespace: String,
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub type_oid: u32,
+}
+
+pub type Tuple = Vec<TupleValue>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TupleValue {
+    Null,
+    /// A TOASTed value that was left as is, and isn't sent.
+    Unchanged,
+    /// The value in the text format of its type.
+    Text(Vec<u8>),
+}
+
+pub fn decode(data: &[u8]) -> Result<Message, DecodeError> {
+    let mut reader = Reader { data };
+    let message = match reader.u8()? {
+        b'B' => Message::Begin {
+            final_lsn: reader.u64()?,
+            commit_timestamp: reader.u64()? as i64,
+            xid: reader.u32()?,
+        },
+        b'C' => {
+            let _flags = reader.u8()?;
+            let _commit_lsn = reader.u64()?;
+            Message::Commit {
+                end_lsn: reader.u64()?,
+            }
+        }
+        b'R' => {
+            let id = reader.u32()?;
+            let namespace = reader.string()?;
+            let name = reader.string()?;
+            let _replica_identity = reader.u8()?;
+            let count = reader.u16()?;
+            let mut columns = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let _flags = reader.u8()?;
+                let name = reader.string()?;
+                let type_oid = reader.u32()?;
+                let _type_modifier = reader.u32()?;
+                columns.push(Column { name, type_oid });
+            }
+            Message::Relation(Relation {
+                id,
+                namespace,
+                name,
+                columns,
+            })
+        }
+        b'I' => {
+            let relation = reader.u32()?;
+            reader.expect_tuple(b'N')?;
+            Message::Insert {
+                relation,
+                new: reader.tuple()?,
+            }
+        }
+        b'U' => {
+            let relation = reader.u32()?;
+            let old = match reader.u8()? {
+                b'K' | b'O' => {
+                    let old = reader.tuple()?;
+                    reader.expect_tuple(b'N')?;
+                    Some(old)
+                }
+                b'N' => None,
+                tag => return Err(DecodeError::UnknownTuple { tag }),
+            };
+            Message::Update {
+                relation,
+                old,
+                new: reader.tuple()?,
+            }
+        }
+        b'D' => {
+            let relation = reader.u32()?;
+            match reader.u8()? {
+                b'K' | b'O' => Message::Delete {
+                    relation,
+                    old: reader.tuple()?,
+                },
+                tag => return Err(DecodeError::UnknownTuple { tag }),
+            }
+        }
+        b'T' => {
+            let count = reader.u32()?;
+            let _options = reader.u8()?;
+            let mut relations = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                relations.push(reader.u32()?);
+            }
+            Message::Truncate { relations }
+        }
+        b'O' | b'Y' => Message::Ignored,
+        tag => return Err(DecodeError::UnknownMessage { tag }),
+    };
+    Ok(message)
+}
+
+/// Formats a position in the write-ahead log the way PostgreSQL does.
+pub fn format_lsn(lsn: u64) -> String {
+    format!("{:X}/{:X}", lsn >> 32, lsn & 0xffff_ffff)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < count {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, DecodeError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    /// A null terminated string.
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let end = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(DecodeError::Truncated)?;
+        let string = str::from_utf8(&self.data[..end])
+            .map_err(|_| DecodeError::InvalidName)?
+            .to_owned();
+        self.data = &self.data[end + 1..];
+        Ok(string)
+    }
+
+    fn expect_tuple(&mut self, expected: u8) -> Result<(), DecodeError> {
+        match self.u8()? {
+            tag if tag == expected => Ok(()),
+            tag => Err(DecodeError::UnknownTuple { tag }),
+        }
+    }
+
+    fn tuple(&mut self) -> Result<Tuple, DecodeError> {
+        let count = self.u16()?;
+        let mut values = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let value = match self.u8()? {
+                b'n' => TupleValue::Null,
+                b'u' => TupleValue::Unchanged,
+                b't' => {
+                    let length = self.u32()? as usize;
+                    TupleValue::Text(self.take(length)?.to_vec())
+                }
+                tag => return Err(DecodeError::UnknownTuple { tag }),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgoutput_decodes_relation_and_update() {
+        let mut relation = b"R\0\0\x40\x01public\0users\0d\0\x02".to_vec();
+        relation.extend_from_slice(b"\x01id\0\0\0\0\x17\xff\xff\xff\xff");
+        relation.extend_from_slice(b"\x00name\0\0\0\0\x19\xff\xff\xff\xff");
+        assert_eq!(
+            decode(&relation).unwrap(),
+            Message::Relation(Relation {
+                id: 0x4001,
+                namespace: "public".into(),
+                name: "users".into(),
+                columns: vec![
+                    Column {
+                        name: "id".into(),
+                        type_oid: 23,
+                    },
+                    Column {
+                        name: "name".into(),
+                        type_oid: 25,
+                    },
+                ],
+            })
+        );
+
+        let mut update = b"U\0\0\x40\x01K\0\x02t\0\0\0\x011n".to_vec();
+        update.extend_from_slice(b"N\0\x02t\0\0\0\x012t\0\0\0\x03bob");
+        assert_eq!(
+            decode(&update).unwrap(),
+            Message::Update {
+                relation: 0x4001,
+                old: Some(vec![TupleValue::Text(b"1".to_vec()), TupleValue::Null]),
+                new: vec![
+                    TupleValue::Text(b"2".to_vec()),
+                    TupleValue::Text(b"bob".to_vec())
+                ],
+            }
+        );
+
+        assert_eq!(decode(&update[..10]), Err(DecodeError::Truncated));
+        assert_eq!(format_lsn(0x1_16B3_748), "1/16B3748");
+    }
+}
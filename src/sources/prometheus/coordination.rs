@@ -0,0 +1,297 @@
+//! Shares the discovered targets among the instances of an aggregator
+//! deployment, so each one is scraped by a single instance.
+//!
+//! Every instance renews a Kubernetes Lease of its own, labeled with the
+//! group. The instances whose leases haven't expired are the members, and
+//! each target is assigned to one of them by rendezvous hashing, so only the
+//! targets of joining or leaving members move.
+
+use super::kubernetes::{DiscoveryError, Http, InvalidLeaseList, InvalidUrl, Target};
+use crate::sources::util::HttpsClient;
+use chrono::{DateTime, Duration, Utc};
+use futures01::{future, Future, Stream};
+use http::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method, Request, StatusCode,
+};
+use hyper::{Body, Chunk};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::{futures01::FutureExt as _, ResultExt};
+use std::hash::Hasher;
+
+const GROUP_LABEL: &str = "vector.dev/coordination-group";
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CoordinationConfig {
+    /// The instances sharing the targets, which all set the same group.
+    pub group: String,
+    /// Unique within the group, defaults to the host name, which is the pod
+    /// name in Kubernetes.
+    pub identity: Option<String>,
+    /// The namespace of the leases, defaults to the one of the pod.
+    pub namespace: Option<String>,
+    /// How long an instance remains a member without renewing its lease,
+    /// defaults to three refresh intervals.
+    pub lease_duration_secs: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct Coordinator {
+    leases_uri: String,
+    lease_name: String,
+    group: String,
+    identity: String,
+    lease_duration_secs: u64,
+}
+
+impl Coordinator {
+    pub fn new(
+        api_server: &str,
+        namespace: &str,
+        group: &str,
+        identity: &str,
+        lease_duration_secs: u64,
+    ) -> Self {
+        Self {
+            leases_uri: format!(
+                "{}/apis/coordination.k8s.io/v1/namespaces/{}/leases",
+                api_server, namespace
+            ),
+            lease_name: format!("{}-{}", group, identity).to_lowercase(),
+            group: group.to_owned(),
+            identity: identity.to_owned(),
+            lease_duration_secs,
+        }
+    }
+
+    /// Renews the lease of this instance, creating it when missing, and
+    /// returns the targets assigned to it.
+    pub fn assign(
+        &self,
+        client: &HttpsClient,
+        token: Option<String>,
+        targets: Vec<Target>,
+    ) -> impl Future<Item = Vec<Target>, Error = DiscoveryError> {
+        let now = Utc::now();
+        let lease = self.lease(now).to_string();
+        let requests = || -> Result<_, DiscoveryError> {
+            let own_uri = format!("{}/{}", self.leases_uri, self.lease_name);
+            let selector = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("labelSelector", &format!("{}={}", GROUP_LABEL, self.group))
+                .finish();
+            let list_uri = format!("{}?{}", self.leases_uri, selector);
+            Ok((
+                request(
+                    Method::PATCH,
+                    &own_uri,
+                    &token,
+                    Some(("application/merge-patch+json", lease.clone())),
+                )?,
+                request(
+                    Method::POST,
+                    &self.leases_uri,
+                    &token,
+                    Some(("application/json", lease)),
+                )?,
+                request(Method::GET, &list_uri, &token, None)?,
+            ))
+        };
+        let client = client.clone();
+        let identity = self.identity.clone();
+
+        future::result(requests()).and_then(move |(renew, create, list)| {
+            let create_client = client.clone();
+            send(&client, renew)
+                .and_then(move |(status, _)| match status {
+                    StatusCode::NOT_FOUND => future::Either::A(
+                        send(&create_client, create).and_then(|(status, _)| check(status)),
+                    ),
+                    status => future::Either::B(future::result(check(status))),
+                })
+                .and_then(move |()| send(&client, list))
+                .and_then(|(status, body)| {
+                    check(status)?;
+                    serde_json::from_slice::<LeaseList>(&body).context(InvalidLeaseList)
+                })
+                .map(move |leases| {
+                    let members = members(leases, &identity, now);
+                    debug!(message = "coordination members.", ?members);
+                    targets
+                        .into_iter()
+                        .filter(|target| owner(&members, &target.url) == Some(&identity))
+                        .collect()
+                })
+        })
+    }
+
+    fn lease(&self, now: DateTime<Utc>) -> serde_json::Value {
+        json!({
+            "apiVersion": "coordination.k8s.io/v1",
+            "kind": "Lease",
+            "metadata": {
+                "name": self.lease_name,
+                "labels": {GROUP_LABEL: self.group},
+            },
+            "spec": {
+                "holderIdentity": self.identity,
+                "leaseDurationSeconds": self.lease_duration_secs,
+                "renewTime": now.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+            },
+        })
+    }
+}
+
+fn request(
+    method: Method,
+    uri: &str,
+    token: &Option<String>,
+    body: Option<(&str, String)>,
+) -> Result<Request<Body>, DiscoveryError> {
+    let mut request = Request::builder();
+    request.method(method).uri(uri);
+    if let Some(token) = token {
+        request.header(AUTHORIZATION, format!("Bearer {}", token.trim()));
+    }
+    let body = match body {
+        Some((content_type, body)) => {
+            request.header(CONTENT_TYPE, content_type);
+            Body::from(body)
+        }
+        None => Body::empty(),
+    };
+    request.body(body).context(InvalidUrl)
+}
+
+fn send(
+    client: &HttpsClient,
+    request: Request<Body>,
+) -> impl Future<Item = (StatusCode, Chunk), Error = DiscoveryError> {
+    client.request(request).context(Http).and_then(|response| {
+        let status = response.status();
+        response
+            .into_body()
+            .concat2()
+            .context(Http)
+            .map(move |body| (status, body))
+    })
+}
+
+fn check(status: StatusCode) -> Result<(), DiscoveryError> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(DiscoveryError::ResponseStatus { status })
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct LeaseList {
+    #[serde(default)]
+    items: Vec<Lease>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Lease {
+    spec: LeaseSpec,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct LeaseSpec {
+    holder_identity: Option<String>,
+    lease_duration_seconds: Option<i64>,
+    renew_time: Option<DateTime<Utc>>,
+}
+
+/// The identities holding unexpired leases, including this instance's own
+/// which was just renewed.
+fn members(leases: LeaseList, identity: &str, now: DateTime<Utc>) -> Vec<String> {
+    let mut members = leases
+        .items
+        .into_iter()
+        .filter_map(|lease| {
+            let spec = lease.spec;
+            let expiry = spec.renew_time? + Duration::seconds(spec.lease_duration_seconds?);
+            if expiry > now {
+                spec.holder_identity
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    members.push(identity.to_owned());
+    members.sort();
+    members.dedup();
+    members
+}
+
+/// The member with the highest hash of itself and the key.
+fn owner<'a>(members: &'a [String], key: &str) -> Option<&'a String> {
+    members.iter().max_by_key(|member| {
+        let mut hasher = seahash::SeaHasher::new();
+        hasher.write(member.as_bytes());
+        hasher.write_u8(0);
+        hasher.write(key.as_bytes());
+        hasher.finish()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordination_keeps_unexpired_members() {
+        let now = Utc::now();
+        let leases = serde_json::from_value::<LeaseList>(json!({
+            "items": [
+                {"spec": {
+                    "holderIdentity": "vector-1",
+                    "leaseDurationSeconds": 30,
+                    "renewTime": (now - Duration::seconds(10)).to_rfc3339(),
+                }},
+                {"spec": {
+                    "holderIdentity": "vector-2",
+                    "leaseDurationSeconds": 30,
+                    "renewTime": (now - Duration::seconds(60)).to_rfc3339(),
+                }},
+                {"spec": {"holderIdentity": "vector-3"}},
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            members(leases, "vector-0", now),
+            vec!["vector-0", "vector-1"]
+        );
+    }
+
+    #[test]
+    fn coordination_moves_only_targets_of_leaving_members() {
+        let members = ["a", "b", "c"]
+            .iter()
+            .map(|member| member.to_string())
+            .collect::<Vec<_>>();
+        let keys = (0..100)
+            .map(|index| format!("http://10.0.0.{}:9090/metrics", index))
+            .collect::<Vec<_>>();
+
+        let before = keys
+            .iter()
+            .map(|key| owner(&members, key).unwrap().clone())
+            .collect::<Vec<_>>();
+        for member in &members {
+            assert!(before.iter().filter(|owner| *owner == member).count() > 10);
+        }
+
+        let remaining = &members[..2];
+        for (key, previous) in keys.iter().zip(before) {
+            let owner = owner(remaining, key).unwrap();
+            if previous != "c" {
+                assert_eq!(owner, &previous);
+            }
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Discovers the pods to scrape through the Kubernetes API, by their
+//! `prometheus.io/*` annotations.
+
+use super::coordination::{CoordinationConfig, Coordinator};
+use crate::{sources::util::HttpsClient, tls::TlsOptions};
+use futures01::{future, Future, Stream};
+use http::{header::AUTHORIZATION, Request, Uri};
+use hyper::Body;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use snafu::{futures01::FutureExt as _, ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+#[derive(Debug, Snafu)]
+pub enum BuildError {
+    #[snafu(display(
+        "`api_server` isn't set and KUBERNETES_SERVICE_HOST is missing, is Vector running in a pod?"
+    ))]
+    NotInCluster,
+    #[snafu(display("Invalid regex in filter for {:?}: {}", source_label, source))]
+    InvalidFilterRegex {
+        source_label: String,
+        source: regex::Error,
+    },
+    #[snafu(display(
+        "`coordination.namespace` isn't set and the namespace of the pod is unknown: {}",
+        source
+    ))]
+    MissingLeaseNamespace { source: std::io::Error },
+    #[snafu(display("`coordination.identity` isn't set and the host name is unknown"))]
+    MissingIdentity,
+}
+
+#[derive(Debug, Snafu)]
+pub enum DiscoveryError {
+    #[snafu(display("Unable to read the service account token: {}", source))]
+    ReadToken { source: std::io::Error },
+    #[snafu(display("Invalid pods URL: {}", source))]
+    InvalidUrl { source: http::Error },
+    #[snafu(display("Request failed: {}", source))]
+    Http { source: hyper::Error },
+    #[snafu(display("Unexpected response status: {}", status))]
+    ResponseStatus { status: http::StatusCode },
+    #[snafu(display("Invalid pod list: {}", source))]
+    InvalidPodList { source: serde_json::Error },
+    #[snafu(display("Invalid lease list: {}", source))]
+    InvalidLeaseList { source: serde_json::Error },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct KubernetesDiscoveryConfig {
+    /// The namespaces to discover pods in, all of them if empty.
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    /// Selects the pods by label, e.g. `app=web,tier!=cache`.
+    pub label_selector: Option<String>,
+    #[serde(default = "default_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+    /// Applied in order, like the `keep` and `drop` actions of Prometheus
+    /// relabeling.
+    #[serde(default)]
+    pub filters: Vec<TargetFilterConfig>,
+    /// Defaults to the API server of the cluster Vector runs in.
+    pub api_server: Option<String>,
+    /// Defaults to the token of the pod's service account, when running
+    /// in the cluster.
+    pub token_path: Option<PathBuf>,
+    pub tls: Option<TlsOptions>,
+    /// Shares the targets with the other instances of the group, each
+    /// scraping its own part of them.
+    pub coordination: Option<CoordinationConfig>,
+}
+
+fn default_refresh_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TargetFilterConfig {
+    /// `namespace`, `pod`, `label.<name>` or `annotation.<name>`.
+    pub source_label: String,
+    /// Has to match the whole value, missing labels have an empty one.
+    pub regex: String,
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Drops the targets not matching.
+    Keep,
+    /// Drops the targets matching.
+    Drop,
+}
+
+impl Default for FilterAction {
+    fn default() -> Self {
+        FilterAction::Keep
+    }
+}
+
+#[derive(Clone)]
+struct TargetFilter {
+    source_label: String,
+    regex: Regex,
+    action: FilterAction,
+}
+
+/// A scraped endpoint, along with the tags added to its metrics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub url: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Discovery {
+    api_server: String,
+    namespaces: Vec<String>,
+    label_selector: Option<String>,
+    token_path: Option<PathBuf>,
+    filters: Vec<TargetFilter>,
+    coordinator: Option<Coordinator>,
+}
+
+impl KubernetesDiscoveryConfig {
+    /// The TLS options to reach the API server, the cluster's CA is
+    /// trusted by default.
+    pub fn tls_options(&self) -> Option<TlsOptions> {
+        match (&self.tls, &self.api_server) {
+            (Some(tls), _) => Some(tls.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(TlsOptions {
+                ca_file: Some(Path::new(SERVICE_ACCOUNT_DIR).join("ca.crt")),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn build(&self) -> Result<Discovery, BuildError> {
+        let api_server = match &self.api_server {
+            Some(api_server) => api_server.trim_end_matches('/').to_owned(),
+            None => {
+                let host =
+                    env::var("KUBERNETES_SERVICE_HOST").map_err(|_| BuildError::NotInCluster)?;
+                let port = env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_owned());
+                if host.contains(':') {
+                    format!("https://[{}]:{}", host, port)
+                } else {
+                    format!("https://{}:{}", host, port)
+                }
+            }
+        };
+        let token_path = match (&self.token_path, &self.api_server) {
+            (Some(path), _) => Some(path.clone()),
+            (None, Some(_)) => None,
+            (None, None) => Some(Path::new(SERVICE_ACCOUNT_DIR).join("token")),
+        };
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| {
+                Ok(TargetFilter {
+                    source_label: filter.source_label.clone(),
+                    regex: Regex::new(&format!("^(?:{})$", filter.regex)).context(
+                        InvalidFilterRegex {
+                            source_label: &filter.source_label,
+                        },
+                    )?,
+                    action: filter.action,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let coordinator = match &self.coordination {
+            Some(coordination) => Some(self.coordinator(coordination, &api_server)?),
+            None => None,
+        };
+
+        Ok(Discovery {
+            api_server,
+            namespaces: self.namespaces.clone(),
+            label_selector: self.label_selector.clone(),
+            token_path,
+            filters,
+            coordinator,
+        })
+    }
+
+    fn coordinator(
+        &self,
+        coordination: &CoordinationConfig,
+        api_server: &str,
+    ) -> Result<Coordinator, BuildError> {
+        let namespace = match &coordination.namespace {
+            Some(namespace) => namespace.clone(),
+            None => fs::read_to_string(Path::new(SERVICE_ACCOUNT_DIR).join("namespace"))
+                .context(MissingLeaseNamespace)?
+                .trim()
+                .to_owned(),
+        };
+        let identity = match &coordination.identity {
+            Some(identity) => identity.clone(),
+            None => env::var("HOSTNAME")
+                .ok()
+                .or_else(hostname::get_hostname)
+                .ok_or(BuildError::MissingIdentity)?,
+        };
+        let lease_duration_secs = coordination
+            .lease_duration_secs
+            .unwrap_or(3 * self.refresh_interval_secs.max(1));
+
+        Ok(Coordinator::new(
+            api_server,
+            &namespace,
+            &coordination.group,
+            &identity,
+            lease_duration_secs,
+        ))
+    }
+}
+
+impl Discovery {
+    /// Lists the pods of every namespace, and returns the targets among them
+    /// assigned to this instance.
+    pub fn discover(
+        &self,
+        client: &HttpsClient,
+    ) -> impl Future<Item = Vec<Target>, Error = DiscoveryError> {
+        let requests = self
+            .token()
+            .and_then(|token| Ok((self.requests(&token)?, token)));
+        let client = client.clone();
+        let filters = self.filters.clone();
+        let coordinator = self.coordinator.clone();
+
+        future::result(requests).and_then(move |(requests, token)| {
+            let lists = requests.into_iter().map(|request| {
+                client
+                    .request(request)
+                    .context(Http)
+                    .and_then(|response| {
+                        let status = response.status();
+                        if !status.is_success() {
+                            return future::Either::A(future::err(
+                                DiscoveryError::ResponseStatus { status },
+                            ));
+                        }
+                        future::Either::B(response.into_body().concat2().context(Http))
+                    })
+                    .and_then(|body| {
+                        serde_json::from_slice::<PodList>(&body).context(InvalidPodList)
+                    })
+            });
+            future::join_all(lists).and_then(move |lists| {
+                let targets = lists
+                    .into_iter()
+                    .flat_map(|list| list.items)
+                    .filter_map(|pod| target(&pod, &filters))
+                    .collect();
+                match coordinator {
+                    Some(coordinator) => {
+                        future::Either::A(coordinator.assign(&client, token, targets))
+                    }
+                    None => future::Either::B(future::ok(targets)),
+                }
+            })
+        })
+    }
+
+    fn token(&self) -> Result<Option<String>, DiscoveryError> {
+        match &self.token_path {
+            Some(path) => Ok(Some(fs::read_to_string(path).context(ReadToken)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn requests(&self, token: &Option<String>) -> Result<Vec<Request<Body>>, DiscoveryError> {
+        let query = self.label_selector.as_ref().map(|selector| {
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("labelSelector", selector)
+                .finish()
+        });
+        let paths = if self.namespaces.is_empty() {
+            vec!["/api/v1/pods".to_owned()]
+        } else {
+            self.namespaces
+                .iter()
+                .map(|namespace| format!("/api/v1/namespaces/{}/pods", namespace))
+                .collect()
+        };
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let mut uri = format!("{}{}", self.api_server, path);
+                if let Some(query) = &query {
+                    uri.push('?');
+                    uri.push_str(query);
+                }
+                let mut request = Request::get(uri);
+                if let Some(token) = &token {
+                    request.header(AUTHORIZATION, format!("Bearer {}", token.trim()));
+                }
+                request.body(Body::empty()).context(InvalidUrl)
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct PodList {
+    #[serde(default)]
+    items: Vec<Pod>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Pod {
+    metadata: PodMeta,
+    spec: PodSpec,
+    status: PodStatus,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct PodMeta {
+    name: String,
+    namespace: String,
+    labels: BTreeMap<String, String>,
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct PodSpec {
+    containers: Vec<Container>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Container {
+    ports: Vec<ContainerPort>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct ContainerPort {
+    container_port: u16,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct PodStatus {
+    phase: String,
+    #[serde(rename = "podIP")]
+    pod_ip: Option<String>,
+}
+
+/// The target of a running pod annotated with `prometheus.io/scrape: "true"`,
+/// if the filters keep it. The port is the one annotated, or else the first
+/// container port.
+fn target(pod: &Pod, filters: &[TargetFilter]) -> Option<Target> {
+    let annotation = |name: &str| pod.metadata.annotations.get(name).map(String::as_str);
+    if annotation("prometheus.io/scrape") != Some("true") || pod.status.phase != "Running" {
+        return None;
+    }
+    for filter in filters {
+        let value = match filter.source_label.as_str() {
+            "namespace" => Some(&pod.metadata.namespace),
+            "pod" => Some(&pod.metadata.name),
+            label if label.starts_with("label.") => pod.metadata.labels.get(&label[6..]),
+            label if label.starts_with("annotation.") => pod.metadata.annotations.get(&label[11..]),
+            _ => None,
+        };
+        let matches = filter
+            .regex
+            .is_match(value.map(String::as_str).unwrap_or(""));
+        if matches != (filter.action == FilterAction::Keep) {
+            return None;
+        }
+    }
+
+    let ip = pod.status.pod_ip.as_ref()?;
+    let port = match annotation("prometheus.io/port") {
+        Some(port) => port.parse::<u16>().ok()?,
+        None => pod
+            .spec
+            .containers
+            .iter()
+            .flat_map(|container| container.ports.iter())
+            .map(|port| port.container_port)
+            .next()?,
+    };
+    let scheme = annotation("prometheus.io/scheme").unwrap_or("http");
+    let path = annotation("prometheus.io/path").unwrap_or("/metrics");
+    let host = if ip.contains(':') {
+        format!("[{}]", ip)
+    } else {
+        ip.clone()
+    };
+    let url = format!("{}://{}:{}{}", scheme, host, port, path);
+    url.parse::<Uri>().ok()?;
+
+    let mut tags = BTreeMap::new();
+    tags.insert(
+        "kubernetes_namespace".to_owned(),
+        pod.metadata.namespace.clone(),
+    );
+    tags.insert("kubernetes_pod".to_owned(), pod.metadata.name.clone());
+    Some(Target { url, tags })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sources::util::https_client,
+        test_util::{next_addr, runtime},
+    };
+    use hyper::{
+        service::{make_service_fn, service_fn_ok},
+        Response, Server,
+    };
+
+    const PODS: &str = r#"{
+        "kind": "PodList",
+        "items": [
+            {
+                "metadata": {
+                    "name": "web-1",
+                    "namespace": "default",
+                    "labels": {"app": "web"},
+                    "annotations": {"prometheus.io/scrape": "true", "prometheus.io/port": "9090"}
+                },
+                "spec": {"containers": [{"name": "web", "ports": [{"containerPort": 8080}]}]},
+                "status": {"phase": "Running", "podIP": "10.0.0.1"}
+            },
+            {
+                "metadata": {
+                    "name": "worker-1",
+                    "namespace": "default",
+                    "labels": {"app": "worker"},
+                    "annotations": {"prometheus.io/scrape": "true", "prometheus.io/path": "/stats"}
+                },
+                "spec": {"containers": [{"name": "worker", "ports": [{"containerPort": 8081}]}]},
+                "status": {"phase": "Running", "podIP": "10.0.0.2"}
+            },
+            {
+                "metadata": {"name": "db-1", "namespace": "default"},
+                "spec": {"containers": [{"name": "db", "ports": [{"containerPort": 5432}]}]},
+                "status": {"phase": "Running", "podIP": "10.0.0.3"}
+            },
+            {
+                "metadata": {
+                    "name": "web-2",
+                    "namespace": "default",
+                    "annotations": {"prometheus.io/scrape": "true", "prometheus.io/port": "9090"}
+                },
+                "status": {"phase": "Pending"}
+            }
+        ]
+    }"#;
+
+    fn discovery(config: &str) -> Discovery {
+        toml::from_str::<KubernetesDiscoveryConfig>(config)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn targets(discovery: &Discovery) -> Vec<Target> {
+        let pods = serde_json::from_str::<PodList>(PODS).unwrap();
+        pods.items
+            .iter()
+            .filter_map(|pod| target(pod, &discovery.filters))
+            .collect()
+    }
+
+    #[test]
+    fn kubernetes_discovers_annotated_pods() {
+        let targets = targets(&discovery(r#"api_server = "http://localhost""#));
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].url, "http://10.0.0.1:9090/metrics");
+        assert_eq!(targets[0].tags["kubernetes_pod"], "web-1");
+        assert_eq!(targets[0].tags["kubernetes_namespace"], "default");
+        assert_eq!(targets[1].url, "http://10.0.0.2:8081/stats");
+    }
+
+    #[test]
+    fn kubernetes_filters_targets() {
+        let keep = discovery(
+            r#"
+            api_server = "http://localhost"
+            [[filters]]
+            source_label = "label.app"
+            regex = "web|api"
+            "#,
+        );
+        let targets = targets(&keep);
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].tags["kubernetes_pod"], "web-1");
+
+        let drop = discovery(
+            r#"
+            api_server = "http://localhost"
+            [[filters]]
+            source_label = "pod"
+            regex = "web-.*"
+            action = "drop"
+            "#,
+        );
+        let targets = targets(&drop);
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].tags["kubernetes_pod"], "worker-1");
+    }
+
+    #[test]
+    fn kubernetes_lists_pods_from_api() {
+        let mut rt = runtime();
+        let addr = next_addr();
+        let make_svc = make_service_fn(|_| {
+            service_fn_ok(|request: Request<Body>| {
+                assert_eq!(request.uri().path(), "/api/v1/namespaces/default/pods");
+                assert_eq!(request.uri().query(), Some("labelSelector=app%3Dweb"));
+                Response::new(Body::from(PODS))
+            })
+        });
+        rt.spawn(
+            Server::bind(&addr)
+                .serve(make_svc)
+                .map_err(|error| panic!("server error: {}", error)),
+        );
+
+        let discovery = discovery(&format!(
+            r#"
+            api_server = "http://{}"
+            namespaces = ["default"]
+            label_selector = "app=web"
+            "#,
+            addr
+        ));
+        let client = https_client(&None).unwrap();
+        let targets = rt.block_on(discovery.discover(&client)).unwrap();
+        assert_eq!(targets.len(), 2);
+    }
+}
@@ -1,25 +1,51 @@
 use crate::{
-    internal_events::{PrometheusHttpError, PrometheusParseError, PrometheusRequestCompleted},
+    internal_events::{
+        PrometheusHttpError, PrometheusKubernetesDiscoveryError, PrometheusParseError,
+        PrometheusRequestCompleted, PrometheusTargetsDiscovered,
+    },
     shutdown::ShutdownSignal,
+    sources::util::{https_client, HttpsClient},
     stream::StreamExt,
-    topology::config::GlobalOptions,
+    topology::{config::GlobalOptions, scheduler},
     Event,
 };
-use futures01::{sync::mpsc, Future, Sink, Stream};
+use futures01::{future, sync::mpsc, Future, Sink, Stream};
 use http::Uri;
 use hyper_openssl::HttpsConnector;
+use kubernetes::{Discovery, KubernetesDiscoveryConfig, Target};
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
-use std::time::{Duration, Instant};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use tokio01::timer::Interval;
 
+pub mod coordination;
+pub mod kubernetes;
 pub mod parser;
 
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Either `hosts` or `kubernetes` has to be set"))]
+    NoTargets,
+    #[snafu(display("`scrape_interval_secs` must be at least 1"))]
+    IntervalTooShort,
+    #[snafu(display("`scrape_timeout_secs` must be at least 1"))]
+    TimeoutTooShort,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 struct PrometheusConfig {
+    #[serde(default)]
     hosts: Vec<String>,
     #[serde(default = "default_scrape_interval_secs")]
     scrape_interval_secs: u64,
+    /// Scrapes not done by then are skipped, defaults to the interval.
+    scrape_timeout_secs: Option<u64>,
+    /// Scrapes the pods discovered in Kubernetes, besides the hosts.
+    kubernetes: Option<KubernetesDiscoveryConfig>,
 }
 
 pub fn default_scrape_interval_secs() -> u64 {
@@ -35,12 +61,40 @@ impl crate::topology::config::SourceConfig for PrometheusConfig {
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
-        let mut urls = Vec::new();
+        if self.hosts.is_empty() && self.kubernetes.is_none() {
+            return Err(Box::new(BuildError::NoTargets));
+        }
+        if self.scrape_interval_secs == 0 {
+            return Err(Box::new(BuildError::IntervalTooShort));
+        }
+        if self.scrape_timeout_secs == Some(0) {
+            return Err(Box::new(BuildError::TimeoutTooShort));
+        }
+        let mut targets = Vec::new();
         for host in self.hosts.iter() {
             let base_uri = host.parse::<Uri>().context(super::UriParseError)?;
-            urls.push(format!("{}metrics", base_uri));
+            targets.push(Target {
+                url: format!("{}metrics", base_uri),
+                tags: BTreeMap::new(),
+            });
         }
-        Ok(prometheus(urls, self.scrape_interval_secs, shutdown, out))
+        let discovery = match &self.kubernetes {
+            Some(config) => Some((
+                config.build()?,
+                https_client(&config.tls_options())?,
+                config.refresh_interval_secs,
+            )),
+            None => None,
+        };
+        Ok(prometheus(
+            targets,
+            discovery,
+            self.scrape_interval_secs,
+            self.scrape_timeout_secs
+                .unwrap_or(self.scrape_interval_secs),
+            shutdown,
+            out,
+        ))
     }
 
     fn output_type(&self) -> crate::topology::config::DataType {
@@ -53,30 +107,75 @@ impl crate::topology::config::SourceConfig for PrometheusConfig {
 }
 
 fn prometheus(
-    urls: Vec<String>,
+    static_targets: Vec<Target>,
+    discovery: Option<(Discovery, HttpsClient, u64)>,
     interval: u64,
+    timeout: u64,
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> super::Source {
     let out = out.sink_map_err(|e| error!("error sending metric: {:?}", e));
+    let deadline = Duration::from_secs(timeout);
 
-    let task = Interval::new(Instant::now(), Duration::from_secs(interval))
+    let discovered = Arc::new(Mutex::new(Vec::new()));
+    let discovery_task: Box<dyn Future<Item = (), Error = ()> + Send> = match discovery {
+        Some((discovery, client, refresh_interval)) => {
+            let discovered = Arc::clone(&discovered);
+            let refresh_interval = Duration::from_secs(refresh_interval.max(1));
+            Box::new(
+                Interval::new(Instant::now(), refresh_interval)
+                    .map_err(|e| error!("timer error: {:?}", e))
+                    .take_until(shutdown.clone())
+                    .for_each(move |_| {
+                        let discovered = Arc::clone(&discovered);
+                        discovery.discover(&client).then(move |result| {
+                            match result {
+                                Ok(targets) => {
+                                    emit!(PrometheusTargetsDiscovered {
+                                        count: targets.len()
+                                    });
+                                    *discovered.lock().unwrap() = targets;
+                                }
+                                // The targets discovered previously are kept.
+                                Err(error) => emit!(PrometheusKubernetesDiscoveryError { error }),
+                            }
+                            Ok(())
+                        })
+                    }),
+            )
+        }
+        None => Box::new(future::ok(())),
+    };
+
+    let task = scheduler::interval(Duration::from_secs(interval))
         .map_err(|e| error!("timer error: {:?}", e))
         .take_until(shutdown)
-        .map(move |_| futures01::stream::iter_ok(urls.clone()))
+        .map(move |_| {
+            let mut targets = static_targets.clone();
+            targets.extend(discovered.lock().unwrap().iter().cloned());
+            futures01::stream::iter_ok(targets)
+        })
         .flatten()
-        .map(move |url| {
+        .map(move |target| {
             let https = HttpsConnector::new(4).expect("TLS initialization failed");
             let client = hyper::Client::builder().build(https);
 
+            let Target { url, tags } = target;
             let request = hyper::Request::get(&url)
                 .body(hyper::Body::empty())
                 .expect("error creating request");
 
-            client
+            let scrape = client
                 .request(request)
-                .and_then(|response| response.into_body().concat2())
+                .and_then(|response| response.into_body().concat2());
+
+            scheduler::scrape("prometheus", deadline, scrape)
                 .map(|body| {
+                    // Skipped scrapes have no metrics.
+                    let body = match body {
+                        Some(body) => body,
+                        None => return futures01::stream::iter_ok(Vec::new()),
+                    };
                     emit!(PrometheusRequestCompleted);
 
                     let packet = String::from_utf8_lossy(&body);
@@ -86,7 +185,16 @@ fn prometheus(
                         })
                         .unwrap_or_default()
                         .into_iter()
-                        .map(Event::Metric);
+                        .map(move |mut metric| {
+                            if !tags.is_empty() {
+                                metric
+                                    .tags
+                                    .get_or_insert_with(BTreeMap::new)
+                                    .extend(tags.clone());
+                            }
+                            Event::Metric(metric)
+                        })
+                        .collect::<Vec<_>>();
 
                     futures01::stream::iter_ok(metrics)
                 })
@@ -99,7 +207,7 @@ fn prometheus(
         .forward(out)
         .map(|_| info!("finished sending"));
 
-    Box::new(task)
+    Box::new(task.join(discovery_task).map(|_| ()))
 }
 
 #[cfg(feature = "sinks-prometheus")]
@@ -168,6 +276,8 @@ mod test {
             PrometheusConfig {
                 hosts: vec![format!("http://{}", in_addr)],
                 scrape_interval_secs: 1,
+                scrape_timeout_secs: None,
+                kubernetes: None,
             },
         );
         config.add_sink(
@@ -182,7 +292,7 @@ mod test {
         );
 
         let (topology, _crash) = topology::start(config, &mut rt, false).unwrap();
-        thread::sleep(Duration::from_secs(1));
+        thread::sleep(Duration::from_secs(2));
 
         let client = hyper::Client::new();
         let response =
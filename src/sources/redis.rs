@@ -0,0 +1,427 @@
+use crate::{
+    event::{self, Event},
+    internal_events::{RedisMessageReceived, RedisReceiveFailed},
+    shutdown::ShutdownSignal,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use futures::future::{FutureExt, TryFutureExt};
+use futures01::{sync::mpsc, Future, Sink};
+use redis::{
+    streams::{StreamClaimReply, StreamId, StreamPendingCountReply, StreamReadReply},
+    Connection, RedisError, RedisResult, Value,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::spawn_blocking;
+
+/// How long reading blocks waiting for a message before checking for
+/// shutdown.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`group` must be set to read a stream"))]
+    MissingGroup,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RedisSourceConfig {
+    /// A `redis://` URL.
+    pub endpoint: String,
+    /// The list or stream read.
+    pub key: String,
+    #[serde(default)]
+    pub mode: Mode,
+    /// The consumer group reading the stream, created when missing.
+    pub group: Option<String>,
+    /// Names this instance in the consumer group.
+    #[serde(default = "default_consumer")]
+    pub consumer: String,
+    /// How many stream entries are read at once.
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Entries delivered to other consumers and left unacknowledged for
+    /// this long are claimed, as their consumer likely failed.
+    #[serde(default = "default_claim_idle_ms")]
+    pub claim_idle_ms: u64,
+    #[serde(default = "default_key_field")]
+    pub key_field: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// `BLPOP` from a list.
+    List,
+    /// `XREADGROUP` from a stream.
+    Stream,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::List
+    }
+}
+
+fn default_consumer() -> String {
+    hostname::get_hostname().unwrap_or_else(|| "vector".into())
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_claim_idle_ms() -> u64 {
+    60_000
+}
+
+fn default_key_field() -> String {
+    "redis_key".into()
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<RedisSourceConfig>("redis")
+}
+
+#[typetag::serde(name = "redis")]
+impl SourceConfig for RedisSourceConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        if self.mode == Mode::Stream && self.group.is_none() {
+            return Err(Box::new(BuildError::MissingGroup));
+        }
+        let client = redis::Client::open(self.endpoint.as_str())?;
+
+        let config = self.clone();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&stopped);
+        Ok(Box::new(
+            async move {
+                // The client is blocking, so it runs on its own thread,
+                // which sees the shutdown through `stopped`.
+                match spawn_blocking(move || redis_source(config, client, stopped, out)).await {
+                    Ok(result) => result,
+                    Err(error) => {
+                        error!(message = "redis source unexpectedly stopped.", %error);
+                        Err(())
+                    }
+                }
+            }
+            .boxed()
+            .compat()
+            .select(shutdown.map(move |_| stop.store(true, Ordering::Release)))
+            .map(|_| ())
+            .map_err(|_| ()),
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "redis"
+    }
+}
+
+/// Why reading stopped.
+enum ReadError {
+    Redis(RedisError),
+    PipelineClosed,
+}
+
+impl From<RedisError> for ReadError {
+    fn from(error: RedisError) -> Self {
+        ReadError::Redis(error)
+    }
+}
+
+fn redis_source(
+    config: RedisSourceConfig,
+    client: redis::Client,
+    stopped: Arc<AtomicBool>,
+    out: mpsc::Sender<Event>,
+) -> Result<(), ()> {
+    let mut out = out.wait();
+    while !stopped.load(Ordering::Acquire) {
+        let result = client
+            .get_connection_with_timeout(POLL_TIMEOUT)
+            .map_err(ReadError::from)
+            .and_then(|mut connection| {
+                info!(message = "reading.", key = %config.key);
+                match config.mode {
+                    Mode::List => read_list(&config, &mut connection, &stopped, &mut out),
+                    Mode::Stream => read_stream(&config, &mut connection, &stopped, &mut out),
+                }
+            });
+        match result {
+            Ok(()) => (),
+            Err(ReadError::PipelineClosed) => {
+                error!(message = "error sending event.");
+                return Err(());
+            }
+            Err(ReadError::Redis(error)) => {
+                emit!(RedisReceiveFailed { error });
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn send(
+    out: &mut futures01::sink::Wait<mpsc::Sender<Event>>,
+    event: Event,
+) -> Result<(), ReadError> {
+    out.send(event)
+        .and_then(|()| out.flush())
+        .map_err(|_| ReadError::PipelineClosed)
+}
+
+/// Pops the messages of the list, which are gone from Redis once popped.
+fn read_list(
+    config: &RedisSourceConfig,
+    connection: &mut Connection,
+    stopped: &AtomicBool,
+    out: &mut futures01::sink::Wait<mpsc::Sender<Event>>,
+) -> Result<(), ReadError> {
+    while !stopped.load(Ordering::Acquire) {
+        let popped: Option<(String, Vec<u8>)> = redis::cmd("BLPOP")
+            .arg(&config.key)
+            .arg(POLL_TIMEOUT.as_secs())
+            .query(connection)?;
+        if let Some((key, payload)) = popped {
+            emit!(RedisMessageReceived {
+                key: &key,
+                byte_size: payload.len(),
+            });
+            let mut event = Event::from(Bytes::from(payload));
+            let log = event.as_mut_log();
+            log.insert(event::log_schema().source_type_key(), "redis");
+            log.insert(config.key_field.as_str(), key);
+            send(out, event)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the stream as a member of the consumer group, acknowledging the
+/// entries once their events are sent downstream.
+fn read_stream(
+    config: &RedisSourceConfig,
+    connection: &mut Connection,
+    stopped: &AtomicBool,
+    out: &mut futures01::sink::Wait<mpsc::Sender<Event>>,
+) -> Result<(), ReadError> {
+    let group = config.group.as_ref().expect("checked on build");
+    create_group(connection, &config.key, group)?;
+
+    // The entries delivered to this consumer before it restarted are
+    // read first, from `0`, then the new ones, from `>`.
+    let mut position = "0";
+    let mut claimed_at = Instant::now();
+    while !stopped.load(Ordering::Acquire) {
+        let mut read = redis::cmd("XREADGROUP");
+        read.arg("GROUP")
+            .arg(group)
+            .arg(&config.consumer)
+            .arg("COUNT")
+            .arg(config.batch_size);
+        if position == ">" {
+            read.arg("BLOCK").arg(POLL_TIMEOUT.as_millis() as u64);
+        }
+        let reply: Option<StreamReadReply> = read
+            .arg("STREAMS")
+            .arg(&config.key)
+            .arg(position)
+            .query(connection)?;
+        let entries = reply
+            .into_iter()
+            .flat_map(|reply| reply.keys)
+            .flat_map(|key| key.ids)
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            position = ">";
+        }
+        forward(config, connection, group, entries, out)?;
+
+        let claim_idle = Duration::from_millis(config.claim_idle_ms);
+        if claimed_at.elapsed() >= claim_idle {
+            let entries = claim(config, connection, group)?;
+            forward(config, connection, group, entries, out)?;
+            claimed_at = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+fn create_group(connection: &mut Connection, key: &str, group: &str) -> RedisResult<()> {
+    let created = redis::cmd("XGROUP")
+        .arg("CREATE")
+        .arg(key)
+        .arg(group)
+        .arg("$")
+        .arg("MKSTREAM")
+        .query::<()>(connection);
+    match created {
+        // The group exists already.
+        Err(error) if error.code() == Some("BUSYGROUP") => Ok(()),
+        result => result,
+    }
+}
+
+/// Claims the entries left unacknowledged by other consumers for
+/// `claim_idle_ms`.
+fn claim(
+    config: &RedisSourceConfig,
+    connection: &mut Connection,
+    group: &str,
+) -> RedisResult<Vec<StreamId>> {
+    let pending: StreamPendingCountReply = redis::cmd("XPENDING")
+        .arg(&config.key)
+        .arg(group)
+        .arg("-")
+        .arg("+")
+        .arg(config.batch_size)
+        .query(connection)?;
+    let ids = pending
+        .ids
+        .iter()
+        .filter(|pending| {
+            pending.consumer != config.consumer
+                && pending.last_delivered_ms as u64 >= config.claim_idle_ms
+        })
+        .map(|pending| pending.id.clone())
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let claimed: StreamClaimReply = redis::cmd("XCLAIM")
+        .arg(&config.key)
+        .arg(group)
+        .arg(&config.consumer)
+        .arg(config.claim_idle_ms)
+        .arg(&ids)
+        .query(connection)?;
+    Ok(claimed.ids)
+}
+
+fn forward(
+    config: &RedisSourceConfig,
+    connection: &mut Connection,
+    group: &str,
+    entries: Vec<StreamId>,
+    out: &mut futures01::sink::Wait<mpsc::Sender<Event>>,
+) -> Result<(), ReadError> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let ids = entries
+        .iter()
+        .map(|entry| entry.id.clone())
+        .collect::<Vec<_>>();
+    for entry in entries {
+        send(out, create_event(config, entry))?;
+    }
+    redis::cmd("XACK")
+        .arg(&config.key)
+        .arg(group)
+        .arg(&ids)
+        .query::<()>(connection)?;
+    Ok(())
+}
+
+/// The fields of the entry become fields of the event, which is
+/// timestamped with the time the entry was added.
+fn create_event(config: &RedisSourceConfig, entry: StreamId) -> Event {
+    let mut byte_size = 0;
+    let mut event = Event::new_empty_log();
+    let log = event.as_mut_log();
+    for (field, value) in entry.map {
+        let value = match value {
+            Value::Data(data) => data,
+            value => redis::from_redis_value::<String>(&value)
+                .map(String::into_bytes)
+                .unwrap_or_default(),
+        };
+        byte_size += value.len();
+        log.insert_flat(field, Bytes::from(value));
+    }
+    emit!(RedisMessageReceived {
+        key: &config.key,
+        byte_size,
+    });
+
+    let millis = entry
+        .id
+        .split('-')
+        .next()
+        .and_then(|millis| millis.parse::<i64>().ok());
+    let timestamp = millis
+        .map(|millis| Utc.timestamp_millis(millis))
+        .unwrap_or_else(Utc::now);
+    log.insert(event::log_schema().timestamp_key(), timestamp);
+    log.insert(event::log_schema().source_type_key(), "redis");
+    log.insert(config.key_field.as_str(), config.key.clone());
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn redis_creates_events_from_stream_entries() {
+        let config: RedisSourceConfig = toml::from_str(
+            r#"
+            endpoint = "redis://127.0.0.1:6379"
+            key = "logs"
+            mode = "stream"
+            group = "vector"
+            "#,
+        )
+        .unwrap();
+        let mut map = HashMap::new();
+        map.insert("message".to_owned(), Value::Data(b"hello".to_vec()));
+        map.insert("app".to_owned(), Value::Data(b"web".to_vec()));
+        let entry = StreamId {
+            id: "1588000000123-0".into(),
+            map,
+        };
+
+        let event = create_event(&config, entry);
+        let log = event.as_log();
+        assert_eq!(log[&event::log_schema().message_key()], "hello".into());
+        assert_eq!(log[&"app".into()], "web".into());
+        assert_eq!(log[&"redis_key".into()], "logs".into());
+        assert_eq!(
+            log[&event::log_schema().timestamp_key()],
+            Utc.timestamp_millis(1_588_000_000_123).into()
+        );
+
+        let config = RedisSourceConfig {
+            group: None,
+            ..config
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        let result = config.build("in", &GlobalOptions::default(), ShutdownSignal::noop(), tx);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! The subset of the Basic Encoding Rules of ASN.1 that SNMP messages use.
+
+use snafu::Snafu;
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+pub const INTEGER: u8 = 0x02;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+pub const IP_ADDRESS: u8 = 0x40;
+pub const COUNTER32: u8 = 0x41;
+pub const GAUGE32: u8 = 0x42;
+pub const TIME_TICKS: u8 = 0x43;
+pub const OPAQUE: u8 = 0x44;
+pub const COUNTER64: u8 = 0x46;
+pub const NO_SUCH_OBJECT: u8 = 0x80;
+pub const NO_SUCH_INSTANCE: u8 = 0x81;
+pub const END_OF_MIB_VIEW: u8 = 0x82;
+
+#[derive(Debug, Snafu, PartialEq)]
+pub enum BerError {
+    #[snafu(display("Message ends within a value"))]
+    Truncated,
+    #[snafu(display("Expected tag {:#04x}, found {:#04x}", expected, found))]
+    UnexpectedTag { expected: u8, found: u8 },
+    #[snafu(display("Unsupported length encoding"))]
+    InvalidLength,
+    #[snafu(display("Integer does not fit in 64 bits"))]
+    IntegerOverflow,
+    #[snafu(display("Invalid object identifier"))]
+    InvalidOid,
+}
+
+/// A value as encoded, with the position of its content in the message.
+#[derive(Debug, Clone, Copy)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    pub offset: usize,
+}
+
+/// Reads the values of a message, or of a constructed value, in order.
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    /// The position of `data` in the message.
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn read_any(&mut self) -> Result<Tlv<'a>, BerError> {
+        let tag = *self.data.first().ok_or(BerError::Truncated)?;
+        let (length, header) = match *self.data.get(1).ok_or(BerError::Truncated)? {
+            length if length < 0x80 => (length as usize, 2),
+            0x80 => return Err(BerError::InvalidLength),
+            long => {
+                let bytes = (long & 0x7f) as usize;
+                if bytes > 4 {
+                    return Err(BerError::InvalidLength);
+                }
+                let length = self
+                    .data
+                    .get(2..2 + bytes)
+                    .ok_or(BerError::Truncated)?
+                    .iter()
+                    .fold(0, |length, byte| length << 8 | *byte as usize);
+                (length, 2 + bytes)
+            }
+        };
+        let content = self
+            .data
+            .get(header..header + length)
+            .ok_or(BerError::Truncated)?;
+        let tlv = Tlv {
+            tag,
+            content,
+            offset: self.offset + header,
+        };
+        self.data = &self.data[header + length..];
+        self.offset += header + length;
+        Ok(tlv)
+    }
+
+    pub fn read(&mut self, tag: u8) -> Result<Tlv<'a>, BerError> {
+        let tlv = self.read_any()?;
+        if tlv.tag != tag {
+            return Err(BerError::UnexpectedTag {
+                expected: tag,
+                found: tlv.tag,
+            });
+        }
+        Ok(tlv)
+    }
+
+    /// Reads a constructed value, returning a reader of what it contains.
+    pub fn constructed(&mut self, tag: u8) -> Result<Reader<'a>, BerError> {
+        self.read(tag).map(Reader::from)
+    }
+
+    pub fn integer(&mut self) -> Result<i64, BerError> {
+        decode_integer(self.read(INTEGER)?.content)
+    }
+
+    pub fn octet_string(&mut self) -> Result<&'a [u8], BerError> {
+        Ok(self.read(OCTET_STRING)?.content)
+    }
+
+    pub fn oid(&mut self) -> Result<Oid, BerError> {
+        decode_oid(self.read(OBJECT_IDENTIFIER)?.content)
+    }
+
+    pub fn value(&mut self) -> Result<SnmpValue, BerError> {
+        let tlv = self.read_any()?;
+        Ok(match tlv.tag {
+            INTEGER => SnmpValue::Integer(decode_integer(tlv.content)?),
+            OCTET_STRING => SnmpValue::OctetString(tlv.content.to_vec()),
+            NULL => SnmpValue::Null,
+            OBJECT_IDENTIFIER => SnmpValue::Oid(decode_oid(tlv.content)?),
+            IP_ADDRESS => match tlv.content {
+                [a, b, c, d] => SnmpValue::IpAddress(Ipv4Addr::new(*a, *b, *c, *d)),
+                _ => SnmpValue::OctetString(tlv.content.to_vec()),
+            },
+            COUNTER32 | GAUGE32 | COUNTER64 => SnmpValue::Unsigned(decode_unsigned(tlv.content)?),
+            TIME_TICKS => SnmpValue::TimeTicks(decode_unsigned(tlv.content)?),
+            OPAQUE => SnmpValue::OctetString(tlv.content.to_vec()),
+            NO_SUCH_OBJECT | NO_SUCH_INSTANCE | END_OF_MIB_VIEW => SnmpValue::Null,
+            // Unknown application types are kept as their bytes.
+            _ => SnmpValue::OctetString(tlv.content.to_vec()),
+        })
+    }
+}
+
+impl<'a> From<Tlv<'a>> for Reader<'a> {
+    fn from(tlv: Tlv<'a>) -> Self {
+        Self {
+            data: tlv.content,
+            offset: tlv.offset,
+        }
+    }
+}
+
+pub fn decode_integer(content: &[u8]) -> Result<i64, BerError> {
+    if content.is_empty() {
+        return Err(BerError::Truncated);
+    }
+    if content.len() > 8 {
+        return Err(BerError::IntegerOverflow);
+    }
+    let negative = content[0] & 0x80 != 0;
+    let initial = if negative { -1 } else { 0 };
+    Ok(content
+        .iter()
+        .fold(initial, |value, byte| value << 8 | *byte as i64))
+}
+
+pub fn decode_unsigned(content: &[u8]) -> Result<u64, BerError> {
+    // A leading zero keeps values with the high bit set positive.
+    let content = match content {
+        [0, rest @ ..] => rest,
+        content => content,
+    };
+    if content.len() > 8 {
+        return Err(BerError::IntegerOverflow);
+    }
+    Ok(content
+        .iter()
+        .fold(0, |value, byte| value << 8 | *byte as u64))
+}
+
+pub fn decode_oid(content: &[u8]) -> Result<Oid, BerError> {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for (i, byte) in content.iter().enumerate() {
+        arc = arc << 7 | (byte & 0x7f) as u64;
+        if arc > u32::max_value() as u64 {
+            return Err(BerError::InvalidOid);
+        }
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                // The first two arcs share the first subidentifier.
+                let first = (arc / 40).min(2);
+                arcs.push(first as u32);
+                arcs.push((arc - first * 40) as u32);
+            } else {
+                arcs.push(arc as u32);
+            }
+            arc = 0;
+        } else if i == content.len() - 1 {
+            return Err(BerError::InvalidOid);
+        }
+    }
+    if arcs.is_empty() {
+        return Err(BerError::InvalidOid);
+    }
+    Ok(Oid(arcs))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Oid(pub Vec<u32>);
+
+impl fmt::Display for Oid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut arcs = self.0.iter();
+        if let Some(arc) = arcs.next() {
+            write!(f, "{}", arc)?;
+        }
+        for arc in arcs {
+            write!(f, ".{}", arc)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Oid {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim_start_matches('.')
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Oid)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnmpValue {
+    Integer(i64),
+    OctetString(Vec<u8>),
+    Null,
+    Oid(Oid),
+    IpAddress(Ipv4Addr),
+    /// Counters and gauges.
+    Unsigned(u64),
+    TimeTicks(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_values() {
+        let data = [
+            0x30, 0x16, // SEQUENCE
+            0x02, 0x02, 0xff, 0x7f, // INTEGER -129
+            0x06, 0x06, 0x2b, 0x06, 0x01, 0x82, 0x37, 0x01, // OID 1.3.6.1.311.1
+            0x41, 0x05, 0x00, 0xff, 0xff, 0xff, 0xff, // Counter32 4294967295
+            0x04, 0x01, 0x61, // OCTET STRING "a"
+        ];
+
+        let mut sequence = Reader::new(&data).constructed(SEQUENCE).unwrap();
+        assert_eq!(sequence.integer(), Ok(-129));
+        assert_eq!(sequence.oid().unwrap().to_string(), "1.3.6.1.311.1");
+        assert_eq!(
+            sequence.value(),
+            Ok(SnmpValue::Unsigned(u32::max_value() as u64))
+        );
+        let string = sequence.read(OCTET_STRING).unwrap();
+        assert_eq!((string.content, string.offset), (&b"a"[..], 23));
+        assert!(sequence.is_empty());
+
+        assert_eq!(
+            Reader::new(&[0x04, 0x05, 0x61]).octet_string(),
+            Err(BerError::Truncated)
+        );
+    }
+}
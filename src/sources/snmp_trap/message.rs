@@ -0,0 +1,479 @@
+//! Decoding of the SNMPv1, v2c and v3 messages carrying notifications.
+
+use super::{
+    ber::{self, BerError, Oid, Reader, SnmpValue},
+    usm::{self, AuthProtocol, PrivProtocol},
+};
+use snafu::Snafu;
+use std::{collections::HashMap, net::Ipv4Addr};
+
+const TRAP_V1: u8 = 0xa4;
+const INFORM_REQUEST: u8 = 0xa6;
+const TRAP_V2: u8 = 0xa7;
+
+const FLAG_AUTH: u8 = 0x01;
+const FLAG_PRIV: u8 = 0x02;
+const USER_BASED_SECURITY_MODEL: i64 = 3;
+
+#[derive(Debug, Snafu)]
+pub enum DecodeError {
+    #[snafu(display("Malformed message: {}", source))]
+    Malformed { source: BerError },
+    #[snafu(display("Unsupported SNMP version {}", version))]
+    UnsupportedVersion { version: i64 },
+    #[snafu(display("Unsupported PDU type {:#04x}", tag))]
+    UnsupportedPdu { tag: u8 },
+    #[snafu(display("Unsupported security model {}", model))]
+    UnsupportedSecurityModel { model: i64 },
+    #[snafu(display("Unknown community {:?}", community))]
+    UnknownCommunity { community: String },
+    #[snafu(display("Unknown user {:?}", user))]
+    UnknownUser { user: String },
+    #[snafu(display("Message of user {:?} is not {}", user, missing))]
+    SecurityLevel { user: String, missing: &'static str },
+    #[snafu(display("Authentication of user {:?} failed", user))]
+    AuthenticationFailed { user: String },
+    #[snafu(display("Unable to decrypt the message of user {:?}", user))]
+    DecryptionFailed { user: String },
+}
+
+impl From<BerError> for DecodeError {
+    fn from(source: BerError) -> Self {
+        Self::Malformed { source }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Version {
+    V1,
+    V2c,
+    V3,
+}
+
+impl Version {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V1 => "1",
+            Self::V2c => "2c",
+            Self::V3 => "3",
+        }
+    }
+}
+
+/// A trap, or an inform, in the form of SNMPv2 notifications.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub version: Version,
+    pub community: Option<String>,
+    pub user: Option<String>,
+    pub context_name: Option<String>,
+    /// The agent SNMPv1 traps are from, which may be behind a proxy.
+    pub agent_address: Option<Ipv4Addr>,
+    pub uptime: Option<u64>,
+    pub trap_oid: Option<Oid>,
+    pub variables: Vec<(Oid, SnmpValue)>,
+}
+
+/// The keys of a user, derived from their passwords once.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub auth: Option<(AuthProtocol, Vec<u8>)>,
+    pub privacy: Option<(PrivProtocol, Vec<u8>)>,
+}
+
+/// The keys of a user, localized to an engine.
+#[derive(Debug, Clone)]
+struct LocalizedKeys {
+    auth: Vec<u8>,
+    privacy: Option<Vec<u8>>,
+}
+
+/// Decodes messages, keeping the keys localized to each engine sending them.
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    users: HashMap<String, User>,
+    localized: HashMap<(String, Vec<u8>), LocalizedKeys>,
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+impl Decoder {
+    pub fn new(users: HashMap<String, User>) -> Self {
+        Self {
+            users,
+            localized: HashMap::new(),
+        }
+    }
+
+    pub fn decode(&mut self, message: &[u8]) -> Result<Notification, DecodeError> {
+        let mut reader = Reader::new(message).constructed(ber::SEQUENCE)?;
+        match reader.integer()? {
+            0 => {
+                let community = string(reader.octet_string()?);
+                let pdu = reader.read_any()?;
+                if pdu.tag != TRAP_V1 {
+                    return Err(DecodeError::UnsupportedPdu { tag: pdu.tag });
+                }
+                let mut notification = decode_v1_pdu(pdu.into())?;
+                notification.community = Some(community);
+                Ok(notification)
+            }
+            1 => {
+                let community = string(reader.octet_string()?);
+                let mut notification = decode_v2_pdu(reader, Version::V2c)?;
+                notification.community = Some(community);
+                Ok(notification)
+            }
+            3 => self.decode_v3(message, reader),
+            version => Err(DecodeError::UnsupportedVersion { version }),
+        }
+    }
+
+    fn decode_v3(
+        &mut self,
+        message: &[u8],
+        mut reader: Reader<'_>,
+    ) -> Result<Notification, DecodeError> {
+        let mut global = reader.constructed(ber::SEQUENCE)?;
+        let _id = global.integer()?;
+        let _max_size = global.integer()?;
+        let flags = global.octet_string()?.first().copied().unwrap_or(0);
+        let model = global.integer()?;
+        if model != USER_BASED_SECURITY_MODEL {
+            return Err(DecodeError::UnsupportedSecurityModel { model });
+        }
+
+        let mut security =
+            Reader::from(reader.read(ber::OCTET_STRING)?).constructed(ber::SEQUENCE)?;
+        let engine_id = security.octet_string()?;
+        let engine_boots = security.integer()? as u32;
+        let engine_time = security.integer()? as u32;
+        let user_name = string(security.octet_string()?);
+        let auth_params = security.read(ber::OCTET_STRING)?;
+        let priv_params = security.octet_string()?;
+
+        let user = self
+            .users
+            .get(&user_name)
+            .ok_or_else(|| DecodeError::UnknownUser {
+                user: user_name.clone(),
+            })?;
+        let level_error = |missing| DecodeError::SecurityLevel {
+            user: user_name.clone(),
+            missing,
+        };
+        // Users with keys only accept messages protected with them.
+        if user.auth.is_some() && flags & FLAG_AUTH == 0 {
+            return Err(level_error("authenticated"));
+        }
+        if user.privacy.is_some() && flags & FLAG_PRIV == 0 {
+            return Err(level_error("encrypted"));
+        }
+
+        let plaintext;
+        let mut scoped = if flags & FLAG_AUTH == 0 {
+            reader.constructed(ber::SEQUENCE)?
+        } else {
+            let protocol = match &user.auth {
+                Some((protocol, _)) => *protocol,
+                None => return Err(DecodeError::AuthenticationFailed { user: user_name }),
+            };
+            let keys = localize(&mut self.localized, &user_name, user, engine_id);
+
+            let mut zeroed = message.to_vec();
+            let mac = auth_params.offset..auth_params.offset + auth_params.content.len();
+            zeroed[mac].iter_mut().for_each(|byte| *byte = 0);
+            let authenticated = usm::authenticate(protocol, &keys.auth, &zeroed)
+                .map_or(false, |expected| {
+                    usm::verify(&expected, auth_params.content)
+                });
+            if !authenticated {
+                return Err(DecodeError::AuthenticationFailed { user: user_name });
+            }
+
+            if flags & FLAG_PRIV == 0 {
+                reader.constructed(ber::SEQUENCE)?
+            } else {
+                let decrypted = match (&user.privacy, &keys.privacy) {
+                    (Some((protocol, _)), Some(key)) => usm::decrypt(
+                        *protocol,
+                        key,
+                        engine_boots,
+                        engine_time,
+                        priv_params,
+                        reader.octet_string()?,
+                    ),
+                    _ => None,
+                };
+                plaintext = decrypted.ok_or(DecodeError::DecryptionFailed {
+                    user: user_name.clone(),
+                })?;
+                Reader::new(&plaintext).constructed(ber::SEQUENCE)?
+            }
+        };
+
+        let _context_engine_id = scoped.octet_string()?;
+        let context_name = string(scoped.octet_string()?);
+        let mut notification = decode_v2_pdu(scoped, Version::V3)?;
+        notification.user = Some(user_name);
+        if !context_name.is_empty() {
+            notification.context_name = Some(context_name);
+        }
+        Ok(notification)
+    }
+}
+
+fn localize<'a>(
+    localized: &'a mut HashMap<(String, Vec<u8>), LocalizedKeys>,
+    user_name: &str,
+    user: &User,
+    engine_id: &[u8],
+) -> &'a LocalizedKeys {
+    localized
+        .entry((user_name.to_owned(), engine_id.to_vec()))
+        .or_insert_with(|| {
+            let (protocol, key) = user.auth.as_ref().expect("authenticated user");
+            let localize = |key| usm::localize_key(*protocol, key, engine_id).unwrap_or_default();
+            LocalizedKeys {
+                auth: localize(key),
+                privacy: user.privacy.as_ref().map(|(_, key)| localize(key)),
+            }
+        })
+}
+
+fn decode_v2_pdu(mut reader: Reader<'_>, version: Version) -> Result<Notification, DecodeError> {
+    let pdu = reader.read_any()?;
+    if pdu.tag != TRAP_V2 && pdu.tag != INFORM_REQUEST {
+        return Err(DecodeError::UnsupportedPdu { tag: pdu.tag });
+    }
+    let mut pdu = Reader::from(pdu);
+    let _request_id = pdu.integer()?;
+    let _error_status = pdu.integer()?;
+    let _error_index = pdu.integer()?;
+
+    let mut notification = Notification {
+        version,
+        community: None,
+        user: None,
+        context_name: None,
+        agent_address: None,
+        uptime: None,
+        trap_oid: None,
+        variables: Vec::new(),
+    };
+    let uptime_oid = Oid(vec![1, 3, 6, 1, 2, 1, 1, 3, 0]);
+    let trap_oid = Oid(vec![1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0]);
+    for (oid, value) in decode_variables(pdu.constructed(ber::SEQUENCE)?)? {
+        match value {
+            SnmpValue::TimeTicks(ticks) if oid == uptime_oid => notification.uptime = Some(ticks),
+            SnmpValue::Oid(trap) if oid == trap_oid => notification.trap_oid = Some(trap),
+            value => notification.variables.push((oid, value)),
+        }
+    }
+    Ok(notification)
+}
+
+/// SNMPv1 traps are translated as RFC 3584 describes.
+fn decode_v1_pdu(mut pdu: Reader<'_>) -> Result<Notification, DecodeError> {
+    let enterprise = pdu.oid()?;
+    let agent_address = match pdu.value()? {
+        SnmpValue::IpAddress(address) => Some(address),
+        _ => None,
+    };
+    let generic = pdu.integer()?;
+    let specific = pdu.integer()?;
+    let uptime = match pdu.value()? {
+        SnmpValue::TimeTicks(ticks) => Some(ticks),
+        _ => None,
+    };
+    let variables = decode_variables(pdu.constructed(ber::SEQUENCE)?)?;
+
+    let trap_oid = if (0..6).contains(&generic) {
+        Oid(vec![1, 3, 6, 1, 6, 3, 1, 1, 5, generic as u32 + 1])
+    } else {
+        let mut oid = enterprise;
+        oid.0.extend(&[0, specific as u32]);
+        oid
+    };
+
+    Ok(Notification {
+        version: Version::V1,
+        community: None,
+        user: None,
+        context_name: None,
+        agent_address,
+        uptime,
+        trap_oid: Some(trap_oid),
+        variables,
+    })
+}
+
+fn decode_variables(mut reader: Reader<'_>) -> Result<Vec<(Oid, SnmpValue)>, BerError> {
+    let mut variables = Vec::new();
+    while !reader.is_empty() {
+        let mut binding = reader.constructed(ber::SEQUENCE)?;
+        variables.push((binding.oid()?, binding.value()?));
+    }
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![tag];
+        if content.len() < 0x80 {
+            encoded.push(content.len() as u8);
+        } else {
+            encoded.extend(&[0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        encoded.extend(content);
+        encoded
+    }
+
+    fn sequence(values: &[Vec<u8>]) -> Vec<u8> {
+        tlv(ber::SEQUENCE, &values.concat())
+    }
+
+    fn v2_pdu() -> Vec<u8> {
+        let variables = sequence(&[
+            sequence(&[
+                tlv(ber::OBJECT_IDENTIFIER, &[0x2b, 6, 1, 2, 1, 1, 3, 0]),
+                tlv(ber::TIME_TICKS, &[0x01, 0x00]),
+            ]),
+            sequence(&[
+                tlv(ber::OBJECT_IDENTIFIER, &[0x2b, 6, 1, 6, 3, 1, 1, 4, 1, 0]),
+                tlv(ber::OBJECT_IDENTIFIER, &[0x2b, 6, 1, 6, 3, 1, 1, 5, 3]),
+            ]),
+            sequence(&[
+                tlv(ber::OBJECT_IDENTIFIER, &[0x2b, 6, 1, 2, 1, 2, 2, 1, 1, 2]),
+                tlv(ber::INTEGER, &[2]),
+            ]),
+        ]);
+        tlv(
+            TRAP_V2,
+            &[
+                tlv(ber::INTEGER, &[7]),
+                tlv(ber::INTEGER, &[0]),
+                tlv(ber::INTEGER, &[0]),
+                variables,
+            ]
+            .concat(),
+        )
+    }
+
+    fn assert_link_down(notification: &Notification) {
+        assert_eq!(notification.uptime, Some(256));
+        assert_eq!(
+            notification.trap_oid,
+            Some("1.3.6.1.6.3.1.1.5.3".parse().unwrap())
+        );
+        assert_eq!(
+            notification.variables,
+            vec![(
+                "1.3.6.1.2.1.2.2.1.1.2".parse().unwrap(),
+                SnmpValue::Integer(2)
+            )]
+        );
+    }
+
+    #[test]
+    fn decodes_v2c_traps() {
+        let message = sequence(&[
+            tlv(ber::INTEGER, &[1]),
+            tlv(ber::OCTET_STRING, b"public"),
+            v2_pdu(),
+        ]);
+
+        let notification = Decoder::default().decode(&message).unwrap();
+        assert_eq!(notification.version, Version::V2c);
+        assert_eq!(notification.community, Some("public".into()));
+        assert_link_down(&notification);
+    }
+
+    fn v3_message(
+        engine_id: &[u8],
+        flags: u8,
+        auth_params: &[u8],
+        priv_params: &[u8],
+        data: Vec<u8>,
+    ) -> Vec<u8> {
+        let security = sequence(&[
+            tlv(ber::OCTET_STRING, engine_id),
+            tlv(ber::INTEGER, &[1]),
+            tlv(ber::INTEGER, &[2]),
+            tlv(ber::OCTET_STRING, b"alice"),
+            tlv(ber::OCTET_STRING, auth_params),
+            tlv(ber::OCTET_STRING, priv_params),
+        ]);
+        sequence(&[
+            tlv(ber::INTEGER, &[3]),
+            sequence(&[
+                tlv(ber::INTEGER, &[1]),
+                tlv(ber::INTEGER, &[0x05, 0xdc]),
+                tlv(ber::OCTET_STRING, &[flags]),
+                tlv(ber::INTEGER, &[3]),
+            ]),
+            tlv(ber::OCTET_STRING, &security),
+            data,
+        ])
+    }
+
+    #[test]
+    fn decodes_authenticated_and_encrypted_v3_traps() {
+        let engine_id = [0x80, 0, 0x1f, 0x88, 0x04, 1, 2, 3];
+        let auth_key = usm::password_to_key(AuthProtocol::Sha, b"authpassword").unwrap();
+        let priv_key = usm::password_to_key(AuthProtocol::Sha, b"privpassword").unwrap();
+        let mut users = HashMap::new();
+        users.insert(
+            "alice".to_owned(),
+            User {
+                auth: Some((AuthProtocol::Sha, auth_key.clone())),
+                privacy: Some((PrivProtocol::Aes, priv_key.clone())),
+            },
+        );
+        let mut decoder = Decoder::new(users);
+
+        let scoped = sequence(&[
+            tlv(ber::OCTET_STRING, &engine_id),
+            tlv(ber::OCTET_STRING, b"ctx"),
+            v2_pdu(),
+        ]);
+        let salt = [9; 8];
+        let priv_key = usm::localize_key(AuthProtocol::Sha, &priv_key, &engine_id).unwrap();
+        let mut iv = vec![0, 0, 0, 1, 0, 0, 0, 2];
+        iv.extend(&salt);
+        let encrypted = openssl::symm::encrypt(
+            openssl::symm::Cipher::aes_128_cfb128(),
+            &priv_key[..16],
+            Some(&iv),
+            &scoped,
+        )
+        .unwrap();
+        let data = tlv(ber::OCTET_STRING, &encrypted);
+
+        let mut message = v3_message(&engine_id, 0x03, &[0; 12], &salt, data);
+        let auth_key = usm::localize_key(AuthProtocol::Sha, &auth_key, &engine_id).unwrap();
+        let mac = usm::authenticate(AuthProtocol::Sha, &auth_key, &message).unwrap();
+        let offset = message
+            .windows(12)
+            .position(|window| window == [0; 12])
+            .unwrap();
+        message[offset..offset + 12].copy_from_slice(&mac);
+
+        let notification = decoder.decode(&message).unwrap();
+        assert_eq!(notification.version, Version::V3);
+        assert_eq!(notification.user, Some("alice".into()));
+        assert_eq!(notification.context_name, Some("ctx".into()));
+        assert_link_down(&notification);
+
+        message[offset] ^= 1;
+        assert!(matches!(
+            decoder.decode(&message),
+            Err(DecodeError::AuthenticationFailed { .. })
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+//! Names of object identifiers, read from the definitions of MIB modules.
+//!
+//! Only the `::=` assignments placing objects in the tree are read, the
+//! syntax and descriptions of objects are ignored.
+
+use super::ber::Oid;
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Snafu)]
+pub enum MibError {
+    #[snafu(display("Unable to read MIB file {:?}: {}", path, source))]
+    ReadFile { path: PathBuf, source: io::Error },
+}
+
+/// Names every trap carries, known without loading the SNMPv2-MIB.
+const BUILTIN: &[(&str, &str)] = &[
+    ("iso", "1"),
+    ("org", "1.3"),
+    ("dod", "1.3.6"),
+    ("internet", "1.3.6.1"),
+    ("mgmt", "1.3.6.1.2"),
+    ("mib-2", "1.3.6.1.2.1"),
+    ("system", "1.3.6.1.2.1.1"),
+    ("sysDescr", "1.3.6.1.2.1.1.1"),
+    ("sysObjectID", "1.3.6.1.2.1.1.2"),
+    ("sysUpTime", "1.3.6.1.2.1.1.3"),
+    ("sysName", "1.3.6.1.2.1.1.5"),
+    ("ifIndex", "1.3.6.1.2.1.2.2.1.1"),
+    ("ifDescr", "1.3.6.1.2.1.2.2.1.2"),
+    ("ifAdminStatus", "1.3.6.1.2.1.2.2.1.7"),
+    ("ifOperStatus", "1.3.6.1.2.1.2.2.1.8"),
+    ("private", "1.3.6.1.4"),
+    ("enterprises", "1.3.6.1.4.1"),
+    ("snmpV2", "1.3.6.1.6"),
+    ("snmpModules", "1.3.6.1.6.3"),
+    ("snmpTrapOID", "1.3.6.1.6.3.1.1.4.1"),
+    ("snmpTrapEnterprise", "1.3.6.1.6.3.1.1.4.3"),
+    ("snmpTraps", "1.3.6.1.6.3.1.1.5"),
+    ("coldStart", "1.3.6.1.6.3.1.1.5.1"),
+    ("warmStart", "1.3.6.1.6.3.1.1.5.2"),
+    ("linkDown", "1.3.6.1.6.3.1.1.5.3"),
+    ("linkUp", "1.3.6.1.6.3.1.1.5.4"),
+    ("authenticationFailure", "1.3.6.1.6.3.1.1.5.5"),
+];
+
+/// The macros whose invocations assign an object identifier to a name.
+const MACROS: &[&str] = &[
+    "AGENT-CAPABILITIES",
+    "MODULE-COMPLIANCE",
+    "MODULE-IDENTITY",
+    "NOTIFICATION-GROUP",
+    "NOTIFICATION-TYPE",
+    "OBJECT-GROUP",
+    "OBJECT-IDENTITY",
+    "OBJECT-TYPE",
+    "TRAP-TYPE",
+];
+
+#[derive(Debug, Clone)]
+pub struct Mib {
+    names: BTreeMap<Oid, String>,
+}
+
+/// An assignment of a name, relative to a parent named or not.
+#[derive(Debug, PartialEq)]
+struct Definition {
+    parent: Option<String>,
+    arcs: Vec<u32>,
+}
+
+impl Default for Mib {
+    fn default() -> Self {
+        let names = BUILTIN
+            .iter()
+            .map(|(name, oid)| (oid.parse().expect("valid builtin OID"), name.to_string()))
+            .collect();
+        Self { names }
+    }
+}
+
+impl Mib {
+    /// Loads the MIB files, and every file of the directories, given. The
+    /// definitions may refer to names defined in any of them, in any order.
+    pub fn load(paths: &[PathBuf]) -> Result<Self, MibError> {
+        let mut definitions = HashMap::new();
+        for path in paths {
+            let files = if path.is_dir() {
+                let mut files = fs::read_dir(path)
+                    .context(ReadFile { path })?
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.is_file())
+                    .collect::<Vec<_>>();
+                files.sort();
+                files
+            } else {
+                vec![path.clone()]
+            };
+            for file in files {
+                definitions.extend(read_file(&file)?);
+            }
+        }
+
+        let mut mib = Self::default();
+        mib.resolve(definitions);
+        Ok(mib)
+    }
+
+    /// Places the definitions in the tree, dropping those whose ancestors
+    /// aren't known.
+    fn resolve(&mut self, mut definitions: HashMap<String, Definition>) {
+        let mut oids = self
+            .names
+            .iter()
+            .map(|(oid, name)| (name.clone(), oid.clone()))
+            .collect::<HashMap<_, _>>();
+        oids.insert("ccitt".into(), Oid(vec![0]));
+        oids.insert("joint-iso-ccitt".into(), Oid(vec![2]));
+
+        loop {
+            let resolved = definitions
+                .iter()
+                .filter_map(|(name, definition)| {
+                    let mut oid = match &definition.parent {
+                        Some(parent) => oids.get(parent)?.clone(),
+                        None => Oid(Vec::new()),
+                    };
+                    oid.0.extend(&definition.arcs);
+                    Some((name.clone(), oid))
+                })
+                .collect::<Vec<_>>();
+            if resolved.is_empty() {
+                break;
+            }
+            for (name, oid) in resolved {
+                definitions.remove(&name);
+                self.names.insert(oid.clone(), name.clone());
+                oids.insert(name, oid);
+            }
+        }
+
+        if !definitions.is_empty() {
+            let mut unresolved = definitions.keys().cloned().collect::<Vec<_>>();
+            unresolved.sort();
+            warn!(
+                message = "Some MIB definitions refer to unknown objects, are all the MIB files they import loaded?",
+                unresolved = ?unresolved
+            );
+        }
+    }
+
+    /// The name of the closest ancestor known, followed by the arcs of the
+    /// object below it, e.g. `ifDescr.3`. Objects without a known ancestor
+    /// keep their numeric form.
+    pub fn name(&self, oid: &Oid) -> String {
+        for length in (1..=oid.0.len()).rev() {
+            let prefix = Oid(oid.0[..length].to_vec());
+            if let Some(name) = self.names.get(&prefix) {
+                let mut name = name.clone();
+                for arc in &oid.0[length..] {
+                    name.push_str(&format!(".{}", arc));
+                }
+                return name;
+            }
+        }
+        oid.to_string()
+    }
+}
+
+fn read_file(path: &Path) -> Result<HashMap<String, Definition>, MibError> {
+    let bytes = fs::read(path).context(ReadFile { path })?;
+    Ok(parse(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Splits the module in words and punctuation, leaving out comments and
+/// quoted strings, since descriptions could contain anything.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '"' {
+            rest = match rest[1..].find('"') {
+                Some(end) => &rest[end + 2..],
+                None => "",
+            };
+        } else if rest.starts_with("--") {
+            rest = match rest.find('\n') {
+                Some(end) => &rest[end..],
+                None => "",
+            };
+        } else if rest.starts_with("::=") {
+            tokens.push(&rest[..3]);
+            rest = &rest[3..];
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or_else(|| rest.len());
+            tokens.push(&rest[..end]);
+            rest = &rest[end..];
+        } else {
+            tokens.push(&rest[..c.len_utf8()]);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    tokens
+}
+
+fn is_name(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+fn parse(text: &str) -> HashMap<String, Definition> {
+    let tokens = tokenize(text);
+    let mut definitions = HashMap::new();
+    // The name being defined, and the enterprise of SMIv1 traps.
+    let mut name = None;
+    let mut enterprise = None;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        let previous = if i > 0 { tokens[i - 1] } else { "" };
+        let next = tokens.get(i + 1).copied().unwrap_or("");
+
+        if (MACROS.contains(&token) || (token == "OBJECT" && next == "IDENTIFIER"))
+            && is_name(previous)
+        {
+            name = Some(previous);
+            enterprise = None;
+        } else if token == "ENTERPRISE" && is_name(next) {
+            enterprise = Some(next);
+        } else if token == "::=" {
+            if let Some(defined) = name.take() {
+                if next == "{" {
+                    let end = tokens[i..]
+                        .iter()
+                        .position(|token| *token == "}")
+                        .map_or(tokens.len(), |end| i + end);
+                    if let Some(definition) = parse_value(&tokens[i + 2..end]) {
+                        definitions.insert(defined.to_owned(), definition);
+                    }
+                    i = end;
+                } else if let (Some(enterprise), Ok(number)) = (enterprise, next.parse()) {
+                    // SMIv1 traps are numbered within their enterprise.
+                    definitions.insert(
+                        defined.to_owned(),
+                        Definition {
+                            parent: Some(enterprise.to_owned()),
+                            arcs: vec![0, number],
+                        },
+                    );
+                }
+            }
+        }
+        i += 1;
+    }
+    definitions
+}
+
+/// Reads values such as `{ ifEntry 2 }` or `{ iso org(3) dod(6) }`.
+fn parse_value(tokens: &[&str]) -> Option<Definition> {
+    let (parent, mut rest) = match tokens.split_first()? {
+        (first, rest) if is_name(first) => (Some(first.to_string()), rest),
+        _ => (None, tokens),
+    };
+    let mut arcs = Vec::new();
+    while let Some((token, remaining)) = rest.split_first() {
+        rest = remaining;
+        if let Ok(arc) = token.parse() {
+            arcs.push(arc);
+        } else if is_name(token) && rest.get(0) == Some(&"(") {
+            arcs.push(rest.get(1)?.parse().ok()?);
+            rest = rest.get(3..)?;
+        } else {
+            return None;
+        }
+    }
+    Some(Definition { parent, arcs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_definitions_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("ACME-MIB.txt"),
+            r#"
+            ACME-MIB DEFINITIONS ::= BEGIN
+            IMPORTS MODULE-IDENTITY, OBJECT-TYPE, NOTIFICATION-TYPE FROM SNMPv2-SMI;
+
+            acmeMIB MODULE-IDENTITY
+                DESCRIPTION "Not ::= { a 1 } -- really"
+                ::= { acme 1 }
+
+            -- acmeNothing OBJECT IDENTIFIER ::= { acmeMIB 9 }
+            acmeTraps OBJECT IDENTIFIER ::= { acmeMIB 0 }
+
+            acmeDiskFull NOTIFICATION-TYPE
+                OBJECTS { acmeDiskName }
+                STATUS current
+                ::= { acmeTraps 1 }
+
+            acmeDiskName OBJECT-TYPE
+                SYNTAX OCTET STRING
+                ::= { acmeMIB 2 }
+
+            acmeOldTrap TRAP-TYPE
+                ENTERPRISE acme
+                VARIABLES { acmeDiskName }
+                ::= 7
+            END
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("ACME-SMI.txt"),
+            "acme OBJECT IDENTIFIER ::= { iso org(3) dod(6) internet(1) private(4) enterprises(1) 99999 }",
+        )
+        .unwrap();
+
+        let mib = Mib::load(&[dir.path().to_owned()]).unwrap();
+        let name = |oid: &str| mib.name(&oid.parse().unwrap());
+        assert_eq!(name("1.3.6.1.4.1.99999.1.0.1"), "acmeDiskFull");
+        assert_eq!(name("1.3.6.1.4.1.99999.1.2.0"), "acmeDiskName.0");
+        assert_eq!(name("1.3.6.1.4.1.99999.0.7"), "acmeOldTrap");
+        assert_eq!(name("1.3.6.1.4.1.99999.1.9"), "acmeMIB.9");
+        assert_eq!(name("1.3.6.1.6.3.1.1.5.3"), "linkDown");
+        assert_eq!(name("2.5"), "2.5");
+    }
+}
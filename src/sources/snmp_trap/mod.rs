@@ -0,0 +1,351 @@
+use crate::{
+    event::{self, Event, LogEvent, PathComponent, Value},
+    internal_events::{SnmpTrapDecodeFailed, SnmpTrapEventReceived, SnmpTrapUdpReadError},
+    shutdown::ShutdownSignal,
+    stream::StreamExt,
+    topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
+};
+use ber::SnmpValue;
+use chrono::Utc;
+use futures01::{future, sync::mpsc, Future, Sink, Stream};
+use message::{Decoder, Notification, User};
+use mib::Mib;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
+use tokio01::{
+    codec::BytesCodec,
+    net::{UdpFramed, UdpSocket},
+};
+use tracing::field;
+use usm::{AuthProtocol, PrivProtocol};
+
+mod ber;
+mod message;
+mod mib;
+mod usm;
+
+pub use message::DecodeError;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SnmpTrapConfig {
+    pub address: SocketAddr,
+    /// The communities SNMPv1 and SNMPv2c traps are accepted from, any if
+    /// empty.
+    #[serde(default)]
+    pub communities: Vec<String>,
+    /// The users SNMPv3 traps are accepted from.
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    /// MIB files, or directories of them, naming the objects of traps.
+    #[serde(default)]
+    pub mib_paths: Vec<PathBuf>,
+    pub host_key: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    pub name: String,
+    #[serde(default)]
+    pub auth_protocol: AuthProtocol,
+    /// Traps of the user must be authenticated with it if set.
+    pub auth_password: Option<String>,
+    #[serde(default)]
+    pub priv_protocol: PrivProtocol,
+    /// Traps of the user must be encrypted with it if set.
+    pub priv_password: Option<String>,
+}
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Password of user {:?} is shorter than 8 characters", user))]
+    PasswordTooShort { user: String },
+    #[snafu(display("User {:?} has a priv_password but no auth_password", user))]
+    PrivacyWithoutAuth { user: String },
+    #[snafu(display("User {:?} is configured twice", user))]
+    DuplicateUser { user: String },
+}
+
+inventory::submit! {
+    SourceDescription::new_without_default::<SnmpTrapConfig>("snmp_trap")
+}
+
+#[typetag::serde(name = "snmp_trap")]
+impl SourceConfig for SnmpTrapConfig {
+    fn build(
+        &self,
+        _name: &str,
+        _globals: &GlobalOptions,
+        shutdown: ShutdownSignal,
+        out: mpsc::Sender<Event>,
+    ) -> crate::Result<super::Source> {
+        let mut users = HashMap::new();
+        for user in &self.users {
+            let built = user.build()?;
+            if users.insert(user.name.clone(), built).is_some() {
+                return Err(BuildError::DuplicateUser {
+                    user: user.name.clone(),
+                }
+                .into());
+            }
+        }
+        let mib = Mib::load(&self.mib_paths)?;
+        let host_key = self
+            .host_key
+            .clone()
+            .unwrap_or_else(|| event::log_schema().host_key().to_string());
+
+        Ok(udp(
+            self.address,
+            Decoder::new(users),
+            self.communities.clone(),
+            mib,
+            host_key,
+            shutdown,
+            out,
+        ))
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn source_type(&self) -> &'static str {
+        "snmp_trap"
+    }
+}
+
+impl UserConfig {
+    fn build(&self) -> crate::Result<User> {
+        let key = |password: &str| -> crate::Result<Vec<u8>> {
+            // RFC 3414 requires as much, short passwords being easy to guess.
+            if password.len() < 8 {
+                return Err(BuildError::PasswordTooShort {
+                    user: self.name.clone(),
+                }
+                .into());
+            }
+            Ok(usm::password_to_key(
+                self.auth_protocol,
+                password.as_bytes(),
+            )?)
+        };
+
+        let auth = match &self.auth_password {
+            Some(password) => Some((self.auth_protocol, key(password)?)),
+            None => None,
+        };
+        let privacy = match (&self.priv_password, &auth) {
+            (Some(password), Some(_)) => Some((self.priv_protocol, key(password)?)),
+            (Some(_), None) => {
+                return Err(BuildError::PrivacyWithoutAuth {
+                    user: self.name.clone(),
+                }
+                .into())
+            }
+            (None, _) => None,
+        };
+        Ok(User { auth, privacy })
+    }
+}
+
+fn udp(
+    address: SocketAddr,
+    mut decoder: Decoder,
+    communities: Vec<String>,
+    mib: Mib,
+    host_key: String,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> super::Source {
+    let out = out.sink_map_err(|e| error!("error sending event: {:?}", e));
+
+    Box::new(
+        future::lazy(move || {
+            let socket = UdpSocket::bind(&address).expect("failed to bind to udp listener socket");
+
+            info!(
+                message = "listening.",
+                addr = &field::display(address),
+                r#type = "udp"
+            );
+
+            future::ok(socket)
+        })
+        .and_then(move |socket| {
+            UdpFramed::new(socket, BytesCodec::new())
+                .take_until(shutdown)
+                .filter_map(move |(bytes, received_from)| {
+                    emit!(SnmpTrapEventReceived {
+                        byte_size: bytes.len()
+                    });
+                    let notification = match decoder.decode(&bytes) {
+                        Ok(notification) => notification,
+                        Err(error) => {
+                            emit!(SnmpTrapDecodeFailed { error });
+                            return None;
+                        }
+                    };
+                    if let Some(community) = &notification.community {
+                        if !communities.is_empty() && !communities.contains(community) {
+                            emit!(SnmpTrapDecodeFailed {
+                                error: DecodeError::UnknownCommunity {
+                                    community: community.clone()
+                                }
+                            });
+                            return None;
+                        }
+                    }
+                    Some(build_event(
+                        notification,
+                        &mib,
+                        &host_key,
+                        received_from.ip().to_string(),
+                    ))
+                })
+                .map_err(|error| emit!(SnmpTrapUdpReadError { error }))
+                .forward(out)
+                .map(|_| info!("finished sending"))
+        }),
+    )
+}
+
+/// One event per trap, named after the trap, with the variables it carried
+/// keyed by their names.
+fn build_event(notification: Notification, mib: &Mib, host_key: &str, host: String) -> Event {
+    let mut log = LogEvent::new();
+
+    let trap_oid = notification.trap_oid.as_ref();
+    let trap = trap_oid.map_or_else(|| "unknown".to_owned(), |oid| mib.name(oid));
+    log.insert(event::log_schema().message_key().clone(), trap);
+    log.insert(event::log_schema().timestamp_key().clone(), Utc::now());
+    log.insert(event::log_schema().source_type_key().clone(), "snmp_trap");
+    log.insert(host_key, host);
+    log.insert("version", notification.version.as_str());
+    if let Some(oid) = trap_oid {
+        log.insert("trap_oid", oid.to_string());
+    }
+    if let Some(uptime) = notification.uptime {
+        log.insert("uptime", uptime as i64);
+    }
+    if let Some(community) = notification.community {
+        log.insert("community", community);
+    }
+    if let Some(user) = notification.user {
+        log.insert("user", user);
+    }
+    if let Some(context_name) = notification.context_name {
+        log.insert("context_name", context_name);
+    }
+    if let Some(agent_address) = notification.agent_address {
+        log.insert("agent_address", agent_address.to_string());
+    }
+
+    // Names such as `ifDescr.3` are keys of their own, not nested fields.
+    for (oid, value) in notification.variables {
+        let path = vec![
+            PathComponent::Key("variables".into()),
+            PathComponent::Key(mib.name(&oid)),
+        ];
+        log.insert_path(path, to_value(value, mib));
+    }
+
+    Event::Log(log)
+}
+
+fn to_value(value: SnmpValue, mib: &Mib) -> Value {
+    match value {
+        SnmpValue::Integer(integer) => integer.into(),
+        SnmpValue::OctetString(bytes) => match String::from_utf8(bytes) {
+            Ok(string)
+                if string
+                    .chars()
+                    .all(|c| !c.is_control() || c.is_ascii_whitespace()) =>
+            {
+                string.into()
+            }
+            // Binary strings, such as MAC addresses, are shown in hex.
+            Err(error) => hex(error.as_bytes()).into(),
+            Ok(string) => hex(string.as_bytes()).into(),
+        },
+        SnmpValue::Null => Value::Null,
+        SnmpValue::Oid(oid) => mib.name(&oid).into(),
+        SnmpValue::IpAddress(address) => address.to_string().into(),
+        SnmpValue::Unsigned(unsigned) | SnmpValue::TimeTicks(unsigned) => (unsigned as i64).into(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{collect_n, next_addr, runtime};
+    use std::net::UdpSocket as StdUdpSocket;
+
+    #[test]
+    fn snmp_trap_receives_v1_traps() {
+        let address = next_addr();
+        let config: SnmpTrapConfig = toml::from_str(&format!(
+            r#"
+            address = "{}"
+            communities = ["public"]
+            "#,
+            address
+        ))
+        .unwrap();
+
+        let mut rt = runtime();
+        let (tx, rx) = mpsc::channel(10);
+        let source = config
+            .build(
+                "default",
+                &GlobalOptions::default(),
+                ShutdownSignal::noop(),
+                tx,
+            )
+            .unwrap();
+        rt.spawn(source);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // A linkDown trap of community "public", then of "private".
+        let trap = |community: &[u8]| {
+            let mut message = vec![0x30, 0x00, 0x02, 0x01, 0x00, 0x04, community.len() as u8];
+            message.extend(community);
+            message.extend(&[
+                0xa4, 0x2c, // Trap-PDU
+                0x06, 0x08, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x86, 0x8d, 0x1f, // enterprise
+                0x40, 0x04, 0x0a, 0x00, 0x00, 0x01, // agent-addr
+                0x02, 0x01, 0x02, // generic-trap
+                0x02, 0x01, 0x00, // specific-trap
+                0x43, 0x01, 0x2a, // time-stamp
+                0x30, 0x11, 0x30, 0x0f, // variable-bindings
+                0x06, 0x0a, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x02, 0x03, 0x04, 0x01,
+                0x78, // ifDescr.3 = "x"
+            ]);
+            message[1] = message.len() as u8 - 2;
+            message
+        };
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(&trap(b"private"), address).unwrap();
+        socket.send_to(&trap(b"public"), address).unwrap();
+
+        let events = rt.block_on(collect_n(rx, 1)).unwrap();
+        let log = events[0].as_log();
+        assert_eq!(log[&event::log_schema().message_key()], "linkDown".into());
+        assert_eq!(log[&"trap_oid".into()], "1.3.6.1.6.3.1.1.5.3".into());
+        assert_eq!(log[&"version".into()], "1".into());
+        assert_eq!(log[&"community".into()], "public".into());
+        assert_eq!(log[&"agent_address".into()], "10.0.0.1".into());
+        assert_eq!(log[&"uptime".into()], 42.into());
+        assert_eq!(log[&"variables.ifDescr\\.3".into()], "x".into());
+    }
+}
@@ -0,0 +1,170 @@
+//! The User-based Security Model of SNMPv3, from RFC 3414 and RFC 3826, as
+//! needed to authenticate and decrypt the traps users send.
+
+use openssl::{
+    error::ErrorStack,
+    hash::{Hasher, MessageDigest},
+    memcmp,
+    pkey::PKey,
+    sign::Signer,
+    symm::{Cipher, Crypter, Mode},
+};
+use serde::{Deserialize, Serialize};
+
+/// The length of the authentication parameters of a message.
+pub const MAC_LENGTH: usize = 12;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthProtocol {
+    Md5,
+    Sha,
+}
+
+impl Default for AuthProtocol {
+    fn default() -> Self {
+        Self::Sha
+    }
+}
+
+impl AuthProtocol {
+    fn digest(self) -> MessageDigest {
+        match self {
+            Self::Md5 => MessageDigest::md5(),
+            Self::Sha => MessageDigest::sha1(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivProtocol {
+    Des,
+    Aes,
+}
+
+impl Default for PrivProtocol {
+    fn default() -> Self {
+        Self::Aes
+    }
+}
+
+/// Derives the key of a password, by hashing a megabyte of it repeated.
+pub fn password_to_key(protocol: AuthProtocol, password: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut hasher = Hasher::new(protocol.digest())?;
+    let mut block = [0; 64];
+    let mut index = 0;
+    for _ in 0..(1024 * 1024 / block.len()) {
+        for byte in block.iter_mut() {
+            *byte = password[index % password.len()];
+            index += 1;
+        }
+        hasher.update(&block)?;
+    }
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// Localizes a key to the engine sending the messages.
+pub fn localize_key(
+    protocol: AuthProtocol,
+    key: &[u8],
+    engine_id: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let mut hasher = Hasher::new(protocol.digest())?;
+    hasher.update(key)?;
+    hasher.update(engine_id)?;
+    hasher.update(key)?;
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// The MAC of a whole message, with its authentication parameters zeroed.
+pub fn authenticate(
+    protocol: AuthProtocol,
+    key: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(protocol.digest(), &key)?;
+    signer.update(message)?;
+    let mut mac = signer.sign_to_vec()?;
+    mac.truncate(MAC_LENGTH);
+    Ok(mac)
+}
+
+pub fn verify(expected: &[u8], actual: &[u8]) -> bool {
+    expected.len() == actual.len() && memcmp::eq(expected, actual)
+}
+
+/// Decrypts the scoped PDU of a message, given the localized privacy key.
+/// The salt is the privacy parameters of the message.
+pub fn decrypt(
+    protocol: PrivProtocol,
+    key: &[u8],
+    engine_boots: u32,
+    engine_time: u32,
+    salt: &[u8],
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    if salt.len() != 8 {
+        return None;
+    }
+    let (cipher, key, iv) = match protocol {
+        PrivProtocol::Des => {
+            if key.len() < 16 || data.len() % 8 != 0 {
+                return None;
+            }
+            let iv = key[8..16]
+                .iter()
+                .zip(salt)
+                .map(|(pre_iv, salt)| pre_iv ^ salt)
+                .collect::<Vec<_>>();
+            (Cipher::des_cbc(), &key[..8], iv)
+        }
+        PrivProtocol::Aes => {
+            if key.len() < 16 {
+                return None;
+            }
+            let mut iv = Vec::with_capacity(16);
+            iv.extend_from_slice(&engine_boots.to_be_bytes());
+            iv.extend_from_slice(&engine_time.to_be_bytes());
+            iv.extend_from_slice(salt);
+            (Cipher::aes_128_cfb128(), &key[..16], iv)
+        }
+    };
+
+    let mut crypter = Crypter::new(cipher, Mode::Decrypt, key, Some(&iv)).ok()?;
+    crypter.pad(false);
+    let mut plaintext = vec![0; data.len() + cipher.block_size()];
+    let mut length = crypter.update(data, &mut plaintext).ok()?;
+    length += crypter.finalize(&mut plaintext[length..]).ok()?;
+    plaintext.truncate(length);
+    Some(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn localizes_keys_of_rfc_3414() {
+        let engine_id = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+        let key = password_to_key(AuthProtocol::Md5, b"maplesyrup").unwrap();
+        assert_eq!(
+            localize_key(AuthProtocol::Md5, &key, &engine_id).unwrap(),
+            [
+                0x52, 0x6f, 0x5e, 0xed, 0x9f, 0xcc, 0xe2, 0x6f, 0x89, 0x64, 0xc2, 0x93, 0x07, 0x87,
+                0xd8, 0x2b
+            ]
+        );
+
+        let key = password_to_key(AuthProtocol::Sha, b"maplesyrup").unwrap();
+        assert_eq!(
+            localize_key(AuthProtocol::Sha, &key, &engine_id).unwrap(),
+            [
+                0x66, 0x95, 0xfe, 0xbc, 0x92, 0x88, 0xe3, 0x62, 0x82, 0x23, 0x5f, 0xc7, 0x15, 0x1f,
+                0x12, 0x84, 0x97, 0xb3, 0x8f, 0x3f
+            ]
+        );
+    }
+}
@@ -3,7 +3,7 @@ mod udp;
 #[cfg(unix)]
 mod unix;
 
-use super::util::TcpSource;
+use super::util::{validate_shards, TcpSource};
 use crate::{
     event::{self, Event},
     shutdown::ShutdownSignal,
@@ -29,6 +29,25 @@ pub enum Mode {
     Udp(udp::UdpConfig),
     #[cfg(unix)]
     Unix(unix::UnixConfig),
+    #[cfg(unix)]
+    UnixDatagram(unix::UnixConfig),
+}
+
+/// How the messages received are turned into events.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Decoding {
+    /// Newline delimited text, one event per line.
+    Text,
+    /// GELF messages, delimited by null bytes over TCP and chunked or
+    /// compressed over UDP.
+    Gelf,
+}
+
+impl Default for Decoding {
+    fn default() -> Self {
+        Decoding::Text
+    }
 }
 
 impl SocketConfig {
@@ -85,16 +104,27 @@ impl SourceConfig for SocketConfig {
                     config.address,
                     config.shutdown_timeout_secs,
                     tls,
+                    config.access,
+                    config.tls_client_subject_key,
                     shutdown,
                     out,
                 )
             }
             Mode::Udp(config) => {
+                validate_shards(config.shards)?;
                 let host_key = config
                     .host_key
                     .clone()
                     .unwrap_or(event::log_schema().host_key().clone());
-                Ok(udp::udp(config.address, host_key, shutdown, out))
+                Ok(udp::udp(
+                    config.address,
+                    host_key,
+                    config.decoding,
+                    config.shards,
+                    config.access,
+                    shutdown,
+                    out,
+                ))
             }
             #[cfg(unix)]
             Mode::Unix(config) => {
@@ -104,6 +134,22 @@ impl SourceConfig for SocketConfig {
                     .unwrap_or(event::log_schema().host_key().to_string());
                 Ok(unix::unix(
                     config.path,
+                    config.permissions.build()?,
+                    config.max_length,
+                    host_key,
+                    shutdown,
+                    out,
+                ))
+            }
+            #[cfg(unix)]
+            Mode::UnixDatagram(config) => {
+                let host_key = config
+                    .host_key
+                    .clone()
+                    .unwrap_or(event::log_schema().host_key().to_string());
+                Ok(unix::unix_datagram(
+                    config.path,
+                    config.permissions.build()?,
                     config.max_length,
                     host_key,
                     shutdown,
@@ -634,6 +680,60 @@ mod test {
         );
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn udp_shards() {
+        let (tx, rx) = mpsc::channel(10);
+        let addr = next_addr();
+
+        let server = SocketConfig::from(UdpConfig {
+            shards: 4,
+            ..UdpConfig::new(addr)
+        })
+        .build(
+            "default",
+            &GlobalOptions::default(),
+            ShutdownSignal::noop(),
+            tx,
+        )
+        .unwrap();
+        let mut rt = runtime();
+        rt.spawn(server);
+        thread::sleep(Duration::from_millis(100));
+
+        // Each sender is likely hashed to a different shard.
+        for i in 0..8 {
+            send_lines_udp(addr, vec![format!("test{}", i)]);
+        }
+        let events = rt.block_on(collect_n(rx, 8)).ok().unwrap();
+
+        let mut messages = events
+            .iter()
+            .map(|event| event.as_log()[&event::log_schema().message_key()].to_string_lossy())
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            (0..8).map(|i| format!("test{}", i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn udp_zero_shards() {
+        let (tx, _rx) = mpsc::channel(10);
+        let result = SocketConfig::from(UdpConfig {
+            shards: 0,
+            ..UdpConfig::new(next_addr())
+        })
+        .build(
+            "default",
+            &GlobalOptions::default(),
+            ShutdownSignal::noop(),
+            tx,
+        );
+        assert!(result.is_err());
+    }
+
     #[test]
     fn udp_it_includes_host() {
         let (tx, rx) = mpsc::channel(2);
@@ -845,4 +945,48 @@ mod test {
             "test2".into()
         );
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_datagram_lines_and_shutdown() {
+        let source_name = "unix_datagram_lines_and_shutdown";
+        let (tx, rx) = mpsc::channel(10);
+        let path = tempfile::tempdir()
+            .unwrap()
+            .into_path()
+            .join("unix_datagram_test");
+
+        let mut shutdown = SourceShutdownCoordinator::new();
+        let (shutdown_signal, _) = shutdown.register_source(source_name);
+
+        let server = SocketConfig {
+            mode: super::Mode::UnixDatagram(UnixConfig::new(path.clone())),
+        }
+        .build(source_name, &GlobalOptions::default(), shutdown_signal, tx)
+        .unwrap();
+        let mut rt = runtime();
+        let source_handle = oneshot::spawn(server, &rt.executor());
+
+        // Sending fails until the source is bound.
+        let socket = std::os::unix::net::UnixDatagram::unbound().unwrap();
+        while socket.send_to(b"test\ntest2", &path).is_err() {}
+
+        let events = rt.block_on(collect_n(rx, 2)).ok().unwrap();
+        assert_eq!(
+            events[0].as_log()[&event::log_schema().message_key()],
+            "test".into()
+        );
+        assert_eq!(
+            events[1].as_log()[&event::log_schema().message_key()],
+            "test2".into()
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let shutdown_complete = shutdown.shutdown_source(source_name, deadline);
+        assert!(rt.block_on(shutdown_complete).unwrap());
+        rt.block_on(source_handle).unwrap();
+
+        // The socket file is removed along with the source.
+        assert!(!path.exists());
+    }
 }
@@ -1,7 +1,9 @@
+use super::Decoding;
 use crate::{
     event::{self, Event},
-    internal_events::TcpEventReceived,
-    sources::util::{SocketListenAddr, TcpSource},
+    gelf,
+    internal_events::{GelfDecodeFailed, TcpEventReceived},
+    sources::util::{AccessConfig, SocketListenAddr, TcpSource},
     tls::TlsConfig,
 };
 use bytes::Bytes;
@@ -20,6 +22,13 @@ pub struct TcpConfig {
     pub shutdown_timeout_secs: u64,
     pub host_key: Option<Atom>,
     pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub access: AccessConfig,
+    /// Where to put the subject of the certificate clients authenticated
+    /// with.
+    pub tls_client_subject_key: Option<Atom>,
+    #[serde(default)]
+    pub decoding: Decoding,
 }
 
 fn default_max_length() -> usize {
@@ -38,6 +47,9 @@ impl TcpConfig {
             host_key: None,
             shutdown_timeout_secs: default_shutdown_timeout_secs(),
             tls: Default::default(),
+            access: Default::default(),
+            tls_client_subject_key: None,
+            decoding: Decoding::default(),
         }
     }
 }
@@ -51,12 +63,25 @@ impl TcpSource for RawTcpSource {
     type Decoder = BytesDelimitedCodec;
 
     fn decoder(&self) -> Self::Decoder {
-        BytesDelimitedCodec::new_with_max_length(b'\n', self.config.max_length)
+        let delimiter = match self.config.decoding {
+            Decoding::Text => b'\n',
+            Decoding::Gelf => b'\0',
+        };
+        BytesDelimitedCodec::new_with_max_length(delimiter, self.config.max_length)
     }
 
     fn build_event(&self, frame: Bytes, host: Bytes) -> Option<Event> {
         let byte_size = frame.len();
-        let mut event = Event::from(frame);
+        let mut event = match self.config.decoding {
+            Decoding::Text => Event::from(frame),
+            Decoding::Gelf => match gelf::decode(&frame) {
+                Ok(log) => Event::from(log),
+                Err(error) => {
+                    emit!(GelfDecodeFailed { mode: "tcp", error });
+                    return None;
+                }
+            },
+        };
 
         event
             .as_mut_log()
@@ -68,7 +93,8 @@ impl TcpSource for RawTcpSource {
             &event::log_schema().host_key()
         };
 
-        event.as_mut_log().insert(host_key.clone(), host);
+        // GELF messages name the host they were sent from.
+        event.as_mut_log().try_insert(host_key, host);
 
         trace!(
             message = "Received one event.",
@@ -1,8 +1,13 @@
+use super::Decoding;
 use crate::{
     event::{self, Event},
-    internal_events::{UdpEventReceived, UdpSocketError},
+    gelf::{self, ChunkAssembler},
+    internal_events::{GelfDecodeFailed, UdpEventReceived, UdpShardEventReceived, UdpSocketError},
     shutdown::ShutdownSignal,
-    sources::Source,
+    sources::{
+        util::{bind_udp_shards, default_shards, spawn_udp_shards, AccessConfig},
+        Source,
+    },
     stream::StreamExt,
 };
 use bytes::Bytes;
@@ -11,6 +16,7 @@ use futures01::{future, sync::mpsc, Future, Sink, Stream};
 use serde::{Deserialize, Serialize};
 use std::{io, net::SocketAddr};
 use string_cache::DefaultAtom as Atom;
+use tokio01::codec::BytesCodec;
 use tokio01::net::udp::{UdpFramed, UdpSocket};
 
 /// UDP processes messages per packet, where messages are separated by newline.
@@ -19,6 +25,14 @@ use tokio01::net::udp::{UdpFramed, UdpSocket};
 pub struct UdpConfig {
     pub address: SocketAddr,
     pub host_key: Option<Atom>,
+    /// The number of sockets bound to the address, each read by its own
+    /// task.
+    #[serde(default = "default_shards")]
+    pub shards: usize,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub decoding: Decoding,
 }
 
 impl UdpConfig {
@@ -26,6 +40,9 @@ impl UdpConfig {
         Self {
             address,
             host_key: None,
+            shards: default_shards(),
+            access: Default::default(),
+            decoding: Decoding::default(),
         }
     }
 }
@@ -33,6 +50,9 @@ impl UdpConfig {
 pub fn udp(
     address: SocketAddr,
     host_key: Atom,
+    decoding: Decoding,
+    shards: usize,
+    access: AccessConfig,
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> Source {
@@ -40,40 +60,77 @@ pub fn udp(
 
     Box::new(
         future::lazy(move || {
-            let socket = UdpSocket::bind(&address).expect("failed to bind to udp listener socket");
+            let sockets =
+                bind_udp_shards(&address, shards).expect("failed to bind to udp listener socket");
 
-            info!(message = "listening.", %address);
+            info!(message = "listening.", %address, shards = shards as u64);
 
-            Ok(socket)
+            Ok(sockets)
         })
-        .and_then(move |socket| {
-            let host_key = host_key.clone();
-            // UDP processes messages per packet, where messages are separated by newline.
-            // And stretch to end of packet.
-            UdpFramed::with_decode(socket, BytesDelimitedCodec::new(b'\n'), true)
-                .take_until(shutdown)
-                .map(move |(line, addr): (Bytes, _)| {
-                    let byte_size = line.len();
-                    let mut event = Event::from(line);
+        .and_then(move |sockets| {
+            spawn_udp_shards(sockets, move |shard, socket: UdpSocket| {
+                let host_key = host_key.clone();
+                let access = access.clone();
+                let frames: Box<dyn Stream<Item = (Bytes, SocketAddr), Error = io::Error> + Send> =
+                    match decoding {
+                        // UDP processes messages per packet, where messages are separated by newline.
+                        // And stretch to end of packet.
+                        Decoding::Text => Box::new(UdpFramed::with_decode(
+                            socket,
+                            BytesDelimitedCodec::new(b'\n'),
+                            true,
+                        )),
+                        // A GELF message is a whole packet, or the chunks of several.
+                        Decoding::Gelf => {
+                            let mut assembler = ChunkAssembler::default();
+                            Box::new(UdpFramed::new(socket, BytesCodec::new()).filter_map(
+                                move |(datagram, addr)| {
+                                    assembler
+                                        .push(datagram.freeze())
+                                        .map(|message| (message, addr))
+                                },
+                            ))
+                        }
+                    };
+                frames
+                    .take_until(shutdown.clone())
+                    .filter(move |(_, addr)| access.admits(*addr))
+                    .filter_map(move |(frame, addr): (Bytes, SocketAddr)| {
+                        let byte_size = frame.len();
+                        let mut event = match decoding {
+                            Decoding::Text => Event::from(frame),
+                            Decoding::Gelf => match gelf::decode(&frame) {
+                                Ok(log) => Event::from(log),
+                                Err(error) => {
+                                    emit!(GelfDecodeFailed { mode: "udp", error });
+                                    return None;
+                                }
+                            },
+                        };
 
-                    event
-                        .as_mut_log()
-                        .insert(event::log_schema().source_type_key(), "socket");
+                        event
+                            .as_mut_log()
+                            .insert(event::log_schema().source_type_key(), "socket");
 
-                    event
-                        .as_mut_log()
-                        .insert(host_key.clone(), addr.to_string());
+                        // GELF messages name the host they were sent from.
+                        event.as_mut_log().try_insert(&host_key, addr.to_string());
 
-                    emit!(UdpEventReceived { byte_size });
-                    event
-                })
-                // Error from Decoder or UdpSocket
-                .map_err(|error: io::Error| {
-                    emit!(UdpSocketError { error });
-                })
-                .forward(out)
-                // Done with listening and sending
-                .map(|_| ())
+                        emit!(UdpEventReceived { byte_size });
+                        emit!(UdpShardEventReceived {
+                            component_type: "socket",
+                            shard,
+                            byte_size,
+                        });
+                        Some(event)
+                    })
+                    // Error from Decoder or UdpSocket
+                    .map_err(|error: io::Error| {
+                        emit!(UdpSocketError { error });
+                    })
+                    .forward(out.clone())
+                    // Done with listening and sending
+                    .map(|_| ())
+            })
         }),
     )
 }
@@ -1,8 +1,12 @@
 use crate::{
     event::{self, Event},
     internal_events::UnixSocketEventReceived,
+    permissions::{Permissions, PermissionsConfig},
     shutdown::ShutdownSignal,
-    sources::{util::build_unix_source, Source},
+    sources::{
+        util::{build_unix_datagram_source, build_unix_source},
+        Source,
+    },
 };
 use bytes::Bytes;
 use futures01::sync::mpsc;
@@ -16,6 +20,9 @@ pub struct UnixConfig {
     #[serde(default = "default_max_length")]
     pub max_length: usize,
     pub host_key: Option<String>,
+    /// Given to the socket once bound.
+    #[serde(default)]
+    pub permissions: PermissionsConfig,
 }
 
 fn default_max_length() -> usize {
@@ -28,6 +35,7 @@ impl UnixConfig {
             path,
             max_length: default_max_length(),
             host_key: None,
+            permissions: PermissionsConfig::default(),
         }
     }
 }
@@ -51,10 +59,38 @@ fn build_event(host_key: &str, received_from: Option<Bytes>, line: &str) -> Opti
 
 pub fn unix(
     path: PathBuf,
+    permissions: Permissions,
     max_length: usize,
     host_key: String,
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> Source {
-    build_unix_source(path, max_length, host_key, shutdown, out, build_event)
+    build_unix_source(
+        path,
+        permissions,
+        max_length,
+        host_key,
+        shutdown,
+        out,
+        build_event,
+    )
+}
+
+pub fn unix_datagram(
+    path: PathBuf,
+    permissions: Permissions,
+    max_length: usize,
+    host_key: String,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> Source {
+    build_unix_datagram_source(
+        path,
+        permissions,
+        max_length,
+        host_key,
+        shutdown,
+        out,
+        build_event,
+    )
 }
@@ -1,23 +1,33 @@
 use crate::{
     event::{self, Event, LogEvent, Value},
     shutdown::ShutdownSignal,
+    sources::util::{
+        retry_after_secs, AccessConfig, EventSender, Quota, QuotaConfig, SendError, WalConfig,
+    },
     tls::{MaybeTlsSettings, TlsConfig},
     topology::config::{DataType, GlobalOptions, SourceConfig},
 };
 use bytes::{Buf, Bytes};
 use chrono::{DateTime, TimeZone, Utc};
 use flate2::read::GzDecoder;
-use futures01::{sync::mpsc, Async, Future, Sink, Stream};
-use hyper::{Body, Response, StatusCode};
+use futures01::{sync::mpsc, Async, Future, Stream};
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    Body, Response, StatusCode,
+};
 use lazy_static::lazy_static;
 use serde::{de, Deserialize, Serialize};
 use serde_json::{de::IoRead, json, Deserializer, Value as JsonValue};
 use snafu::Snafu;
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
     io::Read,
     net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 use string_cache::DefaultAtom as Atom;
+use uuid::Uuid;
 use warp::{body::FullBody, filters::BoxedFilter, path, Filter, Rejection, Reply};
 
 // Event fields unique to splunk_hec source
@@ -38,6 +48,32 @@ pub struct SplunkConfig {
     /// Splunk HEC token
     token: Option<String>,
     tls: Option<TlsConfig>,
+    access: AccessConfig,
+    quota: Option<QuotaConfig>,
+    /// Records the events of each request before acknowledging it
+    wal: Option<WalConfig>,
+    acknowledgements: Option<AcknowledgementsConfig>,
+}
+
+/// Indexer acknowledgements, given for the requests whose events were
+/// accepted, so clients polling for them can tell.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AcknowledgementsConfig {
+    /// The oldest acknowledgements are dropped past this many not yet queried.
+    #[serde(default = "default_max_pending_acks_per_channel")]
+    max_pending_acks_per_channel: usize,
+    /// Channels not used for this long are forgotten.
+    #[serde(default = "default_max_idle_secs")]
+    max_idle_secs: u64,
+}
+
+fn default_max_pending_acks_per_channel() -> usize {
+    1_000_000
+}
+
+fn default_max_idle_secs() -> u64 {
+    600
 }
 
 impl SplunkConfig {
@@ -56,6 +92,10 @@ impl Default for SplunkConfig {
             address: default_socket_address(),
             token: None,
             tls: None,
+            access: AccessConfig::default(),
+            quota: None,
+            wal: None,
+            acknowledgements: None,
         }
     }
 }
@@ -68,16 +108,19 @@ fn default_socket_address() -> SocketAddr {
 impl SourceConfig for SplunkConfig {
     fn build(
         &self,
-        _: &str,
-        _: &GlobalOptions,
+        name: &str,
+        globals: &GlobalOptions,
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
         let source = SplunkSource::new(self);
+        let (sender, forward) =
+            EventSender::new(&self.wal, globals, name, out.clone(), shutdown.clone())?;
 
-        let event_service = source.event_service(out.clone());
-        let raw_service = source.raw_service(out.clone());
-        let health_service = source.health_service(out.clone());
+        let event_service = source.event_service(sender.clone());
+        let raw_service = source.raw_service(sender);
+        let ack_service = source.ack_service();
+        let health_service = source.health_service(out);
         let options = SplunkSource::options();
 
         let services = path!("services" / "collector")
@@ -85,6 +128,8 @@ impl SourceConfig for SplunkConfig {
                 event_service
                     .or(raw_service)
                     .unify()
+                    .or(ack_service)
+                    .unify()
                     .or(health_service)
                     .unify()
                     .or(options)
@@ -93,13 +138,21 @@ impl SourceConfig for SplunkConfig {
             .or_else(finish_err);
 
         let tls = MaybeTlsSettings::from_config(&self.tls, true)?;
-        let incoming = tls.bind(&self.address)?.incoming();
+        let incoming = self
+            .access
+            .clone()
+            .filter_incoming(tls.bind(&self.address)?.incoming());
 
         let server = warp::serve(services)
             .serve_incoming_with_graceful_shutdown(incoming, shutdown.clone().map(|_| ()));
 
         // We should drop the last copy of ShutdownSignalToken only after the server has shut down.
-        Ok(Box::new(server.map(move |()| drop(shutdown))))
+        Ok(Box::new(
+            server
+                .map(move |()| drop(shutdown))
+                .join(forward)
+                .map(|_| ()),
+        ))
     }
 
     fn output_type(&self) -> DataType {
@@ -114,6 +167,8 @@ impl SourceConfig for SplunkConfig {
 /// Shared data for responding to requests.
 struct SplunkSource {
     credentials: Option<Bytes>,
+    quota: Option<Quota>,
+    acks: Option<Arc<Acks>>,
 }
 
 impl SplunkSource {
@@ -123,10 +178,17 @@ impl SplunkSource {
                 .token
                 .as_ref()
                 .map(|token| format!("Splunk {}", token).into()),
+            quota: config.quota.as_ref().map(Quota::new),
+            acks: config
+                .acknowledgements
+                .clone()
+                .map(|config| Arc::new(Acks::new(config))),
         }
     }
 
-    fn event_service(&self, out: mpsc::Sender<Event>) -> BoxedFilter<(Response<Body>,)> {
+    fn event_service(&self, out: EventSender) -> BoxedFilter<(Response<Body>,)> {
+        let quota = self.quota.clone();
+        let acks = self.acks.clone();
         warp::post2()
             .and(
                 warp::path::end()
@@ -134,7 +196,7 @@ impl SplunkSource {
                     .or(path!("event" / "1.0").and(warp::path::end())),
             )
             .and(self.authorization())
-            .and(warp::header::optional::<String>("x-splunk-request-channel"))
+            .and(self.channel(self.acks.is_some()))
             .and(warp::header::optional::<String>("host"))
             .and(self.gzip())
             .and(warp::body::concat())
@@ -145,61 +207,95 @@ impl SplunkSource {
                       host: Option<String>,
                       gzip: bool,
                       body: FullBody| {
+                    let byte_size = body.remaining();
+                    let acks = acks.clone();
+                    let ack_channel = channel.clone();
                     // Construct event parser
-                    if gzip {
-                        Box::new(
-                            EventStream::new(GzDecoder::new(body.reader()), channel, host)
-                                .forward(out.clone().sink_map_err(|_| ApiError::ServerShutdown))
-                                .map(|_| ()),
-                        )
-                            as Box<dyn Future<Item = (), Error = Rejection> + Send>
+                    let events = if gzip {
+                        parse_events(EventStream::new(
+                            GzDecoder::new(body.reader()),
+                            channel,
+                            host,
+                        ))
                     } else {
-                        Box::new(
-                            EventStream::new(body.reader(), channel, host)
-                                .forward(out.clone().sink_map_err(|_| ApiError::ServerShutdown))
-                                .map(|_| ()),
-                        )
-                            as Box<dyn Future<Item = (), Error = Rejection> + Send>
-                    }
+                        parse_events(EventStream::new(body.reader(), channel, host))
+                    };
+                    send_events(events, byte_size, &quota, &out)
+                        .map(move |()| acknowledge(&acks, ack_channel.as_deref()))
                 },
             )
             .map(finish_ok)
             .boxed()
     }
 
-    fn raw_service(&self, out: mpsc::Sender<Event>) -> BoxedFilter<(Response<Body>,)> {
+    fn raw_service(&self, out: EventSender) -> BoxedFilter<(Response<Body>,)> {
+        let quota = self.quota.clone();
+        let acks = self.acks.clone();
         warp::post2()
             .and(
                 (path!("raw" / "1.0").and(warp::path::end()))
                     .or(path!("raw").and(warp::path::end())),
             )
             .and(self.authorization())
-            .and(
-                warp::header::optional::<String>("x-splunk-request-channel").and_then(
-                    |channel: Option<String>| {
-                        if let Some(channel) = channel {
-                            Ok(channel)
-                        } else {
-                            Err(Rejection::from(ApiError::MissingChannel))
-                        }
-                    },
-                ),
-            )
+            .and(self.channel(true))
             .and(warp::header::optional::<String>("host"))
+            .and(warp::query::<RawQuery>())
             .and(self.gzip())
             .and(warp::body::concat())
             .and_then(
-                move |_, _, channel: String, host: Option<String>, gzip: bool, body: FullBody| {
-                    // Construct event parser
-                    futures01::stream::once(raw_event(body, gzip, channel, host))
-                        .forward(out.clone().sink_map_err(|_| ApiError::ServerShutdown))
-                        .map(|_| ())
+                move |_,
+                      _,
+                      channel: Option<String>,
+                      host: Option<String>,
+                      mut query: RawQuery,
+                      gzip: bool,
+                      body: FullBody| {
+                    let byte_size = body.remaining();
+                    let channel = channel.expect("required channel");
+                    query.host = query.host.or(host);
+                    // Construct events
+                    let events = match raw_events(body, gzip, &channel, query) {
+                        Ok(events) => events,
+                        Err(rejection) => {
+                            return Box::new(futures01::future::err(rejection))
+                                as Box<dyn Future<Item = _, Error = _> + Send>
+                        }
+                    };
+                    let acks = acks.clone();
+                    Box::new(
+                        send_events((events, None), byte_size, &quota, &out)
+                            .map(move |()| acknowledge(&acks, Some(&channel))),
+                    )
                 },
             )
             .map(finish_ok)
             .boxed()
     }
 
+    fn ack_service(&self) -> BoxedFilter<(Response<Body>,)> {
+        let acks = self.acks.clone();
+        warp::post2()
+            .and(
+                (path!("ack" / "1.0").and(warp::path::end()))
+                    .or(path!("ack").and(warp::path::end())),
+            )
+            .and(self.authorization())
+            .and(self.channel(true))
+            .and(warp::body::json())
+            .and_then(move |_, _, channel: Option<String>, request: AckRequest| {
+                let acks = acks
+                    .as_ref()
+                    .ok_or_else(|| Rejection::from(ApiError::AckDisabled))?;
+                let channel = channel.expect("required channel");
+                let statuses = acks.query(&channel, &request.acks);
+                Ok::<_, Rejection>(response_json(
+                    StatusCode::OK,
+                    json!({ "acks": statuses }).to_string(),
+                ))
+            })
+            .boxed()
+    }
+
     fn health_service(&self, out: mpsc::Sender<Event>) -> BoxedFilter<(Response<Body>,)> {
         let credentials = self.credentials.clone();
         let authorize =
@@ -243,7 +339,9 @@ impl SplunkSource {
                     .or(path!("event").and(warp::path::end()))
                     .or(path!("event" / "1.0").and(warp::path::end()))
                     .or(path!("raw" / "1.0").and(warp::path::end()))
-                    .or(path!("raw").and(warp::path::end())),
+                    .or(path!("raw").and(warp::path::end()))
+                    .or(path!("ack" / "1.0").and(warp::path::end()))
+                    .or(path!("ack").and(warp::path::end())),
             )
             .map(|_| warp::reply::with_header(warp::reply(), "Allow", "POST").into_response());
 
@@ -274,6 +372,22 @@ impl SplunkSource {
             .boxed()
     }
 
+    /// The channel of the request, from its header or else its query. It must
+    /// be a GUID.
+    fn channel(&self, required: bool) -> BoxedFilter<(Option<String>,)> {
+        warp::header::optional::<String>("x-splunk-request-channel")
+            .and(warp::query::<ChannelQuery>())
+            .and_then(move |header: Option<String>, query: ChannelQuery| {
+                match header.or(query.channel) {
+                    Some(channel) if Uuid::parse_str(&channel).is_ok() => Ok(Some(channel)),
+                    Some(_) => Err(Rejection::from(ApiError::InvalidChannel)),
+                    None if required => Err(Rejection::from(ApiError::MissingChannel)),
+                    None => Ok(None),
+                }
+            })
+            .boxed()
+    }
+
     /// Is body encoded with gzip
     fn gzip(&self) -> BoxedFilter<(bool,)> {
         warp::header::optional::<String>("Content-Encoding")
@@ -286,6 +400,100 @@ impl SplunkSource {
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct ChannelQuery {
+    channel: Option<String>,
+}
+
+/// The fields Splunk takes from the query of raw requests.
+#[derive(Deserialize, Debug)]
+struct RawQuery {
+    host: Option<String>,
+    index: Option<String>,
+    source: Option<String>,
+    sourcetype: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AckRequest {
+    acks: Vec<u64>,
+}
+
+/// The acknowledgements of each channel not queried yet. Requests are only
+/// answered once their events were accepted, so their acknowledgements are
+/// given right away.
+struct Acks {
+    config: AcknowledgementsConfig,
+    channels: Mutex<HashMap<String, AckChannel>>,
+}
+
+struct AckChannel {
+    next_id: u64,
+    pending: BTreeSet<u64>,
+    used_at: Instant,
+}
+
+impl Acks {
+    fn new(config: AcknowledgementsConfig) -> Self {
+        Acks {
+            config,
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn add(&self, channel: &str) -> u64 {
+        let mut channels = self.channels.lock().unwrap();
+        self.expire(&mut channels);
+        let channel = channels
+            .entry(channel.to_owned())
+            .or_insert_with(|| AckChannel {
+                next_id: 0,
+                pending: BTreeSet::new(),
+                used_at: Instant::now(),
+            });
+        channel.used_at = Instant::now();
+
+        let id = channel.next_id;
+        channel.next_id += 1;
+        channel.pending.insert(id);
+        if channel.pending.len() > self.config.max_pending_acks_per_channel {
+            let oldest = *channel.pending.iter().next().expect("not empty");
+            channel.pending.remove(&oldest);
+        }
+        id
+    }
+
+    /// Acknowledgements are only reported once, as Splunk does.
+    fn query(&self, channel: &str, ids: &[u64]) -> BTreeMap<u64, bool> {
+        let mut channels = self.channels.lock().unwrap();
+        self.expire(&mut channels);
+        let mut channel = channels.get_mut(channel);
+        if let Some(channel) = channel.as_mut() {
+            channel.used_at = Instant::now();
+        }
+        ids.iter()
+            .map(|id| {
+                let acked = channel
+                    .as_mut()
+                    .map_or(false, |channel| channel.pending.remove(id));
+                (*id, acked)
+            })
+            .collect()
+    }
+
+    fn expire(&self, channels: &mut HashMap<String, AckChannel>) {
+        let max_idle = Duration::from_secs(self.config.max_idle_secs);
+        channels.retain(|_, channel| channel.used_at.elapsed() < max_idle);
+    }
+}
+
+fn acknowledge(acks: &Option<Arc<Acks>>, channel: Option<&str>) -> Option<u64> {
+    match (acks, channel) {
+        (Some(acks), Some(channel)) => Some(acks.add(channel)),
+        _ => None,
+    }
+}
+
 /// Constructs one ore more events from json-s coming from reader.
 /// If errors, it's done with input.
 struct EventStream<R: Read> {
@@ -540,18 +748,18 @@ enum Time {
     Provided(DateTime<Utc>),
 }
 
-/// Creates event from raw request
-fn raw_event(
+/// Creates an event from each line of a raw request, as Splunk breaks them
+/// by default.
+fn raw_events(
     bytes: FullBody,
     gzip: bool,
-    channel: String,
-    host: Option<String>,
-) -> Result<Event, Rejection> {
+    channel: &str,
+    query: RawQuery,
+) -> Result<Vec<Event>, Rejection> {
     // Process gzip
-    let message: Value = if gzip {
+    let data: Bytes = if gzip {
         let mut data = Vec::new();
         match GzDecoder::new(bytes.reader()).read_to_end(&mut data) {
-            Ok(0) => return Err(ApiError::NoData.into()),
             Ok(_) => data.into(),
             Err(error) => {
                 error!(message = "Malformed request body",%error);
@@ -562,30 +770,53 @@ fn raw_event(
         bytes.bytes().into()
     };
 
-    // Construct event
-    let mut event = Event::new_empty_log();
-    let log = event.as_mut_log();
-
-    // Add message
-    log.insert(event::log_schema().message_key().clone(), message);
-
-    // Add channel
-    log.insert(CHANNEL.clone(), channel.as_bytes());
+    let timestamp = Utc::now();
+    let events = data
+        .split(|byte| *byte == b'\n')
+        .map(|line| match line.last() {
+            Some(b'\r') => &line[..line.len() - 1],
+            _ => line,
+        })
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // Construct event
+            let mut event = Event::new_empty_log();
+            let log = event.as_mut_log();
+
+            // Add message
+            log.insert(event::log_schema().message_key().clone(), line);
+
+            // Add channel
+            log.insert(CHANNEL.clone(), channel.as_bytes());
+
+            // Add the fields of the query
+            if let Some(host) = &query.host {
+                log.insert(event::log_schema().host_key().clone(), host.as_bytes());
+            }
+            if let Some(index) = &query.index {
+                log.insert(INDEX.clone(), index.as_bytes());
+            }
+            if let Some(source) = &query.source {
+                log.insert(SOURCE.clone(), source.as_bytes());
+            }
+            if let Some(sourcetype) = &query.sourcetype {
+                log.insert(SOURCETYPE.clone(), sourcetype.as_bytes());
+            }
 
-    // Add host
-    if let Some(host) = host {
-        log.insert(event::log_schema().host_key().clone(), host.as_bytes());
-    }
+            // Add timestamp
+            log.insert(event::log_schema().timestamp_key().clone(), timestamp);
 
-    // Add timestamp
-    log.insert(event::log_schema().timestamp_key().clone(), Utc::now());
+            // Add source type
+            log.try_insert(event::log_schema().source_type_key(), "splunk_hec");
 
-    // Add source type
-    event
-        .as_mut_log()
-        .try_insert(event::log_schema().source_type_key(), "splunk_hec");
+            event
+        })
+        .collect::<Vec<_>>();
 
-    Ok(event)
+    if events.is_empty() {
+        return Err(ApiError::NoData.into());
+    }
+    Ok(events)
 }
 
 #[derive(Debug, Snafu)]
@@ -594,12 +825,61 @@ enum ApiError {
     InvalidAuthorization,
     UnsupportedEncoding,
     MissingChannel,
+    InvalidChannel,
+    AckDisabled,
     NoData,
     InvalidDataFormat { event: usize },
     ServerShutdown,
     EmptyEventField { event: usize },
     MissingEventField { event: usize },
     BadRequest,
+    ServerBusy,
+    QuotaExceeded { retry_after: Duration },
+}
+
+/// The events parsed before any error in the request, and that error.
+fn parse_events<R: Read>(stream: EventStream<R>) -> (Vec<Event>, Option<Rejection>) {
+    let mut events = Vec::new();
+    // The body is in memory already, so parsing it never blocks.
+    for event in stream.wait() {
+        match event {
+            Ok(event) => events.push(event),
+            Err(rejection) => return (events, Some(rejection)),
+        }
+    }
+    (events, None)
+}
+
+/// Sends the events parsed before any error in the request, then fails with
+/// that error.
+fn send_events(
+    (events, error): (Vec<Event>, Option<Rejection>),
+    byte_size: usize,
+    quota: &Option<Quota>,
+    out: &EventSender,
+) -> Box<dyn Future<Item = (), Error = Rejection> + Send> {
+    if let Some(quota) = quota {
+        if let Err(retry_after) = quota.take(events.len(), byte_size) {
+            return Box::new(futures01::future::err(
+                ApiError::QuotaExceeded { retry_after }.into(),
+            ));
+        }
+    }
+    Box::new(
+        out.send(events)
+            .map_err(send_error)
+            .and_then(move |()| error.map_or(Ok(()), Err)),
+    )
+}
+
+fn send_error(error: SendError) -> Rejection {
+    match error {
+        SendError::PipelineClosed => ApiError::ServerShutdown.into(),
+        error => {
+            error!(message = "unable to record events.", %error);
+            ApiError::ServerBusy.into()
+        }
+    }
 }
 
 impl From<ApiError> for Rejection {
@@ -629,15 +909,27 @@ mod splunk_response {
             json_to_bytes(json!({"text":"Internal server error","code":8}));
         pub static ref SERVER_SHUTDOWN: Bytes =
             json_to_bytes(json!({"text":"Server is shuting down","code":9}));
+        pub static ref SERVER_BUSY: Bytes =
+            json_to_bytes(json!({"text":"Server is busy","code":9}));
         pub static ref UNSUPPORTED_MEDIA_TYPE: Bytes =
             json_to_bytes(json!({"text":"unsupported content encoding"}));
         pub static ref NO_CHANNEL: Bytes =
             json_to_bytes(json!({"text":"Data channel is missing","code":10}));
+        pub static ref INVALID_CHANNEL: Bytes =
+            json_to_bytes(json!({"text":"Invalid data channel","code":11}));
+        pub static ref ACK_DISABLED: Bytes =
+            json_to_bytes(json!({"text":"ACK is disabled","code":14}));
     }
 }
 
-fn finish_ok(_: ()) -> Response<Body> {
-    response_json(StatusCode::OK, splunk_response::SUCCESS.as_ref())
+fn finish_ok(ack_id: Option<u64>) -> Response<Body> {
+    match ack_id {
+        Some(ack_id) => response_json(
+            StatusCode::OK,
+            json!({"text": "Success", "code": 0, "ackId": ack_id}).to_string(),
+        ),
+        None => response_json(StatusCode::OK, splunk_response::SUCCESS.as_ref()),
+    }
 }
 
 fn finish_err(rejection: Rejection) -> Result<(Response<Body>,), Rejection> {
@@ -659,6 +951,14 @@ fn finish_err(rejection: Rejection) -> Result<(Response<Body>,), Rejection> {
                 StatusCode::BAD_REQUEST,
                 splunk_response::NO_CHANNEL.as_ref(),
             ),
+            ApiError::InvalidChannel => response_json(
+                StatusCode::BAD_REQUEST,
+                splunk_response::INVALID_CHANNEL.as_ref(),
+            ),
+            ApiError::AckDisabled => response_json(
+                StatusCode::BAD_REQUEST,
+                splunk_response::ACK_DISABLED.as_ref(),
+            ),
             ApiError::NoData => {
                 response_json(StatusCode::BAD_REQUEST, splunk_response::NO_DATA.as_ref())
             }
@@ -674,6 +974,20 @@ fn finish_err(rejection: Rejection) -> Result<(Response<Body>,), Rejection> {
                 event_error("Event field is required", 12, *event)
             }
             ApiError::BadRequest => empty_response(StatusCode::BAD_REQUEST),
+            ApiError::ServerBusy => response_json(
+                StatusCode::SERVICE_UNAVAILABLE,
+                splunk_response::SERVER_BUSY.as_ref(),
+            ),
+            ApiError::QuotaExceeded { retry_after } => {
+                let mut response = response_json(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    splunk_response::SERVER_BUSY.as_ref(),
+                );
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, retry_after_secs(*retry_after).into());
+                response
+            }
         },))
     } else {
         Err(rejection)
@@ -687,9 +1001,14 @@ fn empty_response(code: StatusCode) -> Response<Body> {
     res
 }
 
-/// Response with body
-fn response_json(code: StatusCode, body: impl Serialize) -> Response<Body> {
-    warp::reply::with_status(warp::reply::json(&body), code).into_response()
+/// Response with a body of encoded JSON
+fn response_json(code: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = code;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
 }
 
 /// Error happened during parsing of events
@@ -714,7 +1033,7 @@ fn event_error(text: &str, code: u16, event: usize) -> Response<Body> {
 #[cfg(feature = "sinks-splunk_hec")]
 #[cfg(test)]
 mod tests {
-    use super::{parse_timestamp, SplunkConfig};
+    use super::{parse_timestamp, AcknowledgementsConfig, SplunkConfig};
     use crate::runtime::{Runtime, TaskExecutor};
     use crate::test_util::{self, collect_n, runtime};
     use crate::{
@@ -735,13 +1054,20 @@ mod tests {
     /// Splunk token
     const TOKEN: &'static str = "token";
 
+    /// Splunk channel
+    const GUID: &'static str = "5a1b7d1e-4c6f-4e8a-9f0b-2d3c4e5f6a7b";
+
     const CHANNEL_CAPACITY: usize = 1000;
 
     fn source(rt: &mut Runtime) -> (mpsc::Receiver<Event>, SocketAddr) {
-        source_with(rt, Some(TOKEN.to_owned()))
+        source_with(rt, Some(TOKEN.to_owned()), None)
     }
 
-    fn source_with(rt: &mut Runtime, token: Option<String>) -> (mpsc::Receiver<Event>, SocketAddr) {
+    fn source_with(
+        rt: &mut Runtime,
+        token: Option<String>,
+        acknowledgements: Option<AcknowledgementsConfig>,
+    ) -> (mpsc::Receiver<Event>, SocketAddr) {
         test_util::trace_init();
         let (sender, recv) = mpsc::channel(CHANNEL_CAPACITY);
         let address = test_util::next_addr();
@@ -750,6 +1076,10 @@ mod tests {
                 address,
                 token,
                 tls: None,
+                access: Default::default(),
+                quota: None,
+                wal: None,
+                acknowledgements,
             }
             .build(
                 "default",
@@ -824,7 +1154,7 @@ mod tests {
         reqwest::Client::new()
             .request(method, &format!("http://{}/{}", address, api))
             .header("Authorization", format!("Splunk {}", token))
-            .header("x-splunk-request-channel", "guid")
+            .header("x-splunk-request-channel", GUID)
             .body(message.to_owned())
             .send()
             .unwrap()
@@ -1003,7 +1333,7 @@ mod tests {
             event.as_log()[&event::log_schema().message_key()],
             message.into()
         );
-        assert_eq!(event.as_log()[&super::CHANNEL], "guid".into());
+        assert_eq!(event.as_log()[&super::CHANNEL], GUID.into());
         assert!(event
             .as_log()
             .get(&event::log_schema().timestamp_key())
@@ -1014,6 +1344,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn raw_lines_acknowledged() {
+        let mut rt = runtime();
+        let acknowledgements = toml::from_str::<AcknowledgementsConfig>("").unwrap();
+        let (source, address) =
+            source_with(&mut rt, Some(TOKEN.to_owned()), Some(acknowledgements));
+        let request = |api: &str, channel: &str, body: &str| {
+            let mut response = reqwest::Client::new()
+                .post(&format!("http://{}/{}", address, api))
+                .header("Authorization", format!("Splunk {}", TOKEN))
+                .header("x-splunk-request-channel", channel)
+                .body(body.to_owned())
+                .send()
+                .unwrap();
+            let body = serde_json::from_str::<serde_json::Value>(&response.text().unwrap());
+            (response.status().as_u16(), body.unwrap_or_default())
+        };
+
+        let (status, body) = request(
+            "services/collector/raw?sourcetype=app",
+            GUID,
+            "first\r\nsecond\n",
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body["ackId"], 0);
+
+        let events = rt.block_on(collect_n(source, 2)).unwrap();
+        assert_eq!(
+            events[0].as_log()[&event::log_schema().message_key()],
+            "first".into()
+        );
+        assert_eq!(
+            events[1].as_log()[&event::log_schema().message_key()],
+            "second".into()
+        );
+        assert_eq!(events[1].as_log()[&super::SOURCETYPE], "app".into());
+
+        let (status, body) = request("services/collector/ack", GUID, r#"{"acks":[0,1]}"#);
+        assert_eq!(status, 200);
+        assert_eq!(body["acks"]["0"], true);
+        assert_eq!(body["acks"]["1"], false);
+        let (_, body) = request("services/collector/ack", GUID, r#"{"acks":[0]}"#);
+        assert_eq!(body["acks"]["0"], false);
+
+        let (status, body) = request("services/collector/raw", "guid", "raw");
+        assert_eq!(status, 400);
+        assert_eq!(body["code"], 11);
+    }
+
     #[test]
     fn no_data() {
         let mut rt = runtime();
@@ -1043,7 +1422,7 @@ mod tests {
     fn no_autorization() {
         let message = "no_autorization";
         let mut rt = runtime();
-        let (source, address) = source_with(&mut rt, None);
+        let (source, address) = source_with(&mut rt, None, None);
         let (sink, health) = sink(address, Encoding::Text, Compression::Gzip, rt.executor());
         assert!(rt.block_on(health).is_ok());
 
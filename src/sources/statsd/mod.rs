@@ -1,22 +1,65 @@
 use crate::{shutdown::ShutdownSignal, stream::StreamExt, topology::config::GlobalOptions, Event};
-use futures01::{future, sync::mpsc, Future, Sink, Stream};
+use futures01::{future, stream, sync::mpsc, Future, Sink, Stream};
 use parser::parse;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+#[cfg(unix)]
+use snafu::{ResultExt, Snafu};
+#[cfg(unix)]
+use std::{
+    fs,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+use std::{io, net::SocketAddr};
 use tokio01::{
     self,
     codec::BytesCodec,
     net::{UdpFramed, UdpSocket},
 };
+#[cfg(unix)]
+use tokio_uds::{UnixDatagram, UnixDatagramFramed};
 use tracing::field;
 
 pub mod parser;
 
-#[derive(Deserialize, Serialize, Debug)]
-struct StatsdConfig {
+#[cfg(unix)]
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Unable to bind to unix datagram socket {:?}: {}", path, source))]
+    UnixBind { path: PathBuf, source: io::Error },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+enum StatsdConfig {
+    Mode(Mode),
+    /// Configs without a `mode`, from before it was introduced, are UDP.
+    Udp(UdpConfig),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum Mode {
+    Udp(UdpConfig),
+    #[cfg(unix)]
+    Unix(UnixConfig),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct UdpConfig {
     address: SocketAddr,
 }
 
+/// DogStatsD clients may send over a Unix datagram socket instead, as
+/// they do from containers.
+#[cfg(unix)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct UnixConfig {
+    path: PathBuf,
+}
+
 #[typetag::serde(name = "statsd")]
 impl crate::topology::config::SourceConfig for StatsdConfig {
     fn build(
@@ -26,7 +69,15 @@ impl crate::topology::config::SourceConfig for StatsdConfig {
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<super::Source> {
-        Ok(statsd(self.address, shutdown, out))
+        match self {
+            StatsdConfig::Mode(Mode::Udp(config)) | StatsdConfig::Udp(config) => {
+                Ok(statsd_udp(config.address, shutdown, out))
+            }
+            #[cfg(unix)]
+            StatsdConfig::Mode(Mode::Unix(config)) => {
+                statsd_unix(config.path.clone(), shutdown, out)
+            }
+        }
     }
 
     fn output_type(&self) -> crate::topology::config::DataType {
@@ -38,7 +89,23 @@ impl crate::topology::config::SourceConfig for StatsdConfig {
     }
 }
 
-fn statsd(addr: SocketAddr, shutdown: ShutdownSignal, out: mpsc::Sender<Event>) -> super::Source {
+/// The metrics of a packet, one per line.
+fn parse_packet(bytes: &[u8]) -> stream::IterOk<std::vec::IntoIter<Event>, io::Error> {
+    let packet = String::from_utf8_lossy(bytes);
+    let metrics = packet
+        .lines()
+        .map(parse)
+        .filter_map(|res| res.map_err(|e| error!("{}", e)).ok())
+        .map(Event::Metric)
+        .collect::<Vec<_>>();
+    stream::iter_ok(metrics)
+}
+
+fn statsd_udp(
+    addr: SocketAddr,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> super::Source {
     let out = out.sink_map_err(|e| error!("error sending metric: {:?}", e));
 
     Box::new(
@@ -56,16 +123,7 @@ fn statsd(addr: SocketAddr, shutdown: ShutdownSignal, out: mpsc::Sender<Event>)
         .and_then(move |socket| {
             let metrics_in = UdpFramed::new(socket, BytesCodec::new())
                 .take_until(shutdown)
-                .map(|(bytes, _sock)| {
-                    let packet = String::from_utf8_lossy(bytes.as_ref());
-                    let metrics = packet
-                        .lines()
-                        .map(parse)
-                        .filter_map(|res| res.map_err(|e| error!("{}", e)).ok())
-                        .map(Event::Metric)
-                        .collect::<Vec<_>>();
-                    futures01::stream::iter_ok::<_, std::io::Error>(metrics)
-                })
+                .map(|(bytes, _sock)| parse_packet(&bytes))
                 .flatten()
                 .map_err(|e| error!("error reading datagram: {:?}", e));
 
@@ -74,10 +132,47 @@ fn statsd(addr: SocketAddr, shutdown: ShutdownSignal, out: mpsc::Sender<Event>)
     )
 }
 
+#[cfg(unix)]
+fn statsd_unix(
+    path: PathBuf,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+) -> crate::Result<super::Source> {
+    let out = out.sink_map_err(|e| error!("error sending metric: {:?}", e));
+
+    // The socket of a previous run is left behind when it didn't shut down
+    // cleanly.
+    remove_stale_socket(&path).context(UnixBind { path: &path })?;
+    let socket = UnixDatagram::bind(&path).context(UnixBind { path: &path })?;
+    info!(message = "listening.", ?path, r#type = "unix_datagram");
+
+    let metrics_in = UnixDatagramFramed::<PathBuf, _>::new(socket, BytesCodec::new())
+        .take_until(shutdown)
+        .map(|(bytes, _sock)| parse_packet(&bytes))
+        .flatten()
+        .map_err(|e| error!("error reading datagram: {:?}", e));
+
+    Ok(Box::new(
+        metrics_in.forward(out).map(|_| info!("finished sending")),
+    ))
+}
+
+/// Removes the file at the path if it's a socket. Other files are left for
+/// binding to fail on.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(feature = "sinks-prometheus")]
 #[cfg(test)]
 mod test {
-    use super::StatsdConfig;
+    #[cfg(unix)]
+    use super::statsd_unix;
+    use super::{Mode, StatsdConfig, UdpConfig};
     use crate::{
         sinks::prometheus::PrometheusSinkConfig,
         test_util::{block_on, next_addr, runtime, shutdown_on_idle},
@@ -102,7 +197,7 @@ mod test {
         let out_addr = next_addr();
 
         let mut config = config::Config::empty();
-        config.add_source("in", StatsdConfig { address: in_addr });
+        config.add_source("in", StatsdConfig::Udp(UdpConfig { address: in_addr }));
         config.add_sink(
             "out",
             &["in"],
@@ -222,4 +317,26 @@ mod test {
         block_on(topology.stop()).unwrap();
         shutdown_on_idle(rt);
     }
+
+    #[test]
+    fn statsd_mode_defaults_to_udp() {
+        let config = toml::from_str::<StatsdConfig>(r#"address = "127.0.0.1:8125""#).unwrap();
+        assert!(matches!(config, StatsdConfig::Udp(_)));
+
+        let config =
+            toml::from_str::<StatsdConfig>("mode = \"udp\"\naddress = \"127.0.0.1:8125\"").unwrap();
+        assert!(matches!(config, StatsdConfig::Mode(Mode::Udp(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn statsd_unix_replaces_stale_socket() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("dsd.socket");
+        // Left behind, as by a previous run that crashed.
+        drop(std::os::unix::net::UnixDatagram::bind(&path).unwrap());
+
+        let (tx, _rx) = futures01::sync::mpsc::channel(10);
+        assert!(statsd_unix(path, crate::shutdown::ShutdownSignal::noop(), tx).is_ok());
+    }
 }
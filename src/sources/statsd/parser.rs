@@ -1,4 +1,5 @@
 use crate::event::metric::{Metric, MetricKind, MetricValue};
+use chrono::{DateTime, TimeZone, Utc};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{
@@ -31,77 +32,86 @@ pub fn parse(packet: &str) -> Result<Metric, ParseError> {
 
     let name = sanitize_key(key);
     let metric_type = parts[1];
-
-    // sampling part is optional and comes after metric type part
-    let sampling = parts.get(2).filter(|s| s.starts_with('@'));
-    let sample_rate = if let Some(s) = sampling {
-        1.0 / sanitize_sampling(parse_sampling(s)?)
-    } else {
-        1.0
-    };
-
-    // tags are optional and could be found either after sampling of after metric type part
-    let tags = if sampling.is_none() {
-        parts.get(2)
-    } else {
-        parts.get(3)
-    };
-    let tags = tags.filter(|s| s.starts_with('#'));
-    let tags = if let Some(t) = tags {
-        Some(parse_tags(t)?)
-    } else {
-        None
-    };
+    // DogStatsD packs several values of a metric in one line.
+    let values = parts[0].split(':').collect::<Vec<_>>();
+
+    // The sampling, tags, container id and timestamp are optional and may
+    // come in any order. Fields not known are skipped, as later versions of
+    // the protocol may add more.
+    let mut sample_rate = 1.0;
+    let mut tags: Option<BTreeMap<String, String>> = None;
+    let mut timestamp = None;
+    for field in &parts[2..] {
+        if field.starts_with('@') {
+            sample_rate = 1.0 / sanitize_sampling(parse_sampling(field)?);
+        } else if field.starts_with('#') {
+            tags.get_or_insert_with(BTreeMap::new)
+                .extend(parse_tags(field)?);
+        } else if field.starts_with("c:") {
+            tags.get_or_insert_with(BTreeMap::new)
+                .insert("container_id".to_owned(), field[2..].to_owned());
+        } else if field.starts_with('T') {
+            timestamp = Some(parse_timestamp(&field[1..])?);
+        }
+    }
 
     let metric = match metric_type {
         "c" => {
-            let val: f64 = parts[0].parse()?;
+            let mut sum = 0.0;
+            for value in values {
+                sum += value.parse::<f64>()?;
+            }
             Metric {
                 name,
-                timestamp: None,
+                timestamp,
                 tags,
                 kind: MetricKind::Incremental,
                 value: MetricValue::Counter {
-                    value: val * sample_rate,
+                    value: sum * sample_rate,
                 },
             }
         }
-        unit @ "h" | unit @ "ms" => {
-            let val: f64 = parts[0].parse()?;
+        unit @ "h" | unit @ "ms" | unit @ "d" => {
+            let values = values
+                .into_iter()
+                .map(|value| Ok(convert_to_base_units(unit, value.parse()?)))
+                .collect::<Result<Vec<_>, ParseError>>()?;
             Metric {
                 name,
-                timestamp: None,
+                timestamp,
                 tags,
                 kind: MetricKind::Incremental,
                 value: MetricValue::Distribution {
-                    values: vec![convert_to_base_units(unit, val)],
-                    sample_rates: vec![sample_rate as u32],
+                    sample_rates: vec![sample_rate as u32; values.len()],
+                    values,
                 },
             }
         }
         "g" => {
-            let value = if parts[0]
+            // Only the last of packed gauge values stands.
+            let last = values[values.len() - 1];
+            let value = if last
                 .chars()
                 .next()
                 .map(|c| c.is_ascii_digit())
                 .ok_or_else(|| ParseError::Malformed("empty first body component"))?
             {
-                parts[0].parse()?
+                last.parse()?
             } else {
-                parts[0][1..].parse()?
+                last[1..].parse()?
             };
 
-            match parse_direction(parts[0])? {
+            match parse_direction(last)? {
                 None => Metric {
                     name,
-                    timestamp: None,
+                    timestamp,
                     tags,
                     kind: MetricKind::Absolute,
                     value: MetricValue::Gauge { value },
                 },
                 Some(sign) => Metric {
                     name,
-                    timestamp: None,
+                    timestamp,
                     tags,
                     kind: MetricKind::Incremental,
                     value: MetricValue::Gauge {
@@ -112,11 +122,11 @@ pub fn parse(packet: &str) -> Result<Metric, ParseError> {
         }
         "s" => Metric {
             name,
-            timestamp: None,
+            timestamp,
             tags,
             kind: MetricKind::Incremental,
             value: MetricValue::Set {
-                values: vec![parts[0].into()].into_iter().collect(),
+                values: values.into_iter().map(Into::into).collect(),
             },
         },
         other => return Err(ParseError::UnknownMetricType(other.into())),
@@ -124,6 +134,14 @@ pub fn parse(packet: &str) -> Result<Metric, ParseError> {
     Ok(metric)
 }
 
+/// Timestamps are in seconds since the epoch.
+fn parse_timestamp(input: &str) -> Result<DateTime<Utc>, ParseError> {
+    let seconds: i64 = input.parse()?;
+    Utc.timestamp_opt(seconds, 0)
+        .single()
+        .ok_or_else(|| ParseError::Malformed("timestamp out of range"))
+}
+
 fn parse_sampling(input: &str) -> Result<f64, ParseError> {
     if !input.starts_with('@') || input.len() < 2 {
         return Err(ParseError::Malformed(
@@ -231,6 +249,7 @@ impl From<ParseFloatError> for ParseError {
 mod test {
     use super::{parse, sanitize_key, sanitize_sampling};
     use crate::event::metric::{Metric, MetricKind, MetricValue};
+    use chrono::{TimeZone, Utc};
 
     #[test]
     fn basic_counter() {
@@ -391,6 +410,54 @@ mod test {
         );
     }
 
+    #[test]
+    fn packed_distribution_with_container_and_timestamp() {
+        assert_eq!(
+            parse("latency:1.5:2:0.5|d|#env:prod|c:83c0a99c0a54|T1592496000|@0.5"),
+            Ok(Metric {
+                name: "latency".into(),
+                timestamp: Some(Utc.timestamp(1_592_496_000, 0)),
+                tags: Some(
+                    vec![
+                        ("env".to_owned(), "prod".to_owned()),
+                        ("container_id".to_owned(), "83c0a99c0a54".to_owned()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Distribution {
+                    values: vec![1.5, 2.0, 0.5],
+                    sample_rates: vec![2, 2, 2],
+                },
+            }),
+        );
+    }
+
+    #[test]
+    fn packed_counters_and_gauges() {
+        assert_eq!(
+            parse("hits:1:2:3|c|x:unknown"),
+            Ok(Metric {
+                name: "hits".into(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 6.0 },
+            }),
+        );
+        assert_eq!(
+            parse("temp:20:21|g"),
+            Ok(Metric {
+                name: "temp".into(),
+                timestamp: None,
+                tags: None,
+                kind: MetricKind::Absolute,
+                value: MetricValue::Gauge { value: 21.0 },
+            }),
+        );
+    }
+
     #[test]
     fn sanitizing_keys() {
         assert_eq!("foo-bar-baz", sanitize_key("foo/bar/baz"));
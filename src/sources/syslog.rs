@@ -1,26 +1,31 @@
-use super::util::{SocketListenAddr, TcpSource};
+use super::util::{
+    bind_udp_shards, default_shards, spawn_udp_shards, validate_shards, SocketListenAddr, TcpSource,
+};
+#[cfg(unix)]
+use crate::permissions::PermissionsConfig;
 #[cfg(unix)]
 use crate::sources::util::build_unix_source;
 use crate::{
-    event::{self, Event, Value},
-    internal_events::{SyslogEventReceived, SyslogUdpReadError},
+    event::{self, Event, PathComponent, Value},
+    internal_events::{SyslogEventReceived, SyslogUdpReadError, UdpShardEventReceived},
     shutdown::ShutdownSignal,
     stream::StreamExt,
     tls::{MaybeTlsSettings, TlsConfig},
     topology::config::{DataType, GlobalOptions, SourceConfig, SourceDescription},
 };
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use chrono::{Datelike, Utc};
 use derive_is_enum_variant::is_enum_variant;
 use futures01::{future, sync::mpsc, Future, Sink, Stream};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
 #[cfg(unix)]
 use std::path::PathBuf;
+use std::{io, net::SocketAddr};
+use string_cache::DefaultAtom as Atom;
 use syslog_loose::{self, IncompleteDate, Message, ProcId, Protocol};
 use tokio01::{
     self,
-    codec::{BytesCodec, LinesCodec},
+    codec::{BytesCodec, Decoder, LinesCodec},
     net::{UdpFramed, UdpSocket},
 };
 use tracing::field;
@@ -43,13 +48,23 @@ pub enum Mode {
     Tcp {
         address: SocketListenAddr,
         tls: Option<TlsConfig>,
+        /// Where to put the subject of the certificate clients authenticated
+        /// with.
+        tls_client_subject_key: Option<Atom>,
     },
     Udp {
         address: SocketAddr,
+        /// The number of sockets bound to the address, each read by its own
+        /// task.
+        #[serde(default = "default_shards")]
+        shards: usize,
     },
     #[cfg(unix)]
     Unix {
         path: PathBuf,
+        /// Given to the socket once bound.
+        #[serde(default)]
+        permissions: PermissionsConfig,
     },
 }
 
@@ -86,19 +101,42 @@ impl SourceConfig for SyslogConfig {
             .unwrap_or(event::log_schema().host_key().to_string());
 
         match self.mode.clone() {
-            Mode::Tcp { address, tls } => {
+            Mode::Tcp {
+                address,
+                tls,
+                tls_client_subject_key,
+            } => {
                 let source = SyslogTcpSource {
                     max_length: self.max_length,
                     host_key,
                 };
                 let shutdown_secs = 30;
                 let tls = MaybeTlsSettings::from_config(&tls, true)?;
-                source.run(address, shutdown_secs, tls, shutdown, out)
+                source.run(
+                    address,
+                    shutdown_secs,
+                    tls,
+                    Default::default(),
+                    tls_client_subject_key,
+                    shutdown,
+                    out,
+                )
+            }
+            Mode::Udp { address, shards } => {
+                validate_shards(shards)?;
+                Ok(udp(
+                    address,
+                    self.max_length,
+                    host_key,
+                    shards,
+                    shutdown,
+                    out,
+                ))
             }
-            Mode::Udp { address } => Ok(udp(address, self.max_length, host_key, shutdown, out)),
             #[cfg(unix)]
-            Mode::Unix { path } => Ok(build_unix_source(
+            Mode::Unix { path, permissions } => Ok(build_unix_source(
                 path,
+                permissions.build()?,
                 self.max_length,
                 host_key,
                 shutdown,
@@ -124,10 +162,10 @@ struct SyslogTcpSource {
 }
 
 impl TcpSource for SyslogTcpSource {
-    type Decoder = LinesCodec;
+    type Decoder = SyslogDecoder;
 
     fn decoder(&self) -> Self::Decoder {
-        LinesCodec::new_with_max_length(self.max_length)
+        SyslogDecoder::new(self.max_length)
     }
 
     fn build_event(&self, frame: String, host: Bytes) -> Option<Event> {
@@ -142,10 +180,137 @@ impl TcpSource for SyslogTcpSource {
     }
 }
 
+/// Decodes the frames of a syslog stream, which are either prefixed with their
+/// length, as in the octet counting of RFC 6587 that RFC 5425 mandates over
+/// TLS, or terminated by a newline. Senders may mix both.
+#[derive(Debug)]
+pub struct SyslogDecoder {
+    lines: LinesCodec,
+    max_length: usize,
+    /// The bytes left to skip of an octet counted frame over `max_length`.
+    discarding: usize,
+    /// Whether the last frame was octet counted, and a newline some senders
+    /// still append after it is to be skipped.
+    after_octet_counted: bool,
+}
+
+impl SyslogDecoder {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            lines: LinesCodec::new_with_max_length(max_length),
+            max_length,
+            discarding: 0,
+            after_octet_counted: false,
+        }
+    }
+}
+
+enum Framing {
+    /// The length of the frame and of its prefix, including the space.
+    OctetCounted {
+        length: usize,
+        prefix: usize,
+    },
+    NonTransparent,
+    Incomplete,
+}
+
+/// The length prefix is a number not starting with 0, followed by a space.
+fn detect_framing(buf: &[u8]) -> Framing {
+    match buf.first() {
+        None => return Framing::Incomplete,
+        Some(b'1'..=b'9') => (),
+        Some(_) => return Framing::NonTransparent,
+    }
+    let digits = buf.iter().take_while(|b| b.is_ascii_digit()).count();
+    // Frames of 10 digits and above would be too large in any case.
+    if digits >= 10 {
+        return Framing::NonTransparent;
+    }
+    match buf.get(digits) {
+        None => Framing::Incomplete,
+        Some(b' ') => Framing::OctetCounted {
+            length: std::str::from_utf8(&buf[..digits])
+                .ok()
+                .and_then(|digits| digits.parse().ok())
+                .unwrap_or_default(),
+            prefix: digits + 1,
+        },
+        Some(_) => Framing::NonTransparent,
+    }
+}
+
+impl Decoder for SyslogDecoder {
+    type Item = String;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<String>, io::Error> {
+        loop {
+            if self.discarding > 0 {
+                let skipped = self.discarding.min(buf.len());
+                buf.advance(skipped);
+                self.discarding -= skipped;
+                if self.discarding > 0 {
+                    return Ok(None);
+                }
+            }
+            if self.after_octet_counted {
+                match buf.first() {
+                    None => return Ok(None),
+                    Some(b'\n') => buf.advance(1),
+                    Some(_) => (),
+                }
+                self.after_octet_counted = false;
+            }
+
+            match detect_framing(buf) {
+                Framing::OctetCounted { length, prefix } if length > self.max_length => {
+                    warn!(
+                        message = "discarding frame larger than max_length",
+                        length = length,
+                        max_length = self.max_length,
+                        rate_limit_secs = 30
+                    );
+                    buf.advance(prefix);
+                    self.discarding = length;
+                    self.after_octet_counted = true;
+                }
+                Framing::OctetCounted { length, prefix } => {
+                    if buf.len() < prefix + length {
+                        buf.reserve(prefix + length - buf.len());
+                        return Ok(None);
+                    }
+                    buf.advance(prefix);
+                    let frame = buf.split_to(length);
+                    self.after_octet_counted = true;
+                    return Ok(Some(String::from_utf8_lossy(&frame).into_owned()));
+                }
+                Framing::NonTransparent => return self.lines.decode(buf),
+                Framing::Incomplete => return Ok(None),
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<String>, io::Error> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None => match detect_framing(buf) {
+                Framing::NonTransparent => self.lines.decode_eof(buf),
+                // A truncated frame, or a lone number, is dropped.
+                _ => {
+                    buf.clear();
+                    Ok(None)
+                }
+            },
+        }
+    }
+}
+
 pub fn udp(
     addr: SocketAddr,
     _max_length: usize,
     host_key: String,
+    shards: usize,
     shutdown: ShutdownSignal,
     out: mpsc::Sender<Event>,
 ) -> super::Source {
@@ -153,32 +318,44 @@ pub fn udp(
 
     Box::new(
         future::lazy(move || {
-            let socket = UdpSocket::bind(&addr).expect("failed to bind to udp listener socket");
+            let sockets =
+                bind_udp_shards(&addr, shards).expect("failed to bind to udp listener socket");
 
             info!(
                 message = "listening.",
                 addr = &field::display(addr),
-                r#type = "udp"
+                r#type = "udp",
+                shards = shards as u64
             );
 
-            future::ok(socket)
+            future::ok(sockets)
         })
-        .and_then(move |socket| {
-            let host_key = host_key.clone();
-
-            let lines_in = UdpFramed::new(socket, BytesCodec::new())
-                .take_until(shutdown)
-                .filter_map(move |(bytes, received_from)| {
-                    let host_key = host_key.clone();
-                    let received_from = received_from.to_string().into();
-
-                    std::str::from_utf8(&bytes)
-                        .ok()
-                        .and_then(|s| event_from_str(&host_key, Some(received_from), s))
-                })
-                .map_err(|error| emit!(SyslogUdpReadError { error }));
-
-            lines_in.forward(out).map(|_| info!("finished sending"))
+        .and_then(move |sockets| {
+            spawn_udp_shards(sockets, move |shard, socket: UdpSocket| {
+                let host_key = host_key.clone();
+
+                let lines_in = UdpFramed::new(socket, BytesCodec::new())
+                    .take_until(shutdown.clone())
+                    .filter_map(move |(bytes, received_from)| {
+                        let host_key = host_key.clone();
+                        let received_from = received_from.to_string().into();
+
+                        emit!(UdpShardEventReceived {
+                            component_type: "syslog",
+                            shard,
+                            byte_size: bytes.len(),
+                        });
+
+                        std::str::from_utf8(&bytes)
+                            .ok()
+                            .and_then(|s| event_from_str(&host_key, Some(received_from), s))
+                    })
+                    .map_err(|error| emit!(SyslogUdpReadError { error }));
+
+                lines_in
+                    .forward(out.clone())
+                    .map(|_| info!("finished sending"))
+            })
         }),
     )
 }
@@ -271,19 +448,43 @@ fn insert_fields_from_syslog(event: &mut Event, parsed: Message<&str>) {
         log.insert("procid", value);
     }
 
+    // The SD-IDs and parameter names may contain dots, so the path is built
+    // from literal keys.
     for element in parsed.structured_data.iter() {
         for (name, value) in element.params.iter() {
-            let key = format!("{}.{}", element.id, name);
-            log.insert(key, value.clone());
+            let path = vec![
+                PathComponent::Key(element.id.to_string()),
+                PathComponent::Key(name.to_string()),
+            ];
+            log.insert_path(path, unescape_param_value(value));
         }
     }
 }
 
+/// Parameter values escape `"`, `\` and `]` with a backslash, while other
+/// backslashes are kept, as RFC 5424 requires.
+fn unescape_param_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next)) if next == '"' || next == '\\' || next == ']' => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
 #[cfg(test)]
 mod test {
-    use super::{event_from_str, SyslogConfig};
+    use super::{event_from_str, SyslogConfig, SyslogDecoder};
     use crate::event::{self, Event};
+    use bytes::BytesMut;
     use chrono::TimeZone;
+    use tokio01::codec::Decoder;
 
     #[test]
     fn config_tcp() {
@@ -365,6 +566,39 @@ mod test {
         );
     }
 
+    #[test]
+    fn decodes_octet_counted_and_non_transparent_frames() {
+        let mut decoder = SyslogDecoder::new(16);
+        let mut buf =
+            BytesMut::from(&b"5 hello6 world\nplain\n20 discarded, too long!11 octet"[..]);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(frames, vec!["hello", "world\n", "plain"]);
+
+        // The rest of the last frame is awaited.
+        buf.extend_from_slice(b" count");
+        assert_eq!(
+            decoder.decode(&mut buf).unwrap(),
+            Some("octet count".into())
+        );
+        assert_eq!(decoder.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_escaped_and_dotted_structured_data() {
+        let raw = format!(
+            r#"<13>1 2019-02-13T19:48:34+00:00 74794bfb6795 root 8449 - {} qwerty"#,
+            r#"[origin@1.2 x.y="a \"b\" \] \\ \d"]"#
+        );
+
+        let event = event_from_str(&"host".to_string(), None, &raw).unwrap();
+        let log = event.as_log();
+        assert_eq!(log[&"origin@1\\.2.x\\.y".into()], r#"a "b" ] \ \d"#.into());
+    }
+
     #[test]
     fn handles_incorrect_sd_element() {
         let msg = "qwerty";
@@ -0,0 +1,88 @@
+//! Connection level access control for network sources, by the address of
+//! the peer.
+
+use crate::{internal_events::PeerDenied, tls::MaybeTlsIncomingStream};
+use futures01::Stream;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tokio01::net::TcpStream;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AccessConfig {
+    /// When not empty, only the peers in one of these networks are let in.
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    /// The peers in one of these networks are kept out, even if allowed.
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+}
+
+impl AccessConfig {
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        let ip = unmap(ip);
+        (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip)))
+            && !self.deny.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether the peer is let in, reporting it otherwise.
+    pub fn admits(&self, peer_addr: SocketAddr) -> bool {
+        let allowed = self.allows(peer_addr.ip());
+        if !allowed {
+            emit!(PeerDenied { peer_addr });
+        }
+        allowed
+    }
+
+    /// Closes the connections of the peers kept out as they are accepted,
+    /// before any TLS handshake.
+    pub fn filter_incoming<I>(self, incoming: I) -> impl Stream<Item = I::Item, Error = I::Error>
+    where
+        I: Stream<Item = MaybeTlsIncomingStream<TcpStream>>,
+    {
+        incoming.filter(move |stream| self.admits(stream.peer_addr()))
+    }
+}
+
+/// Dual stack sockets see IPv4 peers as IPv4-mapped IPv6 addresses, which
+/// are matched as the IPv4 addresses they are.
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => IpAddr::V4(Ipv4Addr::new(
+                (high >> 8) as u8,
+                high as u8,
+                (low >> 8) as u8,
+                low as u8,
+            )),
+            _ => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_allows_and_denies_networks() {
+        let config: AccessConfig = toml::from_str(
+            r#"
+            allow = ["10.0.0.0/8", "2001:db8::/32"]
+            deny = ["10.1.0.0/16"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.allows("10.2.3.4".parse().unwrap()));
+        assert!(config.allows("::ffff:10.2.3.4".parse().unwrap()));
+        assert!(config.allows("2001:db8::1".parse().unwrap()));
+        assert!(!config.allows("10.1.3.4".parse().unwrap()));
+        assert!(!config.allows("192.168.0.1".parse().unwrap()));
+
+        assert!(AccessConfig::default().allows("192.168.0.1".parse().unwrap()));
+        assert!(toml::from_str::<AccessConfig>(r#"allow = ["10.0.0.0/33"]"#).is_err());
+    }
+}
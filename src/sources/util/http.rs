@@ -1,16 +1,19 @@
+use super::{retry_after_secs, AccessConfig, EventSender, Quota, QuotaConfig, SendError};
 use crate::event::Event;
 use crate::{
     shutdown::ShutdownSignal,
     tls::{MaybeTlsSettings, TlsConfig},
 };
-use futures01::{sync::mpsc, Future, IntoFuture, Sink};
+use bytes::Buf;
+use futures01::{Future, IntoFuture};
 use serde::Serialize;
 use std::error::Error;
 use std::fmt::{self, Display};
 use std::net::SocketAddr;
+use std::time::Duration;
 use warp::filters::{body::FullBody, BoxedFilter};
 use warp::http::{HeaderMap, StatusCode};
-use warp::{Filter, Rejection};
+use warp::{Filter, Rejection, Reply};
 
 #[derive(Serialize, Debug)]
 pub struct ErrorMessage {
@@ -32,6 +35,17 @@ impl Display for ErrorMessage {
     }
 }
 
+#[derive(Debug)]
+struct QuotaExceeded {
+    retry_after: Duration,
+}
+impl Error for QuotaExceeded {}
+impl Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Quota exceeded, retry after {:?}", self.retry_after)
+    }
+}
+
 pub trait HttpSource: Clone + Send + Sync + 'static {
     fn build_event(
         &self,
@@ -39,14 +53,18 @@ pub trait HttpSource: Clone + Send + Sync + 'static {
         header_map: HeaderMap,
     ) -> Result<Vec<Event>, ErrorMessage>;
 
+    #[allow(clippy::too_many_arguments)]
     fn run(
         self,
         address: SocketAddr,
         path: &'static str,
         tls: &Option<TlsConfig>,
-        out: mpsc::Sender<Event>,
+        access: &AccessConfig,
+        quota: &Option<QuotaConfig>,
+        out: EventSender,
         shutdown: ShutdownSignal,
     ) -> crate::Result<crate::sources::Source> {
+        let quota = quota.as_ref().map(Quota::new);
         let mut filter: BoxedFilter<()> = warp::post2().boxed();
         if !path.is_empty() && path != "/" {
             for s in path.split('/') {
@@ -57,36 +75,63 @@ pub trait HttpSource: Clone + Send + Sync + 'static {
             .and(warp::path::end())
             .and(warp::header::headers_cloned())
             .and(warp::body::concat())
-            .and_then(move |headers: HeaderMap, body| {
+            .and_then(move |headers: HeaderMap, body: FullBody| {
                 let out = out.clone();
                 info!("Handling http request: {:?}", headers);
 
+                let byte_size = body.remaining();
                 self.build_event(body, headers)
                     .map_err(warp::reject::custom)
+                    .and_then(|events| match &quota {
+                        Some(quota) => quota
+                            .take(events.len(), byte_size)
+                            .map(|()| events)
+                            .map_err(|retry_after| {
+                                warp::reject::custom(QuotaExceeded { retry_after })
+                            }),
+                        None => Ok(events),
+                    })
                     .into_future()
-                    .and_then(|events| {
-                        out.send_all(futures01::stream::iter_ok(events)).map_err(
-                            move |e: mpsc::SendError<Event>| {
-                                // can only fail if receiving end disconnected, so we are shuting down,
-                                // probably not gracefully.
+                    .and_then(move |events| {
+                        out.send(events).map_err(|error| match error {
+                            // can only fail if receiving end disconnected, so we are shuting down,
+                            // probably not gracefully.
+                            SendError::PipelineClosed => {
                                 error!("Failed to forward events, downstream is closed");
-                                error!("Tried to send the following event: {:?}", e);
-
                                 warp::reject::custom("shutting down")
-                            },
-                        )
+                            }
+                            error => {
+                                error!(message = "Failed to record events", %error);
+                                warp::reject::custom(ErrorMessage::new(
+                                    StatusCode::SERVICE_UNAVAILABLE,
+                                    error.to_string(),
+                                ))
+                            }
+                        })
                     })
                     .map(|_| warp::reply())
             });
 
         let ping = warp::get2().and(warp::path("ping")).map(|| "pong");
         let routes = svc.or(ping).recover(|r: Rejection| {
-            if let Some(e_msg) = r.find_cause::<ErrorMessage>() {
+            if let Some(exceeded) = r.find_cause::<QuotaExceeded>() {
+                let json = warp::reply::json(&ErrorMessage::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    exceeded.to_string(),
+                ));
+                Ok(warp::reply::with_header(
+                    warp::reply::with_status(json, StatusCode::TOO_MANY_REQUESTS),
+                    "Retry-After",
+                    retry_after_secs(exceeded.retry_after).to_string(),
+                )
+                .into_response())
+            } else if let Some(e_msg) = r.find_cause::<ErrorMessage>() {
                 let json = warp::reply::json(e_msg);
                 Ok(warp::reply::with_status(
                     json,
                     StatusCode::from_u16(e_msg.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-                ))
+                )
+                .into_response())
             } else {
                 //other internal error - will return 500 internal server error
                 Err(r)
@@ -97,7 +142,9 @@ pub trait HttpSource: Clone + Send + Sync + 'static {
         info!(message = "building http server", addr = %address);
 
         let tls = MaybeTlsSettings::from_config(tls, true)?;
-        let incoming = tls.bind(&address)?.incoming();
+        let incoming = access
+            .clone()
+            .filter_incoming(tls.bind(&address)?.incoming());
 
         let server = warp::serve(routes)
             .serve_incoming_with_graceful_shutdown(incoming, shutdown.clone().map(|_| ()));
@@ -0,0 +1,24 @@
+use crate::tls::{tls_connector_builder, MaybeTlsSettings, TlsOptions, TlsSettings};
+use hyper::{client::HttpConnector, Body, Client};
+use hyper_openssl::HttpsConnector;
+
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>, Body>;
+
+/// Builds a client for sources pulling over HTTP, or over HTTPS
+/// configured by the TLS options.
+pub fn https_client(tls: &Option<TlsOptions>) -> crate::Result<HttpsClient> {
+    let mut http = HttpConnector::new(4);
+    http.enforce_http(false);
+
+    let tls = MaybeTlsSettings::from(TlsSettings::from_options(tls)?);
+    let mut https = HttpsConnector::with_connector(http, tls_connector_builder(&tls)?)?;
+    let settings = tls.tls().cloned();
+    https.set_callback(move |c, _uri| {
+        if let Some(settings) = &settings {
+            settings.apply_connect_configuration(c);
+        }
+        Ok(())
+    });
+
+    Ok(Client::builder().build(https))
+}
@@ -1,14 +1,70 @@
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-socket",
+    feature = "sources-splunk_hec"
+))]
+mod access;
 #[cfg(feature = "sources-http")]
 mod http;
+#[cfg(any(
+    feature = "sources-azure_event_hubs",
+    feature = "sources-gcp_pubsub",
+    feature = "sources-http_scrape",
+    feature = "sources-prometheus"
+))]
+mod http_client;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+mod quota;
 #[cfg(feature = "sources-socket")]
 mod tcp;
+#[cfg(feature = "sources-socket")]
+mod udp;
 #[cfg(all(unix, feature = "sources-socket"))]
 mod unix;
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+mod wal;
 
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-socket",
+    feature = "sources-splunk_hec"
+))]
+pub use self::access::AccessConfig;
 #[cfg(feature = "sources-http")]
 pub use self::http::{ErrorMessage, HttpSource};
+#[cfg(any(
+    feature = "sources-azure_event_hubs",
+    feature = "sources-gcp_pubsub",
+    feature = "sources-http_scrape",
+    feature = "sources-prometheus"
+))]
+pub use self::http_client::{https_client, HttpsClient};
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+pub use self::quota::{retry_after_secs, Quota, QuotaConfig};
 #[cfg(feature = "sources-socket")]
 pub use tcp::{SocketListenAddr, TcpSource};
+#[cfg(feature = "sources-socket")]
+pub use udp::{bind_udp_shards, default_shards, spawn_udp_shards, validate_shards};
 
 #[cfg(all(unix, feature = "sources-socket"))]
-pub use unix::build_unix_source;
+pub use unix::{build_unix_datagram_source, build_unix_source};
+#[cfg(any(
+    feature = "sources-http",
+    feature = "sources-logplex",
+    feature = "sources-splunk_hec"
+))]
+pub use wal::{EventSender, SendError, WalConfig};
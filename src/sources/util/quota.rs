@@ -0,0 +1,101 @@
+//! Caps what a push source accepts over a window of time, across all its
+//! senders. Requests past the quota are refused with a 429 status and a
+//! `Retry-After` header, until the window ends.
+
+use crate::internal_events::QuotaExceeded;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    pub max_events: Option<u64>,
+    pub max_bytes: Option<u64>,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+}
+
+fn default_window_secs() -> u64 {
+    1
+}
+
+#[derive(Clone)]
+pub struct Quota {
+    max_events: u64,
+    max_bytes: u64,
+    window: Duration,
+    used: Arc<Mutex<Used>>,
+}
+
+struct Used {
+    since: Instant,
+    events: u64,
+    bytes: u64,
+}
+
+impl Quota {
+    pub fn new(config: &QuotaConfig) -> Self {
+        Self {
+            max_events: config.max_events.unwrap_or_else(u64::max_value),
+            max_bytes: config.max_bytes.unwrap_or_else(u64::max_value),
+            window: Duration::from_secs(config.window_secs.max(1)),
+            used: Arc::new(Mutex::new(Used {
+                since: Instant::now(),
+                events: 0,
+                bytes: 0,
+            })),
+        }
+    }
+
+    /// Counts a request against the quota, unless the quota was used up
+    /// already, returning then how long until the window ends. The request
+    /// using the quota up is let through whole.
+    pub fn take(&self, events: usize, bytes: usize) -> Result<(), Duration> {
+        let mut used = self.used.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(used.since);
+        if elapsed >= self.window {
+            *used = Used {
+                since: now,
+                events: 0,
+                bytes: 0,
+            };
+        } else if used.events >= self.max_events || used.bytes >= self.max_bytes {
+            let retry_after = self.window - elapsed;
+            emit!(QuotaExceeded { retry_after });
+            return Err(retry_after);
+        }
+        used.events += events as u64;
+        used.bytes += bytes as u64;
+        Ok(())
+    }
+}
+
+/// The value of the `Retry-After` header, rounded up to whole seconds.
+pub fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + if retry_after.subsec_nanos() > 0 { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quota_refuses_requests_once_used_up() {
+        let quota = Quota::new(&QuotaConfig {
+            max_events: Some(10),
+            max_bytes: None,
+            window_secs: 60,
+        });
+
+        assert!(quota.take(6, 100).is_ok());
+        assert!(quota.take(6, 100).is_ok());
+        let retry_after = quota.take(1, 10).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(60));
+        assert_eq!(retry_after_secs(Duration::from_millis(59_001)), 60);
+        assert_eq!(retry_after_secs(Duration::from_secs(3)), 3);
+    }
+}
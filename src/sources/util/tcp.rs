@@ -1,3 +1,4 @@
+use super::AccessConfig;
 use crate::{
     internal_events::TcpConnectionError,
     shutdown::ShutdownSignal,
@@ -6,7 +7,7 @@ use crate::{
     Event,
 };
 use bytes::Bytes;
-use futures01::{future, stream, sync::mpsc, Async, Future, Sink, Stream};
+use futures01::{future, stream, sync::mpsc, try_ready, Async, Future, Sink, Stream};
 use listenfd::ListenFd;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
@@ -14,6 +15,7 @@ use std::{
     net::{Shutdown, SocketAddr},
     time::{Duration, Instant},
 };
+use string_cache::DefaultAtom as Atom;
 use tokio01::{
     codec::{Decoder, FramedRead},
     net::{TcpListener, TcpStream},
@@ -67,11 +69,14 @@ pub trait TcpSource: Clone + Send + 'static {
         host: Bytes,
     ) -> Option<Event>;
 
+    #[allow(clippy::too_many_arguments)]
     fn run(
         self,
         addr: SocketListenAddr,
         shutdown_timeout_secs: u64,
         tls: MaybeTlsSettings,
+        access: AccessConfig,
+        tls_client_subject_key: Option<Atom>,
         shutdown: ShutdownSignal,
         out: mpsc::Sender<Event>,
     ) -> crate::Result<crate::sources::Source> {
@@ -103,8 +108,8 @@ pub trait TcpSource: Clone + Send + 'static {
                 })
                 .shared();
 
-            let future = listener
-                .incoming()
+            let future = access
+                .filter_incoming(listener.incoming())
                 .take_until(shutdown.clone())
                 .map_err(|error| {
                     error!(
@@ -140,6 +145,7 @@ pub trait TcpSource: Clone + Send + 'static {
                             source,
                             tripwire,
                             host,
+                            tls_client_subject_key.clone(),
                             out.clone(),
                         )
                     });
@@ -152,6 +158,7 @@ pub trait TcpSource: Clone + Send + 'static {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_stream(
     span: Span,
     shutdown: ShutdownSignal,
@@ -159,10 +166,13 @@ fn handle_stream(
     source: impl TcpSource,
     tripwire: impl Future<Item = (), Error = ()> + Send + 'static,
     host: Bytes,
+    tls_client_subject_key: Option<Atom>,
     out: impl Sink<SinkItem = Event, SinkError = ()> + Send + 'static,
 ) {
     let mut shutdown = Some(shutdown);
     let mut token = None;
+    let mut client_subject = None;
+    let subject_key = tls_client_subject_key.clone();
     let mut reader = FramedRead::new(socket, source.decoder());
     let handler = stream::poll_fn(move || {
         // Gracefull shutdown procedure
@@ -190,12 +200,25 @@ fn handle_stream(
         }
 
         // Actual work
-        reader.poll()
+        let frame = try_ready!(reader.poll());
+        // Known once the handshake is done, which the first frame awaits.
+        if subject_key.is_some() && client_subject.is_none() {
+            client_subject = reader.get_ref().peer_subject().map(Bytes::from);
+        }
+        Ok(Async::Ready(
+            frame.map(|frame| (frame, client_subject.clone())),
+        ))
     })
     .take_until(tripwire)
-    .filter_map(move |frame| {
+    .filter_map(move |(frame, client_subject)| {
         let host = host.clone();
-        source.build_event(frame, host)
+        let mut event = source.build_event(frame, host)?;
+        if let (Some(key), Some(subject), Event::Log(log)) =
+            (&tls_client_subject_key, client_subject, &mut event)
+        {
+            log.insert(key.clone(), subject);
+        }
+        Some(event)
     })
     .map_err(|error| {
         emit!(TcpConnectionError { error });
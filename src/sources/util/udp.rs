@@ -0,0 +1,76 @@
+use futures01::{future, sync::oneshot, Future};
+use snafu::Snafu;
+use std::{io, net::SocketAddr};
+use tokio01::{executor::DefaultExecutor, net::UdpSocket};
+
+#[derive(Debug, Snafu)]
+pub enum ShardsError {
+    #[snafu(display("`shards` must be at least 1"))]
+    NoShards,
+    #[snafu(display("`shards` above 1 requires SO_REUSEPORT, which is only available on unix"))]
+    ReusePortUnsupported,
+}
+
+pub fn default_shards() -> usize {
+    1
+}
+
+pub fn validate_shards(shards: usize) -> Result<(), ShardsError> {
+    match shards {
+        0 => Err(ShardsError::NoShards),
+        1 => Ok(()),
+        _ if cfg!(unix) => Ok(()),
+        _ => Err(ShardsError::ReusePortUnsupported),
+    }
+}
+
+/// Binds `shards` sockets to the address. Above one they are bound with
+/// `SO_REUSEPORT`, so the kernel spreads the datagrams across them.
+pub fn bind_udp_shards(address: &SocketAddr, shards: usize) -> io::Result<Vec<UdpSocket>> {
+    if shards <= 1 {
+        return Ok(vec![UdpSocket::bind(address)?]);
+    }
+    (0..shards).map(|_| bind_reuse_port(address)).collect()
+}
+
+#[cfg(unix)]
+fn bind_reuse_port(address: &SocketAddr) -> io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    use tokio01::reactor::Handle;
+
+    let domain = if address.is_ipv4() {
+        Domain::ipv4()
+    } else {
+        Domain::ipv6()
+    };
+    let socket = Socket::new(domain, Type::dgram(), Some(Protocol::udp()))?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&(*address).into())?;
+    UdpSocket::from_std(socket.into_udp_socket(), &Handle::default())
+}
+
+#[cfg(not(unix))]
+fn bind_reuse_port(_address: &SocketAddr) -> io::Result<UdpSocket> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        ShardsError::ReusePortUnsupported.to_string(),
+    ))
+}
+
+/// Reads each socket in its own task, so shards are read in parallel.
+/// Completes once all the readers have.
+pub fn spawn_udp_shards<F, R>(
+    sockets: Vec<UdpSocket>,
+    reader: R,
+) -> impl Future<Item = (), Error = ()>
+where
+    F: Future<Item = (), Error = ()> + Send + 'static,
+    R: Fn(usize, UdpSocket) -> F,
+{
+    let readers = sockets
+        .into_iter()
+        .enumerate()
+        .map(|(shard, socket)| oneshot::spawn(reader(shard, socket), &DefaultExecutor::current()))
+        .collect::<Vec<_>>();
+    future::join_all(readers).map(|_| ())
+}
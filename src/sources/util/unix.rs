@@ -1,10 +1,18 @@
 use crate::{
     async_read::AsyncAllowReadExt, emit, event::Event, internal_events::UnixSocketError,
-    shutdown::ShutdownSignal, sources::Source, stream::StreamExt,
+    permissions::Permissions, shutdown::ShutdownSignal, sources::Source, stream::StreamExt,
 };
 use bytes::Bytes;
-use futures01::{future, sync::mpsc, Future, Sink, Stream};
-use std::path::PathBuf;
+use futures::{
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+};
+use futures01::{future, stream::iter_ok, sync::mpsc, Future, Sink, Stream};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tokio::net::UnixDatagram;
 use tokio01::{
     self,
     codec::{FramedRead, LinesCodec},
@@ -20,6 +28,7 @@ use tracing_futures::Instrument;
 **/
 pub fn build_unix_source(
     path: PathBuf,
+    permissions: Permissions,
     max_length: usize,
     host_key: String,
     shutdown: ShutdownSignal,
@@ -34,10 +43,16 @@ pub fn build_unix_source(
 
     Box::new(future::lazy(move || {
         let listener = UnixListener::bind(&path).expect("failed to bind to listener socket");
+        if let Err(error) = permissions.apply(&path) {
+            error!(message = "unable to set the permissions of the socket.", ?path, %error);
+            remove_socket(&path);
+            return future::Either::A(future::err(()));
+        }
 
         info!(message = "listening.", ?path, r#type = "unix");
 
-        listener
+        let socket_path = path.clone();
+        let accept = listener
             .incoming()
             .take_until(shutdown.clone())
             .map_err(|e| error!("failed to accept socket; error = {:?}", e))
@@ -77,5 +92,90 @@ pub fn build_unix_source(
                 let handler = lines_in.forward(out).map(|_| info!("finished sending"));
                 tokio01::spawn(handler.instrument(span))
             })
+            .then(move |result| {
+                remove_socket(&socket_path);
+                result
+            });
+        future::Either::B(accept)
     }))
 }
+
+/**
+* Returns a Source object receiving the datagrams sent to a Unix domain socket, such as the
+* `/dev/log` of syslog. Each line of a datagram is built into an event by build_event, as with
+* build_unix_source, and datagrams longer than max_length are truncated.
+**/
+pub fn build_unix_datagram_source(
+    path: PathBuf,
+    permissions: Permissions,
+    max_length: usize,
+    host_key: String,
+    shutdown: ShutdownSignal,
+    out: mpsc::Sender<Event>,
+    build_event: impl Fn(&str, Option<Bytes>, &str) -> Option<Event>
+        + std::marker::Send
+        + std::marker::Sync
+        + 'static,
+) -> Source {
+    let fut = async move {
+        let mut socket = match UnixDatagram::bind(&path) {
+            Ok(socket) => socket,
+            Err(error) => {
+                error!(message = "unable to bind to the socket.", ?path, %error);
+                return Err(());
+            }
+        };
+        if let Err(error) = permissions.apply(&path) {
+            error!(message = "unable to set the permissions of the socket.", ?path, %error);
+            remove_socket(&path);
+            return Err(());
+        }
+
+        info!(message = "listening.", ?path, r#type = "unix_datagram");
+
+        let mut out = out.sink_map_err(|e| error!("error sending line: {:?}", e));
+        let mut shutdown = shutdown.compat();
+        let mut buf = vec![0; max_length];
+        let result = loop {
+            let recv = Box::pin(socket.recv_from(&mut buf));
+            let received = match select(recv, &mut shutdown).await {
+                Either::Left((received, _)) => received,
+                Either::Right(_) => break Ok(()),
+            };
+            let (size, address) = match received {
+                Ok(received) => received,
+                Err(error) => {
+                    emit!(UnixSocketError { error, path: &path });
+                    continue;
+                }
+            };
+
+            let received_from: Option<Bytes> = address
+                .as_pathname()
+                .map(|p| p.to_string_lossy().into_owned().into());
+            let events = String::from_utf8_lossy(&buf[..size])
+                .lines()
+                .filter(|line| !line.is_empty())
+                .filter_map(|line| build_event(&host_key, received_from.clone(), line))
+                .collect::<Vec<_>>();
+
+            match out.send_all(iter_ok(events)).compat().await {
+                Ok((sink, _)) => out = sink,
+                Err(()) => break Err(()),
+            }
+        };
+
+        remove_socket(&path);
+        result
+    };
+
+    Box::new(fut.boxed().compat())
+}
+
+/// Bound sockets leave a file behind, which would keep the next source from
+/// binding to the path.
+fn remove_socket(path: &Path) {
+    if let Err(error) = fs::remove_file(path) {
+        warn!(message = "unable to remove the socket file.", ?path, %error);
+    }
+}
@@ -0,0 +1,501 @@
+//! A write-ahead log between push sources and the pipeline.
+//!
+//! The events accepted by a request are appended to the log and synced to
+//! disk before the request is answered, and forwarded to the pipeline from
+//! the log. Events not forwarded yet when Vector stops or crashes are
+//! forwarded once the source starts again.
+//!
+//! The log is split in segments, each named by the position in the log of
+//! its first record. The position up to which events were forwarded is kept
+//! in the `checkpoint` file, and the segments before it are removed.
+
+use crate::{
+    event::{proto, Event},
+    internal_events::{WalEventsRecorded, WalRecordInvalid},
+    shutdown::ShutdownSignal,
+    topology::config::GlobalOptions,
+};
+use futures::{
+    channel::mpsc as notify,
+    compat::Future01CompatExt,
+    future::{select, Either, FutureExt, TryFutureExt},
+    stream::StreamExt,
+};
+use futures01::{future, sync::mpsc, Future, Sink};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::task::spawn_blocking;
+
+/// Segments past this size are closed for a new one on the next append.
+const SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The checkpoint is saved after this many forwarded records, and when
+/// caught up.
+const CHECKPOINT_RECORDS: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum SendError {
+    #[snafu(display("Unable to write to the write-ahead log: {}", source))]
+    WriteLog { source: io::Error },
+    #[snafu(display("The write-ahead log is full, {} bytes are yet to be forwarded", size))]
+    Full { size: u64 },
+    #[snafu(display("The pipeline closed"))]
+    PipelineClosed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WalConfig {
+    /// Defaults to the global `data_dir`.
+    pub data_dir: Option<PathBuf>,
+    /// How many bytes of events may wait to be forwarded, requests are
+    /// refused beyond.
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+}
+
+fn default_max_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+/// Where push sources put the events they accepted.
+#[derive(Clone)]
+pub enum EventSender {
+    Pipeline(mpsc::Sender<Event>),
+    Wal(WalWriter),
+}
+
+impl EventSender {
+    /// Sets up the write-ahead log if configured, returning the sender and
+    /// the task forwarding from the log, which the source has to run.
+    pub fn new(
+        config: &Option<WalConfig>,
+        globals: &GlobalOptions,
+        name: &str,
+        out: mpsc::Sender<Event>,
+        shutdown: ShutdownSignal,
+    ) -> crate::Result<(Self, Box<dyn Future<Item = (), Error = ()> + Send>)> {
+        let config = match config {
+            Some(config) => config,
+            None => return Ok((EventSender::Pipeline(out), Box::new(future::ok(())))),
+        };
+        let dir = globals
+            .resolve_and_make_data_subdir(config.data_dir.as_ref(), &format!("{}_wal", name))?;
+        let log = Arc::new(Mutex::new(Log::open(dir, config.max_size)?));
+        let (wake, woken) = notify::channel(1);
+
+        let forward = forward(Arc::clone(&log), woken, out, shutdown);
+        let writer = WalWriter {
+            log,
+            appender: Arc::new(Mutex::new(Appender::default())),
+            wake,
+        };
+        Ok((EventSender::Wal(writer), Box::new(forward.boxed().compat())))
+    }
+
+    /// Resolves once the events are safe to acknowledge: synced to the
+    /// write-ahead log, or accepted by the pipeline without one.
+    pub fn send(&self, events: Vec<Event>) -> Box<dyn Future<Item = (), Error = SendError> + Send> {
+        match self {
+            EventSender::Pipeline(out) => Box::new(
+                out.clone()
+                    .send_all(futures01::stream::iter_ok(events))
+                    .map(|_| ())
+                    .map_err(|_| SendError::PipelineClosed),
+            ),
+            EventSender::Wal(writer) => {
+                let writer = writer.clone();
+                let append = async move {
+                    spawn_blocking(move || writer.append(&events))
+                        .await
+                        .map_err(|error| SendError::WriteLog {
+                            source: io::Error::new(io::ErrorKind::Other, error.to_string()),
+                        })?
+                };
+                Box::new(append.boxed().compat())
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WalWriter {
+    log: Arc<Mutex<Log>>,
+    appender: Arc<Mutex<Appender>>,
+    wake: notify::Sender<()>,
+}
+
+impl WalWriter {
+    /// Blocks until the events are synced to disk, so it's run on the
+    /// blocking pool.
+    fn append(&self, events: &[Event]) -> Result<(), SendError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let mut records = Vec::new();
+        for event in events {
+            let mut record = Vec::new();
+            proto::EventWrapper::from(event.clone())
+                .encode(&mut record)
+                .expect("writing to a Vec doesn't fail");
+            records.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            records.extend(record);
+        }
+
+        self.appender.lock().unwrap().append(&self.log, &records)?;
+        emit!(WalEventsRecorded {
+            count: events.len(),
+            byte_size: records.len(),
+        });
+        // Full when a wake up is pending already.
+        let _ = self.wake.clone().try_send(());
+        Ok(())
+    }
+}
+
+struct Log {
+    dir: PathBuf,
+    max_size: u64,
+    /// The start positions of the segments, in order.
+    segments: Vec<u64>,
+    /// The end of the last record.
+    written: u64,
+    /// The end of the last record forwarded.
+    checkpoint: u64,
+}
+
+impl Log {
+    fn open(dir: PathBuf, max_size: u64) -> io::Result<Self> {
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name();
+            let start = name
+                .to_str()
+                .and_then(|name| name.trim_end_matches(".log").parse::<u64>().ok());
+            if let Some(start) = start {
+                segments.push(start);
+            }
+        }
+        segments.sort();
+
+        let checkpoint = match fs::read_to_string(dir.join("checkpoint")) {
+            Ok(checkpoint) => checkpoint.trim().parse().unwrap_or(0),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        let first = segments.first().copied().unwrap_or(0);
+
+        let mut log = Self {
+            dir,
+            max_size,
+            segments,
+            written: u64::max_value(),
+            checkpoint: checkpoint.max(first),
+        };
+        // The log ends with the last complete record, a crash may have cut
+        // the one after short.
+        log.written = match log.segments.last() {
+            Some(&start) => {
+                let mut reader = Reader::new(start);
+                while let Some(Ok(_)) = reader.next_record(&log) {}
+                reader.position
+            }
+            None => 0,
+        };
+        Ok(log)
+    }
+
+    fn segment_path(&self, start: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.log", start))
+    }
+
+    /// The segment holding the position, and where it ends.
+    fn segment(&self, position: u64) -> Option<(u64, u64)> {
+        let index = match self.segments.binary_search(&position) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let end = self
+            .segments
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.written);
+        Some((self.segments[index], end))
+    }
+
+    fn save_checkpoint(&mut self, position: u64) -> io::Result<()> {
+        self.checkpoint = position;
+        // Written aside first, so it is never read partially.
+        let path = self.dir.join("checkpoint");
+        let partial = self.dir.join("checkpoint.partial");
+        fs::write(&partial, position.to_string())?;
+        fs::rename(&partial, &path)?;
+
+        while self.segments.len() > 1 && self.segments[1] <= position {
+            let start = self.segments.remove(0);
+            fs::remove_file(self.segment_path(start))?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends to the log, one append at a time. The log is only locked to
+/// account for the records, not while they are written and synced, so
+/// forwarding goes on in the meantime.
+#[derive(Default)]
+struct Appender {
+    /// The segment appended to and its start, opened on the first append so
+    /// records never follow an incomplete one.
+    segment: Option<(u64, File)>,
+}
+
+impl Appender {
+    fn append(&mut self, log: &Mutex<Log>, records: &[u8]) -> Result<(), SendError> {
+        // Only the appender moves the end of the log, so it holds while the
+        // log is unlocked.
+        let written = {
+            let mut log = log.lock().unwrap();
+            let size = log.written.saturating_sub(log.checkpoint);
+            if size + records.len() as u64 > log.max_size {
+                return Err(SendError::Full { size });
+            }
+
+            let full = self
+                .segment
+                .as_ref()
+                .map_or(true, |(start, _)| log.written - start >= SEGMENT_SIZE);
+            if full {
+                let written = log.written;
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log.segment_path(written))
+                    .context(WriteLog)?;
+                if log.segments.last() == Some(&written) {
+                    // Holds no complete record, at most one cut short.
+                    file.set_len(0).context(WriteLog)?;
+                } else {
+                    log.segments.push(written);
+                }
+                self.segment = Some((written, file));
+            }
+            log.written
+        };
+
+        let (_, file) = self.segment.as_mut().expect("opened above");
+        let result = file.write_all(records).and_then(|_| file.sync_data());
+        if let Err(error) = result {
+            // The records may be partially written, the next append starts
+            // a new segment past them.
+            self.segment = None;
+            return Err(SendError::WriteLog { source: error });
+        }
+        log.lock().unwrap().written = written + records.len() as u64;
+        Ok(())
+    }
+}
+
+/// Reads the records of the log in order.
+struct Reader {
+    position: u64,
+    file: Option<(u64, File)>,
+}
+
+impl Reader {
+    fn new(position: u64) -> Self {
+        Self {
+            position,
+            file: None,
+        }
+    }
+
+    /// The next record, `None` at the end of the log.
+    fn next_record(&mut self, log: &Log) -> Option<io::Result<Vec<u8>>> {
+        let (start, end) = log.segment(self.position)?;
+        if self.position >= end {
+            return None;
+        }
+        let result = self.read(log, start, end);
+        if result.is_err() {
+            self.file = None;
+        }
+        Some(result)
+    }
+
+    fn skip_segment(&mut self, log: &Log) {
+        if let Some((_, end)) = log.segment(self.position) {
+            self.position = end;
+        }
+    }
+
+    fn read(&mut self, log: &Log, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        if self.file.as_ref().map(|(opened, _)| *opened) != Some(start) {
+            let mut file = File::open(log.segment_path(start))?;
+            file.seek(SeekFrom::Start(self.position - start))?;
+            self.file = Some((start, file));
+        }
+        let file = &mut self.file.as_mut().expect("opened above").1;
+
+        let mut length = [0; 4];
+        file.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as u64;
+        if self.position + 4 + length > end {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "record ends past its segment",
+            ));
+        }
+        let mut record = vec![0; length.try_into().expect("records fit in memory")];
+        file.read_exact(&mut record)?;
+        self.position += 4 + length;
+        Ok(record)
+    }
+}
+
+async fn forward(
+    log: Arc<Mutex<Log>>,
+    mut woken: notify::Receiver<()>,
+    mut out: mpsc::Sender<Event>,
+    shutdown: ShutdownSignal,
+) -> Result<(), ()> {
+    let mut shutdown = shutdown.compat();
+    let mut reader = Reader::new(log.lock().unwrap().checkpoint);
+    let mut forwarded = 0;
+    loop {
+        let record = reader.next_record(&log.lock().unwrap());
+        let record = match record {
+            Some(record) => record,
+            None => {
+                if forwarded > 0 {
+                    checkpoint(&log, reader.position);
+                    forwarded = 0;
+                }
+                // Records not forwarded yet stay in the log for the next
+                // start.
+                match select(woken.next(), &mut shutdown).await {
+                    Either::Left((Some(()), _)) => continue,
+                    _ => return Ok(()),
+                }
+            }
+        };
+
+        let event = record
+            .and_then(|record| {
+                proto::EventWrapper::decode(record)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+            })
+            .map(Event::from);
+        match event {
+            Ok(event) => {
+                let send = out.send(event).compat();
+                out = match select(send, &mut shutdown).await {
+                    Either::Left((Ok(out), _)) => out,
+                    Either::Left((Err(_), _)) => {
+                        error!(message = "error sending event.");
+                        return Err(());
+                    }
+                    Either::Right(_) => return Ok(()),
+                };
+            }
+            // Also when a record can't be read, what follows it in the
+            // segment can't either.
+            Err(error) => {
+                emit!(WalRecordInvalid { error });
+                reader.skip_segment(&log.lock().unwrap());
+            }
+        }
+
+        forwarded += 1;
+        if forwarded >= CHECKPOINT_RECORDS {
+            checkpoint(&log, reader.position);
+            forwarded = 0;
+        }
+    }
+}
+
+fn checkpoint(log: &Mutex<Log>, position: u64) {
+    if let Err(error) = log.lock().unwrap().save_checkpoint(position) {
+        error!(message = "unable to save the checkpoint of the write-ahead log.", %error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(messages: &[&str]) -> Vec<u8> {
+        let mut records = Vec::new();
+        for message in messages {
+            let mut record = Vec::new();
+            proto::EventWrapper::from(Event::from(*message))
+                .encode(&mut record)
+                .unwrap();
+            records.extend_from_slice(&(record.len() as u32).to_le_bytes());
+            records.extend(record);
+        }
+        records
+    }
+
+    fn read_all(log: &Log) -> Vec<Event> {
+        let mut reader = Reader::new(log.checkpoint);
+        let mut events = Vec::new();
+        while let Some(record) = reader.next_record(log) {
+            events.push(Event::from(
+                proto::EventWrapper::decode(record.unwrap()).unwrap(),
+            ));
+        }
+        events
+    }
+
+    #[test]
+    fn wal_replays_from_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = Mutex::new(Log::open(dir.path().into(), 1024).unwrap());
+        let mut appender = Appender::default();
+        appender
+            .append(&log, &records(&["first", "second"]))
+            .unwrap();
+
+        let mut reader = Reader::new(0);
+        reader.next_record(&log.lock().unwrap()).unwrap().unwrap();
+        log.lock()
+            .unwrap()
+            .save_checkpoint(reader.position)
+            .unwrap();
+        appender.append(&log, &records(&["third"])).unwrap();
+        drop(appender);
+        drop(log);
+
+        // The record cut short by a crash is ignored.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(format!("{:020}.log", 0)))
+            .unwrap();
+        file.write_all(&[200, 0, 0, 0, 1]).unwrap();
+
+        let log = Mutex::new(Log::open(dir.path().into(), 1024).unwrap());
+        let events = read_all(&log.lock().unwrap());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].as_log()[&"message".into()], "second".into());
+        assert_eq!(events[1].as_log()[&"message".into()], "third".into());
+
+        let mut appender = Appender::default();
+        appender.append(&log, &records(&["fourth"])).unwrap();
+        assert_eq!(log.lock().unwrap().segments.len(), 2);
+        assert_eq!(read_all(&log.lock().unwrap()).len(), 3);
+
+        match appender.append(&log, &vec![0; 1024]) {
+            Err(SendError::Full { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
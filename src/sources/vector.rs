@@ -52,7 +52,15 @@ impl SourceConfig for VectorConfig {
     ) -> crate::Result<super::Source> {
         let vector = VectorSource;
         let tls = MaybeTlsSettings::from_config(&self.tls, true)?;
-        vector.run(self.address, self.shutdown_timeout_secs, tls, shutdown, out)
+        vector.run(
+            self.address,
+            self.shutdown_timeout_secs,
+            tls,
+            Default::default(),
+            None,
+            shutdown,
+            out,
+        )
     }
 
     fn output_type(&self) -> DataType {
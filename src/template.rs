@@ -5,8 +5,9 @@ use crate::{
 use bytes::Bytes;
 use chrono::{
     format::{strftime::StrftimeItems, Item},
-    Utc,
+    DateTime, Local, Utc,
 };
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 use serde::{
@@ -15,6 +16,7 @@ use serde::{
 };
 use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 use string_cache::DefaultAtom as Atom;
 
 lazy_static! {
@@ -27,6 +29,70 @@ pub struct Template {
     src_bytes: Bytes,
     has_ts: bool,
     has_fields: bool,
+    timezone: TimeZone,
+}
+
+/// The time zone timestamps are rendered in, either the local time zone of
+/// the host or a zone of the IANA database such as `Europe/Paris`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeZone {
+    Local,
+    Named(Tz),
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::Named(Tz::UTC)
+    }
+}
+
+impl TimeZone {
+    /// Formats the timestamp, in this time zone, with a strftime format.
+    pub fn format(self, timestamp: DateTime<Utc>, format: &str) -> String {
+        match self {
+            TimeZone::Local => timestamp.with_timezone(&Local).format(format).to_string(),
+            TimeZone::Named(tz) => timestamp.with_timezone(&tz).format(format).to_string(),
+        }
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(TimeZone::Local),
+            _ => s.parse().map(TimeZone::Named),
+        }
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeZone::Local => write!(f, "local"),
+            TimeZone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for TimeZone {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
 }
 
 impl From<&str> for Template {
@@ -36,6 +102,7 @@ impl From<&str> for Template {
             src_bytes: src.into(),
             has_ts: StrftimeItems::new(src).filter(is_dynamic).count() > 0,
             has_fields: RE.is_match(src),
+            timezone: TimeZone::default(),
         }
     }
 }
@@ -63,14 +130,21 @@ impl From<String> for Template {
 }
 
 impl Template {
+    /// Renders the timestamps of events in the given time zone rather than
+    /// in UTC.
+    pub fn with_timezone(mut self, timezone: TimeZone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
     pub fn render(&self, event: &Event) -> Result<Bytes, Vec<Atom>> {
         match (self.has_fields, self.has_ts) {
             (false, false) => Ok(self.src_bytes.clone()),
             (true, false) => render_fields(&self.src, event).map(Bytes::from),
-            (false, true) => Ok(render_timestamp(&self.src, event).into()),
+            (false, true) => Ok(render_timestamp(&self.src, event, self.timezone).into()),
             (true, true) => {
                 let tmp = render_fields(&self.src, event)?;
-                Ok(render_timestamp(&tmp, event).into())
+                Ok(render_timestamp(&tmp, event, self.timezone).into())
             }
         }
     }
@@ -127,18 +201,16 @@ fn render_fields(src: &str, event: &Event) -> Result<String, Vec<Atom>> {
     }
 }
 
-fn render_timestamp(src: &str, event: &Event) -> String {
+fn render_timestamp(src: &str, event: &Event, timezone: TimeZone) -> String {
     let timestamp = match event {
         Event::Log(log) => log
             .get(&event::log_schema().timestamp_key())
-            .and_then(Value::as_timestamp),
+            .and_then(Value::as_timestamp)
+            .cloned(),
         _ => None,
-    };
-    if let Some(ts) = timestamp {
-        ts.format(src).to_string()
-    } else {
-        Utc::now().format(src).to_string()
     }
+    .unwrap_or_else(Utc::now);
+    timezone.format(timestamp, src)
 }
 
 impl<'de> Deserialize<'de> for Template {
@@ -181,7 +253,7 @@ impl Serialize for Template {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::TimeZone;
+    use chrono::TimeZone as _;
 
     #[test]
     fn get_fields() {
@@ -360,4 +432,25 @@ mod tests {
             template.render(&event)
         )
     }
+
+    #[test]
+    fn render_timestamp_in_timezone() {
+        let ts = Utc.ymd(2001, 2, 3).and_hms(23, 5, 6);
+
+        let mut event = Event::from("hello world");
+        event
+            .as_mut_log()
+            .insert(crate::event::log_schema().timestamp_key().clone(), ts);
+
+        let timezone = "Asia/Tokyo".parse().unwrap();
+        let template = Template::from("date=%F/%H").with_timezone(timezone);
+
+        assert_eq!(
+            Ok(Bytes::from("date=2001-02-04/08")),
+            template.render(&event)
+        );
+        assert_eq!(timezone.to_string(), "Asia/Tokyo");
+        assert_eq!("local".parse(), Ok(TimeZone::Local));
+        assert!("Mars/Olympus_Mons".parse::<TimeZone>().is_err());
+    }
 }
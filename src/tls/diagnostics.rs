@@ -0,0 +1,258 @@
+use crate::internal_events::TlsPeerVerificationFailed;
+use openssl::{
+    nid::Nid,
+    x509::{X509NameRef, X509Ref, X509StoreContextRef},
+};
+use std::fmt;
+
+// The verification errors diagnosed, from `openssl/x509_vfy.h`.
+const UNABLE_TO_GET_ISSUER_CERT: i32 = 2;
+const CERT_NOT_YET_VALID: i32 = 9;
+const CERT_HAS_EXPIRED: i32 = 10;
+const DEPTH_ZERO_SELF_SIGNED_CERT: i32 = 18;
+const SELF_SIGNED_CERT_IN_CHAIN: i32 = 19;
+const UNABLE_TO_GET_ISSUER_CERT_LOCALLY: i32 = 20;
+const UNABLE_TO_VERIFY_LEAF_SIGNATURE: i32 = 21;
+const HOSTNAME_MISMATCH: i32 = 62;
+
+/// Reports why the certificate chain of a peer failed to be verified,
+/// rather than leaving it to the bare error code of the failed handshake.
+pub(super) fn verify_callback(preverify_ok: bool, context: &mut X509StoreContextRef) -> bool {
+    if !preverify_ok {
+        // Returning false ends the handshake, so this is reported once.
+        emit!(TlsPeerVerificationFailed {
+            failure: VerifyFailure::from_context(context),
+        });
+    }
+    preverify_ok
+}
+
+#[derive(Debug)]
+pub struct VerifyFailure {
+    pub reason: &'static str,
+    pub depth: u32,
+    pub hint: Option<String>,
+    /// The chain of the peer, from its own certificate to the last sent.
+    pub chain: Vec<CertificateSummary>,
+}
+
+impl VerifyFailure {
+    fn from_context(context: &X509StoreContextRef) -> Self {
+        let chain = match context.chain() {
+            Some(chain) => chain.iter().map(CertificateSummary::new).collect(),
+            None => context
+                .current_cert()
+                .map(CertificateSummary::new)
+                .into_iter()
+                .collect(),
+        };
+        let error = context.error();
+        Self::new(
+            error.as_raw(),
+            error.error_string(),
+            context.error_depth(),
+            chain,
+        )
+    }
+
+    fn new(error: i32, reason: &'static str, depth: u32, chain: Vec<CertificateSummary>) -> Self {
+        let hint = chain
+            .get(depth as usize)
+            .and_then(|certificate| hint(error, certificate));
+        Self {
+            reason,
+            depth,
+            hint,
+            chain,
+        }
+    }
+}
+
+impl fmt::Display for VerifyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)?;
+        if let Some(hint) = &self.hint {
+            write!(f, ": {}", hint)?;
+        }
+        let chain = self
+            .chain
+            .iter()
+            .enumerate()
+            .map(|(depth, certificate)| format!("[{}] {}", depth, certificate))
+            .collect::<Vec<_>>();
+        write!(f, ". Peer chain: {}", chain.join("; "))
+    }
+}
+
+fn hint(error: i32, certificate: &CertificateSummary) -> Option<String> {
+    match error {
+        CERT_HAS_EXPIRED => Some(format!(
+            "{:?} expired on {}",
+            certificate.subject, certificate.not_after
+        )),
+        CERT_NOT_YET_VALID => Some(format!(
+            "{:?} is not valid before {}",
+            certificate.subject, certificate.not_before
+        )),
+        HOSTNAME_MISMATCH => Some(format!(
+            "the host connected to is none of the names of {:?} ({})",
+            certificate.subject,
+            certificate.names.join(", ")
+        )),
+        UNABLE_TO_GET_ISSUER_CERT
+        | UNABLE_TO_GET_ISSUER_CERT_LOCALLY
+        | UNABLE_TO_VERIFY_LEAF_SIGNATURE => Some(format!(
+            "the issuer {:?} of {:?} is unknown; the peer may not send its intermediate \
+             certificates, or the `ca_file` lacks the issuing authority",
+            certificate.issuer, certificate.subject
+        )),
+        DEPTH_ZERO_SELF_SIGNED_CERT | SELF_SIGNED_CERT_IN_CHAIN => Some(format!(
+            "{:?} is self signed; add it to the `ca_file` to trust it",
+            certificate.subject
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    /// The DNS names and IP addresses of the subject alternative names.
+    pub names: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+}
+
+impl CertificateSummary {
+    fn new(certificate: &X509Ref) -> Self {
+        let names = certificate
+            .subject_alt_names()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| match (name.dnsname(), name.ipaddress()) {
+                        (Some(dns), _) => Some(dns.to_owned()),
+                        (None, Some(ip)) => format_ip(ip),
+                        (None, None) => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            subject: common_name(certificate.subject_name()),
+            issuer: common_name(certificate.issuer_name()),
+            names,
+            not_before: certificate.not_before().to_string(),
+            not_after: certificate.not_after().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CertificateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "subject {:?}, issuer {:?}", self.subject, self.issuer)?;
+        if !self.names.is_empty() {
+            write!(f, ", names {}", self.names.join(" "))?;
+        }
+        write!(f, ", valid from {} to {}", self.not_before, self.not_after)
+    }
+}
+
+fn common_name(name: &X509NameRef) -> String {
+    name.entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|name| name.to_string())
+        .unwrap_or_default()
+}
+
+/// Formats a name as its attributes in order, e.g.
+/// `O=Acme,OU=Web,CN=web-1`.
+#[cfg(feature = "sources-tls")]
+pub(super) fn distinguished_name(name: &X509NameRef) -> String {
+    name.entries()
+        .filter_map(|entry| {
+            let attribute = entry.object().nid().short_name().ok()?;
+            let value = entry.data().as_utf8().ok()?;
+            Some(format!("{}={}", attribute, value))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn format_ip(ip: &[u8]) -> Option<String> {
+    use std::{
+        convert::TryFrom,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    match ip.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(ip).ok()?).to_string()),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(ip).ok()?).to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::x509::X509;
+
+    fn localhost() -> CertificateSummary {
+        let pem = std::fs::read("tests/data/localhost.crt").unwrap();
+        CertificateSummary::new(&X509::from_pem(&pem).unwrap())
+    }
+
+    #[test]
+    fn summarizes_certificates() {
+        let summary = localhost();
+        assert_eq!(summary.subject, "localhost");
+        assert_eq!(summary.issuer, "Timber.io Vector Test CA");
+        assert_eq!(summary.not_after, "Feb  3 22:03:14 2047 GMT");
+    }
+
+    #[cfg(feature = "sources-tls")]
+    #[test]
+    fn formats_distinguished_names() {
+        let pem = std::fs::read("tests/data/localhost.crt").unwrap();
+        let certificate = X509::from_pem(&pem).unwrap();
+        assert_eq!(
+            distinguished_name(certificate.subject_name()),
+            "CN=localhost"
+        );
+        assert_eq!(
+            distinguished_name(certificate.issuer_name()),
+            "C=US,ST=New York,L=Brooklyn,O=Timber.io,CN=Timber.io Vector Test CA"
+        );
+    }
+
+    #[test]
+    fn hints_at_the_cause() {
+        let failure = VerifyFailure::new(
+            UNABLE_TO_GET_ISSUER_CERT_LOCALLY,
+            "unable to get local issuer certificate",
+            0,
+            vec![localhost()],
+        );
+        assert_eq!(
+            failure.to_string(),
+            "unable to get local issuer certificate: the issuer \"Timber.io Vector Test CA\" \
+             of \"localhost\" is unknown; the peer may not send its intermediate certificates, \
+             or the `ca_file` lacks the issuing authority. Peer chain: [0] subject \
+             \"localhost\", issuer \"Timber.io Vector Test CA\", valid from Sep 19 22:03:14 \
+             2019 GMT to Feb  3 22:03:14 2047 GMT"
+        );
+
+        let failure = VerifyFailure::new(
+            CERT_HAS_EXPIRED,
+            "certificate has expired",
+            0,
+            vec![localhost()],
+        );
+        assert_eq!(
+            failure.hint.unwrap(),
+            "\"localhost\" expired on Feb  3 22:03:14 2047 GMT"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use super::{
-    CreateAcceptor, IncomingListener, MaybeTlsSettings, MaybeTlsStream, PeerAddress, Result,
-    TcpBind, TlsError, TlsSettings,
+    diagnostics::distinguished_name, handshake_error, CreateAcceptor, IncomingListener,
+    MaybeTlsSettings, MaybeTlsStream, PeerAddress, Result, TcpBind, TlsError, TlsSettings,
 };
 use futures01::{try_ready, Async, Future, Stream};
 use openssl::ssl::{HandshakeError, SslAcceptor, SslMethod};
@@ -124,6 +124,18 @@ impl<S> MaybeTlsIncomingStream<S> {
         self.peer_addr
     }
 
+    /// The subject of the certificate the peer authenticated with, once
+    /// the handshake is done.
+    pub fn peer_subject(&self) -> Option<String> {
+        match &self.state {
+            StreamState::Accepted(stream) => {
+                let certificate = stream.tls()?.get_ref().ssl().peer_certificate()?;
+                Some(distinguished_name(certificate.subject_name()))
+            }
+            StreamState::Accepting(_) => None,
+        }
+    }
+
     /// None if connection still hasen't been established.
     pub fn get_ref(&self) -> Option<&S> {
         match &self.state {
@@ -163,12 +175,9 @@ fn poll_handshake<S: Read + Write>(acceptor: &mut AcceptAsync<S>) -> io::Result<
                 ErrorKind::WouldBlock,
                 TlsError::HandshakeNotReady,
             )),
-            HandshakeError::Failure(stream) => Err(io::Error::new(
-                ErrorKind::Other,
-                TlsError::Handshake {
-                    source: stream.into_error(),
-                },
-            )),
+            HandshakeError::Failure(stream) => {
+                Err(io::Error::new(ErrorKind::Other, handshake_error(stream)))
+            }
             HandshakeError::SetupFailure(source) => Err(io::Error::new(
                 ErrorKind::Other,
                 TlsError::HandshakeSetup { source },
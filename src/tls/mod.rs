@@ -1,6 +1,9 @@
 use openssl::{
     error::ErrorStack,
-    ssl::{ConnectConfiguration, SslConnector, SslConnectorBuilder, SslMethod},
+    ssl::{
+        ConnectConfiguration, MidHandshakeSslStream, SslConnector, SslConnectorBuilder, SslMethod,
+    },
+    x509::X509VerifyResult,
 };
 use snafu::{ResultExt, Snafu};
 use std::fmt::Debug;
@@ -10,12 +13,14 @@ use std::path::PathBuf;
 use tokio01::net::TcpStream;
 use tokio_openssl::SslStream;
 
+mod diagnostics;
 #[cfg(feature = "sources-tls")]
 mod incoming;
 mod maybe_tls;
 mod outgoing;
 mod settings;
 
+pub use diagnostics::{CertificateSummary, VerifyFailure};
 #[cfg(feature = "sources-tls")]
 pub(crate) use incoming::{MaybeTlsIncomingStream, MaybeTlsListener};
 pub(crate) use maybe_tls::MaybeTls;
@@ -46,6 +51,8 @@ pub enum TlsError {
     TlsIdentityError { source: ErrorStack },
     #[snafu(display("Could not export identity to DER: {}", source))]
     DerExportError { source: ErrorStack },
+    #[snafu(display("Could not export certificate to PEM: {}", source))]
+    PemExportError { source: ErrorStack },
     #[snafu(display("Identity certificate is missing a key"))]
     MissingKey,
     #[snafu(display("Could not parse certificate in {:?}: {}", filename, source))]
@@ -76,6 +83,8 @@ pub enum TlsError {
     MissingRequiredIdentity,
     #[snafu(display("TLS handshake failed: {}", source))]
     Handshake { source: openssl::ssl::Error },
+    #[snafu(display("TLS handshake failed, the peer certificate is invalid: {}", reason))]
+    HandshakeVerify { reason: &'static str },
     #[snafu(display("Not ready for I/O during TLS handshake"))]
     HandshakeNotReady,
     #[snafu(display("TLS handshake setup failed: {}", source))]
@@ -126,6 +135,21 @@ impl MaybeTlsStream<TcpStream> {
     }
 }
 
+/// The error of a failed handshake, naming why the peer certificate is
+/// invalid when it is the cause rather than the bare openssl error.
+fn handshake_error<S>(stream: MidHandshakeSslStream<S>) -> TlsError {
+    let result = stream.ssl().verify_result();
+    if result == X509VerifyResult::OK {
+        TlsError::Handshake {
+            source: stream.into_error(),
+        }
+    } else {
+        TlsError::HandshakeVerify {
+            reason: result.error_string(),
+        }
+    }
+}
+
 pub(crate) fn tls_connector_builder(settings: &MaybeTlsSettings) -> Result<SslConnectorBuilder> {
     let mut builder = SslConnector::builder(SslMethod::tls()).context(TlsBuildConnector)?;
     if let Some(settings) = settings.tls() {
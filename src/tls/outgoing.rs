@@ -1,4 +1,4 @@
-use super::{tls_connector, MaybeTlsSettings, MaybeTlsStream, Result, TlsError};
+use super::{handshake_error, tls_connector, MaybeTlsSettings, MaybeTlsStream, Result, TlsError};
 use futures01::{Async, Future};
 use openssl::ssl::{ConnectConfiguration, HandshakeError};
 use std::net::SocketAddr;
@@ -55,9 +55,7 @@ impl Future for MaybeTlsConnector {
                     Err(error) => {
                         return Err(match error {
                             HandshakeError::WouldBlock(_) => TlsError::HandshakeNotReady,
-                            HandshakeError::Failure(stream) => TlsError::Handshake {
-                                source: stream.into_error(),
-                            },
+                            HandshakeError::Failure(stream) => handshake_error(stream),
                             HandshakeError::SetupFailure(source) => {
                                 TlsError::HandshakeSetup { source }
                             }
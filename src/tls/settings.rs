@@ -1,7 +1,8 @@
 use super::{
-    AddCertToStore, AddExtraChainCert, DerExportError, FileOpenFailed, FileReadFailed, MaybeTls,
-    NewStoreBuilder, ParsePkcs12, Pkcs12Error, PrivateKeyParseError, Result, SetCertificate,
-    SetPrivateKey, SetVerifyCert, TlsError, TlsIdentityError, X509ParseError,
+    diagnostics::verify_callback, AddCertToStore, AddExtraChainCert, DerExportError,
+    FileOpenFailed, FileReadFailed, MaybeTls, NewStoreBuilder, ParsePkcs12, PemExportError,
+    Pkcs12Error, PrivateKeyParseError, Result, SetCertificate, SetPrivateKey, SetVerifyCert,
+    TlsError, TlsIdentityError, X509ParseError,
 };
 use openssl::{
     pkcs12::{ParsedPkcs12, Pkcs12},
@@ -99,11 +100,14 @@ impl TlsSettings {
     }
 
     pub(super) fn apply_context(&self, context: &mut SslContextBuilder) -> Result<()> {
-        context.set_verify(if self.verify_certificate {
-            SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT
+        if self.verify_certificate {
+            context.set_verify_callback(
+                SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
+                verify_callback,
+            );
         } else {
-            SslVerifyMode::NONE
-        });
+            context.set_verify(SslVerifyMode::NONE);
+        }
         if let Some(identity) = self.identity() {
             context
                 .set_certificate(&identity.cert)
@@ -143,6 +147,23 @@ impl TlsSettings {
     pub fn apply_connect_configuration(&self, connection: &mut ConnectConfiguration) {
         connection.set_verify_hostname(self.verify_hostname);
     }
+
+    /// The identity as a PKCS#12 archive and its password, for clients
+    /// not built on openssl.
+    pub fn identity_pkcs12(&self) -> Option<(&[u8], &str)> {
+        self.identity
+            .as_ref()
+            .map(|identity| (identity.0.as_slice(), identity.1.as_str()))
+    }
+
+    /// The authorities PEM encoded, for clients not built on openssl.
+    pub fn authorities_pem(&self) -> Result<Vec<u8>> {
+        let mut pem = Vec::new();
+        for authority in &self.authorities {
+            pem.extend(authority.to_pem().context(PemExportError)?);
+        }
+        Ok(pem)
+    }
 }
 
 impl TlsOptions {
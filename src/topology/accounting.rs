@@ -0,0 +1,415 @@
+//! Counts the events and bytes each component handles, in totals persisted
+//! to the data directory so they keep adding up across restarts. This helps
+//! planning capacity and attributing the egress of each sink to its vendor.
+
+use super::config::GlobalOptions;
+use crate::{event::Event, internal_events::AccountingTenantEventProcessed};
+use futures01::{Future, Stream};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio01::timer::Interval;
+
+const SUBDIR: &str = "accounting";
+const FILE_NAME: &str = "totals.json";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AccountingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How often the totals are persisted, besides on shutdown.
+    #[serde(default = "default_persist_interval_secs")]
+    pub persist_interval_secs: u64,
+    /// The log field, or metric tag, naming the tenant events are accounted
+    /// to, besides their component.
+    #[serde(default)]
+    pub tenant_field: Option<String>,
+}
+
+impl Default for AccountingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            persist_interval_secs: default_persist_interval_secs(),
+            tenant_field: None,
+        }
+    }
+}
+
+fn default_persist_interval_secs() -> u64 {
+    60
+}
+
+/// Events lacking the `tenant_field` are accounted to it.
+pub const UNKNOWN_TENANT: &str = "unknown";
+
+/// What a component handled since accounting was enabled.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    pub events: u64,
+    /// The size of the events encoded as JSON, as the encodings of the
+    /// components differ.
+    pub bytes: u64,
+    /// The share of each tenant, when `tenant_field` is set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tenants: BTreeMap<String, Totals>,
+}
+
+/// The volume a component handled for a tenant, or overall if tenants
+/// aren't accounted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub component: String,
+    /// Sources ingest, sinks egress. Unknown for the components which
+    /// haven't run since the totals were resumed.
+    pub component_kind: Option<&'static str>,
+    pub tenant: Option<String>,
+    pub events: u64,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+struct Account {
+    kind: OnceCell<&'static str>,
+    events: AtomicU64,
+    bytes: AtomicU64,
+    tenants: Mutex<BTreeMap<String, Totals>>,
+}
+
+impl Account {
+    fn add(&self, name: &str, event: &Event, tenant_field: Option<&str>) {
+        let byte_size = encoded_size(event);
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(byte_size as u64, Ordering::Relaxed);
+
+        if let Some(field) = tenant_field {
+            let tenant = tenant(event, field);
+            emit!(AccountingTenantEventProcessed {
+                component_kind: self.kind.get().copied().unwrap_or("unknown"),
+                component_name: name,
+                tenant: &tenant,
+                byte_size,
+            });
+            let mut tenants = self.tenants.lock().unwrap();
+            let totals = tenants.entry(tenant).or_insert_with(Default::default);
+            totals.events += 1;
+            totals.bytes += byte_size as u64;
+        }
+    }
+
+    fn totals(&self) -> Totals {
+        Totals {
+            events: self.events.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            tenants: self.tenants.lock().unwrap().clone(),
+        }
+    }
+}
+
+fn tenant(event: &Event, field: &str) -> String {
+    let tenant = match event {
+        Event::Log(log) => log.get(&field.into()).map(|value| value.to_string_lossy()),
+        Event::Metric(metric) => metric
+            .tags
+            .as_ref()
+            .and_then(|tags| tags.get(field))
+            .cloned(),
+    };
+    tenant.unwrap_or_else(|| UNKNOWN_TENANT.to_owned())
+}
+
+// Accounts are kept when their component is rebuilt or removed on reload,
+// so totals aren't lost.
+static ACCOUNTS: Lazy<Mutex<BTreeMap<String, Arc<Account>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+static TOTALS_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+fn account(name: &str) -> Arc<Account> {
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    Arc::clone(
+        accounts
+            .entry(name.to_owned())
+            .or_insert_with(Default::default),
+    )
+}
+
+/// The totals so far, by component.
+pub fn totals() -> BTreeMap<String, Totals> {
+    ACCOUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, account)| (name.clone(), account.totals()))
+        .collect()
+}
+
+/// The volumes so far, by component and tenant.
+pub fn volumes() -> Vec<Volume> {
+    let accounts = ACCOUNTS.lock().unwrap();
+    let mut volumes = Vec::new();
+    for (name, account) in accounts.iter() {
+        let component_kind = account.kind.get().copied();
+        let totals = account.totals();
+        if totals.tenants.is_empty() {
+            volumes.push(Volume {
+                component: name.clone(),
+                component_kind,
+                tenant: None,
+                events: totals.events,
+                bytes: totals.bytes,
+            });
+        }
+        for (tenant, totals) in totals.tenants {
+            volumes.push(Volume {
+                component: name.clone(),
+                component_kind,
+                tenant: Some(tenant),
+                events: totals.events,
+                bytes: totals.bytes,
+            });
+        }
+    }
+    volumes
+}
+
+/// Reads the totals persisted in the data directory, which has none at first.
+pub fn read_totals(data_dir: &Path) -> io::Result<BTreeMap<String, Totals>> {
+    read_totals_file(&data_dir.join(SUBDIR).join(FILE_NAME))
+}
+
+fn read_totals_file(path: &Path) -> io::Result<BTreeMap<String, Totals>> {
+    match fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Resumes counting from the persisted totals, which are then kept up to date
+/// by `persist`.
+pub fn init(global: &GlobalOptions) -> crate::Result<()> {
+    let path = global
+        .resolve_and_make_data_subdir(None, SUBDIR)?
+        .join(FILE_NAME);
+    resume(read_totals_file(&path)?);
+    let _ = TOTALS_PATH.set(path);
+    Ok(())
+}
+
+fn resume(persisted: BTreeMap<String, Totals>) {
+    let mut accounts = ACCOUNTS.lock().unwrap();
+    for (name, totals) in persisted {
+        let account = accounts.entry(name).or_insert_with(Default::default);
+        account.events.fetch_add(totals.events, Ordering::Relaxed);
+        account.bytes.fetch_add(totals.bytes, Ordering::Relaxed);
+        let mut tenants = account.tenants.lock().unwrap();
+        for (tenant, totals) in totals.tenants {
+            let resumed = tenants.entry(tenant).or_insert_with(Default::default);
+            resumed.events += totals.events;
+            resumed.bytes += totals.bytes;
+        }
+    }
+}
+
+/// Writes the totals to the data directory, if `init` was called.
+pub fn persist() -> io::Result<()> {
+    match TOTALS_PATH.get() {
+        Some(path) => write_totals(path, &totals()),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the file as a whole, so a crash can't leave it half written.
+fn write_totals(path: &Path, totals: &BTreeMap<String, Totals>) -> io::Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(totals)?)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Persists the totals every `persist_interval_secs`.
+pub fn persist_periodically(config: &AccountingConfig) -> impl Future<Item = (), Error = ()> {
+    let period = Duration::from_secs(config.persist_interval_secs.max(1));
+    Interval::new(Instant::now() + period, period)
+        .map_err(|error| error!(message = "Accounting timer failed.", %error))
+        .for_each(|_| {
+            if let Err(error) = persist() {
+                error!(message = "Unable to persist accounting totals.", %error);
+            }
+            Ok(())
+        })
+}
+
+/// Accounts for the events of the stream, if accounting is enabled.
+pub fn account_stream<S>(
+    name: &str,
+    kind: &'static str,
+    config: &AccountingConfig,
+    stream: S,
+) -> Box<dyn Stream<Item = Event, Error = ()> + Send>
+where
+    S: Stream<Item = Event, Error = ()> + Send + 'static,
+{
+    if config.enabled {
+        let account = account(name);
+        let _ = account.kind.set(kind);
+        let name = name.to_owned();
+        let tenant_field = config.tenant_field.clone();
+        Box::new(stream.inspect(move |event| {
+            account.add(&name, event, tenant_field.as_ref().map(String::as_str))
+        }))
+    } else {
+        Box::new(stream)
+    }
+}
+
+fn encoded_size(event: &Event) -> usize {
+    let mut counter = ByteCounter(0);
+    let _ = match event {
+        Event::Log(log) => serde_json::to_writer(&mut counter, log),
+        Event::Metric(metric) => serde_json::to_writer(&mut counter, metric),
+    };
+    counter.0
+}
+
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+    use futures01::stream;
+
+    #[test]
+    fn accounting_counts_events_and_bytes() {
+        let config = AccountingConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let events = vec![Event::from("hello"), Event::from("world!")];
+        let expected_bytes = events.iter().map(encoded_size).sum::<usize>() as u64;
+
+        let stream = account_stream(
+            "accounting_counts",
+            "source",
+            &config,
+            stream::iter_ok(events),
+        );
+        runtime().block_on(stream.collect()).unwrap();
+
+        let totals = totals().remove("accounting_counts").unwrap();
+        assert_eq!(totals.events, 2);
+        assert_eq!(totals.bytes, expected_bytes);
+        assert!(totals.bytes > "helloworld!".len() as u64);
+    }
+
+    #[test]
+    fn accounting_disabled_counts_nothing() {
+        let stream = account_stream(
+            "accounting_disabled",
+            "sink",
+            &AccountingConfig::default(),
+            stream::iter_ok(vec![Event::from("hello")]),
+        );
+        runtime().block_on(stream.collect()).unwrap();
+
+        assert!(!totals().contains_key("accounting_disabled"));
+    }
+
+    #[test]
+    fn accounting_resumes_persisted_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_totals(dir.path()).unwrap().is_empty());
+
+        let mut persisted = BTreeMap::new();
+        persisted.insert(
+            "accounting_resumes".to_owned(),
+            Totals {
+                events: 10,
+                bytes: 100,
+                tenants: BTreeMap::new(),
+            },
+        );
+        fs::create_dir(dir.path().join(SUBDIR)).unwrap();
+        write_totals(&dir.path().join(SUBDIR).join(FILE_NAME), &persisted).unwrap();
+        assert_eq!(read_totals(dir.path()).unwrap(), persisted);
+
+        resume(read_totals(dir.path()).unwrap());
+        account("accounting_resumes").add("accounting_resumes", &Event::from("hello"), None);
+
+        let totals = totals().remove("accounting_resumes").unwrap();
+        assert_eq!(totals.events, 11);
+        assert_eq!(
+            totals.bytes,
+            100 + encoded_size(&Event::from("hello")) as u64
+        );
+    }
+
+    #[test]
+    fn accounting_splits_tenants() {
+        let config = AccountingConfig {
+            enabled: true,
+            tenant_field: Some("team".into()),
+            ..Default::default()
+        };
+        let mut events = vec![
+            Event::from("a"),
+            Event::from("bb"),
+            Event::from("ccc"),
+            Event::from("dddd"),
+        ];
+        events[0].as_mut_log().insert("team", "payments");
+        events[1].as_mut_log().insert("team", "search");
+        events[2].as_mut_log().insert("team", "payments");
+        let sizes = events.iter().map(encoded_size).collect::<Vec<_>>();
+
+        let stream = account_stream(
+            "accounting_tenants",
+            "sink",
+            &config,
+            stream::iter_ok(events),
+        );
+        runtime().block_on(stream.collect()).unwrap();
+
+        let totals = totals().remove("accounting_tenants").unwrap();
+        assert_eq!(totals.events, 4);
+        assert_eq!(totals.tenants["payments"].events, 2);
+        assert_eq!(
+            totals.tenants["payments"].bytes,
+            (sizes[0] + sizes[2]) as u64
+        );
+        assert_eq!(totals.tenants["search"].events, 1);
+        assert_eq!(totals.tenants[UNKNOWN_TENANT].events, 1);
+
+        let volumes = volumes()
+            .into_iter()
+            .filter(|volume| volume.component == "accounting_tenants")
+            .collect::<Vec<_>>();
+        assert_eq!(volumes.len(), 3);
+        assert_eq!(volumes[0].component_kind, Some("sink"));
+        assert_eq!(volumes[0].tenant, Some("payments".into()));
+        assert_eq!(volumes[0].bytes, (sizes[0] + sizes[2]) as u64);
+    }
+}
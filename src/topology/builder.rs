@@ -1,16 +1,30 @@
 use super::{
+    accounting::account_stream,
     config::{DataType, SinkContext, TransformContext},
     fanout::{self, Fanout},
+    lifecycle::lifecycle_stream,
+    profile::{profile_stream, time_stream},
+    startup_report::HealthcheckStatus,
     task::Task,
     ConfigDiff,
 };
-use crate::{buffers, dns::Resolver, event::Event, runtime, shutdown::SourceShutdownCoordinator};
+use crate::{
+    buffers::{
+        self,
+        usage::{BufferUsage, UsageStream},
+    },
+    dns::Resolver,
+    event::Event,
+    runtime,
+    shutdown::SourceShutdownCoordinator,
+    sinks::util::{capture::PayloadCapture, flush},
+};
 use futures01::{
     future::{lazy, Either},
-    sync::mpsc,
+    sync::{mpsc, oneshot},
     Future, Stream,
 };
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio01::util::FutureExt;
 
 pub struct Pieces {
@@ -19,6 +33,8 @@ pub struct Pieces {
     pub tasks: HashMap<String, Task>,
     pub source_tasks: HashMap<String, Task>,
     pub healthchecks: HashMap<String, Task>,
+    /// Resolve once the healthchecks complete.
+    pub healthcheck_results: HashMap<String, oneshot::Receiver<HealthcheckStatus>>,
     pub shutdown_coordinator: SourceShutdownCoordinator,
 }
 
@@ -77,6 +93,23 @@ pub fn check(config: &super::Config) -> Result<Vec<String>, Vec<String>> {
         }
     }
 
+    for name in &config.global.profile.components {
+        if !config.sources.contains_key(name)
+            && !config.transforms.contains_key(name)
+            && !config.sinks.contains_key(name)
+        {
+            errors.push(format!("Profiled component {:?} doesn't exist.", name));
+        }
+    }
+
+    let sample_ratio = config.global.lifecycle.sample_ratio;
+    if !(0.0..=1.0).contains(&sample_ratio) {
+        errors.push(format!(
+            "Lifecycle tracing sample_ratio {} isn't between 0 and 1.",
+            sample_ratio
+        ));
+    }
+
     if let Err(type_errors) = config.typecheck() {
         errors.extend(type_errors);
     }
@@ -99,6 +132,7 @@ pub fn build_pieces(
     let mut tasks = HashMap::new();
     let mut source_tasks = HashMap::new();
     let mut healthchecks = HashMap::new();
+    let mut healthcheck_results = HashMap::new();
     let mut shutdown_coordinator = SourceShutdownCoordinator::new();
 
     let mut errors = vec![];
@@ -135,7 +169,11 @@ pub fn build_pieces(
         };
 
         let (output, control) = Fanout::new();
-        let pump = rx.forward(output).map(|_| ());
+        let pump = profile_stream(&name, &config.global.profile, rx);
+        let pump = account_stream(&name, "source", &config.global.accounting, pump);
+        let pump = lifecycle_stream(&name, "source", &config.global.lifecycle, pump)
+            .forward(output)
+            .map(|_| ());
         let pump = Task::new(&name, &typetag, pump);
 
         // The force_shutdown_tripwire is a Future that when it resolves means that this source
@@ -179,12 +217,21 @@ pub fn build_pieces(
         };
 
         let (input_tx, input_rx) = futures01::sync::mpsc::channel(100);
-        let input_tx = buffers::BufferInputCloner::Memory(input_tx, buffers::WhenFull::Block);
+        let usage = BufferUsage::register(&name, "transform", "memory");
+        let input_tx = buffers::BufferInputCloner::Memory(
+            input_tx,
+            buffers::WhenFull::Block,
+            Arc::clone(&usage),
+        );
+        let input_rx = UsageStream::new(input_rx, usage);
 
         let (output, control) = Fanout::new();
 
-        let transform = transform
-            .transform_stream(filter_event_type(input_rx, input_type))
+        let transform = transform.transform_stream(filter_event_type(input_rx, input_type));
+        let transform = time_stream(&name, *typetag, transform);
+        let transform = profile_stream(&name, &config.global.profile, transform);
+        let transform = account_stream(&name, "transform", &config.global.accounting, transform);
+        let transform = lifecycle_stream(&name, "transform", &config.global.lifecycle, transform)
             .forward(output)
             .map(|_| ());
         let task = Task::new(&name, &typetag, transform);
@@ -215,13 +262,32 @@ pub fn build_pieces(
             Ok(buffer) => buffer,
         };
 
+        let capture = if sink.capture_payloads > 0 {
+            let subdir = format!("payload_capture/{}", name);
+            let capture = config
+                .global
+                .resolve_and_make_data_subdir(None, &subdir)
+                .and_then(|dir| Ok(PayloadCapture::new(dir, sink.capture_payloads)?));
+            match capture {
+                Err(error) => {
+                    errors.push(format!("Sink \"{}\": {}", name, error));
+                    continue;
+                }
+                Ok(capture) => Some(capture),
+            }
+        } else {
+            None
+        };
+
         let cx = SinkContext {
             resolver: resolver.clone(),
             acker,
             exec: exec.clone(),
+            capture,
+            dry_run: None,
         };
 
-        let (sink, healthcheck) = match sink.inner.build(cx) {
+        let (sink, healthcheck) = match flush::building(&name, || sink.inner.build(cx)) {
             Err(error) => {
                 errors.push(format!("Sink \"{}\": {}", name, error));
                 continue;
@@ -229,19 +295,41 @@ pub fn build_pieces(
             Ok((sink, healthcheck)) => (sink, healthcheck),
         };
 
-        let sink = filter_event_type(rx, input_type).forward(sink).map(|_| ());
+        let input = lifecycle_stream(
+            &name,
+            "sink",
+            &config.global.lifecycle,
+            filter_event_type(rx, input_type),
+        );
+        let input = profile_stream(&name, &config.global.profile, input);
+        let sink = account_stream(&name, "sink", &config.global.accounting, input)
+            .forward(sink)
+            .map(|_| ());
         let task = Task::new(&name, &typetag, sink);
 
+        let (result_tx, result_rx) = oneshot::channel();
         let healthcheck_task = if enable_healthcheck {
             let healthcheck_task = healthcheck
                 // TODO: Add healthcheck timeouts per sink
                 .timeout(Duration::from_secs(10))
-                .map(move |_| info!("Healthcheck: Passed."))
-                .map_err(move |err| error!("Healthcheck: Failed Reason: {}", err));
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Healthcheck: Passed.");
+                        let _ = result_tx.send(HealthcheckStatus::Passed);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        error!("Healthcheck: Failed Reason: {}", err);
+                        let reason = err.to_string();
+                        let _ = result_tx.send(HealthcheckStatus::Failed { reason });
+                        Err(())
+                    }
+                });
             Either::A(healthcheck_task)
         } else {
             Either::B(lazy(|| {
                 info!("Healthcheck: Disabled.");
+                let _ = result_tx.send(HealthcheckStatus::Disabled);
                 Ok(())
             }))
         };
@@ -249,6 +337,7 @@ pub fn build_pieces(
 
         inputs.insert(name.clone(), (tx, sink_inputs.clone()));
         healthchecks.insert(name.clone(), healthcheck_task);
+        healthcheck_results.insert(name.clone(), result_rx);
         tasks.insert(name.clone(), task);
     }
 
@@ -269,6 +358,7 @@ pub fn build_pieces(
             tasks,
             source_tasks,
             healthchecks,
+            healthcheck_results,
             shutdown_coordinator,
         };
 
@@ -0,0 +1,83 @@
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+pub static DEFAULTS_PROFILE: OnceCell<DefaultsProfile> = OnceCell::new();
+
+/// The profile set by the config, applied to the defaults of every component.
+pub fn defaults_profile() -> DefaultsProfile {
+    DEFAULTS_PROFILE.get().copied().unwrap_or_default()
+}
+
+/// Tunes the defaults of buffer sizes, batch sizes and request concurrency
+/// across components. Options set on a component always take precedence.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultsProfile {
+    /// The defaults of each component, as documented.
+    Balanced,
+    /// Smaller buffers and batches, and fewer requests in flight.
+    LowMemory,
+    /// Larger buffers and more requests in flight. Batch sizes are kept, as
+    /// their defaults are usually the most the services accept.
+    HighThroughput,
+}
+
+impl Default for DefaultsProfile {
+    fn default() -> Self {
+        DefaultsProfile::Balanced
+    }
+}
+
+impl DefaultsProfile {
+    pub fn memory_buffer_events(self, events: usize) -> usize {
+        match self {
+            DefaultsProfile::Balanced => events,
+            DefaultsProfile::LowMemory => (events / 5).max(1),
+            DefaultsProfile::HighThroughput => events * 20,
+        }
+    }
+
+    pub fn batch_size(self, size: u64) -> u64 {
+        match self {
+            DefaultsProfile::LowMemory => (size / 4).max(1),
+            DefaultsProfile::Balanced | DefaultsProfile::HighThroughput => size,
+        }
+    }
+
+    pub fn in_flight_limit(self, limit: usize) -> usize {
+        match self {
+            DefaultsProfile::Balanced => limit,
+            DefaultsProfile::LowMemory => limit.min(2),
+            DefaultsProfile::HighThroughput => limit * 5,
+        }
+    }
+
+    /// Raised along with the in flight limit, so it isn't the bottleneck.
+    pub fn rate_limit_num(self, num: u64) -> u64 {
+        match self {
+            DefaultsProfile::HighThroughput => num.saturating_mul(5),
+            DefaultsProfile::Balanced | DefaultsProfile::LowMemory => num,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_profile_scales_defaults() {
+        let profile = DefaultsProfile::LowMemory;
+        assert_eq!(profile.memory_buffer_events(500), 100);
+        assert_eq!(profile.batch_size(1000), 250);
+        assert_eq!(profile.batch_size(1), 1);
+        assert_eq!(profile.in_flight_limit(5), 2);
+
+        let profile = DefaultsProfile::HighThroughput;
+        assert_eq!(profile.memory_buffer_events(500), 10000);
+        assert_eq!(profile.batch_size(1000), 1000);
+        assert_eq!(profile.in_flight_limit(5), 25);
+        assert_eq!(profile.rate_limit_num(5), 25);
+        assert_eq!(profile.rate_limit_num(u64::max_value()), u64::max_value());
+    }
+}
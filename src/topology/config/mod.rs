@@ -3,19 +3,27 @@ use crate::{
     conditions,
     dns::Resolver,
     event::{self, Event, Metric},
+    permissions::PermissionsConfig,
     runtime::TaskExecutor,
     shutdown::ShutdownSignal,
-    sinks, sources, transforms,
+    sinks::{
+        self,
+        util::{capture::PayloadCapture, dry_run::DryRun},
+    },
+    sources, transforms,
 };
 use component::ComponentDescription;
 use futures01::sync::mpsc;
 use indexmap::IndexMap; // IndexMap preserves insertion order, allowing us to output errors in the same order they are present in the file
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
-use std::fs::DirBuilder;
 use std::{collections::HashMap, path::PathBuf};
 
+pub use defaults_profile::{defaults_profile, DefaultsProfile, DEFAULTS_PROFILE};
+
 pub mod component;
+mod defaults_profile;
+pub mod remote;
 mod validation;
 mod vars;
 pub mod watcher;
@@ -39,6 +47,12 @@ pub struct Config {
 pub struct GlobalOptions {
     #[serde(default = "default_data_dir")]
     pub data_dir: Option<PathBuf>,
+    /// Given to the subdirectories created in the `data_dir`.
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub data_dir_permissions: PermissionsConfig,
     #[serde(default)]
     pub dns_servers: Vec<String>,
     #[serde(
@@ -46,6 +60,36 @@ pub struct GlobalOptions {
         default
     )]
     pub log_schema: event::LogSchema,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub profile: super::profile::ProfileConfig,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub defaults_profile: DefaultsProfile,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub accounting: super::accounting::AccountingConfig,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub hardening: super::hardening::HardeningConfig,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub lifecycle: super::lifecycle::LifecycleConfig,
+    #[serde(
+        skip_serializing_if = "crate::serde::skip_serializing_if_default",
+        default
+    )]
+    pub scheduler: super::scheduler::SchedulerConfig,
 }
 
 pub fn default_data_dir() -> Option<PathBuf> {
@@ -110,9 +154,9 @@ impl GlobalOptions {
         let mut data_subdir = data_dir.clone();
         data_subdir.push(subdir);
 
-        DirBuilder::new()
-            .recursive(true)
-            .create(&data_subdir)
+        self.data_dir_permissions
+            .build()?
+            .create_dir_all(&data_subdir)
             .with_context(|| CouldNotCreate { subdir, data_dir })?;
         Ok(data_subdir)
     }
@@ -150,6 +194,10 @@ pub struct SinkOuter {
     pub buffer: crate::buffers::BufferConfig,
     #[serde(default = "healthcheck_default")]
     pub healthcheck: bool,
+    /// The number of requests to capture, from startup and from each reload
+    /// changing the sink.
+    #[serde(default)]
+    pub capture_payloads: usize,
     pub inputs: Vec<String>,
     #[serde(flatten)]
     pub inner: Box<dyn SinkConfig>,
@@ -169,6 +217,8 @@ pub struct SinkContext {
     pub(super) acker: Acker,
     pub(super) resolver: Resolver,
     pub(super) exec: TaskExecutor,
+    pub(super) capture: Option<PayloadCapture>,
+    pub(super) dry_run: Option<DryRun>,
 }
 
 impl SinkContext {
@@ -178,6 +228,8 @@ impl SinkContext {
             acker: Acker::Null,
             resolver: Resolver::new(Vec::new(), exec.clone()).unwrap(),
             exec,
+            capture: None,
+            dry_run: None,
         }
     }
 
@@ -196,6 +248,16 @@ impl SinkContext {
     pub fn executor(&self) -> &TaskExecutor {
         &self.exec
     }
+
+    /// Set when the sink is configured with `capture_payloads`.
+    pub fn capture(&self) -> Option<PayloadCapture> {
+        self.capture.clone()
+    }
+
+    /// Set when the sink is built by `vector validate --dry-run`.
+    pub fn dry_run(&self) -> Option<DryRun> {
+        self.dry_run.clone()
+    }
 }
 
 pub type SinkDescription = ComponentDescription<Box<dyn SinkConfig>>;
@@ -312,8 +374,15 @@ impl Config {
         Self {
             global: GlobalOptions {
                 data_dir: None,
+                data_dir_permissions: Default::default(),
                 dns_servers: Vec::new(),
                 log_schema: event::LogSchema::default(),
+                profile: Default::default(),
+                defaults_profile: DefaultsProfile::default(),
+                accounting: Default::default(),
+                hardening: Default::default(),
+                lifecycle: Default::default(),
+                scheduler: Default::default(),
             },
             sources: IndexMap::new(),
             sinks: IndexMap::new(),
@@ -331,6 +400,7 @@ impl Config {
         let sink = SinkOuter {
             buffer: Default::default(),
             healthcheck: true,
+            capture_payloads: 0,
             inner: Box::new(sink),
             inputs,
         };
@@ -424,6 +494,15 @@ impl Config {
             // we consider this an error.
             errors.push("conflicting values for 'data_dir' found".to_owned());
         }
+
+        if self.global.data_dir_permissions == Default::default() {
+            self.global.data_dir_permissions = with.global.data_dir_permissions;
+        } else if with.global.data_dir_permissions != Default::default()
+            && self.global.data_dir_permissions != with.global.data_dir_permissions
+        {
+            errors.push("conflicting values for 'data_dir_permissions' found".to_owned());
+        }
+
         self.global.dns_servers.append(&mut with.global.dns_servers);
         self.global.dns_servers.sort();
         self.global.dns_servers.dedup();
@@ -462,6 +541,46 @@ impl Config {
             }
         }
 
+        if self.global.defaults_profile == DefaultsProfile::default() {
+            self.global.defaults_profile = with.global.defaults_profile;
+        } else if with.global.defaults_profile != DefaultsProfile::default()
+            && self.global.defaults_profile != with.global.defaults_profile
+        {
+            errors.push("conflicting values for 'defaults_profile' found".to_owned());
+        }
+
+        if self.global.accounting == Default::default() {
+            self.global.accounting = with.global.accounting;
+        } else if with.global.accounting != Default::default()
+            && self.global.accounting != with.global.accounting
+        {
+            errors.push("conflicting values for 'accounting' found".to_owned());
+        }
+
+        if self.global.hardening == Default::default() {
+            self.global.hardening = with.global.hardening;
+        } else if with.global.hardening != Default::default()
+            && self.global.hardening != with.global.hardening
+        {
+            errors.push("conflicting values for 'hardening' found".to_owned());
+        }
+
+        if self.global.lifecycle == Default::default() {
+            self.global.lifecycle = with.global.lifecycle;
+        } else if with.global.lifecycle != Default::default()
+            && self.global.lifecycle != with.global.lifecycle
+        {
+            errors.push("conflicting values for 'lifecycle' found".to_owned());
+        }
+
+        if self.global.scheduler == Default::default() {
+            self.global.scheduler = with.global.scheduler;
+        } else if with.global.scheduler != Default::default()
+            && self.global.scheduler != with.global.scheduler
+        {
+            errors.push("conflicting values for 'scheduler' found".to_owned());
+        }
+
         with.sources.keys().for_each(|k| {
             if self.sources.contains_key(k) {
                 errors.push(format!("duplicate source name found: {}", k));
@@ -693,4 +812,18 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn config_append_defaults_profile() {
+        let mut config: Config = toml::from_str(r#"defaults_profile = "low_memory""#).unwrap();
+        assert_eq!(config.append(Config::empty()), Ok(()));
+        assert_eq!(config.global.defaults_profile, DefaultsProfile::LowMemory);
+
+        assert_eq!(
+            config.append(toml::from_str(r#"defaults_profile = "high_throughput""#).unwrap()),
+            Err(vec![
+                "conflicting values for 'defaults_profile' found".into()
+            ])
+        );
+    }
 }
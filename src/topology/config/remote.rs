@@ -0,0 +1,229 @@
+//! Fetches configuration bundles from a control plane, so the configuration
+//! of many instances can be managed centrally.
+//!
+//! A bundle is a configuration file served over HTTP, signed with the
+//! private key of the control plane. The signature over the body is sent
+//! base64 encoded in the `X-Vector-Signature` header, as produced by
+//! `openssl dgst -sha256 -sign key.pem bundle.toml | base64`. Verified
+//! bundles are written to a local file which is loaded along with the other
+//! configuration files, and applied by reloading as on `SIGHUP`.
+
+use super::Config;
+use crate::Error;
+use openssl::{
+    base64,
+    hash::MessageDigest,
+    pkey::{PKey, Public},
+    sign::Verifier,
+};
+use reqwest::{header, Client, StatusCode};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Vector-Signature";
+
+#[derive(Debug, Snafu)]
+pub enum RemoteConfigError {
+    #[snafu(display("Unable to read the public key {:?}: {}", path, source))]
+    ReadKey {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Invalid public key {:?}: {}", path, source))]
+    InvalidKey {
+        path: PathBuf,
+        source: openssl::error::ErrorStack,
+    },
+    #[snafu(display("Fetching the bundle failed: {}", source))]
+    Fetch { source: reqwest::Error },
+    #[snafu(display("Fetching the bundle failed with status {}", status))]
+    FetchStatus { status: StatusCode },
+    #[snafu(display("The bundle has no {} header", SIGNATURE_HEADER))]
+    MissingSignature,
+    #[snafu(display("The signature of the bundle doesn't match"))]
+    InvalidSignature,
+    #[snafu(display("Invalid bundle: {}", errors.join(", ")))]
+    InvalidBundle { errors: Vec<String> },
+    #[snafu(display("Unable to write the bundle to {:?}: {}", path, source))]
+    WriteBundle {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+pub struct RemoteConfig {
+    client: Client,
+    url: String,
+    key: PKey<Public>,
+    /// Where the last verified bundle is kept, loaded as a configuration file.
+    bundle_path: PathBuf,
+    etag: Option<header::HeaderValue>,
+}
+
+impl RemoteConfig {
+    pub fn new(url: String, key_path: &Path, bundle_path: PathBuf) -> Result<Self, Error> {
+        let pem = fs::read(key_path).context(ReadKey { path: key_path })?;
+        let key = PKey::public_key_from_pem(&pem).context(InvalidKey { path: key_path })?;
+        Ok(Self {
+            client: Client::new(),
+            url,
+            key,
+            bundle_path,
+            etag: None,
+        })
+    }
+
+    pub fn bundle_path(&self) -> &Path {
+        &self.bundle_path
+    }
+
+    /// Fetches the bundle, keeping it when it is verified and differs from
+    /// the one kept. Returns whether it changed.
+    pub fn fetch(&mut self) -> Result<bool, RemoteConfigError> {
+        let mut request = self.client.get(&self.url);
+        if let Some(etag) = &self.etag {
+            request = request.header(header::IF_NONE_MATCH, etag.clone());
+        }
+        let mut response = request.send().context(Fetch)?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(false),
+            status if !status.is_success() => {
+                return Err(RemoteConfigError::FetchStatus { status })
+            }
+            _ => (),
+        }
+
+        let signature = response
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|signature| base64::decode_block(signature.to_str().ok()?).ok())
+            .ok_or(RemoteConfigError::MissingSignature)?;
+        let etag = response.headers().get(header::ETAG).cloned();
+        let mut body = Vec::new();
+        response
+            .read_to_end(&mut body)
+            .map_err(|error| RemoteConfigError::InvalidBundle {
+                errors: vec![error.to_string()],
+            })?;
+
+        self.verify(&body, &signature)?;
+        self.etag = etag;
+        if fs::read(&self.bundle_path).ok().as_ref() == Some(&body) {
+            return Ok(false);
+        }
+
+        // Written next to the bundle first, so a reload never reads a
+        // partial one.
+        let partial = self.bundle_path.with_extension("partial");
+        fs::write(&partial, &body)
+            .and_then(|()| fs::rename(&partial, &self.bundle_path))
+            .context(WriteBundle {
+                path: &self.bundle_path,
+            })?;
+        Ok(true)
+    }
+
+    fn verify(&self, body: &[u8], signature: &[u8]) -> Result<(), RemoteConfigError> {
+        let verified = Verifier::new(MessageDigest::sha256(), &self.key)
+            .and_then(|mut verifier| {
+                verifier.update(body)?;
+                verifier.verify(signature)
+            })
+            .unwrap_or(false);
+        if !verified {
+            return Err(RemoteConfigError::InvalidSignature);
+        }
+
+        // Only the bundle is checked, its components may refer to others
+        // in the local configuration files.
+        Config::load(body)
+            .map(|_| ())
+            .map_err(|errors| RemoteConfigError::InvalidBundle { errors })
+    }
+}
+
+/// Fetches the bundle every `interval`, triggering SIGHUP when it changed.
+#[cfg(unix)]
+pub fn remote_config_poller(mut remote: RemoteConfig, interval: Duration) {
+    info!(message = "Polling remote configuration.", url = %remote.url);
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        match remote.fetch() {
+            Ok(true) => {
+                info!("Remote configuration changed.");
+                super::watcher::raise_sighup();
+            }
+            Ok(false) => debug!("Remote configuration unchanged."),
+            Err(error) => error!(
+                message = "Unable to fetch remote configuration, keeping the current one.",
+                %error
+            ),
+        }
+    });
+}
+
+#[cfg(windows)]
+/// Only the bundle fetched on startup is applied on Windows, as reloading
+/// isn't supported there.
+pub fn remote_config_poller(_remote: RemoteConfig, _interval: Duration) {
+    warn!("Reloading config on Windows isn't currently supported, the remote configuration is only fetched on startup.");
+}
+
+#[cfg(all(test, feature = "sources-stdin"))]
+mod tests {
+    use super::*;
+    use openssl::{pkey::Private, rsa::Rsa, sign::Signer};
+
+    fn remote(dir: &Path) -> (RemoteConfig, PKey<Private>) {
+        let private = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let key_path = dir.join("key.pem");
+        fs::write(&key_path, private.public_key_to_pem().unwrap()).unwrap();
+        let remote = RemoteConfig::new(
+            "http://localhost/bundle".into(),
+            &key_path,
+            dir.join("bundle.toml"),
+        )
+        .unwrap();
+        (remote, private)
+    }
+
+    fn sign(key: &PKey<Private>, body: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(body).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+
+    #[test]
+    fn remote_config_verifies_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        let (remote, key) = remote(dir.path());
+        let bundle = br#"
+            [sources.in]
+            type = "stdin"
+        "#;
+
+        assert!(remote.verify(bundle, &sign(&key, bundle)).is_ok());
+
+        let tampered = br#"
+            [sources.in]
+            type = "stdin"
+            host_key = "other"
+        "#;
+        match remote.verify(tampered, &sign(&key, bundle)) {
+            Err(RemoteConfigError::InvalidSignature) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let invalid = b"[sources.in]\ntype = \"nope\"";
+        match remote.verify(invalid, &sign(&key, invalid)) {
+            Err(RemoteConfigError::InvalidBundle { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
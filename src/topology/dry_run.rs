@@ -0,0 +1,178 @@
+//! Checks the sinks of a config against their services without running the
+//! topology, for `vector validate --dry-run`.
+
+use super::config::{Config, DataType, SinkContext};
+use crate::{
+    buffers::Acker,
+    dns::Resolver,
+    event::{
+        metric::{MetricKind, MetricValue},
+        Event, Metric,
+    },
+    runtime::Runtime,
+    sinks::util::dry_run::{DryRun, InterceptedRequest},
+};
+use chrono::Utc;
+use futures01::{stream, Future, Sink};
+use std::time::Duration;
+use tokio01::util::FutureExt;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct SinkReport {
+    /// `None` when the sink's healthcheck is disabled.
+    pub healthcheck: Option<Result<(), String>>,
+    pub request: RequestOutcome,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RequestOutcome {
+    /// The requests built for the sample event, which were not sent.
+    Built(Vec<InterceptedRequest>),
+    /// The sample event didn't make it into a request, e.g. because it could
+    /// not be encoded.
+    NotBuilt,
+    /// The sink can't build requests without sending them, so the sample
+    /// event was not sent.
+    Unsupported,
+    Failed(String),
+}
+
+impl SinkReport {
+    pub fn is_ok(&self) -> bool {
+        let healthy = self.healthcheck.as_ref().map_or(true, Result::is_ok);
+        let request_ok = match self.request {
+            RequestOutcome::Built(_) | RequestOutcome::Unsupported => true,
+            RequestOutcome::NotBuilt | RequestOutcome::Failed(_) => false,
+        };
+        healthy && request_ok
+    }
+}
+
+/// Builds each sink, runs its healthcheck and passes it a sample event.
+pub fn check_sinks(config: &Config, rt: &mut Runtime) -> Vec<(String, crate::Result<SinkReport>)> {
+    let resolver = match Resolver::new(config.global.dns_servers.clone(), rt.executor()) {
+        Ok(resolver) => resolver,
+        Err(error) => {
+            let error = error.to_string();
+            return config
+                .sinks
+                .keys()
+                .map(|name| (name.clone(), Err(error.clone().into())))
+                .collect();
+        }
+    };
+
+    config
+        .sinks
+        .iter()
+        .map(|(name, outer)| {
+            let dry_run = DryRun::new();
+            let cx = SinkContext {
+                acker: Acker::Null,
+                resolver: resolver.clone(),
+                exec: rt.executor(),
+                capture: None,
+                dry_run: Some(dry_run.clone()),
+            };
+            let report = outer.inner.build(cx).map(|(sink, healthcheck)| {
+                let healthcheck = if outer.healthcheck {
+                    let result = rt.block_on(healthcheck.timeout(TIMEOUT));
+                    Some(result.map_err(|error| error.to_string()))
+                } else {
+                    None
+                };
+
+                let request = if dry_run.is_intercepting() {
+                    let event = sample_event(outer.inner.input_type());
+                    let sent = sink
+                        .send_all(stream::iter_ok(vec![event]))
+                        .map_err(|()| "the sink failed to handle the sample event".to_owned())
+                        .timeout(TIMEOUT);
+                    match rt.block_on(sent) {
+                        Err(error) => RequestOutcome::Failed(error.to_string()),
+                        Ok(_) => match dry_run.requests() {
+                            requests if requests.is_empty() => RequestOutcome::NotBuilt,
+                            requests => RequestOutcome::Built(requests),
+                        },
+                    }
+                } else {
+                    RequestOutcome::Unsupported
+                };
+
+                SinkReport {
+                    healthcheck,
+                    request,
+                }
+            });
+            (name.clone(), report)
+        })
+        .collect()
+}
+
+fn sample_event(input_type: DataType) -> Event {
+    match input_type {
+        DataType::Metric => Event::Metric(Metric {
+            name: "vector_validate_dry_run".into(),
+            timestamp: Some(Utc::now()),
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.0 },
+        }),
+        DataType::Log | DataType::Any => Event::from("Vector validate dry run."),
+    }
+}
+
+#[cfg(all(test, feature = "sinks-console", feature = "sinks-http"))]
+mod tests {
+    use super::*;
+    use crate::{
+        sinks::{console::ConsoleSinkConfig, http::HttpSinkConfig},
+        test_util::next_addr,
+    };
+
+    #[test]
+    fn dry_run_builds_requests_without_sending_them() {
+        let addr = next_addr();
+        let mut config = Config::empty();
+        let http = toml::from_str::<HttpSinkConfig>(&format!(
+            r#"
+            uri = "http://{}/logs"
+            encoding = "ndjson"
+            healthcheck_uri = "http://{}/health"
+            "#,
+            addr, addr
+        ))
+        .unwrap();
+        config.add_sink("http", &[], http);
+        let console = toml::from_str::<ConsoleSinkConfig>(r#"encoding = "text""#).unwrap();
+        config.add_sink("console", &[], console);
+
+        let mut rt = Runtime::new().unwrap();
+        let reports = check_sinks(&config, &mut rt);
+
+        let (name, report) = &reports[0];
+        assert_eq!(name, "http");
+        let report = report.as_ref().unwrap();
+        // Nothing listens on the address.
+        assert!(report.healthcheck.as_ref().unwrap().is_err());
+        match &report.request {
+            RequestOutcome::Built(requests) => {
+                assert_eq!(requests.len(), 1);
+                assert_eq!(requests[0].method, "POST");
+                assert_eq!(requests[0].uri, format!("http://{}/logs", addr));
+                assert!(requests[0].byte_size > 0);
+            }
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        assert!(!report.is_ok());
+
+        let (name, report) = &reports[1];
+        assert_eq!(name, "console");
+        assert_eq!(
+            report.as_ref().unwrap().request,
+            RequestOutcome::Unsupported
+        );
+    }
+}
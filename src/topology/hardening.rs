@@ -0,0 +1,510 @@
+//! Restricts what the Vector process can do, for deployments where a
+//! compromised Vector must not be able to reach further into the host.
+//!
+//! Capabilities are dropped at startup, before the runtime starts its
+//! threads, as each thread holds its own. The seccomp filter and the chroot
+//! are applied once the topology started and the healthchecks resolved, as
+//! sources bind their sockets and sinks open their files while starting.
+
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{io, path::Path};
+
+#[derive(Debug, Snafu)]
+pub enum HardeningError {
+    #[snafu(display("Unknown capability {:?}", name))]
+    UnknownCapability { name: String },
+    #[snafu(display("Chrooting requires an absolute data_dir"))]
+    NoDataDir,
+    #[snafu(display("Unable to {}: {}", operation, source))]
+    Syscall {
+        operation: &'static str,
+        source: io::Error,
+    },
+    #[snafu(display("Unable to link {:?} to the root of the chroot", path))]
+    LinkOccupied { path: std::path::PathBuf },
+    #[snafu(display("The seccomp filter isn't supported on this architecture"))]
+    UnsupportedArchitecture,
+    #[snafu(display("Hardening is only supported on Linux"))]
+    Unsupported,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HardeningConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The capabilities kept, such as `net_bind_service`, all others are
+    /// dropped.
+    #[serde(default)]
+    pub keep_capabilities: Vec<String>,
+    #[serde(default = "crate::serde::default_true")]
+    pub seccomp: bool,
+    /// Chroots to the `data_dir`.
+    #[serde(default)]
+    pub chroot: bool,
+}
+
+impl Default for HardeningConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            keep_capabilities: Vec::new(),
+            seccomp: true,
+            chroot: false,
+        }
+    }
+}
+
+/// The capabilities in the order of their numbers.
+const CAPABILITIES: &[&str] = &[
+    "chown",
+    "dac_override",
+    "dac_read_search",
+    "fowner",
+    "fsetid",
+    "kill",
+    "setgid",
+    "setuid",
+    "setpcap",
+    "linux_immutable",
+    "net_bind_service",
+    "net_broadcast",
+    "net_admin",
+    "net_raw",
+    "ipc_lock",
+    "ipc_owner",
+    "sys_module",
+    "sys_rawio",
+    "sys_chroot",
+    "sys_ptrace",
+    "sys_pacct",
+    "sys_admin",
+    "sys_boot",
+    "sys_nice",
+    "sys_resource",
+    "sys_time",
+    "sys_tty_config",
+    "mknod",
+    "lease",
+    "audit_write",
+    "audit_control",
+    "setfcap",
+    "mac_override",
+    "mac_admin",
+    "syslog",
+    "wake_alarm",
+    "block_suspend",
+    "audit_read",
+    "perfmon",
+    "bpf",
+    "checkpoint_restore",
+];
+
+const CAP_SYS_CHROOT: u32 = 18;
+
+/// Parses names such as `net_bind_service` or `CAP_NET_BIND_SERVICE`.
+fn capability(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let name = if name.starts_with("cap_") {
+        &name[4..]
+    } else {
+        &name[..]
+    };
+    CAPABILITIES
+        .iter()
+        .position(|capability| *capability == name)
+        .map(|position| position as u32)
+}
+
+impl HardeningConfig {
+    /// The capabilities kept, as a mask.
+    fn kept(&self) -> Result<u64, HardeningError> {
+        let mut kept = 0;
+        for name in &self.keep_capabilities {
+            let capability = capability(name)
+                .ok_or_else(|| HardeningError::UnknownCapability { name: name.clone() })?;
+            kept |= 1 << capability;
+        }
+        // Only the main thread chroots, and drops this right after.
+        if self.chroot {
+            kept |= 1 << CAP_SYS_CHROOT;
+        }
+        Ok(kept)
+    }
+}
+
+/// Drops the capabilities not kept from the calling thread, and from the
+/// threads and processes it starts afterwards.
+pub fn drop_capabilities(config: &HardeningConfig) -> Result<(), HardeningError> {
+    let kept = config.kept()?;
+    linux::drop_capabilities(kept)
+}
+
+/// Chroots and applies the seccomp filter, as configured, to the whole
+/// process.
+pub fn lock_down(config: &HardeningConfig, data_dir: Option<&Path>) -> Result<(), HardeningError> {
+    if config.chroot {
+        let data_dir = data_dir
+            .filter(|data_dir| data_dir.is_absolute())
+            .ok_or(HardeningError::NoDataDir)?;
+        linux::chroot(data_dir)?;
+        let kept = config.kept()? & !(1 << CAP_SYS_CHROOT);
+        linux::set_capabilities(kept)?;
+    }
+    if config.seccomp {
+        linux::apply_seccomp_filter()?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::HardeningError;
+    use nix::libc;
+    use std::{
+        ffi::CString,
+        fs, io,
+        os::unix::{ffi::OsStrExt, fs::symlink},
+        path::{Component, Path},
+    };
+
+    const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+    const PR_CAP_AMBIENT: libc::c_int = 47;
+    const PR_CAP_AMBIENT_CLEAR_ALL: libc::c_ulong = 4;
+
+    #[repr(C)]
+    struct CapabilityHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CapabilityData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    fn check(result: libc::c_long, operation: &'static str) -> Result<(), HardeningError> {
+        if result < 0 {
+            Err(HardeningError::Syscall {
+                operation,
+                source: io::Error::last_os_error(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn drop_capabilities(kept: u64) -> Result<(), HardeningError> {
+        let result = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0) };
+        check(result.into(), "set no_new_privs")?;
+
+        // The bounding set keeps dropped capabilities from being regained by
+        // executing a program. Changing it takes `CAP_SETPCAP`, without
+        // which there is nothing to regain.
+        for capability in 0..64_u64 {
+            let bounded = unsafe { libc::prctl(libc::PR_CAPBSET_READ, capability, 0, 0, 0) };
+            if bounded < 0 {
+                // Past the last capability of the kernel.
+                break;
+            }
+            if bounded == 1 && kept & (1 << capability) == 0 {
+                let result = unsafe { libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) };
+                if result < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EPERM) {
+                    check(result.into(), "drop from the bounding set")?;
+                }
+            }
+        }
+
+        // Ambient capabilities are only supported since Linux 4.3.
+        let result = unsafe { libc::prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) };
+        if result < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EINVAL) {
+            check(result.into(), "clear ambient capabilities")?;
+        }
+
+        set_capabilities(kept)
+    }
+
+    /// Limits the capabilities of the calling thread to the ones kept, out
+    /// of those it's permitted.
+    pub fn set_capabilities(kept: u64) -> Result<(), HardeningError> {
+        let mut header = CapabilityHeader {
+            version: CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let mut data = [CapabilityData::default(); 2];
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_capget,
+                &mut header as *mut CapabilityHeader,
+                data.as_mut_ptr(),
+            )
+        };
+        check(result, "read capabilities")?;
+
+        let permitted = u64::from(data[0].permitted) | u64::from(data[1].permitted) << 32;
+        let kept = kept & permitted;
+        let data = [
+            CapabilityData {
+                effective: kept as u32,
+                permitted: kept as u32,
+                inheritable: 0,
+            },
+            CapabilityData {
+                effective: (kept >> 32) as u32,
+                permitted: (kept >> 32) as u32,
+                inheritable: 0,
+            },
+        ];
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_capset,
+                &mut header as *mut CapabilityHeader,
+                data.as_ptr(),
+            )
+        };
+        check(result, "set capabilities")
+    }
+
+    /// Chroots to the `data_dir`, in which the `data_dir` is linked to the
+    /// new root, so the paths components keep their state in still
+    /// resolve.
+    pub fn chroot(data_dir: &Path) -> Result<(), HardeningError> {
+        let relative = data_dir
+            .components()
+            .filter(|component| match component {
+                Component::Normal(_) => true,
+                _ => false,
+            })
+            .collect::<std::path::PathBuf>();
+        if relative.components().next().is_some() {
+            let link = data_dir.join(&relative);
+            match fs::read_link(&link) {
+                Ok(target) if target == Path::new("/") => (),
+                Ok(_) => return Err(HardeningError::LinkOccupied { path: link }),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    if let Some(parent) = link.parent() {
+                        fs::create_dir_all(parent).map_err(|source| HardeningError::Syscall {
+                            operation: "create the chroot",
+                            source,
+                        })?;
+                    }
+                    symlink("/", &link).map_err(|source| HardeningError::Syscall {
+                        operation: "link the chroot",
+                        source,
+                    })?;
+                }
+                Err(_) => return Err(HardeningError::LinkOccupied { path: link }),
+            }
+        }
+
+        let path =
+            CString::new(data_dir.as_os_str().as_bytes()).map_err(|_| HardeningError::NoDataDir)?;
+        let result = unsafe { libc::chroot(path.as_ptr()) };
+        check(result.into(), "chroot")?;
+        let root = CString::new("/").expect("no nul bytes");
+        let result = unsafe { libc::chdir(root.as_ptr()) };
+        check(result.into(), "change to the chroot")
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct SocketFilter {
+        pub code: u16,
+        pub jt: u8,
+        pub jf: u8,
+        pub k: u32,
+    }
+
+    #[repr(C)]
+    struct SocketFilterProgram {
+        len: u16,
+        filter: *const SocketFilter,
+    }
+
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    const BPF_JMP_JGE_K: u16 = 0x35;
+    const BPF_RET_K: u16 = 0x06;
+
+    const SECCOMP_SET_MODE_FILTER: libc::c_ulong = 1;
+    const SECCOMP_FILTER_FLAG_TSYNC: libc::c_ulong = 1;
+    pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    /// The offsets of the number and the architecture of the system call
+    /// in `struct seccomp_data`.
+    const OFFSET_NR: u32 = 0;
+    const OFFSET_ARCH: u32 = 4;
+
+    /// System calls this high are only made through the x32 ABI of x86_64,
+    /// and would otherwise get past the filter.
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Administrating the host, debugging other processes, and reaching
+    /// the kernel in ways Vector never needs.
+    const DENIED: &[libc::c_long] = &[
+        libc::SYS_acct,
+        libc::SYS_add_key,
+        libc::SYS_adjtimex,
+        libc::SYS_bpf,
+        libc::SYS_chroot,
+        libc::SYS_clock_settime,
+        libc::SYS_delete_module,
+        libc::SYS_finit_module,
+        libc::SYS_init_module,
+        libc::SYS_kexec_load,
+        libc::SYS_keyctl,
+        libc::SYS_mount,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_perf_event_open,
+        libc::SYS_pivot_root,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_ptrace,
+        libc::SYS_quotactl,
+        libc::SYS_reboot,
+        libc::SYS_request_key,
+        libc::SYS_setdomainname,
+        libc::SYS_sethostname,
+        libc::SYS_setns,
+        libc::SYS_settimeofday,
+        libc::SYS_swapoff,
+        libc::SYS_swapon,
+        libc::SYS_umount2,
+        libc::SYS_unshare,
+        libc::SYS_userfaultfd,
+        libc::SYS_vhangup,
+    ];
+
+    /// Denies the system calls with `EPERM`, and kills the process on
+    /// system calls of other architectures, whose numbers differ.
+    pub fn filter(arch: u32, denied: &[u32]) -> Vec<SocketFilter> {
+        let statement = |code, k| SocketFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let deny = denied.len() + 6;
+        let mut filter = vec![
+            statement(BPF_LD_W_ABS, OFFSET_ARCH),
+            SocketFilter {
+                jt: 1,
+                ..statement(BPF_JMP_JEQ_K, arch)
+            },
+            statement(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            statement(BPF_LD_W_ABS, OFFSET_NR),
+        ];
+        for nr in std::iter::once(X32_SYSCALL_BIT).chain(denied.iter().cloned()) {
+            let code = if nr == X32_SYSCALL_BIT {
+                BPF_JMP_JGE_K
+            } else {
+                BPF_JMP_JEQ_K
+            };
+            filter.push(SocketFilter {
+                jt: (deny - filter.len() - 1) as u8,
+                ..statement(code, nr)
+            });
+        }
+        filter.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW));
+        filter.push(statement(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter
+    }
+
+    /// Applies the filter to every thread of the process.
+    pub fn apply_seccomp_filter() -> Result<(), HardeningError> {
+        let arch = AUDIT_ARCH.ok_or(HardeningError::UnsupportedArchitecture)?;
+        let denied = DENIED.iter().map(|nr| *nr as u32).collect::<Vec<_>>();
+        let filter = filter(arch, &denied);
+        let program = SocketFilterProgram {
+            len: filter.len() as u16,
+            filter: filter.as_ptr(),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                SECCOMP_FILTER_FLAG_TSYNC,
+                &program as *const SocketFilterProgram,
+            )
+        };
+        check(result, "apply the seccomp filter")?;
+        // The id of a thread the filter couldn't be applied to.
+        if result > 0 {
+            return Err(HardeningError::Syscall {
+                operation: "apply the seccomp filter to every thread",
+                source: io::Error::from_raw_os_error(libc::ESRCH),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod linux {
+    use super::HardeningError;
+    use std::path::Path;
+
+    pub fn drop_capabilities(_kept: u64) -> Result<(), HardeningError> {
+        Err(HardeningError::Unsupported)
+    }
+
+    pub fn set_capabilities(_kept: u64) -> Result<(), HardeningError> {
+        Err(HardeningError::Unsupported)
+    }
+
+    pub fn chroot(_data_dir: &Path) -> Result<(), HardeningError> {
+        Err(HardeningError::Unsupported)
+    }
+
+    pub fn apply_seccomp_filter() -> Result<(), HardeningError> {
+        Err(HardeningError::Unsupported)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::linux::*;
+    use super::*;
+
+    #[test]
+    fn hardening_parses_capabilities_and_builds_filter() {
+        let config: HardeningConfig = toml::from_str(
+            r#"
+            enabled = true
+            keep_capabilities = ["net_bind_service", "CAP_NET_RAW"]
+            chroot = true
+            "#,
+        )
+        .unwrap();
+        assert!(config.seccomp);
+        assert_eq!(config.kept().unwrap(), 1 << 10 | 1 << 13 | 1 << 18);
+        let unknown = HardeningConfig {
+            keep_capabilities: vec!["net_teleport".into()],
+            ..Default::default()
+        };
+        assert!(unknown.kept().is_err());
+
+        let filter = filter(0xc000_003e, &[101, 165]);
+        assert_eq!(filter.len(), 9);
+        // Every denied system call jumps to the last statement.
+        for (index, statement) in filter.iter().enumerate().skip(4).take(3) {
+            assert_eq!(index + 1 + statement.jt as usize, filter.len() - 1);
+        }
+        assert_eq!(filter[6].k, 165);
+        assert_eq!(filter[7].k, SECCOMP_RET_ALLOW);
+        assert_eq!(filter[8].k, SECCOMP_RET_ERRNO | 1);
+    }
+}
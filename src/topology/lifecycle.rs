@@ -0,0 +1,296 @@
+//! Follows a sample of the events through the topology, recording when each
+//! component handled them. This helps diagnosing where events are routed,
+//! and where they are delayed, in topologies too large to reason about.
+
+use super::config::GlobalOptions;
+use crate::event::Event;
+use chrono::{DateTime, Utc};
+use futures01::{Future, Stream};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio01::timer::Interval;
+
+const SUBDIR: &str = "lifecycle";
+const FILE_NAME: &str = "traces.json";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LifecycleConfig {
+    /// The fraction of the events received by sources which are traced, none
+    /// by default.
+    #[serde(default)]
+    pub sample_ratio: f64,
+    /// The oldest traces are forgotten beyond these.
+    #[serde(default = "default_max_traces")]
+    pub max_traces: usize,
+    /// The log field, or metric tag, carrying the trace through transforms.
+    /// It's removed before events reach sinks.
+    #[serde(default = "default_trace_key")]
+    pub trace_key: String,
+    /// How often the traces are persisted, besides on shutdown.
+    #[serde(default = "default_persist_interval_secs")]
+    pub persist_interval_secs: u64,
+}
+
+impl Default for LifecycleConfig {
+    fn default() -> Self {
+        Self {
+            sample_ratio: 0.0,
+            max_traces: default_max_traces(),
+            trace_key: default_trace_key(),
+            persist_interval_secs: default_persist_interval_secs(),
+        }
+    }
+}
+
+impl LifecycleConfig {
+    pub fn enabled(&self) -> bool {
+        self.sample_ratio > 0.0
+    }
+}
+
+fn default_max_traces() -> usize {
+    1000
+}
+
+fn default_trace_key() -> String {
+    "_trace_id".into()
+}
+
+fn default_persist_interval_secs() -> u64 {
+    10
+}
+
+/// The components a traced event went through, in order.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub id: String,
+    pub hops: Vec<Hop>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Hop {
+    pub component: String,
+    pub component_kind: String,
+    pub at: DateTime<Utc>,
+}
+
+impl Trace {
+    /// The time from the source to the last component reached.
+    pub fn latency(&self) -> chrono::Duration {
+        match (self.hops.first(), self.hops.last()) {
+            (Some(first), Some(last)) => last.at - first.at,
+            _ => chrono::Duration::zero(),
+        }
+    }
+}
+
+static TRACES: Lazy<Mutex<VecDeque<Trace>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+static TRACES_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// The traces recorded so far, oldest first.
+pub fn traces() -> Vec<Trace> {
+    TRACES.lock().unwrap().iter().cloned().collect()
+}
+
+fn start(id: String, hop: Hop, max_traces: usize) {
+    let mut traces = TRACES.lock().unwrap();
+    while traces.len() >= max_traces.max(1) {
+        traces.pop_front();
+    }
+    traces.push_back(Trace {
+        id,
+        hops: vec![hop],
+    });
+}
+
+/// Events of forgotten traces aren't recorded any further.
+fn record(id: &str, hop: Hop) {
+    let mut traces = TRACES.lock().unwrap();
+    if let Some(trace) = traces.iter_mut().rev().find(|trace| trace.id == id) {
+        trace.hops.push(hop);
+    }
+}
+
+fn trace_id(event: &Event, key: &str) -> Option<String> {
+    match event {
+        Event::Log(log) => log.get(&key.into()).map(|value| value.to_string_lossy()),
+        Event::Metric(metric) => metric.tags.as_ref().and_then(|tags| tags.get(key)).cloned(),
+    }
+}
+
+fn set_trace_id(event: &mut Event, key: &str, id: String) {
+    match event {
+        Event::Log(log) => {
+            log.insert(key, id);
+        }
+        Event::Metric(metric) => {
+            metric
+                .tags
+                .get_or_insert_with(BTreeMap::new)
+                .insert(key.to_owned(), id);
+        }
+    }
+}
+
+fn remove_trace_id(event: &mut Event, key: &str) {
+    match event {
+        Event::Log(log) => {
+            log.remove(&key.into());
+        }
+        Event::Metric(metric) => {
+            if let Some(tags) = &mut metric.tags {
+                tags.remove(key);
+                if tags.is_empty() {
+                    metric.tags = None;
+                }
+            }
+        }
+    }
+}
+
+/// Traces a sample of the events sources output, and records the hops of
+/// traced events through transforms and into sinks, if tracing is enabled.
+pub fn lifecycle_stream<S>(
+    name: &str,
+    kind: &'static str,
+    config: &LifecycleConfig,
+    stream: S,
+) -> Box<dyn Stream<Item = Event, Error = ()> + Send>
+where
+    S: Stream<Item = Event, Error = ()> + Send + 'static,
+{
+    if !config.enabled() {
+        return Box::new(stream);
+    }
+
+    let name = name.to_owned();
+    let config = config.clone();
+    Box::new(stream.map(move |mut event| {
+        let hop = || Hop {
+            component: name.clone(),
+            component_kind: kind.to_owned(),
+            at: Utc::now(),
+        };
+        match kind {
+            "source" => {
+                if rand::random::<f64>() < config.sample_ratio {
+                    let id = format!("{:016x}", rand::random::<u64>());
+                    set_trace_id(&mut event, &config.trace_key, id.clone());
+                    start(id, hop(), config.max_traces);
+                }
+            }
+            _ => {
+                if let Some(id) = trace_id(&event, &config.trace_key) {
+                    record(&id, hop());
+                    if kind == "sink" {
+                        remove_trace_id(&mut event, &config.trace_key);
+                    }
+                }
+            }
+        }
+        event
+    }))
+}
+
+/// Reads the traces persisted in the data directory, which has none at first.
+pub fn read_traces(data_dir: &Path) -> io::Result<Vec<Trace>> {
+    match fs::read(data_dir.join(SUBDIR).join(FILE_NAME)) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Prepares the data directory for `persist`.
+pub fn init(global: &GlobalOptions) -> crate::Result<()> {
+    let path = global
+        .resolve_and_make_data_subdir(None, SUBDIR)?
+        .join(FILE_NAME);
+    let _ = TRACES_PATH.set(path);
+    Ok(())
+}
+
+/// Writes the traces to the data directory, if `init` was called.
+pub fn persist() -> io::Result<()> {
+    match TRACES_PATH.get() {
+        Some(path) => {
+            let tmp_path = path.with_extension("json.tmp");
+            fs::write(&tmp_path, serde_json::to_vec_pretty(&traces())?)?;
+            fs::rename(&tmp_path, path)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Persists the traces every `persist_interval_secs`.
+pub fn persist_periodically(config: &LifecycleConfig) -> impl Future<Item = (), Error = ()> {
+    let period = Duration::from_secs(config.persist_interval_secs.max(1));
+    Interval::new(Instant::now() + period, period)
+        .map_err(|error| error!(message = "Lifecycle tracing timer failed.", %error))
+        .for_each(|_| {
+            if let Err(error) = persist() {
+                error!(message = "Unable to persist lifecycle traces.", %error);
+            }
+            Ok(())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+    use futures01::stream;
+
+    #[test]
+    fn lifecycle_records_hops_until_sinks() {
+        let config = LifecycleConfig {
+            sample_ratio: 1.0,
+            ..Default::default()
+        };
+        let events = vec![Event::from("hello")];
+
+        let stream = lifecycle_stream("in", "source", &config, stream::iter_ok(events));
+        let stream = lifecycle_stream("parse", "transform", &config, stream);
+        let traced = runtime().block_on(stream.collect()).unwrap();
+        let id = trace_id(&traced[0], "_trace_id").unwrap();
+
+        let stream = lifecycle_stream("out", "sink", &config, stream::iter_ok(traced));
+        let received = runtime().block_on(stream.collect()).unwrap();
+        assert_eq!(trace_id(&received[0], "_trace_id"), None);
+
+        let trace = traces().into_iter().find(|trace| trace.id == id).unwrap();
+        let path = trace
+            .hops
+            .iter()
+            .map(|hop| (hop.component.as_str(), hop.component_kind.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            path,
+            vec![("in", "source"), ("parse", "transform"), ("out", "sink")]
+        );
+        assert!(trace.latency() >= chrono::Duration::zero());
+    }
+
+    #[test]
+    fn lifecycle_disabled_traces_nothing() {
+        let config = LifecycleConfig::default();
+        let stream = lifecycle_stream(
+            "in",
+            "source",
+            &config,
+            stream::iter_ok(vec![Event::from("hello")]),
+        );
+        let events = runtime().block_on(stream.collect()).unwrap();
+        assert_eq!(trace_id(&events[0], "_trace_id"), None);
+    }
+}
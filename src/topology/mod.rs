@@ -6,9 +6,17 @@
 //! part contains config related items including config traits for
 //! each type of component.
 
+pub mod accounting;
 pub mod builder;
 pub mod config;
+pub mod dry_run;
 mod fanout;
+pub mod hardening;
+pub mod lifecycle;
+pub mod profile;
+pub mod scheduler;
+pub mod startup_report;
+pub mod state_dump;
 mod task;
 pub mod unit_test;
 
@@ -29,10 +37,17 @@ use futures01::{
 use indexmap::IndexMap;
 use std::collections::{HashMap, HashSet};
 use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use tokio01::timer;
 use tracing_futures::Instrument;
 
+/// Set once a topology is stopped as a whole, rather than some of its
+/// components by a reload. Only then do stateful transforms save their
+/// state for the next instance instead of flushing it.
+pub(crate) static STOPPING: AtomicBool = AtomicBool::new(false);
+
 #[allow(dead_code)]
 pub struct RunningTopology {
     inputs: HashMap<String, buffers::BufferInputCloner>,
@@ -77,6 +92,8 @@ pub fn start_validated(
         return None;
     }
     running_topology.start_diff(&diff, pieces, rt);
+    profile::set_timing(config.global.profile.timing);
+    scheduler::configure(&config.global.scheduler);
     running_topology.config = config;
 
     Some((running_topology, abort_rx))
@@ -105,6 +122,18 @@ impl RunningTopology {
         self.shutdown_coordinator.shutdown_tripwire()
     }
 
+    /// Writes a dump of the state of the running components into the data
+    /// directory, or the temporary one, returning its path.
+    pub fn dump_state(&self) -> std::io::Result<PathBuf> {
+        let directory = self
+            .config
+            .global
+            .data_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        state_dump::StateDump::new(&self.config).write(&directory)
+    }
+
     /// Sends the shutdown signal to all sources and returns a future that resolves
     /// once all components (sources, transforms, and sinks) have finished shutting down.
     /// Transforms and sinks should shut down automatically once their input tasks finish.
@@ -115,6 +144,8 @@ impl RunningTopology {
     /// dropped.
     #[must_use]
     pub fn stop(self) -> impl Future<Item = (), Error = ()> {
+        STOPPING.store(true, Ordering::Relaxed);
+
         // Create handy handles collections of all tasks for the subsequent operations.
         let mut wait_handles = Vec::new();
         // We need a Vec here since source compnents have two tasks. One for pump in self.tasks,
@@ -242,6 +273,9 @@ impl RunningTopology {
         if let Some(mut new_pieces) = validate(&new_config, &diff, rt.executor()) {
             if self.run_healthchecks(&diff, &mut new_pieces, rt, require_healthy) {
                 self.start_diff(&diff, new_pieces, rt);
+                profile::set_timing(new_config.global.profile.timing);
+                profile::retain(&new_config.global.profile);
+                scheduler::configure(&new_config.global.scheduler);
                 self.config = new_config;
                 // We have succesfully changed to new config.
                 return Ok(true);
@@ -357,6 +391,7 @@ impl RunningTopology {
             info!("Removing transform {:?}", name);
 
             self.tasks.remove(name).unwrap().forget();
+            buffers::usage::forget(name);
 
             self.remove_inputs(&name);
             self.remove_outputs(&name);
@@ -367,6 +402,7 @@ impl RunningTopology {
             info!("Removing sink {:?}", name);
 
             self.tasks.remove(name).unwrap().forget();
+            buffers::usage::forget(name);
 
             self.remove_inputs(&name);
         }
@@ -438,6 +474,8 @@ impl RunningTopology {
     ) {
         let task = new_pieces.tasks.remove(name).unwrap();
         let span = info_span!("sink", name = %task.name(), r#type = %task.typetag());
+        #[cfg(feature = "allocation-tracing")]
+        let task = crate::allocations::track("sink", name, task);
         let task = handle_errors(task.instrument(span), self.abort_tx.clone());
         let spawned = oneshot::spawn(task, &rt.executor());
         if let Some(previous) = self.tasks.insert(name.to_string(), spawned) {
@@ -453,6 +491,8 @@ impl RunningTopology {
     ) {
         let task = new_pieces.tasks.remove(name).unwrap();
         let span = info_span!("transform", name = %task.name(), r#type = %task.typetag());
+        #[cfg(feature = "allocation-tracing")]
+        let task = crate::allocations::track("transform", name, task);
         let task = handle_errors(task.instrument(span), self.abort_tx.clone());
         let spawned = oneshot::spawn(task, &rt.executor());
         if let Some(previous) = self.tasks.insert(name.to_string(), spawned) {
@@ -469,6 +509,8 @@ impl RunningTopology {
         let task = new_pieces.tasks.remove(name).unwrap();
         let span = info_span!("source", name = %task.name(), r#type = %task.typetag());
 
+        #[cfg(feature = "allocation-tracing")]
+        let task = crate::allocations::track("source", name, task);
         let task = handle_errors(task.instrument(span.clone()), self.abort_tx.clone());
         let spawned = oneshot::spawn(task, &rt.executor());
         if let Some(previous) = self.tasks.insert(name.to_string(), spawned) {
@@ -479,6 +521,8 @@ impl RunningTopology {
             .takeover_source(name, &mut new_pieces.shutdown_coordinator);
 
         let source_task = new_pieces.source_tasks.remove(name).unwrap();
+        #[cfg(feature = "allocation-tracing")]
+        let source_task = crate::allocations::track("source", name, source_task);
         let source_task = handle_errors(source_task.instrument(span), self.abort_tx.clone());
         self.source_tasks.insert(
             name.to_string(),
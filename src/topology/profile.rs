@@ -0,0 +1,503 @@
+//! Samples the events components output, to infer the names, types and
+//! cardinalities of their fields. This helps writing transforms and sink
+//! mappings against the shape data actually has. The schemas are served
+//! over HTTP and written in the state dump.
+//!
+//! Transforms can also be timed, to tell which of them, such as the one
+//! with the costly regex or grok pattern, takes up the CPU.
+
+use crate::{
+    event::{metric::MetricValue, Event, Value},
+    internal_events::TransformEventTimed,
+};
+use futures01::{Async, Future, Poll, Stream};
+use hyper::{
+    header::HeaderValue, service::service_fn_ok, Body, Method, Request, Response, Server,
+    StatusCode,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashSet},
+    hash::{Hash, Hasher},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Fields beyond these aren't profiled, in case their names are unbounded.
+const MAX_FIELDS: usize = 1000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// The components whose output is profiled, sinks are profiled on input.
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Profiles one in this many events.
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u64,
+    /// Distinct values counted per field, cardinality is capped at it.
+    #[serde(default = "default_max_distinct_values")]
+    pub max_distinct_values: usize,
+    /// Times every transform, taking effect on reload as well.
+    #[serde(default)]
+    pub timing: bool,
+    /// Serves the schemas over HTTP, bound when Vector starts.
+    pub address: Option<SocketAddr>,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        Self {
+            components: Vec::new(),
+            sample_rate: default_sample_rate(),
+            max_distinct_values: default_max_distinct_values(),
+            timing: false,
+            address: None,
+        }
+    }
+}
+
+fn default_sample_rate() -> u64 {
+    100
+}
+
+fn default_max_distinct_values() -> usize {
+    100
+}
+
+/// The schema inferred for a component.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub events_sampled: u64,
+    pub fields: BTreeMap<String, FieldSchema>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FieldSchema {
+    pub types: BTreeSet<&'static str>,
+    /// The number of sampled events having the field.
+    pub occurrences: u64,
+    pub cardinality: usize,
+    /// Whether there may be more distinct values than counted.
+    pub cardinality_capped: bool,
+}
+
+#[derive(Default)]
+struct Profile {
+    schema: Schema,
+    values: BTreeMap<String, HashSet<u64>>,
+}
+
+static PROFILES: Lazy<Mutex<BTreeMap<String, Arc<Mutex<Profile>>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// The schemas inferred so far, by component.
+pub fn schemas() -> BTreeMap<String, Schema> {
+    PROFILES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, profile)| (name.clone(), profile.lock().unwrap().schema.clone()))
+        .collect()
+}
+
+/// Serves the schemas as JSON, all of them on `/schemas` and the one of a
+/// component on `/schemas/<name>`.
+pub fn serve(address: SocketAddr) -> crate::Result<impl Future<Item = (), Error = ()>> {
+    let server = Server::try_bind(&address)?
+        .serve(|| service_fn_ok(handle))
+        .map_err(|error| error!(message = "Schema server failed.", %error));
+    Ok(server)
+}
+
+fn handle(request: Request<Body>) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    if *request.method() != Method::GET {
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return response;
+    }
+
+    let path = request.uri().path().trim_end_matches('/');
+    let body = if path == "/schemas" {
+        serde_json::to_vec(&schemas())
+    } else if path.starts_with("/schemas/") {
+        match schemas().get(&path["/schemas/".len()..]) {
+            Some(schema) => serde_json::to_vec(schema),
+            None => {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                return response;
+            }
+        }
+    } else {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    };
+
+    *response.body_mut() = body.expect("schemas serialize to JSON").into();
+    response
+        .headers_mut()
+        .insert("Content-Type", HeaderValue::from_static("application/json"));
+    response
+}
+
+/// Forgets the profiles of the components no longer profiled, such as the
+/// ones removed on reload.
+pub fn retain(config: &ProfileConfig) {
+    forget(|name| !config.components.iter().any(|component| component == name));
+}
+
+fn forget(is_forgotten: impl Fn(&str) -> bool) {
+    let mut profiles = PROFILES.lock().unwrap();
+    let forgotten = profiles
+        .keys()
+        .filter(|name| is_forgotten(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    for name in forgotten {
+        profiles.remove(&name);
+    }
+}
+
+struct Profiler {
+    config: ProfileConfig,
+    seen: u64,
+    profile: Arc<Mutex<Profile>>,
+}
+
+impl Profiler {
+    /// Registers a new profile for the component, replacing the previous
+    /// one when it's rebuilt on reload.
+    fn new(name: &str, config: &ProfileConfig) -> Self {
+        let profile = Arc::new(Mutex::new(Profile::default()));
+        PROFILES
+            .lock()
+            .unwrap()
+            .insert(name.to_owned(), Arc::clone(&profile));
+        Self {
+            config: config.clone(),
+            seen: 0,
+            profile,
+        }
+    }
+
+    fn observe(&mut self, event: &Event) {
+        self.seen += 1;
+        if (self.seen - 1) % self.config.sample_rate.max(1) != 0 {
+            return;
+        }
+
+        let mut profile = self.profile.lock().unwrap();
+        profile.schema.events_sampled += 1;
+        match event {
+            Event::Log(log) => {
+                for (path, value) in log.all_fields_escaped() {
+                    profile.record(
+                        normalize_path(&path),
+                        value_type(value),
+                        &value.as_bytes(),
+                        self.config.max_distinct_values,
+                    );
+                }
+            }
+            Event::Metric(metric) => {
+                let max = self.config.max_distinct_values;
+                profile.record("name".into(), "string", metric.name.as_bytes(), max);
+                for (key, value) in metric.tags.iter().flatten() {
+                    profile.record(format!("tags.{}", key), "string", value.as_bytes(), max);
+                }
+                let value = match metric.value {
+                    MetricValue::Counter { .. } => "counter",
+                    MetricValue::Gauge { .. } => "gauge",
+                    MetricValue::Set { .. } => "set",
+                    MetricValue::Distribution { .. } => "distribution",
+                    MetricValue::AggregatedHistogram { .. } => "aggregated_histogram",
+                    MetricValue::AggregatedSummary { .. } => "aggregated_summary",
+                };
+                profile.record("value".into(), value, value.as_bytes(), max);
+            }
+        }
+    }
+}
+
+impl Profile {
+    fn record(&mut self, path: String, type_name: &'static str, value: &[u8], max: usize) {
+        if !self.schema.fields.contains_key(&path) && self.schema.fields.len() >= MAX_FIELDS {
+            return;
+        }
+
+        let values = self.values.entry(path.clone()).or_insert_with(HashSet::new);
+        let field = self
+            .schema
+            .fields
+            .entry(path)
+            .or_insert_with(Default::default);
+        field.types.insert(type_name);
+        field.occurrences += 1;
+
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        if values.len() < max {
+            values.insert(hash);
+        } else if !values.contains(&hash) {
+            field.cardinality_capped = true;
+        }
+        field.cardinality = values.len();
+    }
+}
+
+/// Array elements are profiled together, as `field[]`.
+fn normalize_path(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                normalized.push(c);
+            }
+            ']' => {
+                in_index = false;
+                normalized.push(c);
+            }
+            _ if in_index => (),
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Bytes(_) => "string",
+        Value::Timestamp(_) => "timestamp",
+        Value::Integer(_) => "integer",
+        Value::Float(_) => "float",
+        Value::Boolean(_) => "boolean",
+        Value::Map(_) => "map",
+        Value::Array(_) => "array",
+        Value::Null => "null",
+    }
+}
+
+/// Profiles the events of the stream, if the component is configured to be.
+pub fn profile_stream<S>(
+    name: &str,
+    config: &ProfileConfig,
+    stream: S,
+) -> Box<dyn Stream<Item = Event, Error = ()> + Send>
+where
+    S: Stream<Item = Event, Error = ()> + Send + 'static,
+{
+    if config.components.iter().any(|component| component == name) {
+        let mut profiler = Profiler::new(name, config);
+        Box::new(stream.inspect(move |event| profiler.observe(event)))
+    } else {
+        Box::new(stream)
+    }
+}
+
+static TIMING: AtomicBool = AtomicBool::new(false);
+
+/// Switches the timing of transforms, which costs two clock reads per poll
+/// while on.
+pub fn set_timing(enabled: bool) {
+    TIMING.store(enabled, Ordering::Relaxed);
+}
+
+/// Times the output stream of a transform while timing is on. The time
+/// spent on the events it drops counts towards the next one it outputs.
+pub fn time_stream<S>(name: &str, transform_type: &'static str, stream: S) -> TimedStream<S>
+where
+    S: Stream<Item = Event, Error = ()>,
+{
+    timed_by(&TIMING, name, transform_type, stream)
+}
+
+/// Times the stream while `timing` is on.
+fn timed_by<S>(
+    timing: &'static AtomicBool,
+    name: &str,
+    transform_type: &'static str,
+    stream: S,
+) -> TimedStream<S>
+where
+    S: Stream<Item = Event, Error = ()>,
+{
+    TimedStream {
+        timing,
+        name: name.to_owned(),
+        transform_type,
+        inner: stream,
+        busy: Duration::default(),
+    }
+}
+
+pub struct TimedStream<S> {
+    timing: &'static AtomicBool,
+    name: String,
+    transform_type: &'static str,
+    inner: S,
+    busy: Duration,
+}
+
+impl<S> Stream for TimedStream<S>
+where
+    S: Stream<Item = Event, Error = ()>,
+{
+    type Item = Event;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if !self.timing.load(Ordering::Relaxed) {
+            self.busy = Duration::default();
+            return self.inner.poll();
+        }
+
+        let started = Instant::now();
+        let poll = self.inner.poll();
+        self.busy += started.elapsed();
+
+        if let Ok(Async::Ready(Some(_))) = poll {
+            emit!(TransformEventTimed {
+                component_name: &self.name,
+                component_type: self.transform_type,
+                elapsed: self.busy,
+            });
+            self.busy = Duration::default();
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::{Metric, MetricKind};
+    use futures01::{stream, Future};
+
+    fn config(sample_rate: u64, max_distinct_values: usize) -> ProfileConfig {
+        ProfileConfig {
+            components: vec!["in".into()],
+            sample_rate,
+            max_distinct_values,
+            timing: false,
+            address: None,
+        }
+    }
+
+    #[test]
+    fn profile_infers_log_fields() {
+        let mut profiler = Profiler::new("profile_logs", &config(1, 2));
+        for (status, user) in &[(200, "a"), (404, "b"), (500, "c")] {
+            let mut event = Event::from("message");
+            event.as_mut_log().insert("status", *status);
+            event.as_mut_log().insert("user.name", *user);
+            event.as_mut_log().insert("tags[0]", "x");
+            event.as_mut_log().insert("tags[1]", "y");
+            profiler.observe(&event);
+        }
+        let mut event = Event::from("message");
+        event.as_mut_log().insert("status", "unknown");
+        profiler.observe(&event);
+
+        let schema = schemas().remove("profile_logs").unwrap();
+        assert_eq!(schema.events_sampled, 4);
+
+        let status = &schema.fields["status"];
+        assert_eq!(
+            status.types.iter().copied().collect::<Vec<_>>(),
+            vec!["integer", "string"]
+        );
+        assert_eq!(status.occurrences, 4);
+        assert_eq!(status.cardinality, 2);
+        assert!(status.cardinality_capped);
+
+        assert_eq!(schema.fields["user.name"].occurrences, 3);
+        assert_eq!(schema.fields["tags[]"].occurrences, 6);
+        assert_eq!(schema.fields["message"].cardinality, 1);
+        assert!(!schema.fields["message"].cardinality_capped);
+    }
+
+    #[test]
+    fn profile_samples_metrics() {
+        let mut profiler = Profiler::new("profile_metrics", &config(2, 100));
+        for i in 0..4 {
+            let mut tags = BTreeMap::new();
+            tags.insert("host".to_owned(), format!("host{}", i));
+            profiler.observe(&Event::Metric(Metric {
+                name: "requests".into(),
+                timestamp: None,
+                tags: Some(tags),
+                kind: MetricKind::Incremental,
+                value: MetricValue::Counter { value: 1.0 },
+            }));
+        }
+
+        let schema = schemas().remove("profile_metrics").unwrap();
+        assert_eq!(schema.events_sampled, 2);
+        assert_eq!(schema.fields["tags.host"].cardinality, 2);
+        assert!(schema.fields["value"].types.contains("counter"));
+        assert_eq!(schema.fields["value"].cardinality, 1);
+    }
+
+    #[test]
+    fn profile_forgets_unprofiled_components() {
+        Profiler::new("profile_kept", &config(1, 2));
+        Profiler::new("profile_forgotten", &config(1, 2));
+        forget(|name| name == "profile_forgotten");
+
+        let schemas = schemas();
+        assert!(schemas.contains_key("profile_kept"));
+        assert!(!schemas.contains_key("profile_forgotten"));
+    }
+
+    #[test]
+    fn profile_serves_schemas() {
+        let mut profiler = Profiler::new("profile_served", &config(1, 2));
+        profiler.observe(&Event::from("message"));
+
+        let get = |path: &str| {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            let response = handle(request);
+            let status = response.status();
+            let body = response.into_body().concat2().wait().unwrap();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).ok(),
+            )
+        };
+
+        let (status, schemas) = get("/schemas");
+        assert_eq!(status, StatusCode::OK);
+        assert!(schemas.unwrap()["profile_served"].is_object());
+
+        let (status, schema) = get("/schemas/profile_served/");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(schema.unwrap()["events_sampled"], 1);
+
+        assert_eq!(get("/schemas/unknown").0, StatusCode::NOT_FOUND);
+        assert_eq!(get("/metrics").0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn profile_normalizes_array_paths() {
+        assert_eq!(normalize_path("a[0].b[12]"), "a[].b[]");
+        assert_eq!(normalize_path("a.b"), "a.b");
+    }
+
+    #[test]
+    fn timing_passes_events_through() {
+        // Not the global switch, which other tests would see.
+        static TIMING: AtomicBool = AtomicBool::new(true);
+
+        let events = vec![Event::from("a"), Event::from("b")];
+        let stream = timed_by(&TIMING, "timed", "test", stream::iter_ok(events.clone()));
+        assert_eq!(stream.collect().wait().unwrap(), events);
+    }
+}
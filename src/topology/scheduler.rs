@@ -0,0 +1,112 @@
+//! Schedules the scrapes of the sources polling other systems on an
+//! interval, such as `http_scrape` and `prometheus`, and the scheduled runs
+//! of `exec`.
+//!
+//! Each source starts its schedule at a random point of its first interval,
+//! so sources sharing an interval don't all scrape at the same moment.
+//! Scrapes past `max_concurrency` across all the sources wait for a running
+//! one to finish, and a scrape that hasn't finished by its source's deadline,
+//! waiting included, is given up on.
+
+use crate::internal_events::ScrapeDeadlineExceeded;
+use futures::{compat::Future01CompatExt, FutureExt, TryFutureExt};
+use futures01::Future;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, time::timeout};
+use tokio01::timer::Interval;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SchedulerConfig {
+    /// Scrapes running at once across all sources, unlimited if not set.
+    pub max_concurrency: Option<usize>,
+}
+
+/// The `max_concurrency` and the permits of the scrapes.
+static PERMITS: Lazy<Mutex<Option<(usize, Arc<Semaphore>)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Applies the config to the scrapes started from now on. Running scrapes
+/// keep to the limit they started under.
+pub fn configure(config: &SchedulerConfig) {
+    let mut permits = PERMITS.lock().unwrap();
+    if permits.as_ref().map(|(max, _)| *max) != config.max_concurrency {
+        *permits = config
+            .max_concurrency
+            .map(|max| (max, Arc::new(Semaphore::new(max.max(1)))));
+    }
+}
+
+/// Ticks every `period`, the first time at a random point of it.
+pub fn interval(period: Duration) -> Interval {
+    let jitter = period.mul_f64(rand::random::<f64>());
+    Interval::new(Instant::now() + jitter, period)
+}
+
+/// Runs the scrape once there is room for it, resolving to `None` if it
+/// hasn't finished by the deadline.
+pub fn scrape<F>(
+    source_type: &'static str,
+    deadline: Duration,
+    scrape: F,
+) -> impl Future<Item = Option<F::Item>, Error = F::Error>
+where
+    F: Future + Send + 'static,
+    F::Item: Send + 'static,
+    F::Error: Send + 'static,
+{
+    let permits = PERMITS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|(_, permits)| Arc::clone(permits));
+
+    let scrape = async move {
+        let _permit = match &permits {
+            Some(permits) => Some(permits.acquire().await),
+            None => None,
+        };
+        scrape.compat().await
+    };
+
+    async move {
+        match timeout(deadline, scrape).await {
+            Ok(result) => result.map(Some),
+            Err(_) => {
+                emit!(ScrapeDeadlineExceeded {
+                    source_type,
+                    deadline
+                });
+                Ok(None)
+            }
+        }
+    }
+    .boxed()
+    .compat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+    use futures01::future;
+    use tokio01::timer::Delay;
+
+    #[test]
+    fn scrapes_past_their_deadline_are_given_up_on() {
+        let mut rt = runtime();
+
+        let quick = scrape("test", Duration::from_secs(5), future::ok::<_, ()>(1));
+        assert_eq!(rt.block_on(quick), Ok(Some(1)));
+
+        let slow = Delay::new(Instant::now() + Duration::from_secs(5))
+            .map(|_| 1)
+            .map_err(drop);
+        let slow = scrape("test", Duration::from_millis(50), slow);
+        assert_eq!(rt.block_on(slow), Ok(None));
+    }
+}
@@ -0,0 +1,181 @@
+//! A snapshot of the state of a running Vector, written on `SIGUSR2` for the
+//! postmortem of an agent that stopped making progress.
+
+use super::{
+    profile::{self, Schema},
+    startup_report::ComponentReport,
+    Config,
+};
+use crate::buffers::usage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Smaller files in the data directories are dumped along with their names.
+const MAX_CONTENTS_SIZE: u64 = 4096;
+
+#[derive(Debug, Serialize)]
+pub struct StateDump {
+    pub version: String,
+    pub written_at: DateTime<Utc>,
+    pub components: Vec<ComponentReport>,
+    pub buffers: BTreeMap<String, BufferState>,
+    /// The files each component keeps in the data directory, which hold the
+    /// checkpoints of the sources.
+    pub checkpoints: BTreeMap<String, Vec<CheckpointFile>>,
+    /// The schemas inferred for the profiled components.
+    pub schemas: BTreeMap<String, Schema>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BufferState {
+    pub component_kind: &'static str,
+    pub buffer_type: &'static str,
+    pub events: usize,
+    pub byte_size: usize,
+    pub oldest_event_age_secs: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckpointFile {
+    /// Relative to the data directory of the component.
+    pub path: PathBuf,
+    pub byte_size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+}
+
+impl StateDump {
+    pub fn new(config: &Config) -> Self {
+        let buffers = usage::usages()
+            .into_iter()
+            .map(|(name, usage)| {
+                let state = BufferState {
+                    component_kind: usage.component_kind,
+                    buffer_type: usage.buffer_type,
+                    events: usage.events,
+                    byte_size: usage.byte_size,
+                    oldest_event_age_secs: usage.oldest_event_age.map(|age| age.as_secs_f64()),
+                };
+                (name, state)
+            })
+            .collect();
+
+        let names = config
+            .sources
+            .keys()
+            .chain(config.transforms.keys())
+            .chain(config.sinks.keys());
+        let checkpoints = match &config.global.data_dir {
+            Some(data_dir) => names
+                .filter_map(|name| {
+                    let files = checkpoint_files(&data_dir.join(name));
+                    if files.is_empty() {
+                        None
+                    } else {
+                        Some((name.clone(), files))
+                    }
+                })
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        Self {
+            version: crate::get_version(),
+            written_at: Utc::now(),
+            components: ComponentReport::all(config),
+            buffers,
+            checkpoints,
+            schemas: profile::schemas(),
+        }
+    }
+
+    /// Writes the dump as JSON into the directory, returning its path.
+    pub fn write(&self, directory: &Path) -> io::Result<PathBuf> {
+        let path = directory.join(format!(
+            "state-dump-{}.json",
+            self.written_at.format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::from)?;
+        fs::write(&path, json)?;
+        Ok(path)
+    }
+}
+
+fn checkpoint_files(directory: &Path) -> Vec<CheckpointFile> {
+    let mut files = Vec::new();
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(current) = directories.pop() {
+        let entries = match fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if metadata.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            let contents = if metadata.len() <= MAX_CONTENTS_SIZE {
+                fs::read(&path)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .filter(|contents| !contents.is_empty())
+            } else {
+                None
+            };
+            files.push(CheckpointFile {
+                path: path.strip_prefix(directory).unwrap_or(&path).to_path_buf(),
+                byte_size: metadata.len(),
+                modified_at: metadata.modified().ok().map(DateTime::from),
+                contents,
+            });
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::stdin::StdinConfig;
+
+    #[test]
+    fn state_dump_lists_checkpoints() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let checkpoints = data_dir.path().join("in").join("checkpoints");
+        fs::create_dir_all(&checkpoints).unwrap();
+        fs::write(checkpoints.join("1a2b.512"), "").unwrap();
+        fs::write(data_dir.path().join("in").join("checkpoint.txt"), "s=abc\n").unwrap();
+
+        let mut config = Config::empty();
+        config.global.data_dir = Some(data_dir.path().to_path_buf());
+        config.add_source("in", StdinConfig::default());
+
+        let dump = StateDump::new(&config);
+        assert_eq!(dump.components.len(), 1);
+        assert_eq!(dump.components[0].name, "in");
+
+        let files = &dump.checkpoints["in"];
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, Path::new("checkpoint.txt"));
+        assert_eq!(files[0].contents.as_deref(), Some("s=abc\n"));
+        assert_eq!(files[1].path, Path::new("checkpoints/1a2b.512"));
+        assert_eq!(files[1].contents, None);
+
+        let path = dump.write(data_dir.path()).unwrap();
+        let written: serde_json::Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        assert_eq!(written["checkpoints"]["in"][1]["byte_size"], 0);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::topology::{accounting, config::default_data_dir};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(rename_all = "kebab-case")]
+pub struct Opts {
+    /// The `data_dir` of the Vector instance to report on. Defaults to
+    /// `/var/lib/vector/`.
+    #[structopt(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Format the totals in an encoding scheme.
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    format: Format,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            s => Err(format!(
+                "{} is not a valid option, expected `text` or `json`",
+                s
+            )),
+        }
+    }
+}
+
+pub fn cmd(opts: &Opts) -> exitcode::ExitCode {
+    let data_dir = opts
+        .data_dir
+        .clone()
+        .or_else(default_data_dir)
+        .expect("default data_dir");
+    let totals = match accounting::read_totals(&data_dir) {
+        Ok(totals) => totals,
+        Err(error) => {
+            error!(message = "Unable to read accounting totals.", data_dir = ?data_dir, %error);
+            return exitcode::IOERR;
+        }
+    };
+
+    match opts.format {
+        Format::Text => {
+            if totals.is_empty() {
+                println!("No totals persisted yet, is `accounting` enabled?");
+            }
+            let width = totals
+                .iter()
+                .flat_map(|(name, totals)| {
+                    let tenants = totals.tenants.keys().map(|tenant| tenant.len() + 2);
+                    std::iter::once(name.len()).chain(tenants)
+                })
+                .max()
+                .unwrap_or(0);
+            for (name, totals) in totals {
+                print_row(name, &totals, width);
+                for (tenant, totals) in totals.tenants.iter() {
+                    print_row(format!("  {}", tenant), totals, width);
+                }
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&totals).unwrap()),
+    }
+
+    exitcode::OK
+}
+
+fn print_row(name: impl std::fmt::Display, totals: &accounting::Totals, width: usize) {
+    println!(
+        "{:width$}  {:>12} events  {:>16} bytes",
+        name,
+        totals.events,
+        totals.bytes,
+        width = width
+    );
+}
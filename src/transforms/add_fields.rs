@@ -3,7 +3,7 @@ use crate::serde::Fields;
 use crate::{
     event::{Event, Value},
     internal_events::AddFieldsEventProcessed,
-    template::Template,
+    template::{Template, TimeZone},
     topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
 };
 use chrono::{DateTime, Utc};
@@ -18,6 +18,9 @@ pub struct AddFieldsConfig {
     pub fields: Fields<TomlValue>,
     #[serde(default = "crate::serde::default_true")]
     pub overwrite: bool,
+    /// The time zone timestamps are rendered in by templated values.
+    #[serde(default)]
+    pub timezone: TimeZone,
 }
 
 #[derive(Clone)]
@@ -50,14 +53,17 @@ inventory::submit! {
 #[typetag::serde(name = "add_fields")]
 impl TransformConfig for AddFieldsConfig {
     fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
-        Ok(Box::new(AddFields::new(
-            self.fields
-                .clone()
-                .all_fields()
-                .map(|(k, v)| (k.into(), v.into()))
-                .collect(),
-            self.overwrite,
-        )))
+        Ok(Box::new(
+            AddFields::new(
+                self.fields
+                    .clone()
+                    .all_fields()
+                    .map(|(k, v)| (k.into(), v.into()))
+                    .collect(),
+                self.overwrite,
+            )
+            .with_timezone(self.timezone),
+        ))
     }
 
     fn input_type(&self) -> DataType {
@@ -86,6 +92,15 @@ impl AddFields {
             overwrite,
         }
     }
+
+    pub fn with_timezone(mut self, timezone: TimeZone) -> Self {
+        for value in self.fields.values_mut() {
+            if let TemplateOrValue::Template(template) = value {
+                *template = template.clone().with_timezone(timezone);
+            }
+        }
+        self
+    }
 }
 
 impl Transform for AddFields {
@@ -0,0 +1,527 @@
+use super::Transform;
+use crate::{
+    event::metric::{Metric, MetricKind, MetricValue},
+    internal_events::{AggregateEventProcessed, AggregateLateEventDropped},
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+    transforms::util::{
+        runtime_transform::{RuntimeTransform, Timer},
+        snapshot::{Snapshot, SnapshotConfig},
+    },
+    Event,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`interval_secs` must be at least 1"))]
+    IntervalTooShort,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateConfig {
+    /// The length of the windows, which are aligned to the epoch.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// How long after a window ends metrics for it are still accepted.
+    #[serde(default)]
+    pub allowed_lateness_secs: u64,
+    #[serde(default)]
+    pub gauge: GaugeAggregation,
+    /// Only these tags are kept, merging the series that only differ by the
+    /// others.
+    pub group_by: Option<Vec<String>>,
+    /// Hands the open windows on shutdown over to the next instance, rather
+    /// than flushing them.
+    pub snapshot: Option<SnapshotConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GaugeAggregation {
+    Last,
+    Mean,
+}
+
+impl Default for GaugeAggregation {
+    fn default() -> Self {
+        GaugeAggregation::Last
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+inventory::submit! {
+    TransformDescription::new_without_default::<AggregateConfig>("aggregate")
+}
+
+#[typetag::serde(name = "aggregate")]
+impl TransformConfig for AggregateConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        if self.interval_secs == 0 {
+            return Err(Box::new(BuildError::IntervalTooShort));
+        }
+        Ok(Box::new(Aggregate::new(self.clone())))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "aggregate"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+struct SeriesKey {
+    name: String,
+    tags: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Serialize)]
+enum Rollup {
+    /// The latest metric, or the sum of the incremental ones.
+    Metric {
+        kind: MetricKind,
+        value: MetricValue,
+        timestamp: DateTime<Utc>,
+    },
+    MeanGauge {
+        sum: f64,
+        count: u32,
+    },
+}
+
+impl Rollup {
+    fn update(&mut self, kind: MetricKind, value: MetricValue, timestamp: DateTime<Utc>) {
+        match self {
+            Rollup::MeanGauge { sum, count } => {
+                if let MetricValue::Gauge { value } = value {
+                    *sum += value;
+                    *count += 1;
+                }
+            }
+            Rollup::Metric {
+                kind: current_kind,
+                value: current,
+                timestamp: current_timestamp,
+            } => {
+                let same_type = std::mem::discriminant(current) == std::mem::discriminant(&value);
+                if same_type && current_kind.is_incremental() && kind.is_incremental() {
+                    let mut metric = Metric {
+                        name: String::new(),
+                        timestamp: None,
+                        tags: None,
+                        kind: MetricKind::Incremental,
+                        value: std::mem::replace(current, MetricValue::Counter { value: 0.0 }),
+                    };
+                    metric.add(&Metric {
+                        name: String::new(),
+                        timestamp: None,
+                        tags: None,
+                        kind,
+                        value,
+                    });
+                    *current = metric.value;
+                } else if timestamp >= *current_timestamp {
+                    *current_kind = kind;
+                    *current = value;
+                    *current_timestamp = timestamp;
+                }
+            }
+        }
+    }
+}
+
+pub struct Aggregate {
+    config: AggregateConfig,
+    /// The series of each open window, by the start of the window.
+    windows: BTreeMap<i64, IndexMap<SeriesKey, Rollup>>,
+    snapshot: Option<Snapshot>,
+}
+
+/// The windows as saved to the snapshot, serde_json not taking maps with
+/// non-string keys.
+type Windows = Vec<(i64, Vec<(SeriesKey, Rollup)>)>;
+
+impl Aggregate {
+    pub fn new(config: AggregateConfig) -> Self {
+        let snapshot = config.snapshot.as_ref().map(Snapshot::new);
+        Self {
+            config,
+            windows: BTreeMap::new(),
+            snapshot,
+        }
+    }
+
+    fn window_start(&self, timestamp: DateTime<Utc>) -> i64 {
+        let interval = self.config.interval_secs as i64;
+        timestamp.timestamp().div_euclid(interval) * interval
+    }
+
+    /// Whether the window starting then doesn't accept metrics anymore.
+    fn is_closed(&self, start: i64, now: DateTime<Utc>) -> bool {
+        let end = start + (self.config.interval_secs + self.config.allowed_lateness_secs) as i64;
+        end <= now.timestamp()
+    }
+
+    fn record(&mut self, metric: Metric, now: DateTime<Utc>) {
+        let timestamp = metric.timestamp.unwrap_or(now);
+        let start = self.window_start(timestamp);
+        if self.is_closed(start, now) {
+            emit!(AggregateLateEventDropped {
+                name: &metric.name,
+                timestamp,
+            });
+            return;
+        }
+
+        let tags = match (&self.config.group_by, metric.tags) {
+            (Some(group_by), Some(tags)) => Some(
+                tags.into_iter()
+                    .filter(|(key, _)| group_by.contains(key))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+            .filter(|tags| !tags.is_empty()),
+            (_, tags) => tags,
+        };
+        let key = SeriesKey {
+            name: metric.name,
+            tags,
+        };
+
+        let gauge = self.config.gauge;
+        let series = self.windows.entry(start).or_insert_with(IndexMap::new);
+        match series.get_mut(&key) {
+            Some(rollup) => rollup.update(metric.kind, metric.value, timestamp),
+            None => {
+                let rollup = match metric.value {
+                    MetricValue::Gauge { value }
+                        if gauge == GaugeAggregation::Mean && metric.kind.is_absolute() =>
+                    {
+                        Rollup::MeanGauge {
+                            sum: value,
+                            count: 1,
+                        }
+                    }
+                    value => Rollup::Metric {
+                        kind: metric.kind,
+                        value,
+                        timestamp,
+                    },
+                };
+                series.insert(key, rollup);
+            }
+        }
+    }
+
+    /// Emits the series of the windows closed by now, or of all of them.
+    fn flush<F>(&mut self, now: Option<DateTime<Utc>>, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        let starts = self
+            .windows
+            .keys()
+            .copied()
+            .filter(|start| now.map_or(true, |now| self.is_closed(*start, now)))
+            .collect::<Vec<_>>();
+
+        for start in starts {
+            let series = self.windows.remove(&start).unwrap_or_default();
+            for (key, rollup) in series {
+                let (kind, value) = match rollup {
+                    Rollup::Metric { kind, value, .. } => (kind, value),
+                    Rollup::MeanGauge { sum, count } => (
+                        MetricKind::Absolute,
+                        MetricValue::Gauge {
+                            value: sum / f64::from(count),
+                        },
+                    ),
+                };
+                emit_fn(Event::Metric(Metric {
+                    name: key.name,
+                    timestamp: Some(Utc.timestamp(start, 0)),
+                    tags: key.tags,
+                    kind,
+                    value,
+                }));
+            }
+        }
+    }
+
+    /// Restores the windows of the snapshot, those closed meanwhile are
+    /// flushed on the next tick.
+    fn restore(&mut self) {
+        let windows = self.snapshot.as_ref().and_then(|snapshot| {
+            snapshot.restore(|state| serde_json::from_slice::<Windows>(state))
+        });
+        for (start, series) in windows.unwrap_or_default() {
+            self.windows.insert(start, series.into_iter().collect());
+        }
+    }
+
+    /// Saves the windows to the snapshot, or flushes them if that fails.
+    fn save<F>(&mut self, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        if let Some(snapshot) = &self.snapshot {
+            let windows = std::mem::replace(&mut self.windows, BTreeMap::new());
+            let windows = windows
+                .into_iter()
+                .map(|(start, series)| (start, series.into_iter().collect()))
+                .collect::<Windows>();
+            let state = serde_json::to_vec(&windows).expect("windows serialize to json");
+            if snapshot.save(&state) {
+                return;
+            }
+            for (start, series) in windows {
+                self.windows.insert(start, series.into_iter().collect());
+            }
+        }
+        self.flush(None, emit_fn);
+    }
+}
+
+impl RuntimeTransform for Aggregate {
+    fn hook_init<F>(&mut self, _emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.restore();
+    }
+
+    fn hook_process<F>(&mut self, event: Event, _emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        emit!(AggregateEventProcessed);
+        self.record(event.into_metric(), Utc::now());
+    }
+
+    fn hook_shutdown<F>(&mut self, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.save(emit_fn);
+    }
+
+    fn timer_handler<F>(&mut self, _timer: Timer, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.flush(Some(Utc::now()), emit_fn);
+    }
+
+    fn timers(&self) -> Vec<Timer> {
+        // Windows close on second boundaries.
+        vec![Timer {
+            id: 0,
+            interval_seconds: 1,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::util::snapshot;
+    use std::sync::atomic::AtomicBool;
+
+    fn aggregate(config: &str) -> Aggregate {
+        Aggregate::new(toml::from_str(config).unwrap())
+    }
+
+    fn metric(name: &str, kind: MetricKind, value: MetricValue, secs: i64) -> Metric {
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), format!("host{}", secs));
+        tags.insert("region".to_owned(), "eu".to_owned());
+        Metric {
+            name: name.into(),
+            timestamp: Some(Utc.timestamp(secs, 0)),
+            tags: Some(tags),
+            kind,
+            value,
+        }
+    }
+
+    fn counter(value: f64, secs: i64) -> Metric {
+        metric(
+            "requests",
+            MetricKind::Incremental,
+            MetricValue::Counter { value },
+            secs,
+        )
+    }
+
+    fn gauge(value: f64, secs: i64) -> Metric {
+        metric(
+            "memory",
+            MetricKind::Absolute,
+            MetricValue::Gauge { value },
+            secs,
+        )
+    }
+
+    fn flush(aggregate: &mut Aggregate, now: Option<i64>) -> Vec<Metric> {
+        let mut output = Vec::new();
+        aggregate.flush(now.map(|secs| Utc.timestamp(secs, 0)), |event| {
+            output.push(event.into_metric())
+        });
+        output
+    }
+
+    #[test]
+    fn aggregate_rolls_up_windows() {
+        let mut aggregate = aggregate(
+            r#"
+            interval_secs = 10
+            allowed_lateness_secs = 10
+            gauge = "mean"
+            group_by = ["region"]
+            "#,
+        );
+        let now = Utc.timestamp(105, 0);
+        for metric in vec![
+            counter(1.0, 100),
+            counter(2.0, 101),
+            counter(4.0, 95),
+            gauge(10.0, 100),
+            gauge(20.0, 102),
+        ] {
+            aggregate.record(metric, now);
+        }
+
+        let output = flush(&mut aggregate, Some(120));
+        let mut region = BTreeMap::new();
+        region.insert("region".to_owned(), "eu".to_owned());
+        assert_eq!(
+            output,
+            vec![
+                Metric {
+                    name: "requests".into(),
+                    timestamp: Some(Utc.timestamp(90, 0)),
+                    tags: Some(region.clone()),
+                    kind: MetricKind::Incremental,
+                    value: MetricValue::Counter { value: 4.0 },
+                },
+                Metric {
+                    name: "requests".into(),
+                    timestamp: Some(Utc.timestamp(100, 0)),
+                    tags: Some(region.clone()),
+                    kind: MetricKind::Incremental,
+                    value: MetricValue::Counter { value: 3.0 },
+                },
+                Metric {
+                    name: "memory".into(),
+                    timestamp: Some(Utc.timestamp(100, 0)),
+                    tags: Some(region),
+                    kind: MetricKind::Absolute,
+                    value: MetricValue::Gauge { value: 15.0 },
+                },
+            ]
+        );
+        assert!(aggregate.windows.is_empty());
+    }
+
+    #[test]
+    fn aggregate_keeps_last_gauge() {
+        let mut aggregate = aggregate("interval_secs = 10");
+        let now = Utc.timestamp(105, 0);
+        aggregate.record(gauge(20.0, 102), now);
+        aggregate.record(gauge(10.0, 100), now);
+
+        let output = flush(&mut aggregate, None);
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].value, MetricValue::Gauge { value: 20.0 });
+    }
+
+    #[test]
+    fn aggregate_restores_windows_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = format!(
+            r#"
+            interval_secs = 10
+            gauge = "mean"
+
+            [snapshot]
+            directory = "{}"
+            key = "aggregate"
+            "#,
+            dir.path().display()
+        );
+        let now = Utc.timestamp(105, 0);
+
+        let mut previous = aggregate(&config);
+        previous.snapshot = Some(snapshot::stopping(&snapshot_config(&dir)));
+        previous.record(counter(1.0, 100), now);
+        previous.record(gauge(10.0, 101), now);
+        let mut output = Vec::new();
+        previous.hook_shutdown(|event| output.push(event));
+        assert!(output.is_empty());
+
+        let mut next = aggregate(&config);
+        next.hook_init(|event| output.push(event));
+        next.record(counter(2.0, 102), now);
+        next.record(gauge(20.0, 103), now);
+
+        let output = flush(&mut next, None);
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].value, MetricValue::Counter { value: 3.0 });
+        assert_eq!(output[1].value, MetricValue::Gauge { value: 15.0 });
+    }
+
+    #[test]
+    fn aggregate_flushes_windows_when_reloaded() {
+        static STOPPING: AtomicBool = AtomicBool::new(false);
+        let dir = tempfile::tempdir().unwrap();
+        let now = Utc.timestamp(105, 0);
+
+        let mut previous = aggregate("interval_secs = 10");
+        previous.snapshot = Some(Snapshot::with_stopping(&snapshot_config(&dir), &STOPPING));
+        previous.record(counter(1.0, 100), now);
+        let mut output = Vec::new();
+        previous.hook_shutdown(|event| output.push(event));
+        assert_eq!(output.len(), 1);
+        assert!(!dir.path().join("aggregate.snapshot").exists());
+    }
+
+    fn snapshot_config(dir: &tempfile::TempDir) -> SnapshotConfig {
+        SnapshotConfig {
+            directory: dir.path().into(),
+            key: "aggregate".into(),
+            max_age_secs: 300,
+        }
+    }
+
+    #[test]
+    fn aggregate_allowed_lateness() {
+        let mut aggregate = aggregate(
+            r#"
+            interval_secs = 10
+            allowed_lateness_secs = 5
+            "#,
+        );
+        // The window ending at 110 stays open until 115.
+        aggregate.record(counter(1.0, 100), Utc.timestamp(112, 0));
+        assert!(flush(&mut aggregate, Some(114)).is_empty());
+        aggregate.record(counter(2.0, 100), Utc.timestamp(114, 0));
+        aggregate.record(counter(4.0, 100), Utc.timestamp(115, 0));
+
+        let output = flush(&mut aggregate, Some(115));
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].value, MetricValue::Counter { value: 3.0 });
+    }
+}
@@ -0,0 +1,474 @@
+use super::Transform;
+use crate::{
+    event::metric::{Metric, MetricKind, MetricValue},
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+    transforms::util::runtime_transform::{RuntimeTransform, Timer},
+    Event,
+};
+use chrono::Utc;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`interval_secs` must be at least 1"))]
+    IntervalTooShort,
+    #[snafu(display("`buckets` must be sorted and must not be empty"))]
+    InvalidBuckets,
+    #[snafu(display("`relative_accuracy` must be between 0 and 1, got {}", accuracy))]
+    InvalidAccuracy { accuracy: f64 },
+    #[snafu(display("`quantiles` must be between 0 and 1, got {}", quantile))]
+    InvalidQuantile { quantile: f64 },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateHistogramConfig {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default = "default_buckets")]
+    pub buckets: Vec<f64>,
+    #[serde(default = "default_relative_accuracy")]
+    pub relative_accuracy: f64,
+    #[serde(default = "default_quantiles")]
+    pub quantiles: Vec<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    Histogram,
+    Ddsketch,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Histogram
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    10
+}
+
+fn default_buckets() -> Vec<f64> {
+    vec![
+        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+    ]
+}
+
+fn default_relative_accuracy() -> f64 {
+    0.01
+}
+
+fn default_quantiles() -> Vec<f64> {
+    vec![0.5, 0.75, 0.9, 0.95, 0.99]
+}
+
+inventory::submit! {
+    TransformDescription::new_without_default::<AggregateHistogramConfig>("aggregate_histogram")
+}
+
+#[typetag::serde(name = "aggregate_histogram")]
+impl TransformConfig for AggregateHistogramConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        if self.interval_secs == 0 {
+            return Err(Box::new(BuildError::IntervalTooShort));
+        }
+        match self.mode {
+            Mode::Histogram => {
+                if self.buckets.is_empty() || self.buckets.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err(Box::new(BuildError::InvalidBuckets));
+                }
+            }
+            Mode::Ddsketch => {
+                let accuracy = self.relative_accuracy;
+                if !(accuracy > 0.0 && accuracy < 1.0) {
+                    return Err(Box::new(BuildError::InvalidAccuracy { accuracy }));
+                }
+                if let Some(quantile) = self.quantiles.iter().find(|q| !(0.0..=1.0).contains(*q)) {
+                    return Err(Box::new(BuildError::InvalidQuantile {
+                        quantile: *quantile,
+                    }));
+                }
+            }
+        }
+
+        Ok(Box::new(AggregateHistogram::new(self.clone())))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "aggregate_histogram"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    name: String,
+    tags: Option<BTreeMap<String, String>>,
+}
+
+enum Aggregate {
+    Histogram {
+        counts: Vec<u32>,
+        count: u32,
+        sum: f64,
+    },
+    Sketch(DDSketch),
+}
+
+pub struct AggregateHistogram {
+    config: AggregateHistogramConfig,
+    series: IndexMap<SeriesKey, Aggregate>,
+}
+
+impl AggregateHistogram {
+    pub fn new(config: AggregateHistogramConfig) -> Self {
+        Self {
+            config,
+            series: IndexMap::new(),
+        }
+    }
+
+    fn record(&mut self, key: SeriesKey, values: &[f64], sample_rates: &[u32]) {
+        let config = &self.config;
+        let aggregate = self.series.entry(key).or_insert_with(|| match config.mode {
+            Mode::Histogram => Aggregate::Histogram {
+                counts: vec![0; config.buckets.len()],
+                count: 0,
+                sum: 0.0,
+            },
+            Mode::Ddsketch => Aggregate::Sketch(DDSketch::new(config.relative_accuracy)),
+        });
+
+        for (value, rate) in values.iter().zip(sample_rates) {
+            match aggregate {
+                Aggregate::Histogram { counts, count, sum } => {
+                    for (bucket, bucket_count) in config.buckets.iter().zip(counts.iter_mut()) {
+                        if value <= bucket {
+                            *bucket_count += rate;
+                        }
+                    }
+                    *count += rate;
+                    *sum += value * f64::from(*rate);
+                }
+                Aggregate::Sketch(sketch) => sketch.insert(*value, *rate),
+            }
+        }
+    }
+
+    /// Emits one metric per series seen since the last flush. Histograms
+    /// only count the samples of the interval, so they are incremental;
+    /// quantiles can't be added up, so summaries are absolute.
+    fn flush<F>(&mut self, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        let timestamp = Utc::now();
+        for (key, aggregate) in self.series.drain(..) {
+            let (kind, value) = match aggregate {
+                Aggregate::Histogram { counts, count, sum } => (
+                    MetricKind::Incremental,
+                    MetricValue::AggregatedHistogram {
+                        buckets: self.config.buckets.clone(),
+                        counts,
+                        count,
+                        sum,
+                    },
+                ),
+                Aggregate::Sketch(sketch) => (
+                    MetricKind::Absolute,
+                    MetricValue::AggregatedSummary {
+                        quantiles: self.config.quantiles.clone(),
+                        values: self
+                            .config
+                            .quantiles
+                            .iter()
+                            .map(|quantile| sketch.quantile(*quantile))
+                            .collect(),
+                        count: sketch.count,
+                        sum: sketch.sum,
+                    },
+                ),
+            };
+
+            emit_fn(Event::Metric(Metric {
+                name: key.name,
+                timestamp: Some(timestamp),
+                tags: key.tags,
+                kind,
+                value,
+            }));
+        }
+    }
+}
+
+impl RuntimeTransform for AggregateHistogram {
+    fn hook_process<F>(&mut self, event: Event, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        let metric = event.into_metric();
+        match metric.value {
+            MetricValue::Distribution {
+                values,
+                sample_rates,
+            } => {
+                let key = SeriesKey {
+                    name: metric.name,
+                    tags: metric.tags,
+                };
+                self.record(key, &values, &sample_rates);
+            }
+            // Everything else is cheap already.
+            value => emit_fn(Event::Metric(Metric { value, ..metric })),
+        }
+    }
+
+    fn hook_shutdown<F>(&mut self, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.flush(emit_fn);
+    }
+
+    fn timer_handler<F>(&mut self, _timer: Timer, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.flush(emit_fn);
+    }
+
+    fn timers(&self) -> Vec<Timer> {
+        vec![Timer {
+            id: 0,
+            interval_seconds: self.config.interval_secs,
+        }]
+    }
+}
+
+/// A DDSketch: samples are counted in logarithmically sized buckets, so
+/// every quantile is estimated within the relative accuracy no matter how
+/// the samples are distributed.
+///
+/// See https://arxiv.org/abs/1908.10693
+struct DDSketch {
+    gamma_ln: f64,
+    positive: BTreeMap<i32, u32>,
+    negative: BTreeMap<i32, u32>,
+    zero: u32,
+    count: u32,
+    sum: f64,
+}
+
+impl DDSketch {
+    fn new(relative_accuracy: f64) -> Self {
+        let gamma = (1.0 + relative_accuracy) / (1.0 - relative_accuracy);
+        Self {
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zero: 0,
+            count: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn index(&self, value: f64) -> i32 {
+        (value.ln() / self.gamma_ln).ceil() as i32
+    }
+
+    /// The value in the middle of a bucket, in terms of relative error.
+    fn value(&self, index: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (f64::from(index) * self.gamma_ln).exp() / (gamma + 1.0)
+    }
+
+    fn insert(&mut self, value: f64, rate: u32) {
+        if value > 0.0 {
+            *self.positive.entry(self.index(value)).or_insert(0) += rate;
+        } else if value < 0.0 {
+            *self.negative.entry(self.index(-value)).or_insert(0) += rate;
+        } else {
+            self.zero += rate;
+        }
+        self.count += rate;
+        self.sum += value * f64::from(rate);
+    }
+
+    fn quantile(&self, quantile: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+
+        let rank = (quantile * f64::from(self.count - 1)).floor() as u32;
+        let mut seen = 0;
+        // Negative values are ordered by decreasing magnitude.
+        for (index, count) in self.negative.iter().rev() {
+            seen += count;
+            if seen > rank {
+                return -self.value(*index);
+            }
+        }
+        seen += self.zero;
+        if seen > rank {
+            return 0.0;
+        }
+        for (index, count) in &self.positive {
+            seen += count;
+            if seen > rank {
+                return self.value(*index);
+            }
+        }
+        unreachable!("rank is below the total count")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extra: &str) -> AggregateHistogramConfig {
+        toml::from_str(extra).unwrap()
+    }
+
+    fn distribution(name: &str, values: Vec<f64>) -> Event {
+        let sample_rates = vec![1; values.len()];
+        Event::Metric(Metric {
+            name: name.into(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Distribution {
+                values,
+                sample_rates,
+            },
+        })
+    }
+
+    fn process(transform: &mut AggregateHistogram, events: Vec<Event>) -> Vec<Event> {
+        let mut output = Vec::new();
+        for event in events {
+            transform.hook_process(event, |event| output.push(event));
+        }
+        output
+    }
+
+    #[test]
+    fn aggregate_histogram_buckets_distributions() {
+        let mut transform = AggregateHistogram::new(config("buckets = [1.0, 10.0]"));
+        let passed = process(
+            &mut transform,
+            vec![
+                distribution("latency", vec![0.5, 2.0]),
+                distribution("latency", vec![20.0]),
+            ],
+        );
+        assert!(passed.is_empty());
+
+        let mut output = Vec::new();
+        transform.hook_shutdown(|event| output.push(event));
+        assert_eq!(output.len(), 1);
+        let metric = output.remove(0).into_metric();
+        assert_eq!(metric.kind, MetricKind::Incremental);
+        assert_eq!(
+            metric.value,
+            MetricValue::AggregatedHistogram {
+                buckets: vec![1.0, 10.0],
+                counts: vec![1, 2],
+                count: 3,
+                sum: 22.5,
+            }
+        );
+
+        let mut output = Vec::new();
+        transform.timer_handler(transform.timers()[0], |event| output.push(event));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn aggregate_histogram_passes_other_metrics() {
+        let mut transform = AggregateHistogram::new(config(""));
+        let counter = Event::Metric(Metric {
+            name: "hits".into(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.0 },
+        });
+        assert_eq!(
+            process(&mut transform, vec![counter.clone()]),
+            vec![counter]
+        );
+    }
+
+    #[test]
+    fn aggregate_histogram_ddsketch_quantiles() {
+        let mut transform = AggregateHistogram::new(config(
+            r#"
+            mode = "ddsketch"
+            quantiles = [0.0, 0.5, 0.99, 1.0]
+        "#,
+        ));
+        let values = (1..=1000).map(f64::from).collect::<Vec<_>>();
+        process(&mut transform, vec![distribution("latency", values)]);
+
+        let mut output = Vec::new();
+        transform.hook_shutdown(|event| output.push(event));
+        let metric = output.remove(0).into_metric();
+        assert_eq!(metric.kind, MetricKind::Absolute);
+        match metric.value {
+            MetricValue::AggregatedSummary {
+                values, count, sum, ..
+            } => {
+                assert_eq!(count, 1000);
+                assert_eq!(sum, 500_500.0);
+                for (value, expected) in values.iter().zip(&[1.0, 500.0, 990.0, 1000.0]) {
+                    assert!((value - expected).abs() <= expected * 0.01);
+                }
+            }
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn aggregate_histogram_ddsketch_negative_and_zero() {
+        let mut sketch = DDSketch::new(0.01);
+        for value in &[-5.0, 0.0, 3.0] {
+            sketch.insert(*value, 1);
+        }
+        assert!((sketch.quantile(0.0) + 5.0).abs() <= 0.05);
+        assert_eq!(sketch.quantile(0.5), 0.0);
+        assert!((sketch.quantile(1.0) - 3.0).abs() <= 0.03);
+    }
+
+    #[test]
+    fn aggregate_histogram_rejects_invalid_config() {
+        let rt = crate::test_util::runtime();
+        let build = |config: AggregateHistogramConfig| {
+            config.build(TransformContext::new_test(rt.executor()))
+        };
+        assert!(build(config("interval_secs = 0")).is_err());
+        assert!(build(config("buckets = [2.0, 1.0]")).is_err());
+        assert!(build(config(
+            r#"
+            mode = "ddsketch"
+            relative_accuracy = 1.5
+        "#
+        ))
+        .is_err());
+        assert!(build(config("")).is_ok());
+    }
+}
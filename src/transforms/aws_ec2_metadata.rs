@@ -1,4 +1,7 @@
-use super::Transform;
+use super::{
+    util::batch_transform::{BatchTransform, Batched},
+    Transform,
+};
 use crate::{
     event::Event,
     sinks::util::http::HttpClient,
@@ -158,7 +161,7 @@ impl TransformConfig for Ec2Metadata {
             .instrument(info_span!("aws_ec2_metadata: worker")),
         );
 
-        Ok(Box::new(Ec2MetadataTransform { state: read }))
+        Ok(Box::new(Batched::new(Ec2MetadataTransform { state: read })))
     }
 
     fn input_type(&self) -> DataType {
@@ -174,17 +177,23 @@ impl TransformConfig for Ec2Metadata {
     }
 }
 
-impl Transform for Ec2MetadataTransform {
-    fn transform(&mut self, mut event: Event) -> Option<Event> {
-        let log = event.as_mut_log();
-
+impl BatchTransform for Ec2MetadataTransform {
+    fn transform_batch(&mut self, events: Vec<Event>, output: &mut Vec<Event>) {
+        // The metadata is read once for the whole batch.
+        let mut metadata = Vec::new();
         self.state.for_each(|k, v| {
             if let Some(value) = v.get(0) {
-                log.insert(k.clone(), value.clone());
+                metadata.push((k.clone(), value.clone()));
             }
         });
 
-        Some(event)
+        output.extend(events.into_iter().map(|mut event| {
+            let log = event.as_mut_log();
+            for (key, value) in &metadata {
+                log.insert(key.clone(), value.clone());
+            }
+            event
+        }));
     }
 }
 
@@ -3,10 +3,15 @@ use crate::{
     event,
     event::{Event, Value},
     topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+    transforms::util::{
+        runtime_transform::RuntimeTransform,
+        snapshot::{Snapshot, SnapshotConfig},
+    },
 };
 use bytes::Bytes;
 use lru::LruCache;
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use string_cache::DefaultAtom as Atom;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -24,6 +29,10 @@ pub enum FieldMatchConfig {
 #[serde(deny_unknown_fields)]
 pub struct CacheConfig {
     pub num_events: usize,
+    /// Events are only compared against cached events seen within this many
+    /// seconds, so a repeated event is let through once per interval.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -33,10 +42,16 @@ pub struct DedupeConfig {
     pub fields: FieldMatchConfig,
     #[serde(default = "default_cache_config")]
     pub cache: CacheConfig,
+    /// Hands the cache on shutdown over to the next instance, so it keeps
+    /// suppressing the duplicates of events let through before.
+    pub snapshot: Option<SnapshotConfig>,
 }
 
 fn default_cache_config() -> CacheConfig {
-    CacheConfig { num_events: 5000 }
+    CacheConfig {
+        num_events: 5000,
+        ttl_secs: None,
+    }
 }
 
 /// Note that the value returned by this is just a placeholder.  To get the real default you must
@@ -61,13 +76,17 @@ impl DedupeConfig {
         Self {
             fields,
             cache: self.cache.clone(),
+            snapshot: self.snapshot.clone(),
         }
     }
 }
 
 pub struct Dedupe {
     config: DedupeConfig,
-    cache: LruCache<CacheEntry, bool>,
+    /// Maps each cached entry to the time it was first let through.
+    cache: LruCache<CacheEntry, Instant>,
+    ttl: Option<Duration>,
+    snapshot: Option<Snapshot>,
 }
 
 inventory::submit! {
@@ -114,7 +133,7 @@ type TypeId = u8;
 /// are backed by a BTreeMap), and we build CacheEntries by iterating over the fields of the
 /// incoming Events, we know that the CacheEntries for 2 equivalent events will always contain the
 /// fields in the same order.
-#[derive(PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Hash, Deserialize, Serialize)]
 enum CacheEntry {
     Match(Vec<Option<(TypeId, Bytes)>>),
     Ignore(Vec<(Atom, TypeId, Bytes)>),
@@ -137,9 +156,40 @@ fn type_id_for_value(val: &Value) -> TypeId {
 impl Dedupe {
     pub fn new(config: DedupeConfig) -> Self {
         let num_entries = config.cache.num_events;
+        let ttl = config.cache.ttl_secs.map(Duration::from_secs);
+        let snapshot = config.snapshot.as_ref().map(Snapshot::new);
         Self {
             config,
             cache: LruCache::new(num_entries),
+            ttl,
+            snapshot,
+        }
+    }
+
+    /// Restores the entries of the snapshot, keeping how long ago they were
+    /// let through.
+    fn restore(&mut self, now: Instant) {
+        let entries = self.snapshot.as_ref().and_then(|snapshot| {
+            snapshot.restore(|state| serde_json::from_slice::<Vec<(CacheEntry, u64)>>(state))
+        });
+        for (entry, age_ms) in entries.unwrap_or_default() {
+            let seen = now
+                .checked_sub(Duration::from_millis(age_ms))
+                .unwrap_or(now);
+            self.cache.put(entry, seen);
+        }
+    }
+
+    fn save(&mut self, now: Instant) {
+        if let Some(snapshot) = &self.snapshot {
+            // Oldest first, so they are restored in the same order.
+            let mut entries = Vec::with_capacity(self.cache.len());
+            while let Some((entry, seen)) = self.cache.pop_lru() {
+                let age_ms = now.duration_since(seen).as_millis() as u64;
+                entries.push((entry, age_ms));
+            }
+            let state = serde_json::to_vec(&entries).expect("cache entries serialize to json");
+            snapshot.save(&state);
         }
     }
 }
@@ -163,7 +213,7 @@ fn build_cache_entry(event: &Event, fields: &FieldMatchConfig) -> CacheEntry {
         FieldMatchConfig::IgnoreFields(fields) => {
             let mut entry = Vec::new();
 
-            for (field_name, value) in event.as_log().all_fields() {
+            for (field_name, value) in event.as_log().all_fields_escaped() {
                 if !fields.contains(&field_name) {
                     entry.push((
                         Atom::from(field_name),
@@ -181,34 +231,66 @@ fn build_cache_entry(event: &Event, fields: &FieldMatchConfig) -> CacheEntry {
     }
 }
 
-impl Transform for Dedupe {
-    fn transform(&mut self, event: Event) -> Option<Event> {
+impl RuntimeTransform for Dedupe {
+    fn hook_init<F>(&mut self, _emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.restore(Instant::now());
+    }
+
+    fn hook_process<F>(&mut self, event: Event, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
         let cache_entry = build_cache_entry(&event, &self.config.fields);
-        if self.cache.put(cache_entry, true).is_some() {
+        let now = Instant::now();
+        let ttl = self.ttl;
+        let duplicate = match self.cache.get(&cache_entry) {
+            Some(seen) => ttl.map_or(true, |ttl| now.duration_since(*seen) < ttl),
+            None => false,
+        };
+
+        if duplicate {
             warn!(
                 message = "Encountered duplicate event; discarding",
                 rate_limit_secs = 30
             );
             trace!(message = "Encountered duplicate event; discarding", ?event);
-            None
         } else {
-            Some(event)
+            self.cache.put(cache_entry, now);
+            emit_fn(event);
         }
     }
+
+    fn hook_shutdown<F>(&mut self, _emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.save(Instant::now());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Dedupe;
     use crate::transforms::dedupe::{CacheConfig, DedupeConfig, FieldMatchConfig};
-    use crate::{event::Event, event::Value, transforms::Transform};
-    use std::collections::BTreeMap;
+    use crate::transforms::util::snapshot;
+    use crate::{
+        event::Event, event::Value, transforms::util::runtime_transform::RuntimeTransform,
+        transforms::Transform,
+    };
+    use std::{collections::BTreeMap, time::Duration};
     use string_cache::DefaultAtom as Atom;
 
     fn make_match_transform(num_events: usize, fields: Vec<Atom>) -> Dedupe {
         Dedupe::new(DedupeConfig {
-            cache: CacheConfig { num_events },
+            cache: CacheConfig {
+                num_events,
+                ttl_secs: None,
+            },
             fields: { FieldMatchConfig::MatchFields(fields) },
+            snapshot: None,
         })
     }
 
@@ -218,8 +300,12 @@ mod tests {
         fields.extend(given_fields);
 
         Dedupe::new(DedupeConfig {
-            cache: CacheConfig { num_events },
+            cache: CacheConfig {
+                num_events,
+                ttl_secs: None,
+            },
             fields: { FieldMatchConfig::IgnoreFields(fields) },
+            snapshot: None,
         })
     }
 
@@ -365,6 +451,82 @@ mod tests {
         assert_eq!(new_event.as_log()[&"matched".into()], "some value".into());
     }
 
+    #[test]
+    fn dedupe_match_ttl_expiry() {
+        let transform = make_match_transform(5, vec!["matched".into()]);
+        ttl_expiry(transform);
+    }
+
+    #[test]
+    fn dedupe_ignore_ttl_expiry() {
+        let transform = make_ignore_transform(5, vec![]);
+        ttl_expiry(transform);
+    }
+
+    /// Test that cached events stop suppressing duplicates once they expire
+    fn ttl_expiry(mut transform: Dedupe) {
+        transform.ttl = Some(Duration::from_millis(100));
+
+        let mut event1 = Event::from("message");
+        event1.as_mut_log().insert("matched", "some value");
+        let event2 = event1.clone();
+        let event3 = event1.clone();
+        let event4 = event1.clone();
+
+        assert!(transform.transform(event1).is_some());
+        // Within the TTL the duplicate is dropped.
+        assert_eq!(None, transform.transform(event2));
+
+        std::thread::sleep(Duration::from_millis(150));
+
+        // The cached event expired, so the duplicate is let through and
+        // cached again.
+        assert!(transform.transform(event3).is_some());
+        assert_eq!(None, transform.transform(event4));
+    }
+
+    #[test]
+    fn dedupe_restores_cache_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = format!(
+            r#"
+            fields.match = ["message"]
+
+            [snapshot]
+            directory = "{}"
+            key = "dedupe"
+            "#,
+            dir.path().display()
+        );
+        let config: DedupeConfig = toml::from_str(&config).unwrap();
+
+        let snapshot = config.snapshot.clone().unwrap();
+
+        let mut previous = Dedupe::new(config.fill_default());
+        previous.snapshot = Some(snapshot::stopping(&snapshot));
+        assert!(previous.transform(Event::from("first")).is_some());
+        previous.hook_shutdown(|_| ());
+
+        let mut next = Dedupe::new(config.fill_default());
+        next.hook_init(|_| ());
+        assert_eq!(None, next.transform(Event::from("first")));
+        assert!(next.transform(Event::from("second")).is_some());
+    }
+
+    #[test]
+    fn dedupe_parse_ttl() {
+        let config: DedupeConfig = toml::from_str(
+            r#"
+            fields.match = ["kubernetes.pod_name", "message"]
+            cache.num_events = 100
+            cache.ttl_secs = 60
+            "#,
+        )
+        .unwrap();
+        let transform = Dedupe::new(config.fill_default());
+        assert_eq!(transform.ttl, Some(Duration::from_secs(60)));
+    }
+
     #[test]
     fn dedupe_match_type_matching() {
         let transform = make_match_transform(5, vec!["matched".into()]);
@@ -15,6 +15,8 @@ use string_cache::DefaultAtom as Atom;
 #[serde(deny_unknown_fields)]
 pub struct LogToMetricConfig {
     pub metrics: Vec<MetricConfig>,
+    #[serde(default = "default_drop_original")]
+    pub drop_original: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -64,6 +66,10 @@ fn default_increment_by_value() -> bool {
     false
 }
 
+fn default_drop_original() -> bool {
+    true
+}
+
 pub struct LogToMetric {
     config: LogToMetricConfig,
 }
@@ -83,7 +89,11 @@ impl TransformConfig for LogToMetricConfig {
     }
 
     fn output_type(&self) -> DataType {
-        DataType::Metric
+        if self.drop_original {
+            DataType::Metric
+        } else {
+            DataType::Any
+        }
     }
 
     fn transform_type(&self) -> &'static str {
@@ -259,6 +269,10 @@ impl Transform for LogToMetric {
                 }
             }
         }
+
+        if !self.config.drop_original {
+            output.push(event);
+        }
     }
 }
 
@@ -649,4 +663,29 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn pass_through_original() {
+        let config = parse_config(
+            r#"
+            drop_original = false
+
+            [[metrics]]
+            type = "counter"
+            field = "status"
+            "#,
+        );
+
+        let event = create_event("status", "42");
+        let mut transform = LogToMetric::new(config);
+
+        let mut output = Vec::new();
+        transform.transform_into(&mut output, event.clone());
+        assert_eq!(2, output.len());
+        assert_eq!(output.pop().unwrap(), event);
+        assert_eq!(
+            output.pop().unwrap().into_metric().value,
+            MetricValue::Counter { value: 1.0 }
+        );
+    }
 }
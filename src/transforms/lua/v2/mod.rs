@@ -125,7 +125,10 @@ impl Lua {
         let additional_paths = config
             .search_dirs
             .iter()
-            .map(|d| format!("{}/?.lua", d.to_string_lossy()))
+            .map(|d| {
+                let d = d.to_string_lossy();
+                format!("{}/?.lua;{}/?/init.lua", d, d)
+            })
             .collect::<Vec<_>>()
             .join(";");
 
@@ -296,7 +299,7 @@ impl RuntimeTransform for Lua {
                     }
                 })
             })
-            .context(RuntimeErrorHooksInit)
+            .context(RuntimeErrorHooksShutdown)
             .map_err(|e| error!(error = %e, rate_limit = 30));
 
         self.attempt_gc();
@@ -659,6 +662,38 @@ mod tests {
         assert_eq!(event.as_log()[&"new field".into()], "new value".into());
     }
 
+    #[test]
+    fn lua_load_package_dir() {
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("tagger")).unwrap();
+        fs::write(
+            dir.path().join("tagger").join("init.lua"),
+            r#"
+            return { tag = function(event) event.log.tagged = true end }
+            "#,
+        )
+        .unwrap();
+
+        let config = format!(
+            r#"
+            hooks.process = """function (event, emit)
+                require("tagger").tag(event)
+                emit(event)
+            end
+            """
+            search_dirs = ["{}"]
+            "#,
+            dir.path().display()
+        );
+
+        let mut transform = from_config(&config).unwrap();
+        let event = transform.transform(Event::new_empty_log()).unwrap();
+
+        assert_eq!(event.as_log()[&"tagged".into()], Value::Boolean(true));
+    }
+
     #[test]
     fn lua_pairs() {
         let mut transform = from_config(
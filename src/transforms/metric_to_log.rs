@@ -0,0 +1,158 @@
+use super::Transform;
+use crate::{
+    event::{self, Event, LogEvent, Value},
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MetricToLogConfig {
+    /// The tag copied to the host field of the log schema.
+    pub host_tag: Option<String>,
+}
+
+inventory::submit! {
+    TransformDescription::new::<MetricToLogConfig>("metric_to_log")
+}
+
+#[typetag::serde(name = "metric_to_log")]
+impl TransformConfig for MetricToLogConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        Ok(Box::new(MetricToLog::new(self.host_tag.clone())))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Metric
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "metric_to_log"
+    }
+}
+
+pub struct MetricToLog {
+    host_tag: String,
+}
+
+impl MetricToLog {
+    pub fn new(host_tag: Option<String>) -> Self {
+        Self {
+            host_tag: host_tag.unwrap_or_else(|| "host".into()),
+        }
+    }
+}
+
+impl Transform for MetricToLog {
+    fn transform(&mut self, event: Event) -> Option<Event> {
+        let metric = event.into_metric();
+        let fields = match serde_json::to_value(&metric) {
+            Ok(JsonValue::Object(fields)) => fields,
+            Ok(_) => unreachable!("metrics serialize to objects"),
+            Err(error) => {
+                warn!(
+                    message = "failed to serialize metric.",
+                    %error,
+                    rate_limit_secs = 30
+                );
+                return None;
+            }
+        };
+
+        // The value is keyed by the metric type, e.g. `counter.value` or
+        // `aggregated_histogram.buckets`, and tags are kept under `tags`.
+        let mut log = LogEvent::new();
+        for (key, value) in fields {
+            if key != "timestamp" && !value.is_null() {
+                log.insert(key, Value::from(value));
+            }
+        }
+
+        log.insert(
+            event::log_schema().timestamp_key(),
+            metric.timestamp.unwrap_or_else(Utc::now),
+        );
+        if let Some(host) = metric
+            .tags
+            .as_ref()
+            .and_then(|tags| tags.get(&self.host_tag))
+        {
+            log.insert(event::log_schema().host_key(), host.as_str());
+        }
+
+        Some(log.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::{Metric, MetricKind, MetricValue};
+    use chrono::TimeZone;
+    use std::collections::BTreeMap;
+
+    fn log(metric: Metric) -> LogEvent {
+        MetricToLog::new(None)
+            .transform(metric.into())
+            .unwrap()
+            .into_log()
+    }
+
+    #[test]
+    fn transform_counter() {
+        let timestamp = Utc.ymd(2020, 6, 1).and_hms(10, 0, 0);
+        let mut tags = BTreeMap::new();
+        tags.insert("host".to_owned(), "localhost".to_owned());
+        tags.insert("code".to_owned(), "200".to_owned());
+
+        let log = log(Metric {
+            name: "requests".into(),
+            timestamp: Some(timestamp),
+            tags: Some(tags),
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 2.0 },
+        });
+
+        assert_eq!(log[&"name".into()], "requests".into());
+        assert_eq!(log[&"kind".into()], "incremental".into());
+        assert_eq!(log[&"counter.value".into()], Value::Float(2.0));
+        assert_eq!(log[&"tags.code".into()], "200".into());
+        assert_eq!(log[&"host".into()], "localhost".into());
+        assert_eq!(log[&"timestamp".into()], Value::Timestamp(timestamp));
+    }
+
+    #[test]
+    fn transform_histogram() {
+        let log = log(Metric {
+            name: "latency".into(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Absolute,
+            value: MetricValue::AggregatedHistogram {
+                buckets: vec![1.0, 2.0],
+                counts: vec![3, 4],
+                count: 7,
+                sum: 10.5,
+            },
+        });
+
+        assert_eq!(
+            log[&"aggregated_histogram.buckets".into()],
+            Value::Array(vec![Value::Float(1.0), Value::Float(2.0)])
+        );
+        assert_eq!(
+            log[&"aggregated_histogram.counts".into()],
+            Value::Array(vec![Value::Integer(3), Value::Integer(4)])
+        );
+        assert_eq!(log[&"aggregated_histogram.count".into()], Value::Integer(7));
+        assert!(!log.contains(&"tags".into()));
+        assert!(!log.contains(&"host".into()));
+        assert!(log.contains(&"timestamp".into()));
+    }
+}
@@ -3,10 +3,16 @@ use snafu::Snafu;
 
 mod util;
 
+pub use util::batch_transform::{BatchTransform, Batched};
+
 #[cfg(feature = "transforms-add_fields")]
 pub mod add_fields;
 #[cfg(feature = "transforms-add_tags")]
 pub mod add_tags;
+#[cfg(feature = "transforms-aggregate")]
+pub mod aggregate;
+#[cfg(feature = "transforms-aggregate_histogram")]
+pub mod aggregate_histogram;
 #[cfg(feature = "transforms-ansi_stripper")]
 pub mod ansi_stripper;
 #[cfg(feature = "transforms-aws_ec2_metadata")]
@@ -35,6 +41,10 @@ pub mod logfmt_parser;
 pub mod lua;
 #[cfg(feature = "transforms-merge")]
 pub mod merge;
+#[cfg(feature = "transforms-metric_to_log")]
+pub mod metric_to_log;
+#[cfg(feature = "transforms-reduce")]
+pub mod reduce;
 #[cfg(feature = "transforms-regex_parser")]
 pub mod regex_parser;
 #[cfg(feature = "transforms-remove_fields")]
@@ -43,6 +53,8 @@ pub mod remove_fields;
 pub mod remove_tags;
 #[cfg(feature = "transforms-rename_fields")]
 pub mod rename_fields;
+#[cfg(feature = "transforms-route")]
+pub mod route;
 #[cfg(feature = "transforms-sampler")]
 pub mod sampler;
 #[cfg(feature = "transforms-split")]
@@ -51,8 +63,12 @@ pub mod split;
 pub mod swimlanes;
 #[cfg(feature = "transforms-tag_cardinality_limit")]
 pub mod tag_cardinality_limit;
+#[cfg(feature = "transforms-throttle")]
+pub mod throttle;
 #[cfg(feature = "transforms-tokenizer")]
 pub mod tokenizer;
+#[cfg(feature = "transforms-wasm")]
+pub mod wasm;
 
 use futures01::Stream;
 
@@ -0,0 +1,583 @@
+use super::Transform;
+use crate::{
+    conditions::{AnyCondition, Condition},
+    event::{discriminant::Discriminant, proto, Event, LogEvent, Value},
+    internal_events::ReduceEventProcessed,
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+    transforms::util::{
+        runtime_transform::{RuntimeTransform, Timer},
+        snapshot::{Snapshot, SnapshotConfig},
+    },
+};
+use bytes::Buf;
+use indexmap::IndexMap;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("`flush_period_secs` must be at least 1"))]
+    FlushPeriodTooShort,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ReduceConfig {
+    /// Events are reduced with the others having the same values for these
+    /// fields.
+    #[serde(default)]
+    pub identifier_fields: Vec<Atom>,
+    /// How the values of top level fields are merged, fields not listed
+    /// keep their first value.
+    #[serde(default)]
+    pub merge_strategies: IndexMap<String, MergeStrategy>,
+    /// Events matching it end their group, which is then flushed.
+    pub ends_when: Option<AnyCondition>,
+    /// Groups without new events for this long are flushed.
+    #[serde(default = "default_expire_after_secs")]
+    pub expire_after_secs: u64,
+    #[serde(default = "default_flush_period_secs")]
+    pub flush_period_secs: u64,
+    /// Hands the groups in progress on shutdown over to the next instance,
+    /// rather than flushing them.
+    pub snapshot: Option<SnapshotConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    First,
+    Last,
+    /// Joins the values with spaces.
+    Concat,
+    /// Joins the values with newlines, e.g. the lines of a stack trace.
+    ConcatNewline,
+    Array,
+    /// Adds up the numeric values.
+    Sum,
+}
+
+fn default_expire_after_secs() -> u64 {
+    30
+}
+
+fn default_flush_period_secs() -> u64 {
+    1
+}
+
+inventory::submit! {
+    TransformDescription::new_without_default::<ReduceConfig>("reduce")
+}
+
+#[typetag::serde(name = "reduce")]
+impl TransformConfig for ReduceConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        if self.flush_period_secs == 0 {
+            return Err(Box::new(BuildError::FlushPeriodTooShort));
+        }
+        Ok(Box::new(Reduce::new(self)?))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "reduce"
+    }
+}
+
+/// The value of a field merged so far.
+enum Merger {
+    First(Value),
+    Last(Value),
+    Concat { separator: u8, bytes: Vec<u8> },
+    Array(Vec<Value>),
+    Sum(Value),
+}
+
+impl Merger {
+    fn new(strategy: MergeStrategy, value: Value) -> Self {
+        match strategy {
+            MergeStrategy::First => Merger::First(value),
+            MergeStrategy::Last => Merger::Last(value),
+            MergeStrategy::Concat => Merger::Concat {
+                separator: b' ',
+                bytes: value.as_bytes().to_vec(),
+            },
+            MergeStrategy::ConcatNewline => Merger::Concat {
+                separator: b'\n',
+                bytes: value.as_bytes().to_vec(),
+            },
+            MergeStrategy::Array => Merger::Array(vec![value]),
+            MergeStrategy::Sum => Merger::Sum(value),
+        }
+    }
+
+    /// Continues from a value merged by a previous instance.
+    fn restore(strategy: MergeStrategy, value: Value) -> Self {
+        match (strategy, value) {
+            (MergeStrategy::Array, Value::Array(values)) => Merger::Array(values),
+            (strategy, value) => Merger::new(strategy, value),
+        }
+    }
+
+    fn add(&mut self, value: Value) {
+        match self {
+            Merger::First(_) => (),
+            Merger::Last(last) => *last = value,
+            Merger::Concat { separator, bytes } => {
+                bytes.push(*separator);
+                bytes.extend_from_slice(&value.as_bytes());
+            }
+            Merger::Array(values) => values.push(value),
+            Merger::Sum(sum) => {
+                *sum = match (&*sum, value) {
+                    (Value::Integer(a), Value::Integer(b)) => Value::Integer(a.saturating_add(b)),
+                    (Value::Integer(a), Value::Float(b)) => Value::Float(*a as f64 + b),
+                    (Value::Float(a), Value::Integer(b)) => Value::Float(a + b as f64),
+                    (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+                    // Other values don't count, unless nothing numeric did yet.
+                    (Value::Integer(_), _) | (Value::Float(_), _) => return,
+                    (_, value) => value,
+                }
+            }
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Merger::First(value) | Merger::Last(value) | Merger::Sum(value) => value,
+            Merger::Concat { bytes, .. } => Value::Bytes(bytes.into()),
+            Merger::Array(values) => Value::Array(values),
+        }
+    }
+}
+
+struct ReduceState {
+    fields: BTreeMap<String, Merger>,
+    updated: Instant,
+}
+
+impl ReduceState {
+    fn new(now: Instant) -> Self {
+        Self {
+            fields: BTreeMap::new(),
+            updated: now,
+        }
+    }
+
+    fn add(&mut self, log: LogEvent, strategies: &IndexMap<String, MergeStrategy>, now: Instant) {
+        for (key, value) in log {
+            match self.fields.get_mut(&key) {
+                Some(merger) => merger.add(value),
+                None => {
+                    let strategy = strategies
+                        .get(&key)
+                        .copied()
+                        .unwrap_or(MergeStrategy::First);
+                    self.fields.insert(key, Merger::new(strategy, value));
+                }
+            }
+        }
+        self.updated = now;
+    }
+
+    fn restore(log: LogEvent, strategies: &IndexMap<String, MergeStrategy>, now: Instant) -> Self {
+        let fields = log
+            .into_iter()
+            .map(|(key, value)| {
+                let strategy = strategies
+                    .get(&key)
+                    .copied()
+                    .unwrap_or(MergeStrategy::First);
+                let merger = Merger::restore(strategy, value);
+                (key, merger)
+            })
+            .collect();
+        Self {
+            fields,
+            updated: now,
+        }
+    }
+
+    fn flush(self) -> Event {
+        let mut log = LogEvent::new();
+        for (key, merger) in self.fields {
+            log.insert_flat(key, merger.into_value());
+        }
+        Event::Log(log)
+    }
+}
+
+pub struct Reduce {
+    identifier_fields: Vec<Atom>,
+    merge_strategies: IndexMap<String, MergeStrategy>,
+    ends_when: Option<Box<dyn Condition>>,
+    expire_after: Duration,
+    flush_period_secs: u64,
+    /// The groups in the order they were started.
+    groups: IndexMap<Discriminant, ReduceState>,
+    snapshot: Option<Snapshot>,
+}
+
+impl Reduce {
+    pub fn new(config: &ReduceConfig) -> crate::Result<Self> {
+        Ok(Self {
+            identifier_fields: config.identifier_fields.clone(),
+            merge_strategies: config.merge_strategies.clone(),
+            ends_when: config
+                .ends_when
+                .as_ref()
+                .map(AnyCondition::build)
+                .transpose()?,
+            expire_after: Duration::from_secs(config.expire_after_secs),
+            flush_period_secs: config.flush_period_secs,
+            groups: IndexMap::new(),
+            snapshot: config.snapshot.as_ref().map(Snapshot::new),
+        })
+    }
+
+    fn process<F>(&mut self, event: Event, now: Instant, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        emit!(ReduceEventProcessed);
+
+        let ends = self
+            .ends_when
+            .as_ref()
+            .map_or(false, |condition| condition.check(&event));
+        let log = event.into_log();
+        let key = Discriminant::from_log_event(&log, &self.identifier_fields);
+
+        if ends {
+            let mut state = self
+                .groups
+                .shift_remove(&key)
+                .unwrap_or_else(|| ReduceState::new(now));
+            state.add(log, &self.merge_strategies, now);
+            emit_fn(state.flush());
+        } else {
+            self.groups
+                .entry(key)
+                .or_insert_with(|| ReduceState::new(now))
+                .add(log, &self.merge_strategies, now);
+        }
+    }
+
+    /// Flushes the groups that expired by now, or all of them.
+    fn flush<F>(&mut self, now: Option<Instant>, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        let expire_after = self.expire_after;
+        for (key, state) in std::mem::replace(&mut self.groups, IndexMap::new()) {
+            let expired = now.map_or(true, |now| {
+                now.duration_since(state.updated) >= expire_after
+            });
+            if expired {
+                emit_fn(state.flush());
+            } else {
+                self.groups.insert(key, state);
+            }
+        }
+    }
+
+    /// Restores the groups of the snapshot, which expire as if just updated.
+    fn restore(&mut self, now: Instant) {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        for log in snapshot.restore(decode_groups).unwrap_or_default() {
+            let key = Discriminant::from_log_event(&log, &self.identifier_fields);
+            let state = ReduceState::restore(log, &self.merge_strategies, now);
+            self.groups.insert(key, state);
+        }
+    }
+
+    /// Saves the groups to the snapshot, or flushes them if that fails.
+    fn save<F>(&mut self, mut emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        let events = std::mem::replace(&mut self.groups, IndexMap::new())
+            .into_iter()
+            .map(|(_, state)| state.flush())
+            .collect::<Vec<_>>();
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.save(&encode_groups(&events)) {
+                return;
+            }
+        }
+        events.into_iter().for_each(|event| emit_fn(event));
+    }
+}
+
+/// The groups are saved as the events they would have been flushed as.
+fn encode_groups(events: &[Event]) -> Vec<u8> {
+    let mut state = Vec::new();
+    for event in events {
+        proto::EventWrapper::from(event.clone())
+            .encode_length_delimited(&mut state)
+            .expect("writing to a Vec doesn't fail");
+    }
+    state
+}
+
+fn decode_groups(state: &[u8]) -> Result<Vec<LogEvent>, prost::DecodeError> {
+    let mut state = std::io::Cursor::new(state);
+    let mut logs = Vec::new();
+    while state.has_remaining() {
+        let wrapper = proto::EventWrapper::decode_length_delimited(&mut state)?;
+        if let Event::Log(log) = Event::from(wrapper) {
+            logs.push(log);
+        }
+    }
+    Ok(logs)
+}
+
+impl RuntimeTransform for Reduce {
+    fn hook_init<F>(&mut self, _emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.restore(Instant::now());
+    }
+
+    fn hook_process<F>(&mut self, event: Event, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.process(event, Instant::now(), emit_fn);
+    }
+
+    fn hook_shutdown<F>(&mut self, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        if self.snapshot.is_some() {
+            self.save(emit_fn);
+        } else {
+            self.flush(None, emit_fn);
+        }
+    }
+
+    fn timer_handler<F>(&mut self, _timer: Timer, emit_fn: F)
+    where
+        F: FnMut(Event),
+    {
+        self.flush(Some(Instant::now()), emit_fn);
+    }
+
+    fn timers(&self) -> Vec<Timer> {
+        vec![Timer {
+            id: 0,
+            interval_seconds: self.flush_period_secs,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transforms::util::snapshot;
+    use std::sync::atomic::AtomicBool;
+
+    fn reduce(config: &str) -> Reduce {
+        Reduce::new(&toml::from_str(config).unwrap()).unwrap()
+    }
+
+    fn event(request_id: &str, message: &str, bytes: i64) -> Event {
+        let mut event = Event::from(message);
+        event.as_mut_log().insert("request_id", request_id);
+        event.as_mut_log().insert("bytes", bytes);
+        event
+    }
+
+    fn process(reduce: &mut Reduce, event: Event, now: Instant) -> Vec<Event> {
+        let mut output = Vec::new();
+        reduce.process(event, now, |event| output.push(event));
+        output
+    }
+
+    #[test]
+    fn reduce_merges_until_ends_when() {
+        let mut reduce = reduce(
+            r#"
+            identifier_fields = ["request_id"]
+
+            [ends_when]
+            "message.eq" = "done"
+
+            [merge_strategies]
+            message = "concat"
+            bytes = "sum"
+            "#,
+        );
+        let now = Instant::now();
+
+        assert!(process(&mut reduce, event("a", "started", 1), now).is_empty());
+        assert!(process(&mut reduce, event("b", "started", 10), now).is_empty());
+        assert!(process(&mut reduce, event("a", "working", 2), now).is_empty());
+
+        let output = process(&mut reduce, event("a", "done", 4), now);
+        assert_eq!(output.len(), 1);
+        let log = output[0].as_log();
+        assert_eq!(log[&"message".into()], "started working done".into());
+        assert_eq!(log[&"bytes".into()], 7.into());
+        assert_eq!(log[&"request_id".into()], "a".into());
+        assert_eq!(reduce.groups.len(), 1);
+    }
+
+    #[test]
+    fn reduce_merge_strategies() {
+        let mut reduce = reduce(
+            r#"
+            [merge_strategies]
+            message = "concat_newline"
+            bytes = "array"
+            request_id = "last"
+            "#,
+        );
+        let now = Instant::now();
+        process(&mut reduce, event("a", "Exception in main", 1), now);
+        process(&mut reduce, event("b", "  at Foo.bar()", 2), now);
+
+        let mut output = Vec::new();
+        reduce.flush(None, |event| output.push(event));
+        assert_eq!(output.len(), 1);
+        let log = output[0].as_log();
+        assert_eq!(
+            log[&"message".into()],
+            "Exception in main\n  at Foo.bar()".into()
+        );
+        assert_eq!(log[&"bytes".into()], Value::Array(vec![1.into(), 2.into()]));
+        assert_eq!(log[&"request_id".into()], "b".into());
+    }
+
+    #[test]
+    fn reduce_flushes_expired_groups() {
+        let mut reduce = reduce(
+            r#"
+            identifier_fields = ["request_id"]
+            expire_after_secs = 5
+            "#,
+        );
+        let now = Instant::now();
+        process(&mut reduce, event("a", "first", 1), now);
+        process(
+            &mut reduce,
+            event("b", "first", 1),
+            now + Duration::from_secs(3),
+        );
+        process(
+            &mut reduce,
+            event("a", "second", 1),
+            now + Duration::from_secs(4),
+        );
+
+        let mut output = Vec::new();
+        reduce.flush(Some(now + Duration::from_secs(8)), |event| {
+            output.push(event)
+        });
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].as_log()[&"request_id".into()], "b".into());
+
+        reduce.flush(Some(now + Duration::from_secs(9)), |event| {
+            output.push(event)
+        });
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[1].as_log()[&"message".into()], "first".into());
+    }
+
+    #[test]
+    fn reduce_restores_groups_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = format!(
+            r#"
+            identifier_fields = ["request_id"]
+
+            [ends_when]
+            "message.eq" = "done"
+
+            [merge_strategies]
+            message = "concat"
+            bytes = "array"
+
+            [snapshot]
+            directory = "{}"
+            key = "reduce"
+            "#,
+            dir.path().display()
+        );
+        let now = Instant::now();
+
+        let mut previous = reduce(&config);
+        previous.snapshot = Some(snapshot::stopping(&snapshot_config(&dir)));
+        process(&mut previous, event("a", "started", 1), now);
+        process(&mut previous, event("b", "started", 10), now);
+        let mut output = Vec::new();
+        previous.hook_shutdown(|event| output.push(event));
+        assert!(output.is_empty());
+
+        let mut next = reduce(&config);
+        next.hook_init(|event| output.push(event));
+        assert_eq!(next.groups.len(), 2);
+        let output = process(&mut next, event("a", "done", 2), now);
+        assert_eq!(output.len(), 1);
+        let log = output[0].as_log();
+        assert_eq!(log[&"message".into()], "started done".into());
+        assert_eq!(log[&"bytes".into()], Value::Array(vec![1.into(), 2.into()]));
+    }
+
+    #[test]
+    fn reduce_flushes_groups_when_reloaded() {
+        static STOPPING: AtomicBool = AtomicBool::new(false);
+        let dir = tempfile::tempdir().unwrap();
+        let now = Instant::now();
+
+        let mut previous = reduce(
+            r#"
+            identifier_fields = ["request_id"]
+
+            [ends_when]
+            "message.eq" = "done"
+            "#,
+        );
+        previous.snapshot = Some(Snapshot::with_stopping(&snapshot_config(&dir), &STOPPING));
+        process(&mut previous, event("a", "started", 1), now);
+        let mut output = Vec::new();
+        previous.hook_shutdown(|event| output.push(event));
+        assert_eq!(output.len(), 1);
+        assert!(!dir.path().join("reduce.snapshot").exists());
+    }
+
+    fn snapshot_config(dir: &tempfile::TempDir) -> SnapshotConfig {
+        SnapshotConfig {
+            directory: dir.path().into(),
+            key: "reduce".into(),
+            max_age_secs: 300,
+        }
+    }
+
+    #[test]
+    fn reduce_sums_mixed_numbers() {
+        let mut merger = Merger::new(MergeStrategy::Sum, "n/a".into());
+        merger.add(1.into());
+        merger.add(Value::Float(0.5));
+        merger.add("n/a".into());
+        assert_eq!(merger.into_value(), Value::Float(1.5));
+    }
+}
@@ -0,0 +1,192 @@
+use super::{swimlanes::SwimlaneConfig, Transform};
+use crate::{
+    conditions::{AnyCondition, Condition},
+    event::Event,
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// The output for events no route matches.
+const UNMATCHED_ROUTE: &str = "_unmatched";
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    route: IndexMap<String, AnyCondition>,
+}
+
+inventory::submit! {
+    TransformDescription::new_without_default::<RouteConfig>("route")
+}
+
+#[typetag::serde(name = "route")]
+impl TransformConfig for RouteConfig {
+    fn build(&self, _ctx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        Err("this transform must be expanded".into())
+    }
+
+    fn expand(&mut self) -> crate::Result<Option<IndexMap<String, Box<dyn TransformConfig>>>> {
+        if self.route.is_empty() {
+            return Err("must specify at least one route".into());
+        }
+        if self.route.contains_key(UNMATCHED_ROUTE) {
+            return Err(format!("the route name {:?} is reserved", UNMATCHED_ROUTE).into());
+        }
+
+        let mut map: IndexMap<String, Box<dyn TransformConfig>> = IndexMap::new();
+        let mut conditions = Vec::new();
+        for (name, condition) in self.route.drain(..) {
+            conditions.push(clone_condition(&condition)?);
+            map.insert(name, Box::new(SwimlaneConfig::new(condition)));
+        }
+        map.insert(
+            UNMATCHED_ROUTE.into(),
+            Box::new(UnmatchedRouteConfig { conditions }),
+        );
+
+        Ok(Some(map))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "route"
+    }
+}
+
+/// Condition configs can't be cloned, but they can be serialized.
+fn clone_condition(condition: &AnyCondition) -> crate::Result<AnyCondition> {
+    Ok(serde_json::from_value(serde_json::to_value(condition)?)?)
+}
+
+//------------------------------------------------------------------------------
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UnmatchedRouteConfig {
+    conditions: Vec<AnyCondition>,
+}
+
+#[typetag::serde(name = "route_unmatched")]
+impl TransformConfig for UnmatchedRouteConfig {
+    fn build(&self, _ctx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        let conditions = self
+            .conditions
+            .iter()
+            .map(AnyCondition::build)
+            .collect::<crate::Result<_>>()?;
+        Ok(Box::new(UnmatchedRoute { conditions }))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "route_unmatched"
+    }
+}
+
+pub struct UnmatchedRoute {
+    conditions: Vec<Box<dyn Condition>>,
+}
+
+impl Transform for UnmatchedRoute {
+    fn transform(&mut self, event: Event) -> Option<Event> {
+        if self
+            .conditions
+            .iter()
+            .any(|condition| condition.check(&event))
+        {
+            None
+        } else {
+            Some(event)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::runtime;
+
+    fn expand() -> IndexMap<String, Box<dyn TransformConfig>> {
+        let mut config: RouteConfig = toml::from_str(
+            r#"
+            [route.errors]
+            type = "check_fields"
+            "level.eq" = "error"
+
+            [route.warnings]
+            type = "check_fields"
+            "level.eq" = "warning"
+            "#,
+        )
+        .unwrap();
+        config.expand().unwrap().unwrap()
+    }
+
+    fn event(level: &str) -> Event {
+        let mut event = Event::from("message");
+        event.as_mut_log().insert("level", level);
+        event
+    }
+
+    #[test]
+    fn route_expands_to_named_outputs() {
+        let routes = expand();
+        assert_eq!(
+            routes.keys().collect::<Vec<_>>(),
+            vec!["errors", "warnings", "_unmatched"]
+        );
+    }
+
+    #[test]
+    fn route_unmatched_gets_the_rest() {
+        let rt = runtime();
+        let cx = TransformContext::new_test(rt.executor());
+        let mut transforms = expand()
+            .into_iter()
+            .map(|(name, config)| (name, config.build(cx.clone()).unwrap()))
+            .collect::<IndexMap<_, _>>();
+
+        for level in &["error", "warning", "info"] {
+            let mut passed = Vec::new();
+            for (name, transform) in transforms.iter_mut() {
+                if transform.transform(event(level)).is_some() {
+                    passed.push(name.as_str());
+                }
+            }
+            let expected = match *level {
+                "error" => "errors",
+                "warning" => "warnings",
+                _ => "_unmatched",
+            };
+            assert_eq!(passed, vec![expected]);
+        }
+    }
+
+    #[test]
+    fn route_reserves_unmatched() {
+        let mut config: RouteConfig = toml::from_str(
+            r#"
+            [route._unmatched]
+            type = "check_fields"
+            "level.eq" = "error"
+            "#,
+        )
+        .unwrap();
+        assert!(config.expand().is_err());
+    }
+}
@@ -16,6 +16,12 @@ pub struct SwimlaneConfig {
     condition: AnyCondition,
 }
 
+impl SwimlaneConfig {
+    pub(in crate::transforms) fn new(condition: AnyCondition) -> Self {
+        Self { condition }
+    }
+}
+
 #[typetag::serde(name = "swimlane")]
 impl TransformConfig for SwimlaneConfig {
     fn build(&self, _ctx: TransformContext) -> crate::Result<Box<dyn Transform>> {
@@ -0,0 +1,249 @@
+use super::Transform;
+use crate::{
+    event::Event,
+    internal_events::{ThrottleEventProcessed, ThrottleEventThrottled},
+    template::Template,
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use string_cache::DefaultAtom as Atom;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ThrottleConfig {
+    /// Events allowed per second, for each key.
+    pub rate: f64,
+    /// Events allowed at once after a quiet period, defaults to the rate.
+    pub burst: Option<u32>,
+    /// Events with a different rendered key have separate budgets.
+    pub key_field: Option<Template>,
+    #[serde(default)]
+    pub action: Action,
+    #[serde(default = "default_throttled_field")]
+    pub throttled_field: Atom,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Drop,
+    /// Passes the event, with `throttled_field` set.
+    Tag,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::Drop
+    }
+}
+
+fn default_throttled_field() -> Atom {
+    "throttled".into()
+}
+
+/// Buckets left full for this long are forgotten, so keys don't accumulate.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+inventory::submit! {
+    TransformDescription::new_without_default::<ThrottleConfig>("throttle")
+}
+
+#[typetag::serde(name = "throttle")]
+impl TransformConfig for ThrottleConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err("`rate` must be a positive number".into());
+        }
+        if self.burst == Some(0) {
+            return Err("`burst` must be at least 1".into());
+        }
+        Ok(Box::new(Throttle::new(self.clone())))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Log
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "throttle"
+    }
+}
+
+/// A token bucket, refilled at the configured rate up to the burst size.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct Throttle {
+    config: ThrottleConfig,
+    burst: f64,
+    buckets: HashMap<String, Bucket>,
+    pruned: Instant,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        let burst = config.burst.map_or(config.rate.max(1.0), f64::from);
+        Self {
+            config,
+            burst,
+            buckets: HashMap::new(),
+            pruned: Instant::now(),
+        }
+    }
+
+    /// Takes a token from the bucket of the key, if there is one.
+    fn allow(&mut self, key: &str, now: Instant) -> bool {
+        if now.duration_since(self.pruned) >= PRUNE_INTERVAL {
+            self.prune(now);
+        }
+
+        let (rate, burst) = (self.config.rate, self.burst);
+        let bucket = self
+            .buckets
+            .entry(key.to_owned())
+            .or_insert_with(|| Bucket {
+                tokens: burst,
+                updated: now,
+            });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drops the buckets that would be full by now, they're the same as new
+    /// ones.
+    fn prune(&mut self, now: Instant) {
+        let (rate, burst) = (self.config.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+        self.pruned = now;
+    }
+
+    fn process(&mut self, mut event: Event, now: Instant) -> Option<Event> {
+        emit!(ThrottleEventProcessed);
+
+        let key = match &self.config.key_field {
+            Some(template) => match template.render_string(&event) {
+                Ok(key) => key,
+                // Events missing the fields share a budget.
+                Err(_) => String::new(),
+            },
+            None => String::new(),
+        };
+
+        if self.allow(&key, now) {
+            return Some(event);
+        }
+
+        emit!(ThrottleEventThrottled {
+            key: &key,
+            action: self.config.action,
+        });
+        match self.config.action {
+            Action::Drop => None,
+            Action::Tag => {
+                event
+                    .as_mut_log()
+                    .insert(&self.config.throttled_field, true);
+                Some(event)
+            }
+        }
+    }
+}
+
+impl Transform for Throttle {
+    fn transform(&mut self, event: Event) -> Option<Event> {
+        self.process(event, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle(config: &str) -> Throttle {
+        Throttle::new(toml::from_str(config).unwrap())
+    }
+
+    fn event(pod: &str) -> Event {
+        let mut event = Event::from("message");
+        event.as_mut_log().insert("pod", pod);
+        event
+    }
+
+    #[test]
+    fn throttle_per_key() {
+        let mut throttle = throttle(
+            r#"
+            rate = 2
+            key_field = "{{ pod }}"
+            "#,
+        );
+        let now = Instant::now();
+
+        let passed = (0..5)
+            .filter(|_| throttle.process(event("a"), now).is_some())
+            .count();
+        assert_eq!(passed, 2);
+        // Other keys have their own budget.
+        assert!(throttle.process(event("b"), now).is_some());
+
+        // Half a second refills a token.
+        let later = now + Duration::from_millis(500);
+        assert!(throttle.process(event("a"), later).is_some());
+        assert!(throttle.process(event("a"), later).is_none());
+    }
+
+    #[test]
+    fn throttle_tags_events() {
+        let mut throttle = throttle(
+            r#"
+            rate = 1
+            burst = 1
+            action = "tag"
+            "#,
+        );
+        let now = Instant::now();
+
+        let passed = throttle.process(event("a"), now).unwrap();
+        assert!(!passed.as_log().contains(&"throttled".into()));
+        let tagged = throttle.process(event("a"), now).unwrap();
+        assert_eq!(tagged.as_log()[&"throttled".into()], true.into());
+    }
+
+    #[test]
+    fn throttle_prunes_full_buckets() {
+        let mut throttle = throttle(
+            r#"
+            rate = 1
+            key_field = "{{ pod }}"
+            "#,
+        );
+        let now = Instant::now();
+        throttle.process(event("a"), now);
+        throttle.process(event("b"), now);
+        assert_eq!(throttle.buckets.len(), 2);
+
+        throttle.process(event("c"), now + PRUNE_INTERVAL);
+        assert_eq!(throttle.buckets.keys().collect::<Vec<_>>(), vec!["c"]);
+    }
+}
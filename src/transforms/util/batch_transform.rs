@@ -0,0 +1,196 @@
+use crate::{event::Event, transforms::Transform};
+use futures01::{stream, Async, Poll, Stream};
+use std::collections::VecDeque;
+
+/// A transform taking the events in batches of the ones already waiting,
+/// so the work shared by events, such as lookups, is done once per batch.
+pub trait BatchTransform: Send {
+    /// Transforms the events of the batch, pushing the resulting ones to
+    /// `output`.
+    fn transform_batch(&mut self, events: Vec<Event>, output: &mut Vec<Event>);
+
+    /// Pushes the events held back by the transform to `output`, once its
+    /// input ended.
+    fn flush(&mut self, _output: &mut Vec<Event>) {}
+
+    /// The most events taken in a batch.
+    fn max_batch_size(&self) -> usize {
+        1000
+    }
+}
+
+/// Runs a `BatchTransform` as a `Transform`.
+pub struct Batched<T> {
+    transform: T,
+    /// The outputs past the first of the events passed to `transform`,
+    /// returned by the next calls.
+    pending: VecDeque<Event>,
+}
+
+impl<T> Batched<T> {
+    pub fn new(transform: T) -> Self {
+        Self {
+            transform,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Transform for Batched<T>
+where
+    T: BatchTransform + 'static,
+{
+    // used only in config tests (cannot be put behind `#[cfg(test)`])
+    fn transform(&mut self, event: Event) -> Option<Event> {
+        let mut output = Vec::new();
+        self.transform.transform_batch(vec![event], &mut output);
+        self.pending.extend(output);
+        self.pending.pop_front()
+    }
+
+    fn transform_into(&mut self, output: &mut Vec<Event>, event: Event) {
+        output.extend(self.pending.drain(..));
+        self.transform.transform_batch(vec![event], output);
+    }
+
+    fn transform_stream(
+        self: Box<Self>,
+        input_rx: Box<dyn Stream<Item = Event, Error = ()> + Send>,
+    ) -> Box<dyn Stream<Item = Event, Error = ()> + Send> {
+        let Batched { transform, pending } = *self;
+        let max_batch_size = transform.max_batch_size();
+        let batches = Batches {
+            transform,
+            input: input_rx,
+            max_batch_size,
+            done: false,
+        };
+        let batches = batches.map(stream::iter_ok::<_, ()>).flatten();
+        Box::new(stream::iter_ok(pending).chain(batches))
+    }
+}
+
+/// The outputs of the batches of the events ready in `input`, ending with
+/// the flushed ones.
+struct Batches<T> {
+    transform: T,
+    input: Box<dyn Stream<Item = Event, Error = ()> + Send>,
+    max_batch_size: usize,
+    done: bool,
+}
+
+impl<T: BatchTransform> Stream for Batches<T> {
+    type Item = Vec<Event>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        let mut batch = Vec::new();
+        while batch.len() < self.max_batch_size {
+            match self.input.poll()? {
+                Async::Ready(Some(event)) => batch.push(event),
+                Async::Ready(None) => {
+                    self.done = true;
+                    break;
+                }
+                Async::NotReady => break,
+            }
+        }
+
+        if batch.is_empty() && !self.done {
+            return Ok(Async::NotReady);
+        }
+
+        let mut output = Vec::new();
+        if !batch.is_empty() {
+            self.transform.transform_batch(batch, &mut output);
+        }
+        if self.done {
+            self.transform.flush(&mut output);
+        }
+        Ok(Async::Ready(Some(output)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{log_schema, Value};
+    use futures01::Future;
+
+    struct BatchSizes {
+        max_batch_size: usize,
+    }
+
+    impl BatchTransform for BatchSizes {
+        fn transform_batch(&mut self, events: Vec<Event>, output: &mut Vec<Event>) {
+            let size = events.len() as i64;
+            output.extend(events.into_iter().map(|mut event| {
+                event.as_mut_log().insert("batch_size", size);
+                event
+            }));
+        }
+
+        fn flush(&mut self, output: &mut Vec<Event>) {
+            output.push(Event::from("flushed"));
+        }
+
+        fn max_batch_size(&self) -> usize {
+            self.max_batch_size
+        }
+    }
+
+    #[test]
+    fn transforms_ready_events_in_batches() {
+        let transform = Box::new(Batched::new(BatchSizes { max_batch_size: 2 }));
+        let input = stream::iter_ok((0..5).map(|i| Event::from(i.to_string())));
+
+        let output = transform
+            .transform_stream(Box::new(input))
+            .collect()
+            .wait()
+            .unwrap();
+
+        let sizes = output[..5]
+            .iter()
+            .map(|event| event.as_log()[&"batch_size".into()].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            vec![2, 2, 2, 2, 1]
+                .into_iter()
+                .map(Value::Integer)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(output.len(), 6);
+        assert_eq!(
+            output[5].as_log()[&log_schema().message_key()],
+            "flushed".into()
+        );
+    }
+
+    struct Duplicate;
+
+    impl BatchTransform for Duplicate {
+        fn transform_batch(&mut self, events: Vec<Event>, output: &mut Vec<Event>) {
+            for event in events {
+                output.push(event.clone());
+                output.push(event);
+            }
+        }
+    }
+
+    #[test]
+    fn transform_returns_every_output() {
+        let mut transform = Batched::new(Duplicate);
+        let (a, b) = (Event::from("a"), Event::from("b"));
+
+        assert_eq!(transform.transform(a.clone()), Some(a.clone()));
+        let mut output = Vec::new();
+        transform.transform_into(&mut output, b.clone());
+        assert_eq!(output, vec![a, b.clone(), b]);
+    }
+}
@@ -1,2 +1,15 @@
-#[cfg(any(feature = "transforms-lua"))]
+pub mod batch_transform;
+#[cfg(any(
+    feature = "transforms-aggregate",
+    feature = "transforms-aggregate_histogram",
+    feature = "transforms-dedupe",
+    feature = "transforms-lua",
+    feature = "transforms-reduce"
+))]
 pub mod runtime_transform;
+#[cfg(any(
+    feature = "transforms-aggregate",
+    feature = "transforms-dedupe",
+    feature = "transforms-reduce"
+))]
+pub mod snapshot;
@@ -0,0 +1,204 @@
+//! Hands the in-progress state of a stateful transform over across restarts.
+//!
+//! When Vector shuts down the state is written to a snapshot rather than
+//! flushed, and the next instance starting with the same snapshot restores
+//! it. Kept in a directory shared by an aggregator pair, the snapshot of one
+//! instance is restored by whichever starts next, so a rolling restart
+//! doesn't cut the aggregation windows short. A transform stopped by a reload
+//! flushes its state instead, as the one replacing it may already be running.
+
+use crate::internal_events::{
+    StateSnapshotFailed, StateSnapshotInvalid, StateSnapshotRestored, StateSnapshotSaved,
+    StateSnapshotStale,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotConfig {
+    /// May be on a volume shared by the instances.
+    pub directory: PathBuf,
+    /// Names the snapshot within the directory, every transform sharing it
+    /// needs its own.
+    pub key: String,
+    /// Older snapshots are discarded rather than restored.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_max_age_secs() -> u64 {
+    300
+}
+
+pub struct Snapshot {
+    path: PathBuf,
+    max_age: Duration,
+    stopping: &'static AtomicBool,
+}
+
+impl Snapshot {
+    pub fn new(config: &SnapshotConfig) -> Self {
+        Self::with_stopping(config, &crate::topology::STOPPING)
+    }
+
+    /// Saves the state only once `stopping` is set.
+    pub(crate) fn with_stopping(config: &SnapshotConfig, stopping: &'static AtomicBool) -> Self {
+        Self {
+            path: config.directory.join(format!("{}.snapshot", config.key)),
+            max_age: Duration::from_secs(config.max_age_secs),
+            stopping,
+        }
+    }
+
+    /// Claims the snapshot left by a previous instance, so no other one
+    /// restores it as well.
+    pub fn restore<T, E: fmt::Display>(
+        &self,
+        decode: impl FnOnce(&[u8]) -> Result<T, E>,
+    ) -> Option<T> {
+        let claimed = self
+            .path
+            .with_extension(format!("claimed-{}", std::process::id()));
+        let result = fs::rename(&self.path, &claimed).and_then(|()| {
+            let state = fs::metadata(&claimed)
+                .and_then(|metadata| metadata.modified())
+                .and_then(|modified| Ok((modified, fs::read(&claimed)?)));
+            let _ = fs::remove_file(&claimed);
+            state
+        });
+        match result {
+            Ok((modified, _)) if is_stale(modified, self.max_age) => {
+                emit!(StateSnapshotStale {
+                    path: &self.path,
+                    max_age_secs: self.max_age.as_secs(),
+                });
+                None
+            }
+            Ok((_, bytes)) => match decode(&bytes) {
+                Ok(state) => {
+                    emit!(StateSnapshotRestored {
+                        path: &self.path,
+                        byte_size: bytes.len(),
+                    });
+                    Some(state)
+                }
+                Err(error) => {
+                    emit!(StateSnapshotInvalid {
+                        path: &self.path,
+                        error: error.to_string(),
+                    });
+                    None
+                }
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                emit!(StateSnapshotFailed {
+                    path: &self.path,
+                    error,
+                });
+                None
+            }
+        }
+    }
+
+    /// Returns whether the state was saved, it has to be flushed otherwise.
+    /// It is only saved when the topology is stopped as a whole, not when
+    /// the transform is removed or replaced by a reload.
+    pub fn save(&self, state: &[u8]) -> bool {
+        if !self.stopping.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        // Written next to the snapshot first, so it is never restored
+        // partially.
+        let partial = self.path.with_extension("partial");
+        let result = fs::write(&partial, state).and_then(|()| fs::rename(&partial, &self.path));
+        match result {
+            Ok(()) => {
+                emit!(StateSnapshotSaved {
+                    path: &self.path,
+                    byte_size: state.len(),
+                });
+                true
+            }
+            Err(error) => {
+                emit!(StateSnapshotFailed {
+                    path: &self.path,
+                    error,
+                });
+                false
+            }
+        }
+    }
+}
+
+/// A clock gone backwards doesn't make the snapshot stale.
+fn is_stale(modified: SystemTime, max_age: Duration) -> bool {
+    modified.elapsed().map_or(false, |age| age > max_age)
+}
+
+/// A snapshot saved as if the topology were stopping.
+#[cfg(test)]
+pub(crate) fn stopping(config: &SnapshotConfig) -> Snapshot {
+    static STOPPING: AtomicBool = AtomicBool::new(true);
+    Snapshot::with_stopping(config, &STOPPING)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(directory: &std::path::Path) -> SnapshotConfig {
+        SnapshotConfig {
+            directory: directory.into(),
+            key: "reduce".into(),
+            max_age_secs: 300,
+        }
+    }
+
+    #[test]
+    fn snapshot_is_restored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        let previous = stopping(&config);
+        assert!(previous.save(b"state"));
+
+        let next = stopping(&config);
+        let decode = |state: &[u8]| String::from_utf8(state.to_vec());
+        assert_eq!(next.restore(decode), Some("state".to_owned()));
+        assert_eq!(stopping(&config).restore(decode), None);
+        assert!(!dir.path().join("reduce.snapshot").exists());
+    }
+
+    #[test]
+    fn snapshot_is_not_saved_on_reload() {
+        static STOPPING: AtomicBool = AtomicBool::new(false);
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path());
+
+        assert!(!Snapshot::with_stopping(&config, &STOPPING).save(b"state"));
+        assert!(!dir.path().join("reduce.snapshot").exists());
+    }
+
+    #[test]
+    fn stale_snapshot_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SnapshotConfig {
+            max_age_secs: 0,
+            ..config(dir.path())
+        };
+
+        assert!(stopping(&config).save(b"state"));
+        std::thread::sleep(Duration::from_millis(10));
+        let decode = |state: &[u8]| String::from_utf8(state.to_vec());
+        assert_eq!(stopping(&config).restore(decode), None);
+        assert!(!dir.path().join("reduce.snapshot").exists());
+    }
+}
@@ -0,0 +1,404 @@
+//! Runs transforms compiled to WebAssembly.
+//!
+//! A guest module exports its `memory` and the following functions:
+//!
+//! * `allocate(len: i32) -> i32` returns a buffer of `len` bytes in the
+//!   guest memory. The host writes an event there before every call to
+//!   `process`, the buffer belongs to the guest afterwards.
+//! * `process(ptr: i32, len: i32) -> i32` processes the JSON encoded event
+//!   in the given buffer, and returns `0` on success. Events emitted during
+//!   a call that doesn't return `0` are dropped.
+//! * `init() -> i32` is optional, and is called once after the module is
+//!   instantiated. The transform fails to build if it doesn't return `0`.
+//!
+//! And it may import the following functions from the `vector` module:
+//!
+//! * `emit(ptr: i32, len: i32)` outputs the JSON encoded event in the given
+//!   buffer.
+//! * `log(level: i32, ptr: i32, len: i32)` logs the UTF-8 message in the
+//!   given buffer, at the level `0` (error) to `4` (trace).
+//! * `config_get(key_ptr: i32, key_len: i32, ptr: i32, cap: i32) -> i32`
+//!   looks up the option named by the key, and returns the length of its
+//!   value, or `-1` if it isn't set. The value is copied to the buffer only
+//!   if it fits into `cap` bytes.
+//!
+//! Events are encoded as `{"log": {..fields}}` or `{"metric": {..}}`, with
+//! metrics in the same shape as the `metric_to_log` transform outputs them.
+
+use super::Transform;
+use crate::{
+    event::{self, metric::Metric, Event, LogEvent, Value},
+    topology::config::{DataType, TransformConfig, TransformContext, TransformDescription},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{cell::RefCell, collections::BTreeMap, fs, path::PathBuf, rc::Rc};
+use wasmtime::{Caller, Engine, Extern, Linker, Memory, Module, Store, Trap};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WasmConfig {
+    /// The path of the module, in the binary or text format.
+    pub module: PathBuf,
+    /// Options the module reads through the `config_get` hostcall.
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+inventory::submit! {
+    TransformDescription::new_without_default::<WasmConfig>("wasm")
+}
+
+#[typetag::serde(name = "wasm")]
+impl TransformConfig for WasmConfig {
+    fn build(&self, _cx: TransformContext) -> crate::Result<Box<dyn Transform>> {
+        let code = fs::read(&self.module).with_context(|| ReadModule {
+            path: self.module.clone(),
+        })?;
+        Ok(Box::new(Wasm::new(&code, self.options.clone())?))
+    }
+
+    fn input_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn output_type(&self) -> DataType {
+        DataType::Any
+    }
+
+    fn transform_type(&self) -> &'static str {
+        "wasm"
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum BuildError {
+    #[snafu(display("Could not read {:?}: {}", path, source))]
+    ReadModule {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Invalid module: {}", message))]
+    InvalidModule { message: String },
+    #[snafu(display("Module does not export {:?}", name))]
+    MissingExport { name: &'static str },
+    #[snafu(display("Module export {:?} has the wrong type: {}", name, message))]
+    InvalidExport { name: &'static str, message: String },
+    #[snafu(display("\"init\" failed: {}", source))]
+    InitTrap { source: Trap },
+    #[snafu(display("\"init\" returned {}", status))]
+    InitFailed { status: i32 },
+}
+
+// Wasmtime reports errors with `anyhow`, which doesn't implement
+// `std::error::Error`.
+fn invalid_module(error: impl std::fmt::Display) -> BuildError {
+    BuildError::InvalidModule {
+        message: error.to_string(),
+    }
+}
+
+fn invalid_export(name: &'static str, error: impl std::fmt::Display) -> BuildError {
+    BuildError::InvalidExport {
+        name,
+        message: error.to_string(),
+    }
+}
+
+/// The events emitted by the guest during a call to `process`.
+type Output = Rc<RefCell<Vec<Event>>>;
+
+pub struct Wasm {
+    memory: Memory,
+    allocate: Box<dyn Fn(i32) -> Result<i32, Trap>>,
+    process: Box<dyn Fn(i32, i32) -> Result<i32, Trap>>,
+    output: Output,
+}
+
+// The store, and with it everything referring to the instance, is not
+// `Send` because of its reference counts. All of them are owned by the
+// transform and never handed out, so moving the whole of it to another
+// thread is fine.
+unsafe impl Send for Wasm {}
+
+impl Wasm {
+    pub fn new(code: &[u8], options: BTreeMap<String, String>) -> crate::Result<Self> {
+        let store = Store::new(&Engine::default());
+        let module = Module::new(store.engine(), code).map_err(invalid_module)?;
+        let output = Output::default();
+
+        let mut linker = Linker::new(&store);
+        let emitted = Rc::clone(&output);
+        linker
+            .func(
+                "vector",
+                "emit",
+                move |caller: Caller<'_>, ptr: i32, len: i32| {
+                    let bytes = read(&guest_memory(&caller)?, ptr, len)?;
+                    match decode_event(&bytes) {
+                        Ok(event) => emitted.borrow_mut().push(event),
+                        Err(error) => warn!(
+                            message = "dropping invalid event emitted by module.",
+                            %error,
+                            rate_limit_secs = 30
+                        ),
+                    }
+                    Ok(())
+                },
+            )
+            .map_err(invalid_module)?;
+        linker
+            .func(
+                "vector",
+                "log",
+                |caller: Caller<'_>, level: i32, ptr: i32, len: i32| {
+                    let bytes = read(&guest_memory(&caller)?, ptr, len)?;
+                    let message = String::from_utf8_lossy(&bytes);
+                    match level {
+                        0 => error!(message = "module error.", text = %message),
+                        1 => warn!(message = "module warning.", text = %message),
+                        2 => info!(message = "module message.", text = %message),
+                        3 => debug!(message = "module message.", text = %message),
+                        _ => trace!(message = "module message.", text = %message),
+                    }
+                    Ok(())
+                },
+            )
+            .map_err(invalid_module)?;
+        linker
+            .func(
+                "vector",
+                "config_get",
+                move |caller: Caller<'_>, key_ptr: i32, key_len: i32, ptr: i32, cap: i32| {
+                    let memory = guest_memory(&caller)?;
+                    let key = read(&memory, key_ptr, key_len)?;
+                    let value = match options.get(&*String::from_utf8_lossy(&key)) {
+                        Some(value) => value.as_bytes(),
+                        None => return Ok(-1),
+                    };
+                    if value.len() <= cap as usize {
+                        write(&memory, ptr, value)?;
+                    }
+                    Ok(value.len() as i32)
+                },
+            )
+            .map_err(invalid_module)?;
+
+        let instance = linker.instantiate(&module).map_err(invalid_module)?;
+        let memory = instance
+            .get_memory("memory")
+            .ok_or(BuildError::MissingExport { name: "memory" })?;
+        let export = |name: &'static str| {
+            instance
+                .get_func(name)
+                .ok_or(BuildError::MissingExport { name })
+        };
+        let allocate = export("allocate")?
+            .get1::<i32, i32>()
+            .map_err(|error| invalid_export("allocate", error))?;
+        let process = export("process")?
+            .get2::<i32, i32, i32>()
+            .map_err(|error| invalid_export("process", error))?;
+
+        if let Some(init) = instance.get_func("init") {
+            let init = init
+                .get0::<i32>()
+                .map_err(|error| invalid_export("init", error))?;
+            match init().context(InitTrap)? {
+                0 => (),
+                status => return Err(BuildError::InitFailed { status }.into()),
+            }
+        }
+
+        Ok(Self {
+            memory,
+            allocate: Box::new(allocate),
+            process: Box::new(process),
+            output,
+        })
+    }
+
+    fn call(&mut self, event: Event) -> Result<Vec<Event>, Trap> {
+        let bytes = encode_event(&event);
+        let len = bytes.len() as i32;
+        let ptr = (self.allocate)(len)?;
+        write(&self.memory, ptr, &bytes)?;
+
+        let status = (self.process)(ptr, len);
+        let output = self.output.replace(Vec::new());
+        match status? {
+            0 => Ok(output),
+            status => {
+                warn!(
+                    message = "module failed to process event.",
+                    %status,
+                    rate_limit_secs = 30
+                );
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+impl Transform for Wasm {
+    fn transform(&mut self, event: Event) -> Option<Event> {
+        let mut output = Vec::with_capacity(1);
+        self.transform_into(&mut output, event);
+        output.pop()
+    }
+
+    fn transform_into(&mut self, output: &mut Vec<Event>, event: Event) {
+        match self.call(event) {
+            Ok(events) => output.extend(events),
+            Err(error) => error!(
+                message = "module trapped.",
+                %error,
+                rate_limit_secs = 30
+            ),
+        }
+    }
+}
+
+fn guest_memory(caller: &Caller<'_>) -> Result<Memory, Trap> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Trap::new("module does not export \"memory\""))
+}
+
+fn check_bounds(memory: &Memory, ptr: i32, len: usize) -> Result<usize, Trap> {
+    let start = ptr as u32 as usize;
+    match start.checked_add(len) {
+        Some(end) if end <= memory.data_size() => Ok(start),
+        _ => Err(Trap::new("buffer is out of bounds of the module memory")),
+    }
+}
+
+fn read(memory: &Memory, ptr: i32, len: i32) -> Result<Vec<u8>, Trap> {
+    let len = len as u32 as usize;
+    let start = check_bounds(memory, ptr, len)?;
+    // Safe as the guest doesn't run while the slice is borrowed.
+    Ok(unsafe { memory.data_unchecked()[start..start + len].to_vec() })
+}
+
+fn write(memory: &Memory, ptr: i32, bytes: &[u8]) -> Result<(), Trap> {
+    let start = check_bounds(memory, ptr, bytes.len())?;
+    // Safe as the guest doesn't run while the slice is borrowed.
+    unsafe { memory.data_unchecked_mut()[start..start + bytes.len()].copy_from_slice(bytes) };
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum WireEvent<L, M> {
+    Log(L),
+    Metric(M),
+}
+
+fn encode_event(event: &Event) -> Vec<u8> {
+    let event = match event {
+        Event::Log(log) => WireEvent::Log(log),
+        Event::Metric(metric) => WireEvent::Metric(metric),
+    };
+    serde_json::to_vec(&event).expect("events serialize to JSON")
+}
+
+fn decode_event(bytes: &[u8]) -> serde_json::Result<Event> {
+    let event: WireEvent<BTreeMap<String, serde_json::Value>, Metric> =
+        serde_json::from_slice(bytes)?;
+    Ok(match event {
+        WireEvent::Log(fields) => {
+            let mut log = LogEvent::new();
+            for (key, value) in fields {
+                log.insert_flat(key, Value::from(value));
+            }
+            // Timestamps are encoded as strings, parse the one of the
+            // schema back.
+            let timestamp_key = event::log_schema().timestamp_key();
+            let timestamp = log
+                .get(timestamp_key)
+                .and_then(|value| match value {
+                    Value::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+                    _ => None,
+                })
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok());
+            if let Some(timestamp) = timestamp {
+                log.insert(timestamp_key, timestamp.with_timezone(&Utc));
+            }
+            Event::Log(log)
+        }
+        WireEvent::Metric(metric) => Event::Metric(metric),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::metric::{MetricKind, MetricValue};
+
+    // Emits every event twice, or the value of the "event" option instead
+    // if it is set.
+    const MODULE: &str = r#"
+        (module
+          (import "vector" "emit" (func $emit (param i32 i32)))
+          (import "vector" "config_get" (func $config_get (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "event")
+          (func (export "allocate") (param i32) (result i32)
+            i32.const 1024)
+          (func (export "process") (param $ptr i32) (param $len i32) (result i32)
+            (local $n i32)
+            (local.set $n (call $config_get (i32.const 0) (i32.const 5) (i32.const 16) (i32.const 1000)))
+            (if (i32.ge_s (local.get $n) (i32.const 0))
+              (then
+                (call $emit (i32.const 16) (local.get $n)))
+              (else
+                (call $emit (local.get $ptr) (local.get $len))
+                (call $emit (local.get $ptr) (local.get $len))))
+            i32.const 0))
+    "#;
+
+    fn transform(options: BTreeMap<String, String>, event: Event) -> Vec<Event> {
+        let mut wasm = Wasm::new(MODULE.as_bytes(), options).unwrap();
+        let mut output = Vec::new();
+        wasm.transform_into(&mut output, event);
+        output
+    }
+
+    #[test]
+    fn wasm_round_trips_events() {
+        let mut log = Event::from("hello");
+        log.as_mut_log().insert("nested.field", 1);
+        let output = transform(BTreeMap::new(), log.clone());
+        assert_eq!(output, vec![log.clone(), log]);
+
+        let metric = Event::Metric(Metric {
+            name: "requests".into(),
+            timestamp: None,
+            tags: None,
+            kind: MetricKind::Incremental,
+            value: MetricValue::Counter { value: 1.0 },
+        });
+        let output = transform(BTreeMap::new(), metric.clone());
+        assert_eq!(output, vec![metric.clone(), metric]);
+    }
+
+    #[test]
+    fn wasm_reads_options() {
+        let mut options = BTreeMap::new();
+        options.insert("event".into(), r#"{"log":{"message":"hi"}}"#.into());
+        let output = transform(options, Event::from("hello"));
+
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].as_log()[&"message".into()], "hi".into());
+    }
+
+    #[test]
+    fn wasm_requires_exports() {
+        let error = Wasm::new(b"(module (memory (export \"memory\") 1))", BTreeMap::new())
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Module does not export \"allocate\"");
+    }
+}
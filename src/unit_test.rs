@@ -1,6 +1,9 @@
 use crate::{
     config_paths, event,
-    topology::{config::Config, unit_test::UnitTest},
+    topology::{
+        config::{Config, DEFAULTS_PROFILE},
+        unit_test::UnitTest,
+    },
 };
 use colored::*;
 use std::{fs::File, path::PathBuf};
@@ -43,6 +46,9 @@ fn build_tests(i: usize, path: &PathBuf) -> Result<Vec<UnitTest>, Vec<String>> {
         event::LOG_SCHEMA
             .set(config.global.log_schema.clone())
             .expect("Couldn't set schema");
+        DEFAULTS_PROFILE
+            .set(config.global.defaults_profile)
+            .expect("Couldn't set defaults profile");
     }
 
     crate::topology::unit_test::build_unit_tests(&mut config)
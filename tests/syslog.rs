@@ -35,6 +35,7 @@ fn test_tcp_syslog() {
         SyslogConfig::new(Mode::Tcp {
             address: in_addr.into(),
             tls: None,
+            tls_client_subject_key: None,
         }),
     );
     config.add_sink("out", &["in"], tcp_json_sink(out_addr.to_string()));
@@ -77,7 +78,13 @@ fn test_udp_syslog() {
     let out_addr = next_addr();
 
     let mut config = config::Config::empty();
-    config.add_source("in", SyslogConfig::new(Mode::Udp { address: in_addr }));
+    config.add_source(
+        "in",
+        SyslogConfig::new(Mode::Udp {
+            address: in_addr,
+            shards: 1,
+        }),
+    );
     config.add_sink("out", &["in"], tcp_json_sink(out_addr.to_string()));
 
     let mut rt = runtime();
@@ -140,6 +147,7 @@ fn test_unix_stream_syslog() {
         "in",
         SyslogConfig::new(Mode::Unix {
             path: in_path.clone(),
+            permissions: Default::default(),
         }),
     );
     config.add_sink("out", &["in"], tcp_json_sink(out_addr.to_string()));